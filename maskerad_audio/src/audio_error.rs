@@ -0,0 +1,101 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::error::Error;
+use std::fmt;
+use maskerad_resource_management::resources::resource_errors::ResourceError;
+use serde_json::Error as JSONError;
+
+#[derive(Debug)]
+pub enum AudioError {
+    StreamError(String, ResourceError),
+    MusicError(String),
+    SoundbankError(String, JSONError),
+    UnknownEvent(String),
+    VoiceError(String),
+}
+
+unsafe impl Send for AudioError {}
+unsafe impl Sync for AudioError {}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &AudioError::StreamError(ref description, _) => {
+                write!(f, "Audio stream error: {}", description)
+            },
+            &AudioError::MusicError(ref description) => {
+                write!(f, "Music error: {}", description)
+            },
+            &AudioError::SoundbankError(ref description, _) => {
+                write!(f, "Soundbank error: {}", description)
+            },
+            &AudioError::UnknownEvent(ref description) => {
+                write!(f, "Unknown audio event: {}", description)
+            },
+            &AudioError::VoiceError(ref description) => {
+                write!(f, "Voice chat error: {}", description)
+            },
+        }
+    }
+}
+
+impl Error for AudioError {
+    fn description(&self) -> &str {
+        match self {
+            &AudioError::StreamError(_, _) => {
+                "StreamError"
+            },
+            &AudioError::MusicError(_) => {
+                "MusicError"
+            },
+            &AudioError::SoundbankError(_, _) => {
+                "SoundbankError"
+            },
+            &AudioError::UnknownEvent(_) => {
+                "UnknownEvent"
+            },
+            &AudioError::VoiceError(_) => {
+                "VoiceError"
+            },
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match self {
+            &AudioError::StreamError(_, ref resource_error) => {
+                Some(resource_error)
+            },
+            &AudioError::MusicError(_) => {
+                None
+            },
+            &AudioError::SoundbankError(_, ref json_error) => {
+                Some(json_error)
+            },
+            &AudioError::UnknownEvent(_) => {
+                None
+            },
+            &AudioError::VoiceError(_) => {
+                None
+            },
+        }
+    }
+}
+
+pub type AudioResult<T> = Result<T, AudioError>;
+
+impl From<ResourceError> for AudioError {
+    fn from(error: ResourceError) -> Self {
+        AudioError::StreamError(String::from("Error while streaming a sound resource."), error)
+    }
+}
+
+impl From<JSONError> for AudioError {
+    fn from(error: JSONError) -> Self {
+        AudioError::SoundbankError(String::from("Error while deserializing a soundbank."), error)
+    }
+}
@@ -0,0 +1,395 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ DYNAMIC MUSIC.
+
+ A music track is split into several stems (drums, bass, strings...), all played in sync.
+ Gameplay drives an intensity parameter in [0; 1], and each stem fades in when the intensity
+ is inside its range, and fades out otherwise.
+
+ Transitions between tracks can be quantized to the next beat or the next bar, so the music
+ stays on the beat when the game switches from an exploration track to a combat track.
+
+ The stems are streamed packet by packet, like any other sound resource.
+*/
+
+use std::io::{Read, Seek};
+use maskerad_resource_management::resources::sound_resource::SoundResource;
+use audio_error::{AudioError, AudioResult};
+
+//Anything able to give us decoded packets of audio data, one Vec<i16> per channel.
+pub trait StemStream {
+    fn next_packet(&mut self) -> AudioResult<Option<Vec<Vec<i16>>>>;
+}
+
+impl<R: Read + Seek> StemStream for SoundResource<R> {
+    fn next_packet(&mut self) -> AudioResult<Option<Vec<Vec<i16>>>> {
+        self.decompress_packet().map_err(|resource_error| {
+            AudioError::from(resource_error)
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Quantization {
+    Immediate,
+    Beat,
+    Bar,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MusicStem {
+    name: String,
+    intensity_min: f32,
+    intensity_max: f32,
+    volume: f32,
+    gain: f32,
+}
+
+impl MusicStem {
+    pub fn new<S>(name: S, intensity_min: f32, intensity_max: f32) -> Self where
+        S: Into<String>
+    {
+        MusicStem {
+            name: name.into(),
+            intensity_min,
+            intensity_max,
+            volume: 1.0,
+            gain: 0.0,
+        }
+    }
+
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    //The current fade gain of the stem, in [0; 1].
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    pub fn is_active(&self, intensity: f32) -> bool {
+        intensity >= self.intensity_min && intensity <= self.intensity_max
+    }
+
+    fn target_gain(&self, intensity: f32) -> f32 {
+        if self.is_active(intensity) {1.0} else {0.0}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MusicTrack {
+    name: String,
+    tempo_bpm: f64,
+    beats_per_bar: u32,
+    stems: Vec<MusicStem>,
+}
+
+impl MusicTrack {
+    //The tempo must be positive and finite, and a bar must have at least one beat.
+    pub fn new<S>(name: S, tempo_bpm: f64, beats_per_bar: u32) -> AudioResult<Self> where
+        S: Into<String>
+    {
+        if !tempo_bpm.is_finite() || tempo_bpm <= 0.0 {
+            return Err(AudioError::MusicError(format!("The tempo of a music track must be positive and finite, not {}.", tempo_bpm)));
+        }
+        if beats_per_bar == 0 {
+            return Err(AudioError::MusicError(String::from("A bar of a music track must have at least one beat.")));
+        }
+        Ok(MusicTrack {
+            name: name.into(),
+            tempo_bpm,
+            beats_per_bar,
+            stems: Vec::new(),
+        })
+    }
+
+    pub fn with_stem(mut self, stem: MusicStem) -> Self {
+        self.stems.push(stem);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn stems(&self) -> &[MusicStem] {
+        self.stems.as_slice()
+    }
+
+    pub fn seconds_per_beat(&self) -> f64 {
+        60.0 / self.tempo_bpm
+    }
+
+    pub fn seconds_per_bar(&self) -> f64 {
+        self.seconds_per_beat() * self.beats_per_bar as f64
+    }
+}
+
+pub struct DynamicMusic {
+    current: Option<MusicTrack>,
+    pending: Option<(MusicTrack, Quantization)>,
+    //Seconds elapsed since the current track started.
+    position: f64,
+    intensity: f32,
+    //Seconds needed by a stem to go from silent to full gain.
+    fade_time: f64,
+}
+
+impl Default for DynamicMusic {
+    fn default() -> Self {
+        DynamicMusic {
+            current: None,
+            pending: None,
+            position: 0.0,
+            intensity: 0.0,
+            fade_time: 1.0,
+        }
+    }
+}
+
+impl DynamicMusic {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_fade_time(fade_time: f64) -> Self {
+        DynamicMusic {
+            fade_time,
+            .. Default::default()
+        }
+    }
+
+    //Start a track right now, the stems matching the current intensity are audible immediately.
+    pub fn play(&mut self, mut track: MusicTrack) {
+        debug!("Playing the music track {}.", track.name());
+        let intensity = self.intensity;
+        for stem in track.stems.iter_mut() {
+            stem.gain = stem.target_gain(intensity);
+        }
+        self.current = Some(track);
+        self.pending = None;
+        self.position = 0.0;
+    }
+
+    //Switch to another track on the next beat or bar of the current one.
+    pub fn transition_to(&mut self, track: MusicTrack, quantization: Quantization) {
+        debug!("Scheduling a transition to the music track {} ({:?}).", track.name(), quantization);
+        if self.current.is_none() || quantization == Quantization::Immediate {
+            self.play(track);
+        } else {
+            self.pending = Some((track, quantization));
+        }
+    }
+
+    pub fn stop(&mut self) {
+        debug!("Stopping the dynamic music.");
+        self.current = None;
+        self.pending = None;
+        self.position = 0.0;
+    }
+
+    pub fn current_track(&self) -> Option<&MusicTrack> {
+        self.current.as_ref()
+    }
+
+    pub fn has_pending_transition(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        trace!("Setting the music intensity to {}.", intensity);
+        self.intensity = intensity.max(0.0).min(1.0);
+    }
+
+    pub fn current_beat(&self) -> u64 {
+        match self.current {
+            Some(ref track) => (self.position / track.seconds_per_beat()) as u64,
+            None => 0,
+        }
+    }
+
+    pub fn current_bar(&self) -> u64 {
+        match self.current {
+            Some(ref track) => (self.position / track.seconds_per_bar()) as u64,
+            None => 0,
+        }
+    }
+
+    //Seconds until the next beat/bar boundary of the current track.
+    pub fn time_to_boundary(&self, quantization: Quantization) -> f64 {
+        let step = match (self.current.as_ref(), quantization) {
+            (None, _) | (_, Quantization::Immediate) => return 0.0,
+            (Some(track), Quantization::Beat) => track.seconds_per_beat(),
+            (Some(track), Quantization::Bar) => track.seconds_per_bar(),
+        };
+        let elapsed_in_step = self.position % step;
+        if elapsed_in_step == 0.0 {0.0} else {step - elapsed_in_step}
+    }
+
+    pub fn update(&mut self, delta_seconds: f64) {
+        let mut remaining = delta_seconds;
+
+        let boundary = self.pending.as_ref().map(|&(_, quantization)| self.time_to_boundary(quantization));
+        if let Some(time_to_boundary) = boundary {
+            if remaining >= time_to_boundary {
+                let (track, _) = self.pending.take().expect("pending transition disappeared");
+                remaining -= time_to_boundary;
+                self.play(track);
+            }
+        }
+
+        self.position += remaining;
+
+        let intensity = self.intensity;
+        let fade_step = if self.fade_time > 0.0 {(delta_seconds / self.fade_time) as f32} else {1.0};
+        if let Some(ref mut track) = self.current {
+            for stem in track.stems.iter_mut() {
+                let target = stem.target_gain(intensity);
+                if stem.gain < target {
+                    stem.gain = (stem.gain + fade_step).min(target);
+                } else if stem.gain > target {
+                    stem.gain = (stem.gain - fade_step).max(target);
+                }
+            }
+        }
+    }
+
+    //Decode one packet from each stem stream and mix them, according to the stem gains.
+    //The streams must be given in the same order as the stems of the current track.
+    pub fn mix_packet<S: StemStream>(&self, streams: &mut [S]) -> AudioResult<Option<Vec<Vec<f32>>>> {
+        let track = match self.current {
+            Some(ref track) => track,
+            None => return Ok(None),
+        };
+
+        if track.stems.len() != streams.len() {
+            return Err(AudioError::MusicError(format!(
+                "The track {} has {} stems, but {} streams were given.",
+                track.name(),
+                track.stems.len(),
+                streams.len()
+            )));
+        }
+
+        let mut mixed: Option<Vec<Vec<f32>>> = None;
+        for (stem, stream) in track.stems.iter().zip(streams.iter_mut()) {
+            let packet = match stream.next_packet()? {
+                Some(packet) => packet,
+                None => continue,
+            };
+            let factor = stem.gain * stem.volume / i16::max_value() as f32;
+            let output = mixed.get_or_insert_with(Vec::new);
+            if output.len() < packet.len() {
+                output.resize(packet.len(), Vec::new());
+            }
+            for (out_channel, channel) in output.iter_mut().zip(packet.iter()) {
+                if out_channel.len() < channel.len() {
+                    out_channel.resize(channel.len(), 0.0);
+                }
+                for (out_sample, sample) in out_channel.iter_mut().zip(channel.iter()) {
+                    *out_sample += *sample as f32 * factor;
+                }
+            }
+        }
+
+        Ok(mixed)
+    }
+}
+
+#[cfg(test)]
+mod dynamic_music_test {
+    use super::*;
+
+    struct ConstantStream(i16, usize);
+
+    impl StemStream for ConstantStream {
+        fn next_packet(&mut self) -> AudioResult<Option<Vec<Vec<i16>>>> {
+            if self.1 == 0 {
+                return Ok(None);
+            }
+            self.1 -= 1;
+            Ok(Some(vec![vec![self.0; 4]]))
+        }
+    }
+
+    fn combat_track() -> MusicTrack {
+        MusicTrack::new("combat", 120.0, 4).unwrap()
+            .with_stem(MusicStem::new("drums", 0.0, 1.0))
+            .with_stem(MusicStem::new("strings", 0.5, 1.0))
+    }
+
+    #[test]
+    fn dynamic_music_beats_and_bars() {
+        let mut music = DynamicMusic::new();
+        music.play(combat_track());
+        music.update(2.25);
+        assert_eq!(music.current_beat(), 4);
+        assert_eq!(music.current_bar(), 1);
+        assert!((music.time_to_boundary(Quantization::Beat) - 0.25).abs() < 1e-9);
+        assert!((music.time_to_boundary(Quantization::Bar) - 1.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn dynamic_music_quantized_transition() {
+        let mut music = DynamicMusic::new();
+        music.play(combat_track());
+        music.update(0.5);
+        music.transition_to(MusicTrack::new("exploration", 90.0, 3).unwrap(), Quantization::Bar);
+        music.update(1.0);
+        assert_eq!(music.current_track().unwrap().name(), "combat");
+        assert!(music.has_pending_transition());
+        music.update(1.0);
+        assert_eq!(music.current_track().unwrap().name(), "exploration");
+        assert!((music.position() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn dynamic_music_intensity_fades_stems() {
+        let mut music = DynamicMusic::with_fade_time(1.0);
+        music.play(combat_track());
+        assert_eq!(music.current_track().unwrap().stems()[1].gain(), 0.0);
+        music.set_intensity(0.8);
+        music.update(0.5);
+        assert_eq!(music.current_track().unwrap().stems()[1].gain(), 0.5);
+        music.update(0.5);
+        assert_eq!(music.current_track().unwrap().stems()[1].gain(), 1.0);
+
+        let mut streams = vec![ConstantStream(i16::max_value(), 1), ConstantStream(i16::max_value(), 1)];
+        let mixed = music.mix_packet(streams.as_mut_slice()).unwrap().unwrap();
+        assert_eq!(mixed[0][0], 2.0);
+        assert!(music.mix_packet(streams.as_mut_slice()).unwrap().is_none());
+    }
+
+    #[test]
+    fn music_track_invalid_tempo_or_bar() {
+        for &(tempo_bpm, beats_per_bar) in [(0.0, 4), (-120.0, 4), (f64::NAN, 4), (f64::INFINITY, 4), (120.0, 0)].iter() {
+            match MusicTrack::new("silence", tempo_bpm, beats_per_bar) {
+                Err(AudioError::MusicError(_)) => {},
+                _ => panic!("The music track {} bpm, {} beats per bar has been accepted.", tempo_bpm, beats_per_bar),
+            }
+        }
+    }
+}
@@ -3,4 +3,26 @@
 // Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
 // http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
-// copied, modified, or distributed except according to those terms.
\ No newline at end of file
+// copied, modified, or distributed except according to those terms.
+
+extern crate maskerad_resource_management;
+extern crate maskerad_core;
+#[macro_use]
+extern crate log;
+
+extern crate serde_json;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+pub mod audio_error;
+pub mod dynamic_music;
+pub mod soundbank;
+pub mod output_device;
+#[cfg(feature = "voice")]
+pub mod voice;
+
+//The version of the audio subsystem.
+pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+//Whether the voice chat was compiled in (the "voice" feature).
+pub const VOICE_SUPPORT: bool = cfg!(feature = "voice");
@@ -0,0 +1,225 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ AUDIO OUTPUT DEVICES.
+
+ The output devices come and go (headphones unplugged, USB headset plugged in). When the platform
+ notifies a change, the devices are enumerated again:
+ - the device chosen by the player is used whenever it is present, it is saved in the engine config,
+ - without choice, or while the chosen device is absent, the default device of the system is used,
+ - only the output stream is reopened on a switch: the mixer keeps running, and the sounds continue
+   on the new device.
+
+ Without any output device, the audio system is degraded: the mixer runs, its output is dropped.
+*/
+
+use std::collections::VecDeque;
+use maskerad_core::engine_configuration::engine_config::EngineConfig;
+use maskerad_core::system_status::SystemStatus;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioDeviceInfo {
+    //Stable across the runs, saved in the config.
+    pub id: String,
+    pub name: String,
+    pub is_default: bool,
+}
+
+//Implemented by the platform layer.
+pub trait AudioBackend {
+    fn enumerate_outputs(&mut self) -> Vec<AudioDeviceInfo>;
+
+    //The stream pulls the samples from the mixer.
+    fn open_output(&mut self, id: &str) -> Result<(), String>;
+
+    fn close_output(&mut self);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioDeviceEvent {
+    Added(AudioDeviceInfo),
+    Removed(String),
+    //From the previous device (None if there was no device) to the new one.
+    Switched { from: Option<String>, to: String },
+    NoDevice,
+}
+
+pub struct OutputDevices<B: AudioBackend> {
+    backend: B,
+    devices: Vec<AudioDeviceInfo>,
+    preferred: Option<String>,
+    current: Option<String>,
+    events: VecDeque<AudioDeviceEvent>,
+}
+
+impl<B: AudioBackend> OutputDevices<B> {
+    //The preferred device is read from the config.
+    pub fn new(backend: B, config: &EngineConfig) -> Self {
+        let mut devices = OutputDevices {
+            backend,
+            devices: Vec::new(),
+            preferred: config.audio_output_device().map(|device| device.to_string()),
+            current: None,
+            events: VecDeque::new(),
+        };
+        devices.refresh();
+        devices
+    }
+
+    pub fn devices(&self) -> &[AudioDeviceInfo] {
+        self.devices.as_slice()
+    }
+
+    pub fn current(&self) -> Option<&AudioDeviceInfo> {
+        match self.current {
+            Some(ref current) => self.devices.iter().find(|device| device.id == *current),
+            None => None,
+        }
+    }
+
+    pub fn poll_events(&mut self) -> Option<AudioDeviceEvent> {
+        self.events.pop_front()
+    }
+
+    pub fn status(&self) -> SystemStatus {
+        match self.current {
+            Some(_) => SystemStatus::Ok,
+            None => SystemStatus::Degraded(String::from("no audio output device")),
+        }
+    }
+
+    //Choose the output device, None to follow the default device. The choice is saved in the config.
+    pub fn select(&mut self, id: Option<&str>, config: &mut EngineConfig) {
+        debug!("Audio output device selected: {}.", id.unwrap_or("system default"));
+        self.preferred = id.map(|id| id.to_string());
+        config.set_audio_output_device(self.preferred.clone());
+        self.switch_to_wanted();
+    }
+
+    //Called when the platform notifies a change of the devices.
+    pub fn refresh(&mut self) {
+        let devices = self.backend.enumerate_outputs();
+        let mut events = Vec::new();
+        for device in devices.iter().filter(|device| !self.devices.iter().any(|known| known.id == device.id)) {
+            debug!("Audio output device added: {}.", device.name);
+            events.push(AudioDeviceEvent::Added(device.clone()));
+        }
+        for known in self.devices.iter().filter(|known| !devices.iter().any(|device| device.id == known.id)) {
+            debug!("Audio output device removed: {}.", known.name);
+            events.push(AudioDeviceEvent::Removed(known.id.clone()));
+        }
+        self.events.extend(events);
+        self.devices = devices;
+        self.switch_to_wanted();
+    }
+
+    //The preferred device if present, else the default one, else the first one.
+    fn wanted(&self) -> Option<String> {
+        self.preferred.as_ref()
+            .and_then(|preferred| self.devices.iter().find(|device| device.id == *preferred))
+            .or_else(|| self.devices.iter().find(|device| device.is_default))
+            .or_else(|| self.devices.first())
+            .map(|device| device.id.clone())
+    }
+
+    fn switch_to_wanted(&mut self) {
+        let wanted = self.wanted();
+        let current_present = self.current.as_ref().map(|current| self.devices.iter().any(|device| device.id == *current)).unwrap_or(false);
+        if wanted == self.current && current_present {
+            return;
+        }
+        let previous = self.current.take();
+        self.backend.close_output();
+        match wanted {
+            Some(id) => match self.backend.open_output(id.as_str()) {
+                Ok(_) => {
+                    debug!("Audio output switched to {}.", id);
+                    self.current = Some(id.clone());
+                    self.events.push_back(AudioDeviceEvent::Switched {
+                        from: previous,
+                        to: id,
+                    });
+                },
+                Err(reason) => {
+                    error!("Could not open the audio output {}: {}", id, reason);
+                    self.events.push_back(AudioDeviceEvent::NoDevice);
+                },
+            },
+            None => {
+                if previous.is_some() {
+                    warn!("No audio output device left, the audio is muted.");
+                    self.events.push_back(AudioDeviceEvent::NoDevice);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod output_device_test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct FakeBackend {
+        devices: Rc<RefCell<Vec<AudioDeviceInfo>>>,
+        opened: Rc<RefCell<Option<String>>>,
+    }
+
+    impl AudioBackend for FakeBackend {
+        fn enumerate_outputs(&mut self) -> Vec<AudioDeviceInfo> {
+            self.devices.borrow().clone()
+        }
+
+        fn open_output(&mut self, id: &str) -> Result<(), String> {
+            *self.opened.borrow_mut() = Some(id.to_string());
+            Ok(())
+        }
+
+        fn close_output(&mut self) {
+            *self.opened.borrow_mut() = None;
+        }
+    }
+
+    fn device(id: &str, is_default: bool) -> AudioDeviceInfo {
+        AudioDeviceInfo {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+            is_default,
+        }
+    }
+
+    #[test]
+    fn output_device_hotplug_and_selection() {
+        let plugged = Rc::new(RefCell::new(vec![device("speakers", true), device("headphones", false)]));
+        let opened = Rc::new(RefCell::new(None));
+        let mut config = EngineConfig::default();
+        config.set_audio_output_device(String::from("headphones"));
+        let mut outputs = OutputDevices::new(FakeBackend { devices: plugged.clone(), opened: opened.clone() }, &config);
+        assert_eq!(*opened.borrow(), Some(String::from("headphones")));
+
+        //Headphones unplugged: back to the default device, and again on the headphones when plugged back.
+        plugged.borrow_mut().pop();
+        outputs.refresh();
+        assert_eq!(outputs.current().unwrap().id, "speakers");
+        plugged.borrow_mut().push(device("headphones", false));
+        outputs.refresh();
+        assert_eq!(*opened.borrow(), Some(String::from("headphones")));
+
+        outputs.select(None, &mut config);
+        assert_eq!(config.audio_output_device(), None);
+        assert_eq!(outputs.current().unwrap().id, "speakers");
+
+        plugged.borrow_mut().clear();
+        outputs.refresh();
+        assert!(!outputs.status().is_ok());
+        let events: Vec<AudioDeviceEvent> = ::std::iter::from_fn(|| outputs.poll_events()).collect();
+        assert_eq!(events.last(), Some(&AudioDeviceEvent::NoDevice));
+        assert_eq!(events[0], AudioDeviceEvent::Added(device("speakers", true)));
+    }
+}
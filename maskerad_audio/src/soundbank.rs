@@ -0,0 +1,243 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SOUNDBANKS.
+
+ A soundbank is a JSON file, written by the sound designers, describing audio events:
+
+ {
+    "events": {
+        "footstep_grass": {
+            "clips": ["sounds/footstep_grass_01.ogg", "sounds/footstep_grass_02.ogg"],
+            "volume": 0.8,
+            "volume_variance": 0.1,
+            "pitch_variance": 0.05,
+            "cooldown": 0.1,
+            "bus": "sfx"
+        }
+    }
+ }
+
+ Gameplay code never refers to sound files directly, it posts events by name:
+ audio_events.post("footstep_grass"). The event picks a random clip, applies some random
+ variations to the volume and pitch, and tells on which bus the sound must be played.
+*/
+
+use std::collections::HashMap;
+use std::io::Read;
+use serde_json;
+use maskerad_core::random::RandomNumber;
+use audio_error::{AudioError, AudioResult};
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+fn default_pitch() -> f32 {
+    1.0
+}
+
+fn default_bus() -> String {
+    String::from("master")
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioEvent {
+    clips: Vec<String>,
+    #[serde(default = "default_volume")]
+    volume: f32,
+    #[serde(default)]
+    volume_variance: f32,
+    #[serde(default = "default_pitch")]
+    pitch: f32,
+    #[serde(default)]
+    pitch_variance: f32,
+    //Minimum number of seconds between two plays of this event.
+    #[serde(default)]
+    cooldown: f64,
+    #[serde(default = "default_bus")]
+    bus: String,
+}
+
+impl AudioEvent {
+    pub fn clips(&self) -> &[String] {
+        self.clips.as_slice()
+    }
+
+    pub fn cooldown(&self) -> f64 {
+        self.cooldown
+    }
+
+    pub fn bus(&self) -> &str {
+        self.bus.as_str()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Soundbank {
+    events: HashMap<String, AudioEvent>,
+}
+
+impl Soundbank {
+    pub fn from_reader<R: Read>(reader: R) -> AudioResult<Self> {
+        debug!("Deserializing a soundbank.");
+        serde_json::from_reader(reader).map_err(|json_error| {
+            AudioError::from(json_error)
+        })
+    }
+
+    pub fn get<S>(&self, event_name: S) -> Option<&AudioEvent> where
+        S: AsRef<str>
+    {
+        self.events.get(event_name.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+//What the mixer has to play after an event has been posted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayRequest {
+    pub clip: String,
+    pub volume: f32,
+    pub pitch: f32,
+    pub bus: String,
+}
+
+pub struct AudioEvents {
+    soundbanks: Vec<Soundbank>,
+    //Game time (in seconds) at which each event has been played for the last time.
+    last_played: HashMap<String, f64>,
+    time: f64,
+    rng: RandomNumber,
+}
+
+impl AudioEvents {
+    pub fn new() -> Self {
+        AudioEvents {
+            soundbanks: Vec::new(),
+            last_played: HashMap::new(),
+            time: 0.0,
+            rng: RandomNumber::new(),
+        }
+    }
+
+    pub fn add_soundbank(&mut self, soundbank: Soundbank) {
+        debug!("Adding a soundbank with {} events.", soundbank.len());
+        self.soundbanks.push(soundbank);
+    }
+
+    pub fn update(&mut self, delta_seconds: f64) {
+        self.time += delta_seconds;
+    }
+
+    fn find_event(&self, event_name: &str) -> Option<&AudioEvent> {
+        //The last soundbank added wins, so a level soundbank can override the global one.
+        self.soundbanks.iter().rev().filter_map(|soundbank| soundbank.get(event_name)).next()
+    }
+
+    fn vary(rng: &mut RandomNumber, value: f32, variance: f32) -> f32 {
+        if variance > 0.0 {
+            rng.gen_range(value - variance, value + variance)
+        } else {
+            value
+        }
+    }
+
+    //Trigger an event. Returns None if the event is still in its cooldown, or has no clips.
+    pub fn post<S>(&mut self, event_name: S) -> AudioResult<Option<PlayRequest>> where
+        S: AsRef<str>
+    {
+        debug!("Posting the audio event {}.", event_name.as_ref());
+        let event = match self.find_event(event_name.as_ref()) {
+            Some(event) => event.clone(),
+            None => {
+                error!("The audio event {} could not be found in the soundbanks !", event_name.as_ref());
+                return Err(AudioError::UnknownEvent(format!(
+                    "The event {} is not defined in any soundbank.",
+                    event_name.as_ref()
+                )));
+            },
+        };
+
+        if let Some(last_played) = self.last_played.get(event_name.as_ref()) {
+            if self.time - last_played < event.cooldown {
+                trace!("The audio event {} is still in its cooldown.", event_name.as_ref());
+                return Ok(None);
+            }
+        }
+
+        if event.clips.is_empty() {
+            warn!("The audio event {} has no clips.", event_name.as_ref());
+            return Ok(None);
+        }
+
+        let clip_index = self.rng.gen_range(0, event.clips.len());
+        let volume = AudioEvents::vary(&mut self.rng, event.volume, event.volume_variance).max(0.0);
+        let pitch = AudioEvents::vary(&mut self.rng, event.pitch, event.pitch_variance).max(0.0);
+
+        self.last_played.insert(event_name.as_ref().to_string(), self.time);
+
+        Ok(Some(PlayRequest {
+            clip: event.clips[clip_index].clone(),
+            volume,
+            pitch,
+            bus: event.bus,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod soundbank_test {
+    use super::*;
+
+    const SOUNDBANK: &'static str = r#"{
+        "events": {
+            "footstep_grass": {
+                "clips": ["footstep_grass_01.ogg", "footstep_grass_02.ogg"],
+                "volume": 0.8,
+                "volume_variance": 0.1,
+                "cooldown": 0.25,
+                "bus": "sfx"
+            },
+            "ui_click": {
+                "clips": ["click.ogg"]
+            }
+        }
+    }"#;
+
+    #[test]
+    fn soundbank_post_event() {
+        let soundbank = Soundbank::from_reader(SOUNDBANK.as_bytes()).unwrap();
+        assert_eq!(soundbank.len(), 2);
+        assert_eq!(soundbank.get("ui_click").unwrap().bus(), "master");
+
+        let mut audio = AudioEvents::new();
+        audio.add_soundbank(soundbank);
+
+        let request = audio.post("footstep_grass").unwrap().unwrap();
+        assert!(request.clip.starts_with("footstep_grass"));
+        assert!(request.volume >= 0.7 && request.volume <= 0.9);
+        assert_eq!(request.pitch, 1.0);
+        assert_eq!(request.bus, "sfx");
+
+        //cooldown
+        audio.update(0.1);
+        assert!(audio.post("footstep_grass").unwrap().is_none());
+        audio.update(0.2);
+        assert!(audio.post("footstep_grass").unwrap().is_some());
+
+        assert!(audio.post("explosion").is_err());
+    }
+}
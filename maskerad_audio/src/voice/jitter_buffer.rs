@@ -0,0 +1,143 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::BTreeMap;
+use voice::voice_packet::VoicePacket;
+
+//The sequence numbers wrap around: a is before b if b is less than half of the range ahead of it.
+pub fn sequence_before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+//Packets arrive late, out of order or not at all. The jitter buffer keeps a few packets
+//in reserve and gives them back in order, at the playback rate.
+#[derive(Debug, PartialEq)]
+pub enum JitterOutput {
+    Frame(Vec<u8>),
+    //The next packet never came, the codec must conceal it.
+    Lost,
+    //Not enough packets to start the playback.
+    Buffering,
+}
+
+#[derive(Debug)]
+pub struct JitterBuffer {
+    packets: BTreeMap<u32, Vec<u8>>,
+    next_sequence: Option<u32>,
+    target_depth: usize,
+    buffering: bool,
+}
+
+impl JitterBuffer {
+    pub fn new(target_depth: usize) -> Self {
+        JitterBuffer {
+            packets: BTreeMap::new(),
+            next_sequence: None,
+            target_depth: target_depth.max(1),
+            buffering: true,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    pub fn push(&mut self, packet: VoicePacket) {
+        let sequence = packet.sequence();
+        if let Some(next) = self.next_sequence {
+            if sequence_before(sequence, next) {
+                trace!("Dropping the late voice packet {}.", sequence);
+                return;
+            }
+        }
+        self.packets.insert(sequence, packet.into_payload());
+    }
+
+    //The first packet in the sequence order, which is not the smallest key after a wrap around.
+    fn earliest(&self) -> Option<u32> {
+        self.packets.keys().cloned().fold(None, |earliest, sequence| match earliest {
+            Some(earliest) if sequence_before(earliest, sequence) => Some(earliest),
+            _ => Some(sequence),
+        })
+    }
+
+    pub fn pop(&mut self) -> JitterOutput {
+        if self.buffering {
+            if self.packets.len() < self.target_depth {
+                return JitterOutput::Buffering;
+            }
+            self.buffering = false;
+        }
+
+        let next = match self.next_sequence {
+            Some(next) => next,
+            None => match self.earliest() {
+                Some(earliest) => earliest,
+                None => return JitterOutput::Buffering,
+            },
+        };
+
+        if self.packets.is_empty() {
+            //We ran dry, buffer again before resuming the playback.
+            self.buffering = true;
+            self.next_sequence = None;
+            return JitterOutput::Buffering;
+        }
+
+        self.next_sequence = Some(next.wrapping_add(1));
+        match self.packets.remove(&next) {
+            Some(payload) => JitterOutput::Frame(payload),
+            None => JitterOutput::Lost,
+        }
+    }
+}
+
+#[cfg(test)]
+mod jitter_buffer_test {
+    use super::*;
+
+    fn packet(sequence: u32) -> VoicePacket {
+        VoicePacket::new(1, sequence, vec![sequence as u8])
+    }
+
+    #[test]
+    fn jitter_buffer_reorders_and_detects_losses() {
+        let mut buffer = JitterBuffer::new(2);
+        buffer.push(packet(1));
+        assert_eq!(buffer.pop(), JitterOutput::Buffering);
+        buffer.push(packet(0));
+        buffer.push(packet(3));
+
+        assert_eq!(buffer.pop(), JitterOutput::Frame(vec![0]));
+        assert_eq!(buffer.pop(), JitterOutput::Frame(vec![1]));
+        assert_eq!(buffer.pop(), JitterOutput::Lost);
+        //too late
+        buffer.push(packet(2));
+        assert_eq!(buffer.pop(), JitterOutput::Frame(vec![3]));
+        assert_eq!(buffer.pop(), JitterOutput::Buffering);
+    }
+
+    #[test]
+    fn jitter_buffer_sequence_wraps_around() {
+        assert!(sequence_before(u32::MAX, 0));
+        assert!(!sequence_before(0, u32::MAX));
+
+        let mut buffer = JitterBuffer::new(2);
+        buffer.push(packet(0));
+        buffer.push(packet(u32::MAX));
+        assert_eq!(buffer.pop(), JitterOutput::Frame(vec![255]));
+        assert_eq!(buffer.pop(), JitterOutput::Frame(vec![0]));
+        buffer.push(packet(1));
+        buffer.push(packet(u32::MAX));
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.pop(), JitterOutput::Frame(vec![1]));
+    }
+}
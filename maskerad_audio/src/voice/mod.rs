@@ -0,0 +1,26 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ VOICE CHAT.
+
+ capture (microphone) -> codec (encode) -> VoicePacket -> network transport
+ network transport -> VoicePacket -> jitter buffer -> codec (decode) -> voice source (spatialization) -> mixer
+
+ Scope: this module is the engine side of the pipeline only, without any platform or network code.
+ - Provided: the packet format, the jitter buffer, the spatialized voice source, and a raw PCM codec
+   (LAN and tests).
+ - Not provided: a microphone backend (VoiceCapture), an Opus codec (VoiceCodec) and the transport.
+   The platform layer implements the two traits, an Opus codec binds libopus there.
+ - The packets are bytes (VoicePacket::to_bytes/from_bytes): the game sends them itself, on a
+   maskerad_network session or on its own transport. The voice chat doesn't depend on maskerad_network.
+*/
+
+pub mod voice_packet;
+pub mod voice_codec;
+pub mod jitter_buffer;
+pub mod voice_source;
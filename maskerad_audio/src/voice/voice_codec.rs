@@ -0,0 +1,151 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use audio_error::{AudioError, AudioResult};
+use voice::voice_packet::VoicePacket;
+
+//A microphone. read_frame fills the frame with mono samples and returns false if
+//no full frame is available yet.
+pub trait VoiceCapture {
+    fn sample_rate(&self) -> u32;
+    fn read_frame(&mut self, frame: &mut [i16]) -> AudioResult<bool>;
+}
+
+pub trait VoiceCodec {
+    fn encode(&mut self, pcm: &[i16]) -> AudioResult<Vec<u8>>;
+    fn decode(&mut self, data: &[u8]) -> AudioResult<Vec<i16>>;
+
+    //Packet loss concealment. Codecs like Opus can do much better than silence.
+    fn conceal(&mut self, frame_size: usize) -> Vec<i16> {
+        vec![0; frame_size]
+    }
+}
+
+//Uncompressed 16 bits PCM. Only suitable for LAN and tests.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PcmCodec;
+
+impl VoiceCodec for PcmCodec {
+    fn encode(&mut self, pcm: &[i16]) -> AudioResult<Vec<u8>> {
+        let mut data = Vec::with_capacity(pcm.len() * 2);
+        for sample in pcm {
+            data.push(*sample as u8);
+            data.push((*sample >> 8) as u8);
+        }
+        Ok(data)
+    }
+
+    fn decode(&mut self, data: &[u8]) -> AudioResult<Vec<i16>> {
+        if data.len() % 2 != 0 {
+            return Err(AudioError::VoiceError(format!(
+                "A PCM voice payload must have an even length, got {} bytes.",
+                data.len()
+            )));
+        }
+        Ok(data.chunks(2).map(|bytes| (bytes[0] as u16 | (bytes[1] as u16) << 8) as i16).collect())
+    }
+}
+
+//Reads the microphone, encodes the frames and numbers the packets.
+pub struct VoiceTransmitter<C: VoiceCapture, K: VoiceCodec> {
+    capture: C,
+    codec: K,
+    speaker_id: u32,
+    sequence: u32,
+    frame: Vec<i16>,
+    muted: bool,
+}
+
+impl<C: VoiceCapture, K: VoiceCodec> VoiceTransmitter<C, K> {
+    //A frame of 20ms is the usual choice for voice.
+    pub fn new(capture: C, codec: K, speaker_id: u32, frame_size: usize) -> Self {
+        debug!("Creating a VoiceTransmitter for the speaker {}, with frames of {} samples.", speaker_id, frame_size);
+        VoiceTransmitter {
+            capture,
+            codec,
+            speaker_id,
+            sequence: 0,
+            frame: vec![0; frame_size],
+            muted: false,
+        }
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn poll(&mut self) -> AudioResult<Option<VoicePacket>> {
+        if !self.capture.read_frame(self.frame.as_mut_slice())? {
+            return Ok(None);
+        }
+        if self.muted {
+            return Ok(None);
+        }
+
+        let payload = self.codec.encode(self.frame.as_slice())?;
+        let packet = VoicePacket::new(self.speaker_id, self.sequence, payload);
+        self.sequence = self.sequence.wrapping_add(1);
+        Ok(Some(packet))
+    }
+}
+
+#[cfg(test)]
+mod voice_codec_test {
+    use super::*;
+
+    //Frames of increasing samples, as long as there are.
+    struct Microphone {
+        frames: u32,
+    }
+
+    impl VoiceCapture for Microphone {
+        fn sample_rate(&self) -> u32 {
+            16000
+        }
+
+        fn read_frame(&mut self, frame: &mut [i16]) -> AudioResult<bool> {
+            if self.frames == 0 {
+                return Ok(false);
+            }
+            self.frames -= 1;
+            for (index, sample) in frame.iter_mut().enumerate() {
+                *sample = index as i16 * 1000 - 1000;
+            }
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn voice_codec_pcm_round_trip() {
+        let mut codec = PcmCodec;
+        let pcm = vec![0, -1, i16::MAX, i16::MIN];
+        let data = codec.encode(pcm.as_slice()).unwrap();
+        assert_eq!(data.len(), 8);
+        assert_eq!(codec.decode(data.as_slice()).unwrap(), pcm);
+        assert!(codec.decode(&[1, 2, 3]).is_err());
+        assert_eq!(codec.conceal(3), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn voice_transmitter_packets() {
+        let mut transmitter = VoiceTransmitter::new(Microphone { frames: 3 }, PcmCodec, 4, 2);
+        let packet = transmitter.poll().unwrap().unwrap();
+        assert_eq!((packet.speaker_id(), packet.sequence()), (4, 0));
+        assert_eq!(PcmCodec.decode(packet.payload()).unwrap(), vec![-1000, 0]);
+
+        //A muted speaker still consumes its frames, but sends nothing.
+        transmitter.set_muted(true);
+        assert!(transmitter.poll().unwrap().is_none());
+        transmitter.set_muted(false);
+        assert_eq!(transmitter.poll().unwrap().unwrap().sequence(), 1);
+        assert!(transmitter.poll().unwrap().is_none());
+    }
+}
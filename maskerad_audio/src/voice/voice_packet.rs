@@ -0,0 +1,94 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use audio_error::{AudioError, AudioResult};
+
+//4 bytes for the speaker id, 4 bytes for the sequence number.
+const HEADER_SIZE: usize = 8;
+
+//An encoded voice frame, as sent over the network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoicePacket {
+    speaker_id: u32,
+    sequence: u32,
+    payload: Vec<u8>,
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.push(value as u8);
+    bytes.push((value >> 8) as u8);
+    bytes.push((value >> 16) as u8);
+    bytes.push((value >> 24) as u8);
+}
+
+impl VoicePacket {
+    pub fn new(speaker_id: u32, sequence: u32, payload: Vec<u8>) -> Self {
+        VoicePacket {
+            speaker_id,
+            sequence,
+            payload,
+        }
+    }
+
+    pub fn speaker_id(&self) -> u32 {
+        self.speaker_id
+    }
+
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        self.payload.as_slice()
+    }
+
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        write_u32(&mut bytes, self.speaker_id);
+        write_u32(&mut bytes, self.sequence);
+        bytes.extend_from_slice(self.payload.as_slice());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> AudioResult<Self> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(AudioError::VoiceError(format!(
+                "A voice packet needs at least {} bytes, got {}.",
+                HEADER_SIZE,
+                bytes.len()
+            )));
+        }
+
+        Ok(VoicePacket {
+            speaker_id: read_u32(&bytes[0..4]),
+            sequence: read_u32(&bytes[4..8]),
+            payload: bytes[HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod voice_packet_test {
+    use super::*;
+
+    #[test]
+    fn voice_packet_bytes() {
+        let packet = VoicePacket::new(7, 0x0102_0304, vec![1, 2, 3]);
+        let bytes = packet.to_bytes();
+        assert_eq!(&bytes[..8], &[7, 0, 0, 0, 4, 3, 2, 1]);
+        assert_eq!(VoicePacket::from_bytes(bytes.as_slice()).unwrap(), packet);
+        assert!(VoicePacket::from_bytes(&bytes[..7]).is_err());
+    }
+}
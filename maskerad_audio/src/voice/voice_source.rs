@@ -0,0 +1,135 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use audio_error::AudioResult;
+use voice::voice_codec::VoiceCodec;
+use voice::voice_packet::VoicePacket;
+use voice::jitter_buffer::{JitterBuffer, JitterOutput};
+
+//A remote speaker, located in the world. Its voice is attenuated with the distance
+//to the listener, and panned between the left and right channels.
+pub struct VoiceSource<K: VoiceCodec> {
+    speaker_id: u32,
+    position: [f32; 3],
+    min_distance: f32,
+    max_distance: f32,
+    codec: K,
+    jitter_buffer: JitterBuffer,
+    frame_size: usize,
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+impl<K: VoiceCodec> VoiceSource<K> {
+    pub fn new(speaker_id: u32, codec: K, frame_size: usize, jitter_depth: usize) -> Self {
+        debug!("Creating a VoiceSource for the speaker {}.", speaker_id);
+        VoiceSource {
+            speaker_id,
+            position: [0.0; 3],
+            min_distance: 1.0,
+            max_distance: 30.0,
+            codec,
+            jitter_buffer: JitterBuffer::new(jitter_depth),
+            frame_size,
+        }
+    }
+
+    pub fn speaker_id(&self) -> u32 {
+        self.speaker_id
+    }
+
+    pub fn set_position(&mut self, position: [f32; 3]) {
+        self.position = position;
+    }
+
+    pub fn set_distances(&mut self, min_distance: f32, max_distance: f32) {
+        self.min_distance = min_distance;
+        self.max_distance = max_distance.max(min_distance);
+    }
+
+    pub fn receive(&mut self, packet: VoicePacket) {
+        if packet.speaker_id() == self.speaker_id {
+            self.jitter_buffer.push(packet);
+        }
+    }
+
+    //(left gain, right gain). listener_right must be normalized.
+    pub fn gains(&self, listener_position: [f32; 3], listener_right: [f32; 3]) -> (f32, f32) {
+        let to_source = sub(self.position, listener_position);
+        let distance = dot(to_source, to_source).sqrt();
+
+        let attenuation = if distance <= self.min_distance {
+            1.0
+        } else if distance >= self.max_distance {
+            0.0
+        } else {
+            1.0 - (distance - self.min_distance) / (self.max_distance - self.min_distance)
+        };
+
+        let pan = if distance > 0.0 {dot(to_source, listener_right) / distance} else {0.0};
+        (attenuation * (1.0 - pan) * 0.5, attenuation * (1.0 + pan) * 0.5)
+    }
+
+    //The next stereo frame to send to the mixer, or None while buffering.
+    pub fn next_frame(&mut self, listener_position: [f32; 3], listener_right: [f32; 3]) -> AudioResult<Option<Vec<Vec<f32>>>> {
+        let pcm = match self.jitter_buffer.pop() {
+            JitterOutput::Frame(data) => self.codec.decode(data.as_slice())?,
+            JitterOutput::Lost => self.codec.conceal(self.frame_size),
+            JitterOutput::Buffering => return Ok(None),
+        };
+
+        let (left_gain, right_gain) = self.gains(listener_position, listener_right);
+        let normalize = 1.0 / i16::max_value() as f32;
+        let left = pcm.iter().map(|sample| *sample as f32 * normalize * left_gain).collect();
+        let right = pcm.iter().map(|sample| *sample as f32 * normalize * right_gain).collect();
+        Ok(Some(vec![left, right]))
+    }
+}
+
+#[cfg(test)]
+mod voice_source_test {
+    use super::*;
+    use voice::voice_codec::PcmCodec;
+
+    fn packet(speaker_id: u32, sequence: u32) -> VoicePacket {
+        VoicePacket::new(speaker_id, sequence, PcmCodec.encode(&[i16::MAX, i16::MAX]).unwrap())
+    }
+
+    #[test]
+    fn voice_source_gains() {
+        let mut source = VoiceSource::new(1, PcmCodec, 2, 1);
+        source.set_distances(1.0, 11.0);
+        source.set_position([6.0, 0.0, 0.0]);
+        //Half way, on the right.
+        assert_eq!(source.gains([0.0; 3], [1.0, 0.0, 0.0]), (0.0, 0.5));
+        source.set_position([0.0, 0.0, -0.5]);
+        assert_eq!(source.gains([0.0; 3], [1.0, 0.0, 0.0]), (0.5, 0.5));
+        source.set_position([0.0, 0.0, 20.0]);
+        assert_eq!(source.gains([0.0; 3], [1.0, 0.0, 0.0]), (0.0, 0.0));
+    }
+
+    #[test]
+    fn voice_source_frames() {
+        let mut source = VoiceSource::new(1, PcmCodec, 2, 2);
+        source.set_position([0.0, 0.0, -1.0]);
+        source.receive(packet(1, 0));
+        //Another speaker.
+        source.receive(packet(2, 1));
+        assert_eq!(source.next_frame([0.0; 3], [1.0, 0.0, 0.0]).unwrap(), None);
+        source.receive(packet(1, 2));
+        assert_eq!(source.next_frame([0.0; 3], [1.0, 0.0, 0.0]).unwrap(), Some(vec![vec![0.5, 0.5], vec![0.5, 0.5]]));
+        //The packet 1 is lost: the codec conceals it.
+        assert_eq!(source.next_frame([0.0; 3], [1.0, 0.0, 0.0]).unwrap(), Some(vec![vec![0.0, 0.0], vec![0.0, 0.0]]));
+        assert!(source.next_frame([0.0; 3], [1.0, 0.0, 0.0]).unwrap().is_some());
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//The SIMD batch math against the scalar versions: cargo bench -p maskerad_core --bench batch_math
+
+#[macro_use]
+extern crate criterion;
+extern crate maskerad_core;
+
+use criterion::Criterion;
+use std::hint::black_box;
+use maskerad_core::math::batch::{self, scalar, Aabb, Matrix4};
+
+const MATRIX: Matrix4 = [
+    0.9, 0.1, 0.0, 0.0,
+    -0.1, 0.9, 0.2, 0.0,
+    0.0, -0.2, 0.9, 0.0,
+    1.0, 2.0, 3.0, 1.0,
+];
+
+fn transform_vec4s(criterion: &mut Criterion) {
+    let input: Vec<[f32; 4]> = (0..10_000).map(|index| [index as f32, 1.0, -(index as f32), 1.0]).collect();
+    let mut output = vec![[0.0; 4]; input.len()];
+    let mut group = criterion.benchmark_group("transform 10000 vec4");
+    group.bench_function("simd", |bencher| bencher.iter(|| batch::transform_vec4s(black_box(&MATRIX), black_box(&input), &mut output)));
+    group.bench_function("scalar", |bencher| bencher.iter(|| scalar::transform_vec4s(black_box(&MATRIX), black_box(&input), &mut output)));
+    group.finish();
+}
+
+fn multiply_palette(criterion: &mut Criterion) {
+    let bones = vec![MATRIX; 256];
+    let inverse_binds = vec![MATRIX; 256];
+    let mut output = vec![[0.0; 16]; 256];
+    let mut group = criterion.benchmark_group("palette of 256 bones");
+    group.bench_function("simd", |bencher| bencher.iter(|| batch::multiply_palette(black_box(&bones), black_box(&inverse_binds), &mut output)));
+    group.bench_function("scalar", |bencher| bencher.iter(|| scalar::multiply_palette(black_box(&bones), black_box(&inverse_binds), &mut output)));
+    group.finish();
+}
+
+fn cull_aabbs(criterion: &mut Criterion) {
+    let planes = [
+        [1.0, 0.0, 0.0, 50.0], [-1.0, 0.0, 0.0, 50.0],
+        [0.0, 1.0, 0.0, 50.0], [0.0, -1.0, 0.0, 50.0],
+        [0.0, 0.0, 1.0, 50.0], [0.0, 0.0, -1.0, 50.0],
+    ];
+    let aabbs: Vec<Aabb> = (0..10_000).map(|index| Aabb {
+        center: [(index % 100) as f32 - 50.0, (index / 100) as f32 - 50.0, (index % 7) as f32 * 20.0],
+        extents: [0.5, 0.5, 0.5],
+    }).collect();
+    let mut visible = vec![false; aabbs.len()];
+    let mut group = criterion.benchmark_group("cull 10000 AABBs");
+    group.bench_function("simd", |bencher| bencher.iter(|| batch::cull_aabbs(black_box(&planes), black_box(&aabbs), &mut visible)));
+    group.bench_function("scalar", |bencher| bencher.iter(|| scalar::cull_aabbs(black_box(&planes), black_box(&aabbs), &mut visible)));
+    group.finish();
+}
+
+criterion_group!(benches, transform_vec4s, multiply_palette, cull_aabbs);
+criterion_main!(benches);
@@ -0,0 +1,215 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ ACCESSIBILITY.
+
+ The [accessibility] section of the engine configuration:
+
+ [accessibility]
+ ui_scale = 1.25
+ colorblind_filter = "Deuteranopia"
+ colorblind_strength = 1.0
+ subtitles_enabled = true
+ closed_captions_enabled = true
+ subtitle_scale = 1.5
+ hold_to_toggle = ["aim", "sprint"]
+ reduce_screen_shake = true
+
+ The systems don't read the settings once: the Accessibility service sends an event for each changed
+ option, so the gameplay, the UI and the renderer follow the options menu live.
+*/
+
+use reflection::{FieldInfo, TypeInfo};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ColorblindFilter {
+    None,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl Default for ColorblindFilter {
+    fn default() -> Self {
+        ColorblindFilter::None
+    }
+}
+
+const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+impl ColorblindFilter {
+    //How the colors are seen (Machado et al. 2009, full severity), in linear RGB.
+    fn simulation(&self) -> [[f32; 3]; 3] {
+        match *self {
+            ColorblindFilter::None => IDENTITY,
+            ColorblindFilter::Protanopia => [[0.152286, 1.052583, -0.204868], [0.114503, 0.786281, 0.099216], [-0.003882, -0.048116, 1.051998]],
+            ColorblindFilter::Deuteranopia => [[0.367322, 0.860646, -0.227968], [0.280085, 0.672501, 0.047413], [-0.011820, 0.042940, 0.968881]],
+            ColorblindFilter::Tritanopia => [[1.255528, -0.076749, -0.178779], [-0.078411, 0.930809, 0.147602], [0.004733, 0.691367, 0.303900]],
+        }
+    }
+
+    //The color matrix of the post-process (daltonization): the colors lost by the deficiency
+    //are shifted to the channels that are still seen. Strength 0 is no correction.
+    pub fn correction_matrix(&self, strength: f32) -> [[f32; 3]; 3] {
+        let shift = match *self {
+            ColorblindFilter::None => return IDENTITY,
+            ColorblindFilter::Protanopia | ColorblindFilter::Deuteranopia => [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]],
+            ColorblindFilter::Tritanopia => [[1.0, 0.0, 0.7], [0.0, 1.0, 0.7], [0.0, 0.0, 0.0]],
+        };
+        let simulation = self.simulation();
+        let mut matrix = IDENTITY;
+        for row in 0..3 {
+            for column in 0..3 {
+                //I + shift * (I - simulation)
+                let error: f32 = (0..3).map(|k| shift[row][k] * (IDENTITY[k][column] - simulation[k][column])).sum();
+                matrix[row][column] = IDENTITY[row][column] + error * strength;
+            }
+        }
+        matrix
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    pub ui_scale: f32,
+    pub colorblind_filter: ColorblindFilter,
+    pub colorblind_strength: f32,
+    pub subtitles_enabled: bool,
+    pub closed_captions_enabled: bool,
+    pub subtitle_scale: f32,
+    //The actions held by default (aim, sprint, crouch...) that are toggled by a press instead.
+    pub hold_to_toggle: Vec<String>,
+    pub reduce_screen_shake: bool,
+}
+
+impl Default for AccessibilitySettings {
+    fn default() -> Self {
+        AccessibilitySettings {
+            ui_scale: 1.0,
+            colorblind_filter: ColorblindFilter::None,
+            colorblind_strength: 1.0,
+            subtitles_enabled: true,
+            closed_captions_enabled: false,
+            subtitle_scale: 1.0,
+            hold_to_toggle: Vec::new(),
+            reduce_screen_shake: false,
+        }
+    }
+}
+
+impl AccessibilitySettings {
+    //The values out of the supported ranges are clamped.
+    pub fn clamped(mut self) -> Self {
+        self.ui_scale = self.ui_scale.max(0.5).min(3.0);
+        self.colorblind_strength = self.colorblind_strength.max(0.0).min(1.0);
+        self.subtitle_scale = self.subtitle_scale.max(0.5).min(4.0);
+        self
+    }
+
+    pub fn is_hold_to_toggle(&self, action: &str) -> bool {
+        self.hold_to_toggle.iter().any(|toggled| toggled == action)
+    }
+
+    //The fields shown in the options menu, with the ranges of clamped. hold_to_toggle is edited with the key bindings.
+    pub fn type_info() -> TypeInfo {
+        TypeInfo::new::<AccessibilitySettings>("AccessibilitySettings")
+            .with_field(FieldInfo::float("ui_scale", |settings: &AccessibilitySettings| settings.ui_scale, |settings: &mut AccessibilitySettings, value| settings.ui_scale = value)
+                .with_range(0.5, 3.0))
+            .with_field(FieldInfo::variant("colorblind_filter", &["None", "Protanopia", "Deuteranopia", "Tritanopia"],
+                |settings: &AccessibilitySettings| settings.colorblind_filter, |settings: &mut AccessibilitySettings, value| settings.colorblind_filter = value))
+            .with_field(FieldInfo::float("colorblind_strength", |settings: &AccessibilitySettings| settings.colorblind_strength, |settings: &mut AccessibilitySettings, value| settings.colorblind_strength = value)
+                .with_range(0.0, 1.0))
+            .with_field(FieldInfo::boolean("subtitles_enabled", |settings: &AccessibilitySettings| settings.subtitles_enabled, |settings: &mut AccessibilitySettings, value| settings.subtitles_enabled = value))
+            .with_field(FieldInfo::boolean("closed_captions_enabled", |settings: &AccessibilitySettings| settings.closed_captions_enabled, |settings: &mut AccessibilitySettings, value| settings.closed_captions_enabled = value))
+            .with_field(FieldInfo::float("subtitle_scale", |settings: &AccessibilitySettings| settings.subtitle_scale, |settings: &mut AccessibilitySettings, value| settings.subtitle_scale = value)
+                .with_range(0.5, 4.0))
+            .with_field(FieldInfo::boolean("reduce_screen_shake", |settings: &AccessibilitySettings| settings.reduce_screen_shake, |settings: &mut AccessibilitySettings, value| settings.reduce_screen_shake = value))
+            .with_serde::<AccessibilitySettings>()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessibilityEvent {
+    UiScaleChanged(f32),
+    ColorblindFilterChanged(ColorblindFilter, f32),
+    SubtitlesChanged,
+    HoldToToggleChanged(Vec<String>),
+    ScreenShakeChanged(bool),
+}
+
+#[derive(Debug, Default)]
+pub struct Accessibility {
+    settings: AccessibilitySettings,
+    events: Vec<AccessibilityEvent>,
+}
+
+impl Accessibility {
+    pub fn new(settings: AccessibilitySettings) -> Self {
+        Accessibility {
+            settings: settings.clamped(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn settings(&self) -> &AccessibilitySettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: AccessibilitySettings) {
+        let settings = settings.clamped();
+        let old = &self.settings;
+        if settings.ui_scale != old.ui_scale {
+            self.events.push(AccessibilityEvent::UiScaleChanged(settings.ui_scale));
+        }
+        if settings.colorblind_filter != old.colorblind_filter || settings.colorblind_strength != old.colorblind_strength {
+            self.events.push(AccessibilityEvent::ColorblindFilterChanged(settings.colorblind_filter, settings.colorblind_strength));
+        }
+        if settings.subtitles_enabled != old.subtitles_enabled || settings.closed_captions_enabled != old.closed_captions_enabled || settings.subtitle_scale != old.subtitle_scale {
+            self.events.push(AccessibilityEvent::SubtitlesChanged);
+        }
+        if settings.hold_to_toggle != old.hold_to_toggle {
+            self.events.push(AccessibilityEvent::HoldToToggleChanged(settings.hold_to_toggle.clone()));
+        }
+        if settings.reduce_screen_shake != old.reduce_screen_shake {
+            self.events.push(AccessibilityEvent::ScreenShakeChanged(settings.reduce_screen_shake));
+        }
+        debug!("Updating the accessibility settings: {} changes.", self.events.len());
+        self.settings = settings;
+    }
+
+    pub fn poll_events(&mut self) -> Vec<AccessibilityEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod accessibility_test {
+    use super::*;
+
+    #[test]
+    fn accessibility_events_and_filters() {
+        assert_eq!(ColorblindFilter::Deuteranopia.correction_matrix(0.0), IDENTITY);
+        let matrix = ColorblindFilter::Protanopia.correction_matrix(1.0);
+        //The red seen by a protanope is moved to the green and the blue.
+        assert_eq!(matrix[0], IDENTITY[0]);
+        assert!(matrix[1][0] > 0.0 && matrix[2][0] > 0.0);
+
+        let mut accessibility = Accessibility::default();
+        let mut settings = accessibility.settings().clone();
+        settings.ui_scale = 10.0;
+        settings.hold_to_toggle.push(String::from("aim"));
+        accessibility.set_settings(settings);
+        assert_eq!(accessibility.poll_events(), vec![
+            AccessibilityEvent::UiScaleChanged(3.0),
+            AccessibilityEvent::HoldToToggleChanged(vec![String::from("aim")]),
+        ]);
+        assert!(accessibility.settings().is_hold_to_toggle("aim"));
+        assert!(accessibility.poll_events().is_empty());
+    }
+}
@@ -9,11 +9,48 @@ use std::io::{Read, Write};
 use std::path::PathBuf;
 use toml;
 use engine_configuration::engine_config_error::{EngineConfigError, EngineConfigResult};
+use engine_configuration::accessibility::AccessibilitySettings;
+use engine_configuration::threading::ThreadingSettings;
+use engine_configuration::video::VideoSettings;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EngineConfig {
     locale: String,
     script: Option<String>,
+    //"lua" or "wasm".
+    #[serde(default = "default_script_backend")]
+    script_backend: String,
+    //The id of the audio output device chosen by the player, None to follow the default device.
+    #[serde(default)]
+    audio_output_device: Option<String>,
+    //None: no cap.
+    #[serde(default)]
+    frame_rate_cap: Option<u32>,
+    #[serde(default)]
+    accessibility: AccessibilitySettings,
+    #[serde(default)]
+    threads: ThreadingSettings,
+    #[serde(default)]
+    video: VideoSettings,
+}
+
+fn default_script_backend() -> String {
+    String::from("lua")
+}
+
+//Merge the tables of the layer into the base, the values of the layer win.
+fn merge_layer(base: &mut toml::value::Table, layer: toml::value::Table) {
+    for (key, value) in layer {
+        match (base.get_mut(key.as_str()), value) {
+            (Some(&mut toml::Value::Table(ref mut base_table)), toml::Value::Table(layer_table)) => {
+                merge_layer(base_table, layer_table);
+                continue;
+            },
+            (_, value) => {
+                base.insert(key, value);
+            },
+        }
+    }
 }
 
 impl Default for EngineConfig {
@@ -21,6 +58,12 @@ impl Default for EngineConfig {
         EngineConfig {
             locale: String::from("EN"),
             script: None,
+            script_backend: default_script_backend(),
+            audio_output_device: None,
+            frame_rate_cap: None,
+            accessibility: AccessibilitySettings::default(),
+            threads: ThreadingSettings::default(),
+            video: VideoSettings::default(),
         }
     }
 }
@@ -33,6 +76,12 @@ impl EngineConfig {
         EngineConfig {
             locale: locale.into(),
             script: script_path.into(),
+            script_backend: default_script_backend(),
+            audio_output_device: None,
+            frame_rate_cap: None,
+            accessibility: AccessibilitySettings::default(),
+            threads: ThreadingSettings::default(),
+            video: VideoSettings::default(),
         }
     }
 
@@ -45,6 +94,24 @@ impl EngineConfig {
         })
     }
 
+    //The layers are TOML documents, from the lowest priority to the highest: the platform profile,
+    //then the user config. A layer only gives the values it changes.
+    pub fn from_layers(layers: &[&str]) -> EngineConfigResult<Self> {
+        let mut merged = toml::value::Table::new();
+        for layer in layers.iter() {
+            match layer.parse::<toml::Value>()? {
+                toml::Value::Table(table) => merge_layer(&mut merged, table),
+                _ => unreachable!("A TOML document is a table."),
+            }
+        }
+        if !merged.contains_key("locale") {
+            merged.insert(String::from("locale"), toml::Value::String(EngineConfig::default().locale));
+        }
+        toml::Value::Table(merged).try_into().map_err(|toml_deser_error| {
+            EngineConfigError::from(toml_deser_error)
+        })
+    }
+
     pub fn save_to_toml<W: Write>(&self, writer: &mut W) -> EngineConfigResult<()> {
         let string = toml::to_string(&self).map_err(|ser_error| {
             EngineConfigError::from(ser_error)
@@ -69,6 +136,30 @@ impl EngineConfig {
         }
     }
 
+    pub fn script_backend(&self) -> &str {
+        self.script_backend.as_str()
+    }
+
+    pub fn audio_output_device(&self) -> Option<&str> {
+        self.audio_output_device.as_ref().map(|device| device.as_str())
+    }
+
+    pub fn frame_rate_cap(&self) -> Option<u32> {
+        self.frame_rate_cap
+    }
+
+    pub fn accessibility(&self) -> &AccessibilitySettings {
+        &self.accessibility
+    }
+
+    pub fn threading(&self) -> &ThreadingSettings {
+        &self.threads
+    }
+
+    pub fn video(&self) -> &VideoSettings {
+        &self.video
+    }
+
     pub fn set_locale<S>(&mut self, locale: S) where
         S: Into<String>
     {
@@ -80,6 +171,34 @@ impl EngineConfig {
     {
         self.script = script_path.into();
     }
+
+    pub fn set_script_backend<S>(&mut self, script_backend: S) where
+        S: Into<String>
+    {
+        self.script_backend = script_backend.into();
+    }
+
+    pub fn set_audio_output_device<S>(&mut self, device: S) where
+        S: Into<Option<String>>
+    {
+        self.audio_output_device = device.into();
+    }
+
+    pub fn set_frame_rate_cap(&mut self, frame_rate_cap: Option<u32>) {
+        self.frame_rate_cap = frame_rate_cap;
+    }
+
+    pub fn set_accessibility(&mut self, accessibility: AccessibilitySettings) {
+        self.accessibility = accessibility;
+    }
+
+    pub fn set_threading(&mut self, threading: ThreadingSettings) {
+        self.threads = threading;
+    }
+
+    pub fn set_video(&mut self, video: VideoSettings) {
+        self.video = video;
+    }
 }
 
 
@@ -94,4 +213,17 @@ mod engine_configuration_test {
     fn test() {
         assert_eq!(1+1, 2)
     }
+
+    #[test]
+    fn engine_config_layers() {
+        let config = EngineConfig::from_layers(&[
+            "frame_rate_cap = 40\n[accessibility]\nui_scale = 1.5\n",
+            "locale = \"FR\"\nframe_rate_cap = 60\n",
+        ]).unwrap();
+        assert_eq!(config.locale(), "FR");
+        assert_eq!(config.accessibility().ui_scale, 1.5);
+        assert_eq!(config.frame_rate_cap(), Some(60));
+        assert_eq!(EngineConfig::from_layers(&[]).unwrap().locale(), "EN");
+        assert!(EngineConfig::from_layers(&["frame_rate_cap = "]).is_err());
+    }
 }
\ No newline at end of file
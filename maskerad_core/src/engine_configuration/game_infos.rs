@@ -0,0 +1,30 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//The identity of the game: shown to the players, and compared between peers on the network.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GameInfos {
+    pub name: String,
+    pub version: String,
+}
+
+impl GameInfos {
+    pub fn new<N, V>(name: N, version: V) -> Self where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        GameInfos {
+            name: name.into(),
+            version: version.into(),
+        }
+    }
+
+    //Only the same game, at the same version, can play together.
+    pub fn is_compatible(&self, other: &GameInfos) -> bool {
+        self == other
+    }
+}
@@ -6,4 +6,9 @@
 // copied, modified, or distributed except according to those terms.
 
 pub mod engine_config;
-pub mod engine_config_error;
\ No newline at end of file
+pub mod engine_config_error;
+pub mod game_infos;
+pub mod accessibility;
+pub mod platform_profile;
+pub mod threading;
+pub mod video;
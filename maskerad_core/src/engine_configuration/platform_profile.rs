@@ -0,0 +1,136 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ PLATFORM PROFILES.
+
+ The defaults of the engine config depend on the device: a Steam Deck is played with a gamepad, on a
+ small screen held close, and with a battery. The profile is detected at startup, and gives the lowest
+ layer of the config, under the config of the user:
+ - a larger UI scale,
+ - the gamepad glyphs of the device,
+ - the frame rate caps offered to the player (30/40/60 on a Deck, where 40 fits the 40Hz mode of the
+   screen), and the default one.
+
+ A Steam Deck is recognized by the SteamDeck environment variable set by Steam (also under Proton), or
+ by the board name of the device on SteamOS.
+*/
+
+use std::env;
+use std::fs;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Platform {
+    Desktop,
+    SteamDeck,
+}
+
+//The board names of the Steam Deck LCD and OLED.
+const STEAM_DECK_BOARDS: [&'static str; 2] = ["Jupiter", "Galileo"];
+const BOARD_NAME_PATH: &'static str = "/sys/devices/virtual/dmi/id/board_name";
+
+impl Platform {
+    //The environment variable lookup and the board name are given, for the tests.
+    pub fn detect_from(variable: &Fn(&str) -> Option<String>, board_name: Option<&str>) -> Self {
+        let deck_variable = variable("SteamDeck").map(|value| value.trim() == "1").unwrap_or(false);
+        let deck_board = board_name.map(|board| STEAM_DECK_BOARDS.contains(&board.trim())).unwrap_or(false);
+        if deck_variable || deck_board {Platform::SteamDeck} else {Platform::Desktop}
+    }
+
+    pub fn detect() -> Self {
+        let board_name = fs::read_to_string(BOARD_NAME_PATH).ok();
+        let platform = Platform::detect_from(&|name| env::var(name).ok(), board_name.as_ref().map(|board| board.as_str()));
+        debug!("Platform detected: {:?}.", platform);
+        platform
+    }
+
+    //Running a Windows build through Proton: the files must stay in the Wine prefix.
+    pub fn is_proton(variable: &Fn(&str) -> Option<String>) -> bool {
+        variable("STEAM_COMPAT_DATA_PATH").is_some() || variable("WINEPREFIX").is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlatformProfile {
+    pub platform: Platform,
+    pub ui_scale: f32,
+    pub gamepad_first: bool,
+    //The glyph family of the gamepad built in the device, if any.
+    pub glyph_family: Option<&'static str>,
+    pub frame_rate_caps: Vec<u32>,
+    pub default_frame_rate_cap: Option<u32>,
+}
+
+impl PlatformProfile {
+    pub fn for_platform(platform: Platform) -> Self {
+        match platform {
+            Platform::Desktop => PlatformProfile {
+                platform,
+                ui_scale: 1.0,
+                gamepad_first: false,
+                glyph_family: None,
+                frame_rate_caps: vec![30, 60, 120, 144],
+                default_frame_rate_cap: None,
+            },
+            Platform::SteamDeck => PlatformProfile {
+                platform,
+                ui_scale: 1.4,
+                gamepad_first: true,
+                glyph_family: Some("steam_deck"),
+                frame_rate_caps: vec![30, 40, 60],
+                default_frame_rate_cap: Some(60),
+            },
+        }
+    }
+
+    pub fn detect() -> Self {
+        PlatformProfile::for_platform(Platform::detect())
+    }
+
+    //The config layer of the profile, for EngineConfig::from_layers.
+    pub fn config_layer(&self) -> String {
+        let mut layer = String::new();
+        if let Some(cap) = self.default_frame_rate_cap {
+            layer.push_str(format!("frame_rate_cap = {}\n", cap).as_str());
+        }
+        layer.push_str(format!("[accessibility]\nui_scale = {:?}\n", self.ui_scale).as_str());
+        layer
+    }
+
+    //The closest cap offered by the profile, the one chosen by the player may come from another device.
+    pub fn closest_frame_rate_cap(&self, wanted: u32) -> Option<u32> {
+        self.frame_rate_caps.iter().cloned().min_by_key(|cap| (*cap as i64 - wanted as i64).abs())
+    }
+}
+
+#[cfg(test)]
+mod platform_profile_test {
+    use super::*;
+    use engine_configuration::engine_config::EngineConfig;
+
+    #[test]
+    fn platform_profile_detection_and_layer() {
+        let none = |_: &str| None;
+        let deck = |name: &str| if name == "SteamDeck" {Some(String::from("1"))} else {None};
+        assert_eq!(Platform::detect_from(&none, Some("X570 AORUS\n")), Platform::Desktop);
+        assert_eq!(Platform::detect_from(&none, Some("Galileo\n")), Platform::SteamDeck);
+        assert_eq!(Platform::detect_from(&deck, None), Platform::SteamDeck);
+        assert!(!Platform::is_proton(&none));
+
+        let profile = PlatformProfile::for_platform(Platform::SteamDeck);
+        assert_eq!(profile.closest_frame_rate_cap(45), Some(40));
+        assert_eq!(profile.closest_frame_rate_cap(144), Some(60));
+
+        //The user config is over the profile.
+        let layer = profile.config_layer();
+        let config = EngineConfig::from_layers(&[layer.as_str(), "frame_rate_cap = 30\n"]).unwrap();
+        assert_eq!(config.frame_rate_cap(), Some(30));
+        assert_eq!(config.accessibility().ui_scale, 1.4);
+        let config = EngineConfig::from_layers(&[PlatformProfile::for_platform(Platform::Desktop).config_layer().as_str()]).unwrap();
+        assert_eq!(config.frame_rate_cap(), None);
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ THREADING.
+
+ The [threads] section of the engine configuration, a table by thread pool:
+
+ [threads.render]
+ priority = "High"
+ affinity = [0]
+
+ [threads.workers]
+ priority = "Normal"
+ threads = 3
+
+ On handhelds and 4-core CPUs, keeping the workers away from the cores of the render and audio threads
+ avoids the hitches of the audio. Without affinity, the OS places the threads. Without a thread count,
+ the job system sizes the pool from the CPU.
+*/
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ThreadPriority {
+    Low,
+    Normal,
+    High,
+    Highest,
+}
+
+impl Default for ThreadPriority {
+    fn default() -> Self {
+        ThreadPriority::Normal
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoolSettings {
+    pub priority: ThreadPriority,
+    //The logical cores the threads of the pool may run on.
+    pub affinity: Option<Vec<usize>>,
+    //For the pools with several threads.
+    pub threads: Option<usize>,
+}
+
+impl PoolSettings {
+    pub fn with_priority(priority: ThreadPriority) -> Self {
+        PoolSettings {
+            priority,
+            affinity: None,
+            threads: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThreadingSettings {
+    pub render: PoolSettings,
+    pub audio: PoolSettings,
+    pub workers: PoolSettings,
+    //The I/O scheduler, it mostly waits for the disk.
+    pub io: PoolSettings,
+}
+
+impl Default for ThreadingSettings {
+    fn default() -> Self {
+        ThreadingSettings {
+            render: PoolSettings::with_priority(ThreadPriority::High),
+            audio: PoolSettings::with_priority(ThreadPriority::Highest),
+            workers: PoolSettings::with_priority(ThreadPriority::Normal),
+            io: PoolSettings::with_priority(ThreadPriority::Low),
+        }
+    }
+}
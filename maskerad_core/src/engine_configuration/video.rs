@@ -0,0 +1,145 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ VIDEO.
+
+ The [video] section of the engine configuration:
+
+ [video]
+ dynamic_resolution = true
+ min_resolution_scale = 0.6
+ max_resolution_scale = 1.0
+ upscaler = "Sharpened"
+ hdr_output = "Auto"
+ tonemapper = "Aces"
+ exposure = 0.0
+ paper_white_nits = 200.0
+ peak_nits = 1000.0
+ gpu_driven = false
+
+ With the dynamic resolution, the scene is rendered at a fraction of the output resolution (between the
+ two bounds, on each axis), chosen from the GPU frame time to hold the target frame rate. The post stack
+ upscales the image to the output resolution.
+
+ The scene is rendered in linear HDR, then tonemapped for the output: SDR, or HDR when the display
+ supports it (see color_management in the renderer). The paper white is the brightness of the UI and of
+ a white surface in HDR, the peak is the brightness of the display.
+
+ gpu_driven: the objects are culled by a compute pass, and drawn with multi-draw indirect (see gpu_driven
+ in the renderer), when the device supports it. For the scenes with tens of thousands of objects.
+*/
+
+use reflection::{FieldInfo, TypeInfo};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Upscaler {
+    Bilinear,
+    //Bilinear, then a contrast adaptive sharpening.
+    Sharpened,
+}
+
+impl Default for Upscaler {
+    fn default() -> Self {
+        Upscaler::Sharpened
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum HdrOutput {
+    Off,
+    //HDR10 or scRGB when the display supports it, SDR otherwise.
+    Auto,
+    Hdr10,
+    ScRgb,
+}
+
+impl Default for HdrOutput {
+    fn default() -> Self {
+        HdrOutput::Auto
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Tonemapper {
+    //A clamp, for the scenes already in the display range.
+    None,
+    Reinhard,
+    Aces,
+}
+
+impl Default for Tonemapper {
+    fn default() -> Self {
+        Tonemapper::Aces
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoSettings {
+    pub dynamic_resolution: bool,
+    pub min_resolution_scale: f32,
+    pub max_resolution_scale: f32,
+    pub upscaler: Upscaler,
+    pub hdr_output: HdrOutput,
+    pub tonemapper: Tonemapper,
+    //In stops.
+    pub exposure: f32,
+    pub paper_white_nits: f32,
+    pub peak_nits: f32,
+    pub gpu_driven: bool,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        VideoSettings {
+            dynamic_resolution: false,
+            min_resolution_scale: 0.5,
+            max_resolution_scale: 1.0,
+            upscaler: Upscaler::default(),
+            hdr_output: HdrOutput::default(),
+            tonemapper: Tonemapper::default(),
+            exposure: 0.0,
+            paper_white_nits: 200.0,
+            peak_nits: 1000.0,
+            gpu_driven: false,
+        }
+    }
+}
+
+impl VideoSettings {
+    //The bounds, sorted and clamped to ]0, 1].
+    pub fn resolution_bounds(&self) -> (f32, f32) {
+        let min = self.min_resolution_scale.max(0.1).min(1.0);
+        let max = self.max_resolution_scale.max(0.1).min(1.0);
+        (min.min(max), max.max(min))
+    }
+
+    //The fields shown in the options menu.
+    pub fn type_info() -> TypeInfo {
+        TypeInfo::new::<VideoSettings>("VideoSettings")
+            .with_field(FieldInfo::boolean("dynamic_resolution", |settings: &VideoSettings| settings.dynamic_resolution, |settings: &mut VideoSettings, value| settings.dynamic_resolution = value))
+            .with_field(FieldInfo::float("min_resolution_scale", |settings: &VideoSettings| settings.min_resolution_scale, |settings: &mut VideoSettings, value| settings.min_resolution_scale = value)
+                .with_range(0.1, 1.0))
+            .with_field(FieldInfo::float("max_resolution_scale", |settings: &VideoSettings| settings.max_resolution_scale, |settings: &mut VideoSettings, value| settings.max_resolution_scale = value)
+                .with_range(0.1, 1.0))
+            .with_field(FieldInfo::variant("upscaler", &["Bilinear", "Sharpened"],
+                |settings: &VideoSettings| settings.upscaler, |settings: &mut VideoSettings, value| settings.upscaler = value))
+            .with_field(FieldInfo::variant("hdr_output", &["Off", "Auto", "Hdr10", "ScRgb"],
+                |settings: &VideoSettings| settings.hdr_output, |settings: &mut VideoSettings, value| settings.hdr_output = value))
+            .with_field(FieldInfo::variant("tonemapper", &["None", "Reinhard", "Aces"],
+                |settings: &VideoSettings| settings.tonemapper, |settings: &mut VideoSettings, value| settings.tonemapper = value))
+            .with_field(FieldInfo::float("exposure", |settings: &VideoSettings| settings.exposure, |settings: &mut VideoSettings, value| settings.exposure = value)
+                .with_range(-4.0, 4.0))
+            .with_field(FieldInfo::float("paper_white_nits", |settings: &VideoSettings| settings.paper_white_nits, |settings: &mut VideoSettings, value| settings.paper_white_nits = value)
+                .with_range(80.0, 500.0))
+            .with_field(FieldInfo::float("peak_nits", |settings: &VideoSettings| settings.peak_nits, |settings: &mut VideoSettings, value| settings.peak_nits = value)
+                .with_range(400.0, 10000.0))
+            .with_field(FieldInfo::boolean("gpu_driven", |settings: &VideoSettings| settings.gpu_driven, |settings: &mut VideoSettings, value| settings.gpu_driven = value))
+            .with_serde::<VideoSettings>()
+    }
+}
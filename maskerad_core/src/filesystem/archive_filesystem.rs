@@ -0,0 +1,394 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ ARCHIVE FILESYSTEM.
+
+ A read-only VFilesystem over a pack file: the shipped games read their content from a few big
+ archives instead of thousands of loose files. Each RootDir is mapped to a directory of the archive
+ (the WorkingDirectory and the AssetRoot to the root of the archive by default), the roots which are
+ not mapped don't exist. Writing, creating or removing anything fails with a PermissionError.
+
+ The archive format is the one written by the cooker (little endian):
+ magic "KPAK", version (u32), entry count (u32)
+ index: for each entry: key (32 bytes, the GUID of the cooked asset), offset (u64), size (u64)
+ data: the entries, one after the other, each one aligned on 16 bytes (zero padding).
+
+ The entries are files named by their key, at the root of the archive. with_path gives them a path as
+ well (the source paths of the content manifest): the directories are deduced from these paths. An open
+ file reads its bytes from the archive on demand: the reader is shared by the open files.
+*/
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use filesystem::game_directories::RootDir;
+use filesystem::open_options::OpenOptions;
+use filesystem::vfilesystem::{join_path, sandbox_path, VFile, VFilesystem, VMetadata};
+use filesystem::vpath::{VPath, VPathBuf};
+
+const ARCHIVE_MAGIC: &'static [u8; 4] = b"KPAK";
+pub const ARCHIVE_VERSION: u32 = 1;
+pub const ARCHIVE_KEY_SIZE: usize = 32;
+const HEADER_SIZE: u64 = 12;
+const ENTRY_SIZE: u64 = ARCHIVE_KEY_SIZE as u64 + 8 + 8;
+const ALIGNMENT: u64 = 16;
+
+fn padding(offset: u64) -> u64 {
+    (ALIGNMENT - offset % ALIGNMENT) % ALIGNMENT
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> FileSystemResult<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> FileSystemResult<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn not_found(path: &str) -> FileSystemError {
+    FileSystemError::IOError(format!("{} is not in the archive.", path), io::Error::new(ErrorKind::NotFound, path.to_string()))
+}
+
+fn read_only(path: &str) -> FileSystemError {
+    FileSystemError::PermissionError(format!("Cannot modify {}, the archive is read-only.", path))
+}
+
+//Packs the entries, sorted by key. Every key has ARCHIVE_KEY_SIZE bytes. Returns the size of the archive.
+pub fn write_archive<K: AsRef<str> + Ord, W: Write>(entries: &BTreeMap<K, Vec<u8>>, mut writer: W) -> FileSystemResult<u64> {
+    let mut header = Vec::with_capacity((HEADER_SIZE + entries.len() as u64 * ENTRY_SIZE) as usize);
+    header.extend_from_slice(ARCHIVE_MAGIC);
+    header.extend_from_slice(&ARCHIVE_VERSION.to_le_bytes());
+    header.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    let mut offset = HEADER_SIZE + entries.len() as u64 * ENTRY_SIZE;
+    let mut paddings = Vec::with_capacity(entries.len());
+    for (key, data) in entries.iter() {
+        let key = key.as_ref();
+        if key.len() != ARCHIVE_KEY_SIZE {
+            return Err(FileSystemError::ExtensionError(format!("The archive key {} does not have {} bytes.", key, ARCHIVE_KEY_SIZE)));
+        }
+        paddings.push(padding(offset));
+        offset += padding(offset);
+        header.extend_from_slice(key.as_bytes());
+        header.extend_from_slice(&offset.to_le_bytes());
+        header.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        offset += data.len() as u64;
+    }
+    writer.write_all(header.as_slice())?;
+    for (data, padding) in entries.values().zip(paddings) {
+        writer.write_all(&[0u8; ALIGNMENT as usize][..padding as usize])?;
+        writer.write_all(data.as_slice())?;
+    }
+    Ok(offset)
+}
+
+//The offset and the size of each entry, by key. The index is checked against the size of the archive:
+//every entry must be in the data section.
+pub fn read_archive_index<R: Read + Seek>(reader: &mut R) -> FileSystemResult<BTreeMap<String, (u64, u64)>> {
+    let archive_size = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != ARCHIVE_MAGIC {
+        return Err(FileSystemError::ExtensionError(String::from("The file is not a KPAK archive.")));
+    }
+    let version = read_u32(reader)?;
+    if version != ARCHIVE_VERSION {
+        return Err(FileSystemError::ExtensionError(format!("KPAK archive version {}, expected {}.", version, ARCHIVE_VERSION)));
+    }
+    let count = read_u32(reader)? as u64;
+    let data_start = HEADER_SIZE + count * ENTRY_SIZE;
+    if data_start > archive_size {
+        return Err(FileSystemError::ExtensionError(format!("The archive announces {} entries, its {} bytes cannot hold their index.", count, archive_size)));
+    }
+    let mut entries = BTreeMap::new();
+    for _ in 0..count {
+        let mut key = [0u8; ARCHIVE_KEY_SIZE];
+        reader.read_exact(&mut key)?;
+        let key = String::from_utf8(key.to_vec()).map_err(|_| FileSystemError::ExtensionError(String::from("A key of the archive is not UTF-8.")))?;
+        let (offset, size) = (read_u64(reader)?, read_u64(reader)?);
+        match offset.checked_add(size) {
+            Some(end) if offset >= data_start && end <= archive_size => {},
+            _ => return Err(FileSystemError::ExtensionError(format!(
+                "The entry {} is at {}..{}+{}, outside of the data of the archive ({}..{}).", key, offset, offset, size, data_start, archive_size
+            ))),
+        }
+        if entries.insert(key.clone(), (offset, size)).is_some() {
+            return Err(FileSystemError::ExtensionError(format!("The entry {} is twice in the archive index.", key)));
+        }
+    }
+    Ok(entries)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ArchiveMetadata {
+    directory: bool,
+    len: u64,
+}
+
+impl VMetadata for ArchiveMetadata {
+    fn is_dir(&self) -> bool {
+        self.directory
+    }
+
+    fn is_file(&self) -> bool {
+        !self.directory
+    }
+
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+//A file of the archive, read on demand.
+pub struct ArchiveFile<R: Read + Seek> {
+    reader: Arc<Mutex<R>>,
+    start: u64,
+    size: u64,
+    position: u64,
+}
+
+impl<R: Read + Seek> Read for ArchiveFile<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size.saturating_sub(self.position);
+        let count = (buffer.len() as u64).min(remaining) as usize;
+        if count == 0 {
+            return Ok(0);
+        }
+        let mut reader = self.reader.lock().map_err(|_| io::Error::new(ErrorKind::Other, "Poisoned archive."))?;
+        reader.seek(SeekFrom::Start(self.start + self.position))?;
+        let count = reader.read(&mut buffer[..count])?;
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Read + Seek> Write for ArchiveFile<R> {
+    fn write(&mut self, _buffer: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(ErrorKind::PermissionDenied, "The archive is read-only."))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Seek for ArchiveFile<R> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.size as i64 + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+        };
+        if position < 0 {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "Seek before the start of the file."));
+        }
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+impl<R: Read + Seek> VFile for ArchiveFile<R> {}
+
+pub struct ArchiveFilesystem<R: Read + Seek> {
+    reader: Arc<Mutex<R>>,
+    //The offset and the size of each file: the entries by key, and by path once named with with_path.
+    entries: BTreeMap<String, (u64, u64)>,
+    count: usize,
+    roots: HashMap<RootDir, String>,
+}
+
+impl<R: Read + Seek + 'static> ArchiveFilesystem<R> {
+    pub fn from_reader(mut reader: R) -> FileSystemResult<Self> {
+        let entries = read_archive_index(&mut reader)?;
+        debug!("Archive opened, {} files.", entries.len());
+        let mut roots = HashMap::new();
+        roots.insert(RootDir::WorkingDirectory, String::new());
+        roots.insert(RootDir::AssetRoot, String::new());
+        Ok(ArchiveFilesystem {
+            reader: Arc::new(Mutex::new(reader)),
+            count: entries.len(),
+            entries,
+            roots,
+        })
+    }
+
+    //Maps the root to a directory of the archive.
+    pub fn with_root(mut self, root_dir: RootDir, directory: &str) -> FileSystemResult<Self> {
+        self.roots.insert(root_dir, sandbox_path(directory)?);
+        Ok(self)
+    }
+
+    //Names the entry with a path, its source path in the content manifest for a cooked asset.
+    pub fn with_path(mut self, path: &str, key: &str) -> FileSystemResult<Self> {
+        let path = sandbox_path(path)?;
+        let entry = match self.entries.get(key) {
+            Some(&entry) => entry,
+            None => return Err(not_found(key)),
+        };
+        self.entries.insert(path, entry);
+        Ok(self)
+    }
+
+    //The number of entries in the archive.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    //The path in the archive.
+    fn archive_path(&self, path: VPath) -> FileSystemResult<String> {
+        match self.roots.get(&path.root()) {
+            Some(directory) => {
+                if path.is_root() {
+                    Ok(directory.clone())
+                } else {
+                    Ok(join_path(directory.as_str(), path.as_str()))
+                }
+            },
+            None => Err(not_found(format!("The {}", path.root()).as_str())),
+        }
+    }
+
+    fn is_directory(&self, path: &str) -> bool {
+        if path.is_empty() {
+            return true;
+        }
+        let prefix = format!("{}/", path);
+        self.entries.range(prefix.clone()..).next().map(|(known, _)| known.starts_with(prefix.as_str())).unwrap_or(false)
+    }
+}
+
+impl<R: Read + Seek + 'static> VFilesystem for ArchiveFilesystem<R> {
+    fn open_with_options(&self, path: VPath, open_options: &OpenOptions) -> FileSystemResult<Box<VFile>> {
+        if open_options.write() || open_options.append() || open_options.create() || open_options.truncate() {
+            return Err(read_only(path.as_str()));
+        }
+        let archive_path = self.archive_path(path)?;
+        match self.entries.get(&archive_path) {
+            Some(&(start, size)) => Ok(Box::new(ArchiveFile {
+                reader: self.reader.clone(),
+                start,
+                size,
+                position: 0,
+            })),
+            None => Err(not_found(archive_path.as_str())),
+        }
+    }
+
+    fn mkdir(&self, path: VPath) -> FileSystemResult<()> {
+        Err(read_only(path.as_str()))
+    }
+
+    fn rm(&self, path: VPath) -> FileSystemResult<()> {
+        Err(read_only(path.as_str()))
+    }
+
+    fn rmrf(&self, path: VPath) -> FileSystemResult<()> {
+        Err(read_only(path.as_str()))
+    }
+
+    fn metadata(&self, path: VPath) -> FileSystemResult<Box<VMetadata>> {
+        let archive_path = self.archive_path(path)?;
+        if let Some(&(_, size)) = self.entries.get(&archive_path) {
+            return Ok(Box::new(ArchiveMetadata { directory: false, len: size }));
+        }
+        if self.is_directory(archive_path.as_str()) {
+            return Ok(Box::new(ArchiveMetadata { directory: true, len: 0 }));
+        }
+        Err(not_found(archive_path.as_str()))
+    }
+
+    fn read_dir(&self, path: VPath) -> FileSystemResult<Vec<VPathBuf>> {
+        let archive_path = self.archive_path(path)?;
+        if !self.is_directory(archive_path.as_str()) {
+            return Err(not_found(archive_path.as_str()));
+        }
+        let prefix = if archive_path.is_empty() {String::new()} else {format!("{}/", archive_path)};
+        let mut entries = self.entries.range(prefix.clone()..)
+            .take_while(|&(known, _)| known.starts_with(prefix.as_str()))
+            .map(|(known, _)| path.join(known[prefix.len()..].split('/').next().unwrap_or("")))
+            .collect::<FileSystemResult<Vec<VPathBuf>>>()?;
+        entries.dedup();
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod archive_filesystem_test {
+    use super::*;
+    use std::io::Cursor;
+
+    const ROCK: &'static str = "0123456789abcdef0123456789abcdef";
+    const GRASS: &'static str = "11111111111111111111111111111111";
+    const README: &'static str = "22222222222222222222222222222222";
+    const ENGINE: &'static str = "33333333333333333333333333333333";
+
+    #[test]
+    fn read_files_from_an_archive() {
+        let mut files = BTreeMap::new();
+        files.insert(ROCK, vec![1, 2, 3]);
+        files.insert(GRASS, vec![4; 40]);
+        files.insert(README, b"hello".to_vec());
+        files.insert(ENGINE, b"fps = 60".to_vec());
+        let mut bytes = Vec::new();
+        let size = write_archive(&files, &mut bytes).unwrap();
+        assert_eq!(size, bytes.len() as u64);
+        let mut short_key = BTreeMap::new();
+        short_key.insert("textures/rock.png", vec![1]);
+        assert!(write_archive(&short_key, Vec::new()).is_err());
+
+        let fs = ArchiveFilesystem::from_reader(Cursor::new(bytes.clone())).unwrap()
+            .with_path("content/textures/rock.png", ROCK).unwrap()
+            .with_path("content/textures/grass.png", GRASS).unwrap()
+            .with_path("content/readme.txt", README).unwrap()
+            .with_path("defaults/engine.toml", ENGINE).unwrap();
+        assert_eq!(fs.len(), 4);
+        assert!(fs.read_to_string(VPath::new(RootDir::AssetRoot, ROCK).unwrap()).is_ok());
+        let fs = fs.with_root(RootDir::WorkingDirectory, "content").unwrap()
+            .with_root(RootDir::EngineConfigRoot, "defaults").unwrap();
+
+        assert_eq!(fs.read_to_string(VPath::new(RootDir::WorkingDirectory, "readme.txt").unwrap()).unwrap(), "hello");
+        let mut grass = fs.open(VPathBuf::new(RootDir::WorkingDirectory, "./textures//grass.png").unwrap().as_vpath()).unwrap();
+        grass.seek(SeekFrom::End(-2)).unwrap();
+        let mut tail = Vec::new();
+        grass.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, vec![4, 4]);
+        assert!(grass.write_all(b"no").is_err());
+
+        assert!(fs.exists(VPath::new(RootDir::EngineConfigRoot, "engine.toml").unwrap()));
+        assert!(!fs.exists(VPath::new(RootDir::UserSaveRoot, "engine.toml").unwrap()));
+        assert!(fs.metadata(VPath::new(RootDir::WorkingDirectory, "textures").unwrap()).unwrap().is_dir());
+        assert_eq!(fs.metadata(VPath::new(RootDir::WorkingDirectory, "textures/grass.png").unwrap()).unwrap().len(), 40);
+        let entries = fs.read_dir(VPath::root_of(RootDir::WorkingDirectory)).unwrap();
+        assert_eq!(entries.iter().map(|entry| entry.as_str()).collect::<Vec<&str>>(), vec!["readme.txt", "textures"]);
+        let entries = fs.read_dir(VPath::new(RootDir::WorkingDirectory, "textures").unwrap()).unwrap();
+        assert_eq!(entries.iter().map(|entry| entry.as_str()).collect::<Vec<&str>>(), vec!["textures/grass.png", "textures/rock.png"]);
+
+        assert!(fs.create(VPath::new(RootDir::WorkingDirectory, "new.txt").unwrap()).is_err());
+        assert!(fs.rmrf(VPath::new(RootDir::WorkingDirectory, "textures").unwrap()).is_err());
+        assert!(VPathBuf::new(RootDir::WorkingDirectory, "../defaults/engine.toml").is_err());
+
+        //The paths and the roots stay in the archive, the keys must be in its index.
+        let open = || ArchiveFilesystem::from_reader(Cursor::new(bytes.clone())).unwrap();
+        assert!(matches!(open().with_path("../engine.toml", ENGINE), Err(FileSystemError::PathEscapesRoot(_))));
+        assert!(matches!(open().with_root(RootDir::WorkingDirectory, "content/.."), Err(FileSystemError::PathEscapesRoot(_))));
+        assert!(open().with_path("engine.toml", "44444444444444444444444444444444").is_err());
+
+        //A truncated archive is refused.
+        bytes.truncate(bytes.len() - 10);
+        assert!(ArchiveFilesystem::from_reader(Cursor::new(bytes)).is_err());
+    }
+}
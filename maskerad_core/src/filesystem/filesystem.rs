@@ -12,6 +12,8 @@ use std::io::{BufReader, BufWriter};
 use filesystem::game_directories::{GameDirectories, RootDir};
 use filesystem::filesystem_error::{FileSystemError, FileSystemResult};
 use filesystem::open_options::OpenOptions;
+use filesystem::vfilesystem::{sandbox_path, VFile, VFilesystem, VMetadata};
+use filesystem::vpath::{VPath, VPathBuf};
 use remove_dir_all;
 
 //Open to read file
@@ -50,6 +52,12 @@ impl Filesystem {
         })
     }
 
+    pub fn with_directories(directories: GameDirectories) -> Self {
+        Filesystem {
+            directories,
+        }
+    }
+
     pub fn get_absolute_path<P: AsRef<Path>>(path: P) -> FileSystemResult<PathBuf> {
         debug!("Getting the absolute path of {}", path.as_ref().display());
         fs::canonicalize(path.as_ref()).map_err(|io_error| FileSystemError::from(io_error))
@@ -149,18 +157,57 @@ impl Filesystem {
         }
     }
 
+    //The path cannot go out of the root: ".." and the absolute paths are refused.
     pub fn construct_path_from_root(
         &self,
         root_dir: RootDir,
         path: &str,
     ) -> FileSystemResult<PathBuf> {
         debug!("Creating the full path of {}, according to the {}", path, root_dir);
+        let path = sandbox_path(path).map_err(|error| {
+            warn!("Refused the path {} in the {}: {}", path, root_dir, error);
+            error
+        })?;
         let mut root_dir = self.path(root_dir)?;
         root_dir.push(path);
         Ok(root_dir)
     }
 }
 
+impl VFilesystem for Filesystem {
+    fn open_with_options(&self, path: VPath, open_options: &OpenOptions) -> FileSystemResult<Box<VFile>> {
+        let file = Filesystem::open_with_options(self.construct_path_from_root(path.root(), path.as_str())?, open_options)?;
+        Ok(Box::new(file))
+    }
+
+    fn mkdir(&self, path: VPath) -> FileSystemResult<()> {
+        Filesystem::mkdir(self.construct_path_from_root(path.root(), path.as_str())?)
+    }
+
+    fn rm(&self, path: VPath) -> FileSystemResult<()> {
+        Filesystem::rm(self.construct_path_from_root(path.root(), path.as_str())?)
+    }
+
+    fn rmrf(&self, path: VPath) -> FileSystemResult<()> {
+        Filesystem::rmrf(self.construct_path_from_root(path.root(), path.as_str())?)
+    }
+
+    fn metadata(&self, path: VPath) -> FileSystemResult<Box<VMetadata>> {
+        let metadata = fs::metadata(self.construct_path_from_root(path.root(), path.as_str())?).map_err(|io_error| FileSystemError::from(io_error))?;
+        Ok(Box::new(metadata))
+    }
+
+    fn read_dir(&self, path: VPath) -> FileSystemResult<Vec<VPathBuf>> {
+        let mut entries = Vec::new();
+        for entry in Filesystem::read_dir(self.construct_path_from_root(path.root(), path.as_str())?)? {
+            let entry = entry.map_err(|io_error| FileSystemError::from(io_error))?;
+            entries.push(path.join(entry.file_name().to_string_lossy().as_ref())?);
+        }
+        entries.sort();
+        Ok(entries)
+    }
+}
+
 #[cfg(test)]
 mod filesystem_test {
     use super::*;
@@ -209,4 +256,17 @@ mod filesystem_test {
         let mut entries = Filesystem::read_dir(src_dir).unwrap();
         assert!(entries.next().is_some());
     }
+
+    #[test]
+    fn filesystem_sandboxed_paths() {
+        let fs = Filesystem::with_directories(GameDirectories::from_roots(PathBuf::from("game"), PathBuf::from("config"), PathBuf::from("data")));
+        assert_eq!(fs.construct_path_from_root(RootDir::UserDataRoot, "./saves//slot_1.sav").unwrap(), Path::new("data/saves/slot_1.sav"));
+        for path in ["../../etc/passwd", "saves/../../secret", "/etc/passwd", "\\\\server\\share", "C:\\Windows", "saves\\..\\..\\x"].iter() {
+            match fs.construct_path_from_root(RootDir::UserDataRoot, path) {
+                Err(FileSystemError::PathEscapesRoot(_)) => {},
+                other => panic!("{} was not refused: {:?}", path, other),
+            }
+        }
+        assert!(VPathBuf::new(RootDir::UserDataRoot, "..").is_err());
+    }
 }
@@ -17,6 +17,9 @@ pub enum FileSystemError {
     IOError(String, IOError),
     EnvironmentError(String, VarError),
     ExtensionError(String),
+    PermissionError(String),
+    PathEscapesRoot(String),
+    InvalidPath(String),
 }
 
 unsafe impl Send for FileSystemError {}
@@ -40,6 +43,15 @@ impl fmt::Display for FileSystemError {
             &FileSystemError::ExtensionError(ref description) => {
                 write!(f, "file extension error: {}", description)
             }
+            &FileSystemError::PermissionError(ref description) => {
+                write!(f, "Permission error: {}", description)
+            }
+            &FileSystemError::PathEscapesRoot(ref description) => {
+                write!(f, "Path escaping its root: {}", description)
+            }
+            &FileSystemError::InvalidPath(ref description) => {
+                write!(f, "Invalid path: {}", description)
+            }
         }
     }
 }
@@ -52,6 +64,9 @@ impl Error for FileSystemError {
             &FileSystemError::EnvironmentError(_, _) => "EnvironmentError",
             &FileSystemError::IOError(_, _) => "IOError",
             &FileSystemError::ExtensionError(_) => "ExtensionError",
+            &FileSystemError::PermissionError(_) => "PermissionError",
+            &FileSystemError::PathEscapesRoot(_) => "PathEscapesRoot",
+            &FileSystemError::InvalidPath(_) => "InvalidPath",
         }
     }
 
@@ -62,6 +77,9 @@ impl Error for FileSystemError {
             &FileSystemError::IOError(_, ref cause) => Some(cause),
             &FileSystemError::EnvironmentError(_, ref cause) => Some(cause),
             &FileSystemError::ExtensionError(_) => None,
+            &FileSystemError::PermissionError(_) => None,
+            &FileSystemError::PathEscapesRoot(_) => None,
+            &FileSystemError::InvalidPath(_) => None,
         }
     }
 }
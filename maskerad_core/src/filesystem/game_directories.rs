@@ -10,12 +10,16 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::env;
 use filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use filesystem::windows_filesystem::windows_directories;
+use filesystem::macos_directories::macos_directories;
 use std::fmt;
 
 //Enum used to specify the 'root' directory from where to write/delete/open dir/files
-#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
 pub enum RootDir {
     WorkingDirectory,
+    //The content shipped with the game, read-only. The working directory on the desktop.
+    AssetRoot,
     UserDataRoot,
     UserConfigRoot,
     EngineConfigRoot,
@@ -29,6 +33,9 @@ impl fmt::Display for RootDir {
             &RootDir::WorkingDirectory => {
                 write!(f, "current directory")
             },
+            &RootDir::AssetRoot => {
+                write!(f, "asset root")
+            },
             &RootDir::UserDataRoot => {
                 write!(f, "user data root")
             },
@@ -64,27 +71,37 @@ impl GameDirectories {
 
         if cfg!(target_os = "windows") {
             trace!("OS: Windows.");
-            trace!("Trying to get the value of the APPDATA environment variable.");
-            let appdata = env::var("APPDATA")?;
-
-            user_config = PathBuf::from(format!("{}\'{}\'{}", appdata.as_str(), game_author.as_ref(), game_name.as_ref()));
-            user_data = PathBuf::from(format!("{}\'{}\'{}", appdata.as_str(), game_author.as_ref(), game_name.as_ref()));
+            //Under Proton, APPDATA is in the Wine prefix of the game, which Steam syncs and sandboxes.
+            return windows_directories(game_name.as_ref(), game_author.as_ref(), &|name| env::var(name).ok(), env::current_dir()?);
         } else if cfg!(target_os = "macos") {
             trace!("OS: MacOS.");
-            unimplemented!();
+            return macos_directories(game_name.as_ref(), game_author.as_ref(), &|name| env::var(name).ok(), env::current_exe().ok().as_deref(), env::current_dir()?);
         } else {
             trace!("OS: Unix/Linux/BSD.");
             trace!("Trying to get the value of the HOME environment variable.");
             let home = env::var("HOME")?;
 
-            user_config = PathBuf::from(format!("{}/.config/{}/{}", home.as_str(), game_author.as_ref(), game_name.as_ref()));
-            user_data = PathBuf::from(format!("{}/.local/share/{}/{}", home.as_str(), game_author.as_ref(), game_name.as_ref()));
+            //The XDG directories are honored: the Steam runtime container may redirect them.
+            let xdg = |variable: &str, fallback: &str| match env::var(variable) {
+                Ok(ref directory) if !directory.is_empty() => PathBuf::from(directory),
+                _ => PathBuf::from(home.as_str()).join(fallback),
+            };
+            user_config = xdg("XDG_CONFIG_HOME", ".config").join(game_author.as_ref()).join(game_name.as_ref());
+            user_data = xdg("XDG_DATA_HOME", ".local/share").join(game_author.as_ref()).join(game_name.as_ref());
         }
 
         trace!("User config path: {}", user_config.display());
         trace!("User data path: {}", user_data.display());
 
+        trace!("Trying to get the path of the current directory...");
+        let current = env::current_dir()?;
+        trace!("Current directory: {}", current.display());
+
+        Ok(GameDirectories::from_roots(current, user_config, user_data))
+    }
 
+    //The platforms without environment variables (Android, iOS, the browser) give their own roots.
+    pub fn from_roots(working_directory: PathBuf, user_config: PathBuf, user_data: PathBuf) -> Self {
         let mut logs = user_config.clone();
         logs.push("maskerad_logs");
         trace!("engine logs path: {}", logs.display());
@@ -97,20 +114,24 @@ impl GameDirectories {
         saves.push("game_saves");
         trace!("game saves path: {}", saves.display());
 
-        trace!("Trying to get the path of the current directory...");
-        let current = env::current_dir()?;
-        trace!("Current directory: {}", current.display());
-
         trace!("Creating the hashmap associating the RootDir enumeration to those paths.");
-        let mut directories = HashMap::with_capacity(6);
-        directories.insert(RootDir::WorkingDirectory, current);
+        let mut directories = HashMap::with_capacity(7);
+        directories.insert(RootDir::AssetRoot, working_directory.clone());
+        directories.insert(RootDir::WorkingDirectory, working_directory);
         directories.insert(RootDir::UserDataRoot, user_data);
         directories.insert(RootDir::UserConfigRoot, user_config);
         directories.insert(RootDir::EngineConfigRoot, engine_config);
         directories.insert(RootDir::EngineLogRoot, logs);
         directories.insert(RootDir::UserSaveRoot, saves);
         trace!("GameDirectories structure successfully created.");
-        Ok(GameDirectories(directories))
+        GameDirectories(directories)
+    }
+
+    //Moves a root, the platforms have their own rules for the logs or the saves.
+    pub fn with_root(mut self, root_dir: RootDir, path: PathBuf) -> Self {
+        trace!("{} moved to {}", root_dir, path.display());
+        self.0.insert(root_dir, path);
+        self
     }
 
     pub fn get(&self, k: &RootDir) -> Option<&Path> {
@@ -0,0 +1,407 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ KEY/VALUE STORE.
+
+ The small pieces of state which don't belong to a save slot: "tutorial_completed", the last profile
+ used, the dismissed hints... A KvStore keeps JSON values by key, in memory, and appends each committed
+ transaction to a journal in the UserDataRoot, a JSON line by transaction:
+
+ {"generation":3,"set":{"tutorial_completed":true},"remove":[]}
+
+ - A transaction is applied entirely or not at all: a line cut by a crash is ignored at the next start.
+   A line cut by a failed write (a full disk) is not followed by the next transactions either: the journal
+   is compacted before them. A transaction is synced to the disk before being applied.
+ - The journal grows with each write, it is compacted when it has more than max_journal_entries lines:
+   the whole content is written as the first line of a new journal, with the next generation, then
+   the previous journal is removed. The two journals alternate, the one with the highest generation
+   and a complete first line wins: a crash during the compaction loses nothing.
+*/
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, ErrorKind, Write};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Map, Value};
+use filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use filesystem::game_directories::RootDir;
+use filesystem::vfilesystem::VFilesystem;
+use filesystem::vpath::{VPath, VPathBuf};
+
+pub const DEFAULT_MAX_JOURNAL_ENTRIES: usize = 256;
+
+fn json_error(description: String, error: serde_json::Error) -> FileSystemError {
+    FileSystemError::IOError(description, io::Error::new(ErrorKind::InvalidData, error))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    generation: u64,
+    #[serde(default)]
+    set: Map<String, Value>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+//The changes of a transaction, applied by KvStore::commit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transaction {
+    set: Map<String, Value>,
+    remove: HashSet<String>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set<T: Serialize>(&mut self, key: &str, value: &T) -> FileSystemResult<&mut Self> {
+        let value = serde_json::to_value(value).map_err(|error| json_error(format!("The value of {} can't be serialized.", key), error))?;
+        self.remove.remove(key);
+        self.set.insert(String::from(key), value);
+        Ok(self)
+    }
+
+    pub fn remove(&mut self, key: &str) -> &mut Self {
+        self.set.remove(key);
+        self.remove.insert(String::from(key));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.remove.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct KvStore {
+    //The journals are <name>.0.journal and <name>.1.journal.
+    name: String,
+    values: BTreeMap<String, Value>,
+    generation: u64,
+    journal_entries: usize,
+    max_journal_entries: usize,
+    //A write in the journal failed, its last line may be incomplete.
+    dirty: bool,
+}
+
+impl KvStore {
+    fn journal(name: &str, generation: u64) -> FileSystemResult<VPathBuf> {
+        VPathBuf::new(RootDir::UserDataRoot, format!("{}.{}.journal", name, generation % 2).as_str())
+    }
+
+    //The entries of a journal, until the first incomplete line, and whether something after them was ignored.
+    //None if the journal doesn't start with a snapshot.
+    fn read_journal(filesystem: &VFilesystem, path: VPath) -> FileSystemResult<Option<(Vec<Entry>, bool)>> {
+        if !filesystem.exists(path) {
+            return Ok(None);
+        }
+        let text = filesystem.read_to_string(path)?;
+        //The last line is only complete with its '\n'.
+        let complete = match text.rfind('\n') {
+            Some(end) => &text[..end + 1],
+            None => "",
+        };
+        if complete.len() < text.len() {
+            warn!("The last transaction of the journal {} was not completely written, it is ignored.", path);
+        }
+        let mut ignored = complete.len() < text.len();
+        let mut entries: Vec<Entry> = Vec::new();
+        for line in complete.split_terminator('\n') {
+            match serde_json::from_str::<Entry>(line) {
+                Ok(ref entry) if entries.first().map(|first| first.generation != entry.generation).unwrap_or(false) => {
+                    warn!("The journal {} has an entry of the generation {}, the rest is ignored.", path, entry.generation);
+                    ignored = true;
+                    break;
+                },
+                Ok(entry) => entries.push(entry),
+                Err(error) => {
+                    warn!("The journal {} has an invalid transaction ({}), the rest is ignored.", path, error);
+                    ignored = true;
+                    break;
+                },
+            }
+        }
+        Ok(if entries.is_empty() {None} else {Some((entries, ignored))})
+    }
+
+    //Reads the journal of the store, in the UserDataRoot. An empty store if there's none.
+    //A journal ending with an incomplete or invalid transaction is compacted at once: the next transactions
+    //would be appended after the broken line, and ignored by the next open.
+    pub fn open(filesystem: &VFilesystem, name: &str) -> FileSystemResult<Self> {
+        let mut store = KvStore {
+            name: String::from(name),
+            values: BTreeMap::new(),
+            generation: 0,
+            journal_entries: 0,
+            max_journal_entries: DEFAULT_MAX_JOURNAL_ENTRIES,
+            dirty: false,
+        };
+        let mut journals = Vec::new();
+        for index in 0..2 {
+            if let Some(entries) = KvStore::read_journal(filesystem, KvStore::journal(name, index)?.as_vpath())? {
+                journals.push(entries);
+            }
+        }
+        journals.sort_by_key(|journal| journal.0[0].generation);
+        if let Some((entries, ignored)) = journals.pop() {
+            store.generation = entries[0].generation;
+            store.journal_entries = entries.len();
+            for entry in entries {
+                store.apply(entry.set, entry.remove);
+            }
+            //The previous journal, if the compaction was interrupted.
+            let previous = KvStore::journal(name, store.generation + 1)?;
+            if filesystem.exists(previous.as_vpath()) {
+                filesystem.rm(previous.as_vpath())?;
+            }
+            if ignored {
+                store.compact(filesystem)?;
+            }
+        }
+        debug!("Key/value store {} opened with {} keys, generation {}.", name, store.values.len(), store.generation);
+        Ok(store)
+    }
+
+    pub fn with_max_journal_entries(mut self, max_journal_entries: usize) -> Self {
+        self.max_journal_entries = max_journal_entries.max(1);
+        self
+    }
+
+    fn apply<I: IntoIterator<Item = String>>(&mut self, set: Map<String, Value>, remove: I) {
+        for key in remove {
+            self.values.remove(&key);
+        }
+        self.values.extend(set);
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+
+    pub fn keys(&self) -> Vec<&str> {
+        self.values.keys().map(|key| key.as_str()).collect()
+    }
+
+    //None if the key is missing, an error if the value has another type.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> FileSystemResult<Option<T>> {
+        match self.values.get(key) {
+            Some(value) => serde_json::from_value(value.clone())
+                .map(Some)
+                .map_err(|error| json_error(format!("The value of {} has another type.", key), error)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_or<T: DeserializeOwned>(&self, key: &str, default: T) -> T {
+        self.get(key).ok().and_then(|value| value).unwrap_or(default)
+    }
+
+    pub fn set<T: Serialize>(&mut self, filesystem: &VFilesystem, key: &str, value: &T) -> FileSystemResult<()> {
+        let mut transaction = Transaction::new();
+        transaction.set(key, value)?;
+        self.commit(filesystem, transaction)
+    }
+
+    pub fn remove(&mut self, filesystem: &VFilesystem, key: &str) -> FileSystemResult<()> {
+        let mut transaction = Transaction::new();
+        transaction.remove(key);
+        self.commit(filesystem, transaction)
+    }
+
+    //Writes the transaction in the journal, then applies it. Nothing is applied if the write fails.
+    pub fn commit(&mut self, filesystem: &VFilesystem, transaction: Transaction) -> FileSystemResult<()> {
+        if transaction.is_empty() {
+            return Ok(());
+        }
+        if self.dirty || self.journal_entries >= self.max_journal_entries {
+            self.compact(filesystem)?;
+        }
+        let mut remove: Vec<String> = transaction.remove.into_iter().collect();
+        remove.sort();
+        let entry = Entry { generation: self.generation, set: transaction.set, remove };
+        let mut line = serde_json::to_string(&entry).map_err(|error| json_error(String::from("The transaction can't be serialized."), error))?;
+        line.push('\n');
+
+        let path = KvStore::journal(self.name.as_str(), self.generation)?;
+        let written = filesystem.append(path.as_vpath()).and_then(|mut journal| {
+            journal.write_all(line.as_bytes())?;
+            journal.sync_all()?;
+            Ok(())
+        });
+        if let Err(error) = written {
+            warn!("The transaction could not be written in the journal {}, it will be compacted.", path);
+            self.dirty = true;
+            return Err(error);
+        }
+        trace!("Transaction committed in the journal {}.", path);
+
+        self.journal_entries += 1;
+        self.apply(entry.set, entry.remove);
+        Ok(())
+    }
+
+    //Writes the whole content in a new journal, and removes the current one.
+    pub fn compact(&mut self, filesystem: &VFilesystem) -> FileSystemResult<()> {
+        let generation = self.generation + 1;
+        let snapshot = Entry {
+            generation,
+            set: self.values.iter().map(|(key, value)| (key.clone(), value.clone())).collect(),
+            remove: Vec::new(),
+        };
+        let mut line = serde_json::to_string(&snapshot).map_err(|error| json_error(String::from("The snapshot can't be serialized."), error))?;
+        line.push('\n');
+
+        let path = KvStore::journal(self.name.as_str(), generation)?;
+        let mut journal = filesystem.create(path.as_vpath())?;
+        journal.write_all(line.as_bytes())?;
+        journal.sync_all()?;
+        let previous = KvStore::journal(self.name.as_str(), self.generation)?;
+        if filesystem.exists(previous.as_vpath()) {
+            filesystem.rm(previous.as_vpath())?;
+        }
+        debug!("Key/value store {} compacted, generation {}.", self.name, generation);
+        self.generation = generation;
+        self.journal_entries = 1;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod kv_store_test {
+    use super::*;
+    use std::cell::Cell;
+    use std::io::{Read, Seek, SeekFrom};
+    use filesystem::memory_filesystem::MemoryFilesystem;
+    use filesystem::open_options::OpenOptions;
+    use filesystem::vfilesystem::{VFile, VMetadata};
+
+    //Writes the first half of the bytes, then fails: a full disk.
+    struct TornFile(Box<VFile>);
+
+    impl Read for TornFile {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buffer)
+        }
+    }
+
+    impl Write for TornFile {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.0.write_all(&buffer[..buffer.len() / 2])?;
+            Err(io::Error::new(ErrorKind::Other, "No space left on the device."))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl Seek for TornFile {
+        fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+            self.0.seek(position)
+        }
+    }
+
+    impl VFile for TornFile {}
+
+    //The files opened to append are torn while full is set.
+    struct FullDisk {
+        filesystem: MemoryFilesystem,
+        full: Cell<bool>,
+    }
+
+    impl VFilesystem for FullDisk {
+        fn open_with_options(&self, path: VPath, open_options: &OpenOptions) -> FileSystemResult<Box<VFile>> {
+            let file = self.filesystem.open_with_options(path, open_options)?;
+            if self.full.get() && open_options.append() {
+                Ok(Box::new(TornFile(file)))
+            } else {
+                Ok(file)
+            }
+        }
+
+        fn mkdir(&self, path: VPath) -> FileSystemResult<()> {
+            self.filesystem.mkdir(path)
+        }
+
+        fn rm(&self, path: VPath) -> FileSystemResult<()> {
+            self.filesystem.rm(path)
+        }
+
+        fn rmrf(&self, path: VPath) -> FileSystemResult<()> {
+            self.filesystem.rmrf(path)
+        }
+
+        fn metadata(&self, path: VPath) -> FileSystemResult<Box<VMetadata>> {
+            self.filesystem.metadata(path)
+        }
+
+        fn read_dir(&self, path: VPath) -> FileSystemResult<Vec<VPathBuf>> {
+            self.filesystem.read_dir(path)
+        }
+    }
+
+    #[test]
+    fn kv_store_journal() {
+        let fs = MemoryFilesystem::new();
+        let mut store = KvStore::open(&fs, "game_state").unwrap().with_max_journal_entries(3);
+        store.set(&fs, "tutorial_completed", &true).unwrap();
+        store.set(&fs, "last_profile", &"ana").unwrap();
+        let mut transaction = Transaction::new();
+        transaction.set("volume", &0.8).unwrap().set("hints", &vec!["jump", "dash"]).unwrap();
+        transaction.remove("last_profile");
+        store.commit(&fs, transaction).unwrap();
+        assert_eq!(store.get::<bool>("tutorial_completed").unwrap(), Some(true));
+        assert_eq!(store.get::<String>("last_profile").unwrap(), None);
+        assert!(store.get::<u32>("hints").is_err());
+        assert_eq!(store.get_or("volume", 1.0), 0.8);
+
+        //The fourth transaction compacts the journal.
+        store.set(&fs, "volume", &0.5).unwrap();
+        assert!(!fs.exists(VPath::new(RootDir::UserDataRoot, "game_state.0.journal").unwrap()));
+        let store = KvStore::open(&fs, "game_state").unwrap();
+        assert_eq!(store.keys(), vec!["hints", "tutorial_completed", "volume"]);
+        assert_eq!(store.get::<f64>("volume").unwrap(), Some(0.5));
+
+        //A crash while writing a transaction.
+        fs.append(VPath::new(RootDir::UserDataRoot, "game_state.1.journal").unwrap()).unwrap().write_all(b"{\"generation\":1,\"set\":{\"vol").unwrap();
+        let mut store = KvStore::open(&fs, "game_state").unwrap();
+        assert_eq!(store.get::<f64>("volume").unwrap(), Some(0.5));
+        //The transactions after the crash are kept.
+        store.set(&fs, "volume", &0.25).unwrap();
+        let mut store = KvStore::open(&fs, "game_state").unwrap();
+        assert_eq!(store.get::<f64>("volume").unwrap(), Some(0.25));
+        assert_eq!(store.keys().len(), 3);
+
+        //A crash during the compaction, before the previous journal is removed.
+        store.compact(&fs).unwrap();
+        let previous = KvStore::journal("game_state", store.generation - 1).unwrap();
+        fs.write_all(previous.as_vpath(), format!("{{\"generation\":{},\"set\":{{}}}}\n", store.generation - 1).as_bytes()).unwrap();
+        let store = KvStore::open(&fs, "game_state").unwrap();
+        assert_eq!(store.keys().len(), 3);
+        assert!(!fs.exists(previous.as_vpath()));
+    }
+    #[test]
+    fn kv_store_failed_write() {
+        let fs = FullDisk { filesystem: MemoryFilesystem::new(), full: Cell::new(false) };
+        let mut store = KvStore::open(&fs, "game_state").unwrap();
+        store.set(&fs, "volume", &0.5).unwrap();
+        fs.full.set(true);
+        assert!(store.set(&fs, "volume", &0.1).is_err());
+        assert_eq!(store.get::<f64>("volume").unwrap(), Some(0.5));
+
+        //The half written line is not followed by the next transactions, they are kept.
+        fs.full.set(false);
+        store.set(&fs, "volume", &0.75).unwrap();
+        store.set(&fs, "tutorial_completed", &true).unwrap();
+        let store = KvStore::open(&fs, "game_state").unwrap();
+        assert_eq!(store.get::<f64>("volume").unwrap(), Some(0.75));
+        assert_eq!(store.get::<bool>("tutorial_completed").unwrap(), Some(true));
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ MACOS DIRECTORIES.
+
+ The directories of a game on macOS, following the rules of the Apple File System Programming Guide:
+ - ~/Library/Application Support/<author>/<game>: the config of the user and of the engine, the data, the saves.
+ - ~/Library/Logs/<author>/<game>: the logs, where the Console app finds them.
+ An application bundle is started by the Finder with / as current directory: the content of the game is in
+ the Contents/Resources directory of the bundle, which becomes the working directory. Outside of a bundle
+ (cargo run), the current directory stays the working directory.
+
+ The Filesystem of the engine works on the macOS filesystem as is, only the directories differ.
+*/
+
+use std::path::{Path, PathBuf};
+use filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use filesystem::game_directories::{GameDirectories, RootDir};
+
+//The Resources directory of the bundle containing the executable, if it is in a bundle.
+pub fn bundle_resources(executable: &Path) -> Option<PathBuf> {
+    let macos = executable.parent()?;
+    let contents = macos.parent()?;
+    let bundle = contents.parent()?;
+    let in_bundle = macos.file_name().map(|name| name == "MacOS").unwrap_or(false)
+        && contents.file_name().map(|name| name == "Contents").unwrap_or(false)
+        && bundle.extension().map(|extension| extension == "app").unwrap_or(false);
+    if in_bundle {
+        Some(contents.join("Resources"))
+    } else {
+        None
+    }
+}
+
+//The environment variable lookup is given, for the tests.
+pub fn macos_directories<S: AsRef<str>>(game_name: S, game_author: S, variable: &Fn(&str) -> Option<String>, executable: Option<&Path>, current_directory: PathBuf) -> FileSystemResult<GameDirectories> {
+    let home = variable("HOME").filter(|home| !home.is_empty())
+        .ok_or_else(|| FileSystemError::GameDirectoryError(String::from("The HOME environment variable is not set.")))?;
+    let library = PathBuf::from(home).join("Library");
+    let support = library.join("Application Support").join(game_author.as_ref()).join(game_name.as_ref());
+    let logs = library.join("Logs").join(game_author.as_ref()).join(game_name.as_ref());
+    let working_directory = executable.and_then(bundle_resources).unwrap_or(current_directory);
+    trace!("Application support: {}, logs: {}, content: {}", support.display(), logs.display(), working_directory.display());
+    Ok(GameDirectories::from_roots(working_directory, support.clone(), support)
+        .with_root(RootDir::EngineLogRoot, logs))
+}
+
+#[cfg(test)]
+mod macos_directories_test {
+    use super::*;
+
+    #[test]
+    fn library_directories() {
+        let variable = |name: &str| if name == "HOME" {Some(String::from("/Users/player"))} else {None};
+        let directories = macos_directories("game", "studio", &variable, None, PathBuf::from("/Users/player/dev/game")).unwrap();
+        let support = Path::new("/Users/player/Library/Application Support/studio/game");
+        assert_eq!(directories.get(&RootDir::UserConfigRoot), Some(support));
+        assert_eq!(directories.get(&RootDir::UserDataRoot), Some(support));
+        assert_eq!(directories.get(&RootDir::UserSaveRoot), Some(support.join("game_saves").as_path()));
+        assert_eq!(directories.get(&RootDir::EngineConfigRoot), Some(support.join("maskerad_configuration").as_path()));
+        assert_eq!(directories.get(&RootDir::EngineLogRoot), Some(Path::new("/Users/player/Library/Logs/studio/game")));
+        assert_eq!(directories.get(&RootDir::WorkingDirectory), Some(Path::new("/Users/player/dev/game")));
+        assert!(macos_directories("game", "studio", &|_| None, None, PathBuf::from("/")).is_err());
+    }
+
+    #[test]
+    fn bundle_content() {
+        let executable = Path::new("/Applications/Game.app/Contents/MacOS/game");
+        assert_eq!(bundle_resources(executable), Some(PathBuf::from("/Applications/Game.app/Contents/Resources")));
+        assert_eq!(bundle_resources(Path::new("/Users/player/dev/game/target/debug/game")), None);
+
+        let variable = |name: &str| if name == "HOME" {Some(String::from("/Users/player"))} else {None};
+        let directories = macos_directories("game", "studio", &variable, Some(executable), PathBuf::from("/")).unwrap();
+        assert_eq!(directories.get(&RootDir::WorkingDirectory), Some(Path::new("/Applications/Game.app/Contents/Resources")));
+    }
+}
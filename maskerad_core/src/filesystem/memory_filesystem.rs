@@ -0,0 +1,301 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ MEMORY FILESYSTEM.
+
+ A VFilesystem in RAM: the tests of the systems using a VFilesystem don't write in the working
+ directory or in the user directories of the developer, and don't depend on each other.
+
+ It behaves like the OS filesystem: a file is created in an existing directory, rm only removes the
+ empty directories, a directory cannot be opened as a file. The roots always exist.
+ The open files share their content with the filesystem: a write is seen by the next open.
+*/
+
+use std::collections::HashMap;
+use std::io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex, RwLock};
+use filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use filesystem::game_directories::RootDir;
+use filesystem::open_options::OpenOptions;
+use filesystem::vfilesystem::{join_path, VFile, VFilesystem, VMetadata};
+use filesystem::vpath::{VPath, VPathBuf};
+
+type Content = Arc<Mutex<Vec<u8>>>;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Directory,
+    File(Content),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MemoryMetadata {
+    directory: bool,
+    len: u64,
+}
+
+impl VMetadata for MemoryMetadata {
+    fn is_dir(&self) -> bool {
+        self.directory
+    }
+
+    fn is_file(&self) -> bool {
+        !self.directory
+    }
+
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+fn io_error(kind: ErrorKind, description: String) -> FileSystemError {
+    FileSystemError::IOError(description.clone(), io::Error::new(kind, description))
+}
+
+fn parent(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(separator) => &path[..separator],
+        None => "",
+    }
+}
+
+//An open file. The position is its own, the content is shared.
+pub struct MemoryFile {
+    content: Content,
+    position: u64,
+    read: bool,
+    write: bool,
+    append: bool,
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if !self.read {
+            return Err(io::Error::new(ErrorKind::PermissionDenied, "The file is not open for reading."));
+        }
+        let content = self.content.lock().map_err(|_| io::Error::new(ErrorKind::Other, "Poisoned file."))?;
+        let mut cursor = Cursor::new(content.as_slice());
+        cursor.set_position(self.position);
+        let count = cursor.read(buffer)?;
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        if !self.write && !self.append {
+            return Err(io::Error::new(ErrorKind::PermissionDenied, "The file is not open for writing."));
+        }
+        let mut content = self.content.lock().map_err(|_| io::Error::new(ErrorKind::Other, "Poisoned file."))?;
+        if self.append {
+            self.position = content.len() as u64;
+        }
+        let mut cursor = Cursor::new(&mut *content);
+        cursor.set_position(self.position);
+        let count = cursor.write(buffer)?;
+        self.position += count as u64;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let len = self.content.lock().map_err(|_| io::Error::new(ErrorKind::Other, "Poisoned file."))?.len() as i64;
+        let position = match position {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => len + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+        };
+        if position < 0 {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "Seek before the start of the file."));
+        }
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+impl VFile for MemoryFile {}
+
+#[derive(Debug, Default)]
+pub struct MemoryFilesystem {
+    nodes: RwLock<HashMap<(RootDir, String), Node>>,
+}
+
+impl MemoryFilesystem {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn node(&self, root_dir: RootDir, path: &str) -> FileSystemResult<Node> {
+        if path.is_empty() {
+            return Ok(Node::Directory);
+        }
+        let nodes = self.nodes.read().map_err(|_| FileSystemError::CreationError(String::from("The memory filesystem is poisoned.")))?;
+        match nodes.get(&(root_dir, String::from(path))) {
+            Some(node) => Ok(node.clone()),
+            None => Err(io_error(ErrorKind::NotFound, format!("{} does not exist in the {}.", path, root_dir))),
+        }
+    }
+
+    fn is_directory(&self, root_dir: RootDir, path: &str) -> bool {
+        matches!(self.node(root_dir, path), Ok(Node::Directory))
+    }
+}
+
+impl VFilesystem for MemoryFilesystem {
+    fn open_with_options(&self, vpath: VPath, open_options: &OpenOptions) -> FileSystemResult<Box<VFile>> {
+        let (root_dir, path) = (vpath.root(), String::from(vpath.as_str()));
+        trace!("Opening {} in the memory {} with options {}", path, root_dir, open_options);
+        let content = match self.node(root_dir, path.as_str()) {
+            Ok(Node::File(content)) => content,
+            Ok(Node::Directory) => return Err(io_error(ErrorKind::Other, format!("{} is a directory.", path))),
+            Err(error) => {
+                if !open_options.create() {
+                    return Err(error);
+                }
+                if !self.is_directory(root_dir, parent(path.as_str())) {
+                    return Err(io_error(ErrorKind::NotFound, format!("The directory of {} does not exist.", path)));
+                }
+                let content = Arc::new(Mutex::new(Vec::new()));
+                let mut nodes = self.nodes.write().map_err(|_| FileSystemError::CreationError(String::from("The memory filesystem is poisoned.")))?;
+                nodes.insert((root_dir, path.clone()), Node::File(content.clone()));
+                content
+            },
+        };
+        if open_options.truncate() && open_options.write() {
+            if let Ok(mut content) = content.lock() {
+                content.clear();
+            }
+        }
+        Ok(Box::new(MemoryFile {
+            content,
+            position: 0,
+            read: open_options.read(),
+            write: open_options.write(),
+            append: open_options.append(),
+        }))
+    }
+
+    fn mkdir(&self, vpath: VPath) -> FileSystemResult<()> {
+        let (root_dir, path) = (vpath.root(), String::from(vpath.as_str()));
+        debug!("Creating the memory directory {} in the {}", path, root_dir);
+        let mut nodes = self.nodes.write().map_err(|_| FileSystemError::CreationError(String::from("The memory filesystem is poisoned.")))?;
+        let mut directory = String::new();
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            directory = join_path(directory.as_str(), component);
+            match nodes.get(&(root_dir, directory.clone())) {
+                Some(&Node::File(_)) => return Err(io_error(ErrorKind::AlreadyExists, format!("{} is a file.", directory))),
+                Some(&Node::Directory) => {},
+                None => {
+                    nodes.insert((root_dir, directory.clone()), Node::Directory);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn rm(&self, vpath: VPath) -> FileSystemResult<()> {
+        let (root_dir, path) = (vpath.root(), String::from(vpath.as_str()));
+        debug!("Removing {} from the memory {}", path, root_dir);
+        if let Node::Directory = self.node(root_dir, path.as_str())? {
+            if !self.read_dir(vpath)?.is_empty() {
+                return Err(io_error(ErrorKind::Other, format!("The directory {} is not empty.", path)));
+            }
+        }
+        let mut nodes = self.nodes.write().map_err(|_| FileSystemError::CreationError(String::from("The memory filesystem is poisoned.")))?;
+        nodes.remove(&(root_dir, path));
+        Ok(())
+    }
+
+    fn rmrf(&self, vpath: VPath) -> FileSystemResult<()> {
+        let (root_dir, path) = (vpath.root(), String::from(vpath.as_str()));
+        debug!("Removing {} and its content from the memory {}", path, root_dir);
+        self.node(root_dir, path.as_str())?;
+        let prefix = format!("{}/", path);
+        let mut nodes = self.nodes.write().map_err(|_| FileSystemError::CreationError(String::from("The memory filesystem is poisoned.")))?;
+        nodes.retain(|&(root, ref known), _| root != root_dir || !(path.is_empty() || *known == path || known.starts_with(prefix.as_str())));
+        Ok(())
+    }
+
+    fn metadata(&self, vpath: VPath) -> FileSystemResult<Box<VMetadata>> {
+        let (root_dir, path) = (vpath.root(), vpath.as_str());
+        let metadata = match self.node(root_dir, path)? {
+            Node::Directory => MemoryMetadata { directory: true, len: 0 },
+            Node::File(content) => MemoryMetadata {
+                directory: false,
+                len: content.lock().map(|content| content.len() as u64).unwrap_or(0),
+            },
+        };
+        Ok(Box::new(metadata))
+    }
+
+    fn read_dir(&self, vpath: VPath) -> FileSystemResult<Vec<VPathBuf>> {
+        let (root_dir, path) = (vpath.root(), vpath.as_str());
+        if !self.is_directory(root_dir, path) {
+            return Err(io_error(ErrorKind::NotFound, format!("{} is not a directory of the {}.", path, root_dir)));
+        }
+        let nodes = self.nodes.read().map_err(|_| FileSystemError::CreationError(String::from("The memory filesystem is poisoned.")))?;
+        let mut entries = nodes.keys()
+            .filter(|key| key.0 == root_dir && parent(key.1.as_str()) == path)
+            .map(|key| VPathBuf::new(root_dir, key.1.as_str()))
+            .collect::<FileSystemResult<Vec<VPathBuf>>>()?;
+        entries.sort();
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod memory_filesystem_test {
+    use super::*;
+
+    #[test]
+    fn memory_files_and_directories() {
+        let fs = MemoryFilesystem::new();
+        assert!(fs.create(VPath::new(RootDir::UserSaveRoot, "slots/slot_1.sav").unwrap()).is_err());
+        fs.mkdir(VPath::new(RootDir::UserSaveRoot, "slots/autosaves").unwrap()).unwrap();
+        fs.write_all(VPath::new(RootDir::UserSaveRoot, "slots/slot_1.sav").unwrap(), b"level 3").unwrap();
+        fs.append(VPath::new(RootDir::UserSaveRoot, "slots/slot_1.sav").unwrap()).unwrap().write_all(b", 12 lives").unwrap();
+
+        assert_eq!(fs.read_to_string(VPathBuf::new(RootDir::UserSaveRoot, "./slots//slot_1.sav").unwrap().as_vpath()).unwrap(), "level 3, 12 lives");
+        assert!(fs.open(VPath::new(RootDir::UserDataRoot, "slots/slot_1.sav").unwrap()).is_err());
+        assert!(fs.open(VPath::new(RootDir::UserSaveRoot, "slots").unwrap()).is_err());
+
+        let mut file = fs.open_with_options(VPath::new(RootDir::UserSaveRoot, "slots/slot_1.sav").unwrap(), OpenOptions::new().set_read(true).set_write(true)).unwrap();
+        file.seek(SeekFrom::Start(6)).unwrap();
+        file.write_all(b"4").unwrap();
+        assert_eq!(fs.metadata(VPath::new(RootDir::UserSaveRoot, "slots/slot_1.sav").unwrap()).unwrap().len(), 17);
+        assert!(fs.metadata(VPath::new(RootDir::UserSaveRoot, "slots").unwrap()).unwrap().is_dir());
+
+        let entries = fs.read_dir(VPath::new(RootDir::UserSaveRoot, "slots").unwrap()).unwrap();
+        assert_eq!(entries.iter().map(|entry| entry.as_str()).collect::<Vec<&str>>(), vec!["slots/autosaves", "slots/slot_1.sav"]);
+        assert_eq!(fs.read_dir(VPath::root_of(RootDir::UserSaveRoot)).unwrap(), vec![VPathBuf::new(RootDir::UserSaveRoot, "slots").unwrap()]);
+        assert!(fs.rm(VPath::new(RootDir::UserSaveRoot, "slots").unwrap()).is_err());
+        fs.rm(VPath::new(RootDir::UserSaveRoot, "slots/autosaves").unwrap()).unwrap();
+        fs.rmrf(VPath::new(RootDir::UserSaveRoot, "slots").unwrap()).unwrap();
+        assert!(!fs.exists(VPath::new(RootDir::UserSaveRoot, "slots/slot_1.sav").unwrap()));
+        assert!(fs.exists(VPath::root_of(RootDir::UserSaveRoot)));
+    }
+
+    #[test]
+    fn memory_read_write_helpers() {
+        let fs = MemoryFilesystem::new();
+        let path = VPath::new(RootDir::UserConfigRoot, "keybindings.cfg").unwrap();
+        fs.mkdir(VPath::root_of(RootDir::UserConfigRoot)).unwrap();
+        fs.write_lines(path, &["jump = space", "crouch = ctrl"]).unwrap();
+        assert_eq!(fs.read_to_string(path).unwrap(), "jump = space\ncrouch = ctrl\n");
+        fs.write_all(path, &[0xFF, 0xFE]).unwrap();
+        assert_eq!(fs.read_bytes(path).unwrap(), vec![0xFF, 0xFE]);
+        assert!(fs.read_to_string(path).is_err());
+        assert!(fs.read_bytes(VPath::new(RootDir::UserConfigRoot, "missing.cfg").unwrap()).is_err());
+    }
+}
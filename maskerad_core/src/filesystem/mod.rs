@@ -8,4 +8,13 @@
 pub mod filesystem;
 pub mod filesystem_error;
 pub mod game_directories;
-pub mod open_options;
\ No newline at end of file
+pub mod open_options;
+pub mod mod_permissions;
+pub mod vfilesystem;
+pub mod vpath;
+pub mod memory_filesystem;
+pub mod archive_filesystem;
+pub mod overlay_filesystem;
+pub mod windows_filesystem;
+pub mod macos_directories;
+pub mod kv_store;
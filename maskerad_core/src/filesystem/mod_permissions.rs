@@ -0,0 +1,158 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ MOD PERMISSIONS.
+
+ Each mod ships a permission manifest (mod.json), declaring what it needs:
+ - the roots it may write to: "user_data", "user_config" or "user_save". The engine roots and the working
+   directory are never writable by a mod.
+ - whether its scripts may use the network.
+
+ The filesystem and the scripting sandbox consult the policy built from the manifest before writing a file
+ or opening a connection for a mod. A mod only writes in its own directory of a root: <root>/mods/<mod name>/.
+
+ {
+    "name": "better_villages",
+    "version": "1.2.0",
+    "permissions": {
+        "write_roots": ["user_data"],
+        "networking": false
+    }
+ }
+*/
+
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use serde_json;
+use filesystem::filesystem::Filesystem;
+use filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use filesystem::game_directories::RootDir;
+
+pub const MOD_MANIFEST_FILE: &'static str = "mod.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModPermissions {
+    #[serde(default)]
+    pub write_roots: Vec<String>,
+    #[serde(default)]
+    pub networking: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModManifest {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    //No permission by default.
+    #[serde(default)]
+    pub permissions: ModPermissions,
+}
+
+impl ModManifest {
+    pub fn from_reader<R: Read>(reader: R) -> FileSystemResult<Self> {
+        serde_json::from_reader(reader).map_err(|json_error| {
+            FileSystemError::PermissionError(format!("Invalid mod manifest: {}", json_error))
+        })
+    }
+}
+
+//What a mod is allowed to do, checked by the filesystem and the scripting sandbox.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModPolicy {
+    name: String,
+    writable_roots: Vec<RootDir>,
+    networking: bool,
+}
+
+impl ModPolicy {
+    pub fn from_manifest(manifest: &ModManifest) -> FileSystemResult<Self> {
+        if manifest.name.is_empty() || !manifest.name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+            return Err(FileSystemError::PermissionError(format!("Invalid mod name \"{}\".", manifest.name)));
+        }
+        let writable_roots = manifest.permissions.write_roots.iter().map(|root| match root.as_str() {
+            "user_data" => Ok(RootDir::UserDataRoot),
+            "user_config" => Ok(RootDir::UserConfigRoot),
+            "user_save" => Ok(RootDir::UserSaveRoot),
+            _ => Err(FileSystemError::PermissionError(format!("The mod {} asks to write in {}, which is not allowed.", manifest.name, root))),
+        }).collect::<FileSystemResult<Vec<RootDir>>>()?;
+
+        debug!("Mod {}: writes in {:?}, networking {}.", manifest.name, writable_roots, manifest.permissions.networking);
+        Ok(ModPolicy {
+            name: manifest.name.clone(),
+            writable_roots,
+            networking: manifest.permissions.networking,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn allows_networking(&self) -> bool {
+        self.networking
+    }
+
+    pub fn check_networking(&self) -> FileSystemResult<()> {
+        if self.networking {
+            Ok(())
+        } else {
+            warn!("The mod {} tried to use the network without permission.", self.name);
+            Err(FileSystemError::PermissionError(format!("The mod {} is not allowed to use the network.", self.name)))
+        }
+    }
+
+    //The path must be relative, without "..".
+    pub fn check_write(&self, root: RootDir, path: &str) -> FileSystemResult<()> {
+        if !self.writable_roots.contains(&root) {
+            warn!("The mod {} tried to write {} in the {} without permission.", self.name, path, root);
+            return Err(FileSystemError::PermissionError(format!("The mod {} is not allowed to write in the {}.", self.name, root)));
+        }
+        if !Path::new(path).components().all(|component| match component {
+            Component::Normal(_) | Component::CurDir => true,
+            _ => false,
+        }) {
+            warn!("The mod {} tried to write outside of its directory: {}.", self.name, path);
+            return Err(FileSystemError::PermissionError(format!("The mod {} can't write {}, outside of its directory.", self.name, path)));
+        }
+        Ok(())
+    }
+
+    //The full path of a file written by the mod: <root>/mods/<mod name>/<path>.
+    pub fn write_path(&self, filesystem: &Filesystem, root: RootDir, path: &str) -> FileSystemResult<PathBuf> {
+        self.check_write(root, path)?;
+        filesystem.construct_path_from_root(root, format!("mods/{}/{}", self.name, path).as_str())
+    }
+}
+
+#[cfg(test)]
+mod mod_permissions_test {
+    use super::*;
+
+    #[test]
+    fn mod_permissions_policy() {
+        let manifest = ModManifest::from_reader(r#"{
+            "name": "better_villages",
+            "permissions": { "write_roots": ["user_data"] }
+        }"#.as_bytes()).unwrap();
+        let policy = ModPolicy::from_manifest(&manifest).unwrap();
+        assert!(!policy.allows_networking());
+        assert!(policy.check_networking().is_err());
+        assert!(policy.check_write(RootDir::UserDataRoot, "villages/cache.json").is_ok());
+        assert!(policy.check_write(RootDir::UserSaveRoot, "save.json").is_err());
+        assert!(policy.check_write(RootDir::UserDataRoot, "../other_mod/cache.json").is_err());
+        assert!(policy.check_write(RootDir::UserDataRoot, "/etc/passwd").is_err());
+
+        let mut manifest = manifest;
+        manifest.permissions.write_roots.push(String::from("engine_config"));
+        assert!(ModPolicy::from_manifest(&manifest).is_err());
+        manifest.permissions.write_roots.clear();
+        manifest.name = String::from("../escape");
+        assert!(ModPolicy::from_manifest(&manifest).is_err());
+        assert!(ModManifest::from_reader("{}".as_bytes()).is_err());
+    }
+}
@@ -90,6 +90,26 @@ impl OpenOptions {
         self
     }
 
+    pub fn read(&self) -> bool {
+        self.read
+    }
+
+    pub fn write(&self) -> bool {
+        self.write
+    }
+
+    pub fn create(&self) -> bool {
+        self.create
+    }
+
+    pub fn append(&self) -> bool {
+        self.append
+    }
+
+    pub fn truncate(&self) -> bool {
+        self.truncate
+    }
+
     pub fn to_fs_openoptions(&self) -> fs::OpenOptions {
         debug!("Creating an fs::OpenOptions from this OpenOptions.");
         let mut opt = fs::OpenOptions::new();
@@ -0,0 +1,268 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ OVERLAY FILESYSTEM.
+
+ The base game, the DLCs and the mods are layers of one VFilesystem. A layer hides the files of the
+ layers below it: a mod replaces an asset by shipping a file with the same path, without touching the
+ base game.
+ - Reading: the file comes from the topmost layer which has it.
+ - Listing a directory: the entries of all the layers, merged.
+ - Writing: always in the topmost writable layer. A file of a layer below opened to be modified in place
+   is copied first. Modifying a file of a read-only layer above the writable one fails: the readers would
+   still see the file of that layer, the write would be lost for them. The read-only layers are never
+   modified.
+ - Removing: in the topmost writable layer only. Removing a file which would still be visible through a
+   read-only layer, below or above, fails: the other layers can't be hidden.
+*/
+
+use std::io;
+use filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use filesystem::open_options::OpenOptions;
+use filesystem::vfilesystem::{VFile, VFilesystem, VMetadata};
+use filesystem::vpath::{VPath, VPathBuf};
+
+struct Layer {
+    name: String,
+    filesystem: Box<VFilesystem>,
+    writable: bool,
+}
+
+#[derive(Default)]
+pub struct OverlayFilesystem {
+    //From the bottom to the top.
+    layers: Vec<Layer>,
+}
+
+impl OverlayFilesystem {
+    pub fn new() -> Self {
+        OverlayFilesystem::default()
+    }
+
+    //The new layer is on top of the others.
+    pub fn push_layer<F: VFilesystem + 'static>(&mut self, name: &str, filesystem: F, writable: bool) {
+        debug!("Pushing the {} layer ({}).", name, if writable {"writable"} else {"read-only"});
+        self.layers.push(Layer {
+            name: String::from(name),
+            filesystem: Box::new(filesystem),
+            writable,
+        });
+    }
+
+    pub fn with_layer<F: VFilesystem + 'static>(mut self, name: &str, filesystem: F, writable: bool) -> Self {
+        self.push_layer(name, filesystem, writable);
+        self
+    }
+
+    //Returns false if there is no layer with this name.
+    pub fn remove_layer(&mut self, name: &str) -> bool {
+        let count = self.layers.len();
+        self.layers.retain(|layer| layer.name != name);
+        self.layers.len() != count
+    }
+
+    //From the top to the bottom.
+    pub fn layer_names(&self) -> Vec<&str> {
+        self.layers.iter().rev().map(|layer| layer.name.as_str()).collect()
+    }
+
+    //The name of the layer the file is read from.
+    pub fn provider(&self, path: VPath) -> Option<&str> {
+        self.layers.iter().rev()
+            .find(|layer| layer.filesystem.exists(path))
+            .map(|layer| layer.name.as_str())
+    }
+
+    fn writable_layer(&self, path: VPath) -> FileSystemResult<usize> {
+        self.layers.iter().rposition(|layer| layer.writable)
+            .ok_or_else(|| FileSystemError::PermissionError(format!("Cannot modify {}, no layer is writable.", path)))
+    }
+
+    //The directory exists in the writable layer if it exists in the overlay.
+    fn prepare_parent(&self, writable: usize, path: VPath) -> FileSystemResult<()> {
+        let layer = &self.layers[writable].filesystem;
+        if let Some(parent) = path.parent().filter(|parent| !parent.is_root()) {
+            if !layer.exists(parent) && self.exists(parent) {
+                layer.mkdir(parent)?;
+            }
+        }
+        Ok(())
+    }
+
+    //The read-only layer above the writable one which has the file, if any: the readers see its file.
+    fn shadowing_layer(&self, writable: usize, path: VPath) -> Option<&Layer> {
+        self.layers[writable + 1..].iter().rev().find(|layer| layer.filesystem.exists(path))
+    }
+
+    //The file is in another layer than the writable one.
+    fn in_read_only_layer(&self, writable: usize, path: VPath) -> bool {
+        self.layers.iter().enumerate().any(|(index, layer)| index != writable && layer.filesystem.exists(path))
+    }
+
+    //Copies the file the readers see into the writable layer.
+    fn copy_up(&self, writable: usize, path: VPath) -> FileSystemResult<()> {
+        let source = match self.layers.iter().rposition(|layer| layer.filesystem.exists(path)) {
+            Some(index) if index != writable => &self.layers[index],
+            _ => return Ok(()),
+        };
+        debug!("Copying {} up from the {} layer.", path, source.name);
+        self.prepare_parent(writable, path)?;
+        let mut reader = source.filesystem.open(path)?;
+        let mut writer = self.layers[writable].filesystem.create(path)?;
+        io::copy(&mut reader, &mut writer)?;
+        Ok(())
+    }
+}
+
+impl VFilesystem for OverlayFilesystem {
+    fn open_with_options(&self, path: VPath, open_options: &OpenOptions) -> FileSystemResult<Box<VFile>> {
+        let modifies = open_options.write() || open_options.append() || open_options.create() || open_options.truncate();
+        if !modifies {
+            return match self.layers.iter().rev().find(|layer| layer.filesystem.exists(path)) {
+                Some(layer) => layer.filesystem.open_with_options(path, open_options),
+                None => Err(FileSystemError::IOError(format!("{} is in none of the layers.", path), io::Error::new(io::ErrorKind::NotFound, path.to_string()))),
+            };
+        }
+        let writable = self.writable_layer(path)?;
+        if let Some(shadowing) = self.shadowing_layer(writable, path) {
+            return Err(FileSystemError::PermissionError(format!("Cannot modify {}, the read-only {} layer hides it.", path, shadowing.name)));
+        }
+        let layer = &self.layers[writable].filesystem;
+        if !layer.exists(path) {
+            if open_options.truncate() {
+                self.prepare_parent(writable, path)?;
+            } else {
+                self.copy_up(writable, path)?;
+                if !layer.exists(path) {
+                    self.prepare_parent(writable, path)?;
+                }
+            }
+        }
+        layer.open_with_options(path, open_options)
+    }
+
+    fn mkdir(&self, path: VPath) -> FileSystemResult<()> {
+        let writable = self.writable_layer(path)?;
+        self.layers[writable].filesystem.mkdir(path)
+    }
+
+    fn rm(&self, path: VPath) -> FileSystemResult<()> {
+        let writable = self.writable_layer(path)?;
+        if self.in_read_only_layer(writable, path) {
+            return Err(FileSystemError::PermissionError(format!("Cannot remove {}, it is in a read-only layer.", path)));
+        }
+        self.layers[writable].filesystem.rm(path)
+    }
+
+    fn rmrf(&self, path: VPath) -> FileSystemResult<()> {
+        let writable = self.writable_layer(path)?;
+        if self.in_read_only_layer(writable, path) {
+            return Err(FileSystemError::PermissionError(format!("Cannot remove {}, it is in a read-only layer.", path)));
+        }
+        self.layers[writable].filesystem.rmrf(path)
+    }
+
+    fn metadata(&self, path: VPath) -> FileSystemResult<Box<VMetadata>> {
+        let mut error = None;
+        for layer in self.layers.iter().rev() {
+            match layer.filesystem.metadata(path) {
+                Ok(metadata) => return Ok(metadata),
+                Err(e) => if error.is_none() {error = Some(e)},
+            }
+        }
+        Err(error.unwrap_or_else(|| FileSystemError::IOError(format!("{} is in none of the layers.", path), io::Error::new(io::ErrorKind::NotFound, path.to_string()))))
+    }
+
+    fn read_dir(&self, path: VPath) -> FileSystemResult<Vec<VPathBuf>> {
+        let mut entries = Vec::new();
+        let mut found = false;
+        for layer in self.layers.iter() {
+            if let Ok(layer_entries) = layer.filesystem.read_dir(path) {
+                found = true;
+                entries.extend(layer_entries);
+            }
+        }
+        if !found {
+            return Err(FileSystemError::IOError(format!("The directory {} is in none of the layers.", path), io::Error::new(io::ErrorKind::NotFound, path.to_string())));
+        }
+        entries.sort();
+        entries.dedup();
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod overlay_filesystem_test {
+    use super::*;
+    use std::io::Write;
+    use filesystem::game_directories::RootDir;
+    use filesystem::memory_filesystem::MemoryFilesystem;
+
+    fn write(fs: &VFilesystem, path: &str, content: &str) {
+        fs.write_all(VPath::new(RootDir::WorkingDirectory, path).unwrap(), content.as_bytes()).unwrap();
+    }
+
+    fn read(fs: &VFilesystem, path: &str) -> String {
+        fs.read_to_string(VPath::new(RootDir::WorkingDirectory, path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn layers_lookup_merge_and_write_through() {
+        let base = MemoryFilesystem::new();
+        base.mkdir(VPath::new(RootDir::WorkingDirectory, "textures").unwrap()).unwrap();
+        write(&base, "textures/rock.png", "base rock");
+        write(&base, "textures/grass.png", "base grass");
+        write(&base, "config.toml", "base");
+        let mod_layer = MemoryFilesystem::new();
+        mod_layer.mkdir(VPath::new(RootDir::WorkingDirectory, "textures").unwrap()).unwrap();
+        write(&mod_layer, "textures/rock.png", "mod rock");
+        write(&mod_layer, "textures/lava.png", "mod lava");
+
+        let mut fs = OverlayFilesystem::new()
+            .with_layer("base", base, false)
+            .with_layer("user", MemoryFilesystem::new(), true);
+        fs.push_layer("my_mod", mod_layer, false);
+        assert_eq!(fs.layer_names(), vec!["my_mod", "user", "base"]);
+
+        assert_eq!(read(&fs, "textures/rock.png"), "mod rock");
+        assert_eq!(read(&fs, "textures/grass.png"), "base grass");
+        assert_eq!(fs.provider(VPath::new(RootDir::WorkingDirectory, "textures/rock.png").unwrap()), Some("my_mod"));
+        let entries = fs.read_dir(VPath::new(RootDir::WorkingDirectory, "textures").unwrap()).unwrap();
+        assert_eq!(entries.iter().map(|entry| entry.as_str()).collect::<Vec<&str>>(),
+                   vec!["textures/grass.png", "textures/lava.png", "textures/rock.png"]);
+
+        //The writes go to the user layer, the base layer is untouched.
+        write(&fs, "textures/new.png", "new");
+        assert_eq!(fs.provider(VPath::new(RootDir::WorkingDirectory, "textures/new.png").unwrap()), Some("user"));
+        fs.append(VPath::new(RootDir::WorkingDirectory, "config.toml").unwrap()).unwrap().write_all(b" + user").unwrap();
+        assert_eq!(read(&fs, "config.toml"), "base + user");
+        assert_eq!(fs.provider(VPath::new(RootDir::WorkingDirectory, "config.toml").unwrap()), Some("user"));
+        assert!(fs.metadata(VPath::new(RootDir::WorkingDirectory, "config.toml").unwrap()).unwrap().len() > 4);
+        //The rock of the mod, above the user layer, hides the rock written by the user: the write is refused.
+        assert!(fs.append(VPath::new(RootDir::WorkingDirectory, "textures/rock.png").unwrap()).is_err());
+        assert!(fs.create(VPath::new(RootDir::WorkingDirectory, "textures/lava.png").unwrap()).is_err());
+        assert_eq!(read(&fs, "textures/rock.png"), "mod rock");
+
+        //A file still visible through a read-only layer, below or above, can't be removed.
+        assert!(fs.rm(VPath::new(RootDir::WorkingDirectory, "textures/grass.png").unwrap()).is_err());
+        assert!(fs.rm(VPath::new(RootDir::WorkingDirectory, "textures/lava.png").unwrap()).is_err());
+        assert!(fs.rmrf(VPath::new(RootDir::WorkingDirectory, "textures").unwrap()).is_err());
+        fs.rm(VPath::new(RootDir::WorkingDirectory, "textures/new.png").unwrap()).unwrap();
+        assert!(!fs.exists(VPath::new(RootDir::WorkingDirectory, "textures/new.png").unwrap()));
+
+        assert!(fs.remove_layer("my_mod"));
+        assert_eq!(read(&fs, "textures/rock.png"), "base rock");
+        fs.append(VPath::new(RootDir::WorkingDirectory, "textures/rock.png").unwrap()).unwrap().write_all(b" + user").unwrap();
+        assert_eq!(read(&fs, "textures/rock.png"), "base rock + user");
+        assert_eq!(read(&fs, "textures/grass.png"), "base grass");
+        assert!(fs.read_dir(VPath::new(RootDir::WorkingDirectory, "missing").unwrap()).is_err());
+
+        let read_only = OverlayFilesystem::new().with_layer("base", MemoryFilesystem::new(), false);
+        assert!(read_only.create(VPath::new(RootDir::WorkingDirectory, "a.txt").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ VIRTUAL FILESYSTEM.
+
+ The systems which read and write files use a VFilesystem, not the OS filesystem: the paths are VPaths,
+ relative to a RootDir, with '/' as separator, and the backend decides where the files are.
+ - Filesystem: the directories of the OS, given by the GameDirectories.
+ - WindowsFilesystem: the same, with the path rules of Windows.
+ - MemoryFilesystem: everything in RAM, for the tests.
+ - ArchiveFilesystem: the files of a pack, read-only.
+ - OverlayFilesystem: several of them as layers (base game, DLCs, mods).
+
+ The paths given to the backends writing in the OS directories go through sandbox_path: a path going up
+ with ".." or starting from a root would let a mod or a save file name reach any file of the player.
+*/
+
+use std::fs;
+use std::io::{self, Cursor, Read, Seek, Write};
+use filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use filesystem::open_options::OpenOptions;
+use filesystem::vpath::{VPath, VPathBuf};
+
+pub trait VFile: Read + Write + Seek {
+    //Writes the data and the metadata to the storage device: what has been written survives a power loss.
+    //The files which are not on a disk only flush their buffers.
+    fn sync_all(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl VFile for fs::File {
+    fn sync_all(&mut self) -> io::Result<()> {
+        fs::File::sync_all(self)
+    }
+}
+
+impl VFile for Cursor<Vec<u8>> {}
+
+pub trait VMetadata {
+    fn is_dir(&self) -> bool;
+    fn is_file(&self) -> bool;
+    fn len(&self) -> u64;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl VMetadata for fs::Metadata {
+    fn is_dir(&self) -> bool {
+        fs::Metadata::is_dir(self)
+    }
+
+    fn is_file(&self) -> bool {
+        fs::Metadata::is_file(self)
+    }
+
+    fn len(&self) -> u64 {
+        fs::Metadata::len(self)
+    }
+}
+
+pub trait VFilesystem {
+    fn open_with_options(&self, path: VPath, open_options: &OpenOptions) -> FileSystemResult<Box<VFile>>;
+
+    //Creates the missing parent directories too.
+    fn mkdir(&self, path: VPath) -> FileSystemResult<()>;
+
+    //Removes a file, or an empty directory.
+    fn rm(&self, path: VPath) -> FileSystemResult<()>;
+
+    //Removes a file, or a directory and all its content.
+    fn rmrf(&self, path: VPath) -> FileSystemResult<()>;
+
+    fn metadata(&self, path: VPath) -> FileSystemResult<Box<VMetadata>>;
+
+    //The entries of the directory, sorted.
+    fn read_dir(&self, path: VPath) -> FileSystemResult<Vec<VPathBuf>>;
+
+    fn exists(&self, path: VPath) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    fn open(&self, path: VPath) -> FileSystemResult<Box<VFile>> {
+        self.open_with_options(path, OpenOptions::new().set_read(true))
+    }
+
+    //Truncates the file if it exists.
+    fn create(&self, path: VPath) -> FileSystemResult<Box<VFile>> {
+        self.open_with_options(path, OpenOptions::new().set_create(true).set_write(true).set_truncate(true))
+    }
+
+    fn append(&self, path: VPath) -> FileSystemResult<Box<VFile>> {
+        self.open_with_options(path, OpenOptions::new().set_create(true).set_append(true).set_write(true))
+    }
+
+    fn read_bytes(&self, path: VPath) -> FileSystemResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.open(path)?.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    //Fails if the file is not valid UTF-8.
+    fn read_to_string(&self, path: VPath) -> FileSystemResult<String> {
+        let mut text = String::new();
+        self.open(path)?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    //Replaces the content of the file, creating it if necessary.
+    fn write_all(&self, path: VPath, bytes: &[u8]) -> FileSystemResult<()> {
+        let mut file = self.create(path)?;
+        file.write_all(bytes)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    //Each line is terminated by '\n'.
+    fn write_lines(&self, path: VPath, lines: &[&str]) -> FileSystemResult<()> {
+        let mut text = String::with_capacity(lines.iter().map(|line| line.len() + 1).sum());
+        for line in lines {
+            text.push_str(line);
+            text.push('\n');
+        }
+        self.write_all(path, text.as_bytes())
+    }
+}
+
+//The path of an entry of a directory.
+pub fn join_path(directory: &str, name: &str) -> String {
+    let directory = directory.trim_matches('/');
+    if directory.is_empty() {
+        String::from(name)
+    } else {
+        format!("{}/{}", directory, name)
+    }
+}
+
+//"a//b/./c/" -> "a/b/c", with '/' or '\' as separators. The absolute paths ("/etc", "C:\Windows",
+//"\\server\share") and the ".." are refused.
+pub fn sandbox_path(path: &str) -> FileSystemResult<String> {
+    if path.starts_with('/') || path.starts_with('\\') {
+        return Err(FileSystemError::PathEscapesRoot(format!("The path {} is absolute.", path)));
+    }
+    let mut components = Vec::new();
+    for component in path.split(&['/', '\\'][..]) {
+        match component {
+            "" | "." => {},
+            ".." => return Err(FileSystemError::PathEscapesRoot(format!("The path {} goes out of its root.", path))),
+            component if components.is_empty() && component.len() >= 2 && component.as_bytes()[1] == b':' => {
+                return Err(FileSystemError::PathEscapesRoot(format!("The path {} starts with a drive.", path)));
+            },
+            component => components.push(component),
+        }
+    }
+    Ok(components.join("/"))
+}
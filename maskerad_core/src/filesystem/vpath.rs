@@ -0,0 +1,182 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ VIRTUAL PATHS.
+
+ A path of a VFilesystem is relative to a RootDir: a VPathBuf keeps both, so a path passed from a system
+ to another doesn't lose its root. The path is normalized when the VPathBuf is built: '/' or '\' as
+ separators, no "." and no empty component, and the paths going out of their root are refused (see
+ sandbox_path). It is always stored with '/' separators, whatever the platform.
+
+ VPath is the borrowed version, like Path for PathBuf: the VFilesystem methods take a VPath.
+
+ let save = VPathBuf::new(RootDir::UserSaveRoot, "slots\\slot_1.sav")?;
+ assert_eq!(save.as_str(), "slots/slot_1.sav");
+ filesystem.open(save.as_vpath())?;
+ filesystem.open(VPath::new(RootDir::UserConfigRoot, "input.json")?)?;
+*/
+
+use std::fmt;
+use filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use filesystem::game_directories::RootDir;
+use filesystem::vfilesystem::{join_path, sandbox_path};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VPath<'a> {
+    root: RootDir,
+    path: &'a str,
+}
+
+impl<'a> VPath<'a> {
+    //The path must already be normalized ("levels/intro.kscene"), use a VPathBuf otherwise.
+    pub fn new(root: RootDir, path: &'a str) -> FileSystemResult<Self> {
+        if sandbox_path(path)? != path {
+            return Err(FileSystemError::PathEscapesRoot(format!("The path {} is not normalized, use a VPathBuf.", path)));
+        }
+        Ok(VPath {
+            root,
+            path,
+        })
+    }
+
+    //The root directory itself.
+    pub fn root_of(root: RootDir) -> VPath<'static> {
+        VPath {
+            root,
+            path: "",
+        }
+    }
+
+    pub fn root(&self) -> RootDir {
+        self.root
+    }
+
+    //Relative to the root, with '/' as separator. Empty for the root itself.
+    pub fn as_str(&self) -> &'a str {
+        self.path
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    //None for the root.
+    pub fn parent(&self) -> Option<VPath<'a>> {
+        if self.is_root() {
+            return None;
+        }
+        Some(VPath {
+            root: self.root,
+            path: match self.path.rfind('/') {
+                Some(separator) => &self.path[..separator],
+                None => "",
+            },
+        })
+    }
+
+    pub fn file_name(&self) -> Option<&'a str> {
+        self.path.rsplit('/').next().filter(|name| !name.is_empty())
+    }
+
+    pub fn extension(&self) -> Option<&'a str> {
+        self.file_name()
+            .and_then(|name| name.rfind('.').filter(|dot| *dot > 0).map(|dot| &name[dot + 1..]))
+    }
+
+    pub fn join(&self, path: &str) -> FileSystemResult<VPathBuf> {
+        VPathBuf::new(self.root, join_path(self.path, path).as_str())
+    }
+
+    pub fn to_vpath_buf(&self) -> VPathBuf {
+        VPathBuf {
+            root: self.root,
+            path: String::from(self.path),
+        }
+    }
+}
+
+impl<'a> fmt::Display for VPath<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:/{}", self.root, self.path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VPathBuf {
+    root: RootDir,
+    path: String,
+}
+
+impl VPathBuf {
+    //Normalizes the path, an error if it goes out of the root.
+    pub fn new(root: RootDir, path: &str) -> FileSystemResult<Self> {
+        Ok(VPathBuf {
+            root,
+            path: sandbox_path(path)?,
+        })
+    }
+
+    pub fn root(&self) -> RootDir {
+        self.root
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.path.as_str()
+    }
+
+    pub fn as_vpath<'a>(&'a self) -> VPath<'a> {
+        VPath {
+            root: self.root,
+            path: self.path.as_str(),
+        }
+    }
+
+    pub fn push(&mut self, path: &str) -> FileSystemResult<()> {
+        self.path = sandbox_path(join_path(self.path.as_str(), path).as_str())?;
+        Ok(())
+    }
+}
+
+impl<'a> From<&'a VPathBuf> for VPath<'a> {
+    fn from(path: &'a VPathBuf) -> Self {
+        path.as_vpath()
+    }
+}
+
+impl fmt::Display for VPathBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_vpath().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod vpath_test {
+    use super::*;
+
+    #[test]
+    fn vpath_normalization() {
+        let save = VPathBuf::new(RootDir::UserSaveRoot, "./slots\\autosaves//slot_1.sav").unwrap();
+        assert_eq!(save.as_str(), "slots/autosaves/slot_1.sav");
+        assert_eq!(save.root(), RootDir::UserSaveRoot);
+        assert!(VPathBuf::new(RootDir::UserSaveRoot, "slots/../../secret").is_err());
+        assert!(VPathBuf::new(RootDir::UserSaveRoot, "/etc/passwd").is_err());
+
+        let path = save.as_vpath();
+        assert_eq!(path.file_name(), Some("slot_1.sav"));
+        assert_eq!(path.extension(), Some("sav"));
+        assert_eq!(path.parent().unwrap().as_str(), "slots/autosaves");
+        assert_eq!(path.parent().unwrap().parent().unwrap().parent(), Some(VPath::root_of(RootDir::UserSaveRoot)));
+        assert_eq!(VPath::root_of(RootDir::UserSaveRoot).parent(), None);
+        assert_eq!(path.parent().unwrap().join("slot_2.sav").unwrap().as_str(), "slots/autosaves/slot_2.sav");
+        assert!(path.join("../../..").is_err());
+
+        assert!(VPath::new(RootDir::AssetRoot, "levels/intro.kscene").is_ok());
+        assert!(VPath::new(RootDir::AssetRoot, "levels//intro.kscene").is_err());
+        assert_eq!(format!("{}", VPath::new(RootDir::AssetRoot, "levels").unwrap()), "asset root:/levels");
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ WINDOWS FILESYSTEM.
+
+ The directories of a game on Windows:
+ - %APPDATA% (roaming, synced between the machines of a domain or by the cloud saves): the config of the
+   user and of the engine, the saves.
+ - %LOCALAPPDATA% (this machine only): the data of the user (caches, downloaded content), the logs.
+ Both are under <author>/<game>. Under Proton, they are in the Wine prefix of the game.
+
+ Windows refuses some paths which are valid elsewhere: the device names (CON, NUL, COM1...) even with an
+ extension, the characters <>:"|?*, and the names ending with a dot or a space. The WindowsFilesystem
+ refuses them on every OS, so an asset named aux.png is found on the machine of its author, not by a
+ player. The paths longer than MAX_PATH are given to the OS with the \\?\ prefix.
+*/
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use filesystem::filesystem::Filesystem;
+use filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use filesystem::game_directories::{GameDirectories, RootDir};
+use filesystem::open_options::OpenOptions;
+use filesystem::vfilesystem::{VFile, VFilesystem, VMetadata};
+use filesystem::vpath::{VPath, VPathBuf};
+
+pub const MAX_PATH: usize = 260;
+
+const RESERVED_NAMES: [&'static str; 22] = [
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+const INVALID_CHARACTERS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+//The environment variable lookup is given, for the tests.
+pub fn windows_directories<S: AsRef<str>>(game_name: S, game_author: S, variable: &Fn(&str) -> Option<String>, working_directory: PathBuf) -> FileSystemResult<GameDirectories> {
+    let roaming = variable("APPDATA").filter(|directory| !directory.is_empty())
+        .ok_or_else(|| FileSystemError::GameDirectoryError(String::from("The APPDATA environment variable is not set.")))?;
+    let local = variable("LOCALAPPDATA").filter(|directory| !directory.is_empty()).unwrap_or_else(|| {
+        warn!("The LOCALAPPDATA environment variable is not set, the local data go in APPDATA.");
+        roaming.clone()
+    });
+    let game = |directory: &str| PathBuf::from(directory).join(game_author.as_ref()).join(game_name.as_ref());
+    let (roaming, local) = (game(roaming.as_str()), game(local.as_str()));
+    trace!("Roaming directory: {}, local directory: {}", roaming.display(), local.display());
+
+    let engine_config = roaming.join("maskerad_configuration");
+    let saves = roaming.join("game_saves");
+    let logs = local.join("maskerad_logs");
+    Ok(GameDirectories::from_roots(working_directory, roaming, local)
+        .with_root(RootDir::EngineConfigRoot, engine_config)
+        .with_root(RootDir::UserSaveRoot, saves)
+        .with_root(RootDir::EngineLogRoot, logs))
+}
+
+//Refuses the paths Windows can't create.
+pub fn validate_windows_path(path: &str) -> FileSystemResult<()> {
+    for component in path.split(&['/', '\\'][..]).filter(|component| !component.is_empty() && *component != "." && *component != "..") {
+        if let Some(character) = component.chars().find(|character| INVALID_CHARACTERS.contains(character) || (*character as u32) < 32) {
+            return Err(FileSystemError::InvalidPath(format!("The path {} contains the character {:?}, invalid on Windows.", path, character)));
+        }
+        if component.ends_with('.') || component.ends_with(' ') {
+            return Err(FileSystemError::InvalidPath(format!("The name {} ends with a dot or a space, invalid on Windows.", component)));
+        }
+        let stem = component.split('.').next().unwrap_or("").trim_end();
+        if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+            return Err(FileSystemError::InvalidPath(format!("The name {} is a device name on Windows.", component)));
+        }
+    }
+    Ok(())
+}
+
+//Adds the \\?\ prefix to the absolute paths longer than MAX_PATH.
+pub fn long_path(path: &Path) -> PathBuf {
+    let text = path.to_string_lossy();
+    if text.len() < MAX_PATH || text.starts_with("\\\\?\\") {
+        return path.to_path_buf();
+    }
+    let text = text.replace('/', "\\");
+    if let Some(share) = text.strip_prefix("\\\\") {
+        PathBuf::from(format!("\\\\?\\UNC\\{}", share))
+    } else if text.as_bytes().get(1) == Some(&b':') {
+        PathBuf::from(format!("\\\\?\\{}", text))
+    } else {
+        path.to_path_buf()
+    }
+}
+
+#[derive(Debug)]
+pub struct WindowsFilesystem {
+    directories: GameDirectories,
+}
+
+impl WindowsFilesystem {
+    pub fn new<S: AsRef<str>>(game_name: S, game_author: S) -> FileSystemResult<Self> {
+        debug!("Creating a Windows filesystem for {}, created by {}.", game_name.as_ref(), game_author.as_ref());
+        let directories = windows_directories(game_name, game_author, &|name| env::var(name).ok(), env::current_dir()?)?;
+        Ok(WindowsFilesystem::with_directories(directories))
+    }
+
+    pub fn with_directories(directories: GameDirectories) -> Self {
+        WindowsFilesystem {
+            directories,
+        }
+    }
+
+    pub fn directories(&self) -> &GameDirectories {
+        &self.directories
+    }
+
+    fn os_path(&self, path: VPath) -> FileSystemResult<PathBuf> {
+        validate_windows_path(path.as_str())?;
+        match self.directories.get(&path.root()) {
+            //Joined component by component: the \\?\ paths only accept '\' as separator.
+            Some(root) => Ok(long_path(path.as_str().split('/').fold(root.to_path_buf(), |full_path, component| full_path.join(component)).as_path())),
+            None => Err(FileSystemError::GameDirectoryError(format!("The associated path for {:?} could not be found !", path.root()))),
+        }
+    }
+}
+
+impl VFilesystem for WindowsFilesystem {
+    fn open_with_options(&self, path: VPath, open_options: &OpenOptions) -> FileSystemResult<Box<VFile>> {
+        let file = open_options.to_fs_openoptions().open(self.os_path(path)?)?;
+        Ok(Box::new(file))
+    }
+
+    fn mkdir(&self, path: VPath) -> FileSystemResult<()> {
+        Filesystem::mkdir(self.os_path(path)?)
+    }
+
+    fn rm(&self, path: VPath) -> FileSystemResult<()> {
+        Filesystem::rm(self.os_path(path)?)
+    }
+
+    fn rmrf(&self, path: VPath) -> FileSystemResult<()> {
+        Filesystem::rmrf(self.os_path(path)?)
+    }
+
+    fn metadata(&self, path: VPath) -> FileSystemResult<Box<VMetadata>> {
+        let metadata = fs::metadata(self.os_path(path)?)?;
+        Ok(Box::new(metadata))
+    }
+
+    fn read_dir(&self, path: VPath) -> FileSystemResult<Vec<VPathBuf>> {
+        let mut entries = Vec::new();
+        for entry in Filesystem::read_dir(self.os_path(path)?)? {
+            entries.push(path.join(entry?.file_name().to_string_lossy().as_ref())?);
+        }
+        entries.sort();
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod windows_filesystem_test {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn roaming_and_local_directories() {
+        let variable = |name: &str| match name {
+            "APPDATA" => Some(String::from("C:/Users/player/AppData/Roaming")),
+            "LOCALAPPDATA" => Some(String::from("C:/Users/player/AppData/Local")),
+            _ => None,
+        };
+        let directories = windows_directories("game", "studio", &variable, PathBuf::from("C:/Games/game")).unwrap();
+        let roaming = Path::new("C:/Users/player/AppData/Roaming/studio/game");
+        let local = Path::new("C:/Users/player/AppData/Local/studio/game");
+        assert_eq!(directories.get(&RootDir::UserConfigRoot), Some(roaming));
+        assert_eq!(directories.get(&RootDir::UserSaveRoot), Some(roaming.join("game_saves").as_path()));
+        assert_eq!(directories.get(&RootDir::EngineConfigRoot), Some(roaming.join("maskerad_configuration").as_path()));
+        assert_eq!(directories.get(&RootDir::UserDataRoot), Some(local));
+        assert_eq!(directories.get(&RootDir::EngineLogRoot), Some(local.join("maskerad_logs").as_path()));
+
+        let roaming_only = |name: &str| if name == "APPDATA" {Some(String::from("C:/AppData"))} else {None};
+        let directories = windows_directories("game", "studio", &roaming_only, PathBuf::from(".")).unwrap();
+        assert_eq!(directories.get(&RootDir::UserDataRoot), Some(Path::new("C:/AppData/studio/game")));
+        assert!(windows_directories("game", "studio", &|_| None, PathBuf::from(".")).is_err());
+    }
+
+    #[test]
+    fn windows_path_rules() {
+        assert!(validate_windows_path("textures/rock.png").is_ok());
+        assert!(validate_windows_path("../saves\\slot 1.sav").is_ok());
+        assert!(matches!(validate_windows_path("textures/aux.png"), Err(FileSystemError::InvalidPath(_))));
+        assert!(validate_windows_path("Com1").is_err());
+        assert!(validate_windows_path("console.txt").is_ok());
+        assert!(validate_windows_path("what?.txt").is_err());
+        assert!(validate_windows_path("folder./file").is_err());
+        assert!(validate_windows_path("file ").is_err());
+
+        let deep = format!("C:\\Games\\{}", "a".repeat(MAX_PATH));
+        assert!(long_path(Path::new(deep.as_str())).to_string_lossy().starts_with("\\\\?\\C:\\Games"));
+        let share = format!("\\\\server\\share\\{}", "a".repeat(MAX_PATH));
+        assert!(long_path(Path::new(share.as_str())).to_string_lossy().starts_with("\\\\?\\UNC\\server\\share"));
+        assert_eq!(long_path(Path::new("C:\\short")), PathBuf::from("C:\\short"));
+    }
+
+    #[test]
+    fn windows_filesystem_io() {
+        let root = env::temp_dir().join(format!("maskerad_windows_filesystem_test_{}", process::id()));
+        let fs = WindowsFilesystem::with_directories(GameDirectories::from_roots(root.clone(), root.join("config"), root.join("data")));
+        fs.mkdir(VPath::new(RootDir::UserSaveRoot, "slots").unwrap()).unwrap();
+        fs.write_all(VPath::new(RootDir::UserSaveRoot, "slots/1.sav").unwrap(), b"save").unwrap();
+        assert_eq!(fs.read_to_string(VPath::new(RootDir::UserSaveRoot, "slots/1.sav").unwrap()).unwrap(), "save");
+        assert_eq!(fs.read_dir(VPath::new(RootDir::UserSaveRoot, "slots").unwrap()).unwrap(), vec![VPathBuf::new(RootDir::UserSaveRoot, "slots/1.sav").unwrap()]);
+        assert!(matches!(fs.create(VPath::new(RootDir::UserSaveRoot, "slots/nul.sav").unwrap()), Err(FileSystemError::InvalidPath(_))));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
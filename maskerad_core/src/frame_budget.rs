@@ -0,0 +1,259 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ FRAME BUDGET.
+
+ The background work done on the main thread (asset decompression, navmesh rebuilds, cache collection)
+ is split in small steps, and given a time slice every frame: the frame time left after the game, with
+ a min (the work always progresses, even in slow frames) and a max.
+
+ The tasks are registered in the TaskBudget, and stepped by priority, in turns within a priority,
+ until the slice is spent. A task should keep its steps well under a millisecond: a step is never
+ interrupted, a long step overruns the slice.
+*/
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WorkStatus {
+    Pending,
+    Done,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum TaskPriority {
+    Low,
+    Normal,
+    High,
+}
+
+pub trait BudgetedTask {
+    fn name(&self) -> &str;
+    //A short piece of work.
+    fn step(&mut self) -> WorkStatus;
+}
+
+//A task made of a closure.
+pub struct FnTask<F: FnMut() -> WorkStatus> {
+    name: String,
+    function: F,
+}
+
+impl<F: FnMut() -> WorkStatus> FnTask<F> {
+    pub fn new<S: Into<String>>(name: S, function: F) -> Self {
+        FnTask {
+            name: name.into(),
+            function,
+        }
+    }
+}
+
+impl<F: FnMut() -> WorkStatus> BudgetedTask for FnTask<F> {
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    fn step(&mut self) -> WorkStatus {
+        (self.function)()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FrameBudget {
+    pub target_frame_time: Duration,
+    pub min_slice: Duration,
+    pub max_slice: Duration,
+}
+
+impl FrameBudget {
+    pub fn for_frame_rate(frame_rate: u32) -> Self {
+        FrameBudget {
+            target_frame_time: Duration::from_secs(1) / frame_rate.max(1),
+            min_slice: Duration::from_micros(500),
+            max_slice: Duration::from_millis(4),
+        }
+    }
+
+    //The slice granted to the background work, after frame_time spent on the frame.
+    pub fn grant(&self, frame_time: Duration) -> Duration {
+        let left = if frame_time < self.target_frame_time { self.target_frame_time - frame_time } else { Duration::from_secs(0) };
+        left.max(self.min_slice).min(self.max_slice)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct TaskId(u64);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BudgetReport {
+    pub steps: usize,
+    pub elapsed: Duration,
+    pub finished: Vec<TaskId>,
+}
+
+struct RegisteredTask {
+    id: TaskId,
+    priority: TaskPriority,
+    task: Box<BudgetedTask>,
+}
+
+#[derive(Default)]
+pub struct TaskBudget {
+    //Sorted by priority, the highest first.
+    tasks: Vec<RegisteredTask>,
+    next_id: u64,
+    //The first task of each priority to step next frame, for the turns.
+    turns: Vec<(TaskPriority, TaskId)>,
+}
+
+impl TaskBudget {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    pub fn register(&mut self, priority: TaskPriority, task: Box<BudgetedTask>) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+        debug!("Background task {} registered.", task.name());
+        let position = self.tasks.iter().position(|registered| registered.priority < priority).unwrap_or(self.tasks.len());
+        self.tasks.insert(position, RegisteredTask {
+            id,
+            priority,
+            task,
+        });
+        id
+    }
+
+    //False if the task was finished already.
+    pub fn cancel(&mut self, id: TaskId) -> bool {
+        match self.tasks.iter().position(|registered| registered.id == id) {
+            Some(position) => {
+                debug!("Background task {} canceled.", self.tasks[position].task.name());
+                self.tasks.remove(position);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn run(&mut self, slice: Duration) -> BudgetReport {
+        let start = Instant::now();
+        self.run_timed(slice, || start.elapsed())
+    }
+
+    //elapsed: the time spent since the start of the slice. At least one step is done.
+    pub fn run_timed<C: FnMut() -> Duration>(&mut self, slice: Duration, mut elapsed: C) -> BudgetReport {
+        let mut report = BudgetReport::default();
+        let mut priority_index = 0;
+        while priority_index < self.tasks.len() {
+            let priority = self.tasks[priority_index].priority;
+            let count = self.tasks[priority_index..].iter().take_while(|registered| registered.priority == priority).count();
+            //The turns: start with the task after the last one stepped.
+            let first = self.turns.iter()
+                .find(|&&(turn_priority, _)| turn_priority == priority)
+                .and_then(|&(_, id)| self.tasks[priority_index..priority_index + count].iter().position(|registered| registered.id == id))
+                .unwrap_or(0);
+            let mut order: Vec<TaskId> = (0..count).map(|offset| self.tasks[priority_index + (first + offset) % count].id).collect();
+
+            //Step the tasks of this priority in turns, until they are done or the slice is spent.
+            while !order.is_empty() {
+                let mut index = 0;
+                while index < order.len() {
+                    if report.steps > 0 && elapsed() >= slice {
+                        let next = order[index];
+                        self.set_turn(priority, next);
+                        report.elapsed = elapsed();
+                        return report;
+                    }
+                    let position = self.tasks.iter().position(|registered| registered.id == order[index]).expect("The ordered tasks are registered.");
+                    report.steps += 1;
+                    if self.tasks[position].task.step() == WorkStatus::Done {
+                        trace!("Background task {} finished.", self.tasks[position].task.name());
+                        report.finished.push(order[index]);
+                        self.tasks.remove(position);
+                        order.remove(index);
+                    } else {
+                        index += 1;
+                    }
+                }
+            }
+            priority_index += self.tasks[priority_index..].iter().take_while(|registered| registered.priority == priority).count();
+        }
+        report.elapsed = elapsed();
+        report
+    }
+
+    fn set_turn(&mut self, priority: TaskPriority, id: TaskId) {
+        self.turns.retain(|&(turn_priority, _)| turn_priority != priority);
+        self.turns.push((priority, id));
+    }
+}
+
+#[cfg(test)]
+mod frame_budget_test {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn counter(name: &str, steps: usize, log: Rc<Cell<usize>>) -> Box<BudgetedTask> {
+        let mut left = steps;
+        Box::new(FnTask::new(name, move || {
+            log.set(log.get() + 1);
+            left -= 1;
+            if left == 0 { WorkStatus::Done } else { WorkStatus::Pending }
+        }))
+    }
+
+    #[test]
+    fn task_budget_slices_and_turns() {
+        let budget = FrameBudget::for_frame_rate(60);
+        assert_eq!(budget.grant(Duration::from_millis(14)), Duration::from_nanos(2_666_666));
+        assert_eq!(budget.grant(Duration::from_millis(30)), Duration::from_micros(500));
+        assert_eq!(budget.grant(Duration::from_millis(1)), Duration::from_millis(4));
+
+        let decompression = Rc::new(Cell::new(0));
+        let navmesh = Rc::new(Cell::new(0));
+        let cache = Rc::new(Cell::new(0));
+        let mut tasks = TaskBudget::new();
+        tasks.register(TaskPriority::Low, counter("cache", 100, cache.clone()));
+        let first = tasks.register(TaskPriority::Normal, counter("decompression", 3, decompression.clone()));
+        tasks.register(TaskPriority::Normal, counter("navmesh", 100, navmesh.clone()));
+
+        //A fake clock: every step takes 1 ms, the slice is 5 ms.
+        let clock = Cell::new(0);
+        let report = tasks.run_timed(Duration::from_millis(5), || {
+            clock.set(clock.get() + 1);
+            Duration::from_millis(clock.get() / 2)
+        });
+        assert_eq!(report.finished, vec![first]);
+        assert_eq!(decompression.get(), 3);
+        assert!(navmesh.get() >= 2);
+        assert_eq!(cache.get(), 0);
+        assert_eq!(tasks.len(), 2);
+
+        //Even without time left, one step is done: the navmesh, in its turn.
+        let before = navmesh.get();
+        let report = tasks.run_timed(Duration::from_millis(0), || Duration::from_millis(1));
+        assert_eq!(report.steps, 1);
+        assert_eq!(navmesh.get(), before + 1);
+
+        //With time left, the low priority tasks get the rest.
+        let report = tasks.run(Duration::from_secs(10));
+        assert_eq!(report.finished.len(), 2);
+        assert_eq!(cache.get(), 100);
+        assert!(tasks.is_empty());
+    }
+}
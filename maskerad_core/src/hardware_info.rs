@@ -0,0 +1,253 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ HARDWARE INFO.
+
+ What the engine knows about the machine, for the diagnostics (the crash reports, the debug overlay) and
+ the telemetry, and to size the thread pools of the job system:
+ - the CPU topology: the logical cores, the physical cores they belong to (SMT), their NUMA node, and
+   their clusters on heterogeneous CPUs (big.LITTLE, the P-cores and E-cores of hybrid CPUs), from the
+   capacity or the maximum frequency of each core,
+ - the CPU name and the memory.
+
+ On Linux, the topology comes from /sys/devices/system/cpu. Elsewhere, the logical cores are counted, and
+ each one is considered a physical core.
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use serde_json;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LogicalCore {
+    pub id: usize,
+    pub package: usize,
+    //The physical core, in its package.
+    pub core: usize,
+    pub numa_node: usize,
+    //The relative performance of the core (the Linux cpu_capacity, or the maximum frequency in kHz).
+    pub performance: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoreCluster {
+    pub performance: Option<u64>,
+    pub logical_cores: Vec<usize>,
+    pub physical_cores: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CpuTopology {
+    pub logical_cores: Vec<LogicalCore>,
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok().and_then(|value| value.trim().parse().ok())
+}
+
+impl CpuTopology {
+    //Each logical core on its own physical core.
+    pub fn flat(logical_cores: usize) -> Self {
+        CpuTopology {
+            logical_cores: (0..logical_cores.max(1)).map(|id| LogicalCore { id, package: 0, core: id, numa_node: 0, performance: None }).collect(),
+        }
+    }
+
+    //From /sys/devices/system/cpu, or another root for the tests.
+    pub fn from_sysfs(cpu_root: &Path) -> Option<Self> {
+        let mut logical_cores = Vec::new();
+        for entry in fs::read_dir(cpu_root).ok()?.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let id = match name.trim_start_matches("cpu").parse::<usize>() {
+                Ok(id) if name.starts_with("cpu") => id,
+                _ => continue,
+            };
+            let directory = entry.path();
+            let topology = directory.join("topology");
+            //The directory of the core has a nodeN link to its NUMA node.
+            let numa_node = fs::read_dir(&directory).ok()
+                .and_then(|entries| entries.filter_map(|entry| entry.ok())
+                    .filter_map(|entry| {
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        if name.starts_with("node") {name[4..].parse::<usize>().ok()} else {None}
+                    })
+                    .next())
+                .unwrap_or(0);
+            let performance = read_number(&directory.join("cpu_capacity"))
+                .or_else(|| read_number(&directory.join("cpufreq").join("cpuinfo_max_freq")));
+
+            logical_cores.push(LogicalCore {
+                id,
+                package: read_number(&topology.join("physical_package_id")).unwrap_or(0) as usize,
+                core: read_number(&topology.join("core_id")).unwrap_or(id as u64) as usize,
+                numa_node,
+                performance,
+            });
+        }
+        if logical_cores.is_empty() {
+            return None;
+        }
+        logical_cores.sort_by_key(|core| core.id);
+        Some(CpuTopology { logical_cores })
+    }
+
+    pub fn detect() -> Self {
+        let topology = if cfg!(target_os = "linux") {
+            CpuTopology::from_sysfs(Path::new("/sys/devices/system/cpu"))
+        } else {
+            None
+        };
+        topology.unwrap_or_else(|| CpuTopology::flat(thread::available_parallelism().map(|cores| cores.get()).unwrap_or(1)))
+    }
+
+    pub fn logical_core_count(&self) -> usize {
+        self.logical_cores.len()
+    }
+
+    pub fn physical_core_count(&self) -> usize {
+        self.logical_cores.iter().map(|core| (core.package, core.core)).collect::<BTreeSet<_>>().len()
+    }
+
+    pub fn numa_node_count(&self) -> usize {
+        self.logical_cores.iter().map(|core| core.numa_node).collect::<BTreeSet<_>>().len()
+    }
+
+    //The fastest cluster first. A homogeneous CPU has one cluster.
+    pub fn clusters(&self) -> Vec<CoreCluster> {
+        let mut by_performance: BTreeMap<Option<u64>, Vec<&LogicalCore>> = BTreeMap::new();
+        for core in self.logical_cores.iter() {
+            by_performance.entry(core.performance).or_insert_with(Vec::new).push(core);
+        }
+        by_performance.into_iter().rev().map(|(performance, cores)| CoreCluster {
+            performance,
+            logical_cores: cores.iter().map(|core| core.id).collect(),
+            physical_cores: cores.iter().map(|core| (core.package, core.core)).collect::<BTreeSet<_>>().len(),
+        }).collect()
+    }
+
+    pub fn is_heterogeneous(&self) -> bool {
+        self.clusters().len() > 1
+    }
+
+    //The main thread and the render thread keep a physical core each, the workers get the rest. The
+    //sibling threads of SMT add little to the jobs of a frame. On a heterogeneous CPU, the efficient
+    //cores still run jobs, but only count for half.
+    pub fn recommended_workers(&self) -> usize {
+        let clusters = self.clusters();
+        let mut capacity = 0.0;
+        for (index, cluster) in clusters.iter().enumerate() {
+            capacity += if index == 0 {cluster.physical_cores as f32} else {cluster.physical_cores as f32 * 0.5};
+        }
+        let reserved = if self.physical_core_count() >= 4 {2.0} else {1.0};
+        ((capacity - reserved).floor() as usize).max(1)
+    }
+
+    pub fn recommended_io_threads(&self) -> usize {
+        if self.physical_core_count() > 4 {2} else {1}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HardwareInfo {
+    pub os: &'static str,
+    pub architecture: &'static str,
+    pub cpu_name: Option<String>,
+    pub memory_bytes: Option<u64>,
+    pub cpu: CpuTopology,
+}
+
+impl HardwareInfo {
+    pub fn detect() -> Self {
+        let cpu_name = fs::read_to_string("/proc/cpuinfo").ok().and_then(|cpuinfo| {
+            cpuinfo.lines()
+                .find(|line| line.starts_with("model name") || line.starts_with("Hardware"))
+                .and_then(|line| line.splitn(2, ':').nth(1))
+                .map(|name| name.trim().to_string())
+        });
+        let memory_bytes = fs::read_to_string("/proc/meminfo").ok().and_then(|meminfo| {
+            meminfo.lines()
+                .find(|line| line.starts_with("MemTotal:"))
+                .and_then(|line| line.split_whitespace().nth(1))
+                .and_then(|kilobytes| kilobytes.parse::<u64>().ok())
+                .map(|kilobytes| kilobytes * 1024)
+        });
+
+        HardwareInfo {
+            os: env::consts::OS,
+            architecture: env::consts::ARCH,
+            cpu_name,
+            memory_bytes,
+            cpu: CpuTopology::detect(),
+        }
+    }
+
+    //For the crash reports and the debug overlay.
+    pub fn summary(&self) -> String {
+        let clusters: Vec<String> = self.cpu.clusters().iter()
+            .map(|cluster| format!("{} cores ({} threads)", cluster.physical_cores, cluster.logical_cores.len()))
+            .collect();
+        format!("{} {}, {}: {}, {} NUMA node(s), {} MiB",
+            self.os,
+            self.architecture,
+            self.cpu_name.as_ref().map(|name| name.as_str()).unwrap_or("unknown CPU"),
+            clusters.join(" + "),
+            self.cpu.numa_node_count(),
+            self.memory_bytes.map(|bytes| bytes / (1024 * 1024)).unwrap_or(0))
+    }
+
+    //For the telemetry.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod hardware_info_test {
+    use super::*;
+
+    fn write_core(root: &Path, id: usize, core: usize, node: usize, capacity: u64) {
+        let directory = root.join(format!("cpu{}", id));
+        fs::create_dir_all(directory.join("topology")).unwrap();
+        fs::create_dir_all(directory.join(format!("node{}", node))).unwrap();
+        fs::write(directory.join("topology").join("physical_package_id"), "0\n").unwrap();
+        fs::write(directory.join("topology").join("core_id"), format!("{}\n", core)).unwrap();
+        fs::write(directory.join("cpu_capacity"), format!("{}\n", capacity)).unwrap();
+    }
+
+    #[test]
+    fn cpu_topology_from_sysfs() {
+        let root = env::temp_dir().join("maskerad_cpu_topology_test");
+        let _ = fs::remove_dir_all(&root);
+        //4 performance cores with SMT, 4 efficient cores.
+        for id in 0..8 {
+            write_core(&root, id, id / 2, 0, 1024);
+        }
+        for id in 8..12 {
+            write_core(&root, id, id - 4, 0, 512);
+        }
+        fs::create_dir_all(root.join("cpufreq")).unwrap();
+
+        let topology = CpuTopology::from_sysfs(&root).unwrap();
+        assert_eq!(topology.logical_core_count(), 12);
+        assert_eq!(topology.physical_core_count(), 8);
+        assert_eq!(topology.numa_node_count(), 1);
+        let clusters = topology.clusters();
+        assert_eq!(clusters.len(), 2);
+        assert_eq!((clusters[0].performance, clusters[0].physical_cores), (Some(1024), 4));
+        assert_eq!(clusters[1].logical_cores, vec![8, 9, 10, 11]);
+        assert_eq!(topology.recommended_workers(), 4);
+        assert_eq!(topology.recommended_io_threads(), 2);
+
+        assert_eq!(CpuTopology::flat(2).recommended_workers(), 1);
+        assert!(HardwareInfo::detect().to_json().contains("logical_cores"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
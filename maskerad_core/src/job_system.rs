@@ -0,0 +1,256 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ JOB SYSTEM.
+
+ The threads of the engine belong to pools: the render thread, the audio thread, the workers running
+ the jobs of the frame, and the I/O threads loading the files. Each pool has a priority and an optional
+ core affinity, from the [threads] section of the engine config, applied by each thread when it starts.
+
+ The render and audio threads are spawned by their systems with spawn_pool_thread. The workers and the
+ I/O scheduler are ThreadPools: the jobs are sent to a queue shared by the threads of the pool.
+
+ The priorities are applied with the nice value of the thread on Linux. Raising the priority needs
+ CAP_SYS_NICE: when it fails, the thread keeps running with its default priority, and a warning is logged.
+ The other platforms only log that the settings are not applied yet.
+*/
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::thread::{self, JoinHandle};
+use engine_configuration::engine_config::EngineConfig;
+use engine_configuration::threading::{PoolSettings, ThreadPriority, ThreadingSettings};
+use hardware_info::CpuTopology;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PoolKind {
+    Render,
+    Audio,
+    Workers,
+    Io,
+}
+
+impl PoolKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PoolKind::Render => "render",
+            PoolKind::Audio => "audio",
+            PoolKind::Workers => "worker",
+            PoolKind::Io => "io",
+        }
+    }
+
+    pub fn settings<'a>(&self, threading: &'a ThreadingSettings) -> &'a PoolSettings {
+        match *self {
+            PoolKind::Render => &threading.render,
+            PoolKind::Audio => &threading.audio,
+            PoolKind::Workers => &threading.workers,
+            PoolKind::Io => &threading.io,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn apply_priority(priority: ThreadPriority) -> Result<(), String> {
+    let nice = match priority {
+        ThreadPriority::Low => 10,
+        ThreadPriority::Normal => 0,
+        ThreadPriority::High => -5,
+        ThreadPriority::Highest => -10,
+    };
+    //On Linux, the nice value is per thread.
+    let result = unsafe {
+        let thread_id = libc::syscall(libc::SYS_gettid) as libc::id_t;
+        libc::setpriority(libc::PRIO_PROCESS, thread_id, nice)
+    };
+    if result == 0 {Ok(())} else {Err(format!("setpriority failed: {}", io::Error::last_os_error()))}
+}
+
+#[cfg(target_os = "linux")]
+fn apply_affinity(cores: &[usize]) -> Result<(), String> {
+    unsafe {
+        let mut set: libc::cpu_set_t = ::std::mem::zeroed();
+        for core in cores.iter() {
+            libc::CPU_SET(*core, &mut set);
+        }
+        if libc::sched_setaffinity(0, ::std::mem::size_of::<libc::cpu_set_t>(), &set) == 0 {
+            Ok(())
+        } else {
+            Err(format!("sched_setaffinity failed: {}", io::Error::last_os_error()))
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply_priority(priority: ThreadPriority) -> Result<(), String> {
+    match priority {
+        ThreadPriority::Normal => Ok(()),
+        _ => Err(String::from("thread priorities are not supported on this platform yet")),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply_affinity(_cores: &[usize]) -> Result<(), String> {
+    Err(String::from("core affinities are not supported on this platform yet"))
+}
+
+//Applies the settings of the pool to the current thread.
+pub fn apply_thread_settings(settings: &PoolSettings) -> Result<(), String> {
+    let priority = apply_priority(settings.priority);
+    let affinity = match settings.affinity {
+        Some(ref cores) if !cores.is_empty() => apply_affinity(cores.as_slice()),
+        _ => Ok(()),
+    };
+    priority.and(affinity)
+}
+
+pub fn spawn_pool_thread<F, T>(kind: PoolKind, index: usize, settings: PoolSettings, function: F) -> io::Result<JoinHandle<T>> where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let name = format!("{}-{}", kind.name(), index);
+    debug!("Spawning the thread {} with {:?}.", name, settings);
+    thread::Builder::new().name(name.clone()).spawn(move || {
+        if let Err(error) = apply_thread_settings(&settings) {
+            warn!("The settings of the thread {} could not be applied: {}.", name, error);
+        }
+        function()
+    })
+}
+
+type Job = Box<FnOnce() + Send>;
+
+pub struct ThreadPool {
+    kind: PoolKind,
+    sender: Option<Sender<Job>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    //The thread count of the settings, or the default one.
+    pub fn new(kind: PoolKind, settings: &PoolSettings, default_threads: usize) -> io::Result<Self> {
+        let thread_count = settings.threads.unwrap_or(default_threads).max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver: Arc<Mutex<Receiver<Job>>> = Arc::new(Mutex::new(receiver));
+
+        let mut threads = Vec::with_capacity(thread_count);
+        for index in 0..thread_count {
+            let receiver = receiver.clone();
+            threads.push(spawn_pool_thread(kind, index, settings.clone(), move || {
+                loop {
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+                    match job {
+                        Ok(job) => job(),
+                        //The pool has been dropped.
+                        Err(_) => return,
+                    }
+                }
+            })?);
+        }
+
+        Ok(ThreadPool {
+            kind,
+            sender: Some(sender),
+            threads,
+        })
+    }
+
+    pub fn kind(&self) -> PoolKind {
+        self.kind
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.threads.len()
+    }
+
+    pub fn execute<F>(&self, job: F) where
+        F: FnOnce() + Send + 'static
+    {
+        if let Some(ref sender) = self.sender {
+            if sender.send(Box::new(job)).is_err() {
+                error!("The {} pool has no thread left to run the job.", self.kind.name());
+            }
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    //The queued jobs are run before the threads stop.
+    fn drop(&mut self) {
+        self.sender.take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+pub struct JobSystem {
+    workers: ThreadPool,
+    io: ThreadPool,
+}
+
+impl JobSystem {
+    pub fn new(config: &EngineConfig) -> io::Result<Self> {
+        JobSystem::with_topology(config, &CpuTopology::detect())
+    }
+
+    //The pools without a thread count in the config are sized from the topology of the CPU.
+    pub fn with_topology(config: &EngineConfig, topology: &CpuTopology) -> io::Result<Self> {
+        let threading = config.threading();
+        let workers = ThreadPool::new(PoolKind::Workers, &threading.workers, topology.recommended_workers())?;
+        let io = ThreadPool::new(PoolKind::Io, &threading.io, topology.recommended_io_threads())?;
+        debug!("Job system: {} workers, {} I/O threads.", workers.thread_count(), io.thread_count());
+        Ok(JobSystem {
+            workers,
+            io,
+        })
+    }
+
+    pub fn workers(&self) -> &ThreadPool {
+        &self.workers
+    }
+
+    //The I/O scheduler.
+    pub fn io(&self) -> &ThreadPool {
+        &self.io
+    }
+}
+
+#[cfg(test)]
+mod job_system_test {
+    use super::*;
+
+    #[test]
+    fn thread_pools_follow_the_config() {
+        let config = EngineConfig::from_layers(&["[threads.workers]\nthreads = 3\naffinity = [0]\n[threads.io]\npriority = \"Low\"\n"]).unwrap();
+        assert_eq!(config.threading().workers.threads, Some(3));
+        assert_eq!(config.threading().io.priority, ThreadPriority::Low);
+        assert_eq!(config.threading().render.priority, ThreadPriority::High);
+
+        let jobs = JobSystem::new(&config).unwrap();
+        assert_eq!(jobs.workers().thread_count(), 3);
+        assert_eq!(JobSystem::with_topology(&EngineConfig::default(), &CpuTopology::flat(8)).unwrap().workers().thread_count(), 6);
+        let (sender, receiver) = mpsc::channel();
+        for job in 0..10 {
+            let sender = sender.clone();
+            jobs.workers().execute(move || sender.send(job).unwrap());
+        }
+        let mut done: Vec<i32> = receiver.iter().take(10).collect();
+        done.sort();
+        assert_eq!(done, (0..10).collect::<Vec<i32>>());
+
+        let name = spawn_pool_thread(PoolKind::Render, 0, PoolSettings::default(), || thread::current().name().map(String::from))
+            .unwrap().join().unwrap();
+        assert_eq!(name, Some(String::from("render-0")));
+        assert!(apply_thread_settings(&PoolSettings::default()).is_ok());
+    }
+}
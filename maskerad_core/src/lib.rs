@@ -7,10 +7,22 @@
 
 pub mod random;
 pub mod clock;
+pub mod time_channels;
 pub mod engine_configuration;
 pub mod filesystem;
 pub mod localization;
 pub mod allocators;
+pub mod undo;
+pub mod math;
+pub mod reflection;
+pub mod watchdog;
+pub mod startup;
+pub mod system_status;
+pub mod power;
+pub mod job_system;
+pub mod hardware_info;
+pub mod name;
+pub mod frame_budget;
 
 extern crate maskerad_memory_allocators;
 
@@ -27,6 +39,9 @@ extern crate serde_json;
 extern crate cgmath;
 extern crate rand;
 
+#[cfg(unix)]
+extern crate libc;
+
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
\ No newline at end of file
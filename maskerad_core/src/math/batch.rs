@@ -0,0 +1,351 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ BATCH MATH.
+
+ The hot loops over arrays of math values, with SIMD implementations:
+ - transform_vec4s: a matrix applied to an array of vec4 (the vertices of the CPU skinning, the particles).
+ - multiply_palette: the matrix palette of the skinning, each bone matrix by its inverse bind matrix.
+ - cull_aabbs: the frustum test of an array of bounding boxes.
+
+ SSE is part of x86_64 and NEON of aarch64, they are always used on these architectures. AVX is detected
+ at runtime, it transforms two vec4 at once. The other architectures use the scalar versions, which are
+ also the reference of the tests. The benches of maskerad_core compare both.
+
+ The matrices are column-major [f32; 16], like the GPU buffers. The SIMD loads and stores take their pointers
+ from the whole arrays (as_ptr().add(n)), never from a single element: the tests also run under Miri.
+*/
+
+pub type Matrix4 = [f32; 16];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub center: [f32; 3],
+    //Half of the size on each axis.
+    pub extents: [f32; 3],
+}
+
+//A plane [nx, ny, nz, d]: the points p with n.p + d >= 0 are inside. The normals point inside the frustum.
+pub type Plane = [f32; 4];
+
+pub mod scalar {
+    use super::{Aabb, Matrix4, Plane};
+
+    pub fn transform_vec4(matrix: &Matrix4, vector: [f32; 4]) -> [f32; 4] {
+        let mut result = [0.0; 4];
+        for row in 0..4 {
+            result[row] = matrix[row] * vector[0] + matrix[4 + row] * vector[1] + matrix[8 + row] * vector[2] + matrix[12 + row] * vector[3];
+        }
+        result
+    }
+
+    pub fn transform_vec4s(matrix: &Matrix4, input: &[[f32; 4]], output: &mut [[f32; 4]]) {
+        for (vector, result) in input.iter().zip(output.iter_mut()) {
+            *result = transform_vec4(matrix, *vector);
+        }
+    }
+
+    pub fn multiply(a: &Matrix4, b: &Matrix4) -> Matrix4 {
+        let mut result = [0.0; 16];
+        for column in 0..4 {
+            let transformed = transform_vec4(a, [b[column * 4], b[column * 4 + 1], b[column * 4 + 2], b[column * 4 + 3]]);
+            result[column * 4..column * 4 + 4].copy_from_slice(&transformed);
+        }
+        result
+    }
+
+    pub fn multiply_palette(bones: &[Matrix4], inverse_binds: &[Matrix4], output: &mut [Matrix4]) {
+        for ((bone, inverse_bind), result) in bones.iter().zip(inverse_binds.iter()).zip(output.iter_mut()) {
+            *result = multiply(bone, inverse_bind);
+        }
+    }
+
+    pub fn is_visible(planes: &[Plane; 6], aabb: &Aabb) -> bool {
+        planes.iter().all(|plane| {
+            let distance = plane[0] * aabb.center[0] + plane[1] * aabb.center[1] + plane[2] * aabb.center[2] + plane[3];
+            let radius = plane[0].abs() * aabb.extents[0] + plane[1].abs() * aabb.extents[1] + plane[2].abs() * aabb.extents[2];
+            distance + radius >= 0.0
+        })
+    }
+
+    pub fn cull_aabbs(planes: &[Plane; 6], aabbs: &[Aabb], visible: &mut [bool]) {
+        for (aabb, visible) in aabbs.iter().zip(visible.iter_mut()) {
+            *visible = is_visible(planes, aabb);
+        }
+    }
+}
+
+//The planes in SoA, 4 by register: the planes 0 to 3, then 4 and 5 with 2 planes which never cull.
+fn planes_soa(planes: &[Plane; 6]) -> [[[f32; 4]; 4]; 2] {
+    let mut soa = [[[0.0; 4]; 4]; 2];
+    for group in 0..2 {
+        for lane in 0..4 {
+            let plane = planes.get(group * 4 + lane).cloned().unwrap_or([0.0, 0.0, 0.0, ::std::f32::MAX]);
+            for component in 0..4 {
+                soa[group][component][lane] = plane[component];
+            }
+        }
+    }
+    soa
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse {
+    use std::arch::x86_64::*;
+    use super::{Aabb, Matrix4, Plane, planes_soa};
+
+    #[inline(always)]
+    unsafe fn columns(matrix: &Matrix4) -> [__m128; 4] {
+        let matrix = matrix.as_ptr();
+        [_mm_loadu_ps(matrix), _mm_loadu_ps(matrix.add(4)), _mm_loadu_ps(matrix.add(8)), _mm_loadu_ps(matrix.add(12))]
+    }
+
+    #[inline(always)]
+    unsafe fn transform(columns: &[__m128; 4], vector: __m128) -> __m128 {
+        let x = _mm_mul_ps(columns[0], _mm_shuffle_ps(vector, vector, 0x00));
+        let y = _mm_mul_ps(columns[1], _mm_shuffle_ps(vector, vector, 0x55));
+        let z = _mm_mul_ps(columns[2], _mm_shuffle_ps(vector, vector, 0xaa));
+        let w = _mm_mul_ps(columns[3], _mm_shuffle_ps(vector, vector, 0xff));
+        _mm_add_ps(_mm_add_ps(x, y), _mm_add_ps(z, w))
+    }
+
+    pub fn transform_vec4s(matrix: &Matrix4, input: &[[f32; 4]], output: &mut [[f32; 4]]) {
+        unsafe {
+            let columns = columns(matrix);
+            for (vector, result) in input.iter().zip(output.iter_mut()) {
+                _mm_storeu_ps(result.as_mut_ptr(), transform(&columns, _mm_loadu_ps(vector.as_ptr())));
+            }
+        }
+    }
+
+    pub fn multiply_palette(bones: &[Matrix4], inverse_binds: &[Matrix4], output: &mut [Matrix4]) {
+        unsafe {
+            for ((bone, inverse_bind), result) in bones.iter().zip(inverse_binds.iter()).zip(output.iter_mut()) {
+                let columns = columns(bone);
+                for column in 0..4 {
+                    let transformed = transform(&columns, _mm_loadu_ps(inverse_bind.as_ptr().add(column * 4)));
+                    _mm_storeu_ps(result.as_mut_ptr().add(column * 4), transformed);
+                }
+            }
+        }
+    }
+
+    pub fn cull_aabbs(planes: &[Plane; 6], aabbs: &[Aabb], visible: &mut [bool]) {
+        let soa = planes_soa(planes);
+        unsafe {
+            let sign_mask = _mm_set1_ps(-0.0);
+            let groups: Vec<[__m128; 7]> = soa.iter().map(|group| {
+                let (x, y, z) = (_mm_loadu_ps(group[0].as_ptr()), _mm_loadu_ps(group[1].as_ptr()), _mm_loadu_ps(group[2].as_ptr()));
+                [x, y, z, _mm_loadu_ps(group[3].as_ptr()), _mm_andnot_ps(sign_mask, x), _mm_andnot_ps(sign_mask, y), _mm_andnot_ps(sign_mask, z)]
+            }).collect();
+
+            for (aabb, visible) in aabbs.iter().zip(visible.iter_mut()) {
+                let (cx, cy, cz) = (_mm_set1_ps(aabb.center[0]), _mm_set1_ps(aabb.center[1]), _mm_set1_ps(aabb.center[2]));
+                let (ex, ey, ez) = (_mm_set1_ps(aabb.extents[0]), _mm_set1_ps(aabb.extents[1]), _mm_set1_ps(aabb.extents[2]));
+                let mut outside = 0;
+                for group in groups.iter() {
+                    let distance = _mm_add_ps(_mm_add_ps(_mm_mul_ps(group[0], cx), _mm_mul_ps(group[1], cy)), _mm_add_ps(_mm_mul_ps(group[2], cz), group[3]));
+                    let radius = _mm_add_ps(_mm_add_ps(_mm_mul_ps(group[4], ex), _mm_mul_ps(group[5], ey)), _mm_mul_ps(group[6], ez));
+                    outside |= _mm_movemask_ps(_mm_cmplt_ps(_mm_add_ps(distance, radius), _mm_setzero_ps()));
+                }
+                *visible = outside == 0;
+            }
+        }
+    }
+
+    //Two vec4 by register, the columns are duplicated in both halves.
+    #[target_feature(enable = "avx")]
+    pub unsafe fn transform_vec4s_avx(matrix: &Matrix4, input: &[[f32; 4]], output: &mut [[f32; 4]]) {
+        let count = input.len().min(output.len());
+        let duplicate = |column: usize| {
+            let half = _mm_loadu_ps(matrix.as_ptr().add(column * 4));
+            _mm256_set_m128(half, half)
+        };
+        let columns = [duplicate(0), duplicate(1), duplicate(2), duplicate(3)];
+        let pairs = count / 2;
+        //The pointers cover the whole slices: a pair spans two [f32; 4].
+        for pair in 0..pairs {
+            let vectors = _mm256_loadu_ps((input.as_ptr() as *const f32).add(pair * 8));
+            let x = _mm256_mul_ps(columns[0], _mm256_permute_ps(vectors, 0x00));
+            let y = _mm256_mul_ps(columns[1], _mm256_permute_ps(vectors, 0x55));
+            let z = _mm256_mul_ps(columns[2], _mm256_permute_ps(vectors, 0xaa));
+            let w = _mm256_mul_ps(columns[3], _mm256_permute_ps(vectors, 0xff));
+            _mm256_storeu_ps((output.as_mut_ptr() as *mut f32).add(pair * 8), _mm256_add_ps(_mm256_add_ps(x, y), _mm256_add_ps(z, w)));
+        }
+        if count % 2 == 1 {
+            transform_vec4s(matrix, &input[count - 1..count], &mut output[count - 1..count]);
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+    use super::{Aabb, Matrix4, Plane, planes_soa};
+
+    #[inline(always)]
+    unsafe fn columns(matrix: &Matrix4) -> [float32x4_t; 4] {
+        let matrix = matrix.as_ptr();
+        [vld1q_f32(matrix), vld1q_f32(matrix.add(4)), vld1q_f32(matrix.add(8)), vld1q_f32(matrix.add(12))]
+    }
+
+    #[inline(always)]
+    unsafe fn transform(columns: &[float32x4_t; 4], vector: &[f32]) -> float32x4_t {
+        let result = vmulq_n_f32(columns[0], vector[0]);
+        let result = vmlaq_n_f32(result, columns[1], vector[1]);
+        let result = vmlaq_n_f32(result, columns[2], vector[2]);
+        vmlaq_n_f32(result, columns[3], vector[3])
+    }
+
+    pub fn transform_vec4s(matrix: &Matrix4, input: &[[f32; 4]], output: &mut [[f32; 4]]) {
+        unsafe {
+            let columns = columns(matrix);
+            for (vector, result) in input.iter().zip(output.iter_mut()) {
+                vst1q_f32(result.as_mut_ptr(), transform(&columns, vector));
+            }
+        }
+    }
+
+    pub fn multiply_palette(bones: &[Matrix4], inverse_binds: &[Matrix4], output: &mut [Matrix4]) {
+        unsafe {
+            for ((bone, inverse_bind), result) in bones.iter().zip(inverse_binds.iter()).zip(output.iter_mut()) {
+                let columns = columns(bone);
+                for column in 0..4 {
+                    let transformed = transform(&columns, &inverse_bind[column * 4..column * 4 + 4]);
+                    vst1q_f32(result.as_mut_ptr().add(column * 4), transformed);
+                }
+            }
+        }
+    }
+
+    pub fn cull_aabbs(planes: &[Plane; 6], aabbs: &[Aabb], visible: &mut [bool]) {
+        let soa = planes_soa(planes);
+        unsafe {
+            let groups: Vec<[float32x4_t; 7]> = soa.iter().map(|group| {
+                let (x, y, z) = (vld1q_f32(group[0].as_ptr()), vld1q_f32(group[1].as_ptr()), vld1q_f32(group[2].as_ptr()));
+                [x, y, z, vld1q_f32(group[3].as_ptr()), vabsq_f32(x), vabsq_f32(y), vabsq_f32(z)]
+            }).collect();
+
+            for (aabb, visible) in aabbs.iter().zip(visible.iter_mut()) {
+                let mut outside = 0;
+                for group in groups.iter() {
+                    let distance = vmlaq_n_f32(vmlaq_n_f32(vmlaq_n_f32(group[3], group[0], aabb.center[0]), group[1], aabb.center[1]), group[2], aabb.center[2]);
+                    let distance = vmlaq_n_f32(vmlaq_n_f32(vmlaq_n_f32(distance, group[4], aabb.extents[0]), group[5], aabb.extents[1]), group[6], aabb.extents[2]);
+                    outside |= vmaxvq_u32(vcltq_f32(distance, vdupq_n_f32(0.0)));
+                }
+                *visible = outside == 0;
+            }
+        }
+    }
+}
+
+pub fn transform_vec4s(matrix: &Matrix4, input: &[[f32; 4]], output: &mut [[f32; 4]]) {
+    assert_eq!(input.len(), output.len(), "The output of transform_vec4s must have the size of the input.");
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            return unsafe { sse::transform_vec4s_avx(matrix, input, output) };
+        }
+        return sse::transform_vec4s(matrix, input, output);
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        return neon::transform_vec4s(matrix, input, output);
+    }
+    #[allow(unreachable_code)]
+    scalar::transform_vec4s(matrix, input, output)
+}
+
+pub fn multiply_palette(bones: &[Matrix4], inverse_binds: &[Matrix4], output: &mut [Matrix4]) {
+    assert!(bones.len() == inverse_binds.len() && bones.len() == output.len(), "The palette arrays must have the same size.");
+    #[cfg(target_arch = "x86_64")]
+    {
+        return sse::multiply_palette(bones, inverse_binds, output);
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        return neon::multiply_palette(bones, inverse_binds, output);
+    }
+    #[allow(unreachable_code)]
+    scalar::multiply_palette(bones, inverse_binds, output)
+}
+
+pub fn cull_aabbs(planes: &[Plane; 6], aabbs: &[Aabb], visible: &mut [bool]) {
+    assert_eq!(aabbs.len(), visible.len(), "The visibility array must have the size of the AABB array.");
+    #[cfg(target_arch = "x86_64")]
+    {
+        return sse::cull_aabbs(planes, aabbs, visible);
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        return neon::cull_aabbs(planes, aabbs, visible);
+    }
+    #[allow(unreachable_code)]
+    scalar::cull_aabbs(planes, aabbs, visible)
+}
+
+#[cfg(test)]
+mod batch_test {
+    use super::*;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    fn random_matrix(rng: &mut StdRng) -> Matrix4 {
+        let mut matrix = [0.0; 16];
+        for value in matrix.iter_mut() {
+            *value = rng.gen_range(-2.0, 2.0);
+        }
+        matrix
+    }
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a - b).abs() <= 1e-4 * (1.0 + b.abs()), "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn simd_matches_scalar() {
+        let mut rng = StdRng::from_seed(&[42][..]);
+        let matrix = random_matrix(&mut rng);
+        let vectors: Vec<[f32; 4]> = (0..33).map(|_| [rng.gen_range(-10.0, 10.0), rng.gen_range(-10.0, 10.0), rng.gen_range(-10.0, 10.0), 1.0]).collect();
+        let (mut simd, mut reference) = (vec![[0.0; 4]; 33], vec![[0.0; 4]; 33]);
+        transform_vec4s(&matrix, &vectors, &mut simd);
+        scalar::transform_vec4s(&matrix, &vectors, &mut reference);
+        for (simd, reference) in simd.iter().zip(reference.iter()) {
+            assert_close(simd, reference);
+        }
+
+        let bones: Vec<Matrix4> = (0..5).map(|_| random_matrix(&mut rng)).collect();
+        let inverse_binds: Vec<Matrix4> = (0..5).map(|_| random_matrix(&mut rng)).collect();
+        let (mut simd, mut reference) = (vec![[0.0; 16]; 5], vec![[0.0; 16]; 5]);
+        multiply_palette(&bones, &inverse_binds, &mut simd);
+        scalar::multiply_palette(&bones, &inverse_binds, &mut reference);
+        for (simd, reference) in simd.iter().zip(reference.iter()) {
+            assert_close(simd, reference);
+        }
+
+        //A box frustum, from -10 to 10 on each axis.
+        let planes = [
+            [1.0, 0.0, 0.0, 10.0], [-1.0, 0.0, 0.0, 10.0],
+            [0.0, 1.0, 0.0, 10.0], [0.0, -1.0, 0.0, 10.0],
+            [0.0, 0.0, 1.0, 10.0], [0.0, 0.0, -1.0, 10.0],
+        ];
+        let aabbs = [
+            Aabb { center: [0.0, 0.0, 0.0], extents: [1.0, 1.0, 1.0] },
+            Aabb { center: [0.0, 0.0, 10.5], extents: [1.0, 1.0, 1.0] },
+            Aabb { center: [0.0, 0.0, 12.0], extents: [1.0, 1.0, 1.0] },
+            Aabb { center: [-13.0, 0.0, 0.0], extents: [1.0, 1.0, 1.0] },
+        ];
+        let mut visible = [false; 4];
+        cull_aabbs(&planes, &aabbs, &mut visible);
+        assert_eq!(visible, [true, true, false, false]);
+        let mut reference = [false; 4];
+        scalar::cull_aabbs(&planes, &aabbs, &mut reference);
+        assert_eq!(visible, reference);
+    }
+}
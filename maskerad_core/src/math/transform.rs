@@ -0,0 +1,173 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//A position, a rotation and a scale. Plain arrays are used so the transform can be
+//serialized, hashed for checksums and copied around without conversions.
+//The rotation is a unit quaternion, stored as [x, y, z, w].
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            position: [0.0; 3],
+            rotation: IDENTITY_ROTATION,
+            scale: [1.0; 3],
+        }
+    }
+}
+
+pub const IDENTITY_ROTATION: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+//Quaternion from a normalized axis and an angle in radians.
+pub fn rotation_from_axis_angle(axis: [f32; 3], angle: f32) -> [f32; 4] {
+    let (sin, cos) = (angle * 0.5).sin_cos();
+    [axis[0] * sin, axis[1] * sin, axis[2] * sin, cos]
+}
+
+//Hamilton product, a * b applies b first, then a.
+pub fn mul_rotations(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    [
+        a[3] * b[0] + a[0] * b[3] + a[1] * b[2] - a[2] * b[1],
+        a[3] * b[1] - a[0] * b[2] + a[1] * b[3] + a[2] * b[0],
+        a[3] * b[2] + a[0] * b[1] - a[1] * b[0] + a[2] * b[3],
+        a[3] * b[3] - a[0] * b[0] - a[1] * b[1] - a[2] * b[2],
+    ]
+}
+
+pub fn normalize_rotation(q: [f32; 4]) -> [f32; 4] {
+    let length = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+    if length == 0.0 {
+        return IDENTITY_ROTATION;
+    }
+    [q[0] / length, q[1] / length, q[2] / length, q[3] / length]
+}
+
+pub fn rotate_vector(q: [f32; 4], v: [f32; 3]) -> [f32; 3] {
+    //v' = v + 2w(q x v) + 2(q x (q x v))
+    let u = [q[0], q[1], q[2]];
+    let cross = |a: [f32; 3], b: [f32; 3]| [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ];
+    let t = cross(u, v);
+    let t = [2.0 * t[0], 2.0 * t[1], 2.0 * t[2]];
+    let c = cross(u, t);
+    [
+        v[0] + q[3] * t[0] + c[0],
+        v[1] + q[3] * t[1] + c[1],
+        v[2] + q[3] * t[2] + c[2],
+    ]
+}
+
+pub fn lerp_vectors(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+//Normalized linear interpolation, taking the shortest path.
+pub fn nlerp_rotations(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3];
+    let b = if dot < 0.0 {[-b[0], -b[1], -b[2], -b[3]]} else {b};
+    normalize_rotation([
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+        a[3] + (b[3] - a[3]) * t,
+    ])
+}
+
+impl Transform {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn from_position(position: [f32; 3]) -> Self {
+        Transform {
+            position,
+            .. Default::default()
+        }
+    }
+
+    pub fn translate(&mut self, offset: [f32; 3]) {
+        self.position[0] += offset[0];
+        self.position[1] += offset[1];
+        self.position[2] += offset[2];
+    }
+
+    //Rotate around a world axis, angle in radians.
+    pub fn rotate(&mut self, axis: [f32; 3], angle: f32) {
+        self.rotation = normalize_rotation(mul_rotations(rotation_from_axis_angle(axis, angle), self.rotation));
+    }
+
+    pub fn scale_by(&mut self, factors: [f32; 3]) {
+        self.scale[0] *= factors[0];
+        self.scale[1] *= factors[1];
+        self.scale[2] *= factors[2];
+    }
+
+    //Local point to world point.
+    pub fn transform_point(&self, point: [f32; 3]) -> [f32; 3] {
+        let scaled = [point[0] * self.scale[0], point[1] * self.scale[1], point[2] * self.scale[2]];
+        let rotated = rotate_vector(self.rotation, scaled);
+        [rotated[0] + self.position[0], rotated[1] + self.position[1], rotated[2] + self.position[2]]
+    }
+
+    pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        Transform {
+            position: lerp_vectors(self.position, other.position, t),
+            rotation: nlerp_rotations(self.rotation, other.rotation, t),
+            scale: lerp_vectors(self.scale, other.scale, t),
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.position.iter().chain(self.rotation.iter()).chain(self.scale.iter()).all(|value| value.is_finite())
+    }
+}
+
+#[cfg(test)]
+mod transform_test {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn transform_point() {
+        let mut transform = Transform::from_position([1.0, 0.0, 0.0]);
+        transform.rotate([0.0, 0.0, 1.0], FRAC_PI_2);
+        transform.scale_by([2.0, 2.0, 2.0]);
+        assert_close(transform.transform_point([1.0, 0.0, 0.0]), [1.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn transform_lerp() {
+        let a = Transform::new();
+        let mut b = Transform::from_position([2.0, 4.0, 0.0]);
+        b.rotate([0.0, 1.0, 0.0], FRAC_PI_2);
+        let half = a.lerp(&b, 0.5);
+        assert_close(half.position, [1.0, 2.0, 0.0]);
+        let quarter_turn = rotation_from_axis_angle([0.0, 1.0, 0.0], FRAC_PI_2 / 2.0);
+        for i in 0..4 {
+            assert!((half.rotation[i] - quarter_turn[i]).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ NAMES.
+
+ A Name is the 64 bits hash of a string (FNV-1a): copying, comparing and hashing a Name costs nothing,
+ and the hash is stable across runs and platforms, it can be saved and sent over the network.
+ The strings are interned in a global registry when the Name is created, to print the Names in the logs,
+ the inspector and the console. Two strings with the same hash are reported as an error.
+
+ A PathId is the Name of a normalized asset path: the separators are '/', without leading "./" or '/',
+ so "textures\rock.png", "./textures/rock.png" and "textures/rock.png" are the same asset.
+
+ The maps keyed by Names use the NameMap alias, which doesn't hash the Names again.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+pub fn hash_str(string: &str) -> u64 {
+    string.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+fn registry() -> &'static RwLock<HashMap<u64, Arc<str>>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<u64, Arc<str>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn intern(hash: u64, string: &str) {
+    if let Ok(registry) = registry().read() {
+        match registry.get(&hash) {
+            Some(interned) if &**interned == string => return,
+            Some(interned) => {
+                error!("Name collision: \"{}\" and \"{}\" have the same hash {:#x} !", interned, string, hash);
+                return;
+            },
+            None => {},
+        }
+    }
+    if let Ok(mut registry) = registry().write() {
+        registry.entry(hash).or_insert_with(|| Arc::from(string));
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct Name(u64);
+
+impl Name {
+    pub fn new(string: &str) -> Self {
+        let hash = hash_str(string);
+        intern(hash, string);
+        Name(hash)
+    }
+
+    //Without interning the string, for the lookups of the strings given by the player or the scripts.
+    pub fn lookup(string: &str) -> Self {
+        Name(hash_str(string))
+    }
+
+    pub fn from_hash(hash: u64) -> Self {
+        Name(hash)
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.0
+    }
+
+    //None if the string of the Name has never been interned (a Name read from a file, for example).
+    pub fn as_str(&self) -> Option<Arc<str>> {
+        registry().read().ok().and_then(|registry| registry.get(&self.0).cloned())
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.as_str() {
+            Some(string) => write!(f, "{}", string),
+            None => write!(f, "#{:016x}", self.0),
+        }
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Name({})", self)
+    }
+}
+
+impl<'a> From<&'a str> for Name {
+    fn from(string: &'a str) -> Self {
+        Name::new(string)
+    }
+}
+
+impl From<String> for Name {
+    fn from(string: String) -> Self {
+        Name::new(string.as_str())
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct PathId(Name);
+
+impl PathId {
+    pub fn normalize(path: &str) -> String {
+        let mut normalized = path.replace('\\', "/");
+        while normalized.starts_with("./") {
+            normalized.drain(..2);
+        }
+        normalized.trim_start_matches('/').to_string()
+    }
+
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        PathId(Name::new(PathId::normalize(path.as_ref().to_string_lossy().as_ref()).as_str()))
+    }
+
+    pub fn lookup<P: AsRef<Path>>(path: P) -> Self {
+        PathId(Name::lookup(PathId::normalize(path.as_ref().to_string_lossy().as_ref()).as_str()))
+    }
+
+    pub fn name(&self) -> Name {
+        self.0
+    }
+}
+
+impl fmt::Display for PathId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Debug for PathId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PathId({})", self.0)
+    }
+}
+
+impl<'a, P: AsRef<Path> + ?Sized> From<&'a P> for PathId {
+    fn from(path: &'a P) -> Self {
+        PathId::new(path)
+    }
+}
+
+impl From<PathBuf> for PathId {
+    fn from(path: PathBuf) -> Self {
+        PathId::new(path)
+    }
+}
+
+impl From<String> for PathId {
+    fn from(path: String) -> Self {
+        PathId::new(path)
+    }
+}
+
+//The Names are already hashes.
+#[derive(Default)]
+pub struct NameHasher(u64);
+
+impl Hasher for NameHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter() {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 = value;
+    }
+}
+
+pub type NameMap<K, V> = HashMap<K, V, BuildHasherDefault<NameHasher>>;
+
+#[cfg(test)]
+mod name_test {
+    use super::*;
+
+    #[test]
+    fn names_and_path_ids() {
+        let health = Name::new("Health");
+        assert_eq!(health, Name::from("Health"));
+        assert_eq!(health, Name::lookup("Health"));
+        assert_ne!(health, Name::new("health"));
+        assert_eq!(health.to_string(), "Health");
+        assert_eq!(Name::from_hash(3).to_string(), "#0000000000000003");
+        //FNV-1a of the empty string.
+        assert_eq!(Name::new("").hash(), FNV_OFFSET_BASIS);
+
+        let rock = PathId::new("textures/rock.png");
+        assert_eq!(PathId::from("./textures\\rock.png"), rock);
+        assert_eq!(PathId::from(&PathBuf::from("/textures/rock.png")), rock);
+        assert_eq!(format!("{:?}", rock), "PathId(textures/rock.png)");
+
+        let mut map: NameMap<PathId, u32> = NameMap::default();
+        map.insert(rock, 1);
+        assert_eq!(map.get(&PathId::lookup("textures/rock.png")), Some(&1));
+    }
+}
@@ -0,0 +1,296 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ POWER STATE.
+
+ On laptops and handhelds, the game should not drain the battery or heat the device more than needed.
+ The power service polls the power source of the platform (battery level, charging state, thermal
+ pressure, each one only where the platform reports it), and emits an event when one of them changes.
+
+ When the automatic power saving is enabled, the service enters the power saving mode when the device
+ is on a low battery and not charging, or under serious thermal pressure. In this mode, the frame rate
+ cap of the config is lowered to the power saving cap.
+*/
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChargingState {
+    //No battery, a desktop.
+    NoBattery,
+    Discharging,
+    Charging,
+    Full,
+    Unknown,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ThermalState {
+    Nominal,
+    Fair,
+    Serious,
+    Critical,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PowerReading {
+    //In [0, 1], None without battery.
+    pub battery_level: Option<f32>,
+    pub charging: ChargingState,
+    //None if the platform does not report it.
+    pub thermal: Option<ThermalState>,
+}
+
+impl Default for PowerReading {
+    fn default() -> Self {
+        PowerReading {
+            battery_level: None,
+            charging: ChargingState::NoBattery,
+            thermal: None,
+        }
+    }
+}
+
+//Implemented by the platform layer.
+pub trait PowerSource {
+    fn read(&mut self) -> PowerReading;
+}
+
+//The power supplies of Linux, in /sys/class/power_supply.
+pub struct SysfsPowerSource {
+    root: PathBuf,
+}
+
+impl SysfsPowerSource {
+    pub fn new() -> Self {
+        SysfsPowerSource::with_root("/sys/class/power_supply")
+    }
+
+    pub fn with_root<P: Into<PathBuf>>(root: P) -> Self {
+        SysfsPowerSource {
+            root: root.into(),
+        }
+    }
+
+    fn read_attribute(&self, supply: &str, attribute: &str) -> Option<String> {
+        fs::read_to_string(self.root.join(supply).join(attribute)).ok().map(|value| value.trim().to_string())
+    }
+}
+
+impl PowerSource for SysfsPowerSource {
+    fn read(&mut self) -> PowerReading {
+        let mut supplies: Vec<String> = match fs::read_dir(&self.root) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.file_name().to_string_lossy().into_owned()).collect(),
+            Err(_) => return PowerReading::default(),
+        };
+        supplies.sort();
+
+        for supply in supplies.iter() {
+            if self.read_attribute(supply, "type").as_ref().map(|kind| kind.as_str()) != Some("Battery") {
+                continue;
+            }
+            let battery_level = self.read_attribute(supply, "capacity")
+                .and_then(|capacity| capacity.parse::<f32>().ok())
+                .map(|capacity| (capacity / 100.0).max(0.0).min(1.0));
+            let charging = match self.read_attribute(supply, "status").as_ref().map(|status| status.as_str()) {
+                Some("Discharging") | Some("Not charging") => ChargingState::Discharging,
+                Some("Charging") => ChargingState::Charging,
+                Some("Full") => ChargingState::Full,
+                _ => ChargingState::Unknown,
+            };
+            //The kernel has no generic thermal pressure.
+            return PowerReading { battery_level, charging, thermal: None };
+        }
+        PowerReading::default()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PowerEvent {
+    BatteryLevelChanged(Option<f32>),
+    ChargingChanged(ChargingState),
+    ThermalChanged(Option<ThermalState>),
+    PowerSavingChanged(bool),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PowerPolicy {
+    pub auto_power_saving: bool,
+    pub low_battery_level: f32,
+    pub thermal_threshold: ThermalState,
+    pub power_saving_frame_rate_cap: u32,
+    //The battery level changes are only reported by steps, the level moves slowly.
+    pub battery_level_step: f32,
+}
+
+impl Default for PowerPolicy {
+    fn default() -> Self {
+        PowerPolicy {
+            auto_power_saving: true,
+            low_battery_level: 0.2,
+            thermal_threshold: ThermalState::Serious,
+            power_saving_frame_rate_cap: 30,
+            battery_level_step: 0.05,
+        }
+    }
+}
+
+pub struct PowerService {
+    policy: PowerPolicy,
+    reading: PowerReading,
+    reported_level: Option<f32>,
+    power_saving: bool,
+    events: VecDeque<PowerEvent>,
+}
+
+impl PowerService {
+    pub fn new(policy: PowerPolicy) -> Self {
+        PowerService {
+            policy,
+            reading: PowerReading::default(),
+            reported_level: None,
+            power_saving: false,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn reading(&self) -> PowerReading {
+        self.reading
+    }
+
+    pub fn is_power_saving(&self) -> bool {
+        self.power_saving
+    }
+
+    pub fn set_policy(&mut self, policy: PowerPolicy) {
+        self.policy = policy;
+        self.update_power_saving();
+    }
+
+    //Called every few seconds, reading the power source may be slow.
+    pub fn poll(&mut self, source: &mut PowerSource) {
+        let reading = source.read();
+
+        let level_moved = match (self.reported_level, reading.battery_level) {
+            (Some(reported), Some(level)) => (level - reported).abs() >= self.policy.battery_level_step,
+            (reported, level) => reported.is_some() != level.is_some(),
+        };
+        if level_moved {
+            self.reported_level = reading.battery_level;
+            self.events.push_back(PowerEvent::BatteryLevelChanged(reading.battery_level));
+        }
+        if reading.charging != self.reading.charging {
+            self.events.push_back(PowerEvent::ChargingChanged(reading.charging));
+        }
+        if reading.thermal != self.reading.thermal {
+            self.events.push_back(PowerEvent::ThermalChanged(reading.thermal));
+        }
+
+        self.reading = reading;
+        self.update_power_saving();
+    }
+
+    fn update_power_saving(&mut self) {
+        let on_battery = match self.reading.charging {
+            ChargingState::Discharging | ChargingState::Unknown => true,
+            _ => false,
+        };
+        let low_battery = on_battery && self.reading.battery_level.map(|level| level <= self.policy.low_battery_level).unwrap_or(false);
+        let hot = self.reading.thermal.map(|thermal| thermal >= self.policy.thermal_threshold).unwrap_or(false);
+
+        let power_saving = self.policy.auto_power_saving && (low_battery || hot);
+        if power_saving != self.power_saving {
+            debug!("Power saving mode: {} (battery: {:?}, thermal: {:?}).", power_saving, self.reading.battery_level, self.reading.thermal);
+            self.power_saving = power_saving;
+            self.events.push_back(PowerEvent::PowerSavingChanged(power_saving));
+        }
+    }
+
+    //The cap to apply, from the one of the config.
+    pub fn frame_rate_cap(&self, configured: Option<u32>) -> Option<u32> {
+        if !self.power_saving {
+            return configured;
+        }
+        let cap = self.policy.power_saving_frame_rate_cap;
+        Some(configured.map(|configured| configured.min(cap)).unwrap_or(cap))
+    }
+
+    pub fn poll_events(&mut self) -> Vec<PowerEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod power_test {
+    use super::*;
+    use std::env;
+
+    struct Readings(Vec<PowerReading>);
+
+    impl PowerSource for Readings {
+        fn read(&mut self) -> PowerReading {
+            self.0.remove(0)
+        }
+    }
+
+    fn battery(level: f32, charging: ChargingState, thermal: Option<ThermalState>) -> PowerReading {
+        PowerReading { battery_level: Some(level), charging, thermal }
+    }
+
+    #[test]
+    fn power_saving_follows_battery_and_thermal() {
+        let mut source = Readings(vec![
+            battery(0.5, ChargingState::Discharging, Some(ThermalState::Nominal)),
+            battery(0.48, ChargingState::Discharging, Some(ThermalState::Nominal)),
+            battery(0.15, ChargingState::Discharging, Some(ThermalState::Nominal)),
+            battery(0.15, ChargingState::Charging, Some(ThermalState::Serious)),
+            battery(0.2, ChargingState::Charging, Some(ThermalState::Fair)),
+        ]);
+        let mut power = PowerService::new(PowerPolicy::default());
+
+        power.poll(&mut source);
+        assert_eq!(power.poll_events(), vec![
+            PowerEvent::BatteryLevelChanged(Some(0.5)),
+            PowerEvent::ChargingChanged(ChargingState::Discharging),
+            PowerEvent::ThermalChanged(Some(ThermalState::Nominal)),
+        ]);
+        power.poll(&mut source);
+        assert!(power.poll_events().is_empty());
+
+        power.poll(&mut source);
+        assert_eq!(power.poll_events(), vec![PowerEvent::BatteryLevelChanged(Some(0.15)), PowerEvent::PowerSavingChanged(true)]);
+        assert_eq!(power.frame_rate_cap(Some(60)), Some(30));
+        assert_eq!(power.frame_rate_cap(None), Some(30));
+
+        //Charging, but too hot.
+        power.poll(&mut source);
+        assert!(power.is_power_saving());
+        power.poll(&mut source);
+        assert!(!power.is_power_saving());
+        assert_eq!(power.frame_rate_cap(Some(60)), Some(60));
+    }
+
+    #[test]
+    fn sysfs_power_source() {
+        let root = env::temp_dir().join("maskerad_power_supply_test");
+        let battery = root.join("BAT0");
+        fs::create_dir_all(&battery).unwrap();
+        fs::create_dir_all(root.join("AC")).unwrap();
+        fs::write(root.join("AC").join("type"), "Mains\n").unwrap();
+        fs::write(battery.join("type"), "Battery\n").unwrap();
+        fs::write(battery.join("capacity"), "42\n").unwrap();
+        fs::write(battery.join("status"), "Discharging\n").unwrap();
+
+        let reading = SysfsPowerSource::with_root(&root).read();
+        assert_eq!(reading, PowerReading { battery_level: Some(0.42), charging: ChargingState::Discharging, thermal: None });
+        assert_eq!(SysfsPowerSource::with_root(root.join("missing")).read(), PowerReading::default());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -11,6 +11,7 @@ use rand::distributions::range::SampleRange;
 //A wrapper of a Xorshift random number generator.
 pub struct RandomNumber {
     generator: XorShiftRng,
+    seed: [u32; 4],
 }
 
 impl RandomNumber {
@@ -19,11 +20,22 @@ impl RandomNumber {
 
         let seed: [u32; 4] = [random(), random(), random(), random()];
 
+        RandomNumber::from_seed(seed)
+    }
+
+    //Generate a Xorshift generator with a known seed, the same seed gives the same sequence.
+    //Useful for replays and deterministic simulations. The seed must not be [0, 0, 0, 0].
+    pub fn from_seed(seed: [u32; 4]) -> Self {
         RandomNumber {
             generator: XorShiftRng::from_seed(seed),
+            seed,
         }
     }
 
+    pub fn seed(&self) -> [u32; 4] {
+        self.seed
+    }
+
     pub fn gen_range<T: PartialOrd + SampleRange>(&mut self, low: T, high: T) -> T {
         self.generator.gen_range(low, high)
     }
@@ -86,6 +98,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn xorshift_from_seed() {
+        let mut rng = RandomNumber::new();
+        let mut same_rng = RandomNumber::from_seed(rng.seed());
+        for _ in 0..100 {
+            assert_eq!(rng.gen::<u64>(), same_rng.gen::<u64>());
+        }
+    }
+
     #[test]
     fn xorshift_gen_range() {
         let mut rng = RandomNumber::new();
@@ -0,0 +1,402 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ REFLECTION.
+
+ Components and assets register a TypeInfo in the TypeRegistry: the name of the type, and the
+ list of its fields (name, type, valid range, getter, setter), plus optional serde hooks.
+
+ The inspector of the editor, the generic serialization and the script bindings can then read and
+ write any registered type, without hand-written glue code for each type.
+
+ let info = TypeInfo::new::<Health>("Health")
+    .with_field(FieldInfo::new("value", FieldType::Int, |health: &Health| Value::Int(health.value), |health: &mut Health, value| {
+        match value {
+            Value::Int(value) => {health.value = value; true},
+            _ => false,
+        }
+    }).with_range(0.0, 100.0))
+    .with_serde::<Health>();
+ registry.register(info);
+
+ The settings structures (AccessibilitySettings, VideoSettings...) register their fields the same way,
+ with FieldInfo::boolean, FieldInfo::float and FieldInfo::variant. The options menus are built from them.
+*/
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+use name::{Name, NameMap};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FieldType {
+    Bool,
+    Int,
+    Float,
+    Text,
+    Vector3,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Vector3([f32; 3]),
+}
+
+impl Value {
+    pub fn field_type(&self) -> FieldType {
+        match *self {
+            Value::Bool(_) => FieldType::Bool,
+            Value::Int(_) => FieldType::Int,
+            Value::Float(_) => FieldType::Float,
+            Value::Text(_) => FieldType::Text,
+            Value::Vector3(_) => FieldType::Vector3,
+        }
+    }
+
+    //The name of a unit variant of an enum, as serialized: ColorblindFilter::Protanopia -> "Protanopia".
+    pub fn from_variant<E: Serialize>(variant: &E) -> Value {
+        let name = serde_json::to_value(variant).ok().and_then(|json| json.as_str().map(String::from));
+        Value::Text(name.unwrap_or_default())
+    }
+
+    pub fn to_variant<E: DeserializeOwned>(&self) -> Option<E> {
+        match *self {
+            Value::Text(ref name) => serde_json::from_value(serde_json::Value::String(name.clone())).ok(),
+            _ => None,
+        }
+    }
+
+    fn clamped(self, range: Option<(f64, f64)>) -> Value {
+        match (self, range) {
+            (Value::Int(value), Some((min, max))) => Value::Int((value as f64).max(min).min(max) as i64),
+            (Value::Float(value), Some((min, max))) => Value::Float(value.max(min).min(max)),
+            (value, _) => value,
+        }
+    }
+}
+
+pub struct FieldInfo {
+    name: String,
+    field_type: FieldType,
+    range: Option<(f64, f64)>,
+    options: Vec<String>,
+    getter: Box<Fn(&Any) -> Option<Value>>,
+    setter: Box<Fn(&mut Any, Value) -> bool>,
+}
+
+impl FieldInfo {
+    pub fn new<T, S, G, W>(name: S, field_type: FieldType, get: G, set: W) -> Self where
+        T: Any,
+        S: Into<String>,
+        G: Fn(&T) -> Value + 'static,
+        W: Fn(&mut T, Value) -> bool + 'static,
+    {
+        FieldInfo {
+            name: name.into(),
+            field_type,
+            range: None,
+            options: Vec::new(),
+            getter: Box::new(move |object: &Any| object.downcast_ref::<T>().map(|object| get(object))),
+            setter: Box::new(move |object: &mut Any, value| {
+                match object.downcast_mut::<T>() {
+                    Some(object) => set(object, value),
+                    None => false,
+                }
+            }),
+        }
+    }
+
+    pub fn boolean<T, S, G, W>(name: S, get: G, set: W) -> Self where
+        T: Any,
+        S: Into<String>,
+        G: Fn(&T) -> bool + 'static,
+        W: Fn(&mut T, bool) + 'static,
+    {
+        FieldInfo::new(name, FieldType::Bool, move |object: &T| Value::Bool(get(object)), move |object: &mut T, value| {
+            match value {
+                Value::Bool(value) => {
+                    set(object, value);
+                    true
+                },
+                _ => false,
+            }
+        })
+    }
+
+    pub fn float<T, S, G, W>(name: S, get: G, set: W) -> Self where
+        T: Any,
+        S: Into<String>,
+        G: Fn(&T) -> f32 + 'static,
+        W: Fn(&mut T, f32) + 'static,
+    {
+        FieldInfo::new(name, FieldType::Float, move |object: &T| Value::Float(get(object) as f64), move |object: &mut T, value| {
+            match value {
+                Value::Float(value) => {
+                    set(object, value as f32);
+                    true
+                },
+                _ => false,
+            }
+        })
+    }
+
+    //A unit enum, as a Text field with the names of its variants as options.
+    pub fn variant<T, E, S, G, W>(name: S, options: &[&str], get: G, set: W) -> Self where
+        T: Any,
+        E: Serialize + DeserializeOwned,
+        S: Into<String>,
+        G: Fn(&T) -> E + 'static,
+        W: Fn(&mut T, E) + 'static,
+    {
+        FieldInfo::new(name, FieldType::Text, move |object: &T| Value::from_variant(&get(object)), move |object: &mut T, value| {
+            match value.to_variant::<E>() {
+                Some(variant) => {
+                    set(object, variant);
+                    true
+                },
+                None => false,
+            }
+        }).with_options(options)
+    }
+
+    //Numeric values written through the registry are clamped to this range.
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    //The only values accepted by a Text field.
+    pub fn with_options(mut self, options: &[&str]) -> Self {
+        self.options = options.iter().map(|option| option.to_string()).collect();
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn field_type(&self) -> FieldType {
+        self.field_type
+    }
+
+    pub fn range(&self) -> Option<(f64, f64)> {
+        self.range
+    }
+
+    //Empty when any text is accepted.
+    pub fn options(&self) -> &[String] {
+        self.options.as_slice()
+    }
+
+    pub fn get(&self, object: &Any) -> Option<Value> {
+        (self.getter)(object)
+    }
+
+    pub fn set(&self, object: &mut Any, value: Value) -> bool {
+        if value.field_type() != self.field_type {
+            return false;
+        }
+        if let Value::Text(ref text) = value {
+            if !self.options.is_empty() && !self.options.contains(text) {
+                return false;
+            }
+        }
+        (self.setter)(object, value.clamped(self.range))
+    }
+}
+
+pub struct TypeInfo {
+    name: String,
+    type_id: TypeId,
+    fields: Vec<FieldInfo>,
+    to_json: Option<Box<Fn(&Any) -> Option<serde_json::Value>>>,
+    from_json: Option<Box<Fn(serde_json::Value) -> Option<Box<Any>>>>,
+}
+
+impl TypeInfo {
+    pub fn new<T: Any>(name: &str) -> Self {
+        TypeInfo {
+            name: name.to_string(),
+            type_id: TypeId::of::<T>(),
+            fields: Vec::new(),
+            to_json: None,
+            from_json: None,
+        }
+    }
+
+    pub fn with_field(mut self, field: FieldInfo) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    pub fn with_serde<T: Any + Serialize + DeserializeOwned>(mut self) -> Self {
+        self.to_json = Some(Box::new(|object: &Any| {
+            object.downcast_ref::<T>().and_then(|object| serde_json::to_value(object).ok())
+        }));
+        self.from_json = Some(Box::new(|json| {
+            serde_json::from_value::<T>(json).ok().map(|object| Box::new(object) as Box<Any>)
+        }));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    pub fn fields(&self) -> &[FieldInfo] {
+        self.fields.as_slice()
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldInfo> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    pub fn to_json(&self, object: &Any) -> Option<serde_json::Value> {
+        self.to_json.as_ref().and_then(|to_json| to_json(object))
+    }
+
+    pub fn from_json(&self, json: serde_json::Value) -> Option<Box<Any>> {
+        self.from_json.as_ref().and_then(|from_json| from_json(json))
+    }
+}
+
+#[derive(Default)]
+pub struct TypeRegistry {
+    types: HashMap<TypeId, TypeInfo>,
+    names: NameMap<Name, TypeId>,
+}
+
+impl TypeRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn register(&mut self, info: TypeInfo) {
+        debug!("Registering the type {} in the type registry.", info.name());
+        self.names.insert(Name::new(info.name()), info.type_id);
+        self.types.insert(info.type_id, info);
+    }
+
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    pub fn get<T: Any>(&self) -> Option<&TypeInfo> {
+        self.types.get(&TypeId::of::<T>())
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&TypeInfo> {
+        self.get_by_id(Name::lookup(name))
+    }
+
+    //For the component names stored in the scenes and the network messages.
+    pub fn get_by_id(&self, name: Name) -> Option<&TypeInfo> {
+        self.names.get(&name).and_then(|type_id| self.types.get(type_id))
+    }
+
+    pub fn type_info_of(&self, object: &Any) -> Option<&TypeInfo> {
+        self.types.get(&object.type_id())
+    }
+
+    pub fn type_names(&self) -> Vec<&str> {
+        self.types.values().map(|info| info.name()).collect()
+    }
+
+    //All the fields of a registered object, with their current value.
+    pub fn read_fields(&self, object: &Any) -> Vec<(&str, Value)> {
+        match self.type_info_of(object) {
+            Some(info) => info.fields.iter()
+                .filter_map(|field| field.get(object).map(|value| (field.name(), value)))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn write_field(&self, object: &mut Any, field: &str, value: Value) -> bool {
+        let type_id = (*object).type_id();
+        match self.types.get(&type_id).and_then(|info| info.field(field)) {
+            Some(field) => field.set(object, value),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod reflection_test {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Health {
+        value: i64,
+        regenerates: bool,
+    }
+
+    fn health_info() -> TypeInfo {
+        TypeInfo::new::<Health>("Health")
+            .with_field(FieldInfo::new("value", FieldType::Int, |health: &Health| Value::Int(health.value), |health: &mut Health, value| {
+                match value {
+                    Value::Int(value) => {
+                        health.value = value;
+                        true
+                    },
+                    _ => false,
+                }
+            }).with_range(0.0, 100.0))
+            .with_field(FieldInfo::new("regenerates", FieldType::Bool, |health: &Health| Value::Bool(health.regenerates), |health: &mut Health, value| {
+                match value {
+                    Value::Bool(value) => {
+                        health.regenerates = value;
+                        true
+                    },
+                    _ => false,
+                }
+            }))
+            .with_serde::<Health>()
+    }
+
+    #[test]
+    fn reflection_read_write_fields() {
+        let mut registry = TypeRegistry::new();
+        registry.register(health_info());
+        assert!(registry.get_by_name("Health").is_some());
+
+        let mut health = Health { value: 50, regenerates: false };
+        assert_eq!(registry.read_fields(&health), vec![("value", Value::Int(50)), ("regenerates", Value::Bool(false))]);
+
+        assert!(registry.write_field(&mut health, "value", Value::Int(250)));
+        assert_eq!(health.value, 100);
+        assert!(!registry.write_field(&mut health, "value", Value::Text(String::from("a lot"))));
+        assert!(!registry.write_field(&mut health, "armor", Value::Int(1)));
+        assert!(!registry.write_field(&mut 5u32, "value", Value::Int(1)));
+    }
+
+    #[test]
+    fn reflection_serde_hooks() {
+        let mut registry = TypeRegistry::new();
+        registry.register(health_info());
+        let info = registry.get::<Health>().unwrap();
+
+        let json = info.to_json(&Health { value: 10, regenerates: true }).unwrap();
+        let object = info.from_json(json).unwrap();
+        assert_eq!(object.downcast_ref::<Health>(), Some(&Health { value: 10, regenerates: true }));
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ STARTUP PROGRESS.
+
+ The systems are started one after the other before the main loop, and some take long (the shader
+ bake of the first run, the asset database scan). The startup reports its progress, so the launcher
+ can show a splash screen with a progress bar instead of a frozen window:
+ - each system has a weight, its share of the whole startup,
+ - while it starts, a system reports its steps with the fraction of its own work done,
+ - the events go to the callbacks, and to the channels given to other threads (a splash window).
+
+ The percentage only grows, and reaches 100 when all the systems are started.
+*/
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::Instant;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StartupEvent {
+    Progress { system: String, step: String, percentage: f32 },
+    Failed { system: String, reason: String },
+    //All the systems are started, the main loop begins.
+    Finished { milliseconds: u64 },
+}
+
+struct Listeners {
+    callbacks: Vec<Box<FnMut(&StartupEvent)>>,
+    senders: Vec<Sender<StartupEvent>>,
+}
+
+impl Listeners {
+    fn dispatch(&mut self, event: StartupEvent) {
+        for callback in self.callbacks.iter_mut() {
+            callback(&event);
+        }
+        //The receivers dropped by the launcher are forgotten.
+        self.senders.retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
+
+//Given to a system while it starts.
+pub struct SystemProgress<'a> {
+    system: &'a str,
+    start: f32,
+    span: f32,
+    percentage: &'a mut f32,
+    listeners: &'a mut Listeners,
+}
+
+impl<'a> SystemProgress<'a> {
+    //The fraction is the part of the work of the system done, between 0 and 1.
+    pub fn step(&mut self, step: &str, fraction: f32) {
+        let percentage = (self.start + self.span * fraction.max(0.0).min(1.0)).max(*self.percentage);
+        *self.percentage = percentage;
+        trace!("Startup: {}, {} ({:.1}%).", self.system, step, percentage);
+        self.listeners.dispatch(StartupEvent::Progress {
+            system: self.system.to_string(),
+            step: step.to_string(),
+            percentage,
+        });
+    }
+}
+
+struct StartupSystem {
+    name: String,
+    weight: f32,
+    start: Box<FnMut(&mut SystemProgress) -> Result<(), String>>,
+}
+
+pub struct Startup {
+    systems: Vec<StartupSystem>,
+    listeners: Listeners,
+}
+
+impl Default for Startup {
+    fn default() -> Self {
+        Startup {
+            systems: Vec::new(),
+            listeners: Listeners {
+                callbacks: Vec::new(),
+                senders: Vec::new(),
+            },
+        }
+    }
+}
+
+impl Startup {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    //The systems start in the order of registration.
+    pub fn add_system<S>(&mut self, name: S, weight: f32, start: Box<FnMut(&mut SystemProgress) -> Result<(), String>>) where
+        S: Into<String>
+    {
+        self.systems.push(StartupSystem {
+            name: name.into(),
+            weight: weight.max(0.0),
+            start,
+        });
+    }
+
+    pub fn on_event(&mut self, callback: Box<FnMut(&StartupEvent)>) {
+        self.listeners.callbacks.push(callback);
+    }
+
+    //A stream of the events, for another thread.
+    pub fn subscribe(&mut self) -> Receiver<StartupEvent> {
+        let (sender, receiver) = channel();
+        self.listeners.senders.push(sender);
+        receiver
+    }
+
+    //Start all the systems. Stops at the first system failing to start.
+    pub fn run(&mut self) -> Result<(), String> {
+        let started = Instant::now();
+        let total_weight: f32 = self.systems.iter().map(|system| system.weight).sum();
+        let mut percentage = 0.0;
+        let mut done_weight = 0.0;
+        for system in self.systems.iter_mut() {
+            debug!("Starting the system {}.", system.name);
+            let span = if total_weight > 0.0 {100.0 * system.weight / total_weight} else {0.0};
+            let start = if total_weight > 0.0 {100.0 * done_weight / total_weight} else {0.0};
+            let result = {
+                let mut progress = SystemProgress {
+                    system: system.name.as_str(),
+                    start,
+                    span,
+                    percentage: &mut percentage,
+                    listeners: &mut self.listeners,
+                };
+                progress.step("starting", 0.0);
+                let result = (system.start)(&mut progress);
+                if result.is_ok() {
+                    progress.step("started", 1.0);
+                }
+                result
+            };
+            if let Err(reason) = result {
+                error!("The system {} failed to start: {}", system.name, reason);
+                self.listeners.dispatch(StartupEvent::Failed {
+                    system: system.name.clone(),
+                    reason: reason.clone(),
+                });
+                return Err(format!("The system {} failed to start: {}", system.name, reason));
+            }
+            done_weight += system.weight;
+        }
+
+        let elapsed = started.elapsed();
+        let milliseconds = elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1_000_000;
+        debug!("All the systems started in {} ms.", milliseconds);
+        self.listeners.dispatch(StartupEvent::Finished {
+            milliseconds,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod startup_test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn startup_reports_weighted_progress() {
+        let mut startup = Startup::new();
+        startup.add_system("filesystem", 1.0, Box::new(|_progress: &mut SystemProgress| Ok(())));
+        startup.add_system("renderer", 3.0, Box::new(|progress: &mut SystemProgress| {
+            progress.step("baking the shaders", 0.5);
+            //Going back is ignored.
+            progress.step("baking the shaders", 0.25);
+            Ok(())
+        }));
+        let percentages = Rc::new(RefCell::new(Vec::new()));
+        let recorded = percentages.clone();
+        startup.on_event(Box::new(move |event: &StartupEvent| {
+            if let &StartupEvent::Progress { percentage, .. } = event {
+                recorded.borrow_mut().push(percentage);
+            }
+        }));
+        let events = startup.subscribe();
+
+        assert!(startup.run().is_ok());
+        assert_eq!(*percentages.borrow(), vec![0.0, 25.0, 25.0, 62.5, 62.5, 100.0]);
+        let events: Vec<StartupEvent> = events.try_iter().collect();
+        assert_eq!(events[3], StartupEvent::Progress {
+            system: String::from("renderer"),
+            step: String::from("baking the shaders"),
+            percentage: 62.5,
+        });
+        match events.last() {
+            Some(&StartupEvent::Finished { .. }) => {},
+            event => panic!("Unexpected event {:?}.", event),
+        }
+
+        startup.add_system("audio", 1.0, Box::new(|_progress: &mut SystemProgress| Err(String::from("no output device"))));
+        let events = startup.subscribe();
+        assert!(startup.run().is_err());
+        assert_eq!(events.try_iter().last(), Some(StartupEvent::Failed {
+            system: String::from("audio"),
+            reason: String::from("no output device"),
+        }));
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SYSTEM STATUS.
+
+ Each system reports its health: working, degraded (the audio device was lost, the game runs without
+ sound), or failed. The registry of the systems polls them every frame into a status board, which logs
+ the changes and is shown by the debug overlay and the status console command.
+*/
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SystemStatus {
+    Ok,
+    Degraded(String),
+    Failed(String),
+}
+
+impl Default for SystemStatus {
+    fn default() -> Self {
+        SystemStatus::Ok
+    }
+}
+
+impl SystemStatus {
+    pub fn is_ok(&self) -> bool {
+        *self == SystemStatus::Ok
+    }
+}
+
+impl fmt::Display for SystemStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SystemStatus::Ok => write!(f, "ok"),
+            &SystemStatus::Degraded(ref reason) => write!(f, "degraded ({})", reason),
+            &SystemStatus::Failed(ref reason) => write!(f, "failed ({})", reason),
+        }
+    }
+}
+
+//The last status of each system, in the order of the registry.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatusBoard {
+    entries: Vec<(String, SystemStatus)>,
+}
+
+impl StatusBoard {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    //Return true if the status of the system changed.
+    pub fn update(&mut self, system: &str, status: SystemStatus) -> bool {
+        let index = match self.entries.iter().position(|&(ref name, _)| name == system) {
+            Some(index) => index,
+            None => {
+                self.entries.push((system.to_string(), SystemStatus::Ok));
+                self.entries.len() - 1
+            },
+        };
+        if self.entries[index].1 == status {
+            return false;
+        }
+        match status {
+            SystemStatus::Ok => debug!("The system {} works again.", system),
+            SystemStatus::Degraded(ref reason) => warn!("The system {} is degraded: {}", system, reason),
+            SystemStatus::Failed(ref reason) => error!("The system {} failed: {}", system, reason),
+        }
+        self.entries[index].1 = status;
+        true
+    }
+
+    pub fn status(&self, system: &str) -> Option<&SystemStatus> {
+        self.entries.iter().find(|&&(ref name, _)| name == system).map(|&(_, ref status)| status)
+    }
+
+    pub fn entries(&self) -> &[(String, SystemStatus)] {
+        self.entries.as_slice()
+    }
+
+    pub fn all_ok(&self) -> bool {
+        self.entries.iter().all(|&(_, ref status)| status.is_ok())
+    }
+}
+
+#[cfg(test)]
+mod system_status_test {
+    use super::*;
+
+    #[test]
+    fn system_status_board_tracks_changes() {
+        let mut board = StatusBoard::new();
+        assert!(!board.update("renderer", SystemStatus::Ok));
+        assert!(board.update("audio", SystemStatus::Degraded(String::from("the output device was lost"))));
+        assert!(!board.update("audio", SystemStatus::Degraded(String::from("the output device was lost"))));
+        assert!(!board.all_ok());
+        assert_eq!(board.status("audio").unwrap().to_string(), "degraded (the output device was lost)");
+        assert!(board.update("audio", SystemStatus::Ok));
+        assert!(board.all_ok());
+        assert_eq!(board.entries()[0].0, "renderer");
+    }
+}
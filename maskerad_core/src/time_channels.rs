@@ -0,0 +1,169 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::HashMap;
+use time::Duration;
+use clock::Clock;
+
+//Time channels are named clocks, all driven by the real time of the game loop.
+//Each channel can be paused or scaled on its own: pausing the gameplay channel
+//doesn't freeze the animations of the pause menu, which are driven by the ui channel.
+
+pub const GAMEPLAY_CHANNEL: &'static str = "gameplay";
+pub const UI_CHANNEL: &'static str = "ui";
+pub const VFX_CHANNEL: &'static str = "vfx";
+
+//Systems declare the channel driving them. The gameplay channel is used by default.
+pub trait TimeDriven {
+    fn time_channel(&self) -> &str {
+        GAMEPLAY_CHANNEL
+    }
+}
+
+struct TimeChannel {
+    clock: Clock,
+    //Scaled time elapsed during the last update.
+    delta_time: Duration,
+}
+
+impl TimeChannel {
+    fn new() -> Self {
+        TimeChannel {
+            clock: Clock::new(),
+            delta_time: Duration::milliseconds(0),
+        }
+    }
+}
+
+pub struct TimeChannels(HashMap<String, TimeChannel>);
+
+impl Default for TimeChannels {
+    fn default() -> Self {
+        let mut channels = TimeChannels(HashMap::new());
+        channels.add_channel(GAMEPLAY_CHANNEL);
+        channels.add_channel(UI_CHANNEL);
+        channels.add_channel(VFX_CHANNEL);
+        channels
+    }
+}
+
+impl TimeChannels {
+    //Creates the gameplay, ui and vfx channels.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_channel<S>(&mut self, name: S) where
+        S: Into<String>
+    {
+        let name = name.into();
+        debug!("Adding the time channel {}.", name);
+        self.0.entry(name).or_insert_with(TimeChannel::new);
+    }
+
+    pub fn has_channel<S>(&self, name: S) -> bool where
+        S: AsRef<str>
+    {
+        self.0.contains_key(name.as_ref())
+    }
+
+    pub fn update(&mut self, real_delta_time: Duration) {
+        for channel in self.0.values_mut() {
+            let previous_total = channel.clock.total_time_ms();
+            channel.clock.update(real_delta_time);
+            channel.delta_time = Duration::milliseconds(channel.clock.total_time_ms() - previous_total);
+        }
+    }
+
+    pub fn clock<S>(&self, name: S) -> Option<&Clock> where
+        S: AsRef<str>
+    {
+        self.0.get(name.as_ref()).map(|channel| &channel.clock)
+    }
+
+    pub fn clock_mut<S>(&mut self, name: S) -> Option<&mut Clock> where
+        S: AsRef<str>
+    {
+        self.0.get_mut(name.as_ref()).map(|channel| &mut channel.clock)
+    }
+
+    //The scaled time elapsed during the last update, zero for a paused or unknown channel.
+    pub fn delta_time<S>(&self, name: S) -> Duration where
+        S: AsRef<str>
+    {
+        match self.0.get(name.as_ref()) {
+            Some(channel) => channel.delta_time,
+            None => {
+                warn!("The time channel {} does not exist.", name.as_ref());
+                Duration::milliseconds(0)
+            },
+        }
+    }
+
+    pub fn delta_time_for<T: TimeDriven>(&self, system: &T) -> Duration {
+        self.delta_time(system.time_channel())
+    }
+
+    pub fn set_paused<S>(&mut self, name: S, paused: bool) where
+        S: AsRef<str>
+    {
+        debug!("Setting the paused state of the time channel {} to {}.", name.as_ref(), paused);
+        if let Some(clock) = self.clock_mut(name.as_ref()) {
+            clock.set_paused(paused);
+        }
+    }
+
+    pub fn set_time_scale<S>(&mut self, name: S, scale: f64) where
+        S: AsRef<str>
+    {
+        debug!("Setting the time scale of the time channel {} to {}.", name.as_ref(), scale);
+        if let Some(clock) = self.clock_mut(name.as_ref()) {
+            clock.set_time_scale(scale);
+        }
+    }
+
+    pub fn is_paused<S>(&self, name: S) -> bool where
+        S: AsRef<str>
+    {
+        self.clock(name).map(|clock| clock.is_paused()).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod time_channels_test {
+    use super::*;
+
+    struct MenuAnimations;
+
+    impl TimeDriven for MenuAnimations {
+        fn time_channel(&self) -> &str {
+            UI_CHANNEL
+        }
+    }
+
+    struct Physics;
+
+    impl TimeDriven for Physics {}
+
+    #[test]
+    fn time_channels_pause_and_scale() {
+        let mut channels = TimeChannels::new();
+        channels.set_paused(GAMEPLAY_CHANNEL, true);
+        channels.set_time_scale(VFX_CHANNEL, 0.5);
+        channels.update(Duration::milliseconds(100));
+
+        assert_eq!(channels.delta_time_for(&Physics).num_milliseconds(), 0);
+        assert_eq!(channels.delta_time_for(&MenuAnimations).num_milliseconds(), 100);
+        assert_eq!(channels.delta_time(VFX_CHANNEL).num_milliseconds(), 50);
+        assert_eq!(channels.delta_time("unknown").num_milliseconds(), 0);
+        assert!(channels.is_paused(GAMEPLAY_CHANNEL));
+
+        channels.set_paused(GAMEPLAY_CHANNEL, false);
+        channels.update(Duration::milliseconds(100));
+        assert_eq!(channels.clock(GAMEPLAY_CHANNEL).unwrap().total_time_ms(), 100);
+    }
+}
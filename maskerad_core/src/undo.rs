@@ -0,0 +1,351 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ UNDO/REDO.
+
+ Every modification made by the editor and the in-game level tools is a Command, executed through
+ an UndoStack. The stack can then undo and redo them.
+
+ - Merging: dragging a slider creates a lot of commands. A command can absorb the next one
+   if they modify the same thing, so one undo reverts the whole drag.
+ - Transactions: several commands can be grouped (duplicating 10 objects), and are undone as one.
+*/
+
+use std::any::Any;
+
+pub trait Command<T> {
+    fn name(&self) -> &str;
+    fn execute(&mut self, target: &mut T);
+    fn undo(&mut self, target: &mut T);
+
+    fn redo(&mut self, target: &mut T) {
+        self.execute(target)
+    }
+
+    //Try to absorb a command executed right after this one. The other command has already been executed.
+    fn merge(&mut self, _other: &Command<T>) -> bool {
+        false
+    }
+
+    //Needed by merge, to downcast the other command.
+    fn as_any(&self) -> Option<&Any> {
+        None
+    }
+}
+
+//Several commands undone and redone as one.
+pub struct CommandGroup<T> {
+    name: String,
+    commands: Vec<Box<Command<T>>>,
+}
+
+impl<T> CommandGroup<T> {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        CommandGroup {
+            name: name.into(),
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+impl<T> Command<T> for CommandGroup<T> {
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    fn execute(&mut self, target: &mut T) {
+        for command in self.commands.iter_mut() {
+            command.execute(target);
+        }
+    }
+
+    fn undo(&mut self, target: &mut T) {
+        for command in self.commands.iter_mut().rev() {
+            command.undo(target);
+        }
+    }
+
+    fn redo(&mut self, target: &mut T) {
+        for command in self.commands.iter_mut() {
+            command.redo(target);
+        }
+    }
+}
+
+pub struct UndoStack<T> {
+    undo_stack: Vec<Box<Command<T>>>,
+    redo_stack: Vec<Box<Command<T>>>,
+    transactions: Vec<CommandGroup<T>>,
+    //Maximum number of commands kept in the undo stack.
+    limit: usize,
+}
+
+impl<T: 'static> Default for UndoStack<T> {
+    fn default() -> Self {
+        UndoStack::with_limit(100)
+    }
+}
+
+impl<T: 'static> UndoStack<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_limit(limit: usize) -> Self {
+        UndoStack {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            transactions: Vec::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn undo_name(&self) -> Option<&str> {
+        self.undo_stack.last().map(|command| command.name())
+    }
+
+    pub fn redo_name(&self) -> Option<&str> {
+        self.redo_stack.last().map(|command| command.name())
+    }
+
+    pub fn execute(&mut self, mut command: Box<Command<T>>, target: &mut T) {
+        debug!("Executing the command {}.", command.name());
+        command.execute(target);
+        self.redo_stack.clear();
+
+        if let Some(transaction) = self.transactions.last_mut() {
+            transaction.commands.push(command);
+            return;
+        }
+        self.push_undo(command);
+    }
+
+    fn push_undo(&mut self, command: Box<Command<T>>) {
+        if let Some(last) = self.undo_stack.last_mut() {
+            if last.merge(command.as_ref()) {
+                trace!("The command {} has been merged with the previous one.", command.name());
+                return;
+            }
+        }
+
+        self.undo_stack.push(command);
+        if self.undo_stack.len() > self.limit {
+            self.undo_stack.remove(0);
+        }
+    }
+
+    //Transactions can be nested, the outermost one ends up in the undo stack.
+    pub fn begin_transaction<S: Into<String>>(&mut self, name: S) {
+        let group = CommandGroup::new(name);
+        debug!("Beginning the transaction {}.", group.name);
+        self.transactions.push(group);
+    }
+
+    pub fn end_transaction(&mut self) {
+        let group = match self.transactions.pop() {
+            Some(group) => group,
+            None => {
+                warn!("end_transaction called without any transaction in progress.");
+                return;
+            },
+        };
+        debug!("Ending the transaction {} ({} commands).", group.name, group.len());
+        if group.is_empty() {
+            return;
+        }
+
+        match self.transactions.last_mut() {
+            Some(parent) => parent.commands.push(Box::new(group)),
+            None => {
+                self.redo_stack.clear();
+                self.push_undo(Box::new(group));
+            },
+        }
+    }
+
+    //Undo the commands of the transaction in progress and forget them.
+    pub fn cancel_transaction(&mut self, target: &mut T) {
+        if let Some(mut group) = self.transactions.pop() {
+            debug!("Cancelling the transaction {}.", group.name);
+            group.undo(target);
+        }
+    }
+
+    pub fn undo(&mut self, target: &mut T) -> bool {
+        match self.undo_stack.pop() {
+            Some(mut command) => {
+                debug!("Undoing the command {}.", command.name());
+                command.undo(target);
+                self.redo_stack.push(command);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self, target: &mut T) -> bool {
+        match self.redo_stack.pop() {
+            Some(mut command) => {
+                debug!("Redoing the command {}.", command.name());
+                command.redo(target);
+                self.undo_stack.push(command);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.transactions.clear();
+    }
+}
+
+#[cfg(test)]
+mod undo_test {
+    use super::*;
+
+    struct Add(i32);
+
+    impl Command<i32> for Add {
+        fn name(&self) -> &str {
+            "add"
+        }
+
+        fn execute(&mut self, target: &mut i32) {
+            *target += self.0;
+        }
+
+        fn undo(&mut self, target: &mut i32) {
+            *target -= self.0;
+        }
+    }
+
+    //Setting a value, merged while dragging a slider.
+    struct Set {
+        old: i32,
+        new: i32,
+    }
+
+    impl Command<i32> for Set {
+        fn name(&self) -> &str {
+            "set"
+        }
+
+        fn execute(&mut self, target: &mut i32) {
+            *target = self.new;
+        }
+
+        fn undo(&mut self, target: &mut i32) {
+            *target = self.old;
+        }
+
+        fn merge(&mut self, other: &Command<i32>) -> bool {
+            match other.as_any().and_then(|any| any.downcast_ref::<Set>()) {
+                Some(other) => {
+                    self.new = other.new;
+                    true
+                },
+                None => false,
+            }
+        }
+
+        fn as_any(&self) -> Option<&Any> {
+            Some(self)
+        }
+    }
+
+    #[test]
+    fn undo_stack_undo_redo() {
+        let mut value = 0;
+        let mut stack = UndoStack::new();
+        stack.execute(Box::new(Add(2)), &mut value);
+        stack.execute(Box::new(Add(3)), &mut value);
+        assert_eq!(value, 5);
+
+        assert!(stack.undo(&mut value));
+        assert_eq!(value, 2);
+        assert!(stack.redo(&mut value));
+        assert_eq!(value, 5);
+        assert!(!stack.redo(&mut value));
+
+        stack.undo(&mut value);
+        stack.execute(Box::new(Add(10)), &mut value);
+        assert!(!stack.can_redo());
+        assert_eq!(value, 12);
+    }
+
+    #[test]
+    fn undo_stack_merge_and_transactions() {
+        let mut value = 0;
+        let mut stack = UndoStack::new();
+        stack.execute(Box::new(Set { old: 0, new: 1 }), &mut value);
+        stack.execute(Box::new(Set { old: 1, new: 2 }), &mut value);
+        stack.execute(Box::new(Set { old: 2, new: 3 }), &mut value);
+        stack.undo(&mut value);
+        assert_eq!(value, 0);
+        assert!(!stack.can_undo());
+
+        stack.begin_transaction("duplicate");
+        stack.execute(Box::new(Add(1)), &mut value);
+        stack.execute(Box::new(Add(1)), &mut value);
+        stack.end_transaction();
+        assert_eq!(stack.undo_name(), Some("duplicate"));
+        stack.undo(&mut value);
+        assert_eq!(value, 0);
+        stack.redo(&mut value);
+        assert_eq!(value, 2);
+
+        stack.begin_transaction("cancelled");
+        stack.execute(Box::new(Add(5)), &mut value);
+        stack.cancel_transaction(&mut value);
+        assert_eq!(value, 2);
+        assert_eq!(stack.undo_name(), Some("duplicate"));
+    }
+
+    #[test]
+    fn undo_stack_limit() {
+        let mut value = 0;
+        let mut stack = UndoStack::with_limit(2);
+        for _ in 0..3 {
+            stack.execute(Box::new(Add(1)), &mut value);
+        }
+        assert!(stack.undo(&mut value));
+        assert!(stack.undo(&mut value));
+        assert!(!stack.undo(&mut value));
+        assert_eq!(value, 1);
+
+        //The transactions count for the limit too.
+        for _ in 0..3 {
+            stack.begin_transaction("grouped");
+            stack.execute(Box::new(Add(1)), &mut value);
+            stack.end_transaction();
+        }
+        assert!(stack.undo(&mut value));
+        assert!(stack.undo(&mut value));
+        assert!(!stack.undo(&mut value));
+        assert_eq!(value, 2);
+    }
+}
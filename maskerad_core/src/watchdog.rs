@@ -0,0 +1,253 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ WATCHDOG.
+
+ A thread watching the main loop: when no frame has been completed within the frame budget, the game is
+ considered frozen, and a hang report is written in the log directory:
+ - the frame and how long it is stalled,
+ - the stage of each watched thread (main loop, job workers...), set by the thread as it progresses.
+   The standard library cannot capture the stack of another thread, so the stages are how a deadlock
+   is located: the stalled threads show what they were doing,
+ - the state of the profiler, if a provider is registered.
+
+ The crash handler, if registered and enabled, is then called with the report. A hang is reported once,
+ the watchdog waits for the next completed frame before watching again.
+*/
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use filesystem::filesystem_error::FileSystemResult;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WatchdogConfig {
+    //A frame longer than the budget is a hang. Loading screens must keep completing frames.
+    pub frame_budget: Duration,
+    pub poll_interval: Duration,
+    pub trigger_crash_handler: bool,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            frame_budget: Duration::from_secs(5),
+            poll_interval: Duration::from_millis(250),
+            trigger_crash_handler: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThreadStage {
+    pub thread: String,
+    pub stage: String,
+    //Since the thread entered the stage.
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HangReport {
+    //The number of completed frames.
+    pub frame: u64,
+    pub stalled_for: Duration,
+    pub stages: Vec<ThreadStage>,
+    pub profiler_state: Option<String>,
+    //Where the report was written, None if it could not be written.
+    pub path: Option<PathBuf>,
+}
+
+impl HangReport {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("Hang after the frame {}: no frame completed for {} ms.\n\nThreads:\n", self.frame, millis(self.stalled_for));
+        for stage in self.stages.iter() {
+            text.push_str(format!("- {}: {} (for {} ms)\n", stage.thread, stage.stage, millis(stage.elapsed)).as_str());
+        }
+        if let Some(ref profiler_state) = self.profiler_state {
+            text.push_str("\nProfiler:\n");
+            text.push_str(profiler_state.as_str());
+            text.push('\n');
+        }
+        text
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1_000_000
+}
+
+struct WatchdogState {
+    frame: u64,
+    last_frame: Instant,
+    reported: bool,
+    stages: BTreeMap<String, (String, Instant)>,
+    profiler: Option<Box<Fn() -> String + Send>>,
+    crash_handler: Option<Box<Fn(&HangReport) + Send>>,
+}
+
+pub struct Watchdog {
+    state: Arc<Mutex<WatchdogState>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    //The reports are written in the log directory, usually the engine log root.
+    pub fn start(config: WatchdogConfig, log_directory: PathBuf) -> FileSystemResult<Self> {
+        debug!("Starting the watchdog, with a frame budget of {} ms.", millis(config.frame_budget));
+        let state = Arc::new(Mutex::new(WatchdogState {
+            frame: 0,
+            last_frame: Instant::now(),
+            reported: false,
+            stages: BTreeMap::new(),
+            profiler: None,
+            crash_handler: None,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (thread_state, thread_stop) = (state.clone(), stop.clone());
+        let thread = thread::Builder::new().name(String::from("watchdog")).spawn(move || {
+            while !thread_stop.load(Ordering::SeqCst) {
+                thread::sleep(config.poll_interval);
+                Watchdog::check(&thread_state, &config, &log_directory);
+            }
+        })?;
+        Ok(Watchdog {
+            state,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    //Called by the main loop at the end of each frame.
+    pub fn frame_completed(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.frame += 1;
+            state.last_frame = Instant::now();
+            state.reported = false;
+        }
+    }
+
+    //Called by a watched thread when it starts a new stage of its work ("physics", "waiting for the jobs"...).
+    pub fn set_stage(&self, thread: &str, stage: &str) {
+        if let Ok(mut state) = self.state.lock() {
+            state.stages.insert(thread.to_string(), (stage.to_string(), Instant::now()));
+        }
+    }
+
+    pub fn set_profiler_state(&self, provider: Box<Fn() -> String + Send>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.profiler = Some(provider);
+        }
+    }
+
+    pub fn set_crash_handler(&self, handler: Box<Fn(&HangReport) + Send>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.crash_handler = Some(handler);
+        }
+    }
+
+    fn check(state: &Mutex<WatchdogState>, config: &WatchdogConfig, log_directory: &PathBuf) {
+        let mut state = match state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        let stalled_for = state.last_frame.elapsed();
+        if state.reported || stalled_for < config.frame_budget {
+            return;
+        }
+        state.reported = true;
+
+        let now = Instant::now();
+        let mut report = HangReport {
+            frame: state.frame,
+            stalled_for,
+            stages: state.stages.iter().map(|(thread, &(ref stage, since))| ThreadStage {
+                thread: thread.clone(),
+                stage: stage.clone(),
+                elapsed: now.duration_since(since),
+            }).collect(),
+            profiler_state: state.profiler.as_ref().map(|provider| provider()),
+            path: None,
+        };
+        error!("The main loop is stalled since {} ms, after the frame {}.", millis(stalled_for), report.frame);
+
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+        let path = log_directory.join(format!("hang_{}_frame_{}.log", seconds, report.frame));
+        let written = fs::create_dir_all(log_directory.as_path())
+            .and_then(|_| File::create(path.as_path()))
+            .and_then(|mut file| file.write_all(report.to_text().as_bytes()));
+        match written {
+            Ok(_) => report.path = Some(path),
+            Err(io_error) => error!("Could not write the hang report {}: {}", path.display(), io_error),
+        }
+
+        if config.trigger_crash_handler {
+            if let Some(ref handler) = state.crash_handler {
+                handler(&report);
+            }
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod watchdog_test {
+    use super::*;
+    use std::env;
+    use std::sync::mpsc;
+
+    #[test]
+    fn watchdog_reports_a_hang_once() {
+        let directory = env::temp_dir().join("maskerad_watchdog_test");
+        let _ = fs::remove_dir_all(directory.as_path());
+        let watchdog = Watchdog::start(WatchdogConfig {
+            frame_budget: Duration::from_millis(100),
+            poll_interval: Duration::from_millis(10),
+            trigger_crash_handler: true,
+        }, directory.clone()).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        watchdog.set_crash_handler(Box::new(move |report: &HangReport| {
+            let _ = sender.send(report.clone());
+        }));
+        watchdog.set_profiler_state(Box::new(|| String::from("physics: 4200 ms")));
+
+        //Frames within the budget.
+        for _ in 0..5 {
+            watchdog.set_stage("main", "update");
+            thread::sleep(Duration::from_millis(20));
+            watchdog.frame_completed();
+        }
+        assert!(receiver.try_recv().is_err());
+
+        watchdog.set_stage("main", "waiting for the physics job");
+        let report = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(report.frame, 5);
+        assert_eq!(report.stages[0].stage, "waiting for the physics job");
+        let text = fs::read_to_string(report.path.unwrap()).unwrap();
+        assert!(text.contains("- main: waiting for the physics job") && text.contains("physics: 4200 ms"));
+
+        //Reported once.
+        thread::sleep(Duration::from_millis(150));
+        assert!(receiver.try_recv().is_err());
+        drop(watchdog);
+        fs::remove_dir_all(directory.as_path()).unwrap();
+    }
+}
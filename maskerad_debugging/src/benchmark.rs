@@ -0,0 +1,266 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ BENCHMARK MODE.
+
+ The benchmark plays a camera path, while the game records every frame: its duration, the GPU timings of
+ the render passes, and the memory in use. At the end of the path, a report is written in the
+ "benchmarks" directory of the user data root, as JSON (for regression tracking) and as HTML (for
+ hardware reviews).
+
+ The camera path is a Catmull-Rom curve through timed keyframes. The game moves its camera with
+ camera_at(), so the same frames are rendered on every machine.
+*/
+
+use std::io::Write;
+use std::path::PathBuf;
+use maskerad_core::filesystem::filesystem::Filesystem;
+use maskerad_core::filesystem::filesystem_error::FileSystemResult;
+use maskerad_core::filesystem::game_directories::RootDir;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraKeyframe {
+    //In seconds from the start of the benchmark.
+    pub time: f64,
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+}
+
+//The keyframes are sorted by time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraPath {
+    keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraPath {
+    pub fn new(mut keyframes: Vec<CameraKeyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(::std::cmp::Ordering::Equal));
+        CameraPath {
+            keyframes,
+        }
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.keyframes.last().map(|keyframe| keyframe.time).unwrap_or(0.0)
+    }
+
+    //The position and the target of the camera, None for an empty path.
+    pub fn sample(&self, time: f64) -> Option<([f32; 3], [f32; 3])> {
+        let last = self.keyframes.len().checked_sub(1)?;
+        let index = self.keyframes.iter().rposition(|keyframe| keyframe.time <= time).unwrap_or(0).min(last.saturating_sub(1));
+        let (start, end) = (&self.keyframes[index], &self.keyframes[(index + 1).min(last)]);
+        let t = if end.time > start.time {((time - start.time) / (end.time - start.time)).max(0.0).min(1.0) as f32} else {0.0};
+        let keyframe = |offset: isize| &self.keyframes[(index as isize + offset).max(0).min(last as isize) as usize];
+        let curve = |get: &Fn(&CameraKeyframe) -> [f32; 3]| {
+            let (p0, p1, p2, p3) = (get(keyframe(-1)), get(keyframe(0)), get(keyframe(1)), get(keyframe(2)));
+            let mut point = [0.0; 3];
+            for axis in 0..3 {
+                point[axis] = 0.5 * (2.0 * p1[axis] + (p2[axis] - p0[axis]) * t
+                    + (2.0 * p0[axis] - 5.0 * p1[axis] + 4.0 * p2[axis] - p3[axis]) * t * t
+                    + (3.0 * p1[axis] - p0[axis] - 3.0 * p2[axis] + p3[axis]) * t * t * t);
+            }
+            point
+        };
+        Some((curve(&|keyframe| keyframe.position), curve(&|keyframe| keyframe.target)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameSample {
+    pub frame_seconds: f64,
+    //The GPU time of each render pass, in seconds.
+    pub gpu_passes: Vec<(String, f64)>,
+    pub memory_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkReport {
+    pub name: String,
+    pub frames: usize,
+    pub duration: f64,
+    pub average_fps: f64,
+    //The frame times at the 50th, 95th and 99th percentiles, in milliseconds.
+    pub frame_time_p50: f64,
+    pub frame_time_p95: f64,
+    pub frame_time_p99: f64,
+    //The average GPU time of each render pass, in milliseconds.
+    pub gpu_passes: Vec<(String, f64)>,
+    pub average_memory_bytes: u64,
+    pub peak_memory_bytes: u64,
+}
+
+impl BenchmarkReport {
+    pub fn to_json(&self) -> String {
+        let passes: Vec<_> = self.gpu_passes.iter().map(|&(ref name, milliseconds)| json!({"pass": name, "ms": milliseconds})).collect();
+        let report = json!({
+            "name": self.name,
+            "frames": self.frames,
+            "duration": self.duration,
+            "average_fps": self.average_fps,
+            "frame_time_ms": {"p50": self.frame_time_p50, "p95": self.frame_time_p95, "p99": self.frame_time_p99},
+            "gpu_passes": passes,
+            "memory_bytes": {"average": self.average_memory_bytes, "peak": self.peak_memory_bytes},
+        });
+        format!("{:#}", report)
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Benchmark ");
+        html.push_str(escape_html(self.name.as_str()).as_str());
+        html.push_str("</title></head>\n<body>\n");
+        html.push_str(format!("<h1>{}</h1>\n", escape_html(self.name.as_str())).as_str());
+        html.push_str(format!("<p>{} frames in {:.2} s, {:.1} FPS on average.</p>\n", self.frames, self.duration, self.average_fps).as_str());
+        html.push_str("<h2>Frame times</h2>\n<table>\n<tr><th>Percentile</th><th>ms</th></tr>\n");
+        for &(percentile, milliseconds) in [("50th", self.frame_time_p50), ("95th", self.frame_time_p95), ("99th", self.frame_time_p99)].iter() {
+            html.push_str(format!("<tr><td>{}</td><td>{:.2}</td></tr>\n", percentile, milliseconds).as_str());
+        }
+        html.push_str("</table>\n<h2>GPU passes</h2>\n<table>\n<tr><th>Pass</th><th>ms</th></tr>\n");
+        for &(ref pass, milliseconds) in self.gpu_passes.iter() {
+            html.push_str(format!("<tr><td>{}</td><td>{:.2}</td></tr>\n", escape_html(pass.as_str()), milliseconds).as_str());
+        }
+        html.push_str("</table>\n<h2>Memory</h2>\n");
+        html.push_str(format!("<p>{:.1} MiB on average, {:.1} MiB at the peak.</p>\n",
+                              self.average_memory_bytes as f64 / 1048576.0, self.peak_memory_bytes as f64 / 1048576.0).as_str());
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    //Write <name>.json and <name>.html in the benchmarks directory of the user data root.
+    //Return the path of the JSON report.
+    pub fn write(&self, filesystem: &Filesystem) -> FileSystemResult<PathBuf> {
+        let directory = filesystem.construct_path_from_root(RootDir::UserDataRoot, "benchmarks")?;
+        if !directory.exists() {
+            Filesystem::mkdir(directory.as_path())?;
+        }
+        let json_path = directory.join(format!("{}.json", self.name));
+        Filesystem::create(json_path.as_path())?.write_all(self.to_json().as_bytes())?;
+        Filesystem::create(directory.join(format!("{}.html", self.name)))?.write_all(self.to_html().as_bytes())?;
+        debug!("Wrote the benchmark report {}.", json_path.display());
+        Ok(json_path)
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+//The value at the percentile of sorted values, with the nearest rank method.
+fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1).min(sorted.len()) - 1]
+}
+
+pub struct BenchmarkRunner {
+    name: String,
+    path: CameraPath,
+    elapsed: f64,
+    samples: Vec<FrameSample>,
+}
+
+impl BenchmarkRunner {
+    pub fn new<S: Into<String>>(name: S, path: CameraPath) -> Self {
+        BenchmarkRunner {
+            name: name.into(),
+            path,
+            elapsed: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    //The camera of the current frame.
+    pub fn camera(&self) -> Option<([f32; 3], [f32; 3])> {
+        self.path.sample(self.elapsed)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.path.duration()
+    }
+
+    //Record the frame which just ended, and move along the path.
+    pub fn record_frame(&mut self, sample: FrameSample) {
+        if self.is_finished() {
+            return;
+        }
+        self.elapsed += sample.frame_seconds;
+        self.samples.push(sample);
+    }
+
+    pub fn report(&self) -> BenchmarkReport {
+        let mut frame_times: Vec<f64> = self.samples.iter().map(|sample| sample.frame_seconds * 1000.0).collect();
+        frame_times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+        let duration: f64 = self.samples.iter().map(|sample| sample.frame_seconds).sum();
+
+        let mut gpu_passes: Vec<(String, f64, usize)> = Vec::new();
+        for sample in self.samples.iter() {
+            for &(ref pass, seconds) in sample.gpu_passes.iter() {
+                match gpu_passes.iter().position(|&(ref name, _, _)| name == pass) {
+                    Some(index) => {
+                        gpu_passes[index].1 += seconds;
+                        gpu_passes[index].2 += 1;
+                    },
+                    None => gpu_passes.push((pass.clone(), seconds, 1)),
+                }
+            }
+        }
+
+        let frames = self.samples.len();
+        BenchmarkReport {
+            name: self.name.clone(),
+            frames,
+            duration,
+            average_fps: if duration > 0.0 {frames as f64 / duration} else {0.0},
+            frame_time_p50: percentile(frame_times.as_slice(), 50.0),
+            frame_time_p95: percentile(frame_times.as_slice(), 95.0),
+            frame_time_p99: percentile(frame_times.as_slice(), 99.0),
+            gpu_passes: gpu_passes.into_iter().map(|(pass, seconds, count)| (pass, seconds * 1000.0 / count as f64)).collect(),
+            average_memory_bytes: if frames > 0 {self.samples.iter().map(|sample| sample.memory_bytes).sum::<u64>() / frames as u64} else {0},
+            peak_memory_bytes: self.samples.iter().map(|sample| sample.memory_bytes).max().unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod benchmark_test {
+    use super::*;
+
+    #[test]
+    fn benchmark_runs_the_path_and_reports() {
+        let path = CameraPath::new(vec![
+            CameraKeyframe { time: 10.0, position: [10.0, 0.0, 0.0], target: [0.0; 3] },
+            CameraKeyframe { time: 0.0, position: [0.0, 0.0, 0.0], target: [0.0; 3] },
+        ]);
+        assert_eq!(path.sample(5.0).unwrap().0, [5.0, 0.0, 0.0]);
+        assert_eq!(path.sample(30.0).unwrap().0, [10.0, 0.0, 0.0]);
+
+        let mut runner = BenchmarkRunner::new("forest <night>", path);
+        let mut frames = 0;
+        while !runner.is_finished() {
+            //One slow frame in twenty.
+            let frame_seconds = if frames % 20 == 19 {0.25} else {0.0625};
+            runner.record_frame(FrameSample {
+                frame_seconds,
+                gpu_passes: vec![(String::from("shadows"), 0.002), (String::from("opaque"), 0.004)],
+                memory_bytes: 1000 + frames,
+            });
+            frames += 1;
+        }
+        let report = runner.report();
+        assert_eq!(report.frames, 140);
+        assert_eq!(report.frame_time_p50, 62.5);
+        assert_eq!(report.frame_time_p99, 250.0);
+        assert_eq!(report.gpu_passes[1].0, "opaque");
+        assert!((report.gpu_passes[1].1 - 4.0).abs() < 0.0001);
+        assert_eq!(report.peak_memory_bytes, 1139);
+        assert!(report.to_json().contains("\"p95\""));
+        assert!(report.to_html().contains("forest &lt;night&gt;"));
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::any::Any;
+use maskerad_core::math::transform::Transform;
+use maskerad_core::reflection::TypeRegistry;
+
+//The values shown by the inspector are the values of the reflection system.
+pub use maskerad_core::reflection::Value as FieldValue;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InspectedField {
+    pub name: String,
+    pub value: FieldValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InspectedComponent {
+    pub name: String,
+    pub fields: Vec<InspectedField>,
+}
+
+impl InspectedComponent {
+    //Build the panel of a component from its registered reflection data.
+    pub fn from_reflection(registry: &TypeRegistry, component: &Any) -> Option<Self> {
+        registry.type_info_of(component).map(|info| {
+            InspectedComponent {
+                name: info.name().to_string(),
+                fields: registry.read_fields(component).into_iter().map(|(name, value)| {
+                    InspectedField {
+                        name: name.to_string(),
+                        value,
+                    }
+                }).collect(),
+            }
+        })
+    }
+}
+
+//Implemented by the game world, so the editor can read and modify it.
+pub trait EditorScene {
+    fn transform(&self, entity: u64) -> Option<Transform>;
+    fn set_transform(&mut self, entity: u64, transform: Transform);
+
+    //Physics picking: the closest entity hit by the ray, and the distance of the hit.
+    fn raycast(&self, origin: [f32; 3], direction: [f32; 3]) -> Option<(u64, f32)>;
+
+    fn inspect(&self, entity: u64) -> Vec<InspectedComponent>;
+    //Returns false if the component or the field doesn't exist, or the value has the wrong type.
+    fn set_field(&mut self, entity: u64, component: &str, field: &str, value: FieldValue) -> bool;
+
+    //How the editor prints the entity, with its name if it has one.
+    fn label(&self, entity: u64) -> String {
+        format!("#{}", entity)
+    }
+
+    //The entity designated by a name, a path or an index, typed by the developer.
+    fn find(&self, _reference: &str) -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use maskerad_core::math::transform::Transform;
+use maskerad_core::undo::{Command, UndoStack};
+use editor::editor_scene::EditorScene;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GizmoAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl GizmoAxis {
+    pub fn direction(&self) -> [f32; 3] {
+        match *self {
+            GizmoAxis::X => [1.0, 0.0, 0.0],
+            GizmoAxis::Y => [0.0, 1.0, 0.0],
+            GizmoAxis::Z => [0.0, 0.0, 1.0],
+        }
+    }
+}
+
+pub struct SetTransform {
+    entity: u64,
+    old: Transform,
+    new: Transform,
+}
+
+impl SetTransform {
+    pub fn new(entity: u64, old: Transform, new: Transform) -> Self {
+        SetTransform {
+            entity,
+            old,
+            new,
+        }
+    }
+}
+
+impl<S: EditorScene> Command<S> for SetTransform {
+    fn name(&self) -> &str {
+        "set transform"
+    }
+
+    fn execute(&mut self, scene: &mut S) {
+        scene.set_transform(self.entity, self.new);
+    }
+
+    fn undo(&mut self, scene: &mut S) {
+        scene.set_transform(self.entity, self.old);
+    }
+}
+
+struct Drag {
+    entity: u64,
+    axis: GizmoAxis,
+    start: Transform,
+    current: Transform,
+}
+
+pub struct Gizmo {
+    mode: GizmoMode,
+    drag: Option<Drag>,
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Gizmo {
+            mode: GizmoMode::Translate,
+            drag: None,
+        }
+    }
+}
+
+impl Gizmo {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn mode(&self) -> GizmoMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: GizmoMode) {
+        self.mode = mode;
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    pub fn begin_drag<S: EditorScene>(&mut self, scene: &S, entity: u64, axis: GizmoAxis) -> bool {
+        match scene.transform(entity) {
+            Some(start) => {
+                debug!("Editor: dragging the {:?} gizmo of the entity {} on the {:?} axis.", self.mode, entity, axis);
+                self.drag = Some(Drag {
+                    entity,
+                    axis,
+                    start,
+                    current: start,
+                });
+                true
+            },
+            None => false,
+        }
+    }
+
+    //amount is a distance in translate mode, an angle in radians in rotate mode,
+    //and a scale factor offset in scale mode (1.0 doubles the size), relative to the start of the drag.
+    pub fn drag_to<S: EditorScene>(&mut self, scene: &mut S, amount: f32) {
+        let mode = self.mode;
+        if let Some(ref mut drag) = self.drag {
+            let direction = drag.axis.direction();
+            let mut transform = drag.start;
+            match mode {
+                GizmoMode::Translate => {
+                    transform.translate([direction[0] * amount, direction[1] * amount, direction[2] * amount]);
+                },
+                GizmoMode::Rotate => {
+                    transform.rotate(direction, amount);
+                },
+                GizmoMode::Scale => {
+                    let factor = (1.0 + amount).max(0.001);
+                    let mut factors = [1.0; 3];
+                    for i in 0..3 {
+                        if direction[i] != 0.0 {
+                            factors[i] = factor;
+                        }
+                    }
+                    transform.scale_by(factors);
+                },
+            }
+            drag.current = transform;
+            scene.set_transform(drag.entity, transform);
+        }
+    }
+
+    //The whole drag becomes one undoable command.
+    pub fn end_drag<S: EditorScene + 'static>(&mut self, scene: &mut S, undo_stack: &mut UndoStack<S>) {
+        if let Some(drag) = self.drag.take() {
+            if drag.current != drag.start {
+                undo_stack.execute(Box::new(SetTransform::new(drag.entity, drag.start, drag.current)), scene);
+            }
+        }
+    }
+
+    //Forget the drag without restoring the transform. Call undo if needed.
+    pub fn cancel_drag(&mut self) {
+        self.drag = None;
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use maskerad_core::undo::{Command, UndoStack};
+use editor::editor_scene::{EditorScene, FieldValue, InspectedComponent};
+
+pub struct SetField {
+    entity: u64,
+    component: String,
+    field: String,
+    old: FieldValue,
+    new: FieldValue,
+}
+
+impl SetField {
+    pub fn new(entity: u64, component: &str, field: &str, old: FieldValue, new: FieldValue) -> Self {
+        SetField {
+            entity,
+            component: component.to_string(),
+            field: field.to_string(),
+            old,
+            new,
+        }
+    }
+}
+
+impl<S: EditorScene> Command<S> for SetField {
+    fn name(&self) -> &str {
+        "set field"
+    }
+
+    fn execute(&mut self, scene: &mut S) {
+        scene.set_field(self.entity, self.component.as_str(), self.field.as_str(), self.new.clone());
+    }
+
+    fn undo(&mut self, scene: &mut S) {
+        scene.set_field(self.entity, self.component.as_str(), self.field.as_str(), self.old.clone());
+    }
+}
+
+//The component panel of the selected entity.
+#[derive(Debug, Default)]
+pub struct Inspector {
+    entity: Option<u64>,
+    components: Vec<InspectedComponent>,
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn entity(&self) -> Option<u64> {
+        self.entity
+    }
+
+    //The header of the panel.
+    pub fn title<S: EditorScene>(&self, scene: &S) -> Option<String> {
+        self.entity.map(|entity| scene.label(entity))
+    }
+
+    pub fn components(&self) -> &[InspectedComponent] {
+        self.components.as_slice()
+    }
+
+    //Called each frame with the primary selection, the values may have changed.
+    pub fn refresh<S: EditorScene>(&mut self, scene: &S, entity: Option<u64>) {
+        self.entity = entity;
+        self.components = match entity {
+            Some(entity) => scene.inspect(entity),
+            None => Vec::new(),
+        };
+    }
+
+    fn current_value(&self, component: &str, field: &str) -> Option<FieldValue> {
+        self.components.iter()
+            .filter(|inspected| inspected.name == component)
+            .flat_map(|inspected| inspected.fields.iter())
+            .filter(|inspected| inspected.name == field)
+            .map(|inspected| inspected.value.clone())
+            .next()
+    }
+
+    pub fn edit<S: EditorScene + 'static>(&mut self, scene: &mut S, undo_stack: &mut UndoStack<S>, component: &str, field: &str, value: FieldValue) -> bool {
+        let entity = match self.entity {
+            Some(entity) => entity,
+            None => return false,
+        };
+        let old = match self.current_value(component, field) {
+            Some(old) => old,
+            None => {
+                warn!("Editor: the entity {} has no field {}.{}.", entity, component, field);
+                return false;
+            },
+        };
+
+        undo_stack.execute(Box::new(SetField::new(entity, component, field, old, value)), scene);
+        self.refresh(scene, Some(entity));
+        true
+    }
+}
@@ -0,0 +1,614 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ EDITOR IPC.
+
+ The protocol between the running game and an editor in another process. The editor finds and inspects
+ the entities, modifies them (through the undo stack, like the in-game editor), asks for asset reloads,
+ and subscribes to the logs and the metrics of the game.
+
+ Each message is a JSON object, prefixed by its size (u32, little endian).
+ - A request of the editor: {"id": 1, "request": "inspect", "entity": 42}
+ - The response of the game: {"id": 1, "ok": true, "result": ...} or {"id": 1, "ok": false, "error": "..."}
+ - An event of the game, for the subscribed topics: {"event": "log", ...} or {"event": "metrics", ...}
+
+ The requests: hello, find {reference}, inspect {entity}, set_field {entity, component, field, value},
+ set_transform {entity, position, rotation, scale}, undo, redo, reload_asset {path},
+ subscribe {topic}, unsubscribe {topic}. The topics are "logs" and "metrics".
+
+ The server listens on the loopback interface or on a Unix socket, for one editor at a time. poll() must be
+ called once per frame, the requests are handled between two frames.
+*/
+
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use serde_json::{self, Value};
+use maskerad_core::math::transform::Transform;
+use maskerad_core::reflection::FieldType;
+use maskerad_core::undo::UndoStack;
+use editor::editor_scene::{EditorScene, FieldValue};
+use editor::gizmo::SetTransform;
+use editor::inspector::SetField;
+
+pub const IPC_PROTOCOL_VERSION: u32 = 1;
+//A bigger frame is a corrupted stream.
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+//The bytes waiting for the editor to read them. An editor which doesn't read is disconnected.
+const MAX_BACKLOG: usize = 2 * MAX_FRAME_SIZE;
+
+pub fn write_frame<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = serde_json::to_vec(message)?;
+    writer.write_all(&(body.len() as u32).to_le_bytes())?;
+    writer.write_all(body.as_slice())?;
+    writer.flush()
+}
+
+//Accumulates the bytes received, and splits them into messages.
+#[derive(Debug, Default)]
+pub struct FrameBuffer {
+    data: Vec<u8>,
+}
+
+impl FrameBuffer {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    //None until a whole message has been received.
+    pub fn next_frame(&mut self) -> io::Result<Option<Value>> {
+        if self.data.len() < 4 {
+            return Ok(None);
+        }
+        let size = u32::from_le_bytes([self.data[0], self.data[1], self.data[2], self.data[3]]) as usize;
+        if size > MAX_FRAME_SIZE {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("IPC frame of {} bytes.", size)));
+        }
+        if self.data.len() < 4 + size {
+            return Ok(None);
+        }
+        let message = serde_json::from_slice(&self.data[4..4 + size])?;
+        self.data.drain(..4 + size);
+        Ok(Some(message))
+    }
+}
+
+pub fn field_to_json(value: &FieldValue) -> Value {
+    match *value {
+        FieldValue::Bool(value) => json!(value),
+        FieldValue::Int(value) => json!(value),
+        FieldValue::Float(value) => json!(value),
+        FieldValue::Text(ref value) => json!(value),
+        FieldValue::Vector3(value) => json!(value),
+    }
+}
+
+//The type of the field decides how the JSON is read: 2 is an Int or a Float.
+pub fn field_from_json(value: &Value, field_type: FieldType) -> Option<FieldValue> {
+    match field_type {
+        FieldType::Bool => value.as_bool().map(FieldValue::Bool),
+        FieldType::Int => value.as_i64().map(FieldValue::Int),
+        FieldType::Float => value.as_f64().map(FieldValue::Float),
+        FieldType::Text => value.as_str().map(|text| FieldValue::Text(text.to_string())),
+        FieldType::Vector3 => vector(value).map(FieldValue::Vector3),
+    }
+}
+
+fn vector(value: &Value) -> Option<[f32; 3]> {
+    match value.as_array() {
+        Some(values) if values.len() == 3 => {
+            let mut vector = [0.0; 3];
+            for (component, value) in vector.iter_mut().zip(values.iter()) {
+                *component = value.as_f64()? as f32;
+            }
+            Some(vector)
+        },
+        _ => None,
+    }
+}
+
+fn quaternion(value: &Value) -> Option<[f32; 4]> {
+    match value.as_array() {
+        Some(values) if values.len() == 4 => {
+            let mut quaternion = [0.0; 4];
+            for (component, value) in quaternion.iter_mut().zip(values.iter()) {
+                *component = value.as_f64()? as f32;
+            }
+            Some(quaternion)
+        },
+        _ => None,
+    }
+}
+
+fn entity_argument(request: &Value) -> Result<u64, String> {
+    request["entity"].as_u64().ok_or_else(|| String::from("The entity is missing."))
+}
+
+fn string_argument<'a>(request: &'a Value, name: &str) -> Result<&'a str, String> {
+    request[name].as_str().ok_or_else(|| format!("The {} is missing.", name))
+}
+
+//The state of the protocol, without the transport.
+#[derive(Debug, Default)]
+pub struct IpcSession {
+    topics: BTreeSet<String>,
+    reloads: Vec<String>,
+    outgoing: Vec<Value>,
+}
+
+impl IpcSession {
+    pub fn new() -> Self {
+        IpcSession::default()
+    }
+
+    pub fn take_outgoing(&mut self) -> Vec<Value> {
+        self.outgoing.drain(..).collect()
+    }
+
+    //The assets the editor asked to reload, for the hot reload of the game.
+    pub fn take_reload_requests(&mut self) -> Vec<String> {
+        self.reloads.drain(..).collect()
+    }
+
+    pub fn is_subscribed(&self, topic: &str) -> bool {
+        self.topics.contains(topic)
+    }
+
+    pub fn publish_log(&mut self, level: &str, target: &str, message: &str) {
+        if self.is_subscribed("logs") {
+            self.outgoing.push(json!({"event": "log", "level": level, "target": target, "message": message}));
+        }
+    }
+
+    pub fn publish_metrics(&mut self, frame: u64, metrics: &[(&str, f64)]) {
+        if self.is_subscribed("metrics") {
+            let values: serde_json::Map<String, Value> = metrics.iter().map(|&(name, value)| (name.to_string(), json!(value))).collect();
+            self.outgoing.push(json!({"event": "metrics", "frame": frame, "values": values}));
+        }
+    }
+
+    pub fn handle<S: EditorScene + 'static>(&mut self, request: &Value, scene: &mut S, undo_stack: &mut UndoStack<S>) {
+        let id = request["id"].clone();
+        let result = match request["request"].as_str() {
+            Some(name) => {
+                trace!("Editor IPC request: {}.", name);
+                self.execute(name, request, scene, undo_stack)
+            },
+            None => Err(String::from("The request has no name.")),
+        };
+        self.outgoing.push(match result {
+            Ok(result) => json!({"id": id, "ok": true, "result": result}),
+            Err(error) => json!({"id": id, "ok": false, "error": error}),
+        });
+    }
+
+    fn execute<S: EditorScene + 'static>(&mut self, name: &str, request: &Value, scene: &mut S, undo_stack: &mut UndoStack<S>) -> Result<Value, String> {
+        match name {
+            "hello" => Ok(json!({"protocol": IPC_PROTOCOL_VERSION})),
+            "find" => {
+                let reference = string_argument(request, "reference")?;
+                let entity = scene.find(reference).ok_or_else(|| format!("No entity {}.", reference))?;
+                Ok(json!({"entity": entity, "label": scene.label(entity)}))
+            },
+            "inspect" => {
+                let entity = entity_argument(request)?;
+                let components: Vec<Value> = scene.inspect(entity).iter().map(|component| {
+                    let fields: serde_json::Map<String, Value> = component.fields.iter()
+                        .map(|field| (field.name.clone(), field_to_json(&field.value)))
+                        .collect();
+                    json!({"name": component.name, "fields": fields})
+                }).collect();
+                let transform = scene.transform(entity)
+                    .map(|transform| json!({"position": transform.position, "rotation": transform.rotation, "scale": transform.scale}));
+                Ok(json!({"entity": entity, "label": scene.label(entity), "transform": transform, "components": components}))
+            },
+            "set_field" => {
+                let entity = entity_argument(request)?;
+                let (component, field) = (string_argument(request, "component")?, string_argument(request, "field")?);
+                let old = scene.inspect(entity).into_iter()
+                    .filter(|inspected| inspected.name == component)
+                    .flat_map(|inspected| inspected.fields.into_iter())
+                    .find(|inspected| inspected.name == field)
+                    .map(|inspected| inspected.value)
+                    .ok_or_else(|| format!("The entity {} has no field {}.{}.", entity, component, field))?;
+                let new = field_from_json(&request["value"], old.field_type())
+                    .ok_or_else(|| format!("The value of {}.{} must be a {:?}.", component, field, old.field_type()))?;
+                undo_stack.execute(Box::new(SetField::new(entity, component, field, old, new)), scene);
+                Ok(Value::Null)
+            },
+            "set_transform" => {
+                let entity = entity_argument(request)?;
+                let old = scene.transform(entity).ok_or_else(|| format!("The entity {} has no transform.", entity))?;
+                let new = Transform {
+                    position: vector(&request["position"]).unwrap_or(old.position),
+                    rotation: quaternion(&request["rotation"]).unwrap_or(old.rotation),
+                    scale: vector(&request["scale"]).unwrap_or(old.scale),
+                };
+                undo_stack.execute(Box::new(SetTransform::new(entity, old, new)), scene);
+                Ok(Value::Null)
+            },
+            "undo" => Ok(json!(undo_stack.undo(scene))),
+            "redo" => Ok(json!(undo_stack.redo(scene))),
+            "reload_asset" => {
+                self.reloads.push(string_argument(request, "path")?.to_string());
+                Ok(Value::Null)
+            },
+            "subscribe" | "unsubscribe" => {
+                let topic = string_argument(request, "topic")?;
+                if topic != "logs" && topic != "metrics" {
+                    return Err(format!("Unknown topic {}.", topic));
+                }
+                if name == "subscribe" {
+                    self.topics.insert(topic.to_string());
+                } else {
+                    self.topics.remove(topic);
+                }
+                Ok(Value::Null)
+            },
+            _ => Err(format!("Unknown request {}.", name)),
+        }
+    }
+}
+
+pub trait IpcStream: Read + Write {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl IpcStream for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+#[cfg(unix)]
+impl IpcStream for UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    fn accept(&self) -> io::Result<Box<IpcStream>> {
+        match *self {
+            Listener::Tcp(ref listener) => listener.accept().map(|(stream, _)| Box::new(stream) as Box<IpcStream>),
+            #[cfg(unix)]
+            Listener::Unix(ref listener, _) => listener.accept().map(|(stream, _)| Box::new(stream) as Box<IpcStream>),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            if let Listener::Unix(_, ref path) = *self {
+                let _ = ::std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+pub struct IpcServer {
+    listener: Listener,
+    client: Option<Box<IpcStream>>,
+    buffer: FrameBuffer,
+    backlog: Vec<u8>,
+    pub session: IpcSession,
+}
+
+impl IpcServer {
+    fn new(listener: Listener) -> Self {
+        IpcServer {
+            listener,
+            client: None,
+            buffer: FrameBuffer::default(),
+            backlog: Vec::new(),
+            session: IpcSession::new(),
+        }
+    }
+
+    //On 127.0.0.1. Port 0 picks a free port, see port().
+    pub fn bind_tcp(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        debug!("Editor IPC listening on {}.", listener.local_addr()?);
+        Ok(IpcServer::new(Listener::Tcp(listener)))
+    }
+
+    //A socket left by a previous run is replaced.
+    #[cfg(unix)]
+    pub fn bind_unix<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() {
+            ::std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        debug!("Editor IPC listening on {}.", path.display());
+        Ok(IpcServer::new(Listener::Unix(listener, path)))
+    }
+
+    //None for a Unix socket.
+    pub fn port(&self) -> Option<u16> {
+        match self.listener {
+            Listener::Tcp(ref listener) => listener.local_addr().ok().map(|address| address.port()),
+            #[cfg(unix)]
+            Listener::Unix(..) => None,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    fn disconnect(&mut self) {
+        debug!("Editor IPC client disconnected.");
+        self.client = None;
+        self.backlog.clear();
+        self.session = IpcSession::new();
+    }
+
+    fn accept(&mut self) -> io::Result<()> {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok(stream) => {
+                    debug!("Editor IPC client connected.");
+                    stream.set_nonblocking(true)?;
+                    self.client = Some(stream);
+                    self.buffer = FrameBuffer::default();
+                    self.backlog.clear();
+                    self.session = IpcSession::new();
+                },
+                Err(ref error) if error.kind() == ErrorKind::WouldBlock => {},
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<()> {
+        let mut closed = false;
+        if let Some(ref mut client) = self.client {
+            let mut bytes = [0u8; 4096];
+            loop {
+                match client.read(&mut bytes) {
+                    Ok(0) => {
+                        closed = true;
+                        break;
+                    },
+                    Ok(count) => self.buffer.push(&bytes[..count]),
+                    Err(ref error) if error.kind() == ErrorKind::WouldBlock => break,
+                    Err(ref error) if error.kind() == ErrorKind::ConnectionReset => {
+                        closed = true;
+                        break;
+                    },
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+        if closed {
+            self.disconnect();
+        }
+        Ok(())
+    }
+
+    //Sends what the socket accepts without blocking, the rest is sent by the next flushes.
+    fn flush(&mut self) -> io::Result<()> {
+        let outgoing = self.session.take_outgoing();
+        let mut gone = false;
+        if let Some(ref mut client) = self.client {
+            for message in outgoing.iter() {
+                write_frame(&mut self.backlog, message)?;
+            }
+            while !self.backlog.is_empty() {
+                match client.write(self.backlog.as_slice()) {
+                    Ok(0) => {
+                        gone = true;
+                        break;
+                    },
+                    Ok(count) => {
+                        self.backlog.drain(..count);
+                    },
+                    Err(ref error) if error.kind() == ErrorKind::WouldBlock => break,
+                    Err(ref error) => {
+                        warn!("Editor IPC: {}, the client is disconnected.", error);
+                        gone = true;
+                        break;
+                    },
+                }
+            }
+            if self.backlog.len() > MAX_BACKLOG {
+                warn!("Editor IPC: the client doesn't read its messages, it is disconnected.");
+                gone = true;
+            }
+        }
+        if gone {
+            self.disconnect();
+        }
+        Ok(())
+    }
+
+    //Called once per frame: accepts the editor, handles its requests and sends the responses and the events.
+    pub fn poll<S: EditorScene + 'static>(&mut self, scene: &mut S, undo_stack: &mut UndoStack<S>) -> io::Result<()> {
+        self.accept()?;
+        self.receive()?;
+        loop {
+            match self.buffer.next_frame() {
+                Ok(Some(request)) => self.session.handle(&request, scene, undo_stack),
+                Ok(None) => break,
+                Err(error) => {
+                    warn!("Editor IPC: {}, the client is disconnected.", error);
+                    self.disconnect();
+                    break;
+                },
+            }
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod ipc_test {
+    use super::*;
+    use std::collections::HashMap;
+    use std::thread;
+    use std::time::Duration;
+    use editor::editor_scene::{InspectedComponent, InspectedField};
+
+    #[derive(Default)]
+    struct Scene {
+        transforms: HashMap<u64, Transform>,
+        health: HashMap<u64, i64>,
+    }
+
+    impl EditorScene for Scene {
+        fn transform(&self, entity: u64) -> Option<Transform> {
+            self.transforms.get(&entity).cloned()
+        }
+
+        fn set_transform(&mut self, entity: u64, transform: Transform) {
+            self.transforms.insert(entity, transform);
+        }
+
+        fn raycast(&self, _origin: [f32; 3], _direction: [f32; 3]) -> Option<(u64, f32)> {
+            None
+        }
+
+        fn inspect(&self, entity: u64) -> Vec<InspectedComponent> {
+            self.health.get(&entity).map(|&health| vec![InspectedComponent {
+                name: String::from("Health"),
+                fields: vec![InspectedField { name: String::from("value"), value: FieldValue::Int(health) }],
+            }]).unwrap_or_default()
+        }
+
+        fn set_field(&mut self, entity: u64, _component: &str, _field: &str, value: FieldValue) -> bool {
+            match value {
+                FieldValue::Int(health) => {
+                    self.health.insert(entity, health);
+                    true
+                },
+                _ => false,
+            }
+        }
+
+        fn find(&self, reference: &str) -> Option<u64> {
+            if reference == "player" {Some(1)} else {None}
+        }
+    }
+
+    fn scene() -> Scene {
+        let mut scene = Scene::default();
+        scene.transforms.insert(1, Transform::from_position([1.0, 2.0, 3.0]));
+        scene.health.insert(1, 100);
+        scene
+    }
+
+    #[test]
+    fn ipc_session_requests() {
+        let (mut scene, mut undo_stack) = (scene(), UndoStack::new());
+        let mut session = IpcSession::new();
+        session.handle(&json!({"id": 1, "request": "find", "reference": "player"}), &mut scene, &mut undo_stack);
+        session.handle(&json!({"id": 2, "request": "set_field", "entity": 1, "component": "Health", "field": "value", "value": 50}), &mut scene, &mut undo_stack);
+        session.handle(&json!({"id": 3, "request": "set_field", "entity": 1, "component": "Health", "field": "value", "value": "full"}), &mut scene, &mut undo_stack);
+        session.handle(&json!({"id": 4, "request": "set_transform", "entity": 1, "position": [0, 0, 0]}), &mut scene, &mut undo_stack);
+        session.handle(&json!({"id": 5, "request": "inspect", "entity": 1}), &mut scene, &mut undo_stack);
+        session.handle(&json!({"id": 6, "request": "reload_asset", "path": "textures/rock.png"}), &mut scene, &mut undo_stack);
+        session.handle(&json!({"id": 7, "request": "fly"}), &mut scene, &mut undo_stack);
+
+        let responses = session.take_outgoing();
+        assert_eq!(responses[0]["result"]["entity"], json!(1));
+        assert_eq!(responses[1]["ok"], json!(true));
+        assert_eq!(responses[2]["ok"], json!(false));
+        assert_eq!(scene.health[&1], 50);
+        assert_eq!(scene.transforms[&1].position, [0.0, 0.0, 0.0]);
+        assert_eq!(responses[4]["result"]["components"][0]["fields"]["value"], json!(50));
+        assert_eq!(responses[6]["error"], json!("Unknown request fly."));
+        assert_eq!(session.take_reload_requests(), vec!["textures/rock.png"]);
+
+        session.handle(&json!({"id": 8, "request": "undo"}), &mut scene, &mut undo_stack);
+        assert_eq!(scene.transforms[&1].position, [1.0, 2.0, 3.0]);
+
+        session.publish_log("info", "game", "not subscribed");
+        session.handle(&json!({"id": 9, "request": "subscribe", "topic": "logs"}), &mut scene, &mut undo_stack);
+        session.take_outgoing();
+        session.publish_log("warn", "game", "low health");
+        session.publish_metrics(10, &[("fps", 60.0)]);
+        let events = session.take_outgoing();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["message"], json!("low health"));
+    }
+
+    #[test]
+    fn ipc_over_tcp() {
+        let (mut scene, mut undo_stack) = (scene(), UndoStack::new());
+        let mut server = IpcServer::bind_tcp(0).unwrap();
+        let mut client = TcpStream::connect(("127.0.0.1", server.port().unwrap())).unwrap();
+        write_frame(&mut client, &json!({"id": 1, "request": "hello"})).unwrap();
+
+        let mut buffer = FrameBuffer::default();
+        let mut bytes = [0u8; 1024];
+        client.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        for _ in 0..100 {
+            server.poll(&mut scene, &mut undo_stack).unwrap();
+            if let Ok(count) = client.read(&mut bytes) {
+                buffer.push(&bytes[..count]);
+            }
+            if let Some(response) = buffer.next_frame().unwrap() {
+                assert_eq!(response["result"]["protocol"], json!(IPC_PROTOCOL_VERSION));
+                assert!(server.is_connected());
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("No response from the IPC server.");
+    }
+
+    #[test]
+    fn ipc_client_not_reading_is_disconnected() {
+        let (mut scene, mut undo_stack) = (scene(), UndoStack::new());
+        let mut server = IpcServer::bind_tcp(0).unwrap();
+        let mut client = TcpStream::connect(("127.0.0.1", server.port().unwrap())).unwrap();
+        write_frame(&mut client, &json!({"id": 1, "request": "subscribe", "topic": "logs"})).unwrap();
+        for _ in 0..100 {
+            server.poll(&mut scene, &mut undo_stack).unwrap();
+            if server.session.is_subscribed("logs") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(server.session.is_subscribed("logs"));
+
+        //The client never reads: the polls don't block, the messages wait in the backlog until it is too big.
+        let message = "x".repeat(1024 * 1024);
+        for _ in 0..100 {
+            server.session.publish_log("info", "test", message.as_str());
+            server.poll(&mut scene, &mut undo_stack).unwrap();
+            if !server.is_connected() {
+                return;
+            }
+        }
+        panic!("The client was not disconnected.");
+    }
+
+    #[test]
+    fn frames_too_big_are_refused() {
+        let mut buffer = FrameBuffer::default();
+        buffer.push(&(MAX_FRAME_SIZE as u32 + 1).to_le_bytes());
+        assert!(buffer.next_frame().is_err());
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ IN-GAME EDITOR.
+
+ An overlay mode where the developer can select game objects by clicking on them, inspect
+ and edit their components, and move them with translate/rotate/scale gizmos.
+
+ The editor doesn't know how the game stores its objects: the game implements EditorScene,
+ which gives access to the transforms, the physics picking and the component fields.
+ Every modification goes through the undo stack.
+*/
+
+pub mod editor_scene;
+pub mod selection;
+pub mod gizmo;
+pub mod inspector;
+pub mod ipc;
+
+use maskerad_core::undo::UndoStack;
+use editor::editor_scene::EditorScene;
+use editor::selection::Selection;
+use editor::gizmo::Gizmo;
+use editor::inspector::Inspector;
+
+pub struct Editor<S: EditorScene + 'static> {
+    enabled: bool,
+    pub selection: Selection,
+    pub gizmo: Gizmo,
+    pub inspector: Inspector,
+    pub undo_stack: UndoStack<S>,
+}
+
+impl<S: EditorScene + 'static> Default for Editor<S> {
+    fn default() -> Self {
+        Editor {
+            enabled: false,
+            selection: Selection::new(),
+            gizmo: Gizmo::new(),
+            inspector: Inspector::new(),
+            undo_stack: UndoStack::new(),
+        }
+    }
+}
+
+impl<S: EditorScene + 'static> Editor<S> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        debug!("In-game editor enabled: {}.", self.enabled);
+        if !self.enabled {
+            self.selection.clear();
+            self.gizmo.cancel_drag();
+        }
+    }
+
+    pub fn undo(&mut self, scene: &mut S) -> bool {
+        self.undo_stack.undo(scene)
+    }
+
+    pub fn redo(&mut self, scene: &mut S) -> bool {
+        self.undo_stack.redo(scene)
+    }
+}
+
+#[cfg(test)]
+mod editor_test {
+    use super::*;
+    use std::collections::HashMap;
+    use maskerad_core::math::transform::Transform;
+    use editor::editor_scene::{FieldValue, InspectedComponent, InspectedField};
+    use editor::gizmo::{GizmoAxis, GizmoMode};
+
+    #[derive(Default)]
+    struct TestScene {
+        transforms: HashMap<u64, Transform>,
+        health: HashMap<u64, i64>,
+    }
+
+    impl EditorScene for TestScene {
+        fn transform(&self, entity: u64) -> Option<Transform> {
+            self.transforms.get(&entity).cloned()
+        }
+
+        fn set_transform(&mut self, entity: u64, transform: Transform) {
+            self.transforms.insert(entity, transform);
+        }
+
+        fn raycast(&self, origin: [f32; 3], _direction: [f32; 3]) -> Option<(u64, f32)> {
+            //Everything is on the x axis, the ray hits the entity at the same x.
+            self.transforms.iter()
+                .filter(|&(_, transform)| transform.position[0] == origin[0])
+                .map(|(entity, _)| (*entity, 1.0))
+                .next()
+        }
+
+        fn inspect(&self, entity: u64) -> Vec<InspectedComponent> {
+            match self.health.get(&entity) {
+                Some(health) => vec![InspectedComponent {
+                    name: String::from("Health"),
+                    fields: vec![InspectedField { name: String::from("value"), value: FieldValue::Int(*health) }],
+                }],
+                None => Vec::new(),
+            }
+        }
+
+        fn set_field(&mut self, entity: u64, component: &str, field: &str, value: FieldValue) -> bool {
+            match (component, field, value) {
+                ("Health", "value", FieldValue::Int(health)) => {
+                    self.health.insert(entity, health);
+                    true
+                },
+                _ => false,
+            }
+        }
+    }
+
+    #[test]
+    fn editor_pick_move_and_undo() {
+        let mut scene = TestScene::default();
+        scene.set_transform(1, Transform::from_position([5.0, 0.0, 0.0]));
+        scene.health.insert(1, 100);
+
+        let mut editor = Editor::new();
+        editor.toggle();
+        assert!(editor.is_enabled());
+        assert_eq!(editor.selection.pick(&scene, [5.0, 10.0, 0.0], [0.0, -1.0, 0.0], false), Some(1));
+
+        editor.gizmo.set_mode(GizmoMode::Translate);
+        assert!(editor.gizmo.begin_drag(&scene, 1, GizmoAxis::Y));
+        editor.gizmo.drag_to(&mut scene, 1.0);
+        editor.gizmo.drag_to(&mut scene, 2.0);
+        editor.gizmo.end_drag(&mut scene, &mut editor.undo_stack);
+        assert_eq!(scene.transform(1).unwrap().position, [5.0, 2.0, 0.0]);
+
+        editor.inspector.refresh(&scene, editor.selection.primary());
+        assert!(editor.inspector.edit(&mut scene, &mut editor.undo_stack, "Health", "value", FieldValue::Int(50)));
+        assert_eq!(scene.health[&1], 50);
+
+        assert!(editor.undo(&mut scene));
+        assert_eq!(scene.health[&1], 100);
+        assert!(editor.undo(&mut scene));
+        assert_eq!(scene.transform(1).unwrap().position, [5.0, 0.0, 0.0]);
+        assert!(editor.redo(&mut scene));
+        assert_eq!(scene.transform(1).unwrap().position, [5.0, 2.0, 0.0]);
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use editor::editor_scene::EditorScene;
+
+#[derive(Debug, Default, Clone)]
+pub struct Selection {
+    entities: Vec<u64>,
+}
+
+impl Selection {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn entities(&self) -> &[u64] {
+        self.entities.as_slice()
+    }
+
+    //The last selected entity, the one shown by the inspector and moved by the gizmo.
+    pub fn primary(&self) -> Option<u64> {
+        self.entities.last().cloned()
+    }
+
+    pub fn is_selected(&self, entity: u64) -> bool {
+        self.entities.contains(&entity)
+    }
+
+    pub fn select(&mut self, entity: u64, additive: bool) {
+        if !additive {
+            self.entities.clear();
+        }
+        self.entities.retain(|selected| *selected != entity);
+        self.entities.push(entity);
+    }
+
+    pub fn deselect(&mut self, entity: u64) {
+        self.entities.retain(|selected| *selected != entity);
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+
+    //Select the entity under the cursor. Clicking in the void clears a non-additive selection.
+    pub fn pick<S: EditorScene>(&mut self, scene: &S, origin: [f32; 3], direction: [f32; 3], additive: bool) -> Option<u64> {
+        match scene.raycast(origin, direction) {
+            Some((entity, _)) => {
+                debug!("Editor: picked the entity {}.", entity);
+                self.select(entity, additive);
+                Some(entity)
+            },
+            None => {
+                if !additive {
+                    self.clear();
+                }
+                None
+            },
+        }
+    }
+
+    //Select an entity by its name or its path, from the console.
+    pub fn select_by_reference<S: EditorScene>(&mut self, scene: &S, reference: &str, additive: bool) -> Option<u64> {
+        let entity = scene.find(reference);
+        match entity {
+            Some(entity) => self.select(entity, additive),
+            None => warn!("Editor: no entity matches \"{}\".", reference),
+        }
+        entity
+    }
+}
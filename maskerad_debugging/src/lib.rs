@@ -3,4 +3,18 @@
 // Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
 // http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
-// copied, modified, or distributed except according to those terms.
\ No newline at end of file
+// copied, modified, or distributed except according to those terms.
+
+extern crate maskerad_core;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_json;
+
+//The in-game editor is only available in debug builds.
+#[cfg(debug_assertions)]
+pub mod editor;
+
+pub mod benchmark;
+pub mod status_overlay;
+pub mod remote_profiler;
@@ -0,0 +1,483 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ REMOTE PROFILER.
+
+ The game records the timed scopes of each frame and a few counters (draw calls, memory, entities...), and
+ streams them to the profiler viewers connected on the network: the performance of a Steam Deck or of a test
+ PC is inspected live from a workstation.
+
+ The protocol is binary, little endian. The server sends the header "KPRF" + version (u16), then messages,
+ each one starting with its kind (u8):
+ - NAME: id (u16), length (u16), UTF-8 name. Sent before the first use of the name by a viewer.
+ - FRAME: frame number (u64), start (u64, microseconds since the start of the profiler), duration (u32,
+   microseconds), scope count (u16), scopes: name (u16), depth (u8), start (u32, microseconds from the start
+   of the frame), duration (u32, microseconds), counter count (u16), counters: name (u16), value (f64).
+ The viewers never send anything. A viewer too slow to receive the frames is disconnected, the game never waits.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Instant;
+
+pub const PROFILER_MAGIC: &'static [u8; 4] = b"KPRF";
+pub const PROFILER_VERSION: u16 = 1;
+pub const DEFAULT_PROFILER_PORT: u16 = 7455;
+const NAME_MESSAGE: u8 = 1;
+const FRAME_MESSAGE: u8 = 2;
+//The bytes waiting to be sent to a viewer before it is disconnected.
+const MAX_BACKLOG: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScopeRecord {
+    pub name: u16,
+    pub depth: u8,
+    //In microseconds from the start of the frame.
+    pub start: u32,
+    pub duration: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameProfile {
+    pub frame: u64,
+    //In microseconds since the start of the profiler.
+    pub start: u64,
+    pub duration: u32,
+    pub scopes: Vec<ScopeRecord>,
+    pub counters: Vec<(u16, f64)>,
+}
+
+fn micros(from: Instant, to: Instant) -> u64 {
+    let elapsed = to.duration_since(from);
+    elapsed.as_secs() * 1_000_000 + u64::from(elapsed.subsec_micros())
+}
+
+//Records the scopes and the counters of the current frame.
+pub struct Profiler {
+    epoch: Instant,
+    names: Vec<String>,
+    ids: HashMap<String, u16>,
+    frame: u64,
+    frame_start: Instant,
+    stack: Vec<(usize, Instant)>,
+    scopes: Vec<ScopeRecord>,
+    counters: Vec<(u16, f64)>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        let now = Instant::now();
+        Profiler {
+            epoch: now,
+            names: Vec::new(),
+            ids: HashMap::new(),
+            frame: 0,
+            frame_start: now,
+            stack: Vec::new(),
+            scopes: Vec::new(),
+            counters: Vec::new(),
+        }
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    //The names are interned, the id of a name never changes.
+    pub fn intern(&mut self, name: &str) -> u16 {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len().min(u16::MAX as usize) as u16;
+        if self.names.len() < u16::MAX as usize {
+            self.names.push(String::from(name));
+            self.ids.insert(String::from(name), id);
+        } else {
+            warn!("Too many profiler names, {} shares the id of {}.", name, self.names[id as usize - 1]);
+        }
+        id
+    }
+
+    pub fn name(&self, id: u16) -> Option<&str> {
+        self.names.get(id as usize).map(|name| name.as_str())
+    }
+
+    pub fn begin_scope(&mut self, name: &str) {
+        let id = self.intern(name);
+        let start = micros(self.frame_start, Instant::now());
+        self.stack.push((self.scopes.len(), Instant::now()));
+        self.scopes.push(ScopeRecord {
+            name: id,
+            depth: (self.stack.len() - 1).min(u8::MAX as usize) as u8,
+            start: start.min(u64::from(u32::MAX)) as u32,
+            duration: 0,
+        });
+    }
+
+    //Ends the innermost scope.
+    pub fn end_scope(&mut self) {
+        match self.stack.pop() {
+            Some((index, start)) => {
+                self.scopes[index].duration = micros(start, Instant::now()).min(u64::from(u32::MAX)) as u32;
+            },
+            None => warn!("Profiler: end_scope without begin_scope."),
+        }
+    }
+
+    //Runs the function in a scope.
+    pub fn scope<T, F: FnOnce(&mut Profiler) -> T>(&mut self, name: &str, function: F) -> T {
+        self.begin_scope(name);
+        let result = function(self);
+        self.end_scope();
+        result
+    }
+
+    //The last value of the frame is kept.
+    pub fn counter(&mut self, name: &str, value: f64) {
+        let id = self.intern(name);
+        match self.counters.iter_mut().find(|&&mut (counter, _)| counter == id) {
+            Some(counter) => counter.1 = value,
+            None => self.counters.push((id, value)),
+        }
+    }
+
+    //The scopes still open are closed.
+    pub fn end_frame(&mut self) -> FrameProfile {
+        while !self.stack.is_empty() {
+            self.end_scope();
+        }
+        let now = Instant::now();
+        let profile = FrameProfile {
+            frame: self.frame,
+            start: micros(self.epoch, self.frame_start),
+            duration: micros(self.frame_start, now).min(u64::from(u32::MAX)) as u32,
+            scopes: self.scopes.drain(..).collect(),
+            counters: self.counters.drain(..).collect(),
+        };
+        self.frame += 1;
+        self.frame_start = now;
+        profile
+    }
+}
+
+pub fn encode_name(buffer: &mut Vec<u8>, id: u16, name: &str) {
+    let name = &name.as_bytes()[..name.len().min(u16::MAX as usize)];
+    buffer.push(NAME_MESSAGE);
+    buffer.extend_from_slice(&id.to_le_bytes());
+    buffer.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    buffer.extend_from_slice(name);
+}
+
+pub fn encode_frame(buffer: &mut Vec<u8>, profile: &FrameProfile) {
+    buffer.push(FRAME_MESSAGE);
+    buffer.extend_from_slice(&profile.frame.to_le_bytes());
+    buffer.extend_from_slice(&profile.start.to_le_bytes());
+    buffer.extend_from_slice(&profile.duration.to_le_bytes());
+    let scopes = &profile.scopes[..profile.scopes.len().min(u16::MAX as usize)];
+    buffer.extend_from_slice(&(scopes.len() as u16).to_le_bytes());
+    for scope in scopes {
+        buffer.extend_from_slice(&scope.name.to_le_bytes());
+        buffer.push(scope.depth);
+        buffer.extend_from_slice(&scope.start.to_le_bytes());
+        buffer.extend_from_slice(&scope.duration.to_le_bytes());
+    }
+    let counters = &profile.counters[..profile.counters.len().min(u16::MAX as usize)];
+    buffer.extend_from_slice(&(counters.len() as u16).to_le_bytes());
+    for &(name, value) in counters {
+        buffer.extend_from_slice(&name.to_le_bytes());
+        buffer.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+//Reads the stream of a server, on the viewer side.
+#[derive(Debug, Default)]
+pub struct ProfileDecoder {
+    data: Vec<u8>,
+    header_read: bool,
+    names: HashMap<u16, String>,
+}
+
+//Reads the values of a message, None if the message is not complete.
+struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.position..self.position + count)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|bytes| {
+            let mut value = [0u8; 8];
+            value.copy_from_slice(bytes);
+            u64::from_le_bytes(value)
+        })
+    }
+}
+
+impl ProfileDecoder {
+    pub fn new() -> Self {
+        ProfileDecoder::default()
+    }
+
+    pub fn name(&self, id: u16) -> Option<&str> {
+        self.names.get(&id).map(|name| name.as_str())
+    }
+
+    //The frames received completely.
+    pub fn push(&mut self, bytes: &[u8]) -> io::Result<Vec<FrameProfile>> {
+        self.data.extend_from_slice(bytes);
+        let mut frames = Vec::new();
+        let mut consumed = 0;
+        if !self.header_read {
+            if self.data.len() < 6 {
+                return Ok(frames);
+            }
+            if &self.data[..4] != PROFILER_MAGIC {
+                return Err(io::Error::new(ErrorKind::InvalidData, "Not a profiler stream."));
+            }
+            let version = u16::from_le_bytes([self.data[4], self.data[5]]);
+            if version != PROFILER_VERSION {
+                return Err(io::Error::new(ErrorKind::InvalidData, format!("Profiler protocol version {}, expected {}.", version, PROFILER_VERSION)));
+            }
+            self.header_read = true;
+            consumed = 6;
+        }
+        let mut names = Vec::new();
+        {
+            let mut cursor = Cursor { data: self.data.as_slice(), position: consumed };
+            loop {
+                let decoded = match cursor.u8() {
+                    Some(NAME_MESSAGE) => ProfileDecoder::decode_name(&mut cursor).map(|name| names.push(name)),
+                    Some(FRAME_MESSAGE) => ProfileDecoder::decode_frame(&mut cursor).map(|frame| frames.push(frame)),
+                    Some(kind) => return Err(io::Error::new(ErrorKind::InvalidData, format!("Unknown profiler message {}.", kind))),
+                    None => None,
+                };
+                match decoded {
+                    Some(()) => consumed = cursor.position,
+                    None => break,
+                }
+            }
+        }
+        self.names.extend(names);
+        self.data.drain(..consumed);
+        Ok(frames)
+    }
+
+    fn decode_name(cursor: &mut Cursor) -> Option<(u16, String)> {
+        let id = cursor.u16()?;
+        let length = cursor.u16()? as usize;
+        let name = cursor.take(length)?;
+        Some((id, String::from_utf8_lossy(name).into_owned()))
+    }
+
+    fn decode_frame(cursor: &mut Cursor) -> Option<FrameProfile> {
+        let (frame, start, duration) = (cursor.u64()?, cursor.u64()?, cursor.u32()?);
+        let mut scopes = Vec::new();
+        for _ in 0..cursor.u16()? {
+            scopes.push(ScopeRecord { name: cursor.u16()?, depth: cursor.u8()?, start: cursor.u32()?, duration: cursor.u32()? });
+        }
+        let mut counters = Vec::new();
+        for _ in 0..cursor.u16()? {
+            counters.push((cursor.u16()?, f64::from_bits(cursor.u64()?)));
+        }
+        Some(FrameProfile { frame, start, duration, scopes, counters })
+    }
+}
+
+struct Viewer {
+    stream: TcpStream,
+    address: SocketAddr,
+    sent_names: HashSet<u16>,
+    backlog: Vec<u8>,
+}
+
+impl Viewer {
+    //Sends what the socket accepts without blocking. False if the viewer is gone or too slow.
+    fn send(&mut self) -> bool {
+        while !self.backlog.is_empty() {
+            match self.stream.write(self.backlog.as_slice()) {
+                Ok(0) => return false,
+                Ok(count) => {
+                    self.backlog.drain(..count);
+                },
+                Err(ref error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => return false,
+            }
+        }
+        self.backlog.len() <= MAX_BACKLOG
+    }
+
+    fn is_closed(&mut self) -> bool {
+        let mut byte = [0u8; 64];
+        match self.stream.read(&mut byte) {
+            Ok(0) => true,
+            Ok(_) => false,
+            Err(ref error) if error.kind() == ErrorKind::WouldBlock => false,
+            Err(_) => true,
+        }
+    }
+}
+
+//Streams the profiles of the frames to the connected viewers.
+pub struct ProfilerServer {
+    listener: TcpListener,
+    viewers: Vec<Viewer>,
+}
+
+impl ProfilerServer {
+    //The target device is inspected from another machine: bind to 0.0.0.0 to accept the viewers of the
+    //network, to 127.0.0.1 for a viewer on the same machine only. Port 0 picks a free port, see port().
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        info!("Remote profiler listening on {}.", listener.local_addr()?);
+        Ok(ProfilerServer {
+            listener,
+            viewers: Vec::new(),
+        })
+    }
+
+    pub fn port(&self) -> io::Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    pub fn viewer_count(&self) -> usize {
+        self.viewers.len()
+    }
+
+    fn accept(&mut self) -> io::Result<()> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    stream.set_nonblocking(true)?;
+                    stream.set_nodelay(true)?;
+                    debug!("Profiler viewer connected from {}.", address);
+                    let mut backlog = PROFILER_MAGIC.to_vec();
+                    backlog.extend_from_slice(&PROFILER_VERSION.to_le_bytes());
+                    self.viewers.push(Viewer { stream, address, sent_names: HashSet::new(), backlog });
+                },
+                Err(ref error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    //Called once per frame, with the profile of the frame which just ended.
+    pub fn publish(&mut self, profile: &FrameProfile, profiler: &Profiler) -> io::Result<()> {
+        self.accept()?;
+        let mut frame = Vec::new();
+        encode_frame(&mut frame, profile);
+        for viewer in self.viewers.iter_mut() {
+            let used = profile.scopes.iter().map(|scope| scope.name).chain(profile.counters.iter().map(|&(name, _)| name));
+            for id in used {
+                if viewer.sent_names.insert(id) {
+                    encode_name(&mut viewer.backlog, id, profiler.name(id).unwrap_or("?"));
+                }
+            }
+            viewer.backlog.extend_from_slice(frame.as_slice());
+        }
+        let mut kept = Vec::with_capacity(self.viewers.len());
+        for mut viewer in self.viewers.drain(..) {
+            if viewer.is_closed() || !viewer.send() {
+                debug!("Profiler viewer {} disconnected.", viewer.address);
+            } else {
+                kept.push(viewer);
+            }
+        }
+        self.viewers = kept;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod remote_profiler_test {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn profile_frames_and_encode_them() {
+        let mut profiler = Profiler::new();
+        profiler.scope("update", |profiler| {
+            profiler.scope("physics", |_| {});
+            profiler.counter("entities", 10.0);
+        });
+        profiler.counter("entities", 12.0);
+        profiler.begin_scope("render");
+        let profile = profiler.end_frame();
+        assert_eq!(profile.frame, 0);
+        assert_eq!(profile.scopes.len(), 3);
+        assert_eq!(profile.scopes[1].depth, 1);
+        assert_eq!(profiler.name(profile.scopes[1].name), Some("physics"));
+        assert_eq!(profile.counters, vec![(profiler.intern("entities"), 12.0)]);
+        assert_eq!(profiler.end_frame().frame, 1);
+
+        let mut bytes = PROFILER_MAGIC.to_vec();
+        bytes.extend_from_slice(&PROFILER_VERSION.to_le_bytes());
+        for &(id, name) in [(0u16, "update"), (1, "physics"), (2, "entities"), (3, "render")].iter() {
+            encode_name(&mut bytes, id, name);
+        }
+        encode_frame(&mut bytes, &profile);
+
+        //Received in small pieces.
+        let mut decoder = ProfileDecoder::new();
+        let mut frames = Vec::new();
+        for piece in bytes.chunks(5) {
+            frames.extend(decoder.push(piece).unwrap());
+        }
+        assert_eq!(frames, vec![profile]);
+        assert_eq!(decoder.name(3), Some("render"));
+        assert!(ProfileDecoder::new().push(b"HTTP/1.1").is_err());
+    }
+
+    #[test]
+    fn stream_to_a_viewer() {
+        let mut server = ProfilerServer::bind(("127.0.0.1", 0)).unwrap();
+        let mut viewer = TcpStream::connect(("127.0.0.1", server.port().unwrap())).unwrap();
+        viewer.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+        let mut profiler = Profiler::new();
+        let mut decoder = ProfileDecoder::new();
+        let mut bytes = [0u8; 4096];
+        for _ in 0..100 {
+            profiler.scope("frame", |profiler| profiler.counter("draw_calls", 42.0));
+            let profile = profiler.end_frame();
+            server.publish(&profile, &profiler).unwrap();
+            if let Ok(count) = viewer.read(&mut bytes) {
+                let frames = decoder.push(&bytes[..count]).unwrap();
+                if let Some(frame) = frames.first() {
+                    assert_eq!(server.viewer_count(), 1);
+                    assert_eq!(decoder.name(frame.scopes[0].name), Some("frame"));
+                    assert_eq!(frame.counters[0].1, 42.0);
+                    return;
+                }
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("No frame received by the viewer.");
+    }
+}
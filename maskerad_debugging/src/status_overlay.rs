@@ -0,0 +1,71 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//The status of the systems, on the debug overlay. Collapsed, only the systems which are not ok are
+//listed, so a lost audio device is seen at a glance.
+
+use maskerad_core::system_status::{SystemStatus, StatusBoard};
+
+pub const OK_COLOR: [f32; 4] = [0.4, 0.9, 0.4, 1.0];
+pub const DEGRADED_COLOR: [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+pub const FAILED_COLOR: [f32; 4] = [1.0, 0.3, 0.3, 1.0];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayLine {
+    pub text: String,
+    pub color: [f32; 4],
+}
+
+#[derive(Debug, Default)]
+pub struct StatusOverlay {
+    pub expanded: bool,
+}
+
+impl StatusOverlay {
+    pub fn lines(&self, board: &StatusBoard) -> Vec<OverlayLine> {
+        let mut lines: Vec<OverlayLine> = board.entries().iter()
+            .filter(|&&(_, ref status)| self.expanded || !status.is_ok())
+            .map(|&(ref system, ref status)| OverlayLine {
+                text: format!("{}: {}", system, status),
+                color: match status {
+                    &SystemStatus::Ok => OK_COLOR,
+                    &SystemStatus::Degraded(_) => DEGRADED_COLOR,
+                    &SystemStatus::Failed(_) => FAILED_COLOR,
+                },
+            })
+            .collect();
+        if lines.is_empty() && !board.entries().is_empty() {
+            lines.push(OverlayLine {
+                text: format!("{} systems ok", board.entries().len()),
+                color: OK_COLOR,
+            });
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod status_overlay_test {
+    use super::*;
+
+    #[test]
+    fn status_overlay_lists_unhealthy_systems() {
+        let mut board = StatusBoard::new();
+        board.update("renderer", SystemStatus::Ok);
+        board.update("physics", SystemStatus::Ok);
+        let mut overlay = StatusOverlay::default();
+        assert_eq!(overlay.lines(&board)[0].text, "2 systems ok");
+
+        board.update("audio", SystemStatus::Failed(String::from("no output device")));
+        assert_eq!(overlay.lines(&board), vec![OverlayLine {
+            text: String::from("audio: failed (no output device)"),
+            color: FAILED_COLOR,
+        }]);
+        overlay.expanded = true;
+        assert_eq!(overlay.lines(&board).len(), 3);
+    }
+}
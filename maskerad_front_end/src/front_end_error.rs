@@ -0,0 +1,64 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::error::Error;
+use std::fmt;
+use serde_json::Error as JSONError;
+
+#[derive(Debug)]
+pub enum FrontEndError {
+    SubtitleError(String, JSONError),
+    SettingsError(String),
+}
+
+unsafe impl Send for FrontEndError {}
+unsafe impl Sync for FrontEndError {}
+
+impl fmt::Display for FrontEndError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &FrontEndError::SubtitleError(ref description, _) => {
+                write!(f, "Subtitle error: {}", description)
+            },
+            &FrontEndError::SettingsError(ref description) => {
+                write!(f, "Settings error: {}", description)
+            },
+        }
+    }
+}
+
+impl Error for FrontEndError {
+    fn description(&self) -> &str {
+        match self {
+            &FrontEndError::SubtitleError(_, _) => {
+                "SubtitleError"
+            },
+            &FrontEndError::SettingsError(_) => {
+                "SettingsError"
+            },
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match self {
+            &FrontEndError::SubtitleError(_, ref json_error) => {
+                Some(json_error)
+            },
+            &FrontEndError::SettingsError(_) => {
+                None
+            },
+        }
+    }
+}
+
+pub type FrontEndResult<T> = Result<T, FrontEndError>;
+
+impl From<JSONError> for FrontEndError {
+    fn from(error: JSONError) -> Self {
+        FrontEndError::SubtitleError(String::from("Error while deserializing a subtitle track."), error)
+    }
+}
@@ -3,4 +3,19 @@
 // Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
 // http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
-// copied, modified, or distributed except according to those terms.
\ No newline at end of file
+// copied, modified, or distributed except according to those terms.
+
+extern crate maskerad_core;
+extern crate maskerad_inputs;
+#[macro_use]
+extern crate log;
+
+extern crate serde_json;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+pub mod front_end_error;
+pub mod subtitles;
+pub mod virtual_controls;
+pub mod settings_menu;
@@ -0,0 +1,196 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SETTINGS MENU.
+
+ The options menus are not written by hand: the settings structures register their fields in the
+ TypeRegistry (see AccessibilitySettings::type_info), and the SettingsMenu turns each registered settings
+ object into a section of controls. A new engine setting appears in the menus without touching the UI.
+
+ - bool -> toggle.
+ - int or float with a range -> slider. Without a range -> number input.
+ - text with options (an enum) -> dropdown. Without options -> text input.
+ - Vector3 fields are not shown.
+
+ The labels are localization ids: "settings.<section>" for the title of a section, and
+ "settings.<section>.<field>" for an item ("settings.VideoSettings.exposure").
+ The UI draws the model, and calls set when the player changes a value: the value is written through the
+ registry (clamped to its range), and the item is refreshed with the value really stored.
+*/
+
+use std::any::Any;
+use maskerad_core::reflection::{FieldInfo, FieldType, TypeRegistry, Value};
+use front_end_error::{FrontEndError, FrontEndResult};
+
+//The number of steps of a float slider.
+pub const FLOAT_SLIDER_STEPS: f64 = 100.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingControl {
+    Toggle,
+    Slider {
+        min: f64,
+        max: f64,
+        step: f64,
+    },
+    NumberInput,
+    Dropdown(Vec<String>),
+    TextInput,
+}
+
+impl SettingControl {
+    //None for the fields which can't be edited in a menu.
+    pub fn of(field: &FieldInfo) -> Option<Self> {
+        match (field.field_type(), field.range()) {
+            (FieldType::Bool, _) => Some(SettingControl::Toggle),
+            (FieldType::Int, Some((min, max))) => Some(SettingControl::Slider { min, max, step: 1.0 }),
+            (FieldType::Float, Some((min, max))) => Some(SettingControl::Slider { min, max, step: (max - min) / FLOAT_SLIDER_STEPS }),
+            (FieldType::Int, None) | (FieldType::Float, None) => Some(SettingControl::NumberInput),
+            (FieldType::Text, _) if !field.options().is_empty() => Some(SettingControl::Dropdown(field.options().to_vec())),
+            (FieldType::Text, _) => Some(SettingControl::TextInput),
+            (FieldType::Vector3, _) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingItem {
+    pub field: String,
+    pub label: String,
+    pub control: SettingControl,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsSection {
+    //The name of the registered settings type.
+    pub name: String,
+    pub title: String,
+    pub items: Vec<SettingItem>,
+}
+
+impl SettingsSection {
+    pub fn item(&self, field: &str) -> Option<&SettingItem> {
+        self.items.iter().find(|item| item.field == field)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SettingsMenu {
+    sections: Vec<SettingsSection>,
+}
+
+impl SettingsMenu {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    //A section for a registered settings object, with its current values. Replaces the section of the same type.
+    pub fn add_section(&mut self, registry: &TypeRegistry, settings: &Any) -> FrontEndResult<()> {
+        let info = registry.type_info_of(settings).ok_or_else(|| {
+            FrontEndError::SettingsError(String::from("The settings object is not registered in the type registry."))
+        })?;
+        let items = info.fields().iter().filter_map(|field| {
+            let control = SettingControl::of(field);
+            if control.is_none() {
+                trace!("The setting {}.{} has no control, it is not shown.", info.name(), field.name());
+            }
+            Some(SettingItem {
+                field: String::from(field.name()),
+                label: format!("settings.{}.{}", info.name(), field.name()),
+                control: control?,
+                value: field.get(settings)?,
+            })
+        }).collect();
+        let section = SettingsSection {
+            name: String::from(info.name()),
+            title: format!("settings.{}", info.name()),
+            items,
+        };
+        debug!("Settings section {} with {} items.", section.name, section.items.len());
+        match self.sections.iter().position(|known| known.name == section.name) {
+            Some(index) => self.sections[index] = section,
+            None => self.sections.push(section),
+        }
+        Ok(())
+    }
+
+    pub fn sections(&self) -> &[SettingsSection] {
+        self.sections.as_slice()
+    }
+
+    pub fn section(&self, name: &str) -> Option<&SettingsSection> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    //Writes the value chosen by the player in the settings object, and returns the value stored.
+    pub fn set(&mut self, registry: &TypeRegistry, settings: &mut Any, field: &str, value: Value) -> FrontEndResult<Value> {
+        let info = registry.type_info_of(settings).ok_or_else(|| {
+            FrontEndError::SettingsError(String::from("The settings object is not registered in the type registry."))
+        })?;
+        let name = String::from(info.name());
+        if !registry.write_field(settings, field, value.clone()) {
+            return Err(FrontEndError::SettingsError(format!("The value {:?} is not valid for the setting {}.{}.", value, name, field)));
+        }
+        let stored = info.field(field).and_then(|field| field.get(settings)).ok_or_else(|| {
+            FrontEndError::SettingsError(format!("The setting {}.{} can't be read back.", name, field))
+        })?;
+        let item = self.sections.iter_mut()
+            .filter(|section| section.name == name)
+            .flat_map(|section| section.items.iter_mut())
+            .find(|item| item.field == field);
+        if let Some(item) = item {
+            item.value = stored.clone();
+        }
+        Ok(stored)
+    }
+}
+
+#[cfg(test)]
+mod settings_menu_test {
+    use super::*;
+    use maskerad_core::engine_configuration::accessibility::{AccessibilitySettings, ColorblindFilter};
+    use maskerad_core::engine_configuration::video::VideoSettings;
+
+    #[test]
+    fn settings_menu_from_registry() {
+        let mut registry = TypeRegistry::new();
+        registry.register(AccessibilitySettings::type_info());
+        registry.register(VideoSettings::type_info());
+        let mut accessibility = AccessibilitySettings::default();
+        let video = VideoSettings::default();
+
+        let mut menu = SettingsMenu::new();
+        menu.add_section(&registry, &accessibility).unwrap();
+        menu.add_section(&registry, &video).unwrap();
+        assert!(menu.add_section(&registry, &5u32).is_err());
+        assert_eq!(menu.sections().len(), 2);
+
+        let section = menu.section("AccessibilitySettings").unwrap();
+        assert_eq!(section.title, "settings.AccessibilitySettings");
+        let ui_scale = section.item("ui_scale").unwrap();
+        assert_eq!(ui_scale.label, "settings.AccessibilitySettings.ui_scale");
+        assert_eq!(ui_scale.control, SettingControl::Slider { min: 0.5, max: 3.0, step: 0.025 });
+        assert_eq!(section.item("subtitles_enabled").unwrap().control, SettingControl::Toggle);
+        let filter = section.item("colorblind_filter").unwrap();
+        assert_eq!(filter.value, Value::Text(String::from("None")));
+        match filter.control {
+            SettingControl::Dropdown(ref options) => assert_eq!(options.len(), 4),
+            ref other => panic!("Unexpected control {:?}", other),
+        }
+        assert!(menu.section("VideoSettings").unwrap().item("hdr_output").is_some());
+
+        let stored = menu.set(&registry, &mut accessibility, "colorblind_filter", Value::Text(String::from("Tritanopia"))).unwrap();
+        assert_eq!(stored, Value::Text(String::from("Tritanopia")));
+        assert_eq!(accessibility.colorblind_filter, ColorblindFilter::Tritanopia);
+        assert!(menu.set(&registry, &mut accessibility, "colorblind_filter", Value::Text(String::from("Sepia"))).is_err());
+        menu.set(&registry, &mut accessibility, "ui_scale", Value::Float(10.0)).unwrap();
+        assert_eq!(accessibility.ui_scale, 3.0);
+        assert_eq!(menu.section("AccessibilitySettings").unwrap().item("ui_scale").unwrap().value, Value::Float(3.0));
+    }
+}
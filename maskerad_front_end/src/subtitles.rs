@@ -0,0 +1,307 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SUBTITLES AND CLOSED CAPTIONS.
+
+ A subtitle track is a JSON file listing timed lines. Lines store localization ids, not text,
+ so the same track works for every language:
+
+ {
+    "lines": [
+        { "speaker": "npc_blacksmith", "text": "greeting", "start": 0.0, "duration": 2.5 },
+        { "text": "sfx_door_creaks", "start": 2.0, "duration": 1.0, "kind": "SoundDescription" }
+    ]
+ }
+
+ Tracks are started by audio events (bind_audio_event) or by the cutscene timelines (play_track).
+ Each frame, the UI asks the CaptionService which captions are visible, already translated.
+*/
+
+use std::collections::HashMap;
+use std::io::Read;
+use serde_json;
+use maskerad_core::localization::localization::Localization;
+use maskerad_core::engine_configuration::accessibility::AccessibilitySettings;
+use front_end_error::{FrontEndError, FrontEndResult};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum SubtitleKind {
+    Dialogue,
+    //[door creaks], only displayed when closed captions are enabled.
+    SoundDescription,
+}
+
+impl Default for SubtitleKind {
+    fn default() -> Self {
+        SubtitleKind::Dialogue
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubtitleLine {
+    #[serde(default)]
+    speaker: Option<String>,
+    text: String,
+    start: f64,
+    duration: f64,
+    #[serde(default)]
+    kind: SubtitleKind,
+}
+
+impl SubtitleLine {
+    pub fn new<S: Into<String>>(text: S, start: f64, duration: f64) -> Self {
+        SubtitleLine {
+            speaker: None,
+            text: text.into(),
+            start,
+            duration,
+            kind: SubtitleKind::Dialogue,
+        }
+    }
+
+    pub fn with_speaker<S: Into<String>>(mut self, speaker: S) -> Self {
+        self.speaker = Some(speaker.into());
+        self
+    }
+
+    pub fn with_kind(mut self, kind: SubtitleKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    fn is_visible_at(&self, time: f64) -> bool {
+        time >= self.start && time < self.start + self.duration
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SubtitleTrack {
+    lines: Vec<SubtitleLine>,
+}
+
+impl SubtitleTrack {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> FrontEndResult<Self> {
+        debug!("Deserializing a subtitle track.");
+        serde_json::from_reader(reader).map_err(|json_error| {
+            FrontEndError::from(json_error)
+        })
+    }
+
+    pub fn with_line(mut self, line: SubtitleLine) -> Self {
+        self.lines.push(line);
+        self
+    }
+
+    pub fn lines(&self) -> &[SubtitleLine] {
+        self.lines.as_slice()
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.lines.iter().fold(0.0, |duration, line| duration.max(line.start + line.duration))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CaptionSettings {
+    pub subtitles_enabled: bool,
+    pub closed_captions_enabled: bool,
+    pub show_speaker_labels: bool,
+    //Multiplier applied to the default font size of the captions.
+    pub text_scale: f32,
+}
+
+impl Default for CaptionSettings {
+    fn default() -> Self {
+        CaptionSettings {
+            subtitles_enabled: true,
+            closed_captions_enabled: false,
+            show_speaker_labels: true,
+            text_scale: 1.0,
+        }
+    }
+}
+
+impl CaptionSettings {
+    //The subtitle options of the accessibility settings.
+    pub fn from_accessibility(accessibility: &AccessibilitySettings) -> Self {
+        CaptionSettings {
+            subtitles_enabled: accessibility.subtitles_enabled,
+            closed_captions_enabled: accessibility.closed_captions_enabled,
+            text_scale: accessibility.subtitle_scale,
+            .. CaptionSettings::default()
+        }
+    }
+}
+
+//A translated caption, ready to be drawn by the UI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caption {
+    pub speaker: Option<String>,
+    pub text: String,
+    pub kind: SubtitleKind,
+    pub text_scale: f32,
+}
+
+pub struct CaptionService {
+    settings: CaptionSettings,
+    event_tracks: HashMap<String, SubtitleTrack>,
+    //The tracks being played, with the time elapsed since they started.
+    playing: Vec<(SubtitleTrack, f64)>,
+}
+
+impl Default for CaptionService {
+    fn default() -> Self {
+        CaptionService {
+            settings: CaptionSettings::default(),
+            event_tracks: HashMap::new(),
+            playing: Vec::new(),
+        }
+    }
+}
+
+impl CaptionService {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn settings(&self) -> &CaptionSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: CaptionSettings) {
+        debug!("Updating the caption settings: {:?}", settings);
+        self.settings = settings;
+    }
+
+    //Play this track each time the audio event is posted.
+    pub fn bind_audio_event<S>(&mut self, event_name: S, track: SubtitleTrack) where
+        S: Into<String>
+    {
+        self.event_tracks.insert(event_name.into(), track);
+    }
+
+    pub fn on_audio_event<S>(&mut self, event_name: S) where
+        S: AsRef<str>
+    {
+        let track = match self.event_tracks.get(event_name.as_ref()) {
+            Some(track) => track.clone(),
+            None => return,
+        };
+        trace!("Starting the subtitle track bound to the audio event {}.", event_name.as_ref());
+        self.play_track(track);
+    }
+
+    pub fn play_track(&mut self, track: SubtitleTrack) {
+        self.playing.push((track, 0.0));
+    }
+
+    pub fn stop_all(&mut self) {
+        self.playing.clear();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        !self.playing.is_empty()
+    }
+
+    pub fn update(&mut self, delta_seconds: f64) {
+        for &mut (_, ref mut time) in self.playing.iter_mut() {
+            *time += delta_seconds;
+        }
+        self.playing.retain(|&(ref track, time)| time < track.duration());
+    }
+
+    fn translate(localization: &Localization, id: &str) -> String {
+        match localization.get(id) {
+            Some(text) => text.to_string(),
+            None => {
+                warn!("The localization id {} used by a subtitle could not be found.", id);
+                id.to_string()
+            },
+        }
+    }
+
+    pub fn visible_captions(&self, localization: &Localization) -> Vec<Caption> {
+        let mut captions = Vec::new();
+        if !self.settings.subtitles_enabled {
+            return captions;
+        }
+
+        for &(ref track, time) in self.playing.iter() {
+            for line in track.lines.iter().filter(|line| line.is_visible_at(time)) {
+                if line.kind == SubtitleKind::SoundDescription && !self.settings.closed_captions_enabled {
+                    continue;
+                }
+
+                let speaker = if self.settings.show_speaker_labels {
+                    line.speaker.as_ref().map(|speaker| CaptionService::translate(localization, speaker.as_str()))
+                } else {
+                    None
+                };
+
+                captions.push(Caption {
+                    speaker,
+                    text: CaptionService::translate(localization, line.text.as_str()),
+                    kind: line.kind,
+                    text_scale: self.settings.text_scale,
+                });
+            }
+        }
+        captions
+    }
+}
+
+#[cfg(test)]
+mod subtitles_test {
+    use super::*;
+
+    const TRACK: &'static str = r#"{
+        "lines": [
+            { "speaker": "blacksmith", "text": "greeting", "start": 0.0, "duration": 2.0 },
+            { "text": "door", "start": 1.0, "duration": 2.0, "kind": "SoundDescription" }
+        ]
+    }"#;
+
+    const LOCALIZATION: &'static str = r#"{ "blacksmith": "Blacksmith", "greeting": "Hello", "door": "[door creaks]" }"#;
+
+    #[test]
+    fn subtitles_follow_audio_events() {
+        let localization = Localization::from_reader(LOCALIZATION.as_bytes()).unwrap();
+        let track = SubtitleTrack::from_reader(TRACK.as_bytes()).unwrap();
+        assert_eq!(track.duration(), 3.0);
+
+        let mut captions = CaptionService::new();
+        captions.bind_audio_event("blacksmith_greeting", track);
+        assert!(captions.visible_captions(&localization).is_empty());
+
+        captions.on_audio_event("blacksmith_greeting");
+        captions.update(1.5);
+        let visible = captions.visible_captions(&localization);
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].speaker, Some(String::from("Blacksmith")));
+        assert_eq!(visible[0].text, "Hello");
+
+        captions.set_settings(CaptionSettings {
+            closed_captions_enabled: true,
+            show_speaker_labels: false,
+            text_scale: 1.5,
+            .. CaptionSettings::default()
+        });
+        let visible = captions.visible_captions(&localization);
+        assert_eq!(visible.len(), 2);
+        assert_eq!(visible[0].speaker, None);
+        assert_eq!(visible[1].text, "[door creaks]");
+        assert_eq!(visible[1].text_scale, 1.5);
+
+        captions.update(2.0);
+        assert!(!captions.is_playing());
+    }
+}
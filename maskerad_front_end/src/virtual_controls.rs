@@ -0,0 +1,266 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ VIRTUAL CONTROLS.
+
+ On the mobile targets, the gamepad is drawn on the screen:
+ - A virtual joystick lives in a zone of the screen (usually the lower left). A touch beginning in the
+   zone places the joystick under the finger, the knob follows the finger within the radius of the
+   joystick. Its value is in [-1, 1] on both axes, with a dead zone.
+ - A virtual button is a circle bound to an action, pressed while a finger is on it.
+
+ The controls take the touches beginning on them, the other touches go to the gesture recognizer.
+ Each frame, the UI draws the quads given by draw_list.
+*/
+
+use std::collections::HashMap;
+use maskerad_inputs::touch::{TouchEvent, TouchPhase};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn contains(&self, point: (f32, f32)) -> bool {
+        point.0 >= self.x && point.0 < self.x + self.width && point.1 >= self.y && point.1 < self.y + self.height
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VirtualJoystick {
+    pub name: String,
+    pub zone: Rect,
+    pub radius: f32,
+    //In [0, 1], a fraction of the radius.
+    pub dead_zone: f32,
+    //Where the joystick rests without touch.
+    pub rest_center: (f32, f32),
+    center: (f32, f32),
+    knob: (f32, f32),
+    pointer: Option<u64>,
+}
+
+impl VirtualJoystick {
+    pub fn new<S: Into<String>>(name: S, zone: Rect, radius: f32) -> Self {
+        let rest_center = (zone.x + zone.width / 2.0, zone.y + zone.height / 2.0);
+        VirtualJoystick {
+            name: name.into(),
+            zone,
+            radius,
+            dead_zone: 0.15,
+            rest_center,
+            center: rest_center,
+            knob: rest_center,
+            pointer: None,
+        }
+    }
+
+    fn move_knob(&mut self, position: (f32, f32)) {
+        let (dx, dy) = (position.0 - self.center.0, position.1 - self.center.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        self.knob = if length > self.radius {
+            (self.center.0 + dx / length * self.radius, self.center.1 + dy / length * self.radius)
+        } else {
+            position
+        };
+    }
+
+    //Up is positive, like the sticks of the gamepads.
+    pub fn value(&self) -> (f32, f32) {
+        let (x, y) = ((self.knob.0 - self.center.0) / self.radius, (self.center.1 - self.knob.1) / self.radius);
+        let length = (x * x + y * y).sqrt();
+        if length <= self.dead_zone {
+            return (0.0, 0.0);
+        }
+        let scale = (length - self.dead_zone) / (1.0 - self.dead_zone) / length;
+        (x * scale, y * scale)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VirtualButton {
+    pub action: String,
+    pub center: (f32, f32),
+    pub radius: f32,
+    pointer: Option<u64>,
+}
+
+impl VirtualButton {
+    pub fn new<S: Into<String>>(action: S, center: (f32, f32), radius: f32) -> Self {
+        VirtualButton {
+            action: action.into(),
+            center,
+            radius,
+            pointer: None,
+        }
+    }
+
+    fn contains(&self, point: (f32, f32)) -> bool {
+        let (dx, dy) = (point.0 - self.center.0, point.1 - self.center.1);
+        dx * dx + dy * dy <= self.radius * self.radius
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pointer.is_some()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ControlQuadKind {
+    JoystickBase,
+    JoystickKnob,
+    Button,
+    ButtonPressed,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ControlQuad {
+    pub kind: ControlQuadKind,
+    pub center: (f32, f32),
+    pub radius: f32,
+    pub opacity: f32,
+}
+
+pub struct VirtualControls {
+    joysticks: Vec<VirtualJoystick>,
+    buttons: Vec<VirtualButton>,
+    pub visible: bool,
+    //The opacity of the controls without touch.
+    pub idle_opacity: f32,
+}
+
+impl VirtualControls {
+    pub fn new() -> Self {
+        VirtualControls {
+            joysticks: Vec::new(),
+            buttons: Vec::new(),
+            visible: true,
+            idle_opacity: 0.4,
+        }
+    }
+
+    pub fn with_joystick(mut self, joystick: VirtualJoystick) -> Self {
+        self.joysticks.push(joystick);
+        self
+    }
+
+    pub fn with_button(mut self, button: VirtualButton) -> Self {
+        self.buttons.push(button);
+        self
+    }
+
+    //True if the touch was taken by a control.
+    pub fn handle(&mut self, event: TouchEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+        let position = (event.x, event.y);
+        let pointer = Some(event.pointer_id);
+        match event.phase {
+            TouchPhase::Began => {
+                if let Some(button) = self.buttons.iter_mut().find(|button| button.pointer.is_none() && button.contains(position)) {
+                    button.pointer = pointer;
+                    return true;
+                }
+                if let Some(joystick) = self.joysticks.iter_mut().find(|joystick| joystick.pointer.is_none() && joystick.zone.contains(position)) {
+                    joystick.pointer = pointer;
+                    joystick.center = position;
+                    joystick.knob = position;
+                    return true;
+                }
+                false
+            },
+            TouchPhase::Moved => {
+                match self.joysticks.iter_mut().find(|joystick| joystick.pointer == pointer) {
+                    Some(joystick) => {
+                        joystick.move_knob(position);
+                        true
+                    },
+                    None => self.buttons.iter().any(|button| button.pointer == pointer),
+                }
+            },
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                let mut taken = false;
+                for joystick in self.joysticks.iter_mut().filter(|joystick| joystick.pointer == pointer) {
+                    joystick.pointer = None;
+                    joystick.center = joystick.rest_center;
+                    joystick.knob = joystick.rest_center;
+                    taken = true;
+                }
+                for button in self.buttons.iter_mut().filter(|button| button.pointer == pointer) {
+                    button.pointer = None;
+                    taken = true;
+                }
+                taken
+            },
+        }
+    }
+
+    pub fn joystick(&self, name: &str) -> (f32, f32) {
+        self.joysticks.iter().find(|joystick| joystick.name == name).map(|joystick| joystick.value()).unwrap_or((0.0, 0.0))
+    }
+
+    pub fn pressed_actions(&self) -> HashMap<&str, bool> {
+        self.buttons.iter().map(|button| (button.action.as_str(), button.is_pressed())).collect()
+    }
+
+    pub fn draw_list(&self) -> Vec<ControlQuad> {
+        if !self.visible {
+            return Vec::new();
+        }
+        let mut quads = Vec::new();
+        for joystick in self.joysticks.iter() {
+            let opacity = if joystick.pointer.is_some() {1.0} else {self.idle_opacity};
+            quads.push(ControlQuad { kind: ControlQuadKind::JoystickBase, center: joystick.center, radius: joystick.radius, opacity });
+            quads.push(ControlQuad { kind: ControlQuadKind::JoystickKnob, center: joystick.knob, radius: joystick.radius * 0.4, opacity });
+        }
+        for button in self.buttons.iter() {
+            let (kind, opacity) = if button.is_pressed() {(ControlQuadKind::ButtonPressed, 1.0)} else {(ControlQuadKind::Button, self.idle_opacity)};
+            quads.push(ControlQuad { kind, center: button.center, radius: button.radius, opacity });
+        }
+        quads
+    }
+}
+
+#[cfg(test)]
+mod virtual_controls_test {
+    use super::*;
+
+    fn touch(pointer_id: u64, phase: TouchPhase, x: f32, y: f32) -> TouchEvent {
+        TouchEvent { pointer_id, phase, x, y }
+    }
+
+    #[test]
+    fn virtual_joystick_and_buttons() {
+        let zone = Rect { x: 0.0, y: 500.0, width: 500.0, height: 500.0 };
+        let mut controls = VirtualControls::new()
+            .with_joystick(VirtualJoystick::new("move", zone, 100.0))
+            .with_button(VirtualButton::new("jump", (900.0, 900.0), 50.0));
+
+        assert!(controls.handle(touch(0, TouchPhase::Began, 200.0, 800.0)));
+        assert!(controls.handle(touch(0, TouchPhase::Moved, 400.0, 800.0)));
+        assert_eq!(controls.joystick("move"), (1.0, 0.0));
+        controls.handle(touch(0, TouchPhase::Moved, 200.0, 790.0));
+        assert_eq!(controls.joystick("move"), (0.0, 0.0));
+
+        assert!(controls.handle(touch(1, TouchPhase::Began, 910.0, 890.0)));
+        assert_eq!(controls.pressed_actions()["jump"], true);
+        assert!(!controls.handle(touch(2, TouchPhase::Began, 700.0, 100.0)));
+        assert_eq!(controls.draw_list()[0], ControlQuad { kind: ControlQuadKind::JoystickBase, center: (200.0, 800.0), radius: 100.0, opacity: 1.0 });
+        assert_eq!(controls.draw_list()[2].kind, ControlQuadKind::ButtonPressed);
+
+        assert!(controls.handle(touch(0, TouchPhase::Ended, 200.0, 790.0)));
+        assert!(controls.handle(touch(1, TouchPhase::Ended, 910.0, 890.0)));
+        assert_eq!(controls.pressed_actions()["jump"], false);
+        assert_eq!(controls.draw_list()[0].center, (250.0, 750.0));
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ DEFERRED COMMANDS.
+
+ The systems running in parallel only borrow the world: they queue their structural changes (spawns,
+ despawns, added and removed components) in their own Commands, and the queues are applied at the
+ sync points of the frame, in the order of the systems, with World::apply.
+
+ A spawned entity can be used in the next commands of the queue: its index is reserved at once, with
+ an atomic counter shared with the world, and the entity exists when the queue is applied.
+*/
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use ecs::entity::Entity;
+use ecs::world::World;
+
+pub type Command = Box<FnOnce(&mut World) + Send>;
+
+pub struct Commands {
+    //The next index of the world without a slot.
+    reserved: Arc<AtomicUsize>,
+    queue: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new(reserved: Arc<AtomicUsize>) -> Self {
+        Commands {
+            reserved,
+            queue: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn add<F: FnOnce(&mut World) + Send + 'static>(&mut self, command: F) {
+        self.queue.push(Box::new(command));
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        Entity::new(self.reserved.fetch_add(1, Ordering::SeqCst) as u32, 0)
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.despawn(entity);
+        });
+    }
+
+    pub fn insert<T: Send + 'static>(&mut self, entity: Entity, component: T) {
+        self.add(move |world| {
+            world.insert(entity, component);
+        });
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.remove::<T>(entity);
+        });
+    }
+
+    pub fn set_parent(&mut self, entity: Entity, parent: Option<Entity>) {
+        self.add(move |world| {
+            world.set_parent(entity, parent);
+        });
+    }
+
+    pub fn set_name(&mut self, entity: Entity, name: &str) {
+        let name = String::from(name);
+        self.add(move |world| world.set_name(entity, name.as_str()));
+    }
+
+    pub fn add_tag(&mut self, entity: Entity, tag: &str) {
+        let tag = String::from(tag);
+        self.add(move |world| {
+            world.add_tag(entity, tag.as_str());
+        });
+    }
+
+    pub fn drain(&mut self) -> Vec<Command> {
+        ::std::mem::take(&mut self.queue)
+    }
+}
+
+#[cfg(test)]
+mod commands_test {
+    use std::thread;
+    use ecs::world::World;
+
+    #[derive(Debug, PartialEq)]
+    struct Projectile(u32);
+
+    #[test]
+    fn commands_from_parallel_systems() {
+        let mut world = World::new();
+        let target = world.spawn();
+        world.insert(target, Projectile(0));
+
+        //Two systems, on two threads.
+        let handles: Vec<_> = (1..3).map(|system| {
+            let mut commands = world.commands();
+            thread::spawn(move || {
+                let projectile = commands.spawn();
+                commands.insert(projectile, Projectile(system));
+                commands.set_name(projectile, "Projectile");
+                commands.add_tag(projectile, "hot");
+                commands
+            })
+        }).collect();
+        let mut queues: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(world.len(), 1);
+
+        for commands in queues.iter_mut() {
+            world.apply(commands);
+            assert!(commands.is_empty());
+        }
+        assert_eq!(world.len(), 3);
+        assert_eq!(world.find_all_by_name("Projectile").len(), 2);
+        let mut values: Vec<u32> = world.query::<Projectile>().iter().map(|&(_, projectile)| projectile.0).collect();
+        values.sort();
+        assert_eq!(values, vec![0, 1, 2]);
+
+        //The reserved entities and the spawned ones don't collide.
+        let mut commands = world.commands();
+        let reserved = commands.spawn();
+        let spawned = world.spawn();
+        assert_ne!(reserved, spawned);
+        commands.remove::<Projectile>(target);
+        commands.despawn(world.find_by_tag("hot")[0]);
+        world.apply(&mut commands);
+        assert!(world.is_alive(reserved));
+        assert!(!world.has::<Projectile>(target));
+        assert_eq!(world.find_by_tag("hot").len(), 1);
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ COMPONENT HOOKS.
+
+ The systems which mirror the components in another structure (a body in the physics world, a proxy
+ on the GPU...) register hooks on the component type, instead of diffing the world each frame:
+ - on_add is called after a component is added,
+ - on_remove is called before a component is removed, when the entity is despawned too.
+ Replacing a component calls on_remove with the old one, then on_add with the new one.
+
+ The hooks don't have access to the world: they capture what they update.
+*/
+
+use std::any::Any;
+use ecs::entity::Entity;
+
+pub type Hook<T> = Box<FnMut(Entity, &T)>;
+
+pub struct Hooks<T> {
+    on_add: Vec<Hook<T>>,
+    on_remove: Vec<Hook<T>>,
+}
+
+impl<T> Default for Hooks<T> {
+    fn default() -> Self {
+        Hooks {
+            on_add: Vec::new(),
+            on_remove: Vec::new(),
+        }
+    }
+}
+
+impl<T> Hooks<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_on_add(&mut self, hook: Hook<T>) {
+        self.on_add.push(hook);
+    }
+
+    pub fn add_on_remove(&mut self, hook: Hook<T>) {
+        self.on_remove.push(hook);
+    }
+
+    pub fn added(&mut self, entity: Entity, component: &T) {
+        for hook in self.on_add.iter_mut() {
+            hook(entity, component);
+        }
+    }
+
+    pub fn removed(&mut self, entity: Entity, component: &T) {
+        for hook in self.on_remove.iter_mut() {
+            hook(entity, component);
+        }
+    }
+}
+
+//The hooks of all the types, in the world. The component given to removed is of the type of the hooks.
+pub trait AnyHooks {
+    fn removed(&mut self, entity: Entity, component: &Any);
+    fn as_any_mut(&mut self) -> &mut Any;
+}
+
+impl<T: 'static> AnyHooks for Hooks<T> {
+    fn removed(&mut self, entity: Entity, component: &Any) {
+        if let Some(component) = component.downcast_ref::<T>() {
+            Hooks::removed(self, entity, component);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod hooks_test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use ecs::world::World;
+    use ecs::entity::Entity;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct RigidBody(f32);
+
+    #[test]
+    fn hooks_mirror_the_components() {
+        //A physics world with the bodies of the entities.
+        let bodies: Rc<RefCell<Vec<(Entity, f32)>>> = Rc::new(RefCell::new(Vec::new()));
+        let mut world = World::new();
+        let added = bodies.clone();
+        world.on_add::<RigidBody, _>(move |entity, body| added.borrow_mut().push((entity, body.0)));
+        let removed = bodies.clone();
+        world.on_remove::<RigidBody, _>(move |entity, _| removed.borrow_mut().retain(|&(known, _)| known != entity));
+
+        let parent = world.spawn();
+        let child = world.spawn();
+        world.set_parent(child, Some(parent));
+        world.insert(parent, RigidBody(1.0));
+        world.insert(child, RigidBody(2.0));
+        assert_eq!(*bodies.borrow(), vec![(parent, 1.0), (child, 2.0)]);
+
+        world.insert(parent, RigidBody(3.0));
+        assert_eq!(*bodies.borrow(), vec![(child, 2.0), (parent, 3.0)]);
+        assert_eq!(world.remove::<RigidBody>(child), Some(RigidBody(2.0)));
+        assert_eq!(*bodies.borrow(), vec![(parent, 3.0)]);
+        world.insert(child, RigidBody(4.0));
+
+        //Despawning calls on_remove for the entity and its children.
+        world.despawn(parent);
+        assert!(bodies.borrow().is_empty());
+
+        world.clear_hooks::<RigidBody>();
+        let other = world.spawn();
+        world.insert(other, RigidBody(5.0));
+        assert!(bodies.borrow().is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ MIGRATION BETWEEN WORLDS.
+
+ An entity and its descendants can be moved to another world (the player from the loading world to
+ the level, a character from a server room to another). The entities get new ids in the target world,
+ the components are moved, and the hierarchy is rebuilt.
+
+ The components referencing entities (a target, an owner...) implement MapEntities and are registered
+ with World::register_entity_references: after the move, their references to the moved entities are
+ replaced by the new ids. The references to the entities which stayed in the source world are left
+ to the component, which is given None for them.
+*/
+
+use std::collections::HashMap;
+use ecs::entity::Entity;
+use ecs::world::World;
+
+//The new id of each moved entity.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EntityMap(HashMap<Entity, Entity>);
+
+impl EntityMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, old: Entity, new: Entity) {
+        self.0.insert(old, new);
+    }
+
+    pub fn get(&self, old: Entity) -> Option<Entity> {
+        self.0.get(&old).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+pub trait MapEntities {
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+//Registered in the worlds, for each type implementing MapEntities.
+pub type EntityMapper = fn(&mut World, Entity, &EntityMap);
+
+pub fn map_component<T: MapEntities + 'static>(world: &mut World, entity: Entity, map: &EntityMap) {
+    if let Some(component) = world.get_mut::<T>(entity) {
+        component.map_entities(map);
+    }
+}
+
+#[cfg(test)]
+mod migration_test {
+    use super::*;
+    use ecs::naming::Tags;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Target(Option<Entity>);
+
+    impl MapEntities for Target {
+        fn map_entities(&mut self, map: &EntityMap) {
+            self.0 = self.0.and_then(|entity| map.get(entity));
+        }
+    }
+
+    #[test]
+    fn migrate_a_hierarchy() {
+        let mut lobby = World::new();
+        lobby.register_entity_references::<Target>();
+        let bystander = lobby.spawn();
+        let player = lobby.spawn();
+        lobby.set_name(player, "Player");
+        lobby.insert(player, Health(100));
+        let weapon = lobby.spawn();
+        lobby.set_parent(weapon, Some(player));
+        lobby.insert(weapon, Target(Some(player)));
+        lobby.insert(weapon, Tags::new(&["weapon"]));
+        let scope = lobby.spawn();
+        lobby.set_parent(scope, Some(weapon));
+        lobby.insert(scope, Target(Some(bystander)));
+
+        let mut level = World::new();
+        level.spawn();
+        let map = lobby.migrate(player, &mut level).unwrap();
+        assert_eq!(map.len(), 3);
+        assert_eq!(lobby.len(), 1);
+        assert!(lobby.find_by_name("Player").is_none());
+        assert!(lobby.query::<Target>().is_empty());
+
+        let new_player = level.find_by_name("Player").unwrap();
+        assert_eq!(Some(new_player), map.get(player));
+        assert_eq!(level.get::<Health>(new_player), Some(&Health(100)));
+        let new_weapon = level.children(new_player)[0];
+        assert_eq!(level.find_by_tag("weapon"), &[new_weapon]);
+        //The references follow the moved entities, the others are dropped.
+        assert_eq!(level.get::<Target>(new_weapon), Some(&Target(Some(new_player))));
+        let new_scope = level.children(new_weapon)[0];
+        assert_eq!(level.get::<Target>(new_scope), Some(&Target(None)));
+
+        //The registration moved with the components: the hierarchy can go back.
+        let back = level.migrate(new_player, &mut lobby).unwrap();
+        let weapon = back.get(new_weapon).unwrap();
+        assert_eq!(lobby.get::<Target>(weapon), Some(&Target(back.get(new_player))));
+        assert!(lobby.migrate(player, &mut level).is_none());
+    }
+}
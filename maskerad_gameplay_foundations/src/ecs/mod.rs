@@ -0,0 +1,41 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ ENTITY COMPONENT SYSTEM.
+
+ An entity is an index and a generation: when an entity is despawned, its index is reused with the
+ next generation, so an old Entity never designates a new entity.
+
+ The components of a type are packed in a storage (a sparse set): the components are contiguous,
+ and adding or removing a component doesn't move the components of the other types.
+
+ The entities form a hierarchy: despawning an entity despawns its children.
+
+ The systems are told when a component is added or removed by hooks on its type. The systems running
+ in parallel queue their structural changes in Commands.
+
+ Several worlds can run side by side, each with its schedule, and the entities can move from a
+ world to another.
+*/
+
+pub mod entity;
+pub mod storage;
+pub mod hooks;
+pub mod commands;
+pub mod stats;
+pub mod migration;
+pub mod snapshot;
+pub mod schedule;
+pub mod naming;
+pub mod world;
+
+pub use ecs::commands::Commands;
+pub use ecs::entity::Entity;
+pub use ecs::naming::Tags;
+pub use ecs::schedule::{Schedule, System, Worlds};
+pub use ecs::world::World;
@@ -0,0 +1,121 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ NAMES AND TAGS.
+
+ Two optional components to find the entities in the console, the inspector and the scripts:
+ - a Name ("Player", "Door_03"), several entities can have the same name,
+ - Tags ("enemy", "interactive"...).
+
+ The world indexes them: find_by_name and the tag queries don't iterate over the entities.
+ The components are modified with World::insert, add_tag and remove_tag, which update the index.
+*/
+
+use std::slice::Iter;
+use maskerad_core::name::{Name, NameMap};
+use ecs::entity::Entity;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tags(Vec<Name>);
+
+impl Tags {
+    pub fn new(tags: &[&str]) -> Self {
+        let mut set = Tags::default();
+        for tag in tags.iter() {
+            set.insert(Name::new(tag));
+        }
+        set
+    }
+
+    pub fn contains(&self, tag: &str) -> bool {
+        self.0.contains(&Name::lookup(tag))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter<'a>(&'a self) -> Iter<'a, Name> {
+        self.0.iter()
+    }
+
+    pub(crate) fn insert(&mut self, tag: Name) -> bool {
+        if self.0.contains(&tag) {
+            return false;
+        }
+        self.0.push(tag);
+        true
+    }
+
+    pub(crate) fn remove(&mut self, tag: Name) -> bool {
+        let count = self.0.len();
+        self.0.retain(|known| *known != tag);
+        self.0.len() != count
+    }
+}
+
+//The entities of each name and of each tag, in the order they were indexed.
+#[derive(Debug, Default)]
+pub struct NameIndex {
+    names: NameMap<Name, Vec<Entity>>,
+    tags: NameMap<Name, Vec<Entity>>,
+}
+
+fn add(map: &mut NameMap<Name, Vec<Entity>>, key: Name, entity: Entity) {
+    let entities = map.entry(key).or_default();
+    if !entities.contains(&entity) {
+        entities.push(entity);
+    }
+}
+
+fn remove(map: &mut NameMap<Name, Vec<Entity>>, key: Name, entity: Entity) {
+    let empty = match map.get_mut(&key) {
+        Some(entities) => {
+            entities.retain(|known| *known != entity);
+            entities.is_empty()
+        },
+        None => false,
+    };
+    if empty {
+        map.remove(&key);
+    }
+}
+
+impl NameIndex {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_name(&mut self, name: Name, entity: Entity) {
+        add(&mut self.names, name, entity);
+    }
+
+    pub fn remove_name(&mut self, name: Name, entity: Entity) {
+        remove(&mut self.names, name, entity);
+    }
+
+    pub fn add_tag(&mut self, tag: Name, entity: Entity) {
+        add(&mut self.tags, tag, entity);
+    }
+
+    pub fn remove_tag(&mut self, tag: Name, entity: Entity) {
+        remove(&mut self.tags, tag, entity);
+    }
+
+    pub fn named(&self, name: Name) -> &[Entity] {
+        self.names.get(&name).map(|entities| entities.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn tagged(&self, tag: Name) -> &[Entity] {
+        self.tags.get(&tag).map(|entities| entities.as_slice()).unwrap_or(&[])
+    }
+}
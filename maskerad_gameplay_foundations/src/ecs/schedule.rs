@@ -0,0 +1,245 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ WORLDS AND SCHEDULES.
+
+ A game can run several worlds at the same time: the 3D background of the main menu and the level
+ being loaded, or the rooms of a server. Each world has its own schedule, the systems it runs each
+ frame, and an inactive world is kept in memory without being updated.
+*/
+
+use ecs::entity::Entity;
+use ecs::migration::EntityMap;
+use ecs::world::World;
+
+pub trait System {
+    fn name(&self) -> &str;
+    fn run(&mut self, world: &mut World);
+}
+
+pub struct FnSystem<F: FnMut(&mut World)> {
+    name: String,
+    function: F,
+}
+
+impl<F: FnMut(&mut World)> FnSystem<F> {
+    pub fn new(name: &str, function: F) -> Self {
+        FnSystem {
+            name: String::from(name),
+            function,
+        }
+    }
+}
+
+impl<F: FnMut(&mut World)> System for FnSystem<F> {
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    fn run(&mut self, world: &mut World) {
+        (self.function)(world)
+    }
+}
+
+//The systems of a world, run in the order they were added.
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<Box<System>>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_system(&mut self, system: Box<System>) {
+        self.systems.push(system);
+    }
+
+    pub fn remove_system(&mut self, name: &str) -> bool {
+        let count = self.systems.len();
+        self.systems.retain(|system| system.name() != name);
+        self.systems.len() != count
+    }
+
+    pub fn system_names(&self) -> Vec<&str> {
+        self.systems.iter().map(|system| system.name()).collect()
+    }
+
+    pub fn run(&mut self, world: &mut World) {
+        for system in self.systems.iter_mut() {
+            system.run(world);
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct WorldId(u32);
+
+struct WorldEntry {
+    id: WorldId,
+    name: String,
+    active: bool,
+    world: World,
+    schedule: Schedule,
+}
+
+#[derive(Default)]
+pub struct Worlds {
+    entries: Vec<WorldEntry>,
+    next_id: u32,
+}
+
+impl Worlds {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn position(&self, id: WorldId) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.id == id)
+    }
+
+    pub fn create(&mut self, name: &str) -> WorldId {
+        self.add(name, World::new())
+    }
+
+    pub fn add(&mut self, name: &str, world: World) -> WorldId {
+        let id = WorldId(self.next_id);
+        self.next_id += 1;
+        debug!("Adding the world {}.", name);
+        self.entries.push(WorldEntry {
+            id,
+            name: String::from(name),
+            active: true,
+            world,
+            schedule: Schedule::new(),
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: WorldId) -> Option<World> {
+        let position = self.position(id)?;
+        debug!("Removing the world {}.", self.entries[position].name);
+        Some(self.entries.remove(position).world)
+    }
+
+    pub fn find(&self, name: &str) -> Option<WorldId> {
+        self.entries.iter().find(|entry| entry.name == name).map(|entry| entry.id)
+    }
+
+    pub fn world(&self, id: WorldId) -> Option<&World> {
+        self.position(id).map(move |position| &self.entries[position].world)
+    }
+
+    pub fn world_mut(&mut self, id: WorldId) -> Option<&mut World> {
+        match self.position(id) {
+            Some(position) => Some(&mut self.entries[position].world),
+            None => None,
+        }
+    }
+
+    pub fn schedule_mut(&mut self, id: WorldId) -> Option<&mut Schedule> {
+        match self.position(id) {
+            Some(position) => Some(&mut self.entries[position].schedule),
+            None => None,
+        }
+    }
+
+    pub fn set_active(&mut self, id: WorldId, active: bool) -> bool {
+        match self.position(id) {
+            Some(position) => {
+                self.entries[position].active = active;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn is_active(&self, id: WorldId) -> bool {
+        self.position(id).map(|position| self.entries[position].active).unwrap_or(false)
+    }
+
+    //Runs the schedule of each active world, in the order the worlds were added.
+    pub fn run(&mut self) {
+        for entry in self.entries.iter_mut().filter(|entry| entry.active) {
+            entry.schedule.run(&mut entry.world);
+        }
+    }
+
+    //Moves the entity and its descendants from a world to another.
+    pub fn migrate(&mut self, entity: Entity, from: WorldId, to: WorldId) -> Option<EntityMap> {
+        let (source, target) = (self.position(from)?, self.position(to)?);
+        if source == target {
+            return None;
+        }
+        //Two mutable borrows of the entries.
+        let (first, second) = self.entries.split_at_mut(source.max(target));
+        let (source, target) = if source < target {
+            (&mut first[source], &mut second[0])
+        } else {
+            (&mut second[0], &mut first[target])
+        };
+        source.world.migrate(entity, &mut target.world)
+    }
+}
+
+#[cfg(test)]
+mod schedule_test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f32);
+
+    fn movement() -> Box<System> {
+        Box::new(FnSystem::new("movement", |world: &mut World| {
+            let entities: Vec<Entity> = world.query::<Position>().iter().map(|&(entity, _)| entity).collect();
+            for entity in entities {
+                world.get_mut::<Position>(entity).unwrap().0 += 1.0;
+            }
+        }))
+    }
+
+    #[test]
+    fn worlds_with_their_schedules() {
+        let mut worlds = Worlds::new();
+        let menu = worlds.create("menu");
+        let level = worlds.create("level");
+        worlds.schedule_mut(level).unwrap().add_system(movement());
+        assert_eq!(worlds.schedule_mut(level).unwrap().system_names(), vec!["movement"]);
+
+        let camera = {
+            let world = worlds.world_mut(menu).unwrap();
+            let camera = world.spawn();
+            world.insert(camera, Position(0.0));
+            camera
+        };
+        worlds.run();
+        assert_eq!(worlds.world(menu).unwrap().get::<Position>(camera), Some(&Position(0.0)));
+
+        let map = worlds.migrate(camera, menu, level).unwrap();
+        let moved = map.get(camera).unwrap();
+        worlds.run();
+        assert_eq!(worlds.world(level).unwrap().get::<Position>(moved), Some(&Position(1.0)));
+
+        worlds.set_active(level, false);
+        worlds.run();
+        assert_eq!(worlds.world(level).unwrap().get::<Position>(moved), Some(&Position(1.0)));
+        assert!(worlds.migrate(moved, level, level).is_none());
+        assert_eq!(worlds.find("level"), Some(level));
+        assert!(worlds.remove(menu).unwrap().is_empty());
+        assert_eq!(worlds.len(), 1);
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ WORLD SNAPSHOTS.
+
+ The saves of the world. The component types to save are registered with a name in a SaveRegistry,
+ the other components are not saved.
+
+ A full snapshot holds every entity and every saved component. An incremental snapshot only holds
+ what changed after the previous snapshot, using the change ticks of the world:
+ - the entities spawned or moved in the hierarchy,
+ - the components added or borrowed mutably (get_mut marks a component as changed, even if it is not
+   modified in the end),
+ - the components removed and the entities despawned.
+ A large open world where the player only changed a few things is saved in a few kilobytes.
+
+ A save is a full snapshot followed by incremental ones, restored in order in an empty world.
+ IncrementalSaver takes a full snapshot from time to time, so the chain doesn't grow forever.
+
+ The world only records the removals after the first snapshot. A game which stops saving calls
+ World::track_removals(false), so they don't pile up.
+*/
+
+use std::any::TypeId;
+use std::io::{Read, Write};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use ecs::entity::Entity;
+use ecs::world::{Removal, World};
+use gameplay_error::{GameplayError, GameplayResult};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentRecord {
+    pub component: String,
+    pub entity: u64,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    //The tick of the previous snapshot, None for a full snapshot.
+    pub base_tick: Option<u64>,
+    pub tick: u64,
+    //The entities and their parent.
+    pub entities: Vec<(u64, Option<u64>)>,
+    pub components: Vec<ComponentRecord>,
+    pub removed_components: Vec<(String, u64)>,
+    pub despawned: Vec<u64>,
+}
+
+impl WorldSnapshot {
+    pub fn is_full(&self) -> bool {
+        self.base_tick.is_none()
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> GameplayResult<Self> {
+        debug!("Deserializing a world snapshot.");
+        serde_json::from_reader(reader).map_err(|json_error| {
+            GameplayError::from(json_error)
+        })
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> GameplayResult<()> {
+        debug!("Serializing a world snapshot of {} components.", self.components.len());
+        serde_json::to_writer(writer, self).map_err(|json_error| {
+            GameplayError::from(json_error)
+        })
+    }
+}
+
+type SaveFn = fn(&World, Option<u64>) -> GameplayResult<Vec<(Entity, Value)>>;
+
+struct SaveCodec {
+    name: String,
+    type_id: TypeId,
+    save: SaveFn,
+    load: fn(&mut World, Entity, Value) -> GameplayResult<()>,
+    remove: fn(&mut World, Entity),
+}
+
+fn save_components<T: Serialize + 'static>(world: &World, since: Option<u64>) -> GameplayResult<Vec<(Entity, Value)>> {
+    let storage = match world.storage::<T>() {
+        Some(storage) => storage,
+        None => return Ok(Vec::new()),
+    };
+    let components = match since {
+        Some(tick) => storage.changed_since(tick),
+        None => storage.entities().iter().cloned().zip(storage.components().iter()).collect(),
+    };
+    components.into_iter()
+        .map(|(entity, component)| serde_json::to_value(component).map(|value| (entity, value)).map_err(GameplayError::from))
+        .collect()
+}
+
+fn load_component<T: DeserializeOwned + 'static>(world: &mut World, entity: Entity, value: Value) -> GameplayResult<()> {
+    let component: T = serde_json::from_value(value)?;
+    world.insert(entity, component);
+    Ok(())
+}
+
+fn remove_component<T: 'static>(world: &mut World, entity: Entity) {
+    world.remove::<T>(entity);
+}
+
+#[derive(Default)]
+pub struct SaveRegistry {
+    codecs: Vec<SaveCodec>,
+}
+
+impl SaveRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    //The name is written in the saves: it must not change between two versions of the game.
+    pub fn register<T: Serialize + DeserializeOwned + 'static>(&mut self, name: &str) {
+        self.codecs.retain(|codec| codec.type_id != TypeId::of::<T>());
+        self.codecs.push(SaveCodec {
+            name: String::from(name),
+            type_id: TypeId::of::<T>(),
+            save: save_components::<T>,
+            load: load_component::<T>,
+            remove: remove_component::<T>,
+        });
+    }
+
+    fn codec(&self, name: &str) -> GameplayResult<&SaveCodec> {
+        self.codecs.iter().find(|codec| codec.name == name).ok_or_else(|| {
+            GameplayError::SaveError(format!("The component {} is not registered.", name))
+        })
+    }
+
+    //Everything after the since tick, or everything. The next changes are after the snapshot.
+    pub fn snapshot(&self, world: &mut World, since: Option<u64>) -> GameplayResult<WorldSnapshot> {
+        let mut snapshot = WorldSnapshot {
+            base_tick: since,
+            tick: world.change_tick(),
+            entities: world.entities_changed_since(since).into_iter()
+                .map(|(entity, parent)| (entity.to_bits(), parent.map(|parent| parent.to_bits())))
+                .collect(),
+            components: Vec::new(),
+            removed_components: Vec::new(),
+            despawned: Vec::new(),
+        };
+        for codec in self.codecs.iter() {
+            for (entity, value) in (codec.save)(world, since)? {
+                snapshot.components.push(ComponentRecord {
+                    component: codec.name.clone(),
+                    entity: entity.to_bits(),
+                    value,
+                });
+            }
+        }
+        if let Some(since) = since {
+            for removal in world.removals_since(since) {
+                match removal {
+                    Removal::Entity(entity) => snapshot.despawned.push(entity.to_bits()),
+                    Removal::Component(type_id, entity) => {
+                        if let Some(codec) = self.codecs.iter().find(|codec| codec.type_id == type_id) {
+                            snapshot.removed_components.push((codec.name.clone(), entity.to_bits()));
+                        }
+                    },
+                }
+            }
+        }
+        //The removals are recorded from the first snapshot on, for the incremental ones.
+        world.forget_removals(snapshot.tick);
+        world.track_removals(true);
+        world.increment_change_tick();
+        debug!("World snapshot: {} entities, {} components, {} removals.",
+            snapshot.entities.len(), snapshot.components.len(), snapshot.removed_components.len() + snapshot.despawned.len());
+        Ok(snapshot)
+    }
+
+    //A full snapshot is restored in an empty world, then the incremental ones, in order.
+    pub fn restore(&self, world: &mut World, snapshot: &WorldSnapshot) -> GameplayResult<()> {
+        if snapshot.is_full() && !world.is_empty() {
+            return Err(GameplayError::SaveError(String::from("A full snapshot must be restored in an empty world.")));
+        }
+        for entity in snapshot.despawned.iter() {
+            world.despawn(Entity::from_bits(*entity));
+        }
+        for &(entity, _) in snapshot.entities.iter() {
+            let entity = Entity::from_bits(entity);
+            if !world.is_alive(entity) && !world.spawn_at(entity) {
+                return Err(GameplayError::SaveError(format!("The entity {} cannot be restored, its index is used.", entity)));
+            }
+        }
+        for &(entity, parent) in snapshot.entities.iter() {
+            world.set_parent(Entity::from_bits(entity), parent.map(Entity::from_bits));
+        }
+        for &(ref component, entity) in snapshot.removed_components.iter() {
+            (self.codec(component.as_str())?.remove)(world, Entity::from_bits(entity));
+        }
+        for record in snapshot.components.iter() {
+            (self.codec(record.component.as_str())?.load)(world, Entity::from_bits(record.entity), record.value.clone())?;
+        }
+        //What was loaded is not a change to save.
+        world.increment_change_tick();
+        Ok(())
+    }
+}
+
+//The autosaves: incremental snapshots, and a full one every full_interval saves.
+#[derive(Debug, Clone)]
+pub struct IncrementalSaver {
+    last_tick: Option<u64>,
+    full_interval: u32,
+    since_full: u32,
+}
+
+impl IncrementalSaver {
+    pub fn new(full_interval: u32) -> Self {
+        IncrementalSaver {
+            last_tick: None,
+            full_interval: full_interval.max(1),
+            since_full: 0,
+        }
+    }
+
+    //After a load, the next snapshot only holds the changes made after it.
+    pub fn resume(&mut self, world: &World) {
+        self.last_tick = Some(world.change_tick().saturating_sub(1));
+        self.since_full = 1;
+    }
+
+    pub fn save(&mut self, registry: &SaveRegistry, world: &mut World) -> GameplayResult<WorldSnapshot> {
+        let since = if self.since_full >= self.full_interval {None} else {self.last_tick};
+        let snapshot = registry.snapshot(world, since)?;
+        self.since_full = if snapshot.is_full() {1} else {self.since_full + 1};
+        self.last_tick = Some(snapshot.tick);
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod snapshot_test {
+    use super::*;
+    use maskerad_core::name::Name;
+    use ecs::naming::Tags;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Position([f32; 3]);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Opened(bool);
+
+    fn registry() -> SaveRegistry {
+        let mut registry = SaveRegistry::new();
+        registry.register::<Position>("position");
+        registry.register::<Opened>("opened");
+        registry.register::<Name>("name");
+        registry.register::<Tags>("tags");
+        registry
+    }
+
+    type ChestState = (Entity, Option<Entity>, Option<Position>, Option<Opened>);
+
+    fn state(world: &World) -> Vec<ChestState> {
+        let mut state: Vec<_> = world.entities_changed_since(None).into_iter()
+            .map(|(entity, parent)| (entity, parent, world.get::<Position>(entity).cloned(), world.get::<Opened>(entity).cloned()))
+            .collect();
+        state.sort_by_key(|entry| entry.0);
+        state
+    }
+
+    #[test]
+    fn incremental_snapshots() {
+        let registry = registry();
+        let mut world = World::new();
+        let chests: Vec<Entity> = (0..100).map(|index| {
+            let chest = world.spawn();
+            world.insert(chest, Position([index as f32, 0.0, 0.0]));
+            world.insert(chest, Opened(false));
+            chest
+        }).collect();
+        world.set_name(chests[0], "FirstChest");
+        world.add_tag(chests[0], "loot");
+
+        let mut saver = IncrementalSaver::new(10);
+        let full = saver.save(&registry, &mut world).unwrap();
+        assert!(full.is_full());
+        assert_eq!(full.components.len(), 202);
+
+        //The player opens a chest, moves another, empties and removes a third, and drops an item.
+        world.get_mut::<Opened>(chests[1]).unwrap().0 = true;
+        world.get_mut::<Position>(chests[2]).unwrap().0[1] = 5.0;
+        world.remove::<Opened>(chests[3]);
+        world.despawn(chests[4]);
+        let item = world.spawn();
+        world.insert(item, Position([1.0, 2.0, 3.0]));
+        world.set_parent(item, Some(chests[5]));
+
+        let delta = saver.save(&registry, &mut world).unwrap();
+        assert_eq!(delta.base_tick, Some(full.tick));
+        assert_eq!(delta.components.len(), 3);
+        assert_eq!(delta.entities.len(), 1);
+        assert_eq!(delta.removed_components, vec![(String::from("opened"), chests[3].to_bits())]);
+        assert_eq!(delta.despawned, vec![chests[4].to_bits()]);
+        let (mut full_bytes, mut delta_bytes) = (Vec::new(), Vec::new());
+        full.save(&mut full_bytes).unwrap();
+        delta.save(&mut delta_bytes).unwrap();
+        assert!(delta_bytes.len() * 20 < full_bytes.len());
+
+        //Nothing changed.
+        assert!(saver.save(&registry, &mut world).unwrap().components.is_empty());
+
+        let mut loaded = World::new();
+        registry.restore(&mut loaded, &WorldSnapshot::from_reader(full_bytes.as_slice()).unwrap()).unwrap();
+        registry.restore(&mut loaded, &WorldSnapshot::from_reader(delta_bytes.as_slice()).unwrap()).unwrap();
+        assert_eq!(state(&loaded), state(&world));
+        assert_eq!(loaded.find_by_name("FirstChest"), Some(chests[0]));
+        assert_eq!(loaded.find_by_tag("loot"), &[chests[0]]);
+        assert!(registry.restore(&mut loaded, &full).is_err());
+
+        //After a load, only the new changes are saved.
+        saver.resume(&loaded);
+        loaded.get_mut::<Opened>(chests[6]).unwrap().0 = true;
+        assert_eq!(saver.save(&registry, &mut loaded).unwrap().components.len(), 1);
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ WORLD STATISTICS.
+
+ What the world holds and how much memory it takes, for the "world_stats" console command:
+ - the entities, the free slots (despawned entities whose index is not reused yet), and the entities
+   without any component, which are often entities nobody remembered to despawn,
+ - for each component type: the count, the bytes used and the bytes allocated by the storage.
+
+ The fragmentation is the share of the allocated bytes which is not used: the capacity of the vectors
+ above their length, and the empty entries of the sparse array.
+*/
+
+use std::any::type_name;
+use std::mem;
+use ecs::entity::Entity;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageStats {
+    pub type_name: &'static str,
+    pub count: usize,
+    pub component_size: usize,
+    pub used_bytes: usize,
+    pub allocated_bytes: usize,
+}
+
+impl StorageStats {
+    pub fn new<T>(count: usize, capacity: usize, entity_capacity: usize, tick_capacity: usize, sparse_len: usize, sparse_capacity: usize) -> Self {
+        let entity_size = mem::size_of::<Entity>();
+        let tick_size = mem::size_of::<u64>();
+        StorageStats {
+            type_name: type_name::<T>(),
+            count,
+            component_size: mem::size_of::<T>(),
+            used_bytes: count * (mem::size_of::<T>() + entity_size + tick_size + mem::size_of::<u32>()),
+            allocated_bytes: capacity * mem::size_of::<T>() + entity_capacity * entity_size + tick_capacity * tick_size
+                + sparse_capacity.max(sparse_len) * mem::size_of::<u32>(),
+        }
+    }
+
+    pub fn fragmentation(&self) -> f32 {
+        if self.allocated_bytes == 0 {
+            return 0.0;
+        }
+        1.0 - self.used_bytes as f32 / self.allocated_bytes as f32
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorldStats {
+    pub entities: usize,
+    pub slots: usize,
+    pub free_slots: usize,
+    pub empty_entities: usize,
+    pub root_entities: usize,
+    //By allocated bytes, the largest first.
+    pub storages: Vec<StorageStats>,
+}
+
+impl WorldStats {
+    pub fn used_bytes(&self) -> usize {
+        self.storages.iter().map(|storage| storage.used_bytes).sum()
+    }
+
+    pub fn allocated_bytes(&self) -> usize {
+        self.storages.iter().map(|storage| storage.allocated_bytes).sum()
+    }
+
+    pub fn fragmentation(&self) -> f32 {
+        if self.allocated_bytes() == 0 {
+            return 0.0;
+        }
+        1.0 - self.used_bytes() as f32 / self.allocated_bytes() as f32
+    }
+
+    //The lines of the "world_stats" console command.
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("{} entities ({} roots, {} without components), {} slots, {} free",
+                self.entities, self.root_entities, self.empty_entities, self.slots, self.free_slots),
+            format!("{} components: {} used, {} allocated, {:.0}% fragmentation",
+                self.storages.iter().map(|storage| storage.count).sum::<usize>(),
+                format_bytes(self.used_bytes()), format_bytes(self.allocated_bytes()), self.fragmentation() * 100.0),
+        ];
+        for storage in self.storages.iter() {
+            lines.push(format!("  {}: {} x {} B, {} allocated, {:.0}% fragmentation",
+                storage.type_name, storage.count, storage.component_size, format_bytes(storage.allocated_bytes), storage.fragmentation() * 100.0));
+        }
+        lines
+    }
+}
+
+pub fn format_bytes(bytes: usize) -> String {
+    if bytes >= 1 << 20 {
+        format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+    } else if bytes >= 1 << 10 {
+        format!("{:.1} KiB", bytes as f64 / (1 << 10) as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::any::Any;
+use ecs::entity::Entity;
+use ecs::stats::StorageStats;
+use ecs::world::World;
+
+const EMPTY: u32 = u32::MAX;
+
+//The components of a type. sparse maps an entity index to a position in the dense arrays.
+//ticks holds the change tick of the world when each component was last added or borrowed mutably.
+pub struct Storage<T> {
+    sparse: Vec<u32>,
+    entities: Vec<Entity>,
+    components: Vec<T>,
+    ticks: Vec<u64>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Storage {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            components: Vec::new(),
+            ticks: Vec::new(),
+        }
+    }
+}
+
+impl<T> Storage<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    fn position(&self, entity: Entity) -> Option<usize> {
+        match self.sparse.get(entity.index() as usize) {
+            Some(&position) if position != EMPTY && self.entities[position as usize] == entity => Some(position as usize),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.position(entity).is_some()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.position(entity).map(move |position| &self.components[position])
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.position(entity) {
+            Some(position) => Some(&mut self.components[position]),
+            None => None,
+        }
+    }
+
+    //Returns the previous component of the entity.
+    pub fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        if let Some(position) = self.position(entity) {
+            return Some(::std::mem::replace(&mut self.components[position], component));
+        }
+        let index = entity.index() as usize;
+        if self.sparse.len() <= index {
+            self.sparse.resize(index + 1, EMPTY);
+        }
+        self.sparse[index] = self.components.len() as u32;
+        self.entities.push(entity);
+        self.components.push(component);
+        self.ticks.push(0);
+        None
+    }
+
+    //The last component takes the place of the removed one.
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let position = self.position(entity)?;
+        self.sparse[entity.index() as usize] = EMPTY;
+        self.entities.swap_remove(position);
+        self.ticks.swap_remove(position);
+        let component = self.components.swap_remove(position);
+        if position < self.entities.len() {
+            self.sparse[self.entities[position].index() as usize] = position as u32;
+        }
+        Some(component)
+    }
+
+    pub fn set_changed(&mut self, entity: Entity, tick: u64) {
+        if let Some(position) = self.position(entity) {
+            self.ticks[position] = tick;
+        }
+    }
+
+    pub fn changed_tick(&self, entity: Entity) -> Option<u64> {
+        self.position(entity).map(|position| self.ticks[position])
+    }
+
+    //The components added or modified after the tick.
+    pub fn changed_since(&self, tick: u64) -> Vec<(Entity, &T)> {
+        (0..self.components.len())
+            .filter(|&position| self.ticks[position] > tick)
+            .map(|position| (self.entities[position], &self.components[position]))
+            .collect()
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        self.entities.as_slice()
+    }
+
+    pub fn components(&self) -> &[T] {
+        self.components.as_slice()
+    }
+
+    pub fn components_mut(&mut self) -> &mut [T] {
+        self.components.as_mut_slice()
+    }
+}
+
+//The storages of all the types, in the world.
+pub trait AnyStorage {
+    fn get_any(&self, entity: Entity) -> Option<&Any>;
+    fn remove_entity(&mut self, entity: Entity) -> bool;
+    fn stats(&self) -> StorageStats;
+    //Moves the component of the entity to an entity of another world.
+    fn move_entity(&mut self, from: Entity, to: Entity, target: &mut World) -> bool;
+    fn as_any(&self) -> &Any;
+    fn as_any_mut(&mut self) -> &mut Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+    fn get_any(&self, entity: Entity) -> Option<&Any> {
+        self.get(entity).map(|component| component as &Any)
+    }
+
+    fn remove_entity(&mut self, entity: Entity) -> bool {
+        self.remove(entity).is_some()
+    }
+
+    fn move_entity(&mut self, from: Entity, to: Entity, target: &mut World) -> bool {
+        match self.remove(from) {
+            Some(component) => {
+                target.insert(to, component);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn stats(&self) -> StorageStats {
+        StorageStats::new::<T>(self.components.len(), self.components.capacity(), self.entities.capacity(), self.ticks.capacity(), self.sparse.len(), self.sparse.capacity())
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut Any {
+        self
+    }
+}
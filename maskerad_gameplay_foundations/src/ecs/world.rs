@@ -0,0 +1,701 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use maskerad_core::name::Name;
+use ecs::entity::Entity;
+use ecs::storage::{AnyStorage, Storage};
+use ecs::hooks::{AnyHooks, Hooks};
+use ecs::commands::Commands;
+use ecs::stats::WorldStats;
+use ecs::migration::{map_component, EntityMap, EntityMapper, MapEntities};
+use ecs::naming::{NameIndex, Tags};
+use scripting::script_backend::ScriptValue;
+
+#[derive(Debug, Default)]
+struct Slot {
+    generation: u32,
+    alive: bool,
+    parent: Option<Entity>,
+    children: Vec<Entity>,
+    //The change tick of the spawn or of the last change of parent.
+    changed: u64,
+}
+
+//The structural changes, for the incremental saves.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Removal {
+    Component(TypeId, Entity),
+    Entity(Entity),
+}
+
+#[derive(Default)]
+pub struct World {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    alive: usize,
+    //The indices reserved by the Commands, from the end of the slots.
+    reserved: Arc<AtomicUsize>,
+    storages: HashMap<TypeId, Box<AnyStorage>>,
+    hooks: HashMap<TypeId, Box<AnyHooks>>,
+    mappers: HashMap<TypeId, EntityMapper>,
+    index: NameIndex,
+    change_tick: u64,
+    //Only recorded while the snapshots need them, see track_removals.
+    tracking_removals: bool,
+    removals: Vec<(u64, Removal)>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.alive
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alive == 0
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        self.flush_reserved();
+        self.alive += 1;
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.alive = true;
+                slot.changed = self.change_tick;
+                Entity::new(index, slot.generation)
+            },
+            None => {
+                self.slots.push(Slot {
+                    alive: true,
+                    changed: self.change_tick,
+                    .. Default::default()
+                });
+                self.reserved.store(self.slots.len(), Ordering::SeqCst);
+                Entity::new((self.slots.len() - 1) as u32, 0)
+            },
+        }
+    }
+
+    //The entities reserved by the Commands are spawned.
+    fn flush_reserved(&mut self) {
+        let reserved = self.reserved.load(Ordering::SeqCst);
+        while self.slots.len() < reserved {
+            self.slots.push(Slot {
+                alive: true,
+                changed: self.change_tick,
+                .. Default::default()
+            });
+            self.alive += 1;
+        }
+    }
+
+    //Spawns an entity with this id, when a saved world is loaded. Fails if the index is used.
+    pub fn spawn_at(&mut self, entity: Entity) -> bool {
+        self.flush_reserved();
+        let index = entity.index() as usize;
+        while self.slots.len() <= index {
+            self.free.push(self.slots.len() as u32);
+            self.slots.push(Slot::default());
+        }
+        self.reserved.store(self.slots.len(), Ordering::SeqCst);
+        if self.slots[index].alive {
+            return false;
+        }
+        self.free.retain(|free| *free != entity.index());
+        let slot = &mut self.slots[index];
+        slot.alive = true;
+        slot.generation = entity.generation();
+        slot.changed = self.change_tick;
+        self.alive += 1;
+        true
+    }
+
+    pub fn commands(&self) -> Commands {
+        Commands::new(self.reserved.clone())
+    }
+
+    //At a sync point: the reserved entities are spawned, then the commands are run in order.
+    pub fn apply(&mut self, commands: &mut Commands) {
+        self.flush_reserved();
+        for command in commands.drain() {
+            command(self);
+        }
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        match self.slots.get(entity.index() as usize) {
+            Some(slot) => slot.alive && slot.generation == entity.generation(),
+            None => false,
+        }
+    }
+
+    //The alive entity with this index, whatever its generation.
+    pub fn entity_at(&self, index: u32) -> Option<Entity> {
+        match self.slots.get(index as usize) {
+            Some(slot) if slot.alive => Some(Entity::new(index, slot.generation)),
+            _ => None,
+        }
+    }
+
+    //Despawns the entity and its descendants.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        self.set_parent(entity, None);
+        let mut stack = vec![entity];
+        while let Some(entity) = stack.pop() {
+            self.unindex(entity);
+            for (type_id, storage) in self.storages.iter_mut() {
+                if let (Some(hooks), Some(component)) = (self.hooks.get_mut(type_id), storage.get_any(entity)) {
+                    hooks.removed(entity, component);
+                }
+                storage.remove_entity(entity);
+            }
+            if self.tracking_removals {
+                self.removals.push((self.change_tick, Removal::Entity(entity)));
+            }
+            let slot = &mut self.slots[entity.index() as usize];
+            stack.append(&mut slot.children);
+            slot.alive = false;
+            slot.parent = None;
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free.push(entity.index());
+            self.alive -= 1;
+        }
+        true
+    }
+
+    pub fn storage<T: 'static>(&self) -> Option<&Storage<T>> {
+        self.storages.get(&TypeId::of::<T>()).and_then(|storage| storage.as_any().downcast_ref::<Storage<T>>())
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> &mut Storage<T> {
+        self.storages.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Storage::<T>::new()))
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+            .expect("The storage of a type holds the components of another type.")
+    }
+
+    //The hooks of the type, with the component of the entity.
+    fn run_hooks<T: 'static>(&mut self, entity: Entity, added: bool) {
+        let hooks = match self.hooks.get_mut(&TypeId::of::<T>()).and_then(|hooks| hooks.as_any_mut().downcast_mut::<Hooks<T>>()) {
+            Some(hooks) => hooks,
+            None => return,
+        };
+        let component = match self.storages.get(&TypeId::of::<T>()).and_then(|storage| storage.as_any().downcast_ref::<Storage<T>>()) {
+            Some(storage) => match storage.get(entity) {
+                Some(component) => component,
+                None => return,
+            },
+            None => return,
+        };
+        if added {
+            hooks.added(entity, component);
+        } else {
+            hooks.removed(entity, component);
+        }
+    }
+
+    fn hooks_entry<T: 'static>(&mut self) -> &mut Hooks<T> {
+        self.hooks.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Hooks::<T>::new()))
+            .as_any_mut()
+            .downcast_mut::<Hooks<T>>()
+            .expect("The hooks of a type are registered for another type.")
+    }
+
+    pub fn on_add<T: 'static, F: FnMut(Entity, &T) + 'static>(&mut self, hook: F) {
+        self.hooks_entry::<T>().add_on_add(Box::new(hook));
+    }
+
+    pub fn on_remove<T: 'static, F: FnMut(Entity, &T) + 'static>(&mut self, hook: F) {
+        self.hooks_entry::<T>().add_on_remove(Box::new(hook));
+    }
+
+    pub fn clear_hooks<T: 'static>(&mut self) {
+        self.hooks.remove(&TypeId::of::<T>());
+    }
+
+    fn is_indexed<T: 'static>() -> bool {
+        TypeId::of::<T>() == TypeId::of::<Name>() || TypeId::of::<T>() == TypeId::of::<Tags>()
+    }
+
+    //Returns the previous component. Nothing is inserted if the entity is dead.
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+        if !self.is_alive(entity) {
+            warn!("Cannot add a component to the dead entity {}.", entity);
+            return None;
+        }
+        if World::is_indexed::<T>() {
+            self.unindex(entity);
+        }
+        self.run_hooks::<T>(entity, false);
+        let tick = self.change_tick;
+        let storage = self.storage_mut::<T>();
+        let previous = storage.insert(entity, component);
+        storage.set_changed(entity, tick);
+        if World::is_indexed::<T>() {
+            self.reindex(entity);
+        }
+        self.run_hooks::<T>(entity, true);
+        previous
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        if World::is_indexed::<T>() {
+            self.unindex(entity);
+        }
+        self.run_hooks::<T>(entity, false);
+        let removed = match self.storages.get_mut(&TypeId::of::<T>()).and_then(|storage| storage.as_any_mut().downcast_mut::<Storage<T>>()) {
+            Some(storage) => storage.remove(entity),
+            None => None,
+        };
+        if removed.is_some() && self.tracking_removals {
+            self.removals.push((self.change_tick, Removal::Component(TypeId::of::<T>(), entity)));
+        }
+        if World::is_indexed::<T>() {
+            self.reindex(entity);
+        }
+        removed
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        self.storage::<T>().and_then(|storage| storage.get(entity))
+    }
+
+    //The names and the tags are modified with insert, add_tag and remove_tag, to keep the index up to date.
+    //The component is marked as changed.
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        debug_assert!(!World::is_indexed::<T>(), "The names and the tags cannot be modified in place.");
+        let tick = self.change_tick;
+        match self.storages.get_mut(&TypeId::of::<T>()).and_then(|storage| storage.as_any_mut().downcast_mut::<Storage<T>>()) {
+            Some(storage) => {
+                storage.set_changed(entity, tick);
+                storage.get_mut(entity)
+            },
+            None => None,
+        }
+    }
+
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick
+    }
+
+    //The changes made from now on are after the current tick.
+    pub fn increment_change_tick(&mut self) -> u64 {
+        self.change_tick += 1;
+        self.change_tick
+    }
+
+    //The entities spawned or moved in the hierarchy after the tick (all of them without tick), with their parent.
+    pub fn entities_changed_since(&self, tick: Option<u64>) -> Vec<(Entity, Option<Entity>)> {
+        self.slots.iter().enumerate()
+            .filter(|&(_, slot)| slot.alive && tick.map(|tick| slot.changed > tick).unwrap_or(true))
+            .map(|(index, slot)| (Entity::new(index as u32, slot.generation), slot.parent))
+            .collect()
+    }
+
+    //The components removed and the entities despawned after the tick.
+    pub fn removals_since(&self, tick: u64) -> Vec<Removal> {
+        self.removals.iter().filter(|&&(at, _)| at > tick).map(|&(_, removal)| removal).collect()
+    }
+
+    //Records the removals for the next incremental snapshot. Without tracking, nothing is recorded and the
+    //recorded removals are forgotten.
+    pub fn track_removals(&mut self, tracking: bool) {
+        self.tracking_removals = tracking;
+        if !tracking {
+            self.removals.clear();
+        }
+    }
+
+    pub fn is_tracking_removals(&self) -> bool {
+        self.tracking_removals
+    }
+
+    //The removals until the tick are saved, they are forgotten.
+    pub fn forget_removals(&mut self, tick: u64) {
+        self.removals.retain(|&(at, _)| at > tick);
+    }
+
+    pub fn has<T: 'static>(&self, entity: Entity) -> bool {
+        self.get::<T>(entity).is_some()
+    }
+
+    //The entities with a component of this type, and their components.
+    pub fn query<T: 'static>(&self) -> Vec<(Entity, &T)> {
+        match self.storage::<T>() {
+            Some(storage) => storage.entities().iter().cloned().zip(storage.components().iter()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    //Fails if the parent is the entity or one of its descendants.
+    pub fn set_parent(&mut self, entity: Entity, parent: Option<Entity>) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        if let Some(parent) = parent {
+            if !self.is_alive(parent) || self.is_ancestor(entity, parent) {
+                return false;
+            }
+        }
+        if let Some(previous) = self.slots[entity.index() as usize].parent.take() {
+            self.slots[previous.index() as usize].children.retain(|child| *child != entity);
+        }
+        if let Some(parent) = parent {
+            self.slots[parent.index() as usize].children.push(entity);
+        }
+        self.slots[entity.index() as usize].parent = parent;
+        self.slots[entity.index() as usize].changed = self.change_tick;
+        true
+    }
+
+    pub fn is_ancestor(&self, ancestor: Entity, entity: Entity) -> bool {
+        let mut current = Some(entity);
+        while let Some(entity) = current {
+            if entity == ancestor {
+                return true;
+            }
+            current = self.parent(entity);
+        }
+        false
+    }
+
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        if self.is_alive(entity) {self.slots[entity.index() as usize].parent} else {None}
+    }
+
+    pub fn children(&self, entity: Entity) -> &[Entity] {
+        if self.is_alive(entity) {self.slots[entity.index() as usize].children.as_slice()} else {&[]}
+    }
+
+    fn unindex(&mut self, entity: Entity) {
+        if let Some(&name) = self.get::<Name>(entity) {
+            self.index.remove_name(name, entity);
+        }
+        let tags: Vec<Name> = self.get::<Tags>(entity).map(|tags| tags.iter().cloned().collect()).unwrap_or_default();
+        for tag in tags {
+            self.index.remove_tag(tag, entity);
+        }
+    }
+
+    fn reindex(&mut self, entity: Entity) {
+        if let Some(&name) = self.get::<Name>(entity) {
+            self.index.add_name(name, entity);
+        }
+        let tags: Vec<Name> = self.get::<Tags>(entity).map(|tags| tags.iter().cloned().collect()).unwrap_or_default();
+        for tag in tags {
+            self.index.add_tag(tag, entity);
+        }
+    }
+
+    pub fn set_name(&mut self, entity: Entity, name: &str) {
+        self.insert(entity, Name::new(name));
+    }
+
+    pub fn add_tag(&mut self, entity: Entity, tag: &str) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        let tag = Name::new(tag);
+        let tick = self.change_tick;
+        let storage = self.storage_mut::<Tags>();
+        storage.set_changed(entity, tick);
+        let added = storage.get_mut(entity).map(|tags| tags.insert(tag));
+        match added {
+            Some(added) => {
+                if added {
+                    self.index.add_tag(tag, entity);
+                }
+                added
+            },
+            None => {
+                let mut tags = Tags::default();
+                tags.insert(tag);
+                self.insert(entity, tags);
+                true
+            },
+        }
+    }
+
+    pub fn remove_tag(&mut self, entity: Entity, tag: &str) -> bool {
+        let tag = Name::lookup(tag);
+        let removed = match self.storages.get_mut(&TypeId::of::<Tags>()).and_then(|storage| storage.as_any_mut().downcast_mut::<Storage<Tags>>()) {
+            Some(storage) => {
+                storage.set_changed(entity, self.change_tick);
+                storage.get_mut(entity).map(|tags| tags.remove(tag)).unwrap_or(false)
+            },
+            None => false,
+        };
+        if removed {
+            self.index.remove_tag(tag, entity);
+        }
+        removed
+    }
+
+    //The first entity spawned with this name.
+    pub fn find_by_name(&self, name: &str) -> Option<Entity> {
+        self.index.named(Name::lookup(name)).first().cloned()
+    }
+
+    pub fn find_all_by_name(&self, name: &str) -> &[Entity] {
+        self.index.named(Name::lookup(name))
+    }
+
+    pub fn find_by_tag(&self, tag: &str) -> &[Entity] {
+        self.index.tagged(Name::lookup(tag))
+    }
+
+    //The entities with all the tags.
+    pub fn find_by_tags(&self, tags: &[&str]) -> Vec<Entity> {
+        let (first, others) = match tags.split_first() {
+            Some(split) => split,
+            None => return Vec::new(),
+        };
+        self.find_by_tag(first).iter()
+            .filter(|&&entity| {
+                let entity_tags = self.get::<Tags>(entity);
+                others.iter().all(|tag| entity_tags.map(|entity_tags| entity_tags.contains(tag)).unwrap_or(false))
+            })
+            .cloned()
+            .collect()
+    }
+
+    //The named children of the named roots: "Level/Enemies/Orc".
+    pub fn find_by_path(&self, path: &str) -> Option<Entity> {
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+        let first = Name::lookup(segments.next()?);
+        let mut current = self.index.named(first).iter().cloned().find(|entity| self.parent(*entity).is_none())?;
+        for segment in segments {
+            let name = Name::lookup(segment);
+            current = self.children(current).iter().cloned().find(|child| self.get::<Name>(*child) == Some(&name))?;
+        }
+        Some(current)
+    }
+
+    //The references of the console and the scripts: "#12" (an index), a path, or a name.
+    pub fn resolve(&self, reference: &str) -> Option<Entity> {
+        if let Some(index) = reference.strip_prefix('#') {
+            return index.parse::<u32>().ok().and_then(|index| self.entity_at(index));
+        }
+        if reference.contains('/') {
+            return self.find_by_path(reference);
+        }
+        self.find_by_name(reference)
+    }
+
+    pub fn resolve_for_script(&self, reference: &str) -> ScriptValue {
+        match self.resolve(reference) {
+            Some(entity) => ScriptValue::Int(entity.to_bits() as i64),
+            None => ScriptValue::Nil,
+        }
+    }
+
+    //The components of this type reference entities, they are remapped when they move to another world.
+    pub fn register_entity_references<T: MapEntities + 'static>(&mut self) {
+        self.mappers.insert(TypeId::of::<T>(), map_component::<T>);
+    }
+
+    //Moves the entity and its descendants to the target world. Returns their new ids.
+    pub fn migrate(&mut self, root: Entity, target: &mut World) -> Option<EntityMap> {
+        if !self.is_alive(root) {
+            return None;
+        }
+        let mut moved = vec![root];
+        let mut next = 0;
+        while next < moved.len() {
+            let children = self.children(moved[next]).to_vec();
+            moved.extend(children);
+            next += 1;
+        }
+        debug!("Moving {} entities to another world.", moved.len());
+
+        let mut map = EntityMap::new();
+        for entity in moved.iter() {
+            map.insert(*entity, target.spawn());
+        }
+        for entity in moved.iter() {
+            let new = map.get(*entity).expect("The moved entities are in the map.");
+            self.unindex(*entity);
+            for (type_id, storage) in self.storages.iter_mut() {
+                if let (Some(hooks), Some(component)) = (self.hooks.get_mut(type_id), storage.get_any(*entity)) {
+                    hooks.removed(*entity, component);
+                }
+                storage.move_entity(*entity, new, target);
+            }
+            if *entity != root {
+                let parent = self.parent(*entity).and_then(|parent| map.get(parent));
+                target.set_parent(new, parent);
+            }
+        }
+        self.despawn(root);
+
+        for (type_id, mapper) in self.mappers.iter() {
+            target.mappers.insert(*type_id, *mapper);
+            for entity in moved.iter() {
+                mapper(target, map.get(*entity).expect("The moved entities are in the map."), &map);
+            }
+        }
+        Some(map)
+    }
+
+    pub fn stats(&self) -> WorldStats {
+        let alive = self.slots.iter().enumerate().filter(|&(_, slot)| slot.alive).map(|(index, slot)| (Entity::new(index as u32, slot.generation), slot));
+        let mut stats = WorldStats {
+            entities: self.alive,
+            slots: self.slots.len(),
+            free_slots: self.free.len(),
+            .. Default::default()
+        };
+        for (entity, slot) in alive {
+            if slot.parent.is_none() {
+                stats.root_entities += 1;
+            }
+            if !self.storages.values().any(|storage| storage.get_any(entity).is_some()) {
+                stats.empty_entities += 1;
+            }
+        }
+        stats.storages = self.storages.values().map(|storage| storage.stats()).collect();
+        stats.storages.sort_by(|a, b| b.allocated_bytes.cmp(&a.allocated_bytes).then(a.type_name.cmp(b.type_name)));
+        stats
+    }
+
+    //How the inspector and the console print an entity: "Player #3v0".
+    pub fn label(&self, entity: Entity) -> String {
+        match self.get::<Name>(entity) {
+            Some(name) => format!("{} {}", name, entity),
+            None => format!("{}", entity),
+        }
+    }
+}
+
+#[cfg(test)]
+mod world_test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[test]
+    fn components_and_hierarchy() {
+        let mut world = World::new();
+        let level = world.spawn();
+        let enemy = world.spawn();
+        let other = world.spawn();
+        world.insert(enemy, Health(10));
+        world.insert(other, Health(20));
+        assert_eq!(world.insert(enemy, Health(5)), Some(Health(10)));
+        world.get_mut::<Health>(other).unwrap().0 += 1;
+        assert_eq!(world.query::<Health>(), vec![(enemy, &Health(5)), (other, &Health(21))]);
+
+        assert!(world.set_parent(enemy, Some(level)));
+        assert!(!world.set_parent(level, Some(enemy)));
+        assert_eq!(world.children(level), &[enemy]);
+
+        //The children are despawned with their parent, and the indices are reused.
+        assert!(world.despawn(level));
+        assert!(!world.is_alive(enemy));
+        assert_eq!(world.len(), 1);
+        assert_eq!(world.query::<Health>(), vec![(other, &Health(21))]);
+        let reused = world.spawn();
+        assert_ne!(reused, level);
+        assert!(!world.has::<Health>(reused));
+        assert_eq!(Entity::from_bits(reused.to_bits()), reused);
+    }
+
+    #[test]
+    fn removals_only_while_tracking() {
+        let mut world = World::new();
+        let entities: Vec<Entity> = (0..3).map(|_| world.spawn()).collect();
+        world.insert(entities[0], Health(1));
+        world.remove::<Health>(entities[0]);
+        world.despawn(entities[1]);
+        assert!(world.removals_since(0).is_empty());
+
+        world.track_removals(true);
+        world.increment_change_tick();
+        world.despawn(entities[2]);
+        assert_eq!(world.removals_since(0), vec![Removal::Entity(entities[2])]);
+        world.track_removals(false);
+        assert!(world.removals_since(0).is_empty());
+    }
+
+    #[test]
+    fn world_stats() {
+        let mut world = World::new();
+        let entities: Vec<Entity> = (0..8).map(|_| world.spawn()).collect();
+        for (index, entity) in entities.iter().enumerate() {
+            world.insert(*entity, Health(index as u32));
+        }
+        world.set_parent(entities[1], Some(entities[0]));
+        //Forgotten entities, without components.
+        world.spawn();
+        world.spawn();
+        for entity in entities[4..].iter() {
+            world.despawn(*entity);
+        }
+
+        let stats = world.stats();
+        assert_eq!((stats.entities, stats.slots, stats.free_slots), (6, 10, 4));
+        assert_eq!((stats.root_entities, stats.empty_entities), (5, 2));
+        assert_eq!(stats.storages.len(), 1);
+        assert_eq!(stats.storages[0].count, 4);
+        assert_eq!(stats.storages[0].used_bytes, 4 * (4 + 8 + 8 + 4));
+        assert!(stats.fragmentation() > 0.0);
+        let lines = stats.to_lines();
+        assert_eq!(lines[0], "6 entities (5 roots, 2 without components), 10 slots, 4 free");
+        assert!(lines[2].contains("Health: 4 x 4 B"));
+    }
+
+    #[test]
+    fn names_tags_and_search() {
+        let mut world = World::new();
+        let level = world.spawn();
+        world.set_name(level, "Level");
+        let enemies = world.spawn();
+        world.set_name(enemies, "Enemies");
+        world.set_parent(enemies, Some(level));
+        let orc = world.spawn();
+        world.set_name(orc, "Orc");
+        world.set_parent(orc, Some(enemies));
+        world.insert(orc, Tags::new(&["enemy", "green"]));
+        let player = world.spawn();
+        world.set_name(player, "Player");
+        world.add_tag(player, "green");
+
+        assert_eq!(world.find_by_name("Player"), Some(player));
+        assert_eq!(world.find_by_path("Level/Enemies/Orc"), Some(orc));
+        assert_eq!(world.find_by_path("Enemies/Orc"), None);
+        assert_eq!(world.find_by_tag("green"), &[orc, player]);
+        assert_eq!(world.find_by_tags(&["green", "enemy"]), vec![orc]);
+        assert_eq!(world.resolve("#3"), Some(player));
+        assert_eq!(world.resolve_for_script("Nobody"), ScriptValue::Nil);
+        assert_eq!(world.label(player), "Player #3v0");
+
+        //The index follows the renames, the tag changes and the despawns.
+        world.set_name(player, "Hero");
+        assert_eq!(world.find_by_name("Player"), None);
+        assert_eq!(world.find_by_name("Hero"), Some(player));
+        assert!(world.remove_tag(player, "green"));
+        assert!(!world.remove_tag(player, "green"));
+        assert_eq!(world.find_by_tag("green"), &[orc]);
+        world.despawn(level);
+        assert!(world.find_by_tag("enemy").is_empty());
+        assert_eq!(world.find_by_name("Orc"), None);
+    }
+}
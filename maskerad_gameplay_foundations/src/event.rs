@@ -3,4 +3,210 @@
 // Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
 // http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
-// copied, modified, or distributed except according to those terms.
\ No newline at end of file
+// copied, modified, or distributed except according to those terms.
+
+/*
+ EVENT BUS.
+
+ The systems publish typed events on the bus, and the other systems read them during the same frame
+ and the next one: the bus is double buffered, the events published during a frame are readable
+ until the end of the next frame. A system reading after the publisher in the frame sees the event
+ in the current buffer, a system reading before it sees it in the previous buffer.
+
+ Each type of event has its own queue, a reader only sees the events of the types it asks for.
+
+ For debugging, the bus can record its traffic in an EventLog: the systems which publish with
+ publish_from and read with read_by are named in the log.
+*/
+
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use event_log::EventLog;
+
+//Each event is stored with its id, for the log.
+struct EventQueue<E> {
+    previous: Vec<(u64, E)>,
+    current: Vec<(u64, E)>,
+}
+
+trait AnyQueue {
+    fn swap(&mut self);
+    fn as_any(&self) -> &Any;
+    fn as_any_mut(&mut self) -> &mut Any;
+}
+
+impl<E: 'static> AnyQueue for EventQueue<E> {
+    fn swap(&mut self) {
+        self.previous.clear();
+        ::std::mem::swap(&mut self.previous, &mut self.current);
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut Any {
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    queues: HashMap<TypeId, Box<AnyQueue>>,
+    next_id: u64,
+    //Read by read_by, which only borrows the bus.
+    log: Option<RefCell<EventLog>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn push<E: 'static>(&mut self, event: E) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let queue = self.queues.entry(TypeId::of::<E>()).or_insert_with(|| Box::new(EventQueue::<E> {
+            previous: Vec::new(),
+            current: Vec::new(),
+        }));
+        if let Some(queue) = queue.as_any_mut().downcast_mut::<EventQueue<E>>() {
+            queue.current.push((id, event));
+        }
+        id
+    }
+
+    pub fn publish<E: 'static>(&mut self, event: E) {
+        let id = self.push(event);
+        if let Some(ref log) = self.log {
+            log.borrow_mut().record_publish(id, ::std::any::type_name::<E>(), None, None);
+        }
+    }
+
+    //Publish, naming the system in the log.
+    pub fn publish_from<E: Debug + 'static>(&mut self, publisher: &str, event: E) {
+        let description = if self.log.is_some() {Some(format!("{:?}", event))} else {None};
+        let id = self.push(event);
+        if let Some(ref log) = self.log {
+            log.borrow_mut().record_publish(id, ::std::any::type_name::<E>(), Some(publisher), description);
+        }
+    }
+
+    fn events<E: 'static>(&self) -> Vec<&(u64, E)> {
+        match self.queues.get(&TypeId::of::<E>()).and_then(|queue| queue.as_any().downcast_ref::<EventQueue<E>>()) {
+            Some(queue) => queue.previous.iter().chain(queue.current.iter()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    //The events of the previous frame, then the events of this frame.
+    pub fn read<E: 'static>(&self) -> Vec<&E> {
+        self.events::<E>().into_iter().map(|pair| &pair.1).collect()
+    }
+
+    //Read, naming the system in the log.
+    pub fn read_by<E: 'static>(&self, consumer: &str) -> Vec<&E> {
+        let events = self.events::<E>();
+        if let Some(ref log) = self.log {
+            let mut log = log.borrow_mut();
+            for &&(id, _) in events.iter() {
+                log.record_consume(id, consumer);
+            }
+        }
+        events.into_iter().map(|pair| &pair.1).collect()
+    }
+
+    //Keeps the events of the last frames in the log.
+    pub fn start_recording(&mut self, max_frames: u64) {
+        debug!("Recording the events of the last {} frames.", max_frames);
+        self.log = Some(RefCell::new(EventLog::new(max_frames)));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<EventLog> {
+        self.log.take().map(|log| log.into_inner())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.log.is_some()
+    }
+
+    pub fn log<'a>(&'a self) -> Option<Ref<'a, EventLog>> {
+        self.log.as_ref().map(|log| log.borrow())
+    }
+
+    //Called by the game loop at the end of each frame: the events of the previous frame are dropped.
+    pub fn end_frame(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.swap();
+        }
+        if let Some(ref log) = self.log {
+            log.borrow_mut().end_frame();
+        }
+    }
+}
+
+#[cfg(test)]
+mod event_test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Explosion(u32);
+
+    #[test]
+    fn event_bus_keeps_events_for_two_frames() {
+        let mut bus = EventBus::new();
+        bus.publish(Explosion(1));
+        bus.publish(String::from("not an explosion"));
+        assert_eq!(bus.read::<Explosion>(), vec![&Explosion(1)]);
+        assert!(bus.read::<u32>().is_empty());
+
+        bus.end_frame();
+        bus.publish(Explosion(2));
+        assert_eq!(bus.read::<Explosion>(), vec![&Explosion(1), &Explosion(2)]);
+        bus.end_frame();
+        assert_eq!(bus.read::<Explosion>(), vec![&Explosion(2)]);
+        bus.end_frame();
+        assert!(bus.read::<Explosion>().is_empty());
+    }
+
+    #[test]
+    fn event_bus_recording() {
+        use event_log::{console_query, EventQuery};
+
+        let mut bus = EventBus::new();
+        bus.publish_from("physics", Explosion(0));
+        bus.start_recording(2);
+        bus.publish_from("physics", Explosion(1));
+        bus.publish(String::from("anonymous"));
+        assert_eq!(bus.read_by::<Explosion>("audio").len(), 2);
+        bus.end_frame();
+        bus.read_by::<Explosion>("audio");
+        bus.read_by::<Explosion>("ai");
+        bus.publish_from("gameplay", Explosion(2));
+        bus.end_frame();
+
+        {
+            let log = bus.log().unwrap();
+            //The event published before the recording is not in the log.
+            assert_eq!(log.records().len(), 3);
+            let explosions = log.query(&EventQuery::parse("type=Explosion consumer=audio").unwrap());
+            assert_eq!(explosions.len(), 1);
+            assert_eq!(explosions[0].consumers, vec![String::from("audio"), String::from("ai")]);
+            assert_eq!(explosions[0].to_line(), "[0] #1 Explosion from physics Explosion(1) -> audio, ai");
+            assert_eq!(log.query(&EventQuery::parse("frame=1 unconsumed").unwrap())[0].publisher, Some(String::from("gameplay")));
+            assert_eq!(console_query(&log, "publisher=nobody").last().unwrap(), "0 events");
+            assert_eq!(console_query(&log, "colour=red"), vec![String::from("Event log error: Unknown query filter colour.")]);
+        }
+
+        //Only the last two frames are kept, and the dump can be read back.
+        bus.end_frame();
+        let log = bus.stop_recording().unwrap();
+        assert_eq!(log.records().len(), 1);
+        let mut dump = Vec::new();
+        log.save(&mut dump).unwrap();
+        assert_eq!(EventLog::from_reader(dump.as_slice()).unwrap(), log);
+        assert!(!bus.is_recording());
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ EVENT LOG.
+
+ While recording, the event bus logs each event: the frame, the type, the system which published it,
+ a description (its Debug output) and the systems which read it. Only the last frames are kept.
+
+ The log answers the queries of the debug console, e.g. "events frame=120..130 type=Explosion
+ consumer=audio", and is dumped as JSON in the bug reports.
+*/
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use serde_json;
+use gameplay_error::{GameplayError, GameplayResult};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub id: u64,
+    pub frame: u64,
+    pub event_type: String,
+    //None when the event was published without a system name.
+    pub publisher: Option<String>,
+    pub description: Option<String>,
+    pub consumers: Vec<String>,
+}
+
+impl EventRecord {
+    //The type without its module path.
+    pub fn short_type(&self) -> &str {
+        let generics = self.event_type.find('<').unwrap_or(self.event_type.len());
+        match self.event_type[..generics].rfind("::") {
+            Some(separator) => &self.event_type[separator + 2..],
+            None => self.event_type.as_str(),
+        }
+    }
+
+    //A line of the debug console.
+    pub fn to_line(&self) -> String {
+        format!("[{}] #{} {} from {}{} -> {}",
+            self.frame,
+            self.id,
+            self.short_type(),
+            self.publisher.as_deref().unwrap_or("?"),
+            self.description.as_ref().map(|description| format!(" {}", description)).unwrap_or_default(),
+            if self.consumers.is_empty() {String::from("nobody")} else {self.consumers.join(", ")},
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventLog {
+    max_frames: u64,
+    frame: u64,
+    records: VecDeque<EventRecord>,
+}
+
+impl EventLog {
+    pub fn new(max_frames: u64) -> Self {
+        EventLog {
+            max_frames: max_frames.max(1),
+            frame: 0,
+            records: VecDeque::new(),
+        }
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> GameplayResult<Self> {
+        debug!("Deserializing an event log.");
+        serde_json::from_reader(reader).map_err(|json_error| {
+            GameplayError::from(json_error)
+        })
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> GameplayResult<()> {
+        debug!("Serializing an event log of {} events.", self.records.len());
+        serde_json::to_writer(writer, self).map_err(|json_error| {
+            GameplayError::from(json_error)
+        })
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn records(&self) -> &VecDeque<EventRecord> {
+        &self.records
+    }
+
+    pub fn record_publish(&mut self, id: u64, event_type: &str, publisher: Option<&str>, description: Option<String>) {
+        self.records.push_back(EventRecord {
+            id,
+            frame: self.frame,
+            event_type: String::from(event_type),
+            publisher: publisher.map(String::from),
+            description,
+            consumers: Vec::new(),
+        });
+    }
+
+    //An event read twice by the same system is recorded once.
+    pub fn record_consume(&mut self, id: u64, consumer: &str) {
+        //The ids are increasing.
+        let index = match self.records.iter().rev().position(|record| record.id <= id) {
+            Some(position) => self.records.len() - 1 - position,
+            None => return,
+        };
+        let record = &mut self.records[index];
+        if record.id == id && !record.consumers.iter().any(|known| known == consumer) {
+            record.consumers.push(String::from(consumer));
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+        //The frame being recorded, and the last max_frames frames.
+        let oldest = self.frame.saturating_sub(self.max_frames);
+        while self.records.front().map(|record| record.frame < oldest).unwrap_or(false) {
+            self.records.pop_front();
+        }
+    }
+
+    pub fn query(&self, query: &EventQuery) -> Vec<&EventRecord> {
+        self.records.iter().filter(|record| query.matches(record)).collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventQuery {
+    //Inclusive.
+    pub frames: Option<(u64, u64)>,
+    //The short type, or the full path.
+    pub event_type: Option<String>,
+    pub publisher: Option<String>,
+    pub consumer: Option<String>,
+    //The events read by nobody.
+    pub unconsumed: bool,
+}
+
+impl EventQuery {
+    //"frame=10..20 type=Explosion publisher=physics consumer=audio unconsumed", every filter is optional.
+    pub fn parse(text: &str) -> GameplayResult<Self> {
+        let mut query = EventQuery::default();
+        for token in text.split_whitespace() {
+            if token == "unconsumed" {
+                query.unconsumed = true;
+                continue;
+            }
+            let (key, value) = match token.find('=') {
+                Some(separator) => (&token[..separator], &token[separator + 1..]),
+                None => return Err(GameplayError::EventLogError(format!("Expected key=value in the query, found {}.", token))),
+            };
+            match key {
+                "frame" => {
+                    let parse = |frame: &str| frame.parse::<u64>().map_err(|_| {
+                        GameplayError::EventLogError(format!("{} is not a frame number.", frame))
+                    });
+                    query.frames = Some(match value.find("..") {
+                        Some(separator) => (parse(&value[..separator])?, parse(&value[separator + 2..])?),
+                        None => {
+                            let frame = parse(value)?;
+                            (frame, frame)
+                        },
+                    });
+                },
+                "type" => query.event_type = Some(String::from(value)),
+                "publisher" => query.publisher = Some(String::from(value)),
+                "consumer" => query.consumer = Some(String::from(value)),
+                _ => return Err(GameplayError::EventLogError(format!("Unknown query filter {}.", key))),
+            }
+        }
+        Ok(query)
+    }
+
+    pub fn matches(&self, record: &EventRecord) -> bool {
+        self.frames.map(|(first, last)| record.frame >= first && record.frame <= last).unwrap_or(true)
+            && self.event_type.as_ref().map(|event_type| event_type == &record.event_type || event_type == record.short_type()).unwrap_or(true)
+            && self.publisher.as_ref().map(|publisher| record.publisher.as_ref() == Some(publisher)).unwrap_or(true)
+            && self.consumer.as_ref().map(|consumer| record.consumers.contains(consumer)).unwrap_or(true)
+            && (!self.unconsumed || record.consumers.is_empty())
+    }
+}
+
+//The "events" command of the debug console: the query is the rest of the line.
+pub fn console_query(log: &EventLog, arguments: &str) -> Vec<String> {
+    match EventQuery::parse(arguments) {
+        Ok(query) => {
+            let mut lines: Vec<String> = log.query(&query).iter().map(|record| record.to_line()).collect();
+            lines.push(format!("{} events", lines.len()));
+            lines
+        },
+        Err(error) => vec![format!("{}", error)],
+    }
+}
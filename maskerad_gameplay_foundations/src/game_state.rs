@@ -0,0 +1,252 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ GAME STATES.
+
+ The game is a stack of states: MainMenu, Loading, InGame, Pause...
+ Only the state on top of the stack is updated. When drawing, we start from the top and go down
+ while the states are transparent, so the pause menu is drawn over the game.
+
+ A state asks for a transition by returning it from its update function.
+*/
+
+pub enum StateTransition {
+    None,
+    Push(Box<GameState>),
+    Pop,
+    Replace(Box<GameState>),
+    Quit,
+}
+
+pub trait GameState {
+    fn name(&self) -> &str;
+
+    //Called when the state is pushed on the stack.
+    fn on_enter(&mut self) {}
+    //Called when the state is removed from the stack.
+    fn on_exit(&mut self) {}
+    //Called when another state is pushed over this one.
+    fn on_pause(&mut self) {}
+    //Called when this state is on top of the stack again.
+    fn on_resume(&mut self) {}
+
+    fn update(&mut self, delta_seconds: f64) -> StateTransition;
+
+    //interpolation is the fraction of a fixed step not simulated yet, see the game loop.
+    fn draw(&mut self, _interpolation: f64) {}
+
+    //A transparent state lets the states under it be drawn.
+    fn is_transparent(&self) -> bool {
+        false
+    }
+}
+
+pub struct GameStateStack {
+    states: Vec<Box<GameState>>,
+    quit: bool,
+}
+
+impl Default for GameStateStack {
+    fn default() -> Self {
+        GameStateStack {
+            states: Vec::new(),
+            quit: false,
+        }
+    }
+}
+
+impl GameStateStack {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    //The game loop stops when the stack is empty, or a state asked to quit.
+    pub fn is_running(&self) -> bool {
+        !self.quit && !self.states.is_empty()
+    }
+
+    pub fn top(&self) -> Option<&GameState> {
+        self.states.last().map(|state| state.as_ref())
+    }
+
+    pub fn push(&mut self, mut state: Box<GameState>) {
+        debug!("Pushing the game state {}.", state.name());
+        if let Some(top) = self.states.last_mut() {
+            top.on_pause();
+        }
+        state.on_enter();
+        self.states.push(state);
+    }
+
+    pub fn pop(&mut self) -> Option<Box<GameState>> {
+        let mut state = self.states.pop()?;
+        debug!("Popping the game state {}.", state.name());
+        state.on_exit();
+        if let Some(top) = self.states.last_mut() {
+            top.on_resume();
+        }
+        Some(state)
+    }
+
+    pub fn replace(&mut self, mut state: Box<GameState>) -> Option<Box<GameState>> {
+        debug!("Replacing the top game state with {}.", state.name());
+        let previous = self.states.pop().map(|mut previous| {
+            previous.on_exit();
+            previous
+        });
+        state.on_enter();
+        self.states.push(state);
+        previous
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+
+    pub fn update(&mut self, delta_seconds: f64) {
+        let transition = match self.states.last_mut() {
+            Some(top) => top.update(delta_seconds),
+            None => return,
+        };
+
+        match transition {
+            StateTransition::None => {},
+            StateTransition::Push(state) => self.push(state),
+            StateTransition::Pop => {
+                self.pop();
+            },
+            StateTransition::Replace(state) => {
+                self.replace(state);
+            },
+            StateTransition::Quit => {
+                debug!("A game state asked to quit the game.");
+                self.quit = true;
+            },
+        }
+    }
+
+    pub fn draw(&mut self, interpolation: f64) {
+        let mut first_visible = self.states.len();
+        while first_visible > 0 {
+            first_visible -= 1;
+            if !self.states[first_visible].is_transparent() {
+                break;
+            }
+        }
+
+        for state in self.states.iter_mut().skip(first_visible) {
+            state.draw(interpolation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod game_state_test {
+    use super::*;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    struct RecordingState {
+        name: &'static str,
+        transparent: bool,
+        log: Rc<RefCell<Vec<String>>>,
+        next: Option<StateTransition>,
+    }
+
+    impl RecordingState {
+        fn boxed(name: &'static str, transparent: bool, log: &Rc<RefCell<Vec<String>>>) -> Box<GameState> {
+            Box::new(RecordingState {
+                name,
+                transparent,
+                log: log.clone(),
+                next: None,
+            })
+        }
+    }
+
+    impl GameState for RecordingState {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn on_enter(&mut self) {
+            self.log.borrow_mut().push(format!("enter {}", self.name));
+        }
+
+        fn on_exit(&mut self) {
+            self.log.borrow_mut().push(format!("exit {}", self.name));
+        }
+
+        fn on_pause(&mut self) {
+            self.log.borrow_mut().push(format!("pause {}", self.name));
+        }
+
+        fn on_resume(&mut self) {
+            self.log.borrow_mut().push(format!("resume {}", self.name));
+        }
+
+        fn update(&mut self, _delta_seconds: f64) -> StateTransition {
+            self.log.borrow_mut().push(format!("update {}", self.name));
+            self.next.take().unwrap_or(StateTransition::None)
+        }
+
+        fn draw(&mut self, _interpolation: f64) {
+            self.log.borrow_mut().push(format!("draw {}", self.name));
+        }
+
+        fn is_transparent(&self) -> bool {
+            self.transparent
+        }
+    }
+
+    #[test]
+    fn game_state_stack_overlays() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut stack = GameStateStack::new();
+        stack.push(RecordingState::boxed("menu", false, &log));
+        stack.replace(RecordingState::boxed("in_game", false, &log));
+        stack.push(RecordingState::boxed("pause", true, &log));
+        log.borrow_mut().clear();
+
+        stack.update(0.016);
+        stack.draw(0.0);
+        assert_eq!(*log.borrow(), vec!["update pause", "draw in_game", "draw pause"]);
+
+        log.borrow_mut().clear();
+        stack.pop();
+        assert_eq!(*log.borrow(), vec!["exit pause", "resume in_game"]);
+        assert_eq!(stack.top().unwrap().name(), "in_game");
+    }
+
+    #[test]
+    fn game_state_stack_transitions() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut stack = GameStateStack::new();
+        stack.push(Box::new(RecordingState {
+            name: "loading",
+            transparent: false,
+            log: log.clone(),
+            next: Some(StateTransition::Replace(RecordingState::boxed("in_game", false, &log))),
+        }));
+        stack.update(0.016);
+        assert_eq!(stack.len(), 1);
+        assert_eq!(stack.top().unwrap().name(), "in_game");
+        assert!(stack.is_running());
+
+        stack.clear();
+        assert!(!stack.is_running());
+    }
+}
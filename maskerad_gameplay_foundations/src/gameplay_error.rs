@@ -0,0 +1,121 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::error::Error;
+use std::fmt;
+use std::io::Error as IOError;
+use serde_json::Error as JSONError;
+
+#[derive(Debug)]
+pub enum GameplayError {
+    AssetError(String, JSONError),
+    IOError(String, IOError),
+    DebuggerError(String),
+    ScriptError(String),
+    EventLogError(String),
+    SaveError(String),
+    ReplayError(String),
+}
+
+unsafe impl Send for GameplayError {}
+unsafe impl Sync for GameplayError {}
+
+impl fmt::Display for GameplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &GameplayError::AssetError(ref description, _) => {
+                write!(f, "Gameplay asset error: {}", description)
+            },
+            &GameplayError::IOError(ref description, _) => {
+                write!(f, "I/O error: {}", description)
+            },
+            &GameplayError::DebuggerError(ref description) => {
+                write!(f, "Script debugger error: {}", description)
+            },
+            &GameplayError::ScriptError(ref description) => {
+                write!(f, "Script error: {}", description)
+            },
+            &GameplayError::EventLogError(ref description) => {
+                write!(f, "Event log error: {}", description)
+            },
+            &GameplayError::SaveError(ref description) => {
+                write!(f, "Save error: {}", description)
+            },
+            &GameplayError::ReplayError(ref description) => {
+                write!(f, "Replay error: {}", description)
+            },
+        }
+    }
+}
+
+impl Error for GameplayError {
+    fn description(&self) -> &str {
+        match self {
+            &GameplayError::AssetError(_, _) => {
+                "AssetError"
+            },
+            &GameplayError::IOError(_, _) => {
+                "IOError"
+            },
+            &GameplayError::DebuggerError(_) => {
+                "DebuggerError"
+            },
+            &GameplayError::ScriptError(_) => {
+                "ScriptError"
+            },
+            &GameplayError::EventLogError(_) => {
+                "EventLogError"
+            },
+            &GameplayError::SaveError(_) => {
+                "SaveError"
+            },
+            &GameplayError::ReplayError(_) => {
+                "ReplayError"
+            },
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match self {
+            &GameplayError::AssetError(_, ref json_error) => {
+                Some(json_error)
+            },
+            &GameplayError::IOError(_, ref io_error) => {
+                Some(io_error)
+            },
+            &GameplayError::DebuggerError(_) => {
+                None
+            },
+            &GameplayError::ScriptError(_) => {
+                None
+            },
+            &GameplayError::EventLogError(_) => {
+                None
+            },
+            &GameplayError::SaveError(_) => {
+                None
+            },
+            &GameplayError::ReplayError(_) => {
+                None
+            },
+        }
+    }
+}
+
+pub type GameplayResult<T> = Result<T, GameplayError>;
+
+impl From<JSONError> for GameplayError {
+    fn from(error: JSONError) -> Self {
+        GameplayError::AssetError(String::from("Error while deserializing a gameplay asset."), error)
+    }
+}
+
+impl From<IOError> for GameplayError {
+    fn from(error: IOError) -> Self {
+        GameplayError::IOError(String::from("Error while doing I/O operations."), error)
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ HOT RELOAD STATE.
+
+ A gameplay system marks the state that must survive a code reload by implementing ReloadSurvivable:
+ it lists its state objects, by key. The types of these objects are registered in the type registry.
+
+ Before a plugin or script reload, a ReloadSnapshot copies the reflected fields of each object.
+ After the reload, the fields are written back, by name: a field removed by the new code is dropped,
+ a field added by the new code keeps the value given by the new code. A field whose type changed is dropped.
+
+ let snapshot = ReloadSnapshot::capture(&registry, &[&combat_system]);
+ ...reload...
+ snapshot.restore(&registry, &mut [&mut combat_system]);
+*/
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use maskerad_core::reflection::{TypeRegistry, Value};
+use gameplay_error::GameplayResult;
+use scripting::script_backend::ScriptBackend;
+
+pub trait ReloadSurvivable {
+    //The name of the system, the keys of its state are relative to it.
+    fn reload_name(&self) -> &str;
+
+    fn reload_state(&self) -> Vec<(&str, &Any)>;
+
+    fn reload_state_mut(&mut self) -> Vec<(&str, &mut Any)>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateSnapshot {
+    pub type_name: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReloadSnapshot {
+    //By "system/key".
+    states: BTreeMap<String, StateSnapshot>,
+}
+
+fn state_key(system: &str, key: &str) -> String {
+    format!("{}/{}", system, key)
+}
+
+impl ReloadSnapshot {
+    pub fn capture(registry: &TypeRegistry, systems: &[&ReloadSurvivable]) -> Self {
+        let mut snapshot = ReloadSnapshot::default();
+        for system in systems.iter() {
+            for (key, object) in system.reload_state() {
+                match registry.type_info_of(object) {
+                    Some(info) => {
+                        snapshot.states.insert(state_key(system.reload_name(), key), StateSnapshot {
+                            type_name: info.name().to_string(),
+                            fields: registry.read_fields(object).into_iter()
+                                .map(|(name, value)| (name.to_string(), value))
+                                .collect(),
+                        });
+                    },
+                    None => warn!("The state {} of {} is not registered in the type registry, it will not survive the reload.", key, system.reload_name()),
+                }
+            }
+        }
+        debug!("Captured {} gameplay states before a reload.", snapshot.states.len());
+        snapshot
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn state(&self, system: &str, key: &str) -> Option<&StateSnapshot> {
+        self.states.get(&state_key(system, key))
+    }
+
+    //Write the captured fields back. Returns the number of restored fields.
+    pub fn restore(&self, registry: &TypeRegistry, systems: &mut [&mut ReloadSurvivable]) -> usize {
+        let mut restored = 0;
+        for system in systems.iter_mut() {
+            let name = system.reload_name().to_string();
+            for (key, object) in system.reload_state_mut() {
+                let state = match self.states.get(&state_key(name.as_str(), key)) {
+                    Some(state) => state,
+                    None => {
+                        trace!("No captured state for {} of {}.", key, name);
+                        continue;
+                    },
+                };
+                if registry.type_info_of(object).map(|info| info.name()) != Some(state.type_name.as_str()) {
+                    warn!("The state {} of {} is not a {} anymore, it is not restored.", key, name, state.type_name);
+                    continue;
+                }
+                for &(ref field, ref value) in state.fields.iter() {
+                    if registry.write_field(object, field.as_str(), value.clone()) {
+                        restored += 1;
+                    } else {
+                        warn!("The field {} of the state {} of {} is dropped by the reload.", field, key, name);
+                    }
+                }
+            }
+        }
+        debug!("Restored {} fields after a reload.", restored);
+        restored
+    }
+}
+
+//Reload a script module, keeping the state of the systems.
+pub fn reload_script(backend: &mut ScriptBackend, registry: &TypeRegistry, systems: &mut [&mut ReloadSurvivable], module: &str, source: &[u8]) -> GameplayResult<usize> {
+    let snapshot = {
+        let systems: Vec<&ReloadSurvivable> = systems.iter().map(|system| &**system as &ReloadSurvivable).collect();
+        ReloadSnapshot::capture(registry, systems.as_slice())
+    };
+    backend.load_module(module, source)?;
+    Ok(snapshot.restore(registry, systems))
+}
+
+#[cfg(test)]
+mod hot_reload_test {
+    use super::*;
+    use maskerad_core::reflection::{TypeInfo, FieldInfo, FieldType};
+
+    struct Wave {
+        number: i64,
+        boss: bool,
+    }
+
+    struct WaveSystem {
+        wave: Wave,
+        cooldown: f64,
+    }
+
+    impl ReloadSurvivable for WaveSystem {
+        fn reload_name(&self) -> &str {
+            "waves"
+        }
+
+        fn reload_state(&self) -> Vec<(&str, &Any)> {
+            vec![("wave", &self.wave as &Any), ("cooldown", &self.cooldown as &Any)]
+        }
+
+        fn reload_state_mut(&mut self) -> Vec<(&str, &mut Any)> {
+            vec![("wave", &mut self.wave as &mut Any), ("cooldown", &mut self.cooldown as &mut Any)]
+        }
+    }
+
+    fn registry(with_boss: bool) -> TypeRegistry {
+        let mut info = TypeInfo::new::<Wave>("Wave")
+            .with_field(FieldInfo::new("number", FieldType::Int, |wave: &Wave| Value::Int(wave.number), |wave: &mut Wave, value| {
+                match value {
+                    Value::Int(value) => {
+                        wave.number = value;
+                        true
+                    },
+                    _ => false,
+                }
+            }));
+        if with_boss {
+            info = info.with_field(FieldInfo::new("boss", FieldType::Bool, |wave: &Wave| Value::Bool(wave.boss), |wave: &mut Wave, value| {
+                match value {
+                    Value::Bool(value) => {
+                        wave.boss = value;
+                        true
+                    },
+                    _ => false,
+                }
+            }));
+        }
+        let mut registry = TypeRegistry::new();
+        registry.register(info);
+        registry
+    }
+
+    #[test]
+    fn hot_reload_keeps_fields_by_name() {
+        let system = WaveSystem { wave: Wave { number: 7, boss: true }, cooldown: 2.0 };
+        let snapshot = ReloadSnapshot::capture(&registry(true), &[&system]);
+        //The cooldown is not registered.
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot.state("waves", "wave").unwrap().fields.len(), 2);
+
+        //The new code doesn't have the boss field anymore.
+        let mut reloaded = WaveSystem { wave: Wave { number: 0, boss: false }, cooldown: 0.0 };
+        assert_eq!(snapshot.restore(&registry(false), &mut [&mut reloaded]), 1);
+        assert_eq!(reloaded.wave.number, 7);
+        assert!(!reloaded.wave.boss);
+    }
+}
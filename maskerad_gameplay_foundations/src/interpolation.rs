@@ -0,0 +1,274 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ INTERPOLATION BETWEEN FIXED UPDATES.
+
+ The simulation runs with a fixed time step, the rendering at the rate of the display. When the two
+ rates differ, rendering the state of the last tick makes the motion stutter: some frames show the
+ same tick twice, some frames skip one.
+
+ FixedTimestep accumulates the frame time, tells how many ticks to run, and gives the fraction of a
+ tick left in the accumulator (alpha). The marked components keep their values of the last two ticks,
+ and the renderer samples them with alpha:
+ - Interpolate: between the previous and the last tick. Always correct, but one tick late.
+ - Extrapolate: beyond the last tick, along the motion of the last tick. No latency, but wrong when
+   the motion changes (at most one tick is extrapolated).
+
+ Teleported objects (respawn, cutscene cut...) must use teleport(), or they are seen crossing the
+ level for one tick.
+*/
+
+use std::collections::HashMap;
+use std::collections::hash_map::Iter;
+use std::time::Duration;
+use maskerad_core::math::transform::{lerp_vectors, Transform};
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    step: Duration,
+    accumulator: Duration,
+    //After a hitch, the ticks over this count are dropped, or the simulation never catches up.
+    max_ticks_per_frame: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(step: Duration) -> Self {
+        assert!(step > Duration::from_secs(0), "The fixed time step cannot be null.");
+        FixedTimestep {
+            step,
+            accumulator: Duration::from_secs(0),
+            max_ticks_per_frame: 8,
+        }
+    }
+
+    pub fn with_max_ticks_per_frame(mut self, max_ticks_per_frame: u32) -> Self {
+        self.max_ticks_per_frame = max_ticks_per_frame.max(1);
+        self
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    //Add the time of the frame, returns the number of ticks to run.
+    pub fn advance(&mut self, frame_time: Duration) -> u32 {
+        self.accumulator += frame_time;
+        let mut ticks = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            ticks += 1;
+        }
+        if ticks > self.max_ticks_per_frame {
+            debug!("{} ticks were late, {} of them are dropped.", ticks, ticks - self.max_ticks_per_frame);
+            ticks = self.max_ticks_per_frame;
+        }
+        ticks
+    }
+
+    //The fraction of a tick elapsed since the last tick, in [0, 1).
+    pub fn alpha(&self) -> f32 {
+        (seconds(self.accumulator) / seconds(self.step)) as f32
+    }
+}
+
+pub trait Interpolate {
+    //t = 0 gives self, t = 1 gives next, t > 1 extrapolates.
+    fn interpolate(&self, next: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, next: &f32, t: f32) -> f32 {
+        self + (next - self) * t
+    }
+}
+
+impl Interpolate for [f32; 3] {
+    fn interpolate(&self, next: &[f32; 3], t: f32) -> [f32; 3] {
+        lerp_vectors(*self, *next, t)
+    }
+}
+
+impl Interpolate for Transform {
+    fn interpolate(&self, next: &Transform, t: f32) -> Transform {
+        self.lerp(next, t)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum InterpolationMode {
+    //The value of the last tick, as is.
+    None,
+    Interpolate,
+    Extrapolate,
+}
+
+impl Default for InterpolationMode {
+    fn default() -> Self {
+        InterpolationMode::Interpolate
+    }
+}
+
+//The values of a component at the last two ticks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interpolated<T> {
+    previous: T,
+    current: T,
+}
+
+impl<T: Interpolate + Clone> Interpolated<T> {
+    pub fn new(value: T) -> Self {
+        Interpolated {
+            previous: value.clone(),
+            current: value,
+        }
+    }
+
+    //At the end of a tick.
+    pub fn push(&mut self, value: T) {
+        self.previous = ::std::mem::replace(&mut self.current, value);
+    }
+
+    //Moves without any interpolation.
+    pub fn teleport(&mut self, value: T) {
+        self.previous = value.clone();
+        self.current = value;
+    }
+
+    pub fn current(&self) -> &T {
+        &self.current
+    }
+
+    pub fn sample(&self, alpha: f32, mode: InterpolationMode) -> T {
+        let alpha = alpha.max(0.0).min(1.0);
+        match mode {
+            InterpolationMode::None => self.current.clone(),
+            InterpolationMode::Interpolate => self.previous.interpolate(&self.current, alpha),
+            InterpolationMode::Extrapolate => self.previous.interpolate(&self.current, 1.0 + alpha),
+        }
+    }
+}
+
+//The marked components of a type, by entity.
+#[derive(Debug, Clone)]
+pub struct InterpolationSet<T> {
+    mode: InterpolationMode,
+    values: HashMap<u64, Interpolated<T>>,
+}
+
+impl<T: Interpolate + Clone> Default for InterpolationSet<T> {
+    fn default() -> Self {
+        InterpolationSet {
+            mode: InterpolationMode::default(),
+            values: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Interpolate + Clone> InterpolationSet<T> {
+    pub fn new(mode: InterpolationMode) -> Self {
+        InterpolationSet {
+            mode,
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn mode(&self) -> InterpolationMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: InterpolationMode) {
+        self.mode = mode;
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn is_marked(&self, entity: u64) -> bool {
+        self.values.contains_key(&entity)
+    }
+
+    pub fn mark(&mut self, entity: u64, value: T) {
+        self.values.insert(entity, Interpolated::new(value));
+    }
+
+    pub fn unmark(&mut self, entity: u64) -> bool {
+        self.values.remove(&entity).is_some()
+    }
+
+    //At the end of a tick. An entity stored for the first time is marked.
+    pub fn store(&mut self, entity: u64, value: T) {
+        if self.values.contains_key(&entity) {
+            self.values.get_mut(&entity).unwrap().push(value);
+        } else {
+            self.mark(entity, value);
+        }
+    }
+
+    pub fn teleport(&mut self, entity: u64, value: T) {
+        if self.values.contains_key(&entity) {
+            self.values.get_mut(&entity).unwrap().teleport(value);
+        } else {
+            self.mark(entity, value);
+        }
+    }
+
+    pub fn sample(&self, entity: u64, alpha: f32) -> Option<T> {
+        self.values.get(&entity).map(|interpolated| interpolated.sample(alpha, self.mode))
+    }
+
+    pub fn iter<'a>(&'a self) -> Iter<'a, u64, Interpolated<T>> {
+        self.values.iter()
+    }
+}
+
+#[cfg(test)]
+mod interpolation_test {
+    use super::*;
+
+    #[test]
+    fn fixed_timestep_alpha() {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(10)).with_max_ticks_per_frame(3);
+        //A 144 Hz display with a 100 Hz simulation.
+        assert_eq!(timestep.advance(Duration::from_millis(7)), 0);
+        assert!((timestep.alpha() - 0.7).abs() < 1e-5);
+        assert_eq!(timestep.advance(Duration::from_millis(7)), 1);
+        assert!((timestep.alpha() - 0.4).abs() < 1e-5);
+        //A hitch: the late ticks are dropped, not the remainder.
+        assert_eq!(timestep.advance(Duration::from_millis(100)), 3);
+        assert!((timestep.alpha() - 0.4).abs() < 1e-5);
+    }
+
+    #[test]
+    fn sample_marked_transforms() {
+        let mut set = InterpolationSet::new(InterpolationMode::Interpolate);
+        set.store(1, Transform::from_position([0.0, 0.0, 0.0]));
+        set.store(1, Transform::from_position([2.0, 0.0, 0.0]));
+        assert_eq!(set.sample(1, 0.25).unwrap().position, [0.5, 0.0, 0.0]);
+        assert_eq!(set.sample(2, 0.25), None);
+
+        set.set_mode(InterpolationMode::Extrapolate);
+        assert_eq!(set.sample(1, 0.5).unwrap().position, [3.0, 0.0, 0.0]);
+        set.set_mode(InterpolationMode::None);
+        assert_eq!(set.sample(1, 0.5).unwrap().position, [2.0, 0.0, 0.0]);
+
+        set.set_mode(InterpolationMode::Interpolate);
+        set.teleport(1, Transform::from_position([100.0, 0.0, 0.0]));
+        assert_eq!(set.sample(1, 0.1).unwrap().position, [100.0, 0.0, 0.0]);
+        assert!(set.unmark(1));
+        assert!(set.is_empty());
+    }
+}
@@ -5,7 +5,37 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+extern crate maskerad_core;
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate serde_json;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate imagefmt;
+
+#[cfg(feature = "wasm")]
+extern crate wasmtime;
+
 pub mod artificial_intelligence;
 pub mod event;
 pub mod scripting;
-pub mod game_loop;
\ No newline at end of file
+pub mod game_loop;
+pub mod gameplay_error;
+pub mod timeline;
+pub mod game_state;
+pub mod loading;
+pub mod replay;
+pub mod hot_reload;
+pub mod photo_mode;
+pub mod save_thumbnail;
+pub mod world_map;
+pub mod window_events;
+pub mod interpolation;
+pub mod event_log;
+pub mod ecs;
+
+//The version of the gameplay subsystem.
+pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,216 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ LOADING SCREENS.
+
+ Asset loads, streaming cells, shader compilation... report their progress to a LoadingProgress,
+ per category ("models", "sounds", "streaming"...).
+
+ The LoadingState is a game state displaying the loading screen. It stays on top of the stack
+ until the readiness predicate given by the user passes, then it replaces itself with the next state.
+ Usually the predicate is "everything is loaded", but a game might wait for a key press, or only
+ for the assets around the player.
+*/
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+use game_state::{GameState, StateTransition};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CategoryProgress {
+    pub completed: u64,
+    pub total: u64,
+    //How much this category counts in the global progress.
+    pub weight: f32,
+}
+
+impl CategoryProgress {
+    pub fn ratio(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            (self.completed.min(self.total) as f64 / self.total as f64) as f32
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoadingProgress {
+    categories: BTreeMap<String, CategoryProgress>,
+}
+
+impl LoadingProgress {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn category_mut(&mut self, category: &str) -> &mut CategoryProgress {
+        self.categories.entry(category.to_string()).or_insert(CategoryProgress {
+            completed: 0,
+            total: 0,
+            weight: 1.0,
+        })
+    }
+
+    pub fn set_weight<S: AsRef<str>>(&mut self, category: S, weight: f32) {
+        self.category_mut(category.as_ref()).weight = weight;
+    }
+
+    //Some work (items, bytes...) has been scheduled in this category.
+    pub fn add_pending<S: AsRef<str>>(&mut self, category: S, amount: u64) {
+        trace!("{} units of work scheduled in the loading category {}.", amount, category.as_ref());
+        self.category_mut(category.as_ref()).total += amount;
+    }
+
+    pub fn complete<S: AsRef<str>>(&mut self, category: S, amount: u64) {
+        trace!("{} units of work completed in the loading category {}.", amount, category.as_ref());
+        self.category_mut(category.as_ref()).completed += amount;
+    }
+
+    //Overwrite the progress of a category, for sources tracking their own progress.
+    pub fn report<S: AsRef<str>>(&mut self, category: S, completed: u64, total: u64) {
+        let progress = self.category_mut(category.as_ref());
+        progress.completed = completed;
+        progress.total = total;
+    }
+
+    pub fn category(&self, category: &str) -> Option<&CategoryProgress> {
+        self.categories.get(category)
+    }
+
+    //The progress of each category, in [0; 1].
+    pub fn breakdown(&self) -> Vec<(&str, f32)> {
+        self.categories.iter().map(|(name, progress)| (name.as_str(), progress.ratio())).collect()
+    }
+
+    //The weighted progress of all the categories, in [0; 1].
+    pub fn progress(&self) -> f32 {
+        let total_weight: f32 = self.categories.values().map(|progress| progress.weight).sum();
+        if total_weight <= 0.0 {
+            return 1.0;
+        }
+        self.categories.values()
+            .map(|progress| progress.ratio() * progress.weight)
+            .sum::<f32>() / total_weight
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.categories.values().all(|progress| progress.completed >= progress.total)
+    }
+
+    pub fn clear(&mut self) {
+        self.categories.clear();
+    }
+}
+
+pub struct LoadingState {
+    progress: Rc<RefCell<LoadingProgress>>,
+    next_state: Option<Box<GameState>>,
+    is_ready: Box<FnMut(&LoadingProgress) -> bool>,
+    draw_screen: Option<Box<FnMut(&LoadingProgress, f64)>>,
+}
+
+impl LoadingState {
+    pub fn new<F>(progress: Rc<RefCell<LoadingProgress>>, next_state: Box<GameState>, is_ready: F) -> Self where
+        F: FnMut(&LoadingProgress) -> bool + 'static
+    {
+        LoadingState {
+            progress,
+            next_state: Some(next_state),
+            is_ready: Box::new(is_ready),
+            draw_screen: None,
+        }
+    }
+
+    //Wait until every category is complete.
+    pub fn until_complete(progress: Rc<RefCell<LoadingProgress>>, next_state: Box<GameState>) -> Self {
+        LoadingState::new(progress, next_state, |progress: &LoadingProgress| progress.is_complete())
+    }
+
+    pub fn with_screen<D>(mut self, draw_screen: D) -> Self where
+        D: FnMut(&LoadingProgress, f64) + 'static
+    {
+        self.draw_screen = Some(Box::new(draw_screen));
+        self
+    }
+}
+
+impl GameState for LoadingState {
+    fn name(&self) -> &str {
+        "loading"
+    }
+
+    fn update(&mut self, _delta_seconds: f64) -> StateTransition {
+        let ready = (self.is_ready)(&self.progress.borrow());
+        if !ready {
+            return StateTransition::None;
+        }
+
+        match self.next_state.take() {
+            Some(next_state) => {
+                debug!("Loading finished, switching to the game state {}.", next_state.name());
+                StateTransition::Replace(next_state)
+            },
+            None => StateTransition::Pop,
+        }
+    }
+
+    fn draw(&mut self, interpolation: f64) {
+        if let Some(ref mut draw_screen) = self.draw_screen {
+            draw_screen(&self.progress.borrow(), interpolation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod loading_test {
+    use super::*;
+    use game_state::GameStateStack;
+
+    struct InGame;
+
+    impl GameState for InGame {
+        fn name(&self) -> &str {
+            "in_game"
+        }
+
+        fn update(&mut self, _delta_seconds: f64) -> StateTransition {
+            StateTransition::None
+        }
+    }
+
+    #[test]
+    fn loading_progress_breakdown() {
+        let mut progress = LoadingProgress::new();
+        progress.add_pending("models", 4);
+        progress.add_pending("sounds", 2);
+        progress.set_weight("models", 3.0);
+        progress.complete("models", 2);
+        progress.complete("sounds", 2);
+
+        assert_eq!(progress.breakdown(), vec![("models", 0.5), ("sounds", 1.0)]);
+        assert_eq!(progress.progress(), (0.5 * 3.0 + 1.0) / 4.0);
+        assert!(!progress.is_complete());
+    }
+
+    #[test]
+    fn loading_state_waits_for_readiness() {
+        let progress = Rc::new(RefCell::new(LoadingProgress::new()));
+        progress.borrow_mut().add_pending("streaming", 1);
+
+        let mut stack = GameStateStack::new();
+        stack.push(Box::new(LoadingState::until_complete(progress.clone(), Box::new(InGame))));
+        stack.update(0.016);
+        assert_eq!(stack.top().unwrap().name(), "loading");
+
+        progress.borrow_mut().complete("streaming", 1);
+        stack.update(0.016);
+        assert_eq!(stack.top().unwrap().name(), "in_game");
+    }
+}
@@ -0,0 +1,307 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ PHOTO MODE.
+
+ Entering the photo mode freezes the gameplay and vfx time channels (the ui channel keeps running, for the
+ photo mode menu), hides the game UI, and gives the control of a free camera to the player.
+
+ - The camera flies from the gameplay camera, within a maximum distance of it. The collisions are optional:
+   the game gives a sweep function, which returns where the camera stops.
+ - The post-process overrides (depth of field, color filter, exposure, vignette) are applied by the
+   renderer while the photo mode is active.
+ - A photo larger than the screen is captured in tiles: the projection is cut in a grid, each tile is
+   rendered at the screen resolution by the screenshot service, and the tiles are stitched.
+
+ Leaving the photo mode restores the time channels as they were.
+*/
+
+use maskerad_core::time_channels::{TimeChannels, GAMEPLAY_CHANNEL, VFX_CHANNEL};
+
+const FROZEN_CHANNELS: [&'static str; 2] = [GAMEPLAY_CHANNEL, VFX_CHANNEL];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FreeCamera {
+    pub position: [f32; 3],
+    //In radians.
+    pub yaw: f32,
+    pub pitch: f32,
+    pub vertical_fov: f32,
+    pub roll: f32,
+}
+
+impl FreeCamera {
+    pub fn forward(&self) -> [f32; 3] {
+        [self.yaw.sin() * self.pitch.cos(), self.pitch.sin(), -self.yaw.cos() * self.pitch.cos()]
+    }
+
+    pub fn right(&self) -> [f32; 3] {
+        [self.yaw.cos(), 0.0, self.yaw.sin()]
+    }
+}
+
+//The fly controls of a frame: move is (right, up, forward) and look is (yaw, pitch), between -1 and 1.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct FlyInput {
+    pub movement: [f32; 3],
+    pub look: [f32; 2],
+    pub boost: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhotoModeSettings {
+    //In units per second.
+    pub fly_speed: f32,
+    pub boost_factor: f32,
+    //In radians per second.
+    pub look_speed: f32,
+    pub max_distance: f32,
+    pub collisions: bool,
+}
+
+impl Default for PhotoModeSettings {
+    fn default() -> Self {
+        PhotoModeSettings {
+            fly_speed: 4.0,
+            boost_factor: 4.0,
+            look_speed: 1.5,
+            max_distance: 20.0,
+            collisions: true,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DepthOfField {
+    pub focus_distance: f32,
+    pub aperture: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PostProcessOverrides {
+    pub depth_of_field: Option<DepthOfField>,
+    //The name of a color grading filter.
+    pub filter: Option<String>,
+    pub exposure: Option<f32>,
+    pub vignette: Option<f32>,
+}
+
+//A part of a high-resolution photo, rendered at the screen resolution.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CaptureTile {
+    pub column: u32,
+    pub row: u32,
+    //The sub-frustum of the tile, in normalized device coordinates: (left, right, bottom, top).
+    pub frustum: [f32; 4],
+}
+
+//Renders a tile with the sub-frustum, and returns its RGBA8 pixels at the screen resolution.
+pub trait ScreenshotService {
+    fn capture(&mut self, tile: &CaptureTile) -> Vec<u8>;
+}
+
+pub struct PhotoMode {
+    settings: PhotoModeSettings,
+    camera: Option<FreeCamera>,
+    anchor: [f32; 3],
+    overrides: PostProcessOverrides,
+    hide_ui: bool,
+    paused_channels: Vec<(&'static str, bool)>,
+}
+
+impl PhotoMode {
+    pub fn new(settings: PhotoModeSettings) -> Self {
+        PhotoMode {
+            settings,
+            camera: None,
+            anchor: [0.0; 3],
+            overrides: PostProcessOverrides::default(),
+            hide_ui: true,
+            paused_channels: Vec::new(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.camera.is_some()
+    }
+
+    pub fn camera(&self) -> Option<&FreeCamera> {
+        self.camera.as_ref()
+    }
+
+    //The game UI is hidden while the photo mode is active, unless the player shows it.
+    pub fn hides_ui(&self) -> bool {
+        self.is_active() && self.hide_ui
+    }
+
+    pub fn set_hide_ui(&mut self, hide_ui: bool) {
+        self.hide_ui = hide_ui;
+    }
+
+    pub fn overrides(&self) -> Option<&PostProcessOverrides> {
+        if self.is_active() {Some(&self.overrides)} else {None}
+    }
+
+    pub fn overrides_mut(&mut self) -> &mut PostProcessOverrides {
+        &mut self.overrides
+    }
+
+    //Start from the gameplay camera.
+    pub fn enter(&mut self, gameplay_camera: FreeCamera, channels: &mut TimeChannels) {
+        if self.is_active() {
+            return;
+        }
+        debug!("Entering the photo mode.");
+        self.paused_channels = FROZEN_CHANNELS.iter().map(|&channel| (channel, channels.is_paused(channel))).collect();
+        for &channel in FROZEN_CHANNELS.iter() {
+            channels.set_paused(channel, true);
+        }
+        self.anchor = gameplay_camera.position;
+        self.camera = Some(gameplay_camera);
+        self.overrides = PostProcessOverrides::default();
+        self.hide_ui = true;
+    }
+
+    pub fn exit(&mut self, channels: &mut TimeChannels) {
+        if self.camera.take().is_some() {
+            debug!("Leaving the photo mode.");
+            for (channel, paused) in self.paused_channels.drain(..) {
+                channels.set_paused(channel, paused);
+            }
+        }
+    }
+
+    //Move the camera. The sweep, used when the collisions are enabled, returns where the camera stops
+    //when moving from a position to another. The delta time is the one of the ui channel.
+    pub fn fly(&mut self, input: FlyInput, delta_seconds: f32, sweep: Option<&Fn([f32; 3], [f32; 3]) -> [f32; 3]>) {
+        let settings = self.settings;
+        let anchor = self.anchor;
+        let camera = match self.camera {
+            Some(ref mut camera) => camera,
+            None => return,
+        };
+        camera.yaw += input.look[0] * settings.look_speed * delta_seconds;
+        camera.pitch = (camera.pitch + input.look[1] * settings.look_speed * delta_seconds).max(-1.5).min(1.5);
+
+        let speed = settings.fly_speed * if input.boost {settings.boost_factor} else {1.0} * delta_seconds;
+        let (forward, right) = (camera.forward(), camera.right());
+        let mut target = camera.position;
+        for axis in 0..3 {
+            target[axis] += (right[axis] * input.movement[0] + forward[axis] * input.movement[2]) * speed;
+        }
+        target[1] += input.movement[1] * speed;
+
+        //Kept within the maximum distance of the gameplay camera.
+        let offset = [target[0] - anchor[0], target[1] - anchor[1], target[2] - anchor[2]];
+        let distance = offset.iter().map(|value| value * value).sum::<f32>().sqrt();
+        if distance > settings.max_distance {
+            let ratio = settings.max_distance / distance;
+            for axis in 0..3 {
+                target[axis] = anchor[axis] + offset[axis] * ratio;
+            }
+        }
+        camera.position = match sweep {
+            Some(sweep) if settings.collisions => sweep(camera.position, target),
+            _ => target,
+        };
+    }
+
+    //The tiles of a photo 'scale' times larger than the screen, in each dimension.
+    pub fn plan_capture(scale: u32) -> Vec<CaptureTile> {
+        let scale = scale.max(1);
+        let size = 2.0 / scale as f32;
+        let mut tiles = Vec::with_capacity((scale * scale) as usize);
+        for row in 0..scale {
+            for column in 0..scale {
+                let left = -1.0 + column as f32 * size;
+                let top = 1.0 - row as f32 * size;
+                tiles.push(CaptureTile {
+                    column,
+                    row,
+                    frustum: [left, left + size, top - size, top],
+                });
+            }
+        }
+        tiles
+    }
+
+    //Render the tiles and stitch them in one RGBA8 image of (width * scale) x (height * scale).
+    pub fn capture(&self, scale: u32, width: usize, height: usize, screenshots: &mut ScreenshotService) -> Vec<u8> {
+        let scale = scale.max(1);
+        let photo_width = width * scale as usize;
+        let mut photo = vec![0u8; photo_width * height * scale as usize * 4];
+        for tile in PhotoMode::plan_capture(scale) {
+            let pixels = screenshots.capture(&tile);
+            if pixels.len() != width * height * 4 {
+                error!("The screenshot of the tile {}x{} has {} bytes instead of {}.", tile.column, tile.row, pixels.len(), width * height * 4);
+                continue;
+            }
+            for line in 0..height {
+                let destination = ((tile.row as usize * height + line) * photo_width + tile.column as usize * width) * 4;
+                photo[destination..destination + width * 4].copy_from_slice(&pixels[line * width * 4..(line + 1) * width * 4]);
+            }
+        }
+        debug!("Captured a {}x{} photo.", photo_width, height * scale as usize);
+        photo
+    }
+}
+
+#[cfg(test)]
+mod photo_mode_test {
+    use super::*;
+
+    struct TileColors;
+
+    impl ScreenshotService for TileColors {
+        fn capture(&mut self, tile: &CaptureTile) -> Vec<u8> {
+            vec![(tile.row * 2 + tile.column) as u8; 2 * 2 * 4]
+        }
+    }
+
+    fn camera() -> FreeCamera {
+        FreeCamera {
+            position: [0.0, 1.0, 0.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            vertical_fov: 1.0,
+            roll: 0.0,
+        }
+    }
+
+    #[test]
+    fn photo_mode_freezes_time_and_flies() {
+        let mut channels = TimeChannels::new();
+        channels.set_paused(VFX_CHANNEL, true);
+        let mut photo_mode = PhotoMode::new(PhotoModeSettings::default());
+        photo_mode.enter(camera(), &mut channels);
+        assert!(channels.is_paused(GAMEPLAY_CHANNEL) && photo_mode.hides_ui());
+
+        //Forward is -z, and the camera stays within 20 units.
+        photo_mode.fly(FlyInput { movement: [0.0, 0.0, 1.0], look: [0.0, 0.0], boost: false }, 1.0, None);
+        assert_eq!(photo_mode.camera().unwrap().position, [0.0, 1.0, -4.0]);
+        photo_mode.fly(FlyInput { movement: [0.0, 0.0, 1.0], look: [0.0, 0.0], boost: true }, 2.0, None);
+        assert!((photo_mode.camera().unwrap().position[2] + 20.0).abs() < 0.001);
+        let floor = |_from: [f32; 3], to: [f32; 3]| [to[0], to[1].max(0.5), to[2]];
+        photo_mode.fly(FlyInput { movement: [0.0, -1.0, 0.0], look: [0.0, 0.0], boost: false }, 1.0, Some(&floor));
+        assert_eq!(photo_mode.camera().unwrap().position[1], 0.5);
+
+        photo_mode.exit(&mut channels);
+        assert!(!channels.is_paused(GAMEPLAY_CHANNEL) && channels.is_paused(VFX_CHANNEL));
+    }
+
+    #[test]
+    fn photo_mode_stitches_tiles() {
+        let photo_mode = PhotoMode::new(PhotoModeSettings::default());
+        assert_eq!(PhotoMode::plan_capture(2)[3].frustum, [0.0, 1.0, -1.0, 0.0]);
+        let photo = photo_mode.capture(2, 2, 2, &mut TileColors);
+        assert_eq!(photo.len(), 4 * 4 * 4);
+        //The first line: two pixels of the tile 0, two pixels of the tile 1.
+        assert_eq!(&photo[..16].iter().step_by(4).cloned().collect::<Vec<u8>>()[..], &[0, 0, 1, 1]);
+        assert_eq!(photo[(3 * 4 + 3) * 4], 3);
+    }
+}
@@ -0,0 +1,304 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ REPLAYS.
+
+ The simulation is deterministic if it runs with a fixed time step, the same RNG seed and the same
+ inputs. A replay stores exactly that: the seed, the input of each fixed tick, and a checksum of the
+ game state every few ticks.
+
+ When replaying, the game feeds the recorded inputs to the simulation and compares its own checksums
+ with the recorded ones. The first tick where they differ is reported, which is where the
+ non-determinism bug has to be looked for.
+*/
+
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+use gameplay_error::{GameplayError, GameplayResult};
+
+//FNV-1a over a fixed encoding: the integers are hashed as little-endian bytes, usize and isize as 64 bits.
+//Unlike the std hasher, the checksum of a state doesn't depend on the run or on the platform.
+//What the derived Hash implementations feed to the hasher may still change with the compiler version: a replay
+//is only guaranteed to match with the build of the game which recorded it.
+pub struct StateHasher(u64);
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        StateHasher(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, value: u128) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    fn write_i16(&mut self, value: i16) {
+        self.write_u16(value as u16);
+    }
+
+    fn write_i32(&mut self, value: i32) {
+        self.write_u32(value as u32);
+    }
+
+    fn write_i64(&mut self, value: i64) {
+        self.write_u64(value as u64);
+    }
+
+    fn write_i128(&mut self, value: i128) {
+        self.write_u128(value as u128);
+    }
+
+    fn write_isize(&mut self, value: isize) {
+        self.write_u64(value as i64 as u64);
+    }
+}
+
+pub fn state_checksum<T: Hash>(state: &T) -> u64 {
+    let mut hasher = StateHasher::default();
+    state.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay<I> {
+    seed: [u32; 4],
+    fixed_step_ms: u64,
+    checksum_interval: u64,
+    //The input of each tick, the index is the tick number.
+    inputs: Vec<I>,
+    checksums: Vec<(u64, u64)>,
+}
+
+impl<I: Serialize + DeserializeOwned> Replay<I> {
+    //The seed is checked: the Xorshift generator of the simulation can't be seeded with zeroes.
+    pub fn from_reader<R: Read>(reader: R) -> GameplayResult<Self> {
+        debug!("Deserializing a replay.");
+        let replay: Self = serde_json::from_reader(reader).map_err(|json_error| {
+            GameplayError::from(json_error)
+        })?;
+        if replay.seed == [0, 0, 0, 0] {
+            return Err(GameplayError::ReplayError(String::from("The seed of the replay is [0, 0, 0, 0], it can't seed a RandomNumber.")));
+        }
+        Ok(replay)
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> GameplayResult<()> {
+        debug!("Serializing a replay of {} ticks.", self.inputs.len());
+        serde_json::to_writer(writer, self).map_err(|json_error| {
+            GameplayError::from(json_error)
+        })
+    }
+}
+
+impl<I> Replay<I> {
+    pub fn seed(&self) -> [u32; 4] {
+        self.seed
+    }
+
+    pub fn fixed_step_ms(&self) -> u64 {
+        self.fixed_step_ms
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+pub struct ReplayRecorder<I> {
+    replay: Replay<I>,
+}
+
+impl<I> ReplayRecorder<I> {
+    pub fn new(seed: [u32; 4], fixed_step_ms: u64, checksum_interval: u64) -> Self {
+        debug!("Starting a replay recording, with a checksum every {} ticks.", checksum_interval);
+        ReplayRecorder {
+            replay: Replay {
+                seed,
+                fixed_step_ms,
+                checksum_interval: checksum_interval.max(1),
+                inputs: Vec::new(),
+                checksums: Vec::new(),
+            },
+        }
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.replay.inputs.len() as u64
+    }
+
+    //Record the input used for the tick, and the state obtained after the tick.
+    pub fn record<S: Hash>(&mut self, input: I, state: &S) {
+        let tick = self.tick();
+        self.replay.inputs.push(input);
+        if tick % self.replay.checksum_interval == 0 {
+            self.replay.checksums.push((tick, state_checksum(state)));
+        }
+    }
+
+    pub fn finish(self) -> Replay<I> {
+        debug!("Replay recording finished after {} ticks.", self.replay.inputs.len());
+        self.replay
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Divergence {
+    pub tick: u64,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+pub struct ReplayPlayer<I> {
+    replay: Replay<I>,
+    tick: u64,
+    next_checksum: usize,
+    divergence: Option<Divergence>,
+}
+
+impl<I> ReplayPlayer<I> {
+    pub fn new(replay: Replay<I>) -> Self {
+        ReplayPlayer {
+            replay,
+            tick: 0,
+            next_checksum: 0,
+            divergence: None,
+        }
+    }
+
+    pub fn replay(&self) -> &Replay<I> {
+        &self.replay
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick as usize >= self.replay.inputs.len()
+    }
+
+    //The input to use for the current tick.
+    pub fn input(&self) -> Option<&I> {
+        self.replay.inputs.get(self.tick as usize)
+    }
+
+    //Give the state obtained after simulating the current tick, and move to the next tick.
+    pub fn advance<S: Hash>(&mut self, state: &S) {
+        if let Some(&(tick, expected)) = self.replay.checksums.get(self.next_checksum) {
+            if tick == self.tick {
+                self.next_checksum += 1;
+                let actual = state_checksum(state);
+                if actual != expected && self.divergence.is_none() {
+                    error!("The replay diverged at tick {} (expected checksum {}, got {}).", tick, expected, actual);
+                    self.divergence = Some(Divergence {
+                        tick,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+        }
+        self.tick += 1;
+    }
+
+    //The first checked tick where the simulation diverged from the recording.
+    pub fn divergence(&self) -> Option<Divergence> {
+        self.divergence
+    }
+}
+
+#[cfg(test)]
+mod replay_test {
+    use super::*;
+
+    #[derive(Hash)]
+    struct State {
+        position: i64,
+    }
+
+    #[test]
+    fn replay_record_and_detect_divergence() {
+        let mut state = State { position: 0 };
+        let mut recorder = ReplayRecorder::new([1, 2, 3, 4], 16, 2);
+        for input in vec![1i64, 1, -1, 2, 3] {
+            state.position += input;
+            recorder.record(input, &state);
+        }
+
+        let mut serialized = Vec::new();
+        recorder.finish().save(&mut serialized).unwrap();
+        let replay: Replay<i64> = Replay::from_reader(serialized.as_slice()).unwrap();
+        assert_eq!(replay.len(), 5);
+        assert_eq!(replay.seed(), [1, 2, 3, 4]);
+        let zero_seed = String::from_utf8(serialized.clone()).unwrap().replace("[1,2,3,4]", "[0,0,0,0]");
+        match Replay::<i64>::from_reader(zero_seed.as_bytes()) {
+            Err(GameplayError::ReplayError(_)) => {},
+            _ => panic!("A replay with a zero seed has been accepted."),
+        }
+
+        //Same simulation, no divergence.
+        let mut player = ReplayPlayer::new(replay.clone());
+        let mut state = State { position: 0 };
+        while let Some(input) = player.input().cloned() {
+            state.position += input;
+            player.advance(&state);
+        }
+        assert!(player.is_finished());
+        assert!(player.divergence().is_none());
+
+        //A bug doubles the inputs from tick 3.
+        let mut player = ReplayPlayer::new(replay);
+        let mut state = State { position: 0 };
+        while let Some(input) = player.input().cloned() {
+            state.position += if player.tick >= 3 {input * 2} else {input};
+            player.advance(&state);
+        }
+        assert_eq!(player.divergence().unwrap().tick, 4);
+    }
+
+    #[test]
+    fn replay_checksum_encoding() {
+        //The same bytes on every platform: a u32, then a usize on 64 bits, little-endian.
+        let mut hasher = StateHasher::default();
+        hasher.write(&[1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(state_checksum(&(1u32, 2usize)), hasher.finish());
+        assert_eq!(state_checksum(&-1i64), state_checksum(&u64::MAX));
+        assert_eq!(state_checksum(&State { position: 0 }), 0xa8c7_f832_281a_39c5);
+    }
+}
@@ -0,0 +1,387 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SAVE THUMBNAILS.
+
+ Each save carries a small picture of the game at the time of the save, shown by the load-game menu.
+
+ - At save time, the renderer is asked for a copy of the last frame. The copy reaches the CPU a few frames
+   later, without stalling the GPU: the capture polls it in update().
+ - The frame is downscaled (box filter) and compressed as a PNG on a worker thread, the main thread only polls
+   the result.
+ - The PNG is embedded in the header of the save file, which is written before the world snapshots. The
+   load-game menu reads the headers only:
+
+   "KSAV" | header length (u32 LE) | header JSON | width, height, PNG length (u32 LE) | PNG | snapshots...
+
+ A save without thumbnail (the capture failed, or a save from the headless server) has a PNG length of 0.
+The lengths are checked before anything is allocated: save files are shared between players.
+*/
+
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use serde_json;
+use imagefmt::{self, ColFmt, ColType};
+use maskerad_core::job_system::ThreadPool;
+use gameplay_error::{GameplayError, GameplayResult};
+
+pub const SAVE_MAGIC: &'static [u8; 4] = b"KSAV";
+pub const MAX_HEADER_LENGTH: usize = 64 * 1024;
+pub const MAX_THUMBNAIL_LENGTH: usize = 4 * 1024 * 1024;
+
+//The frames captured for a thumbnail: RGBA8 pixels, top-left origin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+//The asynchronous copy of the last rendered frame, from the GPU to the CPU.
+pub trait FrameReadback {
+    fn request_readback(&mut self);
+    //None while the copy is in flight.
+    fn poll_readback(&mut self) -> Option<Frame>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ThumbnailSettings {
+    //The thumbnail fits in this size, keeping the aspect ratio of the frame.
+    pub max_width: usize,
+    pub max_height: usize,
+    //The frames to wait for the readback before giving up.
+    pub timeout_frames: u32,
+}
+
+impl Default for ThumbnailSettings {
+    fn default() -> Self {
+        ThumbnailSettings {
+            max_width: 320,
+            max_height: 180,
+            timeout_frames: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thumbnail {
+    pub width: usize,
+    pub height: usize,
+    pub png: Vec<u8>,
+}
+
+impl Thumbnail {
+    //Downscaling and compression, run on a worker thread.
+    pub fn from_frame(frame: &Frame, settings: &ThumbnailSettings) -> GameplayResult<Self> {
+        if frame.width == 0 || frame.height == 0 || frame.rgba.len() != frame.width * frame.height * 4 {
+            return Err(GameplayError::SaveError(format!("Invalid frame of {} bytes for {}x{} pixels.", frame.rgba.len(), frame.width, frame.height)));
+        }
+        let (width, height) = thumbnail_size(frame.width, frame.height, settings);
+        let rgb = downscale(frame, width, height);
+        let mut png = Vec::new();
+        imagefmt::png::write(&mut png, width, height, ColFmt::RGB, rgb.as_slice(), ColType::Color, None)
+            .map_err(|error| GameplayError::SaveError(format!("The thumbnail could not be encoded: {}.", error)))?;
+        Ok(Thumbnail {
+            width,
+            height,
+            png,
+        })
+    }
+}
+
+enum CaptureState {
+    Idle,
+    Readback(u32),
+    Encoding(Receiver<GameplayResult<Thumbnail>>),
+}
+
+//Drives the capture of one thumbnail at a time: readback, then encoding on the worker pool.
+pub struct ThumbnailCapture {
+    settings: ThumbnailSettings,
+    state: CaptureState,
+}
+
+impl ThumbnailCapture {
+    pub fn new(settings: ThumbnailSettings) -> Self {
+        ThumbnailCapture {
+            settings,
+            state: CaptureState::Idle,
+        }
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        !matches!(self.state, CaptureState::Idle)
+    }
+
+    //A capture in progress is abandoned.
+    pub fn begin(&mut self, readback: &mut FrameReadback) {
+        debug!("Capturing a save thumbnail.");
+        readback.request_readback();
+        self.state = CaptureState::Readback(0);
+    }
+
+    //Once per frame. Returns the thumbnail, or the error, when the capture is over.
+    pub fn update(&mut self, readback: &mut FrameReadback, workers: &ThreadPool) -> Option<GameplayResult<Thumbnail>> {
+        match ::std::mem::replace(&mut self.state, CaptureState::Idle) {
+            CaptureState::Idle => None,
+            CaptureState::Readback(waited) => match readback.poll_readback() {
+                Some(frame) => {
+                    let (sender, receiver) = mpsc::channel();
+                    let settings = self.settings;
+                    workers.execute(move || {
+                        let _ = sender.send(Thumbnail::from_frame(&frame, &settings));
+                    });
+                    self.state = CaptureState::Encoding(receiver);
+                    None
+                },
+                None if waited + 1 >= self.settings.timeout_frames => {
+                    warn!("The frame readback of the save thumbnail timed out after {} frames.", waited + 1);
+                    Some(Err(GameplayError::SaveError(String::from("The frame readback timed out."))))
+                },
+                None => {
+                    self.state = CaptureState::Readback(waited + 1);
+                    None
+                },
+            },
+            CaptureState::Encoding(receiver) => match receiver.try_recv() {
+                Ok(thumbnail) => {
+                    if let Ok(ref thumbnail) = thumbnail {
+                        debug!("Save thumbnail of {}x{} encoded in {} bytes.", thumbnail.width, thumbnail.height, thumbnail.png.len());
+                    }
+                    Some(thumbnail)
+                },
+                Err(TryRecvError::Empty) => {
+                    self.state = CaptureState::Encoding(receiver);
+                    None
+                },
+                Err(TryRecvError::Disconnected) => Some(Err(GameplayError::SaveError(String::from("The thumbnail encoding job was dropped.")))),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SaveHeader {
+    pub slot: String,
+    //Seconds since the Unix epoch.
+    pub saved_at: u64,
+    pub play_seconds: u64,
+    #[serde(skip)]
+    pub thumbnail: Option<Thumbnail>,
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: usize) -> GameplayResult<()> {
+    let value = value as u32;
+    writer.write_all(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8])?;
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> GameplayResult<usize> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok((bytes[0] as usize) | (bytes[1] as usize) << 8 | (bytes[2] as usize) << 16 | (bytes[3] as usize) << 24)
+}
+
+//A length followed by its bytes. A length above max_length is refused before allocating.
+fn read_block<R: Read>(reader: &mut R, max_length: usize, kind: &str) -> GameplayResult<Vec<u8>> {
+    let length = read_u32(reader)?;
+    if length > max_length {
+        return Err(GameplayError::SaveError(format!("The {} of the save is {} bytes long, the maximum is {} bytes.", kind, length, max_length)));
+    }
+    let mut bytes = vec![0u8; length];
+    reader.read_exact(bytes.as_mut_slice())?;
+    Ok(bytes)
+}
+
+impl SaveHeader {
+    //The snapshots of the save are written after the header.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> GameplayResult<()> {
+        let json = serde_json::to_vec(self)?;
+        writer.write_all(SAVE_MAGIC)?;
+        write_u32(&mut writer, json.len())?;
+        writer.write_all(json.as_slice())?;
+        match self.thumbnail {
+            Some(ref thumbnail) => {
+                write_u32(&mut writer, thumbnail.width)?;
+                write_u32(&mut writer, thumbnail.height)?;
+                write_u32(&mut writer, thumbnail.png.len())?;
+                writer.write_all(thumbnail.png.as_slice())?;
+            },
+            None => {
+                for _ in 0..3 {
+                    write_u32(&mut writer, 0)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    //Leaves the reader at the first snapshot of the save.
+    pub fn read_from<R: Read>(mut reader: R) -> GameplayResult<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != SAVE_MAGIC {
+            return Err(GameplayError::SaveError(String::from("This is not a save file.")));
+        }
+        let json = read_block(&mut reader, MAX_HEADER_LENGTH, "header")?;
+        let mut header: SaveHeader = serde_json::from_slice(json.as_slice())?;
+        let width = read_u32(&mut reader)?;
+        let height = read_u32(&mut reader)?;
+        let png = read_block(&mut reader, MAX_THUMBNAIL_LENGTH, "thumbnail")?;
+        if !png.is_empty() {
+            header.thumbnail = Some(Thumbnail {
+                width,
+                height,
+                png,
+            });
+        }
+        Ok(header)
+    }
+}
+
+//The largest size fitting in the settings with the aspect ratio of the frame. Frames are never upscaled.
+fn thumbnail_size(width: usize, height: usize, settings: &ThumbnailSettings) -> (usize, usize) {
+    let (max_width, max_height) = (settings.max_width.max(1).min(width), settings.max_height.max(1).min(height));
+    if width * max_height > height * max_width {
+        (max_width, (height * max_width / width).max(1))
+    } else {
+        ((width * max_height / height).max(1), max_height)
+    }
+}
+
+//Box filter: each pixel of the thumbnail is the average of the pixels of the frame it covers. The alpha is dropped.
+fn downscale(frame: &Frame, width: usize, height: usize) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        let (top, bottom) = (y * frame.height / height, ((y + 1) * frame.height / height).max(y * frame.height / height + 1));
+        for x in 0..width {
+            let (left, right) = (x * frame.width / width, ((x + 1) * frame.width / width).max(x * frame.width / width + 1));
+            let mut sum = [0usize; 3];
+            for source_y in top..bottom {
+                for source_x in left..right {
+                    let pixel = (source_y * frame.width + source_x) * 4;
+                    for (channel, value) in sum.iter_mut().zip(&frame.rgba[pixel..pixel + 3]) {
+                        *channel += *value as usize;
+                    }
+                }
+            }
+            let count = (bottom - top) * (right - left);
+            rgb.extend(sum.iter().map(|channel| ((channel + count / 2) / count) as u8));
+        }
+    }
+    rgb
+}
+
+#[cfg(test)]
+mod save_thumbnail_test {
+    use super::*;
+    use std::io::Cursor;
+    use maskerad_core::engine_configuration::threading::PoolSettings;
+    use maskerad_core::job_system::PoolKind;
+
+    struct Readback {
+        frame: Option<Frame>,
+        polls_until_ready: u32,
+    }
+
+    impl FrameReadback for Readback {
+        fn request_readback(&mut self) {}
+
+        fn poll_readback(&mut self) -> Option<Frame> {
+            if self.polls_until_ready > 0 {
+                self.polls_until_ready -= 1;
+                return None;
+            }
+            self.frame.take()
+        }
+    }
+
+    //Red on the left half, blue on the right half.
+    fn frame(width: usize, height: usize) -> Frame {
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for _ in 0..height {
+            for x in 0..width {
+                rgba.extend_from_slice(if x < width / 2 { &[255, 0, 0, 255] } else { &[0, 0, 255, 255] });
+            }
+        }
+        Frame {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    #[test]
+    fn save_thumbnail_capture() {
+        let workers = ThreadPool::new(PoolKind::Workers, &PoolSettings::default(), 1).unwrap();
+        let settings = ThumbnailSettings {
+            max_width: 16,
+            max_height: 16,
+            timeout_frames: 10,
+        };
+        let mut capture = ThumbnailCapture::new(settings);
+        let mut readback = Readback {
+            frame: Some(frame(64, 32)),
+            polls_until_ready: 2,
+        };
+        assert!(capture.update(&mut readback, &workers).is_none());
+        capture.begin(&mut readback);
+        let thumbnail = loop {
+            if let Some(thumbnail) = capture.update(&mut readback, &workers) {
+                break thumbnail.unwrap();
+            }
+        };
+        assert!(!capture.is_capturing());
+        assert_eq!((thumbnail.width, thumbnail.height), (16, 8));
+        let image = imagefmt::read_from(&mut Cursor::new(thumbnail.png.as_slice()), ColFmt::RGB).unwrap();
+        assert_eq!((image.w, image.h), (16, 8));
+        assert_eq!(&image.buf[..3], &[255, 0, 0]);
+        assert_eq!(&image.buf[image.buf.len() - 3..], &[0, 0, 255]);
+        assert_eq!(downscale(&frame(4, 2), 2, 1), vec![255, 0, 0, 0, 0, 255]);
+
+        capture.begin(&mut readback);
+        let error = (0..10).filter_map(|_| capture.update(&mut readback, &workers)).next();
+        assert!(error.unwrap().is_err());
+        assert!(Thumbnail::from_frame(&Frame { width: 2, height: 2, rgba: vec![0; 4] }, &settings).is_err());
+    }
+
+    #[test]
+    fn save_header_with_thumbnail() {
+        let header = SaveHeader {
+            slot: String::from("slot_1"),
+            saved_at: 1_500_000_000,
+            play_seconds: 3600,
+            thumbnail: Some(Thumbnail::from_frame(&frame(8, 8), &ThumbnailSettings::default()).unwrap()),
+        };
+        let mut save = Vec::new();
+        header.write_to(&mut save).unwrap();
+        save.extend_from_slice(b"{\"tick\": 0}");
+
+        let mut reader = save.as_slice();
+        assert_eq!(SaveHeader::read_from(&mut reader).unwrap(), header);
+        assert_eq!(reader, b"{\"tick\": 0}");
+
+        let mut save = Vec::new();
+        SaveHeader::default().write_to(&mut save).unwrap();
+        assert_eq!(SaveHeader::read_from(save.as_slice()).unwrap().thumbnail, None);
+        assert!(SaveHeader::read_from(&b"{\"tick\": 0}"[..]).is_err());
+
+        //A length larger than the maximum is refused, whatever follows it.
+        let mut save = SAVE_MAGIC.to_vec();
+        save.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        assert!(SaveHeader::read_from(save.as_slice()).is_err());
+        let mut save = Vec::new();
+        SaveHeader::default().write_to(&mut save).unwrap();
+        let length = save.len();
+        save[length - 4..].copy_from_slice(&[0xff, 0xff, 0xff, 0x7f]);
+        match SaveHeader::read_from(save.as_slice()) {
+            Err(GameplayError::SaveError(_)) => {},
+            result => panic!("Unexpected result {:?}.", result),
+        }
+    }
+}
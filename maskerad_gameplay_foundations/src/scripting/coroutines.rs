@@ -0,0 +1,253 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SCRIPT COROUTINES.
+
+ Quest and cutscene scripts are written sequentially:
+     door.open()
+     wait(2.0)
+     wait_for_event("door_opened")
+     npc.say("Welcome")
+ instead of as state machines. Each script runs in a coroutine, and the primitives of the script API
+ yield to the engine:
+ - wait(seconds): resumed when the time channel of the coroutine advanced by this many seconds,
+ - wait_for_event(name): resumed at the update following the event,
+ - yield(): resumed at the next frame.
+
+ The scheduler resumes the coroutines during its update, with the time of the channel driving each coroutine:
+ the coroutines on the gameplay channel are frozen while the game is paused, the coroutines on the ui channel
+ keep running. The time overshooting a wait is carried into the next wait, so a sequence of waits doesn't drift.
+*/
+
+use std::collections::BTreeMap;
+use maskerad_core::time_channels::{TimeChannels, GAMEPLAY_CHANNEL};
+
+//A coroutine stops resuming after this many zero-length waits in one update.
+const MAX_RESUMES_PER_UPDATE: usize = 64;
+
+//What a coroutine waits for, returned each time it yields.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Yield {
+    NextFrame,
+    Seconds(f64),
+    Event(String),
+    Done,
+}
+
+//Why a coroutine is resumed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resume {
+    Started,
+    Frame,
+    Timer,
+    Event(String),
+}
+
+//Implemented by the script VM for its coroutines (a Lua thread...), or by closures.
+pub trait Coroutine {
+    fn resume(&mut self, reason: Resume) -> Yield;
+}
+
+impl<F: FnMut(Resume) -> Yield> Coroutine for F {
+    fn resume(&mut self, reason: Resume) -> Yield {
+        self(reason)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct CoroutineId(u64);
+
+struct ScheduledCoroutine {
+    coroutine: Box<Coroutine>,
+    channel: String,
+    waiting: Yield,
+    //Seconds left for a timer, negative when the timer overshot.
+    remaining: f64,
+}
+
+#[derive(Default)]
+pub struct CoroutineScheduler {
+    next_id: u64,
+    //By id, so the coroutines are resumed in start order.
+    coroutines: BTreeMap<CoroutineId, ScheduledCoroutine>,
+    events: Vec<String>,
+}
+
+impl CoroutineScheduler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    //Start a coroutine driven by a time channel. It runs until its first yield immediately.
+    pub fn start<S: Into<String>>(&mut self, channel: S, coroutine: Box<Coroutine>) -> CoroutineId {
+        let id = CoroutineId(self.next_id);
+        self.next_id += 1;
+        let mut scheduled = ScheduledCoroutine {
+            coroutine,
+            channel: channel.into(),
+            waiting: Yield::NextFrame,
+            remaining: 0.0,
+        };
+        trace!("Starting the coroutine {:?} on the {} channel.", id, scheduled.channel);
+        if CoroutineScheduler::run(&mut scheduled, Resume::Started, 0.0) {
+            self.coroutines.insert(id, scheduled);
+        }
+        id
+    }
+
+    //Start a coroutine driven by the gameplay channel.
+    pub fn start_gameplay(&mut self, coroutine: Box<Coroutine>) -> CoroutineId {
+        self.start(GAMEPLAY_CHANNEL, coroutine)
+    }
+
+    pub fn is_running(&self, id: CoroutineId) -> bool {
+        self.coroutines.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.coroutines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coroutines.is_empty()
+    }
+
+    pub fn cancel(&mut self, id: CoroutineId) -> bool {
+        self.coroutines.remove(&id).is_some()
+    }
+
+    //Resumes the coroutines waiting for this event at the next update.
+    pub fn signal<S: Into<String>>(&mut self, event: S) {
+        self.events.push(event.into());
+    }
+
+    //Resume the coroutine, then again while its waits are already over. Returns false when it is done.
+    fn run(scheduled: &mut ScheduledCoroutine, mut reason: Resume, overshoot: f64) -> bool {
+        let mut overshoot = overshoot;
+        for _ in 0..MAX_RESUMES_PER_UPDATE {
+            scheduled.waiting = scheduled.coroutine.resume(reason);
+            match scheduled.waiting {
+                Yield::Done => return false,
+                Yield::Seconds(seconds) => {
+                    scheduled.remaining = seconds - overshoot;
+                    if scheduled.remaining > 0.0 {
+                        return true;
+                    }
+                    overshoot = -scheduled.remaining;
+                    reason = Resume::Timer;
+                },
+                _ => return true,
+            }
+        }
+        warn!("A coroutine waited for 0 seconds {} times in one update, it is resumed at the next update.", MAX_RESUMES_PER_UPDATE);
+        true
+    }
+
+    //Resume the coroutines whose event was signaled, whose timer expired, or which wait for the next frame.
+    pub fn update(&mut self, channels: &TimeChannels) {
+        self.update_with(|channel| channels.delta_time(channel).num_milliseconds() as f64 / 1000.0);
+    }
+
+    //Same as update, with the time elapsed on each channel in seconds.
+    pub fn update_with<F: Fn(&str) -> f64>(&mut self, delta_time: F) {
+        let events: Vec<String> = self.events.drain(..).collect();
+        let mut finished = Vec::new();
+        for (id, scheduled) in self.coroutines.iter_mut() {
+            let delta_time = delta_time(scheduled.channel.as_str());
+            let running = match scheduled.waiting.clone() {
+                Yield::NextFrame => CoroutineScheduler::run(scheduled, Resume::Frame, 0.0),
+                Yield::Seconds(_) => {
+                    scheduled.remaining -= delta_time;
+                    if scheduled.remaining <= 0.0 {
+                        let overshoot = -scheduled.remaining;
+                        CoroutineScheduler::run(scheduled, Resume::Timer, overshoot)
+                    } else {
+                        true
+                    }
+                },
+                Yield::Event(ref event) if events.contains(event) => CoroutineScheduler::run(scheduled, Resume::Event(event.clone()), 0.0),
+                Yield::Event(_) => true,
+                Yield::Done => false,
+            };
+            if !running {
+                trace!("The coroutine {:?} is done.", id);
+                finished.push(*id);
+            }
+        }
+        for id in finished {
+            self.coroutines.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod coroutines_test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use maskerad_core::time_channels::UI_CHANNEL;
+
+    #[test]
+    fn coroutines_sequence() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let script_log = log.clone();
+        let mut step = 0;
+        //wait(0.5) wait(0.5) wait_for_event("door_opened") yield()
+        let script = move |reason: Resume| {
+            script_log.borrow_mut().push(reason);
+            step += 1;
+            match step {
+                1 => Yield::Seconds(0.5),
+                2 => Yield::Seconds(0.5),
+                3 => Yield::Event(String::from("door_opened")),
+                4 => Yield::NextFrame,
+                _ => Yield::Done,
+            }
+        };
+        let mut scheduler = CoroutineScheduler::new();
+        let id = scheduler.start_gameplay(Box::new(script));
+        assert_eq!(*log.borrow(), vec![Resume::Started]);
+
+        //The gameplay channel is paused: nothing happens.
+        scheduler.update_with(|channel| if channel == UI_CHANNEL {1.0} else {0.0});
+        assert_eq!(log.borrow().len(), 1);
+
+        //0.75s: the first wait is over, 0.25s are carried into the second one.
+        scheduler.update_with(|_| 0.75);
+        assert_eq!(log.borrow().len(), 2);
+        scheduler.update_with(|_| 0.25);
+        assert_eq!(log.borrow().len(), 3);
+
+        scheduler.signal("door_closed");
+        scheduler.update_with(|_| 0.1);
+        assert_eq!(log.borrow().len(), 3);
+        scheduler.signal("door_opened");
+        scheduler.update_with(|_| 0.1);
+        assert_eq!(log.borrow()[3], Resume::Event(String::from("door_opened")));
+
+        scheduler.update_with(|_| 0.1);
+        assert_eq!(log.borrow()[4], Resume::Frame);
+        assert!(!scheduler.is_running(id));
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn coroutines_zero_waits_and_cancel() {
+        let mut scheduler = CoroutineScheduler::new();
+        let counter = Rc::new(RefCell::new(0));
+        let resumes = counter.clone();
+        let id = scheduler.start(UI_CHANNEL, Box::new(move |_| {
+            *resumes.borrow_mut() += 1;
+            Yield::Seconds(0.0)
+        }));
+        assert_eq!(*counter.borrow(), MAX_RESUMES_PER_UPDATE);
+        assert!(scheduler.cancel(id));
+        scheduler.update_with(|_| 1.0);
+        assert_eq!(*counter.borrow(), MAX_RESUMES_PER_UPDATE);
+    }
+}
@@ -0,0 +1,12 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+pub mod script_debugger;
+pub mod coroutines;
+pub mod script_backend;
+#[cfg(feature = "wasm")]
+pub mod wasm_backend;
@@ -0,0 +1,99 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SCRIPT BACKENDS.
+
+ The gameplay code talks to the scripts through the ScriptBackend trait, whatever the language of the scripts.
+ The backend is selected per project, by the "script_backend" entry of the engine configuration:
+ - "lua": Lua scripts.
+ - "wasm": WebAssembly modules (compiled from Rust, C, AssemblyScript...), sandboxed: a module only sees the
+   functions given by the engine, its memory is limited, and each call has an instruction budget.
+   Needs the "wasm" feature.
+*/
+
+use std::str::FromStr;
+use gameplay_error::{GameplayError, GameplayResult};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptValue {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+}
+
+pub trait ScriptBackend {
+    fn name(&self) -> &str;
+
+    //Load (or reload) a module, from its source or binary form.
+    fn load_module(&mut self, module: &str, source: &[u8]) -> GameplayResult<()>;
+
+    fn has_module(&self, module: &str) -> bool;
+
+    fn call(&mut self, module: &str, function: &str, arguments: &[ScriptValue]) -> GameplayResult<ScriptValue>;
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ScriptBackendKind {
+    Lua,
+    Wasm,
+}
+
+impl FromStr for ScriptBackendKind {
+    type Err = GameplayError;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "lua" => Ok(ScriptBackendKind::Lua),
+            "wasm" => Ok(ScriptBackendKind::Wasm),
+            _ => Err(GameplayError::ScriptError(format!("Unknown script backend {}.", kind))),
+        }
+    }
+}
+
+impl ScriptBackendKind {
+    //Whether create_backend can build this backend in this build of the engine.
+    pub fn is_available(&self) -> bool {
+        match self {
+            &ScriptBackendKind::Lua => false,
+            &ScriptBackendKind::Wasm => cfg!(feature = "wasm"),
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+fn wasm_backend() -> GameplayResult<Box<ScriptBackend>> {
+    use scripting::wasm_backend::WasmBackend;
+    Ok(Box::new(WasmBackend::new()?))
+}
+
+#[cfg(not(feature = "wasm"))]
+fn wasm_backend() -> GameplayResult<Box<ScriptBackend>> {
+    Err(GameplayError::ScriptError(String::from("The engine is built without the wasm feature.")))
+}
+
+//The backend named in the engine configuration.
+pub fn create_backend(kind: &str) -> GameplayResult<Box<ScriptBackend>> {
+    debug!("Creating the {} script backend.", kind);
+    match kind.parse()? {
+        ScriptBackendKind::Wasm => wasm_backend(),
+        ScriptBackendKind::Lua => Err(GameplayError::ScriptError(String::from("No Lua backend is available yet."))),
+    }
+}
+
+#[cfg(test)]
+mod script_backend_test {
+    use super::*;
+
+    #[test]
+    fn script_backend_selection() {
+        assert_eq!("wasm".parse::<ScriptBackendKind>().unwrap(), ScriptBackendKind::Wasm);
+        assert!(create_backend("python").is_err());
+        assert_eq!(create_backend("wasm").is_ok(), cfg!(feature = "wasm"));
+    }
+}
@@ -0,0 +1,537 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SCRIPT DEBUGGER.
+
+ A Debug Adapter Protocol (DAP) server, so the scripts can be debugged from VS Code (or any DAP client):
+ breakpoints, pause, step in/over/out, call stack and variable inspection.
+
+ The debugger doesn't know the script VM. The VM calls line_hook() before executing each line, with the depth
+ of its call stack, and exposes its state through the DebugTarget trait while paused.
+ When a breakpoint or a step is hit, line_hook() blocks and answers the requests of the client until it
+ resumes the execution. When no script runs, poll() must be called once per frame to accept the client and
+ to receive the breakpoints.
+
+ The server only listens on the loopback interface. The messages are JSON, prefixed by a
+ "Content-Length: <size>\r\n\r\n" header.
+*/
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Write, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use serde_json::{self, Value};
+use gameplay_error::{GameplayError, GameplayResult};
+
+//The script VM runs on one thread.
+const THREAD_ID: i64 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub name: String,
+    pub source: String,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptVariable {
+    pub name: String,
+    pub value: String,
+    pub type_name: String,
+    //The fields of a table.
+    pub children: Vec<ScriptVariable>,
+}
+
+impl ScriptVariable {
+    pub fn new<S: Into<String>>(name: S, value: S, type_name: S) -> Self {
+        ScriptVariable {
+            name: name.into(),
+            value: value.into(),
+            type_name: type_name.into(),
+            children: Vec::new(),
+        }
+    }
+}
+
+//Implemented by the script VM, queried while the execution is paused.
+pub trait DebugTarget {
+    //The innermost frame first.
+    fn stack_frames(&self) -> Vec<StackFrame>;
+    fn locals(&self, frame: usize) -> Vec<ScriptVariable>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum StepMode {
+    Run,
+    //Pause at the next line.
+    Pause,
+    In,
+    //Pause at the next line at this depth or above.
+    Over(usize),
+    //Pause at the next line above this depth.
+    Out(usize),
+}
+
+//Write a DAP message.
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> GameplayResult<()> {
+    let body = serde_json::to_vec(message)?;
+    write!(writer, "Content-Length: {}\r\n\r\n", body.len())?;
+    writer.write_all(body.as_slice())?;
+    writer.flush()?;
+    Ok(())
+}
+
+//Accumulates the bytes received, and splits them into DAP messages.
+#[derive(Debug, Default)]
+pub struct MessageBuffer {
+    data: Vec<u8>,
+}
+
+impl MessageBuffer {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    //None until a whole message has been received.
+    pub fn next_message(&mut self) -> GameplayResult<Option<Value>> {
+        let header_end = match self.data.windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        let header = String::from_utf8_lossy(&self.data[..header_end]).into_owned();
+        let length = header.lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("Content-Length") => value.trim().parse::<usize>().ok(),
+                    _ => None,
+                }
+            })
+            .next()
+            .ok_or_else(|| GameplayError::DebuggerError(format!("Invalid message header: {}", header)))?;
+
+        let start = header_end + 4;
+        if self.data.len() < start + length {
+            return Ok(None);
+        }
+        let message = serde_json::from_slice(&self.data[start..start + length])?;
+        self.data.drain(..start + length);
+        Ok(Some(message))
+    }
+}
+
+//The state of the protocol, without the transport.
+pub struct DebugSession {
+    seq: i64,
+    //Lines by source path.
+    breakpoints: HashMap<String, BTreeSet<u32>>,
+    step: StepMode,
+    paused: bool,
+    //The children of the variables sent to the client, by reference - 1. Cleared when the execution resumes.
+    references: Vec<Vec<ScriptVariable>>,
+    outgoing: Vec<Value>,
+    disconnected: bool,
+}
+
+impl Default for DebugSession {
+    fn default() -> Self {
+        DebugSession::new()
+    }
+}
+
+impl DebugSession {
+    pub fn new() -> Self {
+        DebugSession {
+            seq: 0,
+            breakpoints: HashMap::new(),
+            step: StepMode::Run,
+            paused: false,
+            references: Vec::new(),
+            outgoing: Vec::new(),
+            disconnected: false,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    //The messages to send to the client.
+    pub fn take_outgoing(&mut self) -> Vec<Value> {
+        self.outgoing.drain(..).collect()
+    }
+
+    fn next_seq(&mut self) -> i64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) {
+        let seq = self.next_seq();
+        self.outgoing.push(json!({"seq": seq, "type": "event", "event": event, "body": body}));
+    }
+
+    fn send_response(&mut self, request: &Value, result: Result<Value, String>) {
+        let seq = self.next_seq();
+        let mut response = json!({
+            "seq": seq,
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = Value::String(message),
+        }
+        self.outgoing.push(response);
+    }
+
+    fn has_breakpoint(&self, source: &str, line: u32) -> bool {
+        self.breakpoints.iter().any(|(path, lines)| {
+            lines.contains(&line) && (Path::new(path).ends_with(source) || Path::new(source).ends_with(path))
+        })
+    }
+
+    //Called before each line. Returns true if the execution must pause.
+    pub fn on_line(&mut self, source: &str, line: u32, depth: usize) -> bool {
+        let reason = match self.step {
+            StepMode::Pause => Some("pause"),
+            StepMode::In => Some("step"),
+            StepMode::Over(step_depth) if depth <= step_depth => Some("step"),
+            StepMode::Out(step_depth) if depth < step_depth => Some("step"),
+            _ => None,
+        }.or_else(|| if self.has_breakpoint(source, line) {Some("breakpoint")} else {None});
+
+        match reason {
+            Some(reason) => {
+                trace!("Script paused at {}:{} ({}).", source, line, reason);
+                self.step = StepMode::Run;
+                self.paused = true;
+                self.send_event("stopped", json!({"reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true}));
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn variables_reference(&mut self, variables: Vec<ScriptVariable>) -> usize {
+        self.references.push(variables);
+        self.references.len()
+    }
+
+    fn resume(&mut self, step: StepMode) {
+        self.step = step;
+        self.paused = false;
+        self.references.clear();
+    }
+
+    //Answer a request of the client. The target is None when no script is paused.
+    pub fn handle(&mut self, request: &Value, target: Option<&DebugTarget>, depth: usize) {
+        let command = request["command"].as_str().unwrap_or("").to_string();
+        let arguments = &request["arguments"];
+        trace!("Script debugger request: {}.", command);
+
+        let result = match command.as_str() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsConditionalBreakpoints": false,
+            })),
+            "launch" | "attach" | "configurationDone" => Ok(Value::Null),
+            "setBreakpoints" => {
+                let path = arguments["source"]["path"].as_str().unwrap_or("").to_string();
+                let lines: BTreeSet<u32> = arguments["breakpoints"].as_array()
+                    .map(|breakpoints| breakpoints.iter().filter_map(|breakpoint| breakpoint["line"].as_u64()).map(|line| line as u32).collect())
+                    .unwrap_or_default();
+                let verified: Vec<Value> = lines.iter().map(|line| json!({"verified": true, "line": line})).collect();
+                debug!("{} breakpoints in {}.", lines.len(), path);
+                self.breakpoints.insert(path, lines);
+                Ok(json!({"breakpoints": verified}))
+            },
+            "threads" => Ok(json!({"threads": [{"id": THREAD_ID, "name": "scripts"}]})),
+            "stackTrace" => match target {
+                Some(target) => {
+                    let frames: Vec<Value> = target.stack_frames().into_iter().enumerate().map(|(id, frame)| json!({
+                        "id": id,
+                        "name": frame.name,
+                        "source": {"path": frame.source},
+                        "line": frame.line,
+                        "column": 1,
+                    })).collect();
+                    Ok(json!({"totalFrames": frames.len(), "stackFrames": frames}))
+                },
+                None => Err(String::from("The scripts are running.")),
+            },
+            "scopes" => match target {
+                Some(target) => {
+                    let frame = arguments["frameId"].as_u64().unwrap_or(0) as usize;
+                    let reference = self.variables_reference(target.locals(frame));
+                    Ok(json!({"scopes": [{"name": "Locals", "variablesReference": reference, "expensive": false}]}))
+                },
+                None => Err(String::from("The scripts are running.")),
+            },
+            "variables" => {
+                let reference = arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+                match self.references.get(reference.wrapping_sub(1)).cloned() {
+                    Some(variables) => {
+                        let variables: Vec<Value> = variables.into_iter().map(|variable| {
+                            let children = if variable.children.is_empty() {0} else {self.variables_reference(variable.children)};
+                            json!({"name": variable.name, "value": variable.value, "type": variable.type_name, "variablesReference": children})
+                        }).collect();
+                        Ok(json!({"variables": variables}))
+                    },
+                    None => Err(format!("Unknown variables reference {}.", reference)),
+                }
+            },
+            "continue" => {
+                self.resume(StepMode::Run);
+                Ok(json!({"allThreadsContinued": true}))
+            },
+            "next" => {
+                self.resume(StepMode::Over(depth));
+                Ok(Value::Null)
+            },
+            "stepIn" => {
+                self.resume(StepMode::In);
+                Ok(Value::Null)
+            },
+            "stepOut" => {
+                self.resume(StepMode::Out(depth));
+                Ok(Value::Null)
+            },
+            "pause" => {
+                self.step = StepMode::Pause;
+                Ok(Value::Null)
+            },
+            "disconnect" => {
+                self.breakpoints.clear();
+                self.resume(StepMode::Run);
+                self.disconnected = true;
+                Ok(Value::Null)
+            },
+            _ => Err(format!("Unsupported request {}.", command)),
+        };
+
+        self.send_response(request, result);
+        if command == "initialize" {
+            self.send_event("initialized", Value::Null);
+        }
+    }
+}
+
+//The DAP server, on 127.0.0.1.
+pub struct DebugServer {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    buffer: MessageBuffer,
+    session: DebugSession,
+}
+
+impl DebugServer {
+    //Port 0 picks a free port, see port().
+    pub fn bind(port: u16) -> GameplayResult<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        debug!("Script debugger listening on {}.", listener.local_addr()?);
+        Ok(DebugServer {
+            listener,
+            client: None,
+            buffer: MessageBuffer::default(),
+            session: DebugSession::new(),
+        })
+    }
+
+    pub fn port(&self) -> GameplayResult<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    fn accept(&mut self) -> GameplayResult<()> {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    debug!("Script debugger client connected from {}.", address);
+                    stream.set_nonblocking(true)?;
+                    self.client = Some(stream);
+                    self.buffer = MessageBuffer::default();
+                    self.session = DebugSession::new();
+                },
+                Err(ref error) if error.kind() == ErrorKind::WouldBlock => {},
+                Err(error) => return Err(GameplayError::from(error)),
+            }
+        }
+        Ok(())
+    }
+
+    //Read what the client sent. Blocks until something is received if blocking is true.
+    fn receive(&mut self, blocking: bool) -> GameplayResult<()> {
+        let mut closed = false;
+        if let Some(ref mut client) = self.client {
+            client.set_nonblocking(!blocking)?;
+            let mut bytes = [0u8; 4096];
+            loop {
+                match client.read(&mut bytes) {
+                    Ok(0) => {
+                        closed = true;
+                        break;
+                    },
+                    Ok(count) => {
+                        self.buffer.push(&bytes[..count]);
+                        if blocking {
+                            break;
+                        }
+                    },
+                    Err(ref error) if error.kind() == ErrorKind::WouldBlock => break,
+                    Err(error) => return Err(GameplayError::from(error)),
+                }
+            }
+        }
+        if closed {
+            debug!("Script debugger client disconnected.");
+            self.disconnect();
+        }
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        self.client = None;
+        self.session = DebugSession::new();
+    }
+
+    fn process(&mut self, target: Option<&DebugTarget>, depth: usize) -> GameplayResult<()> {
+        while let Some(request) = self.buffer.next_message()? {
+            self.session.handle(&request, target, depth);
+        }
+        self.flush()?;
+        if self.session.is_disconnected() {
+            self.disconnect();
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> GameplayResult<()> {
+        let outgoing = self.session.take_outgoing();
+        if let Some(ref mut client) = self.client {
+            for message in outgoing.iter() {
+                write_message(client, message)?;
+            }
+        }
+        Ok(())
+    }
+
+    //Called once per frame: accepts the client and handles its requests.
+    pub fn poll(&mut self) -> GameplayResult<()> {
+        self.accept()?;
+        self.receive(false)?;
+        self.process(None, 0)
+    }
+
+    //Called by the script VM before executing a line. Blocks while the execution is paused.
+    pub fn line_hook(&mut self, source: &str, line: u32, depth: usize, target: &DebugTarget) -> GameplayResult<()> {
+        if self.client.is_none() {
+            return Ok(());
+        }
+        //A pause request may be waiting.
+        self.receive(false)?;
+        self.process(None, depth)?;
+        if !self.session.on_line(source, line, depth) {
+            return Ok(());
+        }
+        self.flush()?;
+        while self.session.is_paused() && self.client.is_some() {
+            self.receive(true)?;
+            self.process(Some(target), depth)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod script_debugger_test {
+    use super::*;
+
+    struct FakeVm;
+
+    impl DebugTarget for FakeVm {
+        fn stack_frames(&self) -> Vec<StackFrame> {
+            vec![StackFrame { name: String::from("open_door"), source: String::from("scripts/door.lua"), line: 12 }]
+        }
+
+        fn locals(&self, _frame: usize) -> Vec<ScriptVariable> {
+            let mut door = ScriptVariable::new("door", "table", "table");
+            door.children.push(ScriptVariable::new("locked", "true", "boolean"));
+            vec![door, ScriptVariable::new("speed", "2.5", "number")]
+        }
+    }
+
+    fn request(seq: i64, command: &str, arguments: Value) -> Value {
+        json!({"seq": seq, "type": "request", "command": command, "arguments": arguments})
+    }
+
+    #[test]
+    fn script_debugger_framing() {
+        let mut data = Vec::new();
+        write_message(&mut data, &request(1, "threads", Value::Null)).unwrap();
+        write_message(&mut data, &request(2, "continue", Value::Null)).unwrap();
+        let mut buffer = MessageBuffer::default();
+        buffer.push(&data[..10]);
+        assert!(buffer.next_message().unwrap().is_none());
+        buffer.push(&data[10..]);
+        assert_eq!(buffer.next_message().unwrap().unwrap()["command"], "threads");
+        assert_eq!(buffer.next_message().unwrap().unwrap()["seq"], 2);
+        assert!(buffer.next_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn script_debugger_breakpoints_and_steps() {
+        let mut session = DebugSession::new();
+        session.handle(&request(1, "initialize", json!({})), None, 0);
+        session.handle(&request(2, "setBreakpoints", json!({
+            "source": {"path": "/home/dev/game/scripts/door.lua"},
+            "breakpoints": [{"line": 12}],
+        })), None, 0);
+        let outgoing = session.take_outgoing();
+        assert_eq!(outgoing[1]["event"], "initialized");
+        assert_eq!(outgoing[2]["body"]["breakpoints"][0]["verified"], true);
+
+        assert!(!session.on_line("scripts/door.lua", 11, 1));
+        assert!(session.on_line("scripts/door.lua", 12, 1));
+        assert_eq!(session.take_outgoing()[0]["body"]["reason"], "breakpoint");
+
+        let vm = FakeVm;
+        session.handle(&request(3, "stackTrace", json!({"threadId": 1})), Some(&vm), 1);
+        session.handle(&request(4, "scopes", json!({"frameId": 0})), Some(&vm), 1);
+        let outgoing = session.take_outgoing();
+        assert_eq!(outgoing[0]["body"]["stackFrames"][0]["name"], "open_door");
+        let locals = outgoing[1]["body"]["scopes"][0]["variablesReference"].clone();
+        session.handle(&request(5, "variables", json!({"variablesReference": locals})), Some(&vm), 1);
+        let variables = session.take_outgoing()[0]["body"]["variables"].clone();
+        assert_eq!(variables[1]["value"], "2.5");
+        session.handle(&request(6, "variables", json!({"variablesReference": variables[0]["variablesReference"]})), Some(&vm), 1);
+        assert_eq!(session.take_outgoing()[0]["body"]["variables"][0]["name"], "locked");
+
+        //Step over: the lines of the called functions are skipped.
+        session.handle(&request(7, "next", Value::Null), Some(&vm), 1);
+        assert!(!session.is_paused());
+        assert!(!session.on_line("scripts/lock.lua", 3, 2));
+        assert!(session.on_line("scripts/door.lua", 13, 1));
+        session.handle(&request(8, "stepOut", Value::Null), Some(&vm), 1);
+        assert!(!session.on_line("scripts/door.lua", 14, 1));
+        assert!(session.on_line("scripts/main.lua", 40, 0));
+
+        session.handle(&request(9, "variables", json!({"variablesReference": locals})), None, 0);
+        assert_eq!(session.take_outgoing().last().unwrap()["success"], false);
+    }
+}
@@ -0,0 +1,145 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//The WebAssembly script backend (wasmtime).
+//
+//Sandboxing: the modules are instantiated without imports, so they can only compute on their arguments and
+//their own memory. The memory of each module is limited, and each call gets an instruction budget ("fuel"):
+//an infinite loop in a module fails the call instead of freezing the game.
+//
+//The arguments are converted to the types of the parameters of the exported function:
+//booleans and integers to i32/i64, numbers to f32/f64.
+
+use std::collections::HashMap;
+use wasmtime::{Config, Engine, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Val, ValType};
+use gameplay_error::{GameplayError, GameplayResult};
+use scripting::script_backend::{ScriptBackend, ScriptValue};
+
+pub const DEFAULT_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+pub const DEFAULT_FUEL_PER_CALL: u64 = 10_000_000;
+
+fn script_error<E: ::std::fmt::Display>(description: &str, error: E) -> GameplayError {
+    GameplayError::ScriptError(format!("{}: {}", description, error))
+}
+
+pub struct WasmBackend {
+    engine: Engine,
+    store: Store<StoreLimits>,
+    linker: Linker<StoreLimits>,
+    instances: HashMap<String, Instance>,
+    fuel_per_call: u64,
+}
+
+impl WasmBackend {
+    pub fn new() -> GameplayResult<Self> {
+        WasmBackend::with_limits(DEFAULT_MEMORY_LIMIT, DEFAULT_FUEL_PER_CALL)
+    }
+
+    pub fn with_limits(memory_limit: usize, fuel_per_call: u64) -> GameplayResult<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|error| script_error("Could not create the wasm engine", error))?;
+        let mut store = Store::new(&engine, StoreLimitsBuilder::new().memory_size(memory_limit).build());
+        store.limiter(|limits| limits);
+        let linker = Linker::new(&engine);
+        Ok(WasmBackend {
+            engine,
+            store,
+            linker,
+            instances: HashMap::new(),
+            fuel_per_call,
+        })
+    }
+}
+
+fn to_wasm(value: &ScriptValue, ty: &ValType) -> GameplayResult<Val> {
+    match (value, ty) {
+        (&ScriptValue::Bool(value), &ValType::I32) => Ok(Val::I32(value as i32)),
+        (&ScriptValue::Int(value), &ValType::I32) => Ok(Val::I32(value as i32)),
+        (&ScriptValue::Int(value), &ValType::I64) => Ok(Val::I64(value)),
+        (&ScriptValue::Float(value), &ValType::F32) => Ok(Val::F32((value as f32).to_bits())),
+        (&ScriptValue::Float(value), &ValType::F64) => Ok(Val::F64(value.to_bits())),
+        (&ScriptValue::Int(value), &ValType::F64) => Ok(Val::F64((value as f64).to_bits())),
+        _ => Err(GameplayError::ScriptError(format!("Can't pass {:?} as a {:?}.", value, ty))),
+    }
+}
+
+fn from_wasm(value: &Val) -> ScriptValue {
+    match value {
+        &Val::I32(value) => ScriptValue::Int(value as i64),
+        &Val::I64(value) => ScriptValue::Int(value),
+        &Val::F32(bits) => ScriptValue::Float(f32::from_bits(bits) as f64),
+        &Val::F64(bits) => ScriptValue::Float(f64::from_bits(bits)),
+        _ => ScriptValue::Nil,
+    }
+}
+
+impl ScriptBackend for WasmBackend {
+    fn name(&self) -> &str {
+        "wasm"
+    }
+
+    fn load_module(&mut self, module: &str, source: &[u8]) -> GameplayResult<()> {
+        debug!("Loading the wasm module {}.", module);
+        let compiled = Module::new(&self.engine, source).map_err(|error| script_error("Invalid wasm module", error))?;
+        self.store.add_fuel(self.fuel_per_call).map_err(|error| script_error("Could not add fuel", error))?;
+        let instance = self.linker.instantiate(&mut self.store, &compiled)
+            .map_err(|error| script_error("Could not instantiate the wasm module", error))?;
+        self.instances.insert(module.to_string(), instance);
+        Ok(())
+    }
+
+    fn has_module(&self, module: &str) -> bool {
+        self.instances.contains_key(module)
+    }
+
+    fn call(&mut self, module: &str, function: &str, arguments: &[ScriptValue]) -> GameplayResult<ScriptValue> {
+        let instance = *self.instances.get(module).ok_or_else(|| {
+            GameplayError::ScriptError(format!("The wasm module {} is not loaded.", module))
+        })?;
+        let func = instance.get_func(&mut self.store, function).ok_or_else(|| {
+            GameplayError::ScriptError(format!("The wasm module {} doesn't export {}.", module, function))
+        })?;
+        let ty = func.ty(&self.store);
+        let params: Vec<ValType> = ty.params().collect();
+        if params.len() != arguments.len() {
+            return Err(GameplayError::ScriptError(format!("{}.{} takes {} arguments, {} given.", module, function, params.len(), arguments.len())));
+        }
+        let params = arguments.iter().zip(params.iter())
+            .map(|(argument, ty)| to_wasm(argument, ty))
+            .collect::<GameplayResult<Vec<Val>>>()?;
+        let mut results = vec![Val::I32(0); ty.results().len()];
+
+        //A new budget for each call.
+        let remaining = self.store.fuel_remaining().unwrap_or(0);
+        if remaining < self.fuel_per_call {
+            self.store.add_fuel(self.fuel_per_call - remaining).map_err(|error| script_error("Could not add fuel", error))?;
+        }
+        func.call(&mut self.store, params.as_slice(), results.as_mut_slice())
+            .map_err(|error| script_error(format!("{}.{} failed", module, function).as_str(), error))?;
+        Ok(results.first().map(from_wasm).unwrap_or(ScriptValue::Nil))
+    }
+}
+
+#[cfg(test)]
+mod wasm_backend_test {
+    use super::*;
+
+    const MODULE: &'static str = r#"(module
+        (func (export "add") (param i64 i64) (result i64) local.get 0 local.get 1 i64.add)
+        (func (export "spin") (loop br 0)))"#;
+
+    #[test]
+    fn wasm_backend_calls_and_fuel() {
+        let mut backend = WasmBackend::with_limits(DEFAULT_MEMORY_LIMIT, 100_000).unwrap();
+        backend.load_module("math", MODULE.as_bytes()).unwrap();
+        assert_eq!(backend.call("math", "add", &[ScriptValue::Int(2), ScriptValue::Int(3)]).unwrap(), ScriptValue::Int(5));
+        assert!(backend.call("math", "add", &[ScriptValue::Int(2)]).is_err());
+        assert!(backend.call("math", "spin", &[]).is_err());
+        assert_eq!(backend.call("math", "add", &[ScriptValue::Int(1), ScriptValue::Int(1)]).unwrap(), ScriptValue::Int(2));
+    }
+}
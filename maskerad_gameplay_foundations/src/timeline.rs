@@ -0,0 +1,323 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ TIMELINES.
+
+ A timeline is the backbone of cutscenes and scripted sequences. It is made of tracks:
+ - property tracks animate a property of a game object (position, light intensity...) with keyframes,
+ - event tracks fire audio events, particle effects and script callbacks at a given time,
+ - the camera track moves the cutscene camera.
+
+ The timeline doesn't know how game objects are stored. Each update, the TimelinePlayer
+ returns a list of TimelineOutput, and the game applies them to its objects.
+*/
+
+use std::io::Read;
+use serde::de::Error;
+use serde_json;
+use gameplay_error::{GameplayError, GameplayResult};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub time: f64,
+    //A scalar has one component, a position three...
+    pub value: Vec<f32>,
+}
+
+fn lerp(from: &[f32], to: &[f32], t: f32) -> Vec<f32> {
+    from.iter().zip(to.iter()).map(|(a, b)| a + (b - a) * t).collect()
+}
+
+//Linear interpolation between the keyframes surrounding the given time. Keyframes must be sorted.
+fn sample(keyframes: &[Keyframe], time: f64) -> Option<Vec<f32>> {
+    let first = keyframes.first()?;
+    if time <= first.time {
+        return Some(first.value.clone());
+    }
+    for pair in keyframes.windows(2) {
+        if time < pair[1].time {
+            let t = ((time - pair[0].time) / (pair[1].time - pair[0].time)) as f32;
+            return Some(lerp(pair[0].value.as_slice(), pair[1].value.as_slice(), t));
+        }
+    }
+    keyframes.last().map(|keyframe| keyframe.value.clone())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropertyTrack {
+    //The id of the animated game object.
+    pub target: u64,
+    pub property: String,
+    pub keyframes: Vec<Keyframe>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TimelineEvent {
+    Audio(String),
+    Particle(String),
+    Script(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventKey {
+    pub time: f64,
+    pub event: TimelineEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraKeyframe {
+    pub time: f64,
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+    pub fov: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timeline {
+    pub name: String,
+    #[serde(default)]
+    pub property_tracks: Vec<PropertyTrack>,
+    #[serde(default)]
+    pub events: Vec<EventKey>,
+    #[serde(default)]
+    pub camera: Vec<CameraKeyframe>,
+}
+
+impl Timeline {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Timeline {
+            name: name.into(),
+            .. Default::default()
+        }
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> GameplayResult<Self> {
+        debug!("Deserializing a timeline.");
+        let mut timeline: Timeline = serde_json::from_reader(reader).map_err(|json_error| {
+            GameplayError::from(json_error)
+        })?;
+        if !timeline.has_finite_times() {
+            return Err(GameplayError::AssetError(
+                format!("The timeline {} has a non-finite time.", timeline.name),
+                serde_json::Error::custom("non-finite time"),
+            ));
+        }
+        timeline.sort();
+        Ok(timeline)
+    }
+
+    pub fn has_finite_times(&self) -> bool {
+        self.property_tracks.iter().flat_map(|track| track.keyframes.iter()).all(|keyframe| keyframe.time.is_finite())
+            && self.events.iter().all(|key| key.time.is_finite())
+            && self.camera.iter().all(|key| key.time.is_finite())
+    }
+
+    //Keyframes and events can be written in any order in the asset.
+    pub fn sort(&mut self) {
+        for track in self.property_tracks.iter_mut() {
+            track.keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        }
+        self.events.sort_by(|a, b| a.time.total_cmp(&b.time));
+        self.camera.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+
+    pub fn duration(&self) -> f64 {
+        let properties = self.property_tracks.iter()
+            .filter_map(|track| track.keyframes.last())
+            .fold(0.0, |duration, keyframe| keyframe.time.max(duration));
+        let events = self.events.last().map(|key| key.time).unwrap_or(0.0);
+        let camera = self.camera.last().map(|key| key.time).unwrap_or(0.0);
+        properties.max(events).max(camera)
+    }
+
+    fn camera_at(&self, time: f64) -> Option<CameraKeyframe> {
+        let keyframes: Vec<Keyframe> = self.camera.iter().map(|key| {
+            let mut value = Vec::with_capacity(7);
+            value.extend_from_slice(&key.position);
+            value.extend_from_slice(&key.look_at);
+            value.push(key.fov);
+            Keyframe { time: key.time, value }
+        }).collect();
+
+        sample(keyframes.as_slice(), time).map(|value| CameraKeyframe {
+            time,
+            position: [value[0], value[1], value[2]],
+            look_at: [value[3], value[4], value[5]],
+            fov: value[6],
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimelineOutput {
+    SetProperty { target: u64, property: String, value: Vec<f32> },
+    Fire(TimelineEvent),
+    Camera(CameraKeyframe),
+    Finished,
+}
+
+pub struct TimelinePlayer {
+    timeline: Timeline,
+    time: f64,
+    playing: bool,
+    finished: bool,
+}
+
+impl TimelinePlayer {
+    pub fn new(timeline: Timeline) -> Self {
+        debug!("Creating a TimelinePlayer for the timeline {}.", timeline.name);
+        TimelinePlayer {
+            timeline,
+            time: 0.0,
+            playing: true,
+            finished: false,
+        }
+    }
+
+    pub fn timeline(&self) -> &Timeline {
+        &self.timeline
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    //Jump to a time, without firing the events in between (skipping a cutscene for example).
+    //The events at the time are fired by the next update.
+    pub fn seek(&mut self, time: f64) {
+        self.time = time.max(0.0);
+        self.finished = false;
+    }
+
+    pub fn update(&mut self, delta_seconds: f64) -> Vec<TimelineOutput> {
+        let mut outputs = Vec::new();
+        if !self.playing || self.finished {
+            return outputs;
+        }
+
+        let previous_time = self.time;
+        let duration = self.timeline.duration();
+        self.time = (self.time + delta_seconds).min(duration);
+
+        //The events in [previous time, time), and the ones at the end on the last update: each event fires once.
+        let last_update = self.time >= duration;
+        for key in self.timeline.events.iter() {
+            if key.time >= previous_time && (key.time < self.time || last_update) {
+                trace!("Timeline {}: firing {:?}.", self.timeline.name, key.event);
+                outputs.push(TimelineOutput::Fire(key.event.clone()));
+            }
+        }
+
+        for track in self.timeline.property_tracks.iter() {
+            if let Some(value) = sample(track.keyframes.as_slice(), self.time) {
+                outputs.push(TimelineOutput::SetProperty {
+                    target: track.target,
+                    property: track.property.clone(),
+                    value,
+                });
+            }
+        }
+
+        if let Some(camera) = self.timeline.camera_at(self.time) {
+            outputs.push(TimelineOutput::Camera(camera));
+        }
+
+        if self.time >= duration {
+            debug!("The timeline {} is finished.", self.timeline.name);
+            self.finished = true;
+            outputs.push(TimelineOutput::Finished);
+        }
+
+        outputs
+    }
+}
+
+#[cfg(test)]
+mod timeline_test {
+    use super::*;
+
+    const TIMELINE: &'static str = r#"{
+        "name": "intro",
+        "property_tracks": [
+            { "target": 7, "property": "light_intensity", "keyframes": [
+                { "time": 2.0, "value": [1.0] },
+                { "time": 0.0, "value": [0.0] }
+            ]}
+        ],
+        "events": [
+            { "time": 1.5, "event": { "Script": "on_door_open" } },
+            { "time": 0.5, "event": { "Audio": "door_creak" } }
+        ]
+    }"#;
+
+    #[test]
+    fn timeline_player_samples_and_fires() {
+        let timeline = Timeline::from_reader(TIMELINE.as_bytes()).unwrap();
+        assert_eq!(timeline.duration(), 2.0);
+
+        let mut player = TimelinePlayer::new(timeline);
+        let outputs = player.update(1.0);
+        assert_eq!(outputs[0], TimelineOutput::Fire(TimelineEvent::Audio(String::from("door_creak"))));
+        assert_eq!(outputs[1], TimelineOutput::SetProperty {
+            target: 7,
+            property: String::from("light_intensity"),
+            value: vec![0.5],
+        });
+
+        let outputs = player.update(5.0);
+        assert_eq!(outputs[0], TimelineOutput::Fire(TimelineEvent::Script(String::from("on_door_open"))));
+        assert_eq!(outputs.last(), Some(&TimelineOutput::Finished));
+        assert!(player.is_finished());
+        assert!(player.update(1.0).is_empty());
+    }
+
+    #[test]
+    fn timeline_events_fire_once() {
+        let mut timeline = Timeline::new("hit");
+        timeline.events = vec![
+            EventKey { time: 0.0, event: TimelineEvent::Particle(String::from("sparks")) },
+            EventKey { time: 0.5, event: TimelineEvent::Audio(String::from("impact")) },
+            EventKey { time: 1.0, event: TimelineEvent::Script(String::from("on_hit")) },
+        ];
+        let fired = |outputs: Vec<TimelineOutput>| outputs.into_iter().filter(|output| matches!(output, TimelineOutput::Fire(_))).count();
+
+        let mut player = TimelinePlayer::new(timeline);
+        assert_eq!(fired(player.update(0.0)), 0);
+        assert_eq!(fired(player.update(0.25)), 1);
+        assert_eq!(fired(player.update(0.0)), 0);
+        assert_eq!(fired(player.update(0.25)), 0);
+        assert_eq!(fired(player.update(0.25)), 1);
+        assert_eq!(fired(player.update(1.0)), 1);
+        assert!(player.is_finished());
+
+        player.seek(0.0);
+        assert_eq!(fired(player.update(2.0)), 3);
+    }
+
+    #[test]
+    fn timeline_non_finite_times_are_rejected() {
+        let mut timeline = Timeline::new("broken");
+        timeline.events.push(EventKey { time: f64::NAN, event: TimelineEvent::Script(String::from("never")) });
+        timeline.events.push(EventKey { time: 1.0, event: TimelineEvent::Script(String::from("on_end")) });
+        assert!(!timeline.has_finite_times());
+        timeline.sort();
+        assert!(Timeline::from_reader(r#"{ "name": "huge", "events": [{ "time": 1e400, "event": { "Audio": "boom" } }] }"#.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ WINDOW EVENTS.
+
+ The events of the window change how the game loop runs:
+ - minimized: nothing is rendered, and the loop is throttled to save the battery and the GPU,
+ - focus lost: the inputs held are released (no key stuck down on return), and the game may be paused,
+ - DPI change: the UI is rescaled, and the swapchain follows the new size in pixels.
+
+ The platform layer gives the raw events to the handler, which publishes the reactions on the event bus.
+*/
+
+use std::time::Duration;
+use event::EventBus;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WindowEvent {
+    Minimized,
+    Restored,
+    FocusLost,
+    FocusGained,
+    //The size in physical pixels.
+    Resized { width: u32, height: u32 },
+    DpiChanged { scale_factor: f32, width: u32, height: u32 },
+}
+
+//Published on the event bus.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WindowReaction {
+    //The game may open its pause menu.
+    PauseRequested,
+    //The input system releases all the keys and buttons held.
+    ResetInputs,
+    UiScaleChanged(f32),
+    ResizeSwapchain { width: u32, height: u32 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WindowPolicy {
+    pub pause_on_focus_loss: bool,
+    //The minimum duration of a frame while minimized.
+    pub minimized_frame_interval: Duration,
+    //Some players keep the game running behind another window, without rendering throttle.
+    pub throttle_unfocused: bool,
+    pub unfocused_frame_interval: Duration,
+}
+
+impl Default for WindowPolicy {
+    fn default() -> Self {
+        WindowPolicy {
+            pause_on_focus_loss: true,
+            minimized_frame_interval: Duration::from_millis(100),
+            throttle_unfocused: false,
+            unfocused_frame_interval: Duration::from_millis(33),
+        }
+    }
+}
+
+pub struct WindowHandler {
+    policy: WindowPolicy,
+    minimized: bool,
+    focused: bool,
+    scale_factor: f32,
+    size: (u32, u32),
+}
+
+impl WindowHandler {
+    pub fn new(policy: WindowPolicy, width: u32, height: u32, scale_factor: f32) -> Self {
+        WindowHandler {
+            policy,
+            minimized: false,
+            focused: true,
+            scale_factor,
+            size: (width, height),
+        }
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    //Nothing is rendered while the window is minimized, or has no area.
+    pub fn should_render(&self) -> bool {
+        !self.minimized && self.size.0 > 0 && self.size.1 > 0
+    }
+
+    //The minimum duration of a frame, the game loop sleeps the rest. None: not throttled.
+    pub fn frame_interval(&self) -> Option<Duration> {
+        if self.minimized {
+            Some(self.policy.minimized_frame_interval)
+        } else if !self.focused && self.policy.throttle_unfocused {
+            Some(self.policy.unfocused_frame_interval)
+        } else {
+            None
+        }
+    }
+
+    pub fn handle(&mut self, event: WindowEvent, bus: &mut EventBus) {
+        trace!("Window event: {:?}.", event);
+        match event {
+            WindowEvent::Minimized => {
+                self.minimized = true;
+                //Already requested if the focus was lost first.
+                if self.policy.pause_on_focus_loss && self.focused {
+                    bus.publish(WindowReaction::PauseRequested);
+                }
+            },
+            WindowEvent::Restored => {
+                self.minimized = false;
+            },
+            WindowEvent::FocusLost => {
+                if !self.focused {
+                    return;
+                }
+                self.focused = false;
+                bus.publish(WindowReaction::ResetInputs);
+                if self.policy.pause_on_focus_loss && !self.minimized {
+                    bus.publish(WindowReaction::PauseRequested);
+                }
+            },
+            WindowEvent::FocusGained => {
+                self.focused = true;
+            },
+            WindowEvent::Resized { width, height } => {
+                //A minimized window is often resized to 0x0, the swapchain keeps its size.
+                if (width, height) != self.size && width > 0 && height > 0 {
+                    self.size = (width, height);
+                    bus.publish(WindowReaction::ResizeSwapchain { width, height });
+                }
+            },
+            WindowEvent::DpiChanged { scale_factor, width, height } => {
+                if scale_factor != self.scale_factor {
+                    debug!("The DPI scale of the window changed from {} to {}.", self.scale_factor, scale_factor);
+                    self.scale_factor = scale_factor;
+                    bus.publish(WindowReaction::UiScaleChanged(scale_factor));
+                }
+                self.handle(WindowEvent::Resized { width, height }, bus);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod window_events_test {
+    use super::*;
+
+    fn reactions(bus: &mut EventBus) -> Vec<WindowReaction> {
+        let reactions = bus.read::<WindowReaction>().into_iter().cloned().collect();
+        bus.end_frame();
+        bus.end_frame();
+        reactions
+    }
+
+    #[test]
+    fn window_events_reactions() {
+        let mut bus = EventBus::new();
+        let mut window = WindowHandler::new(WindowPolicy::default(), 1280, 720, 1.0);
+
+        window.handle(WindowEvent::FocusLost, &mut bus);
+        window.handle(WindowEvent::Minimized, &mut bus);
+        window.handle(WindowEvent::Resized { width: 0, height: 0 }, &mut bus);
+        assert_eq!(reactions(&mut bus), vec![WindowReaction::ResetInputs, WindowReaction::PauseRequested]);
+        assert!(!window.should_render());
+        assert_eq!(window.frame_interval(), Some(Duration::from_millis(100)));
+
+        window.handle(WindowEvent::Restored, &mut bus);
+        window.handle(WindowEvent::FocusGained, &mut bus);
+        assert!(window.should_render() && window.frame_interval().is_none());
+
+        window.handle(WindowEvent::DpiChanged { scale_factor: 2.0, width: 2560, height: 1440 }, &mut bus);
+        assert_eq!(reactions(&mut bus), vec![WindowReaction::UiScaleChanged(2.0), WindowReaction::ResizeSwapchain { width: 2560, height: 1440 }]);
+    }
+}
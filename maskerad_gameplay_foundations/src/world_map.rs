@@ -0,0 +1,331 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ WORLD MAP.
+
+ The map of a region of the world, seen from above, used by the minimap and the world map of the UI.
+
+ - The map texture is either authored (an RGBA8 image covering the region), or captured: the triangles of
+   the geometry marked for the map are projected on the ground plane (x, z) and rasterized, the highest
+   triangle winning.
+ - The fog of war is a mask, at its own resolution, revealed around the positions of the player. The UI
+   multiplies the map texture by the mask.
+ - The markers (quests, players, points of interest) are registered with a world position, and the UI
+   gets their map coordinates through the world to map transform.
+
+ The map coordinates are in [0, 1], (0, 0) being the north-west corner (min x, min z).
+*/
+
+use std::collections::BTreeMap;
+
+//The region of the world covered by the map, on the ground plane.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MapBounds {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl MapBounds {
+    pub fn world_to_map(&self, position: [f32; 3]) -> [f32; 2] {
+        [
+            (position[0] - self.min[0]) / (self.max[0] - self.min[0]),
+            (position[2] - self.min[1]) / (self.max[1] - self.min[1]),
+        ]
+    }
+
+    //The height of the returned position is 0.
+    pub fn map_to_world(&self, coordinates: [f32; 2]) -> [f32; 3] {
+        [
+            self.min[0] + coordinates[0] * (self.max[0] - self.min[0]),
+            0.0,
+            self.min[1] + coordinates[1] * (self.max[1] - self.min[1]),
+        ]
+    }
+
+    pub fn contains(&self, position: [f32; 3]) -> bool {
+        position[0] >= self.min[0] && position[0] <= self.max[0] && position[2] >= self.min[1] && position[2] <= self.max[1]
+    }
+}
+
+//A triangle of the geometry marked for the map, in world space.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MapTriangle {
+    pub vertices: [[f32; 3]; 3],
+    pub color: [u8; 4],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapTexture {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+impl MapTexture {
+    //An authored map. None if the size of the pixels does not match.
+    pub fn authored(width: usize, height: usize, rgba: Vec<u8>) -> Option<Self> {
+        if rgba.len() != width * height * 4 {
+            warn!("The authored map has {} bytes of pixels, instead of {} for {}x{}.", rgba.len(), width * height * 4, width, height);
+            return None;
+        }
+        Some(MapTexture {
+            width,
+            height,
+            rgba,
+        })
+    }
+
+    //Top-down capture of the marked geometry. The pixels without geometry are transparent.
+    pub fn capture(bounds: &MapBounds, width: usize, height: usize, triangles: &[MapTriangle]) -> Self {
+        let mut rgba = vec![0u8; width * height * 4];
+        let mut heights = vec![::std::f32::NEG_INFINITY; width * height];
+        for triangle in triangles.iter() {
+            let points: Vec<[f32; 2]> = triangle.vertices.iter().map(|vertex| {
+                let coordinates = bounds.world_to_map(*vertex);
+                [coordinates[0] * width as f32, coordinates[1] * height as f32]
+            }).collect();
+            let area = edge(points[0], points[1], points[2]);
+            if area == 0.0 {
+                continue;
+            }
+            let min_x = points.iter().map(|point| point[0]).fold(::std::f32::INFINITY, f32::min).floor().max(0.0) as usize;
+            let max_x = points.iter().map(|point| point[0]).fold(::std::f32::NEG_INFINITY, f32::max).ceil().min(width as f32) as usize;
+            let min_y = points.iter().map(|point| point[1]).fold(::std::f32::INFINITY, f32::min).floor().max(0.0) as usize;
+            let max_y = points.iter().map(|point| point[1]).fold(::std::f32::NEG_INFINITY, f32::max).ceil().min(height as f32) as usize;
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    //Sampled at the center of the pixel.
+                    let pixel = [x as f32 + 0.5, y as f32 + 0.5];
+                    let weights = [
+                        edge(points[1], points[2], pixel) / area,
+                        edge(points[2], points[0], pixel) / area,
+                        edge(points[0], points[1], pixel) / area,
+                    ];
+                    if weights.iter().any(|weight| *weight < 0.0) {
+                        continue;
+                    }
+                    let altitude: f32 = (0..3).map(|index| weights[index] * triangle.vertices[index][1]).sum();
+                    let index = y * width + x;
+                    if altitude >= heights[index] {
+                        heights[index] = altitude;
+                        rgba[index * 4..index * 4 + 4].copy_from_slice(&triangle.color);
+                    }
+                }
+            }
+        }
+        debug!("Captured a {}x{} map from {} triangles.", width, height, triangles.len());
+        MapTexture {
+            width,
+            height,
+            rgba,
+        }
+    }
+}
+
+fn edge(a: [f32; 2], b: [f32; 2], point: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (point[1] - a[1]) - (b[1] - a[1]) * (point[0] - a[0])
+}
+
+//0 is hidden, 255 is revealed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FogOfWar {
+    pub width: usize,
+    pub height: usize,
+    pub mask: Vec<u8>,
+}
+
+impl FogOfWar {
+    pub fn new(width: usize, height: usize) -> Self {
+        FogOfWar {
+            width,
+            height,
+            mask: vec![0; width * height],
+        }
+    }
+
+    //Reveal the cells around a position, with a soft edge over the last quarter of the radius.
+    pub fn reveal(&mut self, bounds: &MapBounds, position: [f32; 3], radius: f32) {
+        let center = bounds.world_to_map(position);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let cell = bounds.map_to_world([(x as f32 + 0.5) / self.width as f32, (y as f32 + 0.5) / self.height as f32]);
+                let distance = ((cell[0] - position[0]).powi(2) + (cell[2] - position[2]).powi(2)).sqrt();
+                if distance > radius {
+                    continue;
+                }
+                let visibility = ((radius - distance) / (radius * 0.25)).min(1.0);
+                let value = &mut self.mask[y * self.width + x];
+                *value = (*value).max((visibility * 255.0) as u8);
+            }
+        }
+        trace!("Revealed the fog of war around {:?}.", center);
+    }
+
+    pub fn is_revealed(&self, bounds: &MapBounds, position: [f32; 3]) -> bool {
+        if !bounds.contains(position) {
+            return false;
+        }
+        let coordinates = bounds.world_to_map(position);
+        let x = ((coordinates[0] * self.width as f32) as usize).min(self.width - 1);
+        let y = ((coordinates[1] * self.height as f32) as usize).min(self.height - 1);
+        self.mask[y * self.width + x] > 0
+    }
+}
+
+pub type MarkerId = u32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapMarker {
+    //The icon of the marker, interpreted by the UI.
+    pub kind: String,
+    pub position: [f32; 3],
+    //Shown even under the fog of war.
+    pub always_visible: bool,
+}
+
+//A marker as shown by the UI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisibleMarker<'a> {
+    pub id: MarkerId,
+    pub marker: &'a MapMarker,
+    pub coordinates: [f32; 2],
+}
+
+pub struct WorldMap {
+    bounds: MapBounds,
+    texture: MapTexture,
+    fog: Option<FogOfWar>,
+    markers: BTreeMap<MarkerId, MapMarker>,
+    next_marker: MarkerId,
+}
+
+impl WorldMap {
+    pub fn new(bounds: MapBounds, texture: MapTexture) -> Self {
+        WorldMap {
+            bounds,
+            texture,
+            fog: None,
+            markers: BTreeMap::new(),
+            next_marker: 0,
+        }
+    }
+
+    pub fn with_fog_of_war(mut self, width: usize, height: usize) -> Self {
+        self.fog = Some(FogOfWar::new(width, height));
+        self
+    }
+
+    pub fn bounds(&self) -> &MapBounds {
+        &self.bounds
+    }
+
+    pub fn texture(&self) -> &MapTexture {
+        &self.texture
+    }
+
+    pub fn set_texture(&mut self, texture: MapTexture) {
+        self.texture = texture;
+    }
+
+    pub fn fog_of_war(&self) -> Option<&FogOfWar> {
+        self.fog.as_ref()
+    }
+
+    pub fn reveal(&mut self, position: [f32; 3], radius: f32) {
+        if let Some(ref mut fog) = self.fog {
+            fog.reveal(&self.bounds, position, radius);
+        }
+    }
+
+    pub fn world_to_map(&self, position: [f32; 3]) -> [f32; 2] {
+        self.bounds.world_to_map(position)
+    }
+
+    pub fn map_to_world(&self, coordinates: [f32; 2]) -> [f32; 3] {
+        self.bounds.map_to_world(coordinates)
+    }
+
+    pub fn add_marker(&mut self, marker: MapMarker) -> MarkerId {
+        let id = self.next_marker;
+        self.next_marker += 1;
+        self.markers.insert(id, marker);
+        id
+    }
+
+    pub fn move_marker(&mut self, id: MarkerId, position: [f32; 3]) -> bool {
+        match self.markers.get_mut(&id) {
+            Some(marker) => {
+                marker.position = position;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn remove_marker(&mut self, id: MarkerId) -> Option<MapMarker> {
+        self.markers.remove(&id)
+    }
+
+    //The markers within the map, and not hidden by the fog of war.
+    pub fn visible_markers<'a>(&'a self) -> Vec<VisibleMarker<'a>> {
+        self.markers.iter()
+            .filter(|&(_, marker)| self.bounds.contains(marker.position))
+            .filter(|&(_, marker)| marker.always_visible || self.fog.as_ref().map(|fog| fog.is_revealed(&self.bounds, marker.position)).unwrap_or(true))
+            .map(|(id, marker)| VisibleMarker {
+                id: *id,
+                marker,
+                coordinates: self.bounds.world_to_map(marker.position),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod world_map_test {
+    use super::*;
+
+    fn bounds() -> MapBounds {
+        MapBounds {
+            min: [-10.0, -10.0],
+            max: [10.0, 10.0],
+        }
+    }
+
+    #[test]
+    fn world_map_capture_highest_triangle() {
+        let ground = [[-10.0, 0.0, -10.0], [10.0, 0.0, -10.0], [-10.0, 0.0, 10.0]];
+        let roof = [[-10.0, 5.0, -10.0], [0.0, 5.0, -10.0], [-10.0, 5.0, 0.0]];
+        //The roof is drawn first, and stays on top.
+        let texture = MapTexture::capture(&bounds(), 4, 4, &[
+            MapTriangle { vertices: roof, color: [255, 0, 0, 255] },
+            MapTriangle { vertices: ground, color: [0, 255, 0, 255] },
+        ]);
+        assert_eq!(&texture.rgba[0..4], &[255, 0, 0, 255]);
+        assert_eq!(&texture.rgba[5 * 4..6 * 4], &[0, 255, 0, 255]);
+        assert_eq!(&texture.rgba[15 * 4..16 * 4], &[0, 0, 0, 0]);
+        assert!(MapTexture::authored(2, 2, vec![0; 15]).is_none());
+    }
+
+    #[test]
+    fn world_map_markers_and_fog() {
+        let mut map = WorldMap::new(bounds(), MapTexture::authored(1, 1, vec![0; 4]).unwrap()).with_fog_of_war(20, 20);
+        assert_eq!(map.world_to_map([0.0, 3.0, 5.0]), [0.5, 0.75]);
+        assert_eq!(map.map_to_world([0.5, 0.75]), [0.0, 0.0, 5.0]);
+
+        let quest = map.add_marker(MapMarker { kind: String::from("quest"), position: [5.0, 0.0, 5.0], always_visible: true });
+        let chest = map.add_marker(MapMarker { kind: String::from("chest"), position: [-5.0, 0.0, -5.0], always_visible: false });
+        map.add_marker(MapMarker { kind: String::from("far"), position: [50.0, 0.0, 0.0], always_visible: true });
+        assert_eq!(map.visible_markers().iter().map(|marker| marker.id).collect::<Vec<_>>(), vec![quest]);
+
+        map.reveal([-5.0, 0.0, -5.0], 2.0);
+        assert_eq!(map.visible_markers().len(), 2);
+        assert!(map.move_marker(chest, [5.0, 0.0, -5.0]));
+        assert_eq!(map.visible_markers().len(), 1);
+        assert_eq!(map.remove_marker(quest).unwrap().kind, "quest");
+    }
+}
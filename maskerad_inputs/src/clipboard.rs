@@ -0,0 +1,35 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//The clipboard of the system, implemented by the platform layer.
+pub trait Clipboard {
+    fn text(&self) -> Option<String>;
+
+    fn set_text(&mut self, text: &str);
+}
+
+//A clipboard local to the game: for the tests, and the platforms without clipboard.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryClipboard {
+    text: Option<String>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Clipboard for MemoryClipboard {
+    fn text(&self) -> Option<String> {
+        self.text.clone()
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.text = Some(text.to_string());
+    }
+}
@@ -0,0 +1,340 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ COMBOS.
+
+ A combo is a sequence of inputs emitted as one action when it is entered in time: a quarter circle forward
+ then punch (a fireball), a double tap forward (a dash). The combos are assets:
+
+ {
+    "action": "fireball",
+    "steps": [
+        { "input": "down" },
+        { "input": "down_forward", "within": 0.15 },
+        { "input": "forward", "within": 0.15 },
+        { "input": "punch", "within": 0.2 }
+    ],
+    "max_duration": 0.5,
+    "priority": 1
+ }
+
+ - The inputs are the directions of the stick, relative to the side the character faces ("forward",
+   "down_back"...), and the buttons, named by the game ("punch"). A direction is an input when the stick
+   enters it: holding it is not repeated, and going back to neutral is not an input, so a double tap is
+   "forward", "forward".
+ - "within" is the time allowed since the previous step, in seconds, the step window of the settings by
+   default. "max_duration" limits the whole sequence.
+ - The stick passes through directions the combo doesn't list (down, down_back, down_forward...): they are
+   skipped. A button which is not in the combo breaks it.
+ - When several combos end with the same input, the one with the highest priority is emitted, the longest
+   on equal priorities. Its inputs are consumed: they don't start another combo.
+*/
+
+use std::collections::VecDeque;
+use std::io::Read;
+use serde_json;
+use input_error::{InputError, InputResult};
+
+const MAX_HISTORY: usize = 32;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StickDirection {
+    Neutral,
+    Up,
+    UpForward,
+    Forward,
+    DownForward,
+    Down,
+    DownBack,
+    Back,
+    UpBack,
+}
+
+const DIRECTIONS: [StickDirection; 8] = [
+    StickDirection::Forward,
+    StickDirection::UpForward,
+    StickDirection::Up,
+    StickDirection::UpBack,
+    StickDirection::Back,
+    StickDirection::DownBack,
+    StickDirection::Down,
+    StickDirection::DownForward,
+];
+
+impl StickDirection {
+    //x to the right and y up, in [-1, 1]. Forward is on the left when the character faces left.
+    pub fn from_stick(x: f32, y: f32, facing_right: bool, dead_zone: f32) -> Self {
+        if (x * x + y * y).sqrt() <= dead_zone {
+            return StickDirection::Neutral;
+        }
+        let x = if facing_right { x } else { -x };
+        //8 sectors of 45 degrees, the first one centered on forward.
+        let angle = y.atan2(x).to_degrees() + 22.5;
+        let sector = ((angle + 360.0) % 360.0 / 45.0) as usize;
+        DIRECTIONS[sector.min(7)]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            StickDirection::Neutral => "neutral",
+            StickDirection::Up => "up",
+            StickDirection::UpForward => "up_forward",
+            StickDirection::Forward => "forward",
+            StickDirection::DownForward => "down_forward",
+            StickDirection::Down => "down",
+            StickDirection::DownBack => "down_back",
+            StickDirection::Back => "back",
+            StickDirection::UpBack => "up_back",
+        }
+    }
+
+    pub fn is_direction(input: &str) -> bool {
+        DIRECTIONS.iter().any(|direction| direction.name() == input)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComboStep {
+    pub input: String,
+    //Seconds since the previous step.
+    #[serde(default)]
+    pub within: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Combo {
+    pub action: String,
+    pub steps: Vec<ComboStep>,
+    #[serde(default)]
+    pub max_duration: Option<f64>,
+    #[serde(default)]
+    pub priority: i32,
+}
+
+impl Combo {
+    pub fn from_reader<R: Read>(reader: R) -> InputResult<Self> {
+        debug!("Deserializing a combo.");
+        serde_json::from_reader(reader).map_err(|json_error| {
+            InputError::ComboError(format!("Invalid combo: {}", json_error))
+        })
+    }
+
+    //The inputs in sequence, each one within the step window of the settings.
+    pub fn sequence(action: &str, inputs: &[&str]) -> Self {
+        Combo {
+            action: action.to_string(),
+            steps: inputs.iter().map(|input| ComboStep { input: input.to_string(), within: None }).collect(),
+            max_duration: None,
+            priority: 0,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ComboSettings {
+    pub dead_zone: f32,
+    //The default time allowed between two steps, in seconds.
+    pub step_window: f64,
+}
+
+impl Default for ComboSettings {
+    fn default() -> Self {
+        ComboSettings {
+            dead_zone: 0.5,
+            step_window: 0.25,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComboAction {
+    pub action: String,
+    //The time of the input completing the combo.
+    pub time: f64,
+}
+
+pub struct ComboDetector {
+    settings: ComboSettings,
+    combos: Vec<Combo>,
+    //The inputs, with their time.
+    history: VecDeque<(String, f64)>,
+    direction: StickDirection,
+    actions: Vec<ComboAction>,
+}
+
+impl ComboDetector {
+    pub fn new(settings: ComboSettings) -> Self {
+        ComboDetector {
+            settings,
+            combos: Vec::new(),
+            history: VecDeque::with_capacity(MAX_HISTORY),
+            direction: StickDirection::Neutral,
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn add_combo(&mut self, combo: Combo) -> InputResult<()> {
+        if combo.action.is_empty() || combo.steps.is_empty() {
+            return Err(InputError::ComboError(String::from("A combo needs an action and at least one step.")));
+        }
+        if combo.steps.iter().any(|step| step.within.map(|within| within <= 0.0).unwrap_or(false)) {
+            return Err(InputError::ComboError(format!("The combo {} has a step window which is not positive.", combo.action)));
+        }
+        debug!("Combo {}: {} steps, priority {}.", combo.action, combo.steps.len(), combo.priority);
+        self.combos.push(combo);
+        Ok(())
+    }
+
+    //Called with the state of the stick each frame.
+    pub fn stick(&mut self, x: f32, y: f32, facing_right: bool, time: f64) {
+        let direction = StickDirection::from_stick(x, y, facing_right, self.settings.dead_zone);
+        if direction != self.direction {
+            self.direction = direction;
+            if direction != StickDirection::Neutral {
+                self.input(direction.name(), time);
+            }
+        }
+    }
+
+    //A button pressed, named by the game.
+    pub fn press(&mut self, button: &str, time: f64) {
+        self.input(button, time);
+    }
+
+    pub fn poll_actions(&mut self) -> Vec<ComboAction> {
+        self.actions.drain(..).collect()
+    }
+
+    fn input(&mut self, input: &str, time: f64) {
+        if self.history.len() == MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back((input.to_string(), time));
+
+        let completed = self.combos.iter()
+            .filter(|combo| self.completes(combo))
+            .max_by_key(|combo| (combo.priority, combo.steps.len()));
+        if let Some(combo) = completed {
+            trace!("Combo {} at {}.", combo.action, time);
+            self.actions.push(ComboAction {
+                action: combo.action.clone(),
+                time,
+            });
+            self.history.clear();
+        }
+    }
+
+    //The last input is the last step, the others are found going back in time.
+    fn completes(&self, combo: &Combo) -> bool {
+        let mut remaining = combo.steps.len();
+        let mut next_time: Option<f64> = None;
+        for &(ref input, time) in self.history.iter().rev() {
+            if remaining == 0 {
+                break;
+            }
+            if let Some(next_time) = next_time {
+                let within = combo.steps[remaining].within.unwrap_or(self.settings.step_window);
+                if next_time - time > within {
+                    return false;
+                }
+            }
+            if *input == combo.steps[remaining - 1].input {
+                next_time = Some(time);
+                remaining -= 1;
+            } else if next_time.is_none() || !StickDirection::is_direction(input.as_str()) {
+                return false;
+            }
+        }
+        if remaining > 0 {
+            return false;
+        }
+        match (combo.max_duration, next_time, self.history.back()) {
+            (Some(max_duration), Some(first_time), Some(&(_, last_time))) => last_time - first_time <= max_duration,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod combos_test {
+    use super::*;
+
+    fn detector() -> ComboDetector {
+        let mut detector = ComboDetector::new(ComboSettings::default());
+        detector.add_combo(Combo::from_reader(r#"{
+            "action": "fireball",
+            "steps": [
+                { "input": "down" },
+                { "input": "down_forward", "within": 0.15 },
+                { "input": "forward", "within": 0.15 },
+                { "input": "punch", "within": 0.2 }
+            ],
+            "max_duration": 0.5,
+            "priority": 1
+        }"#.as_bytes()).unwrap()).unwrap();
+        detector.add_combo(Combo::sequence("dash", &["forward", "forward"])).unwrap();
+        detector.add_combo(Combo::sequence("uppercut", &["forward", "punch"])).unwrap();
+        detector
+    }
+
+    fn actions(detector: &mut ComboDetector) -> Vec<String> {
+        detector.poll_actions().into_iter().map(|action| action.action).collect()
+    }
+
+    #[test]
+    fn combos_quarter_circle() {
+        assert_eq!(StickDirection::from_stick(0.7, -0.7, true, 0.5), StickDirection::DownForward);
+        assert_eq!(StickDirection::from_stick(-1.0, 0.0, false, 0.5), StickDirection::Forward);
+        assert_eq!(StickDirection::from_stick(0.2, 0.2, true, 0.5), StickDirection::Neutral);
+
+        let mut detector = detector();
+        //Facing left, with a down-back on the way and the stick held for several frames.
+        for (index, &(x, y)) in [(0.0, -1.0), (0.6, -0.8), (-0.7, -0.7), (-0.7, -0.7), (-1.0, 0.0)].iter().enumerate() {
+            detector.stick(x, y, false, index as f64 * 0.05);
+        }
+        detector.press("punch", 0.3);
+        //The fireball has the priority over the uppercut, and consumes the inputs.
+        assert_eq!(detector.poll_actions(), vec![ComboAction { action: String::from("fireball"), time: 0.3 }]);
+        detector.press("punch", 0.35);
+        assert!(actions(&mut detector).is_empty());
+
+        //Too slow.
+        detector.stick(0.0, -1.0, true, 1.0);
+        detector.stick(0.7, -0.7, true, 1.3);
+        detector.stick(1.0, 0.0, true, 1.35);
+        detector.press("punch", 1.4);
+        assert_eq!(actions(&mut detector), vec![String::from("uppercut")]);
+    }
+
+    #[test]
+    fn combos_double_tap() {
+        let mut detector = detector();
+        detector.stick(1.0, 0.0, true, 0.0);
+        detector.stick(1.0, 0.0, true, 0.05);
+        assert!(actions(&mut detector).is_empty());
+        detector.stick(0.0, 0.0, true, 0.1);
+        detector.stick(1.0, 0.0, true, 0.15);
+        assert_eq!(actions(&mut detector), vec![String::from("dash")]);
+
+        //A button between the taps breaks the dash.
+        detector.stick(0.0, 0.0, true, 1.0);
+        detector.stick(1.0, 0.0, true, 1.05);
+        detector.press("kick", 1.1);
+        detector.stick(0.0, 0.0, true, 1.12);
+        detector.stick(1.0, 0.0, true, 1.15);
+        assert!(actions(&mut detector).is_empty());
+
+        assert!(detector.add_combo(Combo::sequence("nothing", &[])).is_err());
+        assert!(Combo::from_reader(r#"{ "action": "dash" }"#.as_bytes()).is_err());
+    }
+}
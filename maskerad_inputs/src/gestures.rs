@@ -0,0 +1,239 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ GESTURES.
+
+ The gesture recognizer reads the touch events and emits the gestures of the frame:
+ - Tap: a touch which ended quickly, without moving.
+ - Long press: a touch held without moving, emitted once while the finger is still down.
+ - Pinch: two fingers, the scale is the distance between them over their distance at the beginning.
+ - Swipe: a touch which ended quickly after a long enough movement, in its main direction.
+
+ A touch which was part of a pinch or a long press is not a tap or a swipe when it ends.
+ The times are in seconds, from any origin.
+*/
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use touch::{TouchEvent, TouchPhase};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GestureSettings {
+    //In physical pixels.
+    pub tap_slop: f32,
+    pub long_press_duration: f64,
+    pub swipe_min_distance: f32,
+    pub swipe_max_duration: f64,
+}
+
+impl Default for GestureSettings {
+    fn default() -> Self {
+        GestureSettings {
+            tap_slop: 12.0,
+            long_press_duration: 0.5,
+            swipe_min_distance: 80.0,
+            swipe_max_duration: 0.4,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GestureEvent {
+    Tap { x: f32, y: f32 },
+    LongPress { x: f32, y: f32 },
+    Pinch { scale: f32, center: (f32, f32) },
+    PinchEnded,
+    //The velocity in physical pixels by second.
+    Swipe { direction: SwipeDirection, velocity: f32 },
+}
+
+struct TrackedTouch {
+    start: (f32, f32),
+    position: (f32, f32),
+    start_time: f64,
+    //Part of a pinch or of a long press.
+    consumed: bool,
+}
+
+impl TrackedTouch {
+    fn distance(&self) -> f32 {
+        let (dx, dy) = (self.position.0 - self.start.0, self.position.1 - self.start.1);
+        (dx * dx + dy * dy).sqrt()
+    }
+}
+
+pub struct GestureRecognizer {
+    settings: GestureSettings,
+    touches: HashMap<u64, TrackedTouch>,
+    //The two pointers of the pinch, and their distance at its beginning.
+    pinch: Option<(u64, u64, f32)>,
+    events: VecDeque<GestureEvent>,
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((a.0 - b.0) * (a.0 - b.0) + (a.1 - b.1) * (a.1 - b.1)).sqrt()
+}
+
+impl GestureRecognizer {
+    pub fn new(settings: GestureSettings) -> Self {
+        GestureRecognizer {
+            settings,
+            touches: HashMap::new(),
+            pinch: None,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn handle(&mut self, event: TouchEvent, time: f64) {
+        let position = (event.x, event.y);
+        match event.phase {
+            TouchPhase::Began => {
+                self.touches.insert(event.pointer_id, TrackedTouch {
+                    start: position,
+                    position,
+                    start_time: time,
+                    consumed: false,
+                });
+                if self.pinch.is_none() && self.touches.len() == 2 {
+                    self.begin_pinch();
+                }
+            },
+            TouchPhase::Moved => {
+                if let Some(touch) = self.touches.get_mut(&event.pointer_id) {
+                    touch.position = position;
+                }
+                self.update_pinch(event.pointer_id);
+            },
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                let touch = match self.touches.remove(&event.pointer_id) {
+                    Some(mut touch) => {
+                        touch.position = position;
+                        touch
+                    },
+                    None => return,
+                };
+                if let Some((first, second, _)) = self.pinch {
+                    if event.pointer_id == first || event.pointer_id == second {
+                        self.pinch = None;
+                        self.events.push_back(GestureEvent::PinchEnded);
+                    }
+                }
+                if event.phase == TouchPhase::Ended && !touch.consumed {
+                    self.recognize_release(&touch, time);
+                }
+            },
+        }
+    }
+
+    fn begin_pinch(&mut self) {
+        let mut pointers: Vec<u64> = self.touches.keys().cloned().collect();
+        pointers.sort();
+        for pointer in pointers.iter() {
+            if let Some(touch) = self.touches.get_mut(pointer) {
+                touch.consumed = true;
+            }
+        }
+        let start_distance = distance(self.touches[&pointers[0]].position, self.touches[&pointers[1]].position);
+        if start_distance > 0.0 {
+            self.pinch = Some((pointers[0], pointers[1], start_distance));
+        }
+    }
+
+    fn update_pinch(&mut self, pointer_id: u64) {
+        if let Some((first, second, start_distance)) = self.pinch {
+            if pointer_id != first && pointer_id != second {
+                return;
+            }
+            let (a, b) = (self.touches[&first].position, self.touches[&second].position);
+            self.events.push_back(GestureEvent::Pinch {
+                scale: distance(a, b) / start_distance,
+                center: ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0),
+            });
+        }
+    }
+
+    fn recognize_release(&mut self, touch: &TrackedTouch, time: f64) {
+        let duration = time - touch.start_time;
+        let moved = touch.distance();
+        if moved <= self.settings.tap_slop && duration < self.settings.long_press_duration {
+            self.events.push_back(GestureEvent::Tap { x: touch.position.0, y: touch.position.1 });
+        } else if moved >= self.settings.swipe_min_distance && duration <= self.settings.swipe_max_duration {
+            let (dx, dy) = (touch.position.0 - touch.start.0, touch.position.1 - touch.start.1);
+            //The screen coordinates go down.
+            let direction = if dx.abs() >= dy.abs() {
+                if dx > 0.0 {SwipeDirection::Right} else {SwipeDirection::Left}
+            } else {
+                if dy > 0.0 {SwipeDirection::Down} else {SwipeDirection::Up}
+            };
+            self.events.push_back(GestureEvent::Swipe { direction, velocity: moved / duration.max(1e-3) as f32 });
+        }
+    }
+
+    //Called each frame, for the long presses.
+    pub fn update(&mut self, time: f64) {
+        if self.touches.len() != 1 {
+            return;
+        }
+        for touch in self.touches.values_mut() {
+            if !touch.consumed && touch.distance() <= self.settings.tap_slop && time - touch.start_time >= self.settings.long_press_duration {
+                touch.consumed = true;
+                self.events.push_back(GestureEvent::LongPress { x: touch.position.0, y: touch.position.1 });
+            }
+        }
+    }
+
+    pub fn poll_events(&mut self) -> Vec<GestureEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod gestures_test {
+    use super::*;
+
+    fn touch(pointer_id: u64, phase: TouchPhase, x: f32, y: f32) -> TouchEvent {
+        TouchEvent { pointer_id, phase, x, y }
+    }
+
+    #[test]
+    fn gestures_are_recognized() {
+        let mut gestures = GestureRecognizer::new(GestureSettings::default());
+
+        gestures.handle(touch(0, TouchPhase::Began, 100.0, 100.0), 0.0);
+        gestures.handle(touch(0, TouchPhase::Ended, 102.0, 101.0), 0.1);
+        assert_eq!(gestures.poll_events(), vec![GestureEvent::Tap { x: 102.0, y: 101.0 }]);
+
+        gestures.handle(touch(1, TouchPhase::Began, 100.0, 100.0), 1.0);
+        gestures.update(1.2);
+        assert!(gestures.poll_events().is_empty());
+        gestures.update(1.5);
+        gestures.update(1.6);
+        gestures.handle(touch(1, TouchPhase::Ended, 100.0, 100.0), 1.7);
+        assert_eq!(gestures.poll_events(), vec![GestureEvent::LongPress { x: 100.0, y: 100.0 }]);
+
+        gestures.handle(touch(2, TouchPhase::Began, 500.0, 300.0), 2.0);
+        gestures.handle(touch(2, TouchPhase::Moved, 400.0, 310.0), 2.1);
+        gestures.handle(touch(2, TouchPhase::Ended, 300.0, 320.0), 2.2);
+        assert_eq!(gestures.poll_events(), vec![GestureEvent::Swipe { direction: SwipeDirection::Left, velocity: 1004.98755 }]);
+
+        gestures.handle(touch(3, TouchPhase::Began, 100.0, 100.0), 3.0);
+        gestures.handle(touch(4, TouchPhase::Began, 200.0, 100.0), 3.0);
+        gestures.handle(touch(4, TouchPhase::Moved, 300.0, 100.0), 3.1);
+        gestures.handle(touch(4, TouchPhase::Ended, 300.0, 100.0), 3.2);
+        gestures.handle(touch(3, TouchPhase::Ended, 100.0, 100.0), 3.2);
+        assert_eq!(gestures.poll_events(), vec![GestureEvent::Pinch { scale: 2.0, center: (200.0, 100.0) }, GestureEvent::PinchEnded]);
+    }
+}
@@ -0,0 +1,227 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ CONTROLLER GLYPHS.
+
+ The UI prompts ("Press [A] to open") show the glyph of the button bound to the action, on the device
+ the player is using. The GlyphService knows the bindings of the actions, and the device used last:
+ when the player switches from the keyboard to a gamepad (or from a gamepad to another), a
+ DeviceChanged event tells the UI to refresh its prompts.
+
+ The gamepad buttons are named by position (South is A on a Xbox gamepad, Cross on a PlayStation
+ gamepad, B on a Switch gamepad). The glyphs are images in a directory by device family:
+ <root>/xbox/a.png, <root>/playstation/cross.png, <root>/switch/b.png, <root>/keyboard/space.png...
+*/
+
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DeviceFamily {
+    Keyboard,
+    Xbox,
+    PlayStation,
+    Switch,
+    SteamDeck,
+}
+
+impl DeviceFamily {
+    //From the USB vendor id of a gamepad. The unknown gamepads use the Xbox layout, like XInput.
+    pub fn from_vendor_id(vendor_id: u16) -> Self {
+        match vendor_id {
+            0x054c => DeviceFamily::PlayStation,
+            0x057e => DeviceFamily::Switch,
+            //Valve: the controls built in the Steam Deck.
+            0x28de => DeviceFamily::SteamDeck,
+            _ => DeviceFamily::Xbox,
+        }
+    }
+
+    fn directory(&self) -> &'static str {
+        match *self {
+            DeviceFamily::Keyboard => "keyboard",
+            DeviceFamily::Xbox => "xbox",
+            DeviceFamily::PlayStation => "playstation",
+            DeviceFamily::Switch => "switch",
+            DeviceFamily::SteamDeck => "steam_deck",
+        }
+    }
+
+    //From the glyph family of a platform profile.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "keyboard" => Some(DeviceFamily::Keyboard),
+            "xbox" => Some(DeviceFamily::Xbox),
+            "playstation" => Some(DeviceFamily::PlayStation),
+            "switch" => Some(DeviceFamily::Switch),
+            "steam_deck" => Some(DeviceFamily::SteamDeck),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl GamepadButton {
+    //The name of the glyph of the button, on a gamepad family.
+    pub fn glyph_name(&self, family: DeviceFamily) -> &'static str {
+        match (*self, family) {
+            (GamepadButton::South, DeviceFamily::PlayStation) => "cross",
+            (GamepadButton::East, DeviceFamily::PlayStation) => "circle",
+            (GamepadButton::West, DeviceFamily::PlayStation) => "square",
+            (GamepadButton::North, DeviceFamily::PlayStation) => "triangle",
+            (GamepadButton::LeftBumper, DeviceFamily::PlayStation) => "l1",
+            (GamepadButton::RightBumper, DeviceFamily::PlayStation) => "r1",
+            (GamepadButton::LeftTrigger, DeviceFamily::PlayStation) => "l2",
+            (GamepadButton::RightTrigger, DeviceFamily::PlayStation) => "r2",
+            (GamepadButton::Select, DeviceFamily::PlayStation) => "share",
+            (GamepadButton::Start, DeviceFamily::PlayStation) => "options",
+            (GamepadButton::South, DeviceFamily::Switch) => "b",
+            (GamepadButton::East, DeviceFamily::Switch) => "a",
+            (GamepadButton::West, DeviceFamily::Switch) => "y",
+            (GamepadButton::North, DeviceFamily::Switch) => "x",
+            (GamepadButton::LeftBumper, DeviceFamily::Switch) => "l",
+            (GamepadButton::RightBumper, DeviceFamily::Switch) => "r",
+            (GamepadButton::LeftTrigger, DeviceFamily::Switch) => "zl",
+            (GamepadButton::RightTrigger, DeviceFamily::Switch) => "zr",
+            (GamepadButton::Select, DeviceFamily::Switch) => "minus",
+            (GamepadButton::Start, DeviceFamily::Switch) => "plus",
+            (GamepadButton::LeftBumper, DeviceFamily::SteamDeck) => "l1",
+            (GamepadButton::RightBumper, DeviceFamily::SteamDeck) => "r1",
+            (GamepadButton::LeftTrigger, DeviceFamily::SteamDeck) => "l2",
+            (GamepadButton::RightTrigger, DeviceFamily::SteamDeck) => "r2",
+            (GamepadButton::South, _) => "a",
+            (GamepadButton::East, _) => "b",
+            (GamepadButton::West, _) => "x",
+            (GamepadButton::North, _) => "y",
+            (GamepadButton::LeftBumper, _) => "lb",
+            (GamepadButton::RightBumper, _) => "rb",
+            (GamepadButton::LeftTrigger, _) => "lt",
+            (GamepadButton::RightTrigger, _) => "rt",
+            (GamepadButton::Select, _) => "view",
+            (GamepadButton::Start, _) => "menu",
+            (GamepadButton::LeftStick, _) => "left_stick",
+            (GamepadButton::RightStick, _) => "right_stick",
+            (GamepadButton::DPadUp, _) => "dpad_up",
+            (GamepadButton::DPadDown, _) => "dpad_down",
+            (GamepadButton::DPadLeft, _) => "dpad_left",
+            (GamepadButton::DPadRight, _) => "dpad_right",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ActionBindings {
+    //The name of the key, which is the name of its glyph ("space", "e", "left_shift").
+    key: Option<String>,
+    button: Option<GamepadButton>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlyphEvent {
+    DeviceChanged(DeviceFamily),
+}
+
+pub struct GlyphService {
+    root: String,
+    bindings: HashMap<String, ActionBindings>,
+    active_device: DeviceFamily,
+    events: Vec<GlyphEvent>,
+}
+
+impl GlyphService {
+    pub fn new<S: Into<String>>(glyph_root: S) -> Self {
+        GlyphService {
+            root: glyph_root.into(),
+            bindings: HashMap::new(),
+            active_device: DeviceFamily::Keyboard,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn bind_key(&mut self, action: &str, key: &str) {
+        self.bindings.entry(action.to_string()).or_insert_with(ActionBindings::default).key = Some(key.to_lowercase());
+    }
+
+    pub fn bind_button(&mut self, action: &str, button: GamepadButton) {
+        self.bindings.entry(action.to_string()).or_insert_with(ActionBindings::default).button = Some(button);
+    }
+
+    pub fn active_device(&self) -> DeviceFamily {
+        self.active_device
+    }
+
+    //Called by the input system for each input, with the device it comes from.
+    pub fn on_input_from(&mut self, family: DeviceFamily) {
+        if family != self.active_device {
+            debug!("The player switched to a {:?} device.", family);
+            self.active_device = family;
+            self.events.push(GlyphEvent::DeviceChanged(family));
+        }
+    }
+
+    pub fn poll_events(&mut self) -> Vec<GlyphEvent> {
+        self.events.drain(..).collect()
+    }
+
+    //The path of the glyph of the action on the active device, None if the action isn't bound on it.
+    pub fn glyph(&self, action: &str) -> Option<String> {
+        let bindings = self.bindings.get(action)?;
+        let name = match self.active_device {
+            DeviceFamily::Keyboard => bindings.key.clone()?,
+            family => bindings.button?.glyph_name(family).to_string(),
+        };
+        Some(format!("{}/{}/{}.png", self.root, self.active_device.directory(), name))
+    }
+}
+
+#[cfg(test)]
+mod glyphs_test {
+    use super::*;
+
+    #[test]
+    fn glyphs_follow_the_active_device() {
+        let mut glyphs = GlyphService::new("ui/glyphs");
+        glyphs.bind_key("jump", "Space");
+        glyphs.bind_button("jump", GamepadButton::South);
+        glyphs.bind_button("reload", GamepadButton::West);
+        assert_eq!(glyphs.glyph("jump"), Some(String::from("ui/glyphs/keyboard/space.png")));
+        assert_eq!(glyphs.glyph("reload"), None);
+
+        glyphs.on_input_from(DeviceFamily::from_vendor_id(0x054c));
+        glyphs.on_input_from(DeviceFamily::PlayStation);
+        assert_eq!(glyphs.poll_events(), vec![GlyphEvent::DeviceChanged(DeviceFamily::PlayStation)]);
+        assert_eq!(glyphs.glyph("jump"), Some(String::from("ui/glyphs/playstation/cross.png")));
+
+        glyphs.on_input_from(DeviceFamily::Switch);
+        assert_eq!(glyphs.glyph("jump"), Some(String::from("ui/glyphs/switch/b.png")));
+        assert_eq!(glyphs.glyph("reload"), Some(String::from("ui/glyphs/switch/y.png")));
+
+        glyphs.bind_button("aim", GamepadButton::LeftTrigger);
+        glyphs.on_input_from(DeviceFamily::from_name("steam_deck").unwrap());
+        assert_eq!(glyphs.glyph("aim"), Some(String::from("ui/glyphs/steam_deck/l2.png")));
+        assert_eq!(glyphs.glyph("jump"), Some(String::from("ui/glyphs/steam_deck/a.png")));
+    }
+}
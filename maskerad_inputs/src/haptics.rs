@@ -0,0 +1,299 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ HAPTICS.
+
+ A haptic effect is an asset: envelopes over time for the low frequency motor (heavy rumble) and the
+ high frequency motor (light buzz) of the gamepads, plus an optional frequency envelope, in Hz, for the
+ gamepads with voice coil actuators.
+
+ {
+    "duration": 0.3,
+    "low": [[0.0, 1.0], [0.3, 0.0]],
+    "high": [[0.0, 0.5], [0.1, 0.0]]
+ }
+
+ Like the audio events, the gameplay posts haptic events by name ("explosion", "footstep"): the
+ HapticLibrary knows the effect of each event, and the HapticPlayer mixes the effects playing on each
+ gamepad. Each frame, the state of the motors is given to the HapticDevice of the platform.
+*/
+
+use std::collections::HashMap;
+use std::io::Read;
+use serde_json;
+use input_error::{InputError, InputResult};
+
+pub type GamepadId = u32;
+
+//(time in seconds, value) points, linearly interpolated.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HapticEnvelope(pub Vec<(f32, f32)>);
+
+impl HapticEnvelope {
+    pub fn constant(value: f32, duration: f32) -> Self {
+        HapticEnvelope(vec![(0.0, value), (duration, value)])
+    }
+
+    pub fn sample(&self, time: f32) -> f32 {
+        let points = &self.0;
+        match points.iter().position(|&(point_time, _)| point_time > time) {
+            Some(0) => points[0].1,
+            Some(index) => {
+                let (start_time, start) = points[index - 1];
+                let (end_time, end) = points[index];
+                start + (end - start) * (time - start_time) / (end_time - start_time)
+            },
+            None => points.last().map(|&(_, value)| value).unwrap_or(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HapticEffect {
+    pub duration: f32,
+    #[serde(default)]
+    pub low: HapticEnvelope,
+    #[serde(default)]
+    pub high: HapticEnvelope,
+    #[serde(default)]
+    pub frequency: Option<HapticEnvelope>,
+}
+
+impl HapticEffect {
+    pub fn from_reader<R: Read>(reader: R) -> InputResult<Self> {
+        debug!("Deserializing a haptic effect.");
+        serde_json::from_reader(reader).map_err(|json_error| {
+            InputError::from(json_error)
+        })
+    }
+
+    //A short tick of the high frequency motor: UI navigation, a weapon click.
+    pub fn click() -> Self {
+        HapticEffect {
+            duration: 0.03,
+            low: HapticEnvelope::default(),
+            high: HapticEnvelope::constant(0.6, 0.03),
+            frequency: None,
+        }
+    }
+
+    //Both motors at a constant strength.
+    pub fn rumble(duration: f32, low: f32, high: f32) -> Self {
+        HapticEffect {
+            duration,
+            low: HapticEnvelope::constant(low, duration),
+            high: HapticEnvelope::constant(high, duration),
+            frequency: None,
+        }
+    }
+
+    //A hit: full strength, fading out.
+    pub fn impact(duration: f32, strength: f32) -> Self {
+        HapticEffect {
+            duration,
+            low: HapticEnvelope(vec![(0.0, strength), (duration, 0.0)]),
+            high: HapticEnvelope(vec![(0.0, strength), (duration * 0.3, 0.0)]),
+            frequency: None,
+        }
+    }
+
+    //An engine starting: fading in.
+    pub fn ramp_up(duration: f32, strength: f32) -> Self {
+        HapticEffect {
+            duration,
+            low: HapticEnvelope(vec![(0.0, 0.0), (duration, strength)]),
+            high: HapticEnvelope::default(),
+            frequency: None,
+        }
+    }
+
+    //Two beats, for low health.
+    pub fn heartbeat() -> Self {
+        HapticEffect {
+            duration: 0.6,
+            low: HapticEnvelope(vec![(0.0, 0.8), (0.1, 0.0), (0.2, 0.0), (0.25, 0.5), (0.35, 0.0)]),
+            high: HapticEnvelope::default(),
+            frequency: None,
+        }
+    }
+}
+
+//The state of the motors of a gamepad, between 0 and 1.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct MotorState {
+    pub low: f32,
+    pub high: f32,
+    pub frequency: Option<f32>,
+}
+
+//Implemented by the platform layer.
+pub trait HapticDevice {
+    fn set_motors(&mut self, gamepad: GamepadId, state: MotorState);
+}
+
+#[derive(Debug, Default)]
+pub struct HapticLibrary {
+    events: HashMap<String, HapticEffect>,
+}
+
+impl HapticLibrary {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    //The primitives, under their names.
+    pub fn with_primitives() -> Self {
+        let mut library = HapticLibrary::new();
+        library.bind_event("click", HapticEffect::click());
+        library.bind_event("impact", HapticEffect::impact(0.25, 1.0));
+        library.bind_event("rumble", HapticEffect::rumble(0.5, 0.5, 0.2));
+        library.bind_event("ramp_up", HapticEffect::ramp_up(1.0, 0.7));
+        library.bind_event("heartbeat", HapticEffect::heartbeat());
+        library
+    }
+
+    pub fn bind_event<S: Into<String>>(&mut self, event_name: S, effect: HapticEffect) {
+        self.events.insert(event_name.into(), effect);
+    }
+
+    pub fn effect(&self, event_name: &str) -> Option<&HapticEffect> {
+        self.events.get(event_name)
+    }
+}
+
+struct PlayingEffect {
+    gamepad: GamepadId,
+    effect: HapticEffect,
+    intensity: f32,
+    time: f32,
+}
+
+#[derive(Default)]
+pub struct HapticPlayer {
+    playing: Vec<PlayingEffect>,
+    //The gamepads with motors running, to stop them when their effects end.
+    active: Vec<GamepadId>,
+    enabled: bool,
+}
+
+impl HapticPlayer {
+    pub fn new() -> Self {
+        HapticPlayer {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    //The players can disable the vibrations in the options.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.playing.clear();
+        }
+    }
+
+    pub fn play(&mut self, gamepad: GamepadId, effect: HapticEffect, intensity: f32) {
+        if self.enabled {
+            self.playing.push(PlayingEffect {
+                gamepad,
+                effect,
+                intensity: intensity.max(0.0).min(1.0),
+                time: 0.0,
+            });
+        }
+    }
+
+    pub fn post_event(&mut self, library: &HapticLibrary, event_name: &str, gamepad: GamepadId, intensity: f32) {
+        match library.effect(event_name) {
+            Some(effect) => self.play(gamepad, effect.clone(), intensity),
+            None => warn!("No haptic effect for the event {}.", event_name),
+        }
+    }
+
+    pub fn stop(&mut self, gamepad: GamepadId) {
+        self.playing.retain(|playing| playing.gamepad != gamepad);
+    }
+
+    pub fn is_playing(&self, gamepad: GamepadId) -> bool {
+        self.playing.iter().any(|playing| playing.gamepad == gamepad)
+    }
+
+    //Advance the effects, and give the state of the motors to the device.
+    //The effects playing on the same gamepad are mixed by taking the strongest value.
+    pub fn update(&mut self, delta_seconds: f32, device: &mut HapticDevice) {
+        let mut states: Vec<(GamepadId, MotorState)> = Vec::new();
+        for playing in self.playing.iter() {
+            let state = MotorState {
+                low: playing.effect.low.sample(playing.time) * playing.intensity,
+                high: playing.effect.high.sample(playing.time) * playing.intensity,
+                frequency: playing.effect.frequency.as_ref().map(|frequency| frequency.sample(playing.time)),
+            };
+            match states.iter_mut().find(|&&mut (gamepad, _)| gamepad == playing.gamepad) {
+                Some(&mut (_, ref mut mixed)) => {
+                    mixed.low = mixed.low.max(state.low);
+                    mixed.high = mixed.high.max(state.high);
+                    mixed.frequency = mixed.frequency.or(state.frequency);
+                },
+                None => states.push((playing.gamepad, state)),
+            }
+        }
+        for &gamepad in self.active.iter() {
+            if !states.iter().any(|&(active, _)| active == gamepad) {
+                states.push((gamepad, MotorState::default()));
+            }
+        }
+        self.active = states.iter().filter(|&&(_, state)| state != MotorState::default()).map(|&(gamepad, _)| gamepad).collect();
+        for (gamepad, state) in states {
+            device.set_motors(gamepad, state);
+        }
+
+        for playing in self.playing.iter_mut() {
+            playing.time += delta_seconds;
+        }
+        self.playing.retain(|playing| playing.time < playing.effect.duration);
+    }
+}
+
+#[cfg(test)]
+mod haptics_test {
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordedDevice(Vec<(GamepadId, MotorState)>);
+
+    impl HapticDevice for RecordedDevice {
+        fn set_motors(&mut self, gamepad: GamepadId, state: MotorState) {
+            self.0.push((gamepad, state));
+        }
+    }
+
+    #[test]
+    fn haptics_effect_asset_and_mixing() {
+        let effect = HapticEffect::from_reader(r#"{ "duration": 0.2, "low": [[0.0, 1.0], [0.2, 0.0]] }"#.as_bytes()).unwrap();
+        assert_eq!(effect.low.sample(0.1), 0.5);
+        assert_eq!(effect.high.sample(0.1), 0.0);
+
+        let mut library = HapticLibrary::with_primitives();
+        library.bind_event("explosion", effect);
+        let mut player = HapticPlayer::new();
+        let mut device = RecordedDevice::default();
+        player.post_event(&library, "explosion", 0, 1.0);
+        player.post_event(&library, "click", 0, 0.5);
+        player.update(0.1, &mut device);
+        assert_eq!(device.0, vec![(0, MotorState { low: 1.0, high: 0.3, frequency: None })]);
+
+        player.update(0.15, &mut device);
+        assert_eq!(device.0[1].1.low, 0.5);
+        assert!(!player.is_playing(0));
+        //The motors are stopped once.
+        player.update(0.1, &mut device);
+        player.update(0.1, &mut device);
+        assert_eq!(device.0.len(), 3);
+        assert_eq!(device.0[2].1, MotorState::default());
+    }
+}
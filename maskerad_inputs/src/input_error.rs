@@ -0,0 +1,64 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::error::Error;
+use std::fmt;
+use serde_json::Error as JSONError;
+
+#[derive(Debug)]
+pub enum InputError {
+    HapticError(String, JSONError),
+    ComboError(String),
+}
+
+unsafe impl Send for InputError {}
+unsafe impl Sync for InputError {}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &InputError::HapticError(ref description, _) => {
+                write!(f, "Haptic error: {}", description)
+            },
+            &InputError::ComboError(ref description) => {
+                write!(f, "Combo error: {}", description)
+            },
+        }
+    }
+}
+
+impl Error for InputError {
+    fn description(&self) -> &str {
+        match self {
+            &InputError::HapticError(_, _) => {
+                "HapticError"
+            },
+            &InputError::ComboError(_) => {
+                "ComboError"
+            },
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match self {
+            &InputError::HapticError(_, ref json_error) => {
+                Some(json_error)
+            },
+            &InputError::ComboError(_) => {
+                None
+            },
+        }
+    }
+}
+
+pub type InputResult<T> = Result<T, InputError>;
+
+impl From<JSONError> for InputError {
+    fn from(error: JSONError) -> Self {
+        InputError::HapticError(String::from("Error while deserializing a haptic effect."), error)
+    }
+}
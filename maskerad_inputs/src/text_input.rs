@@ -0,0 +1,263 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ TEXT INPUT.
+
+ The key events can't be used to type text: a character may need several keys (dead keys, AltGr),
+ and the CJK languages are typed with an input method editor (IME). While the player types, the IME
+ shows a composition (the syllables being typed, with its own cursor), and commits the final text.
+
+ The window system feeds the TextInput with TextInputEvents, only while text input is enabled: the
+ UI enables it when a text field gets the focus, with the area of the field, so the IME can place its
+ candidate window next to it.
+
+ The TextField applies the events to a text, with a cursor counted in characters. A password field
+ displays bullets, and can't be copied or cut, but it can be pasted into.
+*/
+
+use std::collections::VecDeque;
+use clipboard::Clipboard;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextInputEvent {
+    //Committed text: typed characters, or the final text of an IME composition.
+    Text(String),
+    //The current IME composition, with the cursor in it (in characters). An empty text ends the composition.
+    Composition { text: String, cursor: usize },
+    Backspace,
+    Delete,
+    CursorLeft,
+    CursorRight,
+    Home,
+    End,
+    Copy,
+    Cut,
+    Paste,
+}
+
+//In pixels, where the IME places its candidate window.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextInputArea {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct TextInput {
+    area: Option<TextInputArea>,
+    events: VecDeque<TextInputEvent>,
+}
+
+impl TextInput {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    //Called by the UI when a text field gets the focus. The window system enables the IME.
+    pub fn start(&mut self, area: TextInputArea) {
+        trace!("Text input enabled.");
+        self.area = Some(area);
+    }
+
+    pub fn stop(&mut self) {
+        trace!("Text input disabled.");
+        self.area = None;
+        self.events.clear();
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.area.is_some()
+    }
+
+    pub fn area(&self) -> Option<TextInputArea> {
+        self.area
+    }
+
+    //Called by the window system. The events received while text input is disabled are dropped.
+    pub fn push(&mut self, event: TextInputEvent) {
+        if self.is_active() {
+            self.events.push_back(event);
+        }
+    }
+
+    pub fn poll_events(&mut self) -> Vec<TextInputEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextField {
+    text: String,
+    //In characters.
+    cursor: usize,
+    composition: Option<(String, usize)>,
+    max_length: Option<usize>,
+    password: bool,
+}
+
+impl TextField {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn password() -> Self {
+        TextField {
+            password: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.chars().count();
+        self.composition = None;
+    }
+
+    //The IME composition being typed, and its cursor.
+    pub fn composition(&self) -> Option<(&str, usize)> {
+        self.composition.as_ref().map(|&(ref text, cursor)| (text.as_str(), cursor))
+    }
+
+    //The text to draw: bullets for a password, and the composition at the cursor.
+    pub fn display_text(&self) -> String {
+        let mut text: String = if self.password {self.text.chars().map(|_| '\u{2022}').collect()} else {self.text.clone()};
+        if let Some((ref composition, _)) = self.composition {
+            let index = byte_index(text.as_str(), self.cursor);
+            text.insert_str(index, composition.as_str());
+        }
+        text
+    }
+
+    fn insert(&mut self, inserted: &str) {
+        let length = self.text.chars().count();
+        let room = self.max_length.map(|max| max.saturating_sub(length)).unwrap_or(usize::max_value());
+        let inserted: String = inserted.chars().filter(|character| !character.is_control()).take(room).collect();
+        let index = byte_index(self.text.as_str(), self.cursor);
+        self.text.insert_str(index, inserted.as_str());
+        self.cursor += inserted.chars().count();
+    }
+
+    fn remove(&mut self, position: usize) {
+        if position < self.text.chars().count() {
+            let index = byte_index(self.text.as_str(), position);
+            self.text.remove(index);
+        }
+    }
+
+    //Returns true if the text changed.
+    pub fn apply(&mut self, event: &TextInputEvent, clipboard: &mut Clipboard) -> bool {
+        let before = self.text.clone();
+        let length = self.text.chars().count();
+        match *event {
+            TextInputEvent::Text(ref text) => {
+                self.composition = None;
+                self.insert(text.as_str());
+            },
+            TextInputEvent::Composition { ref text, cursor } => {
+                self.composition = if text.is_empty() {None} else {Some((text.clone(), cursor.min(text.chars().count())))};
+            },
+            //While composing, the editing keys belong to the IME.
+            _ if self.composition.is_some() => {},
+            TextInputEvent::Backspace => if self.cursor > 0 {
+                self.cursor -= 1;
+                let cursor = self.cursor;
+                self.remove(cursor);
+            },
+            TextInputEvent::Delete => {
+                let cursor = self.cursor;
+                self.remove(cursor);
+            },
+            TextInputEvent::CursorLeft => self.cursor = self.cursor.saturating_sub(1),
+            TextInputEvent::CursorRight => self.cursor = (self.cursor + 1).min(length),
+            TextInputEvent::Home => self.cursor = 0,
+            TextInputEvent::End => self.cursor = length,
+            TextInputEvent::Copy | TextInputEvent::Cut if self.password => {
+                debug!("A password field can't be copied.");
+            },
+            TextInputEvent::Copy => clipboard.set_text(self.text.as_str()),
+            TextInputEvent::Cut => {
+                clipboard.set_text(self.text.as_str());
+                self.set_text("");
+            },
+            TextInputEvent::Paste => {
+                if let Some(text) = clipboard.text() {
+                    //A pasted password often ends with a new line.
+                    self.insert(text.trim_end_matches(|character| character == '\n' || character == '\r'));
+                }
+            },
+        }
+        self.text != before
+    }
+}
+
+fn byte_index(text: &str, characters: usize) -> usize {
+    text.char_indices().nth(characters).map(|(index, _)| index).unwrap_or(text.len())
+}
+
+#[cfg(test)]
+mod text_input_test {
+    use super::*;
+    use clipboard::MemoryClipboard;
+
+    #[test]
+    fn text_input_ime_composition() {
+        let mut input = TextInput::new();
+        input.push(TextInputEvent::Text(String::from("lost")));
+        input.start(TextInputArea { x: 10, y: 20, width: 200, height: 30 });
+        input.push(TextInputEvent::Text(String::from("東")));
+        input.push(TextInputEvent::Composition { text: String::from("きょう"), cursor: 3 });
+        input.push(TextInputEvent::Backspace);
+        input.push(TextInputEvent::Composition { text: String::from("京"), cursor: 1 });
+
+        let mut field = TextField::new();
+        let mut clipboard = MemoryClipboard::new();
+        for event in input.poll_events() {
+            field.apply(&event, &mut clipboard);
+        }
+        assert_eq!(field.text(), "東");
+        assert_eq!(field.composition(), Some(("京", 1)));
+        assert_eq!(field.display_text(), "東京");
+
+        field.apply(&TextInputEvent::Text(String::from("京")), &mut clipboard);
+        assert_eq!(field.composition(), None);
+        field.apply(&TextInputEvent::CursorLeft, &mut clipboard);
+        field.apply(&TextInputEvent::Text(String::from("都")), &mut clipboard);
+        assert_eq!(field.text(), "東都京");
+        field.apply(&TextInputEvent::Backspace, &mut clipboard);
+        assert_eq!((field.text(), field.cursor()), ("東京", 1));
+    }
+
+    #[test]
+    fn text_input_password_clipboard() {
+        let mut clipboard = MemoryClipboard::new();
+        clipboard.set_text("hunter2\n");
+        let mut field = TextField::password().with_max_length(6);
+        assert!(field.apply(&TextInputEvent::Paste, &mut clipboard));
+        assert_eq!(field.text(), "hunter");
+        assert_eq!(field.display_text().chars().count(), 6);
+
+        field.apply(&TextInputEvent::Copy, &mut clipboard);
+        assert_eq!(clipboard.text(), Some(String::from("hunter2\n")));
+        assert!(!field.apply(&TextInputEvent::Cut, &mut clipboard));
+    }
+}
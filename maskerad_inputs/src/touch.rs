@@ -0,0 +1,154 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ TOUCH INPUT.
+
+ The platform layer gives the touch events of the screen, a pointer id by finger. The touches are
+ tracked from their beginning to their end, the game reads the active touches of the frame, and the
+ touches which ended during the frame (a tap is a touch which ended without moving much).
+
+ A cancelled touch (the system took the gesture, a notification is pulled down...) ends without being a tap.
+*/
+
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TouchPhase {
+    Began,
+    Moved,
+    Ended,
+    Cancelled,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TouchEvent {
+    pub pointer_id: u64,
+    pub phase: TouchPhase,
+    //In physical pixels.
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Touch {
+    pub pointer_id: u64,
+    pub start: (f32, f32),
+    pub position: (f32, f32),
+    pub phase: TouchPhase,
+}
+
+impl Touch {
+    pub fn distance(&self) -> f32 {
+        let (dx, dy) = (self.position.0 - self.start.0, self.position.1 - self.start.1);
+        (dx * dx + dy * dy).sqrt()
+    }
+}
+
+pub struct TouchState {
+    active: HashMap<u64, Touch>,
+    ended: Vec<Touch>,
+    //The maximum distance of a tap, in physical pixels.
+    tap_slop: f32,
+}
+
+impl TouchState {
+    pub fn new(tap_slop: f32) -> Self {
+        TouchState {
+            active: HashMap::new(),
+            ended: Vec::new(),
+            tap_slop,
+        }
+    }
+
+    pub fn handle(&mut self, event: TouchEvent) {
+        let position = (event.x, event.y);
+        match event.phase {
+            TouchPhase::Began => {
+                self.active.insert(event.pointer_id, Touch {
+                    pointer_id: event.pointer_id,
+                    start: position,
+                    position,
+                    phase: TouchPhase::Began,
+                });
+            },
+            TouchPhase::Moved => {
+                if let Some(touch) = self.active.get_mut(&event.pointer_id) {
+                    touch.position = position;
+                    touch.phase = TouchPhase::Moved;
+                }
+            },
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                if let Some(mut touch) = self.active.remove(&event.pointer_id) {
+                    touch.position = position;
+                    touch.phase = event.phase;
+                    self.ended.push(touch);
+                }
+            },
+        }
+    }
+
+    //All the touches end, when the application loses the focus.
+    pub fn cancel_all(&mut self) {
+        for (_, mut touch) in self.active.drain() {
+            touch.phase = TouchPhase::Cancelled;
+            self.ended.push(touch);
+        }
+    }
+
+    pub fn active(&self) -> Vec<&Touch> {
+        let mut touches: Vec<&Touch> = self.active.values().collect();
+        touches.sort_by_key(|touch| touch.pointer_id);
+        touches
+    }
+
+    pub fn ended(&self) -> &[Touch] {
+        &self.ended
+    }
+
+    pub fn taps(&self) -> Vec<(f32, f32)> {
+        self.ended.iter()
+            .filter(|touch| touch.phase == TouchPhase::Ended && touch.distance() <= self.tap_slop)
+            .map(|touch| touch.position)
+            .collect()
+    }
+
+    pub fn end_frame(&mut self) {
+        self.ended.clear();
+    }
+}
+
+#[cfg(test)]
+mod touch_test {
+    use super::*;
+
+    fn event(pointer_id: u64, phase: TouchPhase, x: f32, y: f32) -> TouchEvent {
+        TouchEvent { pointer_id, phase, x, y }
+    }
+
+    #[test]
+    fn touches_and_taps() {
+        let mut touches = TouchState::new(10.0);
+        touches.handle(event(0, TouchPhase::Began, 100.0, 100.0));
+        touches.handle(event(1, TouchPhase::Began, 300.0, 100.0));
+        touches.handle(event(1, TouchPhase::Moved, 300.0, 200.0));
+        assert_eq!(touches.active().len(), 2);
+        assert_eq!(touches.active()[1].phase, TouchPhase::Moved);
+
+        touches.handle(event(0, TouchPhase::Ended, 104.0, 103.0));
+        touches.handle(event(1, TouchPhase::Ended, 300.0, 250.0));
+        //A swipe is not a tap.
+        assert_eq!(touches.taps(), vec![(104.0, 103.0)]);
+        assert!(touches.active().is_empty());
+
+        touches.end_frame();
+        touches.handle(event(2, TouchPhase::Began, 0.0, 0.0));
+        touches.cancel_all();
+        assert!(touches.taps().is_empty());
+        assert_eq!(touches.ended()[0].phase, TouchPhase::Cancelled);
+    }
+}
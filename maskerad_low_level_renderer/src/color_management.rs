@@ -0,0 +1,246 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ COLOR MANAGEMENT.
+
+ The scene is rendered in linear Rec.709 (the sRGB primaries), in HDR: the sRGB textures are sampled
+ through sRGB views (see the texture importer), the GPU converts them to linear.
+
+ The output mode is negotiated with the swapchain, from the video settings and the surface formats:
+ - SDR: 8 bits sRGB. With an sRGB format the hardware encodes, with a UNORM format the shader does.
+ - HDR10: 10 bits, Rec.2020 primaries, PQ (ST 2084) encoding, in nits.
+ - scRGB: 16 bits float, linear Rec.709, 1.0 is 80 nits, the compositor converts for the display.
+ HDR modes the display or the swapchain do not support fall back to SDR.
+
+ The last pass of the post stack tonemaps and encodes the image for the output mode: ColorOutput::encode
+ is the reference of the shader, used to bake the LUTs and check the golden images.
+*/
+
+use maskerad_core::engine_configuration::video::{HdrOutput, Tonemapper, VideoSettings};
+
+pub const VK_FORMAT_B8G8R8A8_UNORM: i32 = 44;
+pub const VK_FORMAT_B8G8R8A8_SRGB: i32 = 50;
+pub const VK_FORMAT_A2B10G10R10_UNORM_PACK32: i32 = 64;
+pub const VK_FORMAT_R16G16B16A16_SFLOAT: i32 = 97;
+pub const VK_COLOR_SPACE_SRGB_NONLINEAR_KHR: i32 = 0;
+pub const VK_COLOR_SPACE_EXTENDED_SRGB_LINEAR_EXT: i32 = 1000104002;
+pub const VK_COLOR_SPACE_HDR10_ST2084_EXT: i32 = 1000104008;
+
+//The brightness of 1.0 in scRGB.
+const SCRGB_NITS: f32 = 80.0;
+const PQ_MAX_NITS: f32 = 10000.0;
+
+const REC709_TO_REC2020: [[f32; 3]; 3] = [
+    [0.6274, 0.3293, 0.0433],
+    [0.0691, 0.9195, 0.0114],
+    [0.0164, 0.0880, 0.8956],
+];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SurfaceFormat {
+    pub format: i32,
+    pub color_space: i32,
+}
+
+impl SurfaceFormat {
+    pub fn new(format: i32, color_space: i32) -> Self {
+        SurfaceFormat {
+            format,
+            color_space,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OutputMode {
+    Sdr,
+    Hdr10,
+    ScRgb,
+}
+
+impl OutputMode {
+    pub fn is_hdr(&self) -> bool {
+        *self != OutputMode::Sdr
+    }
+
+    fn surface_format(&self) -> SurfaceFormat {
+        match *self {
+            OutputMode::Sdr => SurfaceFormat::new(VK_FORMAT_B8G8R8A8_SRGB, VK_COLOR_SPACE_SRGB_NONLINEAR_KHR),
+            OutputMode::Hdr10 => SurfaceFormat::new(VK_FORMAT_A2B10G10R10_UNORM_PACK32, VK_COLOR_SPACE_HDR10_ST2084_EXT),
+            OutputMode::ScRgb => SurfaceFormat::new(VK_FORMAT_R16G16B16A16_SFLOAT, VK_COLOR_SPACE_EXTENDED_SRGB_LINEAR_EXT),
+        }
+    }
+}
+
+//What the platform reports about the display of the window.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DisplayCapabilities {
+    pub hdr_enabled: bool,
+    pub peak_nits: Option<f32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColorOutput {
+    pub mode: OutputMode,
+    pub surface: SurfaceFormat,
+    //The surface is UNORM: the shader encodes to sRGB.
+    pub shader_srgb_encode: bool,
+    pub tonemapper: Tonemapper,
+    pub exposure_scale: f32,
+    pub paper_white_nits: f32,
+    pub peak_nits: f32,
+}
+
+//The output for the settings, among the formats supported by the surface.
+pub fn negotiate(settings: &VideoSettings, display: &DisplayCapabilities, supported: &[SurfaceFormat]) -> ColorOutput {
+    let available = |mode: OutputMode| display.hdr_enabled && supported.contains(&mode.surface_format());
+    let mode = match settings.hdr_output {
+        HdrOutput::Off => OutputMode::Sdr,
+        HdrOutput::Auto if available(OutputMode::Hdr10) => OutputMode::Hdr10,
+        HdrOutput::Auto if available(OutputMode::ScRgb) => OutputMode::ScRgb,
+        HdrOutput::Auto => OutputMode::Sdr,
+        HdrOutput::Hdr10 | HdrOutput::ScRgb => {
+            let requested = if settings.hdr_output == HdrOutput::Hdr10 { OutputMode::Hdr10 } else { OutputMode::ScRgb };
+            if available(requested) {
+                requested
+            } else {
+                warn!("The {:?} output is not supported by the display, the output is SDR.", requested);
+                OutputMode::Sdr
+            }
+        },
+    };
+
+    let (surface, shader_srgb_encode) = if mode.is_hdr() || supported.is_empty() || supported.contains(&mode.surface_format()) {
+        (mode.surface_format(), false)
+    } else {
+        let unorm = SurfaceFormat::new(VK_FORMAT_B8G8R8A8_UNORM, VK_COLOR_SPACE_SRGB_NONLINEAR_KHR);
+        let fallback = if supported.contains(&unorm) { unorm } else { supported[0] };
+        (fallback, true)
+    };
+    let paper_white_nits = settings.paper_white_nits.max(1.0);
+    let peak_nits = display.peak_nits.map(|peak| peak.min(settings.peak_nits)).unwrap_or(settings.peak_nits).max(paper_white_nits);
+    debug!("Color output: {:?}, surface format {} / color space {}.", mode, surface.format, surface.color_space);
+    ColorOutput {
+        mode,
+        surface,
+        shader_srgb_encode,
+        tonemapper: settings.tonemapper,
+        exposure_scale: 2f32.powf(settings.exposure),
+        paper_white_nits,
+        peak_nits,
+    }
+}
+
+//The ACES filmic curve fitted by Narkowicz, to [0, 1].
+fn aces(x: f32) -> f32 {
+    (x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)).max(0.0).min(1.0)
+}
+
+//From [0, inf[ to [0, max].
+pub fn tonemap(value: f32, tonemapper: Tonemapper, max: f32) -> f32 {
+    let value = value.max(0.0);
+    match tonemapper {
+        Tonemapper::None => value.min(max),
+        Tonemapper::Reinhard => value / (1.0 + value / max),
+        Tonemapper::Aces => aces(value / max) * max,
+    }
+}
+
+pub fn srgb_encode(linear: f32) -> f32 {
+    let linear = linear.max(0.0).min(1.0);
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+//ST 2084: nits to [0, 1].
+pub fn pq_encode(nits: f32) -> f32 {
+    let (m1, m2) = (0.159_301_76, 78.843_75);
+    let (c1, c2, c3) = (0.835_937_5, 18.851_563, 18.6875);
+    let y = (nits / PQ_MAX_NITS).max(0.0).min(1.0).powf(m1);
+    ((c1 + c2 * y) / (1.0 + c3 * y)).powf(m2)
+}
+
+impl ColorOutput {
+    //A linear Rec.709 color of the scene (1.0 is the paper white) to the value written in the swapchain.
+    pub fn encode(&self, color: [f32; 3]) -> [f32; 3] {
+        let exposed = [color[0] * self.exposure_scale, color[1] * self.exposure_scale, color[2] * self.exposure_scale];
+        match self.mode {
+            OutputMode::Sdr => {
+                let mapped = [
+                    tonemap(exposed[0], self.tonemapper, 1.0),
+                    tonemap(exposed[1], self.tonemapper, 1.0),
+                    tonemap(exposed[2], self.tonemapper, 1.0),
+                ];
+                if self.shader_srgb_encode {
+                    [srgb_encode(mapped[0]), srgb_encode(mapped[1]), srgb_encode(mapped[2])]
+                } else {
+                    mapped
+                }
+            },
+            OutputMode::Hdr10 | OutputMode::ScRgb => {
+                let max = self.peak_nits / self.paper_white_nits;
+                let nits = |value: f32| tonemap(value, self.tonemapper, max) * self.paper_white_nits;
+                if self.mode == OutputMode::ScRgb {
+                    return [nits(exposed[0]) / SCRGB_NITS, nits(exposed[1]) / SCRGB_NITS, nits(exposed[2]) / SCRGB_NITS];
+                }
+                let mut encoded = [0.0; 3];
+                for (row, value) in REC709_TO_REC2020.iter().zip(encoded.iter_mut()) {
+                    let rec2020 = row[0] * exposed[0] + row[1] * exposed[1] + row[2] * exposed[2];
+                    *value = pq_encode(nits(rec2020));
+                }
+                encoded
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod color_management_test {
+    use super::*;
+
+    #[test]
+    fn color_output_negotiation_and_encoding() {
+        let sdr_formats = [SurfaceFormat::new(VK_FORMAT_B8G8R8A8_UNORM, VK_COLOR_SPACE_SRGB_NONLINEAR_KHR)];
+        let hdr_formats = [
+            SurfaceFormat::new(VK_FORMAT_B8G8R8A8_SRGB, VK_COLOR_SPACE_SRGB_NONLINEAR_KHR),
+            SurfaceFormat::new(VK_FORMAT_R16G16B16A16_SFLOAT, VK_COLOR_SPACE_EXTENDED_SRGB_LINEAR_EXT),
+            SurfaceFormat::new(VK_FORMAT_A2B10G10R10_UNORM_PACK32, VK_COLOR_SPACE_HDR10_ST2084_EXT),
+        ];
+        let hdr_display = DisplayCapabilities { hdr_enabled: true, peak_nits: Some(600.0) };
+        let sdr_display = DisplayCapabilities { hdr_enabled: false, peak_nits: None };
+        let settings = VideoSettings::default();
+
+        let output = negotiate(&settings, &hdr_display, &hdr_formats);
+        assert_eq!(output.mode, OutputMode::Hdr10);
+        assert_eq!(output.peak_nits, 600.0);
+        assert_eq!(negotiate(&settings, &sdr_display, &hdr_formats).mode, OutputMode::Sdr);
+        let scrgb = negotiate(&VideoSettings { hdr_output: HdrOutput::ScRgb, ..settings.clone() }, &hdr_display, &hdr_formats);
+        assert_eq!(scrgb.surface.format, VK_FORMAT_R16G16B16A16_SFLOAT);
+        let unorm = negotiate(&VideoSettings { hdr_output: HdrOutput::Hdr10, ..settings.clone() }, &hdr_display, &sdr_formats);
+        assert_eq!(unorm.mode, OutputMode::Sdr);
+        assert!(unorm.shader_srgb_encode);
+
+        //The paper white: 200 nits in HDR10, 2.5 in scRGB.
+        assert!((pq_encode(10000.0) - 1.0).abs() < 1e-4);
+        assert!((pq_encode(100.0) - 0.508).abs() < 1e-3);
+        let white = negotiate(&VideoSettings { tonemapper: Tonemapper::None, ..settings.clone() }, &hdr_display, &hdr_formats);
+        assert!((white.encode([1.0, 1.0, 1.0])[1] - pq_encode(200.0)).abs() < 1e-3);
+        let white = ColorOutput { mode: OutputMode::ScRgb, ..white };
+        assert!((white.encode([1.0, 1.0, 1.0])[0] - 2.5).abs() < 1e-4);
+        assert!(white.encode([100.0, 0.0, 0.0])[0] <= 600.0 / 80.0);
+
+        //SDR: tonemapped to [0, 1], encoded by the shader on a UNORM surface.
+        let encoded = unorm.encode([1000.0, 0.5, 0.0]);
+        assert!(encoded[0] <= 1.0 && encoded[0] > 0.99);
+        assert!((srgb_encode(0.5) - 0.7354).abs() < 1e-3);
+        assert!(tonemap(4.0, Tonemapper::Reinhard, 1.0) < 1.0);
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ DEVICE RECOVERY.
+
+ The GPU device can be lost (driver reset, GPU hang, eGPU unplugged) and the surface can be lost (the
+ window moved to another display). Instead of aborting, the renderer recovers:
+ - surface lost or out of date: the swapchain is destroyed and recreated, at the size of the window,
+ - device lost: the swapchain and the device are destroyed and recreated, then the assets resident on
+   the GPU are uploaded again from the asset manager.
+
+ The systems holding GPU handles are notified with events. A device lost again and again (broken
+ driver) is given up after a number of attempts, and the renderer reports a failed status.
+*/
+
+use std::collections::VecDeque;
+use maskerad_core::system_status::SystemStatus;
+
+//The Vulkan result codes handled by the recovery.
+pub const VK_ERROR_DEVICE_LOST: i32 = -4;
+pub const VK_ERROR_SURFACE_LOST_KHR: i32 = -1000000000;
+pub const VK_ERROR_OUT_OF_DATE_KHR: i32 = -1000001004;
+pub const VK_SUBOPTIMAL_KHR: i32 = 1000001003;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GpuError {
+    DeviceLost,
+    SurfaceLost,
+    //The swapchain does not match the surface anymore (resized window).
+    OutOfDate,
+    Other(String),
+}
+
+impl GpuError {
+    //None for the success codes, suboptimal excepted.
+    pub fn from_vk_result(result: i32) -> Option<GpuError> {
+        match result {
+            VK_ERROR_DEVICE_LOST => Some(GpuError::DeviceLost),
+            VK_ERROR_SURFACE_LOST_KHR => Some(GpuError::SurfaceLost),
+            VK_ERROR_OUT_OF_DATE_KHR | VK_SUBOPTIMAL_KHR => Some(GpuError::OutOfDate),
+            result if result < 0 => Some(GpuError::Other(format!("Vulkan error {}", result))),
+            _ => None,
+        }
+    }
+}
+
+//Implemented by the backend.
+pub trait GraphicsDevice {
+    fn create_device(&mut self) -> Result<(), GpuError>;
+    fn destroy_device(&mut self);
+    fn create_swapchain(&mut self, width: u32, height: u32) -> Result<(), GpuError>;
+    fn destroy_swapchain(&mut self);
+}
+
+//Implemented by the asset manager: uploads again the assets which were on the lost device.
+pub trait ResidentAssets {
+    fn reupload(&mut self, device: &mut GraphicsDevice) -> Result<usize, GpuError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderEvent {
+    //The GPU handles are invalid, the systems must drop them.
+    DeviceLost,
+    DeviceRestored { reuploaded_assets: usize },
+    SwapchainRecreated { width: u32, height: u32 },
+    RecoveryFailed { reason: String },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RecoveryOutcome {
+    Recovered,
+    //The error is not recoverable, or the recovery failed: the frame is skipped.
+    Failed,
+    //The device was lost too many times, the renderer stopped recovering.
+    GaveUp,
+}
+
+pub struct DeviceRecovery {
+    max_device_losses: u32,
+    device_losses: u32,
+    status: SystemStatus,
+    events: VecDeque<RenderEvent>,
+}
+
+impl DeviceRecovery {
+    pub fn new(max_device_losses: u32) -> Self {
+        DeviceRecovery {
+            max_device_losses,
+            device_losses: 0,
+            status: SystemStatus::Ok,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn status(&self) -> SystemStatus {
+        self.status.clone()
+    }
+
+    pub fn poll_events(&mut self) -> Option<RenderEvent> {
+        self.events.pop_front()
+    }
+
+    fn fail(&mut self, reason: String) -> RecoveryOutcome {
+        error!("The GPU recovery failed: {}", reason);
+        self.status = SystemStatus::Degraded(reason.clone());
+        self.events.push_back(RenderEvent::RecoveryFailed {
+            reason,
+        });
+        RecoveryOutcome::Failed
+    }
+
+    fn recreate_swapchain(&mut self, device: &mut GraphicsDevice, width: u32, height: u32) -> Result<(), GpuError> {
+        device.destroy_swapchain();
+        device.create_swapchain(width, height)?;
+        self.events.push_back(RenderEvent::SwapchainRecreated {
+            width,
+            height,
+        });
+        Ok(())
+    }
+
+    //Handle an error of the frame. The size is the current size of the window.
+    pub fn handle(&mut self, error: GpuError, device: &mut GraphicsDevice, assets: &mut ResidentAssets, width: u32, height: u32) -> RecoveryOutcome {
+        if self.device_losses >= self.max_device_losses {
+            return RecoveryOutcome::GaveUp;
+        }
+        match error {
+            GpuError::SurfaceLost | GpuError::OutOfDate => {
+                debug!("Recreating the swapchain ({:?}).", error);
+                match self.recreate_swapchain(device, width, height) {
+                    Ok(_) => RecoveryOutcome::Recovered,
+                    //The surface may be gone for good, the device is recreated with it.
+                    Err(GpuError::DeviceLost) => self.handle(GpuError::DeviceLost, device, assets, width, height),
+                    Err(error) => self.fail(format!("the swapchain could not be recreated: {:?}", error)),
+                }
+            },
+            GpuError::DeviceLost => {
+                self.device_losses += 1;
+                warn!("The GPU device was lost ({} of {}), recreating it.", self.device_losses, self.max_device_losses);
+                self.events.push_back(RenderEvent::DeviceLost);
+                device.destroy_swapchain();
+                device.destroy_device();
+                let restored = device.create_device()
+                    .and_then(|_| self.recreate_swapchain(device, width, height))
+                    .and_then(|_| assets.reupload(device));
+                match restored {
+                    Ok(reuploaded_assets) => {
+                        debug!("The GPU device was restored, {} assets uploaded again.", reuploaded_assets);
+                        self.status = SystemStatus::Ok;
+                        self.events.push_back(RenderEvent::DeviceRestored {
+                            reuploaded_assets,
+                        });
+                        RecoveryOutcome::Recovered
+                    },
+                    Err(error) if self.device_losses >= self.max_device_losses => {
+                        let reason = format!("the GPU device was lost {} times, last error: {:?}", self.device_losses, error);
+                        self.fail(reason.clone());
+                        self.status = SystemStatus::Failed(reason);
+                        RecoveryOutcome::GaveUp
+                    },
+                    Err(error) => self.fail(format!("the GPU device could not be recreated: {:?}", error)),
+                }
+            },
+            GpuError::Other(reason) => self.fail(reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod device_recovery_test {
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeDevice {
+        log: Vec<String>,
+        failing_creations: u32,
+    }
+
+    impl GraphicsDevice for FakeDevice {
+        fn create_device(&mut self) -> Result<(), GpuError> {
+            if self.failing_creations > 0 {
+                self.failing_creations -= 1;
+                return Err(GpuError::DeviceLost);
+            }
+            self.log.push(String::from("create device"));
+            Ok(())
+        }
+
+        fn destroy_device(&mut self) {
+            self.log.push(String::from("destroy device"));
+        }
+
+        fn create_swapchain(&mut self, width: u32, height: u32) -> Result<(), GpuError> {
+            self.log.push(format!("create swapchain {}x{}", width, height));
+            Ok(())
+        }
+
+        fn destroy_swapchain(&mut self) {
+            self.log.push(String::from("destroy swapchain"));
+        }
+    }
+
+    struct Textures(usize);
+
+    impl ResidentAssets for Textures {
+        fn reupload(&mut self, _device: &mut GraphicsDevice) -> Result<usize, GpuError> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn device_recovery_recreates_and_reuploads() {
+        assert_eq!(GpuError::from_vk_result(VK_ERROR_DEVICE_LOST), Some(GpuError::DeviceLost));
+        assert_eq!(GpuError::from_vk_result(0), None);
+
+        let mut device = FakeDevice::default();
+        let mut recovery = DeviceRecovery::new(2);
+        assert_eq!(recovery.handle(GpuError::OutOfDate, &mut device, &mut Textures(3), 1280, 720), RecoveryOutcome::Recovered);
+        assert_eq!(recovery.poll_events(), Some(RenderEvent::SwapchainRecreated { width: 1280, height: 720 }));
+
+        assert_eq!(recovery.handle(GpuError::DeviceLost, &mut device, &mut Textures(3), 1280, 720), RecoveryOutcome::Recovered);
+        assert_eq!(recovery.poll_events(), Some(RenderEvent::DeviceLost));
+        assert_eq!(recovery.poll_events(), Some(RenderEvent::SwapchainRecreated { width: 1280, height: 720 }));
+        assert_eq!(recovery.poll_events(), Some(RenderEvent::DeviceRestored { reuploaded_assets: 3 }));
+        assert_eq!(&device.log[2..], &["destroy swapchain", "destroy device", "create device", "destroy swapchain", "create swapchain 1280x720"]);
+
+        //The second loss cannot be recovered: the renderer gives up.
+        device.failing_creations = 1;
+        assert_eq!(recovery.handle(GpuError::DeviceLost, &mut device, &mut Textures(3), 1280, 720), RecoveryOutcome::GaveUp);
+        match recovery.status() {
+            SystemStatus::Failed(_) => {},
+            status => panic!("Unexpected status {:?}.", status),
+        }
+        assert_eq!(recovery.handle(GpuError::OutOfDate, &mut device, &mut Textures(3), 1280, 720), RecoveryOutcome::GaveUp);
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ DYNAMIC RESOLUTION.
+
+ The controller follows the GPU frame time (measured with timestamp queries) and scales the resolution
+ of the scene to hold the target frame rate, between the bounds of the video settings:
+ - the frame time is smoothed, a single slow frame does not change the resolution, a very slow one does,
+ - the resolution is changed to use 90% of the frame time: the GPU cost follows the pixel count, the
+   square of the scale,
+ - after a change, the controller waits a few frames for the new frame times.
+
+ The scene is rendered in the top left corner of the render targets, at render_size, and the last pass of
+ the post stack (upscale_pass) scales it to the output resolution.
+*/
+
+use maskerad_core::engine_configuration::video::{Upscaler, VideoSettings};
+
+const SMOOTHING: f32 = 0.1;
+const HEADROOM: f32 = 0.9;
+const COOLDOWN_FRAMES: u32 = 8;
+//The render sizes are multiples of this, for the tiles of the GPU and the mips of the render targets.
+const SIZE_ALIGNMENT: u32 = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UpscalePass {
+    pub source: (u32, u32),
+    pub output: (u32, u32),
+    pub upscaler: Upscaler,
+}
+
+#[derive(Debug, Clone)]
+pub struct DynamicResolution {
+    enabled: bool,
+    min_scale: f32,
+    max_scale: f32,
+    upscaler: Upscaler,
+    //In milliseconds.
+    target_frame_time: f32,
+    scale: f32,
+    average_frame_time: Option<f32>,
+    frames_since_change: u32,
+}
+
+impl DynamicResolution {
+    pub fn new(settings: &VideoSettings, target_frame_rate: u32) -> Self {
+        let (min_scale, max_scale) = settings.resolution_bounds();
+        DynamicResolution {
+            enabled: settings.dynamic_resolution,
+            min_scale,
+            max_scale,
+            upscaler: settings.upscaler,
+            target_frame_time: 1000.0 / target_frame_rate.max(1) as f32,
+            scale: max_scale,
+            average_frame_time: None,
+            frames_since_change: 0,
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    //The GPU time of the last frame, in milliseconds. Returns the scale of the next frame.
+    pub fn update(&mut self, gpu_frame_time: f32) -> f32 {
+        let average = match self.average_frame_time {
+            Some(average) => average + (gpu_frame_time - average) * SMOOTHING,
+            None => gpu_frame_time,
+        };
+        self.average_frame_time = Some(average);
+        if !self.enabled {
+            return self.scale;
+        }
+
+        self.frames_since_change += 1;
+        let spike = gpu_frame_time > self.target_frame_time * 1.5;
+        if self.frames_since_change < COOLDOWN_FRAMES && !spike {
+            return self.scale;
+        }
+        let measured = if spike { gpu_frame_time.max(average) } else { average };
+        if measured <= self.target_frame_time * 0.95 && measured >= self.target_frame_time * 0.75 {
+            return self.scale;
+        }
+
+        let scale = (self.scale * (self.target_frame_time * HEADROOM / measured.max(1e-3)).sqrt())
+            .max(self.min_scale)
+            .min(self.max_scale);
+        //The small changes are skipped, the bounds are always reached.
+        let at_bound = scale == self.min_scale || scale == self.max_scale;
+        if scale == self.scale || ((scale - self.scale).abs() < 0.02 && !at_bound) {
+            return self.scale;
+        }
+        trace!("Dynamic resolution: {} ms on the GPU, scale {} -> {}.", measured, self.scale, scale);
+        //The frame times to come are at the new resolution.
+        self.average_frame_time = Some(measured * (scale / self.scale).powi(2));
+        self.scale = scale;
+        self.frames_since_change = 0;
+        self.scale
+    }
+
+    pub fn render_size(&self, output: (u32, u32)) -> (u32, u32) {
+        let scaled = |size: u32| {
+            let aligned = ((size as f32 * self.scale) as u32 + SIZE_ALIGNMENT / 2) / SIZE_ALIGNMENT * SIZE_ALIGNMENT;
+            aligned.max(SIZE_ALIGNMENT).min(size)
+        };
+        (scaled(output.0), scaled(output.1))
+    }
+
+    //None when the scene is rendered at the output resolution.
+    pub fn upscale_pass(&self, output: (u32, u32)) -> Option<UpscalePass> {
+        let source = self.render_size(output);
+        if source == output {
+            return None;
+        }
+        Some(UpscalePass {
+            source,
+            output,
+            upscaler: self.upscaler,
+        })
+    }
+}
+
+#[cfg(test)]
+mod dynamic_resolution_test {
+    use super::*;
+
+    //A GPU cost proportional to the pixel count.
+    fn run(controller: &mut DynamicResolution, full_resolution_cost: f32, frames: usize) {
+        for _ in 0..frames {
+            let scale = controller.scale();
+            controller.update(full_resolution_cost * scale * scale);
+        }
+    }
+
+    #[test]
+    fn dynamic_resolution_holds_the_frame_rate() {
+        let settings = VideoSettings {
+            dynamic_resolution: true,
+            min_resolution_scale: 0.5,
+            ..VideoSettings::default()
+        };
+        let mut controller = DynamicResolution::new(&settings, 60);
+        assert_eq!(controller.upscale_pass((1920, 1080)), None);
+
+        run(&mut controller, 25.0, 200);
+        let frame_time = 25.0 * controller.scale() * controller.scale();
+        assert!(frame_time < 1000.0 / 60.0 && frame_time > 1000.0 / 60.0 * 0.75, "{}", frame_time);
+        let pass = controller.upscale_pass((1920, 1080)).unwrap();
+        assert!(pass.source.0 < 1920 && pass.source.0 % 8 == 0);
+        assert_eq!(pass.upscaler, Upscaler::Sharpened);
+
+        //Too slow even at the min scale.
+        run(&mut controller, 100.0, 200);
+        assert_eq!(controller.scale(), 0.5);
+        //Back to light scenes.
+        run(&mut controller, 8.0, 200);
+        assert_eq!(controller.scale(), 1.0);
+
+        let mut disabled = DynamicResolution::new(&VideoSettings::default(), 60);
+        run(&mut disabled, 100.0, 50);
+        assert_eq!(disabled.scale(), 1.0);
+        assert!(!disabled.is_enabled());
+    }
+}
@@ -0,0 +1,456 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ GEOMETRY POOL.
+
+ The meshes do not have their own GPU buffers: their vertices and indices are suballocated from a few
+ large buffers (blocks), one allocation per mesh in a vertex block and in an index block. Fewer buffers,
+ fewer allocations, fewer bindings: the meshes of a block can be drawn with a single multi-draw indirect,
+ the draw arguments are the offsets in the blocks.
+
+ Loading and unloading meshes fragments the blocks. The defragmentation moves the allocations to the
+ start of their block, a few per frame (GPU copies), and releases the empty blocks. The handles of the
+ meshes stay valid, only their draw arguments change.
+*/
+
+use renderer_error::{RendererError, RendererResult};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct BufferId(pub u64);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BufferKind {
+    Vertex,
+    Index,
+}
+
+//Implemented by the backend.
+pub trait GeometryBackend {
+    fn create_buffer(&mut self, kind: BufferKind, size: u64) -> BufferId;
+    fn destroy_buffer(&mut self, buffer: BufferId);
+    fn upload(&mut self, buffer: BufferId, offset: u64, data: &[u8]);
+    //A GPU copy inside a buffer, the ranges do not overlap.
+    fn copy(&mut self, buffer: BufferId, source: u64, destination: u64, size: u64);
+}
+
+//The free ranges of a block, sorted by offset. First fit: the allocations go to the start of the block.
+#[derive(Debug, Clone)]
+pub struct RangeAllocator {
+    size: u64,
+    free: Vec<(u64, u64)>,
+}
+
+impl RangeAllocator {
+    pub fn new(size: u64) -> Self {
+        RangeAllocator {
+            size,
+            free: vec![(0, size)],
+        }
+    }
+
+    pub fn free_size(&self) -> u64 {
+        self.free.iter().map(|&(_, size)| size).sum()
+    }
+
+    pub fn largest_free_range(&self) -> u64 {
+        self.free.iter().map(|&(_, size)| size).max().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free == [(0, self.size)]
+    }
+
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let alignment = alignment.max(1);
+        for index in 0..self.free.len() {
+            let (start, len) = self.free[index];
+            let aligned = (start + alignment - 1) / alignment * alignment;
+            if aligned + size > start + len {
+                continue;
+            }
+            self.free.remove(index);
+            if aligned + size < start + len {
+                self.free.insert(index, (aligned + size, start + len - aligned - size));
+            }
+            if aligned > start {
+                self.free.insert(index, (start, aligned - start));
+            }
+            return Some(aligned);
+        }
+        None
+    }
+
+    pub fn free(&mut self, offset: u64, size: u64) {
+        let index = self.free.iter().position(|&(start, _)| start > offset).unwrap_or(self.free.len());
+        self.free.insert(index, (offset, size));
+        //Merge with the next range, then with the previous one.
+        if index + 1 < self.free.len() && offset + size == self.free[index + 1].0 {
+            self.free[index].1 += self.free[index + 1].1;
+            self.free.remove(index + 1);
+        }
+        if index > 0 && self.free[index - 1].0 + self.free[index - 1].1 == offset {
+            self.free[index - 1].1 += self.free[index].1;
+            self.free.remove(index);
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Allocation {
+    block: usize,
+    offset: u64,
+    size: u64,
+    alignment: u64,
+}
+
+struct Block {
+    buffer: BufferId,
+    allocator: RangeAllocator,
+}
+
+//The blocks of one kind of buffer.
+struct BlockPool {
+    kind: BufferKind,
+    block_size: u64,
+    blocks: Vec<Option<Block>>,
+}
+
+impl BlockPool {
+    fn allocate<B: GeometryBackend>(&mut self, backend: &mut B, size: u64, alignment: u64) -> RendererResult<Allocation> {
+        if size > self.block_size {
+            return Err(RendererError::GeometryPoolError(format!(
+                "{} bytes of {:?} data, more than the {} bytes of a block.", size, self.kind, self.block_size
+            )));
+        }
+        for (index, block) in self.blocks.iter_mut().enumerate() {
+            if let Some(ref mut block) = *block {
+                if let Some(offset) = block.allocator.allocate(size, alignment) {
+                    return Ok(Allocation { block: index, offset, size, alignment });
+                }
+            }
+        }
+        let buffer = backend.create_buffer(self.kind, self.block_size);
+        debug!("New {:?} block of {} bytes for the geometry pool.", self.kind, self.block_size);
+        let mut allocator = RangeAllocator::new(self.block_size);
+        let offset = allocator.allocate(size, alignment).expect("A new block holds any allocation smaller than a block.");
+        let block = Block { buffer, allocator };
+        let index = match self.blocks.iter().position(|block| block.is_none()) {
+            Some(index) => {
+                self.blocks[index] = Some(block);
+                index
+            },
+            None => {
+                self.blocks.push(Some(block));
+                self.blocks.len() - 1
+            },
+        };
+        Ok(Allocation { block: index, offset, size, alignment })
+    }
+
+    fn block(&self, index: usize) -> &Block {
+        self.blocks[index].as_ref().expect("The allocations are in live blocks.")
+    }
+
+    fn block_mut(&mut self, index: usize) -> &mut Block {
+        self.blocks[index].as_mut().expect("The allocations are in live blocks.")
+    }
+
+    fn free(&mut self, allocation: &Allocation) {
+        self.block_mut(allocation.block).allocator.free(allocation.offset, allocation.size);
+    }
+
+    //A lower offset in the same block, the data is copied. None if the allocation is at its place.
+    fn compact<B: GeometryBackend>(&mut self, backend: &mut B, allocation: &Allocation) -> Option<u64> {
+        let block = self.block_mut(allocation.block);
+        let offset = block.allocator.allocate(allocation.size, allocation.alignment)?;
+        if offset + allocation.size > allocation.offset {
+            //Not lower, or overlapping the current place.
+            block.allocator.free(offset, allocation.size);
+            return None;
+        }
+        backend.copy(block.buffer, allocation.offset, offset, allocation.size);
+        block.allocator.free(allocation.offset, allocation.size);
+        Some(offset)
+    }
+
+    //The empty blocks are released, the first one is kept.
+    fn release_empty<B: GeometryBackend>(&mut self, backend: &mut B) {
+        for index in 1..self.blocks.len() {
+            let empty = self.blocks[index].as_ref().map(|block| block.allocator.is_empty()).unwrap_or(false);
+            if empty {
+                let block = self.blocks[index].take().expect("The block is live.");
+                debug!("Releasing an empty {:?} block of the geometry pool.", self.kind);
+                backend.destroy_buffer(block.buffer);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct GeometryHandle(u32);
+
+//The fields of an indexed indirect draw, and the buffers to bind.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DrawArguments {
+    pub vertex_buffer: BufferId,
+    pub index_buffer: BufferId,
+    pub index_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GeometryPoolStats {
+    pub vertex_blocks: usize,
+    pub index_blocks: usize,
+    pub used_bytes: u64,
+    pub free_bytes: u64,
+    //1 - the largest free range / the free bytes: 0 when the free space is in one piece.
+    pub fragmentation: f32,
+}
+
+struct MeshEntry {
+    vertices: Allocation,
+    indices: Allocation,
+    stride: u32,
+    index_count: u32,
+}
+
+pub struct GeometryPool<B: GeometryBackend> {
+    backend: B,
+    vertices: BlockPool,
+    indices: BlockPool,
+    meshes: Vec<Option<MeshEntry>>,
+}
+
+impl<B: GeometryBackend> GeometryPool<B> {
+    pub fn new(backend: B, vertex_block_size: u64, index_block_size: u64) -> Self {
+        GeometryPool {
+            backend,
+            vertices: BlockPool { kind: BufferKind::Vertex, block_size: vertex_block_size, blocks: Vec::new() },
+            indices: BlockPool { kind: BufferKind::Index, block_size: index_block_size, blocks: Vec::new() },
+            meshes: Vec::new(),
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn mesh_count(&self) -> usize {
+        self.meshes.iter().filter(|mesh| mesh.is_some()).count()
+    }
+
+    //The vertices are aligned on their stride: the draws address them with a vertex offset.
+    pub fn add_mesh(&mut self, vertices: &[u8], stride: u32, indices: &[u32]) -> RendererResult<GeometryHandle> {
+        if stride == 0 || vertices.len() % stride as usize != 0 {
+            return Err(RendererError::GeometryPoolError(format!("{} bytes of vertices are not vertices of {} bytes.", vertices.len(), stride)));
+        }
+        let index_bytes: Vec<u8> = indices.iter().flat_map(|index| {
+            vec![*index as u8, (*index >> 8) as u8, (*index >> 16) as u8, (*index >> 24) as u8]
+        }).collect();
+        let vertex_allocation = self.vertices.allocate(&mut self.backend, vertices.len() as u64, stride as u64)?;
+        let index_allocation = match self.indices.allocate(&mut self.backend, index_bytes.len() as u64, 4) {
+            Ok(allocation) => allocation,
+            Err(error) => {
+                self.vertices.free(&vertex_allocation);
+                return Err(error);
+            },
+        };
+        let vertex_buffer = self.vertices.block(vertex_allocation.block).buffer;
+        let index_buffer = self.indices.block(index_allocation.block).buffer;
+        self.backend.upload(vertex_buffer, vertex_allocation.offset, vertices);
+        self.backend.upload(index_buffer, index_allocation.offset, index_bytes.as_slice());
+
+        let entry = MeshEntry {
+            vertices: vertex_allocation,
+            indices: index_allocation,
+            stride,
+            index_count: indices.len() as u32,
+        };
+        let slot = match self.meshes.iter().position(|mesh| mesh.is_none()) {
+            Some(slot) => {
+                self.meshes[slot] = Some(entry);
+                slot
+            },
+            None => {
+                self.meshes.push(Some(entry));
+                self.meshes.len() - 1
+            },
+        };
+        Ok(GeometryHandle(slot as u32))
+    }
+
+    //False if the handle was removed already.
+    pub fn remove_mesh(&mut self, handle: GeometryHandle) -> bool {
+        match self.meshes.get_mut(handle.0 as usize).and_then(|mesh| mesh.take()) {
+            Some(entry) => {
+                self.vertices.free(&entry.vertices);
+                self.indices.free(&entry.indices);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn draw_arguments(&self, handle: GeometryHandle) -> Option<DrawArguments> {
+        let entry = self.meshes.get(handle.0 as usize).and_then(|mesh| mesh.as_ref())?;
+        Some(DrawArguments {
+            vertex_buffer: self.vertices.block(entry.vertices.block).buffer,
+            index_buffer: self.indices.block(entry.indices.block).buffer,
+            index_count: entry.index_count,
+            first_index: (entry.indices.offset / 4) as u32,
+            vertex_offset: (entry.vertices.offset / entry.stride as u64) as i32,
+        })
+    }
+
+    //Moves allocations to the start of their block, until max_bytes are copied. Returns the bytes copied.
+    pub fn defragment(&mut self, max_bytes: u64) -> u64 {
+        let mut moved = 0;
+        let mut order: Vec<(bool, usize, u64)> = Vec::new();
+        for (slot, entry) in self.meshes.iter().enumerate() {
+            if let Some(ref entry) = *entry {
+                order.push((true, slot, entry.vertices.offset));
+                order.push((false, slot, entry.indices.offset));
+            }
+        }
+        //The lowest allocations first: they free the room for the next ones.
+        order.sort_by_key(|&(_, _, offset)| offset);
+        for (vertices, slot, _) in order {
+            let entry = self.meshes[slot].as_mut().expect("The mesh is live.");
+            let (pool, allocation) = if vertices {
+                (&mut self.vertices, &mut entry.vertices)
+            } else {
+                (&mut self.indices, &mut entry.indices)
+            };
+            if moved + allocation.size > max_bytes {
+                continue;
+            }
+            if let Some(offset) = pool.compact(&mut self.backend, allocation) {
+                trace!("Geometry pool: {} bytes moved from {} to {}.", allocation.size, allocation.offset, offset);
+                allocation.offset = offset;
+                moved += allocation.size;
+            }
+        }
+        self.vertices.release_empty(&mut self.backend);
+        self.indices.release_empty(&mut self.backend);
+        moved
+    }
+
+    pub fn stats(&self) -> GeometryPoolStats {
+        let blocks = || self.vertices.blocks.iter().chain(self.indices.blocks.iter()).filter_map(|block| block.as_ref());
+        let capacity: u64 = self.vertices.blocks.iter().filter(|block| block.is_some()).count() as u64 * self.vertices.block_size +
+            self.indices.blocks.iter().filter(|block| block.is_some()).count() as u64 * self.indices.block_size;
+        let free_bytes: u64 = blocks().map(|block| block.allocator.free_size()).sum();
+        let largest = blocks().map(|block| block.allocator.largest_free_range()).max().unwrap_or(0);
+        GeometryPoolStats {
+            vertex_blocks: self.vertices.blocks.iter().filter(|block| block.is_some()).count(),
+            index_blocks: self.indices.blocks.iter().filter(|block| block.is_some()).count(),
+            used_bytes: capacity - free_bytes,
+            free_bytes,
+            fragmentation: if free_bytes == 0 { 0.0 } else { 1.0 - largest as f32 / free_bytes as f32 },
+        }
+    }
+}
+
+impl<B: GeometryBackend> Drop for GeometryPool<B> {
+    fn drop(&mut self) {
+        for block in self.vertices.blocks.drain(..).chain(self.indices.blocks.drain(..)).flatten() {
+            self.backend.destroy_buffer(block.buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod geometry_pool_test {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MemoryBackend {
+        buffers: HashMap<u64, Vec<u8>>,
+        next: u64,
+    }
+
+    impl GeometryBackend for MemoryBackend {
+        fn create_buffer(&mut self, _kind: BufferKind, size: u64) -> BufferId {
+            self.next += 1;
+            self.buffers.insert(self.next, vec![0; size as usize]);
+            BufferId(self.next)
+        }
+
+        fn destroy_buffer(&mut self, buffer: BufferId) {
+            self.buffers.remove(&buffer.0);
+        }
+
+        fn upload(&mut self, buffer: BufferId, offset: u64, data: &[u8]) {
+            self.buffers.get_mut(&buffer.0).unwrap()[offset as usize..offset as usize + data.len()].copy_from_slice(data);
+        }
+
+        fn copy(&mut self, buffer: BufferId, source: u64, destination: u64, size: u64) {
+            let data = self.buffers.get_mut(&buffer.0).unwrap();
+            let copied = data[source as usize..(source + size) as usize].to_vec();
+            data[destination as usize..(destination + size) as usize].copy_from_slice(copied.as_slice());
+        }
+    }
+
+    fn vertices(pool: &GeometryPool<MemoryBackend>, handle: GeometryHandle, count: usize) -> Vec<u8> {
+        let arguments = pool.draw_arguments(handle).unwrap();
+        let start = arguments.vertex_offset as usize * 12;
+        pool.backend().buffers[&arguments.vertex_buffer.0][start..start + count * 12].to_vec()
+    }
+
+    #[test]
+    fn range_allocator_alignment_and_merge() {
+        let mut allocator = RangeAllocator::new(100);
+        assert_eq!(allocator.allocate(10, 1), Some(0));
+        assert_eq!(allocator.allocate(12, 12), Some(12));
+        assert_eq!(allocator.allocate(100, 1), None);
+        allocator.free(0, 10);
+        allocator.free(12, 12);
+        assert!(allocator.is_empty());
+    }
+
+    #[test]
+    fn geometry_pool_suballocation_and_defragmentation() {
+        //Vertices of 12 bytes, blocks of 10 vertices.
+        let mut pool = GeometryPool::new(MemoryBackend::default(), 120, 64);
+        let quad = |value: u8| vec![value; 4 * 12];
+        let first = pool.add_mesh(quad(1).as_slice(), 12, &[0, 1, 2, 0, 2, 3]).unwrap();
+        let second = pool.add_mesh(quad(2).as_slice(), 12, &[0, 1, 2]).unwrap();
+        let arguments = pool.draw_arguments(second).unwrap();
+        assert_eq!(arguments.vertex_offset, 4);
+        assert_eq!(arguments.first_index, 6);
+        assert_eq!(arguments.index_count, 3);
+        assert_eq!(arguments.vertex_buffer, pool.draw_arguments(first).unwrap().vertex_buffer);
+
+        //The third mesh does not fit in the first block.
+        let third = pool.add_mesh(quad(3).as_slice(), 12, &[0, 1, 2]).unwrap();
+        assert_eq!(pool.stats().vertex_blocks, 2);
+        assert!(pool.add_mesh(vec![0; 240].as_slice(), 12, &[0]).is_err());
+        assert!(pool.add_mesh(vec![0; 13].as_slice(), 12, &[0]).is_err());
+
+        //A hole at the start of the first block: the second mesh is moved to it.
+        assert!(pool.remove_mesh(first));
+        assert!(!pool.remove_mesh(first));
+        assert!(pool.stats().fragmentation > 0.0);
+        assert_eq!(pool.defragment(0), 0);
+        assert!(pool.defragment(1024) > 0);
+        assert_eq!(pool.draw_arguments(second).unwrap().vertex_offset, 0);
+        assert_eq!(pool.draw_arguments(second).unwrap().first_index, 0);
+        assert_eq!(vertices(&pool, second, 4), quad(2));
+        assert_eq!(vertices(&pool, third, 4), quad(3));
+
+        //The empty blocks are released.
+        pool.remove_mesh(third);
+        pool.defragment(1024);
+        assert_eq!(pool.stats().vertex_blocks, 1);
+        assert_eq!(pool.mesh_count(), 1);
+        assert_eq!(pool.backend().buffers.len(), 2);
+    }
+}
@@ -0,0 +1,296 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ GOLDEN IMAGE TESTS.
+
+ A rendering test renders a deterministic scene offscreen, reads back the framebuffer, and compares it
+ with a reference image stored with the tests.
+
+ - The comparison is perceptual: the difference of two pixels is measured in the YIQ color space, where
+   the luminance weighs more than the chrominance, and small differences (driver rounding, dithering) are
+   tolerated. A given ratio of pixels may differ, for the anti-aliased edges.
+ - When a test fails, the rendered image and a diff image (the different pixels in red, over the faded
+   reference) are written to the artifacts directory.
+ - A missing reference is created from the rendered image, and the update mode overwrites the references
+   after an intended change of the renderer.
+
+ The images are stored as uncompressed 32 bits TGA, which any image viewer reads.
+*/
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use renderer_error::{RendererError, RendererResult};
+
+//Renders a scene of the tests, always the same way, and reads back its RGBA8 pixels.
+pub trait OffscreenRenderer {
+    fn render(&mut self, scene: &str, width: usize, height: usize) -> RendererResult<Vec<u8>>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldenImage {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+impl GoldenImage {
+    pub fn new(width: usize, height: usize, rgba: Vec<u8>) -> RendererResult<Self> {
+        if rgba.len() != width * height * 4 {
+            return Err(RendererError::GoldenImageError(format!("{} bytes of pixels for a {}x{} image.", rgba.len(), width, height)));
+        }
+        Ok(GoldenImage {
+            width,
+            height,
+            rgba,
+        })
+    }
+
+    //Uncompressed true-color TGA, top-left origin, BGRA pixels.
+    pub fn to_tga(&self) -> Vec<u8> {
+        let mut output = vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        output.extend_from_slice(&[self.width as u8, (self.width >> 8) as u8, self.height as u8, (self.height >> 8) as u8, 32, 0x28]);
+        for pixel in self.rgba.chunks(4) {
+            output.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+        }
+        output
+    }
+
+    pub fn from_tga(data: &[u8]) -> RendererResult<Self> {
+        if data.len() < 18 || data[2] != 2 || data[16] != 32 {
+            return Err(RendererError::GoldenImageError(String::from("The image is not an uncompressed 32 bits TGA.")));
+        }
+        let width = data[12] as usize | (data[13] as usize) << 8;
+        let height = data[14] as usize | (data[15] as usize) << 8;
+        let start = 18 + data[0] as usize;
+        let pixels = data.get(start..start + width * height * 4).ok_or_else(|| {
+            RendererError::GoldenImageError(format!("The TGA image is too short for {}x{} pixels.", width, height))
+        })?;
+        let top_left = data[17] & 0x20 != 0;
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for row in 0..height {
+            let source_row = if top_left {row} else {height - 1 - row};
+            for pixel in pixels[source_row * width * 4..(source_row + 1) * width * 4].chunks(4) {
+                rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+            }
+        }
+        GoldenImage::new(width, height, rgba)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tolerance {
+    //The perceptual difference from which two pixels differ, between 0 and 1.
+    pub threshold: f32,
+    //The ratio of the pixels allowed to differ.
+    pub max_different_ratio: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance {
+            threshold: 0.1,
+            max_different_ratio: 0.001,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub different_pixels: usize,
+    pub max_difference: f32,
+    pub diff: GoldenImage,
+}
+
+impl Comparison {
+    pub fn passes(&self, tolerance: &Tolerance) -> bool {
+        self.different_pixels as f32 <= tolerance.max_different_ratio * (self.diff.width * self.diff.height) as f32
+    }
+}
+
+//The pixels are blended over white, then the squared distance in YIQ is normalized by its maximum.
+fn perceptual_difference(a: &[u8], b: &[u8]) -> f32 {
+    let yiq = |pixel: &[u8]| {
+        let alpha = pixel[3] as f32 / 255.0;
+        let blend = |channel: u8| 255.0 + (channel as f32 - 255.0) * alpha;
+        let (r, g, b) = (blend(pixel[0]), blend(pixel[1]), blend(pixel[2]));
+        [
+            r * 0.29889531 + g * 0.58662247 + b * 0.11448223,
+            r * 0.59597799 - g * 0.27417610 - b * 0.32180189,
+            r * 0.21147017 - g * 0.52261711 + b * 0.31114694,
+        ]
+    };
+    let (a, b) = (yiq(a), yiq(b));
+    let (y, i, q) = (a[0] - b[0], a[1] - b[1], a[2] - b[2]);
+    (0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / 35215.0
+}
+
+pub fn compare(reference: &GoldenImage, actual: &GoldenImage, tolerance: &Tolerance) -> RendererResult<Comparison> {
+    if reference.width != actual.width || reference.height != actual.height {
+        return Err(RendererError::GoldenImageError(format!("The rendered image is {}x{}, the reference is {}x{}.",
+                                                           actual.width, actual.height, reference.width, reference.height)));
+    }
+    let mut different_pixels = 0;
+    let mut max_difference = 0.0f32;
+    let mut diff = Vec::with_capacity(reference.rgba.len());
+    for (expected, rendered) in reference.rgba.chunks(4).zip(actual.rgba.chunks(4)) {
+        let difference = perceptual_difference(expected, rendered);
+        max_difference = max_difference.max(difference);
+        if difference > tolerance.threshold * tolerance.threshold {
+            different_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let faded = (255.0 - (255.0 - (expected[0] as f32 * 0.3 + expected[1] as f32 * 0.59 + expected[2] as f32 * 0.11)) * 0.1) as u8;
+            diff.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+    Ok(Comparison {
+        different_pixels,
+        max_difference: max_difference.sqrt(),
+        diff: GoldenImage::new(reference.width, reference.height, diff)?,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GoldenOutcome {
+    Passed,
+    //The reference was missing, or the references are being updated.
+    ReferenceWritten,
+    //The rendered and diff images were written to the artifacts directory.
+    Failed { different_pixels: usize, artifacts: Vec<PathBuf> },
+}
+
+pub struct GoldenTests {
+    references: PathBuf,
+    artifacts: PathBuf,
+    tolerance: Tolerance,
+    update: bool,
+}
+
+impl GoldenTests {
+    pub fn new<P: Into<PathBuf>>(references: P, artifacts: P) -> Self {
+        GoldenTests {
+            references: references.into(),
+            artifacts: artifacts.into(),
+            tolerance: Tolerance::default(),
+            update: false,
+        }
+    }
+
+    pub fn with_tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    //Overwrite the references with the rendered images.
+    pub fn updating_references(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    fn write(path: &PathBuf, image: &GoldenImage) -> RendererResult<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::File::create(path)?.write_all(image.to_tga().as_slice())?;
+        Ok(())
+    }
+
+    pub fn check(&self, renderer: &mut OffscreenRenderer, scene: &str, width: usize, height: usize) -> RendererResult<GoldenOutcome> {
+        let actual = GoldenImage::new(width, height, renderer.render(scene, width, height)?)?;
+        let reference_path = self.references.join(format!("{}.tga", scene));
+        if self.update || !reference_path.exists() {
+            debug!("Writing the reference image {}.", reference_path.display());
+            GoldenTests::write(&reference_path, &actual)?;
+            return Ok(GoldenOutcome::ReferenceWritten);
+        }
+
+        let mut data = Vec::new();
+        fs::File::open(reference_path.as_path())?.read_to_end(&mut data)?;
+        let reference = GoldenImage::from_tga(data.as_slice())?;
+        let comparison = compare(&reference, &actual, &self.tolerance)?;
+        if comparison.passes(&self.tolerance) {
+            trace!("The scene {} matches its reference ({} different pixels).", scene, comparison.different_pixels);
+            return Ok(GoldenOutcome::Passed);
+        }
+
+        let artifacts = vec![
+            self.artifacts.join(format!("{}.actual.tga", scene)),
+            self.artifacts.join(format!("{}.diff.tga", scene)),
+        ];
+        GoldenTests::write(&artifacts[0], &actual)?;
+        GoldenTests::write(&artifacts[1], &comparison.diff)?;
+        error!("The scene {} differs from its reference: {} pixels, up to {:.3}.", scene, comparison.different_pixels, comparison.max_difference);
+        Ok(GoldenOutcome::Failed {
+            different_pixels: comparison.different_pixels,
+            artifacts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod golden_image_test {
+    use super::*;
+    use std::env;
+
+    //A gradient, brightened by the bias, with an optional square painted over it.
+    struct Scenes {
+        bias: u8,
+        square: Option<[u8; 4]>,
+    }
+
+    impl OffscreenRenderer for Scenes {
+        fn render(&mut self, _scene: &str, width: usize, height: usize) -> RendererResult<Vec<u8>> {
+            let mut rgba = Vec::new();
+            for y in 0..height {
+                for x in 0..width {
+                    match self.square {
+                        Some(color) if x < 4 && y < 4 => rgba.extend_from_slice(&color),
+                        _ => rgba.extend_from_slice(&[(x * 16) as u8 + self.bias, (y * 16) as u8 + self.bias, 128 + self.bias, 255]),
+                    }
+                }
+            }
+            Ok(rgba)
+        }
+    }
+
+    #[test]
+    fn golden_image_tga_round_trip() {
+        let image = GoldenImage::new(2, 1, vec![1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(GoldenImage::from_tga(image.to_tga().as_slice()).unwrap(), image);
+        assert!(GoldenImage::new(2, 2, vec![0; 4]).is_err());
+    }
+
+    #[test]
+    fn golden_image_perceptual_comparison() {
+        let directory = env::temp_dir().join("maskerad_golden_image_test");
+        let _ = fs::remove_dir_all(directory.as_path());
+        let tests = GoldenTests::new(directory.join("references"), directory.join("artifacts"));
+
+        let mut renderer = Scenes { bias: 0, square: None };
+        assert_eq!(tests.check(&mut renderer, "gradient", 16, 16).unwrap(), GoldenOutcome::ReferenceWritten);
+        assert_eq!(tests.check(&mut renderer, "gradient", 16, 16).unwrap(), GoldenOutcome::Passed);
+
+        //A slight rounding difference is tolerated, a red square is not.
+        let mut rounded = Scenes { bias: 2, square: None };
+        assert_eq!(tests.check(&mut rounded, "gradient", 16, 16).unwrap(), GoldenOutcome::Passed);
+        let mut broken = Scenes { bias: 0, square: Some([255, 0, 0, 255]) };
+        match tests.check(&mut broken, "gradient", 16, 16).unwrap() {
+            GoldenOutcome::Failed { different_pixels, artifacts } => {
+                assert_eq!(different_pixels, 16);
+                assert!(artifacts.iter().all(|artifact| artifact.exists()));
+            },
+            outcome => panic!("Unexpected outcome {:?}.", outcome),
+        }
+        assert!(tests.check(&mut renderer, "gradient", 8, 8).is_err());
+
+        let tests = tests.updating_references(true);
+        assert_eq!(tests.check(&mut broken, "gradient", 16, 16).unwrap(), GoldenOutcome::ReferenceWritten);
+        fs::remove_dir_all(directory.as_path()).unwrap();
+    }
+}
@@ -0,0 +1,428 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ GPU-DRIVEN RENDERING.
+
+ An optional path for the scenes with tens of thousands of objects, where recording a draw per object
+ on the CPU is the bottleneck:
+ - the data of every object (transform, bounds, draw arguments) is in a storage buffer, only the changed
+   objects are uploaded,
+ - a compute pass culls the objects against the frustum, one thread per object, and appends an indirect
+   draw command per visible object (CULLING_SHADER),
+ - the commands are drawn with a multi-draw indirect count per batch: the objects whose meshes are in
+   the same blocks of the geometry pool.
+
+ The first instance of a command is the index of the object: the vertex shader reads its transform.
+ cull_reference is the CPU version of the compute pass, with the same output.
+*/
+
+use std::collections::HashMap;
+use maskerad_core::math::batch::{cull_aabbs, Aabb, Matrix4, Plane};
+use geometry_pool::{BufferId, GeometryBackend, GeometryHandle, GeometryPool};
+use renderer_error::{RendererError, RendererResult};
+
+pub const CULLING_WORKGROUP_SIZE: u32 = 64;
+
+//The compute pass, in GLSL. The layouts of the structs match ObjectData, DrawBatch and DrawIndexedIndirectCommand.
+pub const CULLING_SHADER: &'static str = r#"#version 450
+layout(local_size_x = 64) in;
+
+struct ObjectData {
+    mat4 transform;
+    vec3 center;
+    uint index_count;
+    vec3 extents;
+    uint first_index;
+    int vertex_offset;
+    uint batch;
+    uint material;
+    uint padding;
+};
+
+struct DrawBatch {
+    uint first_command;
+    uint max_commands;
+};
+
+struct DrawCommand {
+    uint index_count;
+    uint instance_count;
+    uint first_index;
+    int vertex_offset;
+    uint first_instance;
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer Objects { ObjectData objects[]; };
+layout(std430, set = 0, binding = 1) readonly buffer Batches { DrawBatch batches[]; };
+layout(std430, set = 0, binding = 2) writeonly buffer Commands { DrawCommand commands[]; };
+layout(std430, set = 0, binding = 3) buffer Counts { uint counts[]; };
+layout(push_constant) uniform Frustum { vec4 planes[6]; uint object_count; };
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    if (index >= object_count) {
+        return;
+    }
+    ObjectData object = objects[index];
+    for (int i = 0; i < 6; i++) {
+        float distance = dot(planes[i].xyz, object.center) + planes[i].w;
+        float radius = dot(abs(planes[i].xyz), object.extents);
+        if (distance + radius < 0.0) {
+            return;
+        }
+    }
+    uint slot = atomicAdd(counts[object.batch], 1);
+    commands[batches[object.batch].first_command + slot] = DrawCommand(object.index_count, 1, object.first_index, object.vertex_offset, index);
+}
+"#;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ObjectData {
+    pub transform: Matrix4,
+    //The bounds, in world space.
+    pub center: [f32; 3],
+    pub index_count: u32,
+    pub extents: [f32; 3],
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    pub batch: u32,
+    pub material: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DrawIndexedIndirectCommand {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    pub first_instance: u32,
+}
+
+//The commands of a batch are at first_command, their count is written by the culling pass.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DrawBatch {
+    pub vertex_buffer: BufferId,
+    pub index_buffer: BufferId,
+    pub first_command: u32,
+    pub max_commands: u32,
+}
+
+//The features the path needs.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct DeviceFeatures {
+    pub multi_draw_indirect: bool,
+    pub draw_indirect_count: bool,
+    pub compute_shaders: bool,
+}
+
+impl DeviceFeatures {
+    pub fn supports_gpu_driven(&self) -> bool {
+        self.multi_draw_indirect && self.draw_indirect_count && self.compute_shaders
+    }
+}
+
+//Implemented by the backend.
+pub trait GpuDrivenBackend {
+    fn upload_objects(&mut self, first: usize, objects: &[ObjectData]);
+    fn upload_batches(&mut self, batches: &[DrawBatch]);
+    //Resets the counts, then runs CULLING_SHADER over the objects.
+    fn dispatch_culling(&mut self, planes: &[Plane; 6], object_count: u32);
+    fn draw_indexed_indirect_count(&mut self, batch: &DrawBatch, batch_index: usize);
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ObjectHandle(u32);
+
+//The objects of the scene, packed: removing an object moves the last one to its place.
+#[derive(Default)]
+pub struct GpuScene {
+    objects: Vec<ObjectData>,
+    meshes: Vec<GeometryHandle>,
+    //The index of each handle, and the handle of each index.
+    indices: Vec<Option<usize>>,
+    handles: Vec<u32>,
+    buffers: Vec<(BufferId, BufferId)>,
+    dirty: Option<(usize, usize)>,
+    batches_dirty: bool,
+}
+
+impl GpuScene {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn objects(&self) -> &[ObjectData] {
+        self.objects.as_slice()
+    }
+
+    fn mark_dirty(&mut self, index: usize) {
+        self.dirty = Some(match self.dirty {
+            Some((first, last)) => (first.min(index), last.max(index)),
+            None => (index, index),
+        });
+    }
+
+    fn batch_of(&mut self, buffers: (BufferId, BufferId)) -> u32 {
+        match self.buffers.iter().position(|batch| *batch == buffers) {
+            Some(batch) => batch as u32,
+            None => {
+                self.buffers.push(buffers);
+                self.batches_dirty = true;
+                (self.buffers.len() - 1) as u32
+            },
+        }
+    }
+
+    fn object_data<B: GeometryBackend>(&mut self, pool: &GeometryPool<B>, mesh: GeometryHandle, transform: Matrix4, bounds: &Aabb, material: u32) -> RendererResult<ObjectData> {
+        let arguments = pool.draw_arguments(mesh).ok_or_else(|| {
+            RendererError::GeometryPoolError(format!("The mesh {:?} is not in the geometry pool.", mesh))
+        })?;
+        Ok(ObjectData {
+            transform,
+            center: bounds.center,
+            index_count: arguments.index_count,
+            extents: bounds.extents,
+            first_index: arguments.first_index,
+            vertex_offset: arguments.vertex_offset,
+            batch: self.batch_of((arguments.vertex_buffer, arguments.index_buffer)),
+            material,
+            padding: 0,
+        })
+    }
+
+    pub fn add_object<B: GeometryBackend>(&mut self, pool: &GeometryPool<B>, mesh: GeometryHandle, transform: Matrix4, bounds: &Aabb, material: u32) -> RendererResult<ObjectHandle> {
+        let data = self.object_data(pool, mesh, transform, bounds, material)?;
+        let handle = match self.indices.iter().position(|index| index.is_none()) {
+            Some(handle) => handle,
+            None => {
+                self.indices.push(None);
+                self.indices.len() - 1
+            },
+        };
+        let index = self.objects.len();
+        self.indices[handle] = Some(index);
+        self.handles.push(handle as u32);
+        self.objects.push(data);
+        self.meshes.push(mesh);
+        self.mark_dirty(index);
+        self.batches_dirty = true;
+        Ok(ObjectHandle(handle as u32))
+    }
+
+    pub fn set_transform(&mut self, handle: ObjectHandle, transform: Matrix4, bounds: &Aabb) -> bool {
+        match self.indices.get(handle.0 as usize).cloned().and_then(|index| index) {
+            Some(index) => {
+                self.objects[index].transform = transform;
+                self.objects[index].center = bounds.center;
+                self.objects[index].extents = bounds.extents;
+                self.mark_dirty(index);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn remove_object(&mut self, handle: ObjectHandle) -> bool {
+        let index = match self.indices.get_mut(handle.0 as usize).and_then(|index| index.take()) {
+            Some(index) => index,
+            None => return false,
+        };
+        self.objects.swap_remove(index);
+        self.meshes.swap_remove(index);
+        self.handles.swap_remove(index);
+        if index < self.objects.len() {
+            self.indices[self.handles[index] as usize] = Some(index);
+            self.mark_dirty(index);
+        }
+        self.batches_dirty = true;
+        true
+    }
+
+    //After a defragmentation of the geometry pool: the draw arguments of every object are read again.
+    pub fn refresh_geometry<B: GeometryBackend>(&mut self, pool: &GeometryPool<B>) -> RendererResult<()> {
+        for index in 0..self.objects.len() {
+            let object = self.objects[index];
+            let bounds = Aabb { center: object.center, extents: object.extents };
+            let data = self.object_data(pool, self.meshes[index], object.transform, &bounds, object.material)?;
+            if data != object {
+                self.objects[index] = data;
+                self.mark_dirty(index);
+            }
+        }
+        self.batches_dirty = true;
+        Ok(())
+    }
+
+    //The room of each batch in the command buffer: one command per object of the batch.
+    pub fn batches(&self) -> Vec<DrawBatch> {
+        let mut counts: HashMap<u32, u32> = HashMap::new();
+        for object in self.objects.iter() {
+            *counts.entry(object.batch).or_insert(0) += 1;
+        }
+        let mut first_command = 0;
+        self.buffers.iter().enumerate().map(|(batch, &(vertex_buffer, index_buffer))| {
+            let max_commands = counts.get(&(batch as u32)).cloned().unwrap_or(0);
+            let batch = DrawBatch { vertex_buffer, index_buffer, first_command, max_commands };
+            first_command += max_commands;
+            batch
+        }).collect()
+    }
+
+    //Uploads what changed, culls and draws.
+    pub fn render<G: GpuDrivenBackend>(&mut self, backend: &mut G, planes: &[Plane; 6]) {
+        if let Some((first, last)) = self.dirty.take() {
+            let last = last.min(self.objects.len().saturating_sub(1));
+            if first <= last && !self.objects.is_empty() {
+                backend.upload_objects(first, &self.objects[first..last + 1]);
+            }
+        }
+        let batches = self.batches();
+        if self.batches_dirty {
+            backend.upload_batches(batches.as_slice());
+            self.batches_dirty = false;
+        }
+        if self.objects.is_empty() {
+            return;
+        }
+        backend.dispatch_culling(planes, self.objects.len() as u32);
+        for (index, batch) in batches.iter().enumerate().filter(|&(_, batch)| batch.max_commands > 0) {
+            backend.draw_indexed_indirect_count(batch, index);
+        }
+    }
+}
+
+//The output of the culling pass, computed on the CPU: the command buffer and the count of each batch.
+//The commands of a batch are in the order of the objects (the GPU order is not defined).
+pub fn cull_reference(objects: &[ObjectData], batches: &[DrawBatch], planes: &[Plane; 6]) -> (Vec<DrawIndexedIndirectCommand>, Vec<u32>) {
+    let bounds: Vec<Aabb> = objects.iter().map(|object| Aabb { center: object.center, extents: object.extents }).collect();
+    let mut visible = vec![false; objects.len()];
+    cull_aabbs(planes, bounds.as_slice(), visible.as_mut_slice());
+
+    let empty = DrawIndexedIndirectCommand { index_count: 0, instance_count: 0, first_index: 0, vertex_offset: 0, first_instance: 0 };
+    let total: u32 = batches.iter().map(|batch| batch.max_commands).sum();
+    let mut commands = vec![empty; total as usize];
+    let mut counts = vec![0u32; batches.len()];
+    for (index, object) in objects.iter().enumerate().filter(|&(index, _)| visible[index]) {
+        let batch = object.batch as usize;
+        commands[(batches[batch].first_command + counts[batch]) as usize] = DrawIndexedIndirectCommand {
+            index_count: object.index_count,
+            instance_count: 1,
+            first_index: object.first_index,
+            vertex_offset: object.vertex_offset,
+            first_instance: index as u32,
+        };
+        counts[batch] += 1;
+    }
+    (commands, counts)
+}
+
+#[cfg(test)]
+mod gpu_driven_test {
+    use super::*;
+    use std::mem;
+    use geometry_pool::BufferKind;
+
+    struct NullGeometry(u64);
+
+    impl GeometryBackend for NullGeometry {
+        fn create_buffer(&mut self, _kind: BufferKind, _size: u64) -> BufferId {
+            self.0 += 1;
+            BufferId(self.0)
+        }
+        fn destroy_buffer(&mut self, _buffer: BufferId) {}
+        fn upload(&mut self, _buffer: BufferId, _offset: u64, _data: &[u8]) {}
+        fn copy(&mut self, _buffer: BufferId, _source: u64, _destination: u64, _size: u64) {}
+    }
+
+    #[derive(Default)]
+    struct RecordingBackend {
+        uploads: Vec<(usize, usize)>,
+        batches: Vec<DrawBatch>,
+        dispatches: Vec<u32>,
+        draws: Vec<usize>,
+    }
+
+    impl GpuDrivenBackend for RecordingBackend {
+        fn upload_objects(&mut self, first: usize, objects: &[ObjectData]) {
+            self.uploads.push((first, objects.len()));
+        }
+        fn upload_batches(&mut self, batches: &[DrawBatch]) {
+            self.batches = batches.to_vec();
+        }
+        fn dispatch_culling(&mut self, _planes: &[Plane; 6], object_count: u32) {
+            self.dispatches.push(object_count);
+        }
+        fn draw_indexed_indirect_count(&mut self, _batch: &DrawBatch, batch_index: usize) {
+            self.draws.push(batch_index);
+        }
+    }
+
+    const IDENTITY: Matrix4 = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+    fn at(x: f32) -> Aabb {
+        Aabb { center: [x, 0.0, 0.0], extents: [0.5, 0.5, 0.5] }
+    }
+
+    #[test]
+    fn gpu_driven_scene_culling_and_batches() {
+        //The std430 layouts of the shader.
+        assert_eq!(mem::size_of::<ObjectData>(), 112);
+        assert_eq!(mem::size_of::<DrawIndexedIndirectCommand>(), 20);
+        assert!(!DeviceFeatures { multi_draw_indirect: true, ..DeviceFeatures::default() }.supports_gpu_driven());
+
+        //Two meshes in the first blocks, one in new blocks.
+        let mut pool = GeometryPool::new(NullGeometry(0), 96, 1024);
+        let small = pool.add_mesh(&[0; 48], 12, &[0, 1, 2]).unwrap();
+        let other = pool.add_mesh(&[0; 48], 12, &[0, 1, 2, 0, 2, 3]).unwrap();
+        let large = pool.add_mesh(&[0; 96], 12, &[0, 1, 2]).unwrap();
+
+        let mut scene = GpuScene::new();
+        let first = scene.add_object(&pool, small, IDENTITY, &at(0.0), 0).unwrap();
+        scene.add_object(&pool, other, IDENTITY, &at(5.0), 0).unwrap();
+        scene.add_object(&pool, large, IDENTITY, &at(-5.0), 1).unwrap();
+        let hidden = scene.add_object(&pool, small, IDENTITY, &at(50.0), 0).unwrap();
+        let batches = scene.batches();
+        assert_eq!(batches.len(), 2);
+        assert_eq!((batches[0].max_commands, batches[1].first_command, batches[1].max_commands), (3, 3, 1));
+
+        //The x < 10 half space, and planes far away.
+        let far = [0.0, 0.0, 0.0, 1000.0];
+        let planes: [Plane; 6] = [[-1.0, 0.0, 0.0, 10.0], far, far, far, far, far];
+        let (commands, counts) = cull_reference(scene.objects(), batches.as_slice(), &planes);
+        assert_eq!(counts, vec![2, 1]);
+        assert_eq!(commands[0].first_instance, 0);
+        assert_eq!(commands[1].index_count, 6);
+        assert_eq!(commands[1].first_index, 3);
+        assert_eq!(commands[3].first_instance, 2);
+
+        let mut backend = RecordingBackend::default();
+        scene.render(&mut backend, &planes);
+        assert_eq!(backend.uploads, vec![(0, 4)]);
+        assert_eq!(backend.dispatches, vec![4]);
+        assert_eq!(backend.draws, vec![0, 1]);
+
+        //Only the changed objects are uploaded again.
+        assert!(scene.set_transform(first, IDENTITY, &at(1.0)));
+        assert!(scene.remove_object(hidden));
+        assert!(!scene.remove_object(hidden));
+        scene.render(&mut backend, &planes);
+        assert_eq!(backend.uploads[1], (0, 1));
+        assert_eq!(backend.batches[0].max_commands, 2);
+    }
+}
@@ -3,4 +3,20 @@
 // Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
 // http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
-// copied, modified, or distributed except according to those terms.
\ No newline at end of file
+// copied, modified, or distributed except according to those terms.
+
+extern crate maskerad_core;
+#[macro_use]
+extern crate log;
+
+pub mod renderer_error;
+pub mod golden_image;
+pub mod device_recovery;
+pub mod virtual_texture;
+pub mod dynamic_resolution;
+pub mod color_management;
+pub mod geometry_pool;
+pub mod gpu_driven;
+
+//The version of the renderer subsystem.
+pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
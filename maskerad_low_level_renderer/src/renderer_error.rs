@@ -0,0 +1,84 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::error::Error;
+use std::fmt;
+use std::io::Error as IOError;
+
+#[derive(Debug)]
+pub enum RendererError {
+    IOError(String, IOError),
+    GoldenImageError(String),
+    VirtualTextureError(String),
+    GeometryPoolError(String),
+}
+
+unsafe impl Send for RendererError {}
+unsafe impl Sync for RendererError {}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &RendererError::IOError(ref description, _) => {
+                write!(f, "I/O error: {}", description)
+            },
+            &RendererError::GoldenImageError(ref description) => {
+                write!(f, "Golden image error: {}", description)
+            },
+            &RendererError::VirtualTextureError(ref description) => {
+                write!(f, "Virtual texture error: {}", description)
+            },
+            &RendererError::GeometryPoolError(ref description) => {
+                write!(f, "Geometry pool error: {}", description)
+            },
+        }
+    }
+}
+
+impl Error for RendererError {
+    fn description(&self) -> &str {
+        match self {
+            &RendererError::IOError(_, _) => {
+                "IOError"
+            },
+            &RendererError::GoldenImageError(_) => {
+                "GoldenImageError"
+            },
+            &RendererError::VirtualTextureError(_) => {
+                "VirtualTextureError"
+            },
+            &RendererError::GeometryPoolError(_) => {
+                "GeometryPoolError"
+            },
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match self {
+            &RendererError::IOError(_, ref io_error) => {
+                Some(io_error)
+            },
+            &RendererError::GoldenImageError(_) => {
+                None
+            },
+            &RendererError::VirtualTextureError(_) => {
+                None
+            },
+            &RendererError::GeometryPoolError(_) => {
+                None
+            },
+        }
+    }
+}
+
+pub type RendererResult<T> = Result<T, RendererError>;
+
+impl From<IOError> for RendererError {
+    fn from(error: IOError) -> Self {
+        RendererError::IOError(String::from("Error while reading or writing an image."), error)
+    }
+}
@@ -0,0 +1,428 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ VIRTUAL TEXTURES.
+
+ The very large textures (terrain, environment) are split in pages of a fixed size, for every mip level.
+ Only the pages seen by the camera are in memory, in a physical cache of a fixed number of pages: the
+ memory used does not depend on the size of the texture.
+
+ - The shaders sample the texture through the page table: for every page of every mip, the slot of the
+   cache holding it. A missing page is replaced by the closest resident page of a coarser mip.
+ - The shaders write the pages they wanted in a feedback buffer, read back by the CPU (a packed page per
+   pixel, see PageId::pack).
+ - The missing pages of the feedback are loaded in the background, on the I/O pool of the job system.
+ - The loaded pages are put in the free slots of the cache, or in the slots of the least recently used
+   pages. The pages of the coarsest mip are never evicted: every texel always has a fallback.
+
+ The renderer uploads the loaded pages into the cache texture, and the page table, after update.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Sender, Receiver};
+use maskerad_core::job_system::ThreadPool;
+use renderer_error::{RendererError, RendererResult};
+
+//The packed page of the feedback buffer: mip (4 bits), x (14 bits), y (14 bits).
+pub const NO_PAGE: u32 = 0xffff_ffff;
+const MAX_MIPS: u32 = 15;
+const MAX_PAGES: u32 = 1 << 14;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct PageId {
+    pub mip: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl PageId {
+    pub fn new(mip: u8, x: u32, y: u32) -> Self {
+        PageId {
+            mip,
+            x,
+            y,
+        }
+    }
+
+    pub fn pack(&self) -> u32 {
+        (self.mip as u32) << 28 | (self.x & 0x3fff) << 14 | (self.y & 0x3fff)
+    }
+
+    pub fn unpack(packed: u32) -> Option<PageId> {
+        if packed == NO_PAGE {
+            return None;
+        }
+        Some(PageId::new((packed >> 28) as u8, (packed >> 14) & 0x3fff, packed & 0x3fff))
+    }
+
+    //The page covering this one at the next mip.
+    pub fn parent(&self) -> PageId {
+        PageId::new(self.mip + 1, self.x / 2, self.y / 2)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VirtualTextureSettings {
+    //The size of the texture at mip 0, in pages.
+    pub width_in_pages: u32,
+    pub height_in_pages: u32,
+    pub mip_count: u32,
+    //The size of a page in bytes, borders and compression included.
+    pub page_bytes: usize,
+    //The size of the physical cache, in pages.
+    pub cache_pages: usize,
+    //The number of page loads started per frame, the others wait for the next feedback.
+    pub max_requests_per_frame: usize,
+}
+
+impl VirtualTextureSettings {
+    pub fn pages_at(&self, mip: u32) -> (u32, u32) {
+        ((self.width_in_pages >> mip).max(1), (self.height_in_pages >> mip).max(1))
+    }
+
+    pub fn memory_budget(&self) -> usize {
+        self.cache_pages * self.page_bytes
+    }
+}
+
+//Reads the pages of a texture (a tiled texture file, on the disk or in an archive). Called on the I/O pool.
+pub trait PageSource: Send + Sync {
+    fn load_page(&self, page: PageId) -> Result<Vec<u8>, String>;
+}
+
+//A page of the cache texture to update.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageUpload {
+    pub slot: usize,
+    pub page: PageId,
+    pub data: Vec<u8>,
+}
+
+//The entries of the page table: the slot of every resident page.
+#[derive(Debug, Clone)]
+pub struct PageTable {
+    settings: VirtualTextureSettings,
+    mips: Vec<Vec<Option<usize>>>,
+}
+
+impl PageTable {
+    fn new(settings: &VirtualTextureSettings) -> Self {
+        let mips = (0..settings.mip_count).map(|mip| {
+            let (width, height) = settings.pages_at(mip);
+            vec![None; (width * height) as usize]
+        }).collect();
+        PageTable {
+            settings: settings.clone(),
+            mips,
+        }
+    }
+
+    fn index(&self, page: PageId) -> Option<usize> {
+        if page.mip as u32 >= self.settings.mip_count {
+            return None;
+        }
+        let (width, height) = self.settings.pages_at(page.mip as u32);
+        if page.x >= width || page.y >= height {
+            return None;
+        }
+        Some((page.y * width + page.x) as usize)
+    }
+
+    fn set(&mut self, page: PageId, slot: Option<usize>) {
+        if let Some(index) = self.index(page) {
+            self.mips[page.mip as usize][index] = slot;
+        }
+    }
+
+    pub fn slot(&self, page: PageId) -> Option<usize> {
+        self.index(page).and_then(|index| self.mips[page.mip as usize][index])
+    }
+
+    //The page sampled for this one: itself, or the closest resident page of a coarser mip.
+    pub fn resolve(&self, page: PageId) -> Option<(PageId, usize)> {
+        let mut page = page;
+        loop {
+            self.index(page)?;
+            if let Some(slot) = self.slot(page) {
+                return Some((page, slot));
+            }
+            page = page.parent();
+        }
+    }
+
+    //The table of a mip, as uploaded to the GPU: the slot of the resolved page, and its mip in the high byte.
+    pub fn to_texels(&self, mip: u32) -> Vec<u32> {
+        let (width, height) = self.settings.pages_at(mip);
+        let mut texels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                texels.push(match self.resolve(PageId::new(mip as u8, x, y)) {
+                    Some((page, slot)) => (page.mip as u32) << 24 | slot as u32,
+                    None => NO_PAGE,
+                });
+            }
+        }
+        texels
+    }
+}
+
+struct CacheSlot {
+    page: PageId,
+    last_used: u64,
+}
+
+pub struct VirtualTexture {
+    settings: VirtualTextureSettings,
+    table: PageTable,
+    slots: Vec<Option<CacheSlot>>,
+    pending: HashSet<PageId>,
+    source: Arc<PageSource>,
+    sender: Sender<(PageId, Result<Vec<u8>, String>)>,
+    receiver: Receiver<(PageId, Result<Vec<u8>, String>)>,
+    frame: u64,
+}
+
+impl VirtualTexture {
+    //The cache must hold the pages of the coarsest mip, and leave room for the others.
+    pub fn new(settings: VirtualTextureSettings, source: Arc<PageSource>) -> RendererResult<Self> {
+        if settings.mip_count == 0 || settings.mip_count > MAX_MIPS {
+            return Err(RendererError::VirtualTextureError(format!("{} mips, a virtual texture has 1 to {} mips.", settings.mip_count, MAX_MIPS)));
+        }
+        if settings.width_in_pages == 0 || settings.height_in_pages == 0 || settings.width_in_pages > MAX_PAGES || settings.height_in_pages > MAX_PAGES {
+            return Err(RendererError::VirtualTextureError(format!(
+                "{}x{} pages, a virtual texture has 1 to {} pages per side.", settings.width_in_pages, settings.height_in_pages, MAX_PAGES
+            )));
+        }
+        let (width, height) = settings.pages_at(settings.mip_count - 1);
+        if settings.cache_pages <= (width * height) as usize {
+            return Err(RendererError::VirtualTextureError(format!(
+                "A cache of {} pages cannot hold the {} pages of the coarsest mip.", settings.cache_pages, width * height
+            )));
+        }
+        let (sender, receiver) = mpsc::channel();
+        Ok(VirtualTexture {
+            table: PageTable::new(&settings),
+            slots: (0..settings.cache_pages).map(|_| None).collect(),
+            settings,
+            pending: HashSet::new(),
+            source,
+            sender,
+            receiver,
+            frame: 0,
+        })
+    }
+
+    pub fn settings(&self) -> &VirtualTextureSettings {
+        &self.settings
+    }
+
+    pub fn page_table(&self) -> &PageTable {
+        &self.table
+    }
+
+    pub fn resident_count(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    //The coarsest mip, loaded when the texture is created.
+    pub fn request_coarsest_mip(&mut self, io: &ThreadPool) {
+        let mip = self.settings.mip_count - 1;
+        let (width, height) = self.settings.pages_at(mip);
+        for y in 0..height {
+            for x in 0..width {
+                self.request(PageId::new(mip as u8, x, y), io);
+            }
+        }
+    }
+
+    //The feedback buffer of a frame. The resident pages are marked as used, the missing ones are
+    //requested: the most wanted first, then the coarsest.
+    pub fn process_feedback(&mut self, feedback: &[u32], io: &ThreadPool) {
+        self.frame += 1;
+        let mut wanted: HashMap<PageId, usize> = HashMap::new();
+        for page in feedback.iter().filter_map(|packed| PageId::unpack(*packed)) {
+            if self.table.index(page).is_some() {
+                *wanted.entry(page).or_insert(0) += 1;
+            }
+        }
+
+        let frame = self.frame;
+        let mut missing = Vec::new();
+        for (page, count) in wanted {
+            //The fallback pages are used too.
+            match self.table.resolve(page) {
+                Some((resolved, slot)) => {
+                    if let Some(ref mut cached) = self.slots[slot] {
+                        cached.last_used = frame;
+                    }
+                    if resolved != page {
+                        missing.push((page, count));
+                    }
+                },
+                None => missing.push((page, count)),
+            }
+        }
+        missing.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.mip.cmp(&a.0.mip)).then(a.0.cmp(&b.0)));
+
+        let mut requested = 0;
+        for (page, _) in missing {
+            if requested == self.settings.max_requests_per_frame {
+                break;
+            }
+            if self.request(page, io) {
+                requested += 1;
+            }
+        }
+        trace!("Virtual texture feedback: {} pages requested, {} pending.", requested, self.pending.len());
+    }
+
+    fn request(&mut self, page: PageId, io: &ThreadPool) -> bool {
+        if !self.pending.insert(page) {
+            return false;
+        }
+        let source = self.source.clone();
+        let sender = self.sender.clone();
+        io.execute(move || {
+            let _ = sender.send((page, source.load_page(page)));
+        });
+        true
+    }
+
+    //The pages loaded since the last update, put in the cache. The renderer copies them into the cache texture.
+    pub fn update(&mut self) -> Vec<PageUpload> {
+        let mut uploads = Vec::new();
+        while let Ok((page, result)) = self.receiver.try_recv() {
+            self.pending.remove(&page);
+            let data = match result {
+                Ok(data) => data,
+                Err(error) => {
+                    warn!("The virtual texture page {:?} could not be loaded: {}.", page, error);
+                    continue;
+                },
+            };
+            if self.table.slot(page).is_some() {
+                continue;
+            }
+            match self.free_slot() {
+                Some(slot) => {
+                    self.slots[slot] = Some(CacheSlot {
+                        page,
+                        last_used: self.frame,
+                    });
+                    self.table.set(page, Some(slot));
+                    uploads.push(PageUpload {
+                        slot,
+                        page,
+                        data,
+                    });
+                },
+                //Every page is used by the current frame, the page is requested again by the next feedback.
+                None => debug!("The virtual texture cache is full, the page {:?} is dropped.", page),
+            }
+        }
+        uploads
+    }
+
+    //A free slot, or the slot of the least recently used page, if it was not used by the current frame.
+    fn free_slot(&mut self) -> Option<usize> {
+        if let Some(slot) = self.slots.iter().position(|slot| slot.is_none()) {
+            return Some(slot);
+        }
+        let coarsest = (self.settings.mip_count - 1) as u8;
+        let frame = self.frame;
+        let evicted = self.slots.iter().enumerate()
+            .filter_map(|(slot, cached)| cached.as_ref().map(|cached| (slot, cached)))
+            .filter(|&(_, cached)| cached.page.mip != coarsest && cached.last_used < frame)
+            .min_by_key(|&(_, cached)| cached.last_used)
+            .map(|(slot, cached)| (slot, cached.page));
+        evicted.map(|(slot, page)| {
+            trace!("Evicting the virtual texture page {:?}.", page);
+            self.table.set(page, None);
+            self.slots[slot] = None;
+            slot
+        })
+    }
+}
+
+#[cfg(test)]
+mod virtual_texture_test {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+    use maskerad_core::engine_configuration::threading::PoolSettings;
+    use maskerad_core::job_system::PoolKind;
+
+    struct MipSource;
+
+    impl PageSource for MipSource {
+        fn load_page(&self, page: PageId) -> Result<Vec<u8>, String> {
+            if page.x == 3 && page.y == 3 && page.mip == 0 {
+                return Err(String::from("corrupted page"));
+            }
+            Ok(vec![page.mip])
+        }
+    }
+
+    fn wait(texture: &mut VirtualTexture) -> Vec<PageUpload> {
+        let mut uploads = Vec::new();
+        for _ in 0..500 {
+            uploads.extend(texture.update());
+            if texture.pending_count() == 0 {
+                return uploads;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        panic!("The pages were not loaded.");
+    }
+
+    #[test]
+    fn virtual_texture_feedback_and_eviction() {
+        let settings = VirtualTextureSettings {
+            width_in_pages: 4,
+            height_in_pages: 4,
+            mip_count: 3,
+            page_bytes: 128 * 128 * 4,
+            cache_pages: 3,
+            max_requests_per_frame: 8,
+        };
+        assert_eq!(settings.memory_budget(), 3 * 128 * 128 * 4);
+        assert!(VirtualTexture::new(VirtualTextureSettings { cache_pages: 1, ..settings.clone() }, Arc::new(MipSource)).is_err());
+        let io = ThreadPool::new(PoolKind::Io, &PoolSettings::default(), 1).unwrap();
+        let mut texture = VirtualTexture::new(settings, Arc::new(MipSource)).unwrap();
+        assert_eq!(PageId::unpack(PageId::new(2, 3, 1).pack()), Some(PageId::new(2, 3, 1)));
+
+        texture.request_coarsest_mip(&io);
+        assert_eq!(wait(&mut texture).len(), 1);
+        assert_eq!(texture.page_table().resolve(PageId::new(0, 3, 2)), Some((PageId::new(2, 0, 0), 0)));
+
+        //Two pages wanted, the corrupted one is not loaded.
+        let feedback = [PageId::new(0, 1, 1).pack(), PageId::new(0, 1, 1).pack(), PageId::new(1, 1, 0).pack(), PageId::new(0, 3, 3).pack(), NO_PAGE];
+        texture.process_feedback(&feedback, &io);
+        let uploads = wait(&mut texture);
+        assert_eq!(uploads.len(), 2);
+        assert_eq!(texture.resident_count(), 3);
+        assert_eq!(texture.page_table().resolve(PageId::new(0, 1, 1)).map(|(page, _)| page), Some(PageId::new(0, 1, 1)));
+        assert_eq!(texture.page_table().resolve(PageId::new(0, 3, 1)).map(|(page, _)| page), Some(PageId::new(1, 1, 0)));
+        assert_eq!(texture.page_table().to_texels(1)[1] >> 24, 1);
+
+        //The cache is full: the least recently used page is evicted, never the coarsest mip.
+        texture.process_feedback(&[PageId::new(1, 1, 0).pack()], &io);
+        texture.process_feedback(&[PageId::new(0, 2, 2).pack()], &io);
+        let uploads = wait(&mut texture);
+        assert_eq!(uploads.len(), 1);
+        assert_eq!(texture.page_table().slot(PageId::new(0, 1, 1)), None);
+        assert!(texture.page_table().slot(PageId::new(1, 1, 0)).is_some());
+        assert_eq!(texture.page_table().slot(PageId::new(2, 0, 0)), Some(0));
+        assert_eq!(texture.page_table().resolve(PageId::new(0, 2, 2)).map(|(_, slot)| slot), Some(uploads[0].slot));
+    }
+}
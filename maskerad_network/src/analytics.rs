@@ -0,0 +1,413 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ ANALYTICS.
+
+ The gameplay events (level completed, player death...) are collected for the studio, only if the player
+ opted in: nothing is recorded, stored or sent before, and opting out deletes what was not sent yet.
+
+ - The events are anonymized when they are recorded, by the PrivacyPolicy: the fields can be dropped,
+   replaced by a salted hash (the same value gives the same hash, without the value), or rounded (a
+   position to a 10m grid). The session id is random, never derived from the player or the machine.
+ - They are sent in batches, every batch_size events or every flush_interval seconds.
+ - A batch which cannot be sent is retried later with an exponential backoff. The batches waiting are
+   persisted in the user data root, and sent at the next start if the game is closed while offline.
+ - The engine has no HTTP client: the game gives an AnalyticsUploader, which posts the JSON body to
+   the endpoint of the studio.
+*/
+
+use std::collections::VecDeque;
+use rand_core::{OsRng, RngCore};
+use serde_json::{self, Map, Value};
+use sha2::{Digest, Sha256};
+use maskerad_core::filesystem::game_directories::RootDir;
+use maskerad_core::filesystem::vfilesystem::VFilesystem;
+use maskerad_core::filesystem::vpath::{VPath, VPathBuf};
+use network_error::{NetworkError, NetworkResult};
+
+pub const ANALYTICS_DIRECTORY: &'static str = "analytics";
+const MAX_RETRY_DELAY: u64 = 3600;
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn random_id() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+fn filesystem_error<E: ::std::fmt::Display>(error: E) -> NetworkError {
+    NetworkError::AnalyticsError(format!("Cannot persist the analytics: {}", error))
+}
+
+fn analytics_directory() -> NetworkResult<VPath<'static>> {
+    VPath::new(RootDir::UserDataRoot, ANALYTICS_DIRECTORY).map_err(filesystem_error)
+}
+
+fn batch_path(id: &str) -> NetworkResult<VPathBuf> {
+    analytics_directory()?.join(format!("{}.json", id).as_str()).map_err(filesystem_error)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrivacyPolicy {
+    //Removed from the events.
+    pub drop_fields: Vec<String>,
+    //Replaced by a salted SHA-256.
+    pub hash_fields: Vec<String>,
+    //The numbers (or the arrays of numbers) of the field are rounded to a multiple of the step.
+    pub round_fields: Vec<(String, f64)>,
+    pub salt: String,
+}
+
+impl Default for PrivacyPolicy {
+    fn default() -> Self {
+        PrivacyPolicy {
+            drop_fields: vec![String::from("player_name"), String::from("ip_address"), String::from("email")],
+            hash_fields: Vec::new(),
+            round_fields: Vec::new(),
+            salt: String::new(),
+        }
+    }
+}
+
+impl PrivacyPolicy {
+    fn round(value: &Value, step: f64) -> Value {
+        match *value {
+            Value::Number(ref number) => number.as_f64().map(|number| json!((number / step).round() * step)).unwrap_or(Value::Null),
+            Value::Array(ref values) => Value::Array(values.iter().map(|value| PrivacyPolicy::round(value, step)).collect()),
+            _ => Value::Null,
+        }
+    }
+
+    pub fn anonymize(&self, properties: &mut Map<String, Value>) {
+        for field in self.drop_fields.iter() {
+            properties.remove(field);
+        }
+        for field in self.hash_fields.iter() {
+            if let Some(value) = properties.get_mut(field) {
+                let mut hasher = Sha256::new();
+                hasher.update(self.salt.as_bytes());
+                hasher.update(value.to_string().as_bytes());
+                *value = Value::String(to_hex(hasher.finalize().as_slice()));
+            }
+        }
+        for &(ref field, step) in self.round_fields.iter() {
+            if step > 0.0 {
+                if let Some(value) = properties.get_mut(field) {
+                    *value = PrivacyPolicy::round(value, step);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalyticsConfig {
+    //Opt-in: false until the player accepts.
+    pub enabled: bool,
+    pub batch_size: usize,
+    //In seconds.
+    pub flush_interval: u64,
+    //The oldest events are dropped beyond, so an offline game doesn't fill the disk.
+    pub max_pending_events: usize,
+    pub policy: PrivacyPolicy,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        AnalyticsConfig {
+            enabled: false,
+            batch_size: 50,
+            flush_interval: 60,
+            max_pending_events: 10_000,
+            policy: PrivacyPolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalyticsEvent {
+    pub name: String,
+    //Unix time, in seconds.
+    pub time: u64,
+    pub session: String,
+    pub properties: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Batch {
+    pub id: String,
+    pub events: Vec<AnalyticsEvent>,
+    #[serde(skip)]
+    attempts: u32,
+    #[serde(skip)]
+    next_attempt: u64,
+}
+
+//Posts a batch to the endpoint of the studio.
+pub trait AnalyticsUploader {
+    fn upload(&mut self, body: &[u8]) -> Result<(), String>;
+}
+
+pub struct Analytics {
+    config: AnalyticsConfig,
+    session: String,
+    events: Vec<AnalyticsEvent>,
+    batches: VecDeque<Batch>,
+    last_flush: u64,
+    //Some batches are not persisted yet.
+    dirty: bool,
+}
+
+impl Analytics {
+    pub fn new(config: AnalyticsConfig, now: u64) -> Self {
+        Analytics {
+            config,
+            session: random_id(),
+            events: Vec::new(),
+            batches: VecDeque::new(),
+            last_flush: now,
+            dirty: false,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn session(&self) -> &str {
+        self.session.as_str()
+    }
+
+    pub fn pending_events(&self) -> usize {
+        self.events.len() + self.batches.iter().map(|batch| batch.events.len()).sum::<usize>()
+    }
+
+    //Opting out deletes the events not sent yet, on the disk too.
+    pub fn set_enabled(&mut self, enabled: bool, filesystem: &VFilesystem) -> NetworkResult<()> {
+        info!("Analytics {}.", if enabled {"enabled"} else {"disabled"});
+        self.config.enabled = enabled;
+        if !enabled {
+            self.events.clear();
+            self.batches.clear();
+            self.dirty = false;
+            if filesystem.exists(analytics_directory()?) {
+                filesystem.rmrf(analytics_directory()?).map_err(filesystem_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn record(&mut self, name: &str, mut properties: Map<String, Value>, now: u64) {
+        if !self.config.enabled {
+            return;
+        }
+        self.config.policy.anonymize(&mut properties);
+        self.events.push(AnalyticsEvent {
+            name: String::from(name),
+            time: now,
+            session: self.session.clone(),
+            properties,
+        });
+        self.enforce_limit();
+    }
+
+    fn enforce_limit(&mut self) {
+        while self.pending_events() > self.config.max_pending_events {
+            match self.batches.front_mut() {
+                Some(batch) if batch.events.len() > 1 => {
+                    batch.events.remove(0);
+                },
+                Some(_) => {
+                    self.batches.pop_front();
+                },
+                None => {
+                    self.events.remove(0);
+                },
+            }
+            self.dirty = true;
+        }
+    }
+
+    //The events recorded become a batch.
+    pub fn flush(&mut self, now: u64) {
+        self.last_flush = now;
+        if self.events.is_empty() {
+            return;
+        }
+        let events: Vec<AnalyticsEvent> = self.events.drain(..).collect();
+        debug!("Analytics batch of {} events.", events.len());
+        self.batches.push_back(Batch { id: random_id(), events, attempts: 0, next_attempt: now });
+        self.dirty = true;
+    }
+
+    //Called regularly: makes the batches, sends them, and persists the ones which couldn't be sent.
+    pub fn update(&mut self, now: u64, uploader: &mut AnalyticsUploader, filesystem: &VFilesystem) -> NetworkResult<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        if self.events.len() >= self.config.batch_size || now >= self.last_flush + self.config.flush_interval {
+            self.flush(now);
+        }
+        while let Some(batch) = self.batches.pop_front() {
+            if batch.next_attempt > now {
+                self.batches.push_front(batch);
+                break;
+            }
+            if let Err(error) = Analytics::send(&batch, uploader) {
+                let mut batch = batch;
+                batch.attempts += 1;
+                let delay = (5u64 << batch.attempts.min(10)).min(MAX_RETRY_DELAY);
+                warn!("Cannot send the analytics batch {} ({}), retrying in {} s.", batch.id, error, delay);
+                batch.next_attempt = now + delay;
+                self.batches.push_front(batch);
+                break;
+            }
+            debug!("Analytics batch {} sent.", batch.id);
+            let path = batch_path(batch.id.as_str())?;
+            if filesystem.exists(path.as_vpath()) {
+                filesystem.rm(path.as_vpath()).map_err(filesystem_error)?;
+            }
+        }
+        if self.dirty {
+            self.persist(filesystem)?;
+        }
+        Ok(())
+    }
+
+    fn send(batch: &Batch, uploader: &mut AnalyticsUploader) -> Result<(), String> {
+        let body = serde_json::to_vec(batch).map_err(|error| error.to_string())?;
+        uploader.upload(body.as_slice())
+    }
+
+    //Writes the batches not sent yet, a file by batch.
+    pub fn persist(&mut self, filesystem: &VFilesystem) -> NetworkResult<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        filesystem.mkdir(analytics_directory()?).map_err(filesystem_error)?;
+        for batch in self.batches.iter() {
+            let path = batch_path(batch.id.as_str())?;
+            filesystem.write_all(path.as_vpath(), serde_json::to_vec(batch)?.as_slice()).map_err(filesystem_error)?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    //The batches persisted by the previous runs. An unreadable batch is deleted.
+    pub fn load_persisted(&mut self, filesystem: &VFilesystem, now: u64) -> NetworkResult<usize> {
+        if !self.config.enabled || !filesystem.exists(analytics_directory()?) {
+            return Ok(0);
+        }
+        let mut count = 0;
+        for path in filesystem.read_dir(analytics_directory()?).map_err(filesystem_error)? {
+            let bytes = filesystem.read_bytes(path.as_vpath()).map_err(filesystem_error)?;
+            match serde_json::from_slice::<Batch>(bytes.as_slice()) {
+                Ok(mut batch) => {
+                    if self.batches.iter().all(|known| known.id != batch.id) {
+                        batch.next_attempt = now;
+                        self.batches.push_back(batch);
+                        count += 1;
+                    }
+                },
+                Err(error) => {
+                    warn!("The analytics batch {} is unreadable ({}), it is deleted.", path, error);
+                    filesystem.rm(path.as_vpath()).map_err(filesystem_error)?;
+                },
+            }
+        }
+        self.enforce_limit();
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod analytics_test {
+    use super::*;
+    use maskerad_core::filesystem::memory_filesystem::MemoryFilesystem;
+
+    struct Uploader {
+        online: bool,
+        bodies: Vec<Value>,
+    }
+
+    impl AnalyticsUploader for Uploader {
+        fn upload(&mut self, body: &[u8]) -> Result<(), String> {
+            if !self.online {
+                return Err(String::from("offline"));
+            }
+            self.bodies.push(serde_json::from_slice(body).unwrap());
+            Ok(())
+        }
+    }
+
+    fn properties(value: Value) -> Map<String, Value> {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn anonymize_events() {
+        let policy = PrivacyPolicy {
+            hash_fields: vec![String::from("account")],
+            round_fields: vec![(String::from("position"), 10.0)],
+            salt: String::from("studio"),
+            ..PrivacyPolicy::default()
+        };
+        let mut event = properties(json!({"player_name": "Ana", "account": 42, "position": [12.0, 3.0, -27.0], "level": "intro"}));
+        policy.anonymize(&mut event);
+        assert!(event.get("player_name").is_none());
+        assert_eq!(event["account"].as_str().unwrap().len(), 64);
+        assert_eq!(event["position"], json!([10.0, 0.0, -30.0]));
+        assert_eq!(event["level"], json!("intro"));
+        let mut same = properties(json!({"account": 42}));
+        policy.anonymize(&mut same);
+        assert_eq!(same["account"], event["account"]);
+    }
+
+    #[test]
+    fn batch_retry_and_persist() {
+        let filesystem = MemoryFilesystem::new();
+        let mut uploader = Uploader { online: false, bodies: Vec::new() };
+        let config = AnalyticsConfig { batch_size: 2, ..AnalyticsConfig::default() };
+
+        let mut analytics = Analytics::new(config.clone(), 1000);
+        analytics.record("ignored", Map::new(), 1000);
+        assert_eq!(analytics.pending_events(), 0);
+        analytics.set_enabled(true, &filesystem).unwrap();
+        analytics.record("level_started", properties(json!({"level": "intro"})), 1001);
+        analytics.record("level_completed", properties(json!({"level": "intro"})), 1002);
+        analytics.update(1002, &mut uploader, &filesystem).unwrap();
+        assert_eq!(filesystem.read_dir(analytics_directory().unwrap()).unwrap().len(), 1);
+
+        //The game is closed while offline, the batch is sent at the next start.
+        let mut next_run = Analytics::new(AnalyticsConfig { enabled: true, ..config }, 2000);
+        assert_eq!(next_run.load_persisted(&filesystem, 2000).unwrap(), 1);
+        assert_eq!(next_run.pending_events(), 2);
+        next_run.update(2000, &mut uploader, &filesystem).unwrap();
+        assert_eq!(next_run.pending_events(), 2);
+        uploader.online = true;
+        next_run.update(2001, &mut uploader, &filesystem).unwrap();
+        assert!(uploader.bodies.is_empty());
+        next_run.update(2010, &mut uploader, &filesystem).unwrap();
+        assert_eq!(uploader.bodies.len(), 1);
+        assert_eq!(uploader.bodies[0]["events"][1]["name"], json!("level_completed"));
+        assert_eq!(next_run.pending_events(), 0);
+        assert!(filesystem.read_dir(analytics_directory().unwrap()).unwrap().is_empty());
+
+        //Opting out deletes everything.
+        uploader.online = false;
+        next_run.record("quit", Map::new(), 2020);
+        next_run.flush(2020);
+        next_run.persist(&filesystem).unwrap();
+        next_run.set_enabled(false, &filesystem).unwrap();
+        assert_eq!(next_run.pending_events(), 0);
+        assert!(!filesystem.exists(analytics_directory().unwrap()));
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ BANDWIDTH PROFILER.
+
+ The replication layer reports the bytes it writes for each component, on each channel. The profiler
+ keeps the totals, and the rate over the last second, by channel and by component type.
+
+ The rates are exported as metrics ("net.bandwidth.<channel>.<component>", in kbps), and the report
+ lists the channels and the components by rate, against the bandwidth budget.
+*/
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+const RATE_WINDOW_MS: u64 = 1000;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BandwidthStats {
+    pub total_bytes: u64,
+    pub packets: u64,
+    pub kbps: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandwidthReport {
+    pub budget_kbps: Option<f64>,
+    pub total_kbps: f64,
+    pub channels: Vec<(String, BandwidthStats)>,
+    //By "channel/component".
+    pub components: Vec<(String, BandwidthStats)>,
+}
+
+impl BandwidthReport {
+    pub fn over_budget(&self) -> bool {
+        self.budget_kbps.map(|budget| self.total_kbps > budget).unwrap_or(false)
+    }
+}
+
+impl fmt::Display for BandwidthReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.budget_kbps {
+            Some(budget) => writeln!(f, "Bandwidth: {:.1} kbps of {:.1} kbps{}", self.total_kbps, budget, if self.over_budget() {" (over budget)"} else {""})?,
+            None => writeln!(f, "Bandwidth: {:.1} kbps", self.total_kbps)?,
+        }
+        writeln!(f, "Channels:")?;
+        for &(ref name, ref stats) in self.channels.iter() {
+            writeln!(f, "  {:<32} {:>9.1} kbps {:>12} bytes {:>8} packets", name, stats.kbps, stats.total_bytes, stats.packets)?;
+        }
+        writeln!(f, "Components:")?;
+        for &(ref name, ref stats) in self.components.iter() {
+            writeln!(f, "  {:<32} {:>9.1} kbps {:>12} bytes {:>8} packets", name, stats.kbps, stats.total_bytes, stats.packets)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Counter {
+    total_bytes: u64,
+    packets: u64,
+    //The samples of the rate window.
+    recent: VecDeque<(Instant, u64)>,
+}
+
+impl Counter {
+    fn record(&mut self, bytes: u64, now: Instant) {
+        self.total_bytes += bytes;
+        self.packets += 1;
+        self.recent.push_back((now, bytes));
+    }
+
+    fn stats(&mut self, now: Instant) -> BandwidthStats {
+        let window = Duration::from_millis(RATE_WINDOW_MS);
+        while self.recent.front().map(|&(time, _)| now.duration_since(time) >= window).unwrap_or(false) {
+            self.recent.pop_front();
+        }
+        let bytes: u64 = self.recent.iter().map(|&(_, bytes)| bytes).sum();
+        BandwidthStats {
+            total_bytes: self.total_bytes,
+            packets: self.packets,
+            kbps: (bytes * 8) as f64 / RATE_WINDOW_MS as f64,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BandwidthProfiler {
+    budget_kbps: Option<f64>,
+    channels: BTreeMap<String, Counter>,
+    components: BTreeMap<(String, String), Counter>,
+}
+
+impl BandwidthProfiler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_budget(mut self, kbps: f64) -> Self {
+        self.budget_kbps = Some(kbps);
+        self
+    }
+
+    //The bytes of a component written in a packet of a channel.
+    pub fn record_component(&mut self, channel: &str, component: &str, bytes: usize, now: Instant) {
+        self.components.entry((channel.to_string(), component.to_string())).or_insert_with(Counter::default).record(bytes as u64, now);
+    }
+
+    //A whole packet sent on a channel, headers included.
+    pub fn record_packet(&mut self, channel: &str, bytes: usize, now: Instant) {
+        self.channels.entry(channel.to_string()).or_insert_with(Counter::default).record(bytes as u64, now);
+    }
+
+    pub fn reset(&mut self) {
+        self.channels.clear();
+        self.components.clear();
+    }
+
+    pub fn report(&mut self, now: Instant) -> BandwidthReport {
+        let by_rate = |stats: &mut Vec<(String, BandwidthStats)>| {
+            stats.sort_by(|a, b| b.1.kbps.partial_cmp(&a.1.kbps).unwrap_or(::std::cmp::Ordering::Equal).then(b.1.total_bytes.cmp(&a.1.total_bytes)));
+        };
+        let mut channels: Vec<(String, BandwidthStats)> = self.channels.iter_mut()
+            .map(|(name, counter)| (name.clone(), counter.stats(now)))
+            .collect();
+        by_rate(&mut channels);
+        let mut components: Vec<(String, BandwidthStats)> = self.components.iter_mut()
+            .map(|(&(ref channel, ref component), counter)| (format!("{}/{}", channel, component), counter.stats(now)))
+            .collect();
+        by_rate(&mut components);
+        BandwidthReport {
+            budget_kbps: self.budget_kbps,
+            total_kbps: channels.iter().map(|&(_, ref stats)| stats.kbps).sum(),
+            channels,
+            components,
+        }
+    }
+
+    //Give the current rates to the metrics system.
+    pub fn export_metrics<F: FnMut(&str, f64)>(&mut self, now: Instant, mut gauge: F) {
+        let report = self.report(now);
+        gauge("net.bandwidth.total", report.total_kbps);
+        for &(ref name, ref stats) in report.channels.iter() {
+            gauge(format!("net.bandwidth.{}", name).as_str(), stats.kbps);
+        }
+        for &(ref name, ref stats) in report.components.iter() {
+            gauge(format!("net.bandwidth.{}", name.replace('/', ".")).as_str(), stats.kbps);
+        }
+    }
+}
+
+#[cfg(test)]
+mod bandwidth_profiler_test {
+    use super::*;
+
+    #[test]
+    fn bandwidth_profiler_report() {
+        let start = Instant::now();
+        let mut profiler = BandwidthProfiler::new().with_budget(128.0);
+        for tick in 0..10 {
+            let now = start + Duration::from_millis(tick * 100);
+            profiler.record_component("unreliable", "Transform", 1500, now);
+            profiler.record_component("unreliable", "Health", 100, now);
+            profiler.record_packet("unreliable", 1700, now);
+        }
+        let report = profiler.report(start + Duration::from_millis(950));
+        assert_eq!(report.channels[0].1.kbps, 136.0);
+        assert!(report.over_budget());
+        assert_eq!(report.components[0].0, "unreliable/Transform");
+        assert_eq!(report.components[0].1.kbps, 120.0);
+        assert!(report.to_string().contains("over budget"));
+
+        let mut metrics = Vec::new();
+        profiler.export_metrics(start + Duration::from_millis(1550), |name, value| metrics.push((name.to_string(), value)));
+        assert!(metrics.contains(&(String::from("net.bandwidth.unreliable.Health"), 3.2)));
+    }
+}
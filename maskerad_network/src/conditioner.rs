@@ -0,0 +1,254 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ NETWORK CONDITIONER.
+
+ Simulates a bad connection at the transport level, to test the replication on a LAN or on the same machine:
+ latency, jitter, packet loss, duplication and a bandwidth cap. The packets go through the conditioner
+ when they are sent and received, and are released when their delay is over.
+
+ The settings are variables, set by name from the console or the command line:
+ - net_sim_enabled: "true" or "false".
+ - net_sim_latency and net_sim_jitter: in milliseconds, added to each packet (one way).
+ - net_sim_loss and net_sim_duplicate: probabilities, between 0 and 1.
+ - net_sim_bandwidth: in kbps, 0 for no cap.
+*/
+
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+use maskerad_core::random::RandomNumber;
+use network_error::{NetworkError, NetworkResult};
+
+pub const CONDITIONER_VARIABLES: [&'static str; 6] = [
+    "net_sim_enabled",
+    "net_sim_latency",
+    "net_sim_jitter",
+    "net_sim_loss",
+    "net_sim_duplicate",
+    "net_sim_bandwidth",
+];
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConditionerSettings {
+    pub enabled: bool,
+    pub latency_ms: u64,
+    pub jitter_ms: u64,
+    pub loss: f64,
+    pub duplicate: f64,
+    pub bandwidth_kbps: Option<u64>,
+}
+
+impl ConditionerSettings {
+    pub fn set_variable(&mut self, name: &str, value: &str) -> NetworkResult<()> {
+        let invalid = || NetworkError::ConditionerError(format!("Invalid value {} for {}.", value, name));
+        let probability = || value.parse::<f64>().ok().filter(|value| *value >= 0.0 && *value <= 1.0).ok_or_else(invalid);
+        match name {
+            "net_sim_enabled" => self.enabled = value.parse().map_err(|_| invalid())?,
+            "net_sim_latency" => self.latency_ms = value.parse().map_err(|_| invalid())?,
+            "net_sim_jitter" => self.jitter_ms = value.parse().map_err(|_| invalid())?,
+            "net_sim_loss" => self.loss = probability()?,
+            "net_sim_duplicate" => self.duplicate = probability()?,
+            "net_sim_bandwidth" => self.bandwidth_kbps = match value.parse().map_err(|_| invalid())? {
+                0 => None,
+                kbps => Some(kbps),
+            },
+            _ => return Err(NetworkError::ConditionerError(format!("Unknown network conditioner variable {}.", name))),
+        }
+        debug!("Network conditioner: {} = {}.", name, value);
+        Ok(())
+    }
+}
+
+pub struct NetworkConditioner<A> {
+    settings: ConditionerSettings,
+    random: RandomNumber,
+    //Sorted by release time.
+    queue: VecDeque<(Instant, A, Vec<u8>)>,
+    //When the simulated link is free again, for the bandwidth cap.
+    link_free_at: Option<Instant>,
+}
+
+impl<A: Clone> NetworkConditioner<A> {
+    pub fn new(settings: ConditionerSettings) -> Self {
+        NetworkConditioner {
+            settings,
+            random: RandomNumber::new(),
+            queue: VecDeque::new(),
+            link_free_at: None,
+        }
+    }
+
+    //Reproducible losses and delays.
+    pub fn with_seed(mut self, seed: [u32; 4]) -> Self {
+        self.random = RandomNumber::from_seed(seed);
+        self
+    }
+
+    pub fn settings(&self) -> &ConditionerSettings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut ConditionerSettings {
+        &mut self.settings
+    }
+
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn schedule(&mut self, release: Instant, address: A, packet: Vec<u8>) {
+        let index = self.queue.iter().position(|&(time, _, _)| time > release).unwrap_or(self.queue.len());
+        self.queue.insert(index, (release, address, packet));
+    }
+
+    pub fn push(&mut self, address: A, packet: Vec<u8>, now: Instant) {
+        if !self.settings.enabled {
+            return self.schedule(now, address, packet);
+        }
+        if self.settings.loss > 0.0 && self.random.gen_range(0.0, 1.0) < self.settings.loss {
+            trace!("Network conditioner: dropped a packet of {} bytes.", packet.len());
+            return;
+        }
+        let copies = if self.settings.duplicate > 0.0 && self.random.gen_range(0.0, 1.0) < self.settings.duplicate {2} else {1};
+
+        let mut sent = now;
+        if let Some(kbps) = self.settings.bandwidth_kbps {
+            let start = self.link_free_at.map(|free| free.max(now)).unwrap_or(now);
+            sent = start + Duration::from_micros(packet.len() as u64 * 8 * 1000 / kbps.max(1));
+            self.link_free_at = Some(sent);
+        }
+        for _ in 0..copies {
+            let jitter = if self.settings.jitter_ms > 0 {self.random.gen_range(0, self.settings.jitter_ms + 1)} else {0};
+            let release = sent + Duration::from_millis(self.settings.latency_ms + jitter);
+            self.schedule(release, address.clone(), packet.clone());
+        }
+    }
+
+    //The packets whose delay is over.
+    pub fn poll(&mut self, now: Instant) -> Vec<(A, Vec<u8>)> {
+        let mut released = Vec::new();
+        while self.queue.front().map(|&(time, _, _)| time <= now).unwrap_or(false) {
+            if let Some((_, address, packet)) = self.queue.pop_front() {
+                released.push((address, packet));
+            }
+        }
+        released
+    }
+}
+
+//A UDP socket whose sent and received packets go through conditioners.
+pub struct ConditionedSocket {
+    socket: UdpSocket,
+    outgoing: NetworkConditioner<SocketAddr>,
+    incoming: NetworkConditioner<SocketAddr>,
+    received: VecDeque<(SocketAddr, Vec<u8>)>,
+}
+
+impl ConditionedSocket {
+    pub fn bind<A: ToSocketAddrs>(address: A, settings: ConditionerSettings) -> NetworkResult<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(ConditionedSocket {
+            socket,
+            outgoing: NetworkConditioner::new(settings.clone()),
+            incoming: NetworkConditioner::new(settings),
+            received: VecDeque::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> NetworkResult<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: &str) -> NetworkResult<()> {
+        self.outgoing.settings_mut().set_variable(name, value)?;
+        self.incoming.settings_mut().set_variable(name, value)
+    }
+
+    pub fn send_to(&mut self, packet: &[u8], address: SocketAddr) -> NetworkResult<()> {
+        self.outgoing.push(address, packet.to_vec(), Instant::now());
+        self.flush()
+    }
+
+    //Send the outgoing packets whose delay is over, and receive the incoming ones.
+    pub fn flush(&mut self) -> NetworkResult<()> {
+        let now = Instant::now();
+        for (address, packet) in self.outgoing.poll(now) {
+            self.socket.send_to(packet.as_slice(), address)?;
+        }
+        let mut buffer = [0u8; 65536];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((size, address)) => self.incoming.push(address, buffer[..size].to_vec(), now),
+                Err(ref error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => return Err(NetworkError::from(error)),
+            }
+        }
+        self.received.extend(self.incoming.poll(now));
+        Ok(())
+    }
+
+    pub fn recv_from(&mut self) -> io::Result<(SocketAddr, Vec<u8>)> {
+        if self.received.is_empty() {
+            self.flush().map_err(|error| io::Error::new(ErrorKind::Other, error.to_string()))?;
+        }
+        self.received.pop_front().ok_or_else(|| io::Error::from(ErrorKind::WouldBlock))
+    }
+}
+
+#[cfg(test)]
+mod conditioner_test {
+    use super::*;
+
+    #[test]
+    fn conditioner_variables() {
+        let mut settings = ConditionerSettings::default();
+        settings.set_variable("net_sim_latency", "80").unwrap();
+        settings.set_variable("net_sim_bandwidth", "128").unwrap();
+        assert_eq!(settings.latency_ms, 80);
+        assert_eq!(settings.bandwidth_kbps, Some(128));
+        assert!(settings.set_variable("net_sim_loss", "1.5").is_err());
+        assert!(settings.set_variable("net_sim_ping", "80").is_err());
+    }
+
+    #[test]
+    fn conditioner_latency_loss_and_bandwidth() {
+        let now = Instant::now();
+        let mut settings = ConditionerSettings::default();
+        let mut conditioner = NetworkConditioner::new(settings.clone()).with_seed([1, 2, 3, 4]);
+        conditioner.push(0, vec![0; 10], now);
+        assert_eq!(conditioner.poll(now).len(), 1);
+
+        settings.enabled = true;
+        settings.latency_ms = 50;
+        *conditioner.settings_mut() = settings.clone();
+        conditioner.push(0, vec![0; 10], now);
+        assert!(conditioner.poll(now + Duration::from_millis(49)).is_empty());
+        assert_eq!(conditioner.poll(now + Duration::from_millis(50)).len(), 1);
+
+        //1000 bytes at 80 kbps: 100ms each.
+        settings.latency_ms = 0;
+        settings.bandwidth_kbps = Some(80);
+        *conditioner.settings_mut() = settings.clone();
+        conditioner.push(0, vec![0; 1000], now);
+        conditioner.push(1, vec![0; 1000], now);
+        assert_eq!(conditioner.poll(now + Duration::from_millis(100)).len(), 1);
+        assert_eq!(conditioner.poll(now + Duration::from_millis(200)), vec![(1, vec![0; 1000])]);
+
+        settings.bandwidth_kbps = None;
+        settings.loss = 0.5;
+        *conditioner.settings_mut() = settings;
+        for _ in 0..1000 {
+            conditioner.push(0, vec![0], now);
+        }
+        let delivered = conditioner.poll(now).len();
+        assert!(delivered > 400 && delivered < 600);
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ PROTOCOL HANDSHAKE.
+
+ The first message of a connection, in both directions. The peers compare:
+ - The game infos: another game, or another version of the game, is rejected.
+ - The protocol versions: each peer supports a range of protocol versions, the highest common version is
+   used. A peer too old or too recent is rejected.
+ - The hash of the protocol registry: the names of the replicated types and of the RPCs, in their
+   registration order. Two builds replicating different types would desync silently, they are rejected.
+ - The engine version, reported in the errors to help finding the mismatched build.
+*/
+
+use std::fmt;
+use serde_json;
+use maskerad_core::engine_configuration::game_infos::GameInfos;
+use network_error::{NetworkError, NetworkResult};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+pub const ENGINE_VERSION: &'static str = env!("CARGO_PKG_VERSION");
+const HANDSHAKE_MAGIC: &'static [u8; 4] = b"KHSK";
+
+//The replicated types and the RPCs, the ids on the wire are their index in the registry.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProtocolRegistry {
+    replicated_types: Vec<String>,
+    rpcs: Vec<String>,
+}
+
+impl ProtocolRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn register_type(&mut self, name: &str) -> u32 {
+        self.replicated_types.push(name.to_string());
+        self.replicated_types.len() as u32 - 1
+    }
+
+    pub fn register_rpc(&mut self, name: &str) -> u32 {
+        self.rpcs.push(name.to_string());
+        self.rpcs.len() as u32 - 1
+    }
+
+    pub fn type_id(&self, name: &str) -> Option<u32> {
+        self.replicated_types.iter().position(|type_name| type_name == name).map(|id| id as u32)
+    }
+
+    pub fn rpc_id(&self, name: &str) -> Option<u32> {
+        self.rpcs.iter().position(|rpc| rpc == name).map(|id| id as u32)
+    }
+
+    //FNV-1a of the names, stable across platforms and builds.
+    pub fn hash(&self) -> u64 {
+        let mut hash = 0xcbf29ce484222325u64;
+        for name in self.replicated_types.iter().map(|name| ("type", name)).chain(self.rpcs.iter().map(|name| ("rpc", name))) {
+            for byte in name.0.bytes().chain(name.1.bytes()).chain(Some(0)) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Handshake {
+    pub engine_version: String,
+    pub game: GameInfos,
+    pub protocol_version: u32,
+    pub min_protocol_version: u32,
+    pub registry_hash: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandshakeRejection {
+    OtherGame(String),
+    GameVersion { local: String, remote: String },
+    Protocol { local: (u32, u32), remote: (u32, u32) },
+    Registry { local_engine: String, remote_engine: String },
+}
+
+impl fmt::Display for HandshakeRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &HandshakeRejection::OtherGame(ref game) => {
+                write!(f, "the peer runs another game ({})", game)
+            },
+            &HandshakeRejection::GameVersion { ref local, ref remote } => {
+                write!(f, "the peer runs the version {} of the game, this is the version {}", remote, local)
+            },
+            &HandshakeRejection::Protocol { local, remote } => {
+                write!(f, "no common protocol version (protocols {} to {} here, {} to {} on the peer)", local.0, local.1, remote.0, remote.1)
+            },
+            &HandshakeRejection::Registry { ref local_engine, ref remote_engine } => {
+                write!(f, "the replicated types or the RPCs differ from the ones of the peer (engine {} here, {} on the peer)", local_engine, remote_engine)
+            },
+        }
+    }
+}
+
+impl From<HandshakeRejection> for NetworkError {
+    fn from(rejection: HandshakeRejection) -> Self {
+        NetworkError::HandshakeError(rejection.to_string())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NegotiatedProtocol {
+    pub protocol_version: u32,
+    //The connection uses an older protocol than the local one.
+    pub downgraded: bool,
+}
+
+impl Handshake {
+    pub fn new(game: GameInfos, registry: &ProtocolRegistry) -> Self {
+        Handshake {
+            engine_version: ENGINE_VERSION.to_string(),
+            game,
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            registry_hash: registry.hash(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> NetworkResult<Vec<u8>> {
+        let mut bytes = HANDSHAKE_MAGIC.to_vec();
+        bytes.extend(serde_json::to_vec(self)?);
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> NetworkResult<Self> {
+        if bytes.len() < HANDSHAKE_MAGIC.len() || &bytes[..HANDSHAKE_MAGIC.len()] != HANDSHAKE_MAGIC {
+            return Err(NetworkError::HandshakeError(String::from("The peer didn't send a handshake, it is not a peer of this engine.")));
+        }
+        Ok(serde_json::from_slice(&bytes[HANDSHAKE_MAGIC.len()..])?)
+    }
+
+    //Compare the local handshake with the one of the peer. Both peers reach the same result.
+    pub fn negotiate(&self, remote: &Handshake) -> Result<NegotiatedProtocol, HandshakeRejection> {
+        if self.game.name != remote.game.name {
+            return Err(HandshakeRejection::OtherGame(remote.game.name.clone()));
+        }
+        if self.game.version != remote.game.version {
+            return Err(HandshakeRejection::GameVersion {
+                local: self.game.version.clone(),
+                remote: remote.game.version.clone(),
+            });
+        }
+        let protocol_version = self.protocol_version.min(remote.protocol_version);
+        if protocol_version < self.min_protocol_version.max(remote.min_protocol_version) {
+            return Err(HandshakeRejection::Protocol {
+                local: (self.min_protocol_version, self.protocol_version),
+                remote: (remote.min_protocol_version, remote.protocol_version),
+            });
+        }
+        if self.registry_hash != remote.registry_hash {
+            return Err(HandshakeRejection::Registry {
+                local_engine: self.engine_version.clone(),
+                remote_engine: remote.engine_version.clone(),
+            });
+        }
+        if protocol_version < self.protocol_version {
+            warn!("The peer uses the protocol {}, downgrading from the protocol {}.", protocol_version, self.protocol_version);
+        }
+        Ok(NegotiatedProtocol {
+            protocol_version,
+            downgraded: protocol_version < self.protocol_version,
+        })
+    }
+}
+
+#[cfg(test)]
+mod handshake_test {
+    use super::*;
+
+    fn handshake(version: &str, protocols: (u32, u32), registry: &ProtocolRegistry) -> Handshake {
+        let mut handshake = Handshake::new(GameInfos::new("Kindred", version), registry);
+        handshake.min_protocol_version = protocols.0;
+        handshake.protocol_version = protocols.1;
+        handshake
+    }
+
+    #[test]
+    fn handshake_negotiation() {
+        let mut registry = ProtocolRegistry::new();
+        registry.register_type("Transform");
+        registry.register_rpc("fire");
+        let local = handshake("1.0", (2, 4), &registry);
+
+        let remote = Handshake::from_bytes(handshake("1.0", (1, 3), &registry).to_bytes().unwrap().as_slice()).unwrap();
+        assert_eq!(local.negotiate(&remote), Ok(NegotiatedProtocol { protocol_version: 3, downgraded: true }));
+        assert_eq!(remote.negotiate(&local), Ok(NegotiatedProtocol { protocol_version: 3, downgraded: false }));
+
+        assert!(local.negotiate(&handshake("1.0", (1, 1), &registry)).is_err());
+        assert!(local.negotiate(&handshake("1.1", (2, 4), &registry)).is_err());
+
+        let mut other_registry = registry.clone();
+        other_registry.register_type("Health");
+        let rejection = local.negotiate(&handshake("1.0", (2, 4), &other_registry)).unwrap_err();
+        assert!(NetworkError::from(rejection).to_string().contains("replicated types"));
+        assert!(Handshake::from_bytes(b"GET / HTTP/1.1").is_err());
+    }
+}
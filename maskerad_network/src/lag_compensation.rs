@@ -0,0 +1,135 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ LAG COMPENSATION.
+
+ When a client shoots, it sees the other players where they were a round trip (plus the interpolation
+ delay) ago. The server keeps the hitboxes of the last ticks, and tests the shots against the hitboxes
+ of the tick the shooter was seeing, instead of the current ones.
+*/
+
+use std::collections::{BTreeMap, VecDeque};
+use prediction::Tick;
+
+pub type EntityId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hitbox {
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+impl Hitbox {
+    //The distance along the ray to the hitbox, the direction is normalized.
+    pub fn raycast(&self, origin: [f32; 3], direction: [f32; 3]) -> Option<f32> {
+        let to_center = [self.center[0] - origin[0], self.center[1] - origin[1], self.center[2] - origin[2]];
+        let projection = to_center[0] * direction[0] + to_center[1] * direction[1] + to_center[2] * direction[2];
+        let squared_distance = to_center.iter().map(|value| value * value).sum::<f32>() - projection * projection;
+        let squared_radius = self.radius * self.radius;
+        if squared_distance > squared_radius {
+            return None;
+        }
+        let half_chord = (squared_radius - squared_distance).sqrt();
+        let distance = if projection - half_chord >= 0.0 {projection - half_chord} else {projection + half_chord};
+        if distance >= 0.0 {Some(distance)} else {None}
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RewindHit {
+    pub entity: EntityId,
+    pub distance: f32,
+    pub tick: Tick,
+}
+
+pub struct HitboxHistory {
+    frames: VecDeque<(Tick, BTreeMap<EntityId, Hitbox>)>,
+    max_ticks: usize,
+}
+
+impl HitboxHistory {
+    //max_ticks bounds the rewind, so a client with a huge latency can't shoot the past.
+    pub fn new(max_ticks: usize) -> Self {
+        HitboxHistory {
+            frames: VecDeque::with_capacity(max_ticks),
+            max_ticks,
+        }
+    }
+
+    //The hitboxes of all the entities after the simulation of a tick.
+    pub fn record(&mut self, tick: Tick, hitboxes: BTreeMap<EntityId, Hitbox>) {
+        if self.frames.len() == self.max_ticks {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((tick, hitboxes));
+    }
+
+    pub fn oldest_tick(&self) -> Option<Tick> {
+        self.frames.front().map(|&(tick, _)| tick)
+    }
+
+    //The recorded tick closest to the requested one, clamped to the history.
+    fn frame(&self, tick: Tick) -> Option<&(Tick, BTreeMap<EntityId, Hitbox>)> {
+        self.frames.iter().rev().find(|&&(frame_tick, _)| frame_tick <= tick).or_else(|| self.frames.front())
+    }
+
+    //The tick seen by a client: the current tick, minus its latency and its interpolation delay.
+    pub fn client_view_tick(current: Tick, latency_ticks: u64, interpolation_ticks: u64) -> Tick {
+        current.saturating_sub(latency_ticks + interpolation_ticks)
+    }
+
+    pub fn hitbox_at(&self, entity: EntityId, tick: Tick) -> Option<Hitbox> {
+        self.frame(tick).and_then(|&(_, ref hitboxes)| hitboxes.get(&entity).cloned())
+    }
+
+    //The closest entity hit by a shot, against the hitboxes of the tick seen by the shooter.
+    pub fn raycast(&self, tick: Tick, origin: [f32; 3], direction: [f32; 3], shooter: Option<EntityId>) -> Option<RewindHit> {
+        let &(frame_tick, ref hitboxes) = self.frame(tick)?;
+        if frame_tick != tick {
+            trace!("Rewinding a shot to the tick {} instead of {}.", frame_tick, tick);
+        }
+        hitboxes.iter()
+            .filter(|&(entity, _)| Some(*entity) != shooter)
+            .filter_map(|(entity, hitbox)| hitbox.raycast(origin, direction).map(|distance| RewindHit {
+                entity: *entity,
+                distance,
+                tick: frame_tick,
+            }))
+            .fold(None, |closest: Option<RewindHit>, hit| match closest {
+                Some(closest) if closest.distance <= hit.distance => Some(closest),
+                _ => Some(hit),
+            })
+    }
+}
+
+#[cfg(test)]
+mod lag_compensation_test {
+    use super::*;
+
+    #[test]
+    fn lag_compensation_rewinds_shots() {
+        let mut history = HitboxHistory::new(4);
+        for tick in 0..6 {
+            let mut hitboxes = BTreeMap::new();
+            hitboxes.insert(1, Hitbox { center: [tick as f32, 0.0, 10.0], radius: 0.5 });
+            hitboxes.insert(2, Hitbox { center: [0.0, 0.0, 0.0], radius: 0.5 });
+            history.record(tick, hitboxes);
+        }
+        assert_eq!(history.oldest_tick(), Some(2));
+
+        //The target moved away since the tick seen by the shooter.
+        let tick = HitboxHistory::client_view_tick(5, 2, 1);
+        assert_eq!(tick, 2);
+        let hit = history.raycast(tick, [2.0, 0.0, 0.0], [0.0, 0.0, 1.0], Some(2)).unwrap();
+        assert_eq!(hit.entity, 1);
+        assert!((hit.distance - 9.5).abs() < 0.001);
+        assert!(history.raycast(5, [2.0, 0.0, 0.0], [0.0, 0.0, 1.0], Some(2)).is_none());
+        //Older than the history: clamped to the oldest tick.
+        assert_eq!(history.hitbox_at(1, 0).unwrap().center[0], 2.0);
+    }
+}
@@ -0,0 +1,252 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ LAN DISCOVERY.
+
+ A host announces its session by broadcasting a small UDP packet on the LAN every second: the game
+ infos, the name of the session, the port to join and the player counts.
+
+ The server browser listens on the discovery port, and turns the announcements of the same game at the
+ same version into events: a server is found, updated (player count...), or lost when it stops announcing.
+*/
+
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use serde_json;
+use maskerad_core::engine_configuration::game_infos::GameInfos;
+use network_error::NetworkResult;
+
+pub const DISCOVERY_PORT: u16 = 27016;
+const DISCOVERY_MAGIC: &'static [u8; 4] = b"KLAN";
+pub const ANNOUNCE_INTERVAL_MS: u64 = 1000;
+//A server missing this many announcements is lost.
+const LOST_AFTER_INTERVALS: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionAnnouncement {
+    pub game: GameInfos,
+    pub session_name: String,
+    //The port of the session, on the address the announcement comes from.
+    pub port: u16,
+    pub players: usize,
+    pub max_players: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredServer {
+    pub address: SocketAddr,
+    pub announcement: SessionAnnouncement,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiscoveryEvent {
+    ServerFound(DiscoveredServer),
+    ServerUpdated(DiscoveredServer),
+    ServerLost(SocketAddr),
+}
+
+pub struct LanAnnouncer {
+    socket: UdpSocket,
+    target: SocketAddr,
+    announcement: SessionAnnouncement,
+    last_announce: Option<Instant>,
+}
+
+impl LanAnnouncer {
+    pub fn new(announcement: SessionAnnouncement) -> NetworkResult<Self> {
+        LanAnnouncer::with_target(announcement, SocketAddr::from(([255, 255, 255, 255], DISCOVERY_PORT)))
+    }
+
+    //Announce to another address than the broadcast one (a subnet broadcast address, or a single machine).
+    pub fn with_target(announcement: SessionAnnouncement, target: SocketAddr) -> NetworkResult<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.set_broadcast(true)?;
+        Ok(LanAnnouncer {
+            socket,
+            target,
+            announcement,
+            last_announce: None,
+        })
+    }
+
+    //Announced at the next update.
+    pub fn set_players(&mut self, players: usize) {
+        if self.announcement.players != players {
+            self.announcement.players = players;
+            self.last_announce = None;
+        }
+    }
+
+    pub fn announcement(&self) -> &SessionAnnouncement {
+        &self.announcement
+    }
+
+    pub fn update(&mut self) -> NetworkResult<()> {
+        let due = self.last_announce
+            .map(|last| last.elapsed() >= Duration::from_millis(ANNOUNCE_INTERVAL_MS))
+            .unwrap_or(true);
+        if due {
+            let mut packet = DISCOVERY_MAGIC.to_vec();
+            packet.extend(serde_json::to_vec(&self.announcement)?);
+            self.socket.send_to(packet.as_slice(), self.target)?;
+            self.last_announce = Some(Instant::now());
+        }
+        Ok(())
+    }
+}
+
+pub struct LanBrowser {
+    socket: UdpSocket,
+    game: GameInfos,
+    servers: BTreeMap<SocketAddr, (DiscoveredServer, Instant)>,
+}
+
+impl LanBrowser {
+    pub fn new(game: GameInfos) -> NetworkResult<Self> {
+        LanBrowser::bind(game, DISCOVERY_PORT)
+    }
+
+    pub fn bind(game: GameInfos, port: u16) -> NetworkResult<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        socket.set_nonblocking(true)?;
+        Ok(LanBrowser {
+            socket,
+            game,
+            servers: BTreeMap::new(),
+        })
+    }
+
+    pub fn servers(&self) -> Vec<&DiscoveredServer> {
+        self.servers.values().map(|&(ref server, _)| server).collect()
+    }
+
+    fn parse(&self, packet: &[u8]) -> Option<SessionAnnouncement> {
+        if packet.len() < DISCOVERY_MAGIC.len() || &packet[..DISCOVERY_MAGIC.len()] != DISCOVERY_MAGIC {
+            return None;
+        }
+        match serde_json::from_slice::<SessionAnnouncement>(&packet[DISCOVERY_MAGIC.len()..]) {
+            Ok(ref announcement) if !announcement.game.is_compatible(&self.game) => {
+                trace!("Ignoring a LAN announcement of {} {}.", announcement.game.name, announcement.game.version);
+                None
+            },
+            Ok(announcement) => Some(announcement),
+            Err(error) => {
+                warn!("Invalid LAN announcement: {}.", error);
+                None
+            },
+        }
+    }
+
+    pub fn update(&mut self) -> Vec<DiscoveryEvent> {
+        self.update_at(Instant::now())
+    }
+
+    fn update_at(&mut self, now: Instant) -> Vec<DiscoveryEvent> {
+        let mut events = Vec::new();
+        let mut packet = [0u8; 2048];
+        loop {
+            let (size, source) = match self.socket.recv_from(&mut packet) {
+                Ok(received) => received,
+                Err(ref error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    warn!("Could not receive the LAN announcements: {}.", error);
+                    break;
+                },
+            };
+            let announcement = match self.parse(&packet[..size]) {
+                Some(announcement) => announcement,
+                None => continue,
+            };
+            let server = DiscoveredServer {
+                address: SocketAddr::new(source.ip(), announcement.port),
+                announcement,
+            };
+            let event = match self.servers.get(&server.address) {
+                Some(&(ref known, _)) if *known == server => None,
+                Some(_) => Some(DiscoveryEvent::ServerUpdated(server.clone())),
+                None => {
+                    debug!("Found the LAN session {} at {}.", server.announcement.session_name, server.address);
+                    Some(DiscoveryEvent::ServerFound(server.clone()))
+                },
+            };
+            events.extend(event);
+            self.servers.insert(server.address, (server, now));
+        }
+
+        let timeout = Duration::from_millis(ANNOUNCE_INTERVAL_MS * LOST_AFTER_INTERVALS as u64);
+        let lost: Vec<SocketAddr> = self.servers.iter()
+            .filter(|&(_, &(_, last_seen))| now.duration_since(last_seen) > timeout)
+            .map(|(address, _)| *address)
+            .collect();
+        for address in lost {
+            self.servers.remove(&address);
+            events.push(DiscoveryEvent::ServerLost(address));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod lan_discovery_test {
+    use super::*;
+    use std::thread;
+
+    fn announcement(version: &str, players: usize) -> SessionAnnouncement {
+        SessionAnnouncement {
+            game: GameInfos::new("Kindred", version),
+            session_name: String::from("Friday night"),
+            port: 27015,
+            players,
+            max_players: 8,
+        }
+    }
+
+    fn receive(browser: &mut LanBrowser, now: Instant) -> Vec<DiscoveryEvent> {
+        for _ in 0..100 {
+            let events = browser.update_at(now);
+            if !events.is_empty() {
+                return events;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        Vec::new()
+    }
+
+    #[test]
+    fn lan_discovery_events() {
+        let port = 47816;
+        let mut browser = LanBrowser::bind(GameInfos::new("Kindred", "1.0"), port).unwrap();
+        let target = SocketAddr::from(([127, 0, 0, 1], port));
+        let mut old_version = LanAnnouncer::with_target(announcement("0.9", 1), target).unwrap();
+        let mut announcer = LanAnnouncer::with_target(announcement("1.0", 1), target).unwrap();
+        old_version.update().unwrap();
+        announcer.update().unwrap();
+
+        let now = Instant::now();
+        let events = receive(&mut browser, now);
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            DiscoveryEvent::ServerFound(ref server) => assert_eq!(server.address, SocketAddr::from(([127, 0, 0, 1], 27015))),
+            ref event => panic!("Unexpected event {:?}.", event),
+        }
+
+        //Announced again immediately, the player count changed.
+        announcer.set_players(2);
+        announcer.update().unwrap();
+        match receive(&mut browser, now).as_slice() {
+            &[DiscoveryEvent::ServerUpdated(ref server)] => assert_eq!(server.announcement.players, 2),
+            events => panic!("Unexpected events {:?}.", events),
+        }
+
+        let later = now + Duration::from_millis(ANNOUNCE_INTERVAL_MS * 5);
+        assert_eq!(browser.update_at(later), vec![DiscoveryEvent::ServerLost(SocketAddr::from(([127, 0, 0, 1], 27015)))]);
+        assert!(browser.servers().is_empty());
+    }
+}
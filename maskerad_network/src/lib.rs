@@ -3,4 +3,38 @@
 // Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
 // http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
-// copied, modified, or distributed except according to those terms.
\ No newline at end of file
+// copied, modified, or distributed except according to those terms.
+
+extern crate maskerad_core;
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate serde_json;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+extern crate chacha20poly1305;
+extern crate x25519_dalek;
+extern crate ed25519_dalek;
+extern crate hmac;
+extern crate hkdf;
+extern crate sha2;
+extern crate rand_core;
+
+pub mod network_error;
+pub mod prediction;
+pub mod lag_compensation;
+pub mod session;
+pub mod lan_discovery;
+pub mod conditioner;
+pub mod handshake;
+pub mod bandwidth_profiler;
+pub mod secure_channel;
+pub mod secure_socket;
+pub mod rollback;
+pub mod analytics;
+
+//The version of the networking subsystem.
+pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,93 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::error::Error;
+use std::fmt;
+use std::io::Error as IOError;
+use serde_json::Error as JSONError;
+
+#[derive(Debug)]
+pub enum NetworkError {
+    IOError(String, IOError),
+    MessageError(String, JSONError),
+    SessionError(String),
+    ConditionerError(String),
+    HandshakeError(String),
+    SecurityError(String),
+    AnalyticsError(String),
+}
+
+unsafe impl Send for NetworkError {}
+unsafe impl Sync for NetworkError {}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &NetworkError::IOError(ref description, _) => {
+                write!(f, "I/O error: {}", description)
+            },
+            &NetworkError::MessageError(ref description, _) => {
+                write!(f, "Message error: {}", description)
+            },
+            &NetworkError::SessionError(ref description) => {
+                write!(f, "Session error: {}", description)
+            },
+            &NetworkError::ConditionerError(ref description) => {
+                write!(f, "Network conditioner error: {}", description)
+            },
+            &NetworkError::HandshakeError(ref description) => {
+                write!(f, "Handshake error: {}", description)
+            },
+            &NetworkError::SecurityError(ref description) => {
+                write!(f, "Security error: {}", description)
+            },
+            &NetworkError::AnalyticsError(ref description) => {
+                write!(f, "Analytics error: {}", description)
+            },
+        }
+    }
+}
+
+impl Error for NetworkError {
+    fn description(&self) -> &str {
+        match self {
+            &NetworkError::IOError(_, _) => "IOError",
+            &NetworkError::MessageError(_, _) => "MessageError",
+            &NetworkError::SessionError(_) => "SessionError",
+            &NetworkError::ConditionerError(_) => "ConditionerError",
+            &NetworkError::HandshakeError(_) => "HandshakeError",
+            &NetworkError::SecurityError(_) => "SecurityError",
+            &NetworkError::AnalyticsError(_) => "AnalyticsError",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match self {
+            &NetworkError::IOError(_, ref cause) => Some(cause),
+            &NetworkError::MessageError(_, ref cause) => Some(cause),
+            &NetworkError::SessionError(_) => None,
+            &NetworkError::ConditionerError(_) => None,
+            &NetworkError::HandshakeError(_) => None,
+            &NetworkError::SecurityError(_) => None,
+            &NetworkError::AnalyticsError(_) => None,
+        }
+    }
+}
+
+pub type NetworkResult<T> = Result<T, NetworkError>;
+
+impl From<IOError> for NetworkError {
+    fn from(error: IOError) -> Self {
+        NetworkError::IOError(format!("Error while doing I/O operations"), error)
+    }
+}
+
+impl From<JSONError> for NetworkError {
+    fn from(error: JSONError) -> Self {
+        NetworkError::MessageError(format!("Error while encoding or decoding a network message"), error)
+    }
+}
@@ -0,0 +1,219 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ CLIENT PREDICTION.
+
+ The client doesn't wait for the server to see the result of its inputs: it simulates the flagged
+ components (usually the ones of the local player) ahead, with the same simulation code as the server,
+ and keeps the input and the predicted state of each tick.
+
+ The server sends authoritative snapshots, with the last tick it simulated. When a snapshot arrives,
+ the client compares it with the state it predicted for the same tick. If they match, the history up to
+ this tick is dropped. If they don't (a misprediction: a collision with another player, a correction...),
+ the client rewinds to the authoritative state and replays the inputs of the following ticks.
+
+ let mut prediction = Prediction::new(128);
+ prediction.predict(tick, input, &mut state, simulate);
+ ...
+ prediction.reconcile(snapshot, &mut state, simulate);
+*/
+
+use std::collections::VecDeque;
+
+pub type Tick = u64;
+
+//A component whose state is predicted by the clients.
+pub trait PredictedComponent: Clone {
+    //True if the predicted state is close enough to the authoritative one to be kept.
+    fn matches(&self, authoritative: &Self) -> bool;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot<S> {
+    pub tick: Tick,
+    pub state: S,
+}
+
+//The inputs of a client, by tick. The client sends the inputs not acknowledged by the server yet with each packet,
+//so a lost packet doesn't lose an input.
+#[derive(Debug, Clone)]
+pub struct InputBuffer<I> {
+    inputs: VecDeque<(Tick, I)>,
+    capacity: usize,
+}
+
+impl<I: Clone> InputBuffer<I> {
+    pub fn new(capacity: usize) -> Self {
+        InputBuffer {
+            inputs: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, tick: Tick, input: I) {
+        if self.inputs.len() == self.capacity {
+            warn!("The input buffer is full, the input of the tick {} is dropped.", self.inputs[0].0);
+            self.inputs.pop_front();
+        }
+        self.inputs.push_back((tick, input));
+    }
+
+    //The server simulated the inputs up to this tick.
+    pub fn acknowledge(&mut self, tick: Tick) {
+        while self.inputs.front().map(|&(input_tick, _)| input_tick <= tick).unwrap_or(false) {
+            self.inputs.pop_front();
+        }
+    }
+
+    pub fn get(&self, tick: Tick) -> Option<&I> {
+        self.inputs.iter().find(|&&(input_tick, _)| input_tick == tick).map(|&(_, ref input)| input)
+    }
+
+    pub fn unacknowledged(&self) -> Vec<(Tick, I)> {
+        self.inputs.iter().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reconciliation {
+    //The snapshot is older than the history, or already reconciled.
+    Ignored,
+    Confirmed,
+    //The state was rewound, and this number of ticks was replayed.
+    Corrected(usize),
+}
+
+pub struct Prediction<S, I> {
+    inputs: InputBuffer<I>,
+    //The predicted state after each tick.
+    history: VecDeque<Snapshot<S>>,
+    last_confirmed: Option<Tick>,
+    mispredictions: u64,
+}
+
+impl<S: PredictedComponent, I: Clone> Prediction<S, I> {
+    pub fn new(capacity: usize) -> Self {
+        Prediction {
+            inputs: InputBuffer::new(capacity),
+            history: VecDeque::with_capacity(capacity),
+            last_confirmed: None,
+            mispredictions: 0,
+        }
+    }
+
+    pub fn inputs(&self) -> &InputBuffer<I> {
+        &self.inputs
+    }
+
+    pub fn last_confirmed(&self) -> Option<Tick> {
+        self.last_confirmed
+    }
+
+    pub fn mispredictions(&self) -> u64 {
+        self.mispredictions
+    }
+
+    //Simulate a tick locally.
+    pub fn predict<F>(&mut self, tick: Tick, input: I, state: &mut S, mut simulate: F) where
+        F: FnMut(&mut S, &I)
+    {
+        simulate(state, &input);
+        self.inputs.push(tick, input);
+        if self.history.len() == self.inputs.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(Snapshot {
+            tick,
+            state: state.clone(),
+        });
+    }
+
+    //Compare an authoritative snapshot with the prediction, rewind and replay on a misprediction.
+    pub fn reconcile<F>(&mut self, snapshot: Snapshot<S>, state: &mut S, mut simulate: F) -> Reconciliation where
+        F: FnMut(&mut S, &I)
+    {
+        if self.last_confirmed.map(|tick| snapshot.tick <= tick).unwrap_or(false) {
+            return Reconciliation::Ignored;
+        }
+        self.last_confirmed = Some(snapshot.tick);
+        self.inputs.acknowledge(snapshot.tick);
+        while self.history.front().map(|predicted| predicted.tick < snapshot.tick).unwrap_or(false) {
+            self.history.pop_front();
+        }
+
+        let confirmed = match self.history.front() {
+            Some(predicted) if predicted.tick == snapshot.tick => predicted.state.matches(&snapshot.state),
+            _ => false,
+        };
+        if confirmed {
+            self.history.pop_front();
+            return Reconciliation::Confirmed;
+        }
+
+        self.mispredictions += 1;
+        debug!("Misprediction at the tick {}, replaying {} ticks.", snapshot.tick, self.inputs.len());
+        *state = snapshot.state;
+        self.history.clear();
+        for (tick, input) in self.inputs.unacknowledged() {
+            simulate(state, &input);
+            self.history.push_back(Snapshot {
+                tick,
+                state: state.clone(),
+            });
+        }
+        Reconciliation::Corrected(self.history.len())
+    }
+}
+
+#[cfg(test)]
+mod prediction_test {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Position(f32);
+
+    impl PredictedComponent for Position {
+        fn matches(&self, authoritative: &Self) -> bool {
+            (self.0 - authoritative.0).abs() < 0.01
+        }
+    }
+
+    fn simulate(position: &mut Position, input: &f32) {
+        position.0 += *input;
+    }
+
+    #[test]
+    fn prediction_confirm_and_correct() {
+        let mut prediction = Prediction::new(64);
+        let mut position = Position(0.0);
+        for tick in 1..6 {
+            prediction.predict(tick, 1.0, &mut position, simulate);
+        }
+        assert_eq!(position, Position(5.0));
+
+        assert_eq!(prediction.reconcile(Snapshot { tick: 2, state: Position(2.0) }, &mut position, simulate), Reconciliation::Confirmed);
+        assert_eq!(prediction.inputs().len(), 3);
+
+        //The server blocked the player at the tick 3: the ticks 4 and 5 are replayed from there.
+        assert_eq!(prediction.reconcile(Snapshot { tick: 3, state: Position(2.0) }, &mut position, simulate), Reconciliation::Corrected(2));
+        assert_eq!(position, Position(4.0));
+        assert_eq!(prediction.mispredictions(), 1);
+
+        assert_eq!(prediction.reconcile(Snapshot { tick: 3, state: Position(0.0) }, &mut position, simulate), Reconciliation::Ignored);
+        assert_eq!(prediction.reconcile(Snapshot { tick: 5, state: Position(4.0) }, &mut position, simulate), Reconciliation::Confirmed);
+        assert!(prediction.inputs().is_empty());
+    }
+}
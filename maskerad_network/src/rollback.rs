@@ -0,0 +1,223 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ ROLLBACK NETCODE.
+
+ An alternative to the client/server prediction, for fighting games and platformers: every peer runs the
+ whole simulation, with the inputs of all the players. The simulation must be deterministic: fixed step,
+ seeded RNG, no iteration over hash maps, same float operations on every platform.
+
+ - The local inputs are delayed by a few ticks (input_delay): they are sent ahead, so they usually reach
+   the other peers before they are needed.
+ - When the input of a remote player is missing, the last known input of this player is used.
+ - The state is saved before each tick. When a late remote input differs from the one used, the state
+   is restored at its tick, and the following ticks are simulated again.
+ - If a remote player is more than max_rollback ticks late, the simulation stalls until its inputs arrive.
+*/
+
+use std::collections::{BTreeMap, VecDeque};
+use prediction::Tick;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollbackConfig {
+    pub players: usize,
+    pub local_player: usize,
+    pub input_delay: u64,
+    pub max_rollback: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RollbackStep {
+    //The tick was simulated, after resimulating this number of ticks.
+    Advanced(usize),
+    //Waiting for the inputs of a remote player.
+    Stalled,
+}
+
+pub struct RollbackSession<S, I> {
+    config: RollbackConfig,
+    //The confirmed inputs of each player, by tick.
+    inputs: Vec<BTreeMap<Tick, I>>,
+    //The first tick without a confirmed input, for each player.
+    unconfirmed: Vec<Tick>,
+    //The inputs used to simulate each tick.
+    used_inputs: BTreeMap<Tick, Vec<I>>,
+    //The state before each tick.
+    snapshots: VecDeque<(Tick, S)>,
+    current_tick: Tick,
+    rollback_from: Option<Tick>,
+    rollbacks: u64,
+}
+
+impl<S: Clone, I: Clone + PartialEq + Default> RollbackSession<S, I> {
+    pub fn new(config: RollbackConfig) -> Self {
+        let mut inputs = vec![BTreeMap::new(); config.players];
+        let mut unconfirmed = vec![0; config.players];
+        //The ticks covered by the input delay have no local input.
+        for tick in 0..config.input_delay {
+            inputs[config.local_player].insert(tick, I::default());
+        }
+        unconfirmed[config.local_player] = config.input_delay;
+        RollbackSession {
+            config,
+            inputs,
+            unconfirmed,
+            used_inputs: BTreeMap::new(),
+            snapshots: VecDeque::new(),
+            current_tick: 0,
+            rollback_from: None,
+            rollbacks: 0,
+        }
+    }
+
+    //The next tick to simulate.
+    pub fn current_tick(&self) -> Tick {
+        self.current_tick
+    }
+
+    pub fn rollbacks(&self) -> u64 {
+        self.rollbacks
+    }
+
+    //The local input of this frame. Returns the tick it is scheduled at, to send it to the other peers.
+    pub fn add_local_input(&mut self, input: I) -> Tick {
+        let tick = self.current_tick + self.config.input_delay;
+        self.confirm(self.config.local_player, tick, input);
+        tick
+    }
+
+    pub fn add_remote_input(&mut self, player: usize, tick: Tick, input: I) {
+        if player >= self.config.players || player == self.config.local_player {
+            warn!("Rollback input of an invalid player {}.", player);
+            return;
+        }
+        let mispredicted = self.used_inputs.get(&tick).map(|used| used[player] != input).unwrap_or(false);
+        if mispredicted {
+            self.rollback_from = Some(self.rollback_from.map(|from| from.min(tick)).unwrap_or(tick));
+        }
+        self.confirm(player, tick, input);
+    }
+
+    fn confirm(&mut self, player: usize, tick: Tick, input: I) {
+        self.inputs[player].insert(tick, input);
+        while self.inputs[player].contains_key(&self.unconfirmed[player]) {
+            self.unconfirmed[player] += 1;
+        }
+    }
+
+    //All the players have confirmed their inputs before this tick.
+    pub fn confirmed_tick(&self) -> Tick {
+        self.unconfirmed.iter().cloned().min().unwrap_or(0)
+    }
+
+    fn input(&self, player: usize, tick: Tick) -> I {
+        match self.inputs[player].get(&tick) {
+            Some(input) => input.clone(),
+            None => self.inputs[player].range(..tick).next_back().map(|(_, input)| input.clone()).unwrap_or_default(),
+        }
+    }
+
+    fn simulate_tick<F: FnMut(&mut S, &[I])>(&mut self, state: &mut S, simulate: &mut F) {
+        let tick = self.current_tick;
+        let inputs: Vec<I> = (0..self.config.players).map(|player| self.input(player, tick)).collect();
+        self.snapshots.push_back((tick, state.clone()));
+        simulate(state, inputs.as_slice());
+        self.used_inputs.insert(tick, inputs);
+        self.current_tick += 1;
+    }
+
+    pub fn advance<F: FnMut(&mut S, &[I])>(&mut self, state: &mut S, mut simulate: F) -> RollbackStep {
+        let mut resimulated = 0;
+        if let Some(from) = self.rollback_from.take() {
+            if let Some(index) = self.snapshots.iter().position(|&(tick, _)| tick == from) {
+                let target = self.current_tick;
+                *state = self.snapshots[index].1.clone();
+                self.snapshots.truncate(index);
+                self.current_tick = from;
+                self.rollbacks += 1;
+                trace!("Rolling back to the tick {}, {} ticks to simulate again.", from, target - from);
+                while self.current_tick < target {
+                    self.simulate_tick(state, &mut simulate);
+                    resimulated += 1;
+                }
+            } else {
+                error!("No snapshot of the tick {} to roll back to, the simulation desynchronized.", from);
+            }
+        }
+
+        let confirmed = self.confirmed_tick();
+        if self.current_tick >= confirmed + self.config.max_rollback {
+            return RollbackStep::Stalled;
+        }
+        self.simulate_tick(state, &mut simulate);
+
+        //The snapshots before the confirmed tick will never be restored.
+        while self.snapshots.front().map(|&(tick, _)| tick < confirmed).unwrap_or(false) {
+            self.snapshots.pop_front();
+        }
+        let used_inputs = self.used_inputs.split_off(&confirmed);
+        self.used_inputs = used_inputs;
+        //The last confirmed input of each player is kept, to predict the next ones.
+        for inputs in self.inputs.iter_mut() {
+            let kept = inputs.split_off(&confirmed.saturating_sub(1));
+            *inputs = kept;
+        }
+        RollbackStep::Advanced(resimulated)
+    }
+}
+
+#[cfg(test)]
+mod rollback_test {
+    use super::*;
+
+    //Each player moves its position by its input.
+    fn simulate(state: &mut Vec<i32>, inputs: &[i32]) {
+        for (position, input) in state.iter_mut().zip(inputs.iter()) {
+            *position += *input;
+        }
+    }
+
+    #[test]
+    fn rollback_resimulates_late_inputs() {
+        let mut session = RollbackSession::new(RollbackConfig {
+            players: 2,
+            local_player: 0,
+            input_delay: 1,
+            max_rollback: 4,
+        });
+        let mut state = vec![0, 0];
+        for frame in 0..3 {
+            assert_eq!(session.add_local_input(1), frame + 1);
+            assert_eq!(session.advance(&mut state, simulate), RollbackStep::Advanced(0));
+        }
+        //No remote input yet: the remote player is predicted idle.
+        assert_eq!(state, vec![2, 0]);
+
+        //The remote player moved since the tick 1.
+        session.add_remote_input(1, 0, 0);
+        session.add_remote_input(1, 1, 2);
+        session.add_local_input(1);
+        assert_eq!(session.advance(&mut state, simulate), RollbackStep::Advanced(2));
+        //Ticks 1, 2 and 3 with the input 2 repeated.
+        assert_eq!(state, vec![3, 6]);
+        assert_eq!(session.rollbacks(), 1);
+        assert_eq!(session.confirmed_tick(), 2);
+
+        //The same input as predicted: no rollback.
+        session.add_remote_input(1, 2, 2);
+        session.add_local_input(1);
+        assert_eq!(session.advance(&mut state, simulate), RollbackStep::Advanced(0));
+
+        //Too far ahead of the remote player.
+        for _ in 0..3 {
+            session.add_local_input(1);
+            session.advance(&mut state, simulate);
+        }
+        assert_eq!(session.advance(&mut state, simulate), RollbackStep::Stalled);
+    }
+}
@@ -0,0 +1,478 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SECURE CHANNELS.
+
+ Optional protection of the UDP transport, configured per server:
+ - SecurityMode::None: the packets are sent as they are.
+ - SecurityMode::Authenticated: anti-tamper. Each packet has a sequence number and an HMAC-SHA256 tag,
+   a modified or replayed packet is rejected. The content is readable.
+ - SecurityMode::Encrypted: each packet is encrypted and authenticated with ChaCha20-Poly1305.
+
+ The keys come from a handshake in the spirit of DTLS, with 3 messages:
+ 1. Client hello: an ephemeral X25519 public key and a random.
+ 2. Server hello: the same, plus the proof of the identity of the server:
+    - with a pre-shared key, an HMAC of the transcript with the key;
+    - with a certificate, the Ed25519 public key of the server and its signature of the transcript.
+      The client only accepts the public keys it trusts.
+ 3. Client finished: an HMAC of the transcript with the derived key of the client. With a pre-shared key,
+    the keys depend on the pre-shared key too, so the server knows the client has the key.
+
+ The keys are derived with HKDF-SHA256 from the X25519 shared secret, each with its own label: the key of the
+ client finished message is not a key of the packets, so a handshake message can't be replayed as a packet.
+ The sequence numbers are checked against a window of 64 packets: the packets can arrive out of order,
+ but not twice.
+ The handshake messages and the packets are byte buffers. SecureSocket (secure_socket.rs) carries them
+ over the UDP transport, the ConditionedSocket. The session service (session.rs) works over TCP and
+ doesn't use the channel.
+*/
+
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+use network_error::{NetworkError, NetworkResult};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const HANDSHAKE_MAGIC: &'static [u8; 4] = b"KSC1";
+const TAG_SIZE: usize = 16;
+const REPLAY_WINDOW: u64 = 64;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SecurityMode {
+    None,
+    Authenticated,
+    Encrypted,
+}
+
+impl SecurityMode {
+    fn id(&self) -> u8 {
+        match *self {
+            SecurityMode::None => 0,
+            SecurityMode::Authenticated => 1,
+            SecurityMode::Encrypted => 2,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Authentication {
+    PreSharedKey(Vec<u8>),
+    //The identity of the server.
+    ServerCertificate(SigningKey),
+    //The public keys of the servers trusted by the client.
+    TrustedServers(Vec<[u8; 32]>),
+}
+
+#[derive(Clone)]
+pub struct SecurityConfig {
+    pub mode: SecurityMode,
+    pub authentication: Authentication,
+}
+
+impl SecurityConfig {
+    pub fn pre_shared_key(mode: SecurityMode, key: &[u8]) -> Self {
+        SecurityConfig {
+            mode,
+            authentication: Authentication::PreSharedKey(key.to_vec()),
+        }
+    }
+
+    pub fn server_certificate(mode: SecurityMode, secret_key: [u8; 32]) -> Self {
+        SecurityConfig {
+            mode,
+            authentication: Authentication::ServerCertificate(SigningKey::from_bytes(&secret_key)),
+        }
+    }
+
+    pub fn trusted_servers(mode: SecurityMode, public_keys: Vec<[u8; 32]>) -> Self {
+        SecurityConfig {
+            mode,
+            authentication: Authentication::TrustedServers(public_keys),
+        }
+    }
+
+    //A new identity for a server: the secret key, and the public key to give to the clients.
+    pub fn generate_server_identity() -> ([u8; 32], [u8; 32]) {
+        let mut secret_key = [0u8; 32];
+        OsRng.fill_bytes(&mut secret_key);
+        (secret_key, SigningKey::from_bytes(&secret_key).verifying_key().to_bytes())
+    }
+
+    fn psk(&self) -> &[u8] {
+        match self.authentication {
+            Authentication::PreSharedKey(ref key) => key.as_slice(),
+            _ => &[],
+        }
+    }
+}
+
+fn security_error(description: &str) -> NetworkError {
+    NetworkError::SecurityError(description.to_string())
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    for part in parts {
+        mac.update(part);
+    }
+    let mut output = [0u8; 32];
+    output.copy_from_slice(mac.finalize().into_bytes().as_slice());
+    output
+}
+
+//Constant time, so the tags can't be guessed byte per byte.
+fn verify_hmac(key: &[u8], parts: &[&[u8]], tag: &[u8]) -> bool {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.verify_truncated_left(tag).is_ok()
+}
+
+fn random_bytes() -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+struct SessionKeys {
+    //The key of the client finished message.
+    finished: [u8; 32],
+    //The keys of the packets, client to server and server to client.
+    client: [u8; 32],
+    server: [u8; 32],
+}
+
+fn derive_keys(shared_secret: &[u8], psk: &[u8], transcript: &[u8]) -> SessionKeys {
+    let hkdf = Hkdf::<Sha256>::new(Some(psk), shared_secret);
+    let expand = |label: &[u8]| {
+        let mut key = [0u8; 32];
+        hkdf.expand_multi_info(&[label, transcript], &mut key).expect("32 bytes is a valid HKDF output length");
+        key
+    };
+    SessionKeys {
+        finished: expand(b"ksc1 client finished"),
+        client: expand(b"ksc1 client packets"),
+        server: expand(b"ksc1 server packets"),
+    }
+}
+
+fn read_hello(message: &[u8], mode: SecurityMode, min_size: usize) -> NetworkResult<()> {
+    if message.len() < min_size || &message[..4] != HANDSHAKE_MAGIC {
+        return Err(security_error("Invalid handshake message."));
+    }
+    if message[4] != mode.id() {
+        return Err(security_error("The peer uses another security mode."));
+    }
+    Ok(())
+}
+
+fn public_key(bytes: &[u8]) -> PublicKey {
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&bytes[..32]);
+    PublicKey::from(key)
+}
+
+//A hello: magic, mode, ephemeral public key, random.
+const HELLO_SIZE: usize = 4 + 1 + 32 + 32;
+
+pub struct ClientHandshake {
+    config: SecurityConfig,
+    secret: EphemeralSecret,
+    hello: Vec<u8>,
+}
+
+impl ClientHandshake {
+    //The handshake, and the client hello to send.
+    pub fn new(config: SecurityConfig) -> (Self, Vec<u8>) {
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let mut hello = HANDSHAKE_MAGIC.to_vec();
+        hello.push(config.mode.id());
+        hello.extend_from_slice(PublicKey::from(&secret).as_bytes());
+        hello.extend_from_slice(&random_bytes());
+        (ClientHandshake { config, secret, hello: hello.clone() }, hello)
+    }
+
+    //Check the server hello. Returns the channel, and the client finished to send.
+    pub fn finish(self, server_hello: &[u8]) -> NetworkResult<(SecureChannel, Vec<u8>)> {
+        read_hello(server_hello, self.config.mode, HELLO_SIZE + 32)?;
+        let mut transcript = self.hello.clone();
+        transcript.extend_from_slice(&server_hello[..HELLO_SIZE]);
+        let proof = &server_hello[HELLO_SIZE..];
+
+        match self.config.authentication {
+            Authentication::PreSharedKey(ref key) => {
+                if !verify_hmac(key.as_slice(), &[b"server", transcript.as_slice()], proof) {
+                    return Err(security_error("The server doesn't have the pre-shared key."));
+                }
+            },
+            Authentication::TrustedServers(ref trusted) => {
+                if proof.len() != 32 + 64 {
+                    return Err(security_error("The server didn't send a certificate."));
+                }
+                let mut key = [0u8; 32];
+                key.copy_from_slice(&proof[..32]);
+                if !trusted.contains(&key) {
+                    return Err(security_error("The certificate of the server is not trusted."));
+                }
+                let mut signature = [0u8; 64];
+                signature.copy_from_slice(&proof[32..]);
+                let verifying_key = VerifyingKey::from_bytes(&key).map_err(|_| security_error("Invalid server certificate."))?;
+                verifying_key.verify(transcript.as_slice(), &Signature::from_bytes(&signature))
+                    .map_err(|_| security_error("Invalid signature of the server."))?;
+            },
+            Authentication::ServerCertificate(_) => return Err(security_error("A server certificate is not a client configuration.")),
+        }
+
+        let shared = self.secret.diffie_hellman(&public_key(&server_hello[5..]));
+        let keys = derive_keys(shared.as_bytes(), self.config.psk(), transcript.as_slice());
+        let mut finished = HANDSHAKE_MAGIC.to_vec();
+        finished.extend_from_slice(&hmac(&keys.finished, &[b"finished", transcript.as_slice()]));
+        debug!("Secure channel established with the server ({:?}).", self.config.mode);
+        Ok((SecureChannel::new(self.config.mode, keys.client, keys.server), finished))
+    }
+}
+
+pub struct ServerHandshake {
+    mode: SecurityMode,
+    transcript: Vec<u8>,
+    keys: SessionKeys,
+}
+
+impl ServerHandshake {
+    //Answer a client hello. Returns the handshake, and the server hello to send.
+    pub fn respond(config: &SecurityConfig, client_hello: &[u8]) -> NetworkResult<(Self, Vec<u8>)> {
+        read_hello(client_hello, config.mode, HELLO_SIZE)?;
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let mut hello = HANDSHAKE_MAGIC.to_vec();
+        hello.push(config.mode.id());
+        hello.extend_from_slice(PublicKey::from(&secret).as_bytes());
+        hello.extend_from_slice(&random_bytes());
+
+        let mut transcript = client_hello[..HELLO_SIZE].to_vec();
+        transcript.extend_from_slice(hello.as_slice());
+        match config.authentication {
+            Authentication::PreSharedKey(ref key) => {
+                hello.extend_from_slice(&hmac(key.as_slice(), &[b"server", transcript.as_slice()]));
+            },
+            Authentication::ServerCertificate(ref signing_key) => {
+                hello.extend_from_slice(&signing_key.verifying_key().to_bytes());
+                hello.extend_from_slice(&signing_key.sign(transcript.as_slice()).to_bytes());
+            },
+            Authentication::TrustedServers(_) => return Err(security_error("The server has no pre-shared key and no certificate.")),
+        }
+
+        let shared = secret.diffie_hellman(&public_key(&client_hello[5..]));
+        let keys = derive_keys(shared.as_bytes(), config.psk(), transcript.as_slice());
+        Ok((ServerHandshake {
+            mode: config.mode,
+            transcript,
+            keys,
+        }, hello))
+    }
+
+    pub fn finish(self, client_finished: &[u8]) -> NetworkResult<SecureChannel> {
+        if client_finished.len() != 4 + 32 || &client_finished[..4] != HANDSHAKE_MAGIC
+            || !verify_hmac(&self.keys.finished, &[b"finished", self.transcript.as_slice()], &client_finished[4..]) {
+            return Err(security_error("The client failed the handshake."));
+        }
+        Ok(SecureChannel::new(self.mode, self.keys.server, self.keys.client))
+    }
+}
+
+pub struct SecureChannel {
+    mode: SecurityMode,
+    send_key: [u8; 32],
+    receive_key: [u8; 32],
+    send_sequence: u64,
+    //The highest received sequence, and the packets received before it.
+    highest_received: Option<u64>,
+    received_window: u64,
+}
+
+impl SecureChannel {
+    fn new(mode: SecurityMode, send_key: [u8; 32], receive_key: [u8; 32]) -> Self {
+        SecureChannel {
+            mode,
+            send_key,
+            receive_key,
+            send_sequence: 0,
+            highest_received: None,
+            received_window: 0,
+        }
+    }
+
+    //No protection.
+    pub fn plain() -> Self {
+        SecureChannel::new(SecurityMode::None, [0; 32], [0; 32])
+    }
+
+    pub fn mode(&self) -> SecurityMode {
+        self.mode
+    }
+
+    fn nonce(sequence: u64) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&sequence.to_le_bytes());
+        nonce
+    }
+
+    pub fn seal(&mut self, payload: &[u8]) -> Vec<u8> {
+        if self.mode == SecurityMode::None {
+            return payload.to_vec();
+        }
+        let sequence = self.send_sequence;
+        self.send_sequence += 1;
+        let header = sequence.to_le_bytes();
+        let mut packet = header.to_vec();
+        match self.mode {
+            SecurityMode::Encrypted => {
+                let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.send_key));
+                let sealed = cipher.encrypt(Nonce::from_slice(&SecureChannel::nonce(sequence)), Payload {
+                    msg: payload,
+                    aad: &header,
+                }).expect("ChaCha20-Poly1305 encryption can't fail on a packet");
+                packet.extend(sealed);
+            },
+            _ => {
+                packet.extend_from_slice(payload);
+                packet.extend_from_slice(&hmac(&self.send_key, &[&header, payload])[..TAG_SIZE]);
+            },
+        }
+        packet
+    }
+
+    fn accept_sequence(&mut self, sequence: u64) -> bool {
+        match self.highest_received {
+            None => {
+                self.highest_received = Some(sequence);
+                self.received_window = 1;
+                true
+            },
+            Some(highest) if sequence > highest => {
+                let shift = sequence - highest;
+                self.received_window = if shift >= REPLAY_WINDOW {1} else {(self.received_window << shift) | 1};
+                self.highest_received = Some(sequence);
+                true
+            },
+            Some(highest) => {
+                let age = highest - sequence;
+                if age >= REPLAY_WINDOW || self.received_window & (1 << age) != 0 {
+                    return false;
+                }
+                self.received_window |= 1 << age;
+                true
+            },
+        }
+    }
+
+    pub fn open(&mut self, packet: &[u8]) -> NetworkResult<Vec<u8>> {
+        if self.mode == SecurityMode::None {
+            return Ok(packet.to_vec());
+        }
+        if packet.len() < 8 + TAG_SIZE {
+            return Err(security_error("The packet is too short."));
+        }
+        let (header, body) = packet.split_at(8);
+        let mut sequence = [0u8; 8];
+        sequence.copy_from_slice(header);
+        let sequence = u64::from_le_bytes(sequence);
+
+        let payload = match self.mode {
+            SecurityMode::Encrypted => {
+                let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.receive_key));
+                cipher.decrypt(Nonce::from_slice(&SecureChannel::nonce(sequence)), Payload {
+                    msg: body,
+                    aad: header,
+                }).map_err(|_| security_error("The packet was modified."))?
+            },
+            _ => {
+                let (payload, tag) = body.split_at(body.len() - TAG_SIZE);
+                if !verify_hmac(&self.receive_key, &[header, payload], tag) {
+                    return Err(security_error("The packet was modified."));
+                }
+                payload.to_vec()
+            },
+        };
+        //Checked after the tag, so a forged sequence can't move the window.
+        if !self.accept_sequence(sequence) {
+            return Err(security_error("The packet was replayed."));
+        }
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod secure_channel_test {
+    use super::*;
+
+    fn connect(client: SecurityConfig, server: &SecurityConfig) -> NetworkResult<(SecureChannel, SecureChannel)> {
+        let (client, client_hello) = ClientHandshake::new(client);
+        let (server, server_hello) = ServerHandshake::respond(server, client_hello.as_slice())?;
+        let (client, finished) = client.finish(server_hello.as_slice())?;
+        Ok((client, server.finish(finished.as_slice())?))
+    }
+
+    #[test]
+    fn secure_channel_pre_shared_key() {
+        let server = SecurityConfig::pre_shared_key(SecurityMode::Encrypted, b"server password");
+        let (mut client, mut server_channel) = connect(SecurityConfig::pre_shared_key(SecurityMode::Encrypted, b"server password"), &server).unwrap();
+        let packet = client.seal(b"move forward");
+        assert!(!packet.windows(4).any(|bytes| bytes == b"move"));
+        assert_eq!(server_channel.open(packet.as_slice()).unwrap(), b"move forward");
+        assert!(server_channel.open(packet.as_slice()).is_err());
+        let reply = server_channel.seal(b"ok");
+        assert_eq!(client.open(reply.as_slice()).unwrap(), b"ok");
+
+        assert!(connect(SecurityConfig::pre_shared_key(SecurityMode::Encrypted, b"guess"), &server).is_err());
+        assert!(connect(SecurityConfig::pre_shared_key(SecurityMode::Authenticated, b"server password"), &server).is_err());
+    }
+
+    #[test]
+    fn secure_channel_certificate_and_anti_tamper() {
+        let (secret_key, public_key) = SecurityConfig::generate_server_identity();
+        let server = SecurityConfig::server_certificate(SecurityMode::Authenticated, secret_key);
+        assert!(connect(SecurityConfig::trusted_servers(SecurityMode::Authenticated, vec![[7; 32]]), &server).is_err());
+        let (mut client, mut server_channel) = connect(SecurityConfig::trusted_servers(SecurityMode::Authenticated, vec![public_key]), &server).unwrap();
+
+        let first = client.seal(b"first");
+        let second = client.seal(b"second");
+        //Out of order, but each packet once.
+        assert_eq!(server_channel.open(second.as_slice()).unwrap(), b"second");
+        assert_eq!(server_channel.open(first.as_slice()).unwrap(), b"first");
+
+        let mut tampered = client.seal(b"damage 10");
+        tampered[15] = b'9';
+        assert!(server_channel.open(tampered.as_slice()).is_err());
+    }
+
+    #[test]
+    fn secure_channel_finished_is_not_a_packet() {
+        let config = SecurityConfig::pre_shared_key(SecurityMode::Authenticated, b"server password");
+        let (client, client_hello) = ClientHandshake::new(config.clone());
+        let (server, server_hello) = ServerHandshake::respond(&config, client_hello.as_slice()).unwrap();
+        let (mut client, finished) = client.finish(server_hello.as_slice()).unwrap();
+        let mut server = server.finish(finished.as_slice()).unwrap();
+
+        //"finished" is 8 bytes, read as a sequence number: the finished message would be a valid packet
+        //if its key was the key of the packets.
+        let mut forged = b"finished".to_vec();
+        forged.extend_from_slice(&client_hello[..HELLO_SIZE]);
+        forged.extend_from_slice(&server_hello[..HELLO_SIZE]);
+        forged.extend_from_slice(&finished[4..4 + TAG_SIZE]);
+        assert!(server.open(forged.as_slice()).is_err());
+        let packet = client.seal(b"still alive");
+        assert_eq!(server.open(packet.as_slice()).unwrap(), b"still alive");
+    }
+}
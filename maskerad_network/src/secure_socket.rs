@@ -0,0 +1,353 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SECURE SOCKETS.
+
+ The UDP transport protected by the secure channels: a ConditionedSocket whose peers go through the
+ handshake of secure_channel.rs before exchanging packets. Each datagram starts with its kind:
+ client hello, server hello, client finished, or a packet sealed by the channel of the peer.
+
+ A server accepts the handshake of any address, a client connects to a server with connect(). The
+ datagrams can be lost:
+ - the client sends its hello again every HANDSHAKE_RESEND_MS, until HANDSHAKE_TIMEOUT_MS,
+ - the server answers a hello it already answered with the same server hello,
+ - the server answers a packet of a client which didn't finish the handshake with its server hello,
+   and the client sends its finished message again.
+ A handshake which doesn't finish in HANDSHAKE_TIMEOUT_MS is forgotten by the server.
+*/
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use conditioner::{ConditionedSocket, ConditionerSettings};
+use secure_channel::{ClientHandshake, SecureChannel, SecurityConfig, ServerHandshake};
+use network_error::{NetworkError, NetworkResult};
+
+pub const HANDSHAKE_RESEND_MS: u64 = 200;
+pub const HANDSHAKE_TIMEOUT_MS: u64 = 5000;
+
+const CLIENT_HELLO: u8 = 0;
+const SERVER_HELLO: u8 = 1;
+const CLIENT_FINISHED: u8 = 2;
+const PACKET: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecureSocketEvent {
+    Connected(SocketAddr),
+    Received(SocketAddr, Vec<u8>),
+    //The handshake with the peer failed or timed out.
+    Failed(SocketAddr, String),
+}
+
+enum Peer {
+    Connecting {
+        handshake: ClientHandshake,
+        hello: Vec<u8>,
+        started: Instant,
+        sent: Instant,
+    },
+    Accepting {
+        handshake: ServerHandshake,
+        client_hello: Vec<u8>,
+        hello: Vec<u8>,
+        started: Instant,
+    },
+    //The client keeps the server hello and its finished message, the server may ask for them again.
+    Connected {
+        channel: SecureChannel,
+        server_hello: Option<Vec<u8>>,
+        finished: Vec<u8>,
+    },
+}
+
+fn datagram(kind: u8, message: &[u8]) -> Vec<u8> {
+    let mut datagram = Vec::with_capacity(message.len() + 1);
+    datagram.push(kind);
+    datagram.extend_from_slice(message);
+    datagram
+}
+
+pub struct SecureSocket {
+    socket: ConditionedSocket,
+    config: SecurityConfig,
+    peers: HashMap<SocketAddr, Peer>,
+}
+
+impl SecureSocket {
+    pub fn bind<A: ToSocketAddrs>(address: A, config: SecurityConfig, settings: ConditionerSettings) -> NetworkResult<Self> {
+        debug!("Binding a secure socket ({:?}).", config.mode);
+        Ok(SecureSocket {
+            socket: ConditionedSocket::bind(address, settings)?,
+            config,
+            peers: HashMap::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> NetworkResult<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn conditioned_socket(&mut self) -> &mut ConditionedSocket {
+        &mut self.socket
+    }
+
+    //Starts the handshake with a server. SecureSocketEvent::Connected is returned by update() once it is over.
+    pub fn connect(&mut self, address: SocketAddr) -> NetworkResult<()> {
+        debug!("Connecting to the secure socket {}.", address);
+        let (handshake, hello) = ClientHandshake::new(self.config.clone());
+        self.socket.send_to(datagram(CLIENT_HELLO, hello.as_slice()).as_slice(), address)?;
+        let now = Instant::now();
+        self.peers.insert(address, Peer::Connecting {
+            handshake,
+            hello,
+            started: now,
+            sent: now,
+        });
+        Ok(())
+    }
+
+    pub fn is_connected(&self, address: SocketAddr) -> bool {
+        matches!(self.peers.get(&address), Some(&Peer::Connected { .. }))
+    }
+
+    pub fn disconnect(&mut self, address: SocketAddr) {
+        self.peers.remove(&address);
+    }
+
+    pub fn send_to(&mut self, payload: &[u8], address: SocketAddr) -> NetworkResult<()> {
+        let packet = match self.peers.get_mut(&address) {
+            Some(&mut Peer::Connected { ref mut channel, .. }) => channel.seal(payload),
+            _ => return Err(NetworkError::SecurityError(format!("The handshake with {} is not over.", address))),
+        };
+        self.socket.send_to(datagram(PACKET, packet.as_slice()).as_slice(), address)
+    }
+
+    //Once per frame: receives the datagrams, and goes on with the handshakes.
+    pub fn update(&mut self) -> NetworkResult<Vec<SecureSocketEvent>> {
+        let mut events = Vec::new();
+        loop {
+            match self.socket.recv_from() {
+                Ok((address, datagram)) => {
+                    if let Some(event) = self.receive(address, datagram.as_slice())? {
+                        events.push(event);
+                    }
+                },
+                Err(ref error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => return Err(NetworkError::from(error)),
+            }
+        }
+
+        let now = Instant::now();
+        let timeout = Duration::from_millis(HANDSHAKE_TIMEOUT_MS);
+        let mut resend = Vec::new();
+        let mut failed = Vec::new();
+        for (address, peer) in self.peers.iter_mut() {
+            match *peer {
+                Peer::Connecting { ref hello, started, ref mut sent, .. } => {
+                    if now.duration_since(started) > timeout {
+                        failed.push(*address);
+                    } else if now.duration_since(*sent) > Duration::from_millis(HANDSHAKE_RESEND_MS) {
+                        *sent = now;
+                        resend.push((*address, datagram(CLIENT_HELLO, hello.as_slice())));
+                    }
+                },
+                Peer::Accepting { started, .. } if now.duration_since(started) > timeout => failed.push(*address),
+                _ => {},
+            }
+        }
+        for address in failed {
+            if let Some(Peer::Connecting { .. }) = self.peers.remove(&address) {
+                warn!("The secure handshake with {} timed out.", address);
+                events.push(SecureSocketEvent::Failed(address, String::from("The handshake timed out.")));
+            }
+        }
+        for (address, datagram) in resend {
+            self.socket.send_to(datagram.as_slice(), address)?;
+        }
+        Ok(events)
+    }
+
+    fn receive(&mut self, address: SocketAddr, received: &[u8]) -> NetworkResult<Option<SecureSocketEvent>> {
+        let (kind, message) = match received.split_first() {
+            Some((kind, message)) => (*kind, message),
+            None => return Ok(None),
+        };
+        let peer = self.peers.remove(&address);
+        let (peer, reply, event) = match (kind, peer) {
+            (CLIENT_HELLO, Some(Peer::Accepting { handshake, client_hello, hello, started })) => {
+                //The server hello was lost, or a new hello from the same address.
+                if client_hello.as_slice() == message {
+                    let reply = datagram(SERVER_HELLO, hello.as_slice());
+                    (Some(Peer::Accepting { handshake, client_hello, hello, started }), Some(reply), None)
+                } else {
+                    self.accept(message)
+                }
+            },
+            (CLIENT_HELLO, Some(Peer::Connecting { .. })) | (CLIENT_HELLO, None) => self.accept(message),
+            (SERVER_HELLO, Some(Peer::Connecting { handshake, .. })) => match handshake.finish(message) {
+                Ok((channel, finished)) => {
+                    debug!("Secure connection established with {}.", address);
+                    let reply = datagram(CLIENT_FINISHED, finished.as_slice());
+                    (Some(Peer::Connected { channel, server_hello: Some(message.to_vec()), finished }), Some(reply), Some(SecureSocketEvent::Connected(address)))
+                },
+                Err(error) => {
+                    warn!("The secure handshake with {} failed: {}", address, error);
+                    (None, None, Some(SecureSocketEvent::Failed(address, error.to_string())))
+                },
+            },
+            (SERVER_HELLO, Some(Peer::Connected { channel, server_hello, finished })) => {
+                //The server didn't receive the finished message.
+                let reply = if server_hello.as_ref().map(|hello| hello.as_slice() == message).unwrap_or(false) {
+                    Some(datagram(CLIENT_FINISHED, finished.as_slice()))
+                } else {
+                    None
+                };
+                (Some(Peer::Connected { channel, server_hello, finished }), reply, None)
+            },
+            (CLIENT_FINISHED, Some(Peer::Accepting { handshake, .. })) => match handshake.finish(message) {
+                Ok(channel) => {
+                    debug!("Secure connection accepted from {}.", address);
+                    (Some(Peer::Connected { channel, server_hello: None, finished: Vec::new() }), None, Some(SecureSocketEvent::Connected(address)))
+                },
+                Err(error) => {
+                    debug!("The secure handshake of {} failed: {}", address, error);
+                    (None, None, None)
+                },
+            },
+            (PACKET, Some(Peer::Connected { mut channel, server_hello, finished })) => {
+                let event = match channel.open(message) {
+                    Ok(payload) => Some(SecureSocketEvent::Received(address, payload)),
+                    Err(error) => {
+                        trace!("Packet from {} dropped: {}", address, error);
+                        None
+                    },
+                };
+                (Some(Peer::Connected { channel, server_hello, finished }), None, event)
+            },
+            (PACKET, Some(Peer::Accepting { handshake, client_hello, hello, started })) => {
+                let reply = datagram(SERVER_HELLO, hello.as_slice());
+                (Some(Peer::Accepting { handshake, client_hello, hello, started }), Some(reply), None)
+            },
+            //Unexpected or unknown datagrams are dropped.
+            (_, peer) => (peer, None, None),
+        };
+        if let Some(peer) = peer {
+            self.peers.insert(address, peer);
+        }
+        if let Some(reply) = reply {
+            self.socket.send_to(reply.as_slice(), address)?;
+        }
+        Ok(event)
+    }
+
+    fn accept(&mut self, client_hello: &[u8]) -> (Option<Peer>, Option<Vec<u8>>, Option<SecureSocketEvent>) {
+        match ServerHandshake::respond(&self.config, client_hello) {
+            Ok((handshake, hello)) => {
+                let reply = datagram(SERVER_HELLO, hello.as_slice());
+                (Some(Peer::Accepting {
+                    handshake,
+                    client_hello: client_hello.to_vec(),
+                    hello,
+                    started: Instant::now(),
+                }), Some(reply), None)
+            },
+            Err(error) => {
+                debug!("A client hello was refused: {}", error);
+                (None, None, None)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod secure_socket_test {
+    use super::*;
+    use secure_channel::SecurityMode;
+    use std::thread;
+
+    fn local(config: SecurityConfig, settings: ConditionerSettings) -> (SecureSocket, SocketAddr) {
+        let socket = SecureSocket::bind("127.0.0.1:0", config, settings).unwrap();
+        let address = socket.local_addr().unwrap();
+        (socket, address)
+    }
+
+    //Updates both sockets until the condition holds, for at most 10 seconds.
+    fn exchange<F: FnMut(&[SecureSocketEvent]) -> bool>(client: &mut SecureSocket, server: &mut SecureSocket, mut done: F) -> Vec<SecureSocketEvent> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut events = Vec::new();
+        while Instant::now() < deadline {
+            events.extend(client.update().unwrap());
+            events.extend(server.update().unwrap());
+            if done(events.as_slice()) {
+                return events;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("The secure sockets didn't reach the expected state: {:?}.", events);
+    }
+
+    fn connected(events: &[SecureSocketEvent]) -> usize {
+        events.iter().filter(|event| matches!(event, SecureSocketEvent::Connected(_))).count()
+    }
+
+    #[test]
+    fn secure_socket_handshake_and_packets() {
+        let config = SecurityConfig::pre_shared_key(SecurityMode::Encrypted, b"server password");
+        let (mut server, server_address) = local(config.clone(), ConditionerSettings::default());
+        let (mut client, client_address) = local(config, ConditionerSettings::default());
+        assert!(client.send_to(b"too early", server_address).is_err());
+
+        client.connect(server_address).unwrap();
+        let events = exchange(&mut client, &mut server, |events| connected(events) == 2);
+        assert!(events.contains(&SecureSocketEvent::Connected(server_address)));
+        assert!(events.contains(&SecureSocketEvent::Connected(client_address)));
+        assert!(client.is_connected(server_address) && server.is_connected(client_address));
+
+        client.send_to(b"move forward", server_address).unwrap();
+        let events = exchange(&mut client, &mut server, |events| !events.is_empty());
+        assert_eq!(events, vec![SecureSocketEvent::Received(client_address, b"move forward".to_vec())]);
+        server.send_to(b"ok", client_address).unwrap();
+        let events = exchange(&mut client, &mut server, |events| !events.is_empty());
+        assert_eq!(events, vec![SecureSocketEvent::Received(server_address, b"ok".to_vec())]);
+
+        let (mut intruder, _) = local(SecurityConfig::pre_shared_key(SecurityMode::Encrypted, b"guess"), ConditionerSettings::default());
+        intruder.connect(server_address).unwrap();
+        let events = exchange(&mut intruder, &mut server, |events| !events.is_empty());
+        assert!(matches!(events[0], SecureSocketEvent::Failed(address, _) if address == server_address));
+    }
+
+    #[test]
+    fn secure_socket_lost_finished_message() {
+        let config = SecurityConfig::pre_shared_key(SecurityMode::Authenticated, b"server password");
+        let (mut server, server_address) = local(config.clone(), ConditionerSettings::default());
+        let (mut client, client_address) = local(config, ConditionerSettings::default());
+        client.connect(server_address).unwrap();
+
+        //The server answers the hello, the client finishes, and its finished message is lost.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !client.is_connected(server_address) {
+            assert!(Instant::now() < deadline);
+            server.update().unwrap();
+            client.update().unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+        while server.conditioned_socket().recv_from().is_err() {
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!server.is_connected(client_address));
+
+        //The first packet makes the server send its hello again, and the client its finished message.
+        client.send_to(b"lost", server_address).unwrap();
+        exchange(&mut client, &mut server, |events| connected(events) == 1);
+        client.send_to(b"delivered", server_address).unwrap();
+        let events = exchange(&mut client, &mut server, |events| !events.is_empty());
+        assert_eq!(events, vec![SecureSocketEvent::Received(client_address, b"delivered".to_vec())]);
+    }
+}
@@ -0,0 +1,769 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SESSIONS.
+
+ The gameplay code creates, joins and leaves sessions through the VSessionService trait. The platform
+ matchmaking backends implement it, the DirectSessionService is the reference implementation: direct
+ connections by IP address, on a LAN or with a forwarded port.
+
+ A session has a host, the members, and properties (game mode, map...) set by the host.
+ When the host leaves or drops, the remaining members choose a new host with the HostMigration hook
+ (by default, the oldest member), and the session goes on.
+
+ The service is polled once per frame with update(), which returns the events of the session. It never
+ blocks: the messages wait in a buffer until the socket accepts them, and the connection to a new host
+ is made by a background thread.
+
+ The connections are closed when they misbehave: a message longer than MAX_LINE_LENGTH, a peer which
+ doesn't read its messages, a connection which doesn't join the session in JOIN_TIMEOUT_MS.
+ A member rejoining the new host after a migration proves who it is with its rejoin token: the members
+ only know the SHA-256 of the token of the others (rejoin_key).
+*/
+
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use rand_core::{OsRng, RngCore};
+use serde_json;
+use sha2::{Digest, Sha256};
+use network_error::{NetworkError, NetworkResult};
+
+pub type MemberId = u64;
+
+pub const DEFAULT_SESSION_PORT: u16 = 27015;
+const CONNECT_TIMEOUT_MS: u64 = 500;
+const MIGRATION_TIMEOUT_MS: u64 = 5000;
+const JOIN_TIMEOUT_MS: u64 = 5000;
+pub const MAX_LINE_LENGTH: usize = 64 * 1024;
+//The bytes waiting to be sent to a peer.
+const MAX_OUTGOING: usize = 1024 * 1024;
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+fn rejoin_key(token: &str) -> String {
+    to_hex(Sha256::digest(token.as_bytes()).as_slice())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionMember {
+    pub id: MemberId,
+    pub name: String,
+    //The IP address of the member, as seen by the host.
+    pub address: String,
+    pub is_host: bool,
+    //The SHA-256 of the rejoin token of the member.
+    #[serde(default)]
+    pub rejoin_key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub name: String,
+    pub max_members: usize,
+    pub properties: BTreeMap<String, String>,
+}
+
+impl SessionInfo {
+    pub fn new<S: Into<String>>(name: S, max_members: usize) -> Self {
+        SessionInfo {
+            name: name.into(),
+            max_members,
+            properties: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionEvent {
+    Joined(SessionInfo),
+    MemberJoined(SessionMember),
+    MemberLeft(MemberId),
+    PropertyChanged(String, String),
+    HostMigrated(MemberId),
+    Closed(String),
+}
+
+//Chooses the new host among the remaining members.
+pub trait HostMigration {
+    fn choose_host(&self, members: &[SessionMember]) -> Option<MemberId>;
+}
+
+pub struct OldestMember;
+
+impl HostMigration for OldestMember {
+    fn choose_host(&self, members: &[SessionMember]) -> Option<MemberId> {
+        members.iter().map(|member| member.id).min()
+    }
+}
+
+pub trait VSessionService {
+    fn create_session(&mut self, info: SessionInfo, member_name: &str) -> NetworkResult<()>;
+
+    //The address format depends on the service: "ip:port" for direct connections, a lobby id for a platform...
+    fn join_session(&mut self, address: &str, member_name: &str) -> NetworkResult<()>;
+
+    fn leave_session(&mut self);
+
+    fn session(&self) -> Option<&SessionInfo>;
+
+    fn members(&self) -> &[SessionMember];
+
+    fn local_member(&self) -> Option<MemberId>;
+
+    fn is_host(&self) -> bool;
+
+    //Only the host sets the properties.
+    fn set_property(&mut self, key: &str, value: &str) -> NetworkResult<()>;
+
+    fn set_host_migration(&mut self, migration: Box<HostMigration>);
+
+    fn update(&mut self) -> Vec<SessionEvent>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum SessionMessage {
+    //member and token are set when rejoining after a host migration.
+    Join { name: String, rejoin_key: String, member: Option<MemberId>, token: Option<String> },
+    Welcome { member: MemberId, info: SessionInfo, members: Vec<SessionMember> },
+    Rejected { reason: String },
+    MemberJoined(SessionMember),
+    MemberLeft(MemberId),
+    Property { key: String, value: String },
+    Leave,
+}
+
+//Newline-delimited JSON messages over a non-blocking TCP stream.
+struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    outgoing: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> NetworkResult<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            buffer: Vec::new(),
+            outgoing: Vec::new(),
+        })
+    }
+
+    fn send(&mut self, message: &SessionMessage) -> NetworkResult<()> {
+        self.outgoing.extend(serde_json::to_vec(message)?);
+        self.outgoing.push(b'\n');
+        self.flush()
+    }
+
+    //Writes what the socket accepts without blocking, the rest is written by the next flushes.
+    fn flush(&mut self) -> NetworkResult<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(self.outgoing.as_slice()) {
+                Ok(0) => return Err(NetworkError::SessionError(String::from("The connection is closed."))),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                },
+                Err(ref error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => return Err(NetworkError::from(error)),
+            }
+        }
+        if self.outgoing.len() > MAX_OUTGOING {
+            return Err(NetworkError::SessionError(String::from("The peer doesn't read its messages.")));
+        }
+        Ok(())
+    }
+
+    //The received messages, an error when the connection is closed.
+    fn receive(&mut self) -> NetworkResult<Vec<SessionMessage>> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(NetworkError::SessionError(String::from("The connection is closed."))),
+                Ok(read) => {
+                    self.buffer.extend_from_slice(&chunk[..read]);
+                    let line_start = self.buffer.iter().rposition(|byte| *byte == b'\n').map(|end| end + 1).unwrap_or(0);
+                    if self.buffer.len() - line_start > MAX_LINE_LENGTH {
+                        return Err(NetworkError::SessionError(format!("A message is longer than {} bytes.", MAX_LINE_LENGTH)));
+                    }
+                },
+                Err(ref error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => return Err(NetworkError::from(error)),
+            }
+        }
+        let mut messages = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..end + 1).collect();
+            messages.push(serde_json::from_slice(&line[..end])?);
+        }
+        Ok(messages)
+    }
+}
+
+enum Role {
+    Idle,
+    Host {
+        listener: TcpListener,
+        //The connections which haven't joined yet, with the time they were accepted.
+        pending: Vec<(Connection, Instant)>,
+        clients: Vec<(MemberId, Connection)>,
+    },
+    Client {
+        connection: Connection,
+    },
+    //Waiting for the new host to listen.
+    Migrating {
+        connecting: Receiver<io::Result<TcpStream>>,
+    },
+}
+
+pub struct DirectSessionService {
+    role: Role,
+    port: u16,
+    info: Option<SessionInfo>,
+    members: Vec<SessionMember>,
+    local: Option<MemberId>,
+    local_name: String,
+    //Proves who the local member is, when it rejoins after a host migration.
+    rejoin_token: String,
+    next_member: MemberId,
+    migration: Box<HostMigration>,
+    events: Vec<SessionEvent>,
+}
+
+impl Default for DirectSessionService {
+    fn default() -> Self {
+        DirectSessionService::new(DEFAULT_SESSION_PORT)
+    }
+}
+
+impl DirectSessionService {
+    //The port the host listens on, 0 to let the system choose one (see local_addr).
+    pub fn new(port: u16) -> Self {
+        DirectSessionService {
+            role: Role::Idle,
+            port,
+            info: None,
+            members: Vec::new(),
+            local: None,
+            local_name: String::new(),
+            rejoin_token: String::new(),
+            next_member: 1,
+            migration: Box::new(OldestMember),
+            events: Vec::new(),
+        }
+    }
+
+    //The address the host listens on, None if the service doesn't host a session.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self.role {
+            Role::Host { ref listener, .. } => listener.local_addr().ok(),
+            _ => None,
+        }
+    }
+
+    fn reset(&mut self, reason: &str) {
+        debug!("Leaving the session: {}.", reason);
+        self.role = Role::Idle;
+        self.info = None;
+        self.members.clear();
+        self.local = None;
+        self.events.push(SessionEvent::Closed(reason.to_string()));
+    }
+
+    fn listen(&mut self) -> NetworkResult<()> {
+        let listener = TcpListener::bind(("0.0.0.0", self.port))?;
+        listener.set_nonblocking(true)?;
+        //The port chosen by the system, the members migrate the session to the same port.
+        self.port = listener.local_addr()?.port();
+        self.role = Role::Host {
+            listener,
+            pending: Vec::new(),
+            clients: Vec::new(),
+        };
+        Ok(())
+    }
+
+    fn join_message(&self, rejoining: bool) -> SessionMessage {
+        SessionMessage::Join {
+            name: self.local_name.clone(),
+            rejoin_key: rejoin_key(self.rejoin_token.as_str()),
+            member: if rejoining {self.local} else {None},
+            token: if rejoining {Some(self.rejoin_token.clone())} else {None},
+        }
+    }
+
+    fn remove_member(&mut self, member: MemberId) {
+        self.members.retain(|session_member| session_member.id != member);
+        self.events.push(SessionEvent::MemberLeft(member));
+    }
+
+    fn update_host(&mut self) {
+        let (accepted, mut pending, mut clients) = match self.role {
+            Role::Host { ref listener, ref mut pending, ref mut clients } => {
+                let mut accepted = Vec::new();
+                loop {
+                    match listener.accept() {
+                        Ok((stream, address)) => accepted.push((stream, address)),
+                        Err(ref error) if error.kind() == ErrorKind::WouldBlock => break,
+                        Err(error) => {
+                            warn!("Could not accept a session connection: {}.", error);
+                            break;
+                        },
+                    }
+                }
+                (accepted, ::std::mem::replace(pending, Vec::new()), ::std::mem::replace(clients, Vec::new()))
+            },
+            _ => return,
+        };
+        for (stream, address) in accepted {
+            trace!("Session connection from {}.", address);
+            match Connection::new(stream) {
+                Ok(connection) => pending.push((connection, Instant::now())),
+                Err(error) => warn!("Could not accept a session connection: {}.", error),
+            }
+        }
+
+        let mut broadcasts = Vec::new();
+        let mut joined = Vec::new();
+        for (mut connection, accepted) in pending.drain(..) {
+            let join = match connection.flush().and_then(|_| connection.receive()) {
+                Ok(messages) => messages.into_iter().find(|message| match message {
+                    &SessionMessage::Join { .. } => true,
+                    _ => false,
+                }),
+                Err(_) => continue,
+            };
+            let (name, key, rejoining, token) = match join {
+                Some(SessionMessage::Join { name, rejoin_key, member, token }) => (name, rejoin_key, member, token),
+                _ if accepted.elapsed() > Duration::from_millis(JOIN_TIMEOUT_MS) => {
+                    debug!("A session connection didn't join in time, it is closed.");
+                    continue;
+                },
+                _ => {
+                    joined.push((None, connection, accepted));
+                    continue;
+                },
+            };
+            let max_members = self.info.as_ref().map(|info| info.max_members).unwrap_or(0);
+            let known = match (rejoining, token) {
+                (Some(id), Some(token)) => self.members.iter().any(|member| member.id == id && member.rejoin_key == rejoin_key(token.as_str())),
+                _ => false,
+            };
+            if rejoining.is_some() && !known {
+                warn!("A connection tried to rejoin the session as the member {:?} without its token.", rejoining);
+                let _ = connection.send(&SessionMessage::Rejected { reason: String::from("Invalid rejoin token.") });
+                continue;
+            }
+            if !known && self.members.len() >= max_members {
+                let _ = connection.send(&SessionMessage::Rejected { reason: String::from("The session is full.") });
+                continue;
+            }
+            let id = match rejoining {
+                Some(id) if known => id,
+                _ => {
+                    self.next_member = self.next_member.max(self.members.iter().map(|member| member.id + 1).max().unwrap_or(1));
+                    let id = self.next_member;
+                    self.next_member += 1;
+                    id
+                },
+            };
+            let member = SessionMember {
+                id,
+                name,
+                address: connection.stream.peer_addr().map(|address| address.ip().to_string()).unwrap_or_default(),
+                is_host: false,
+                rejoin_key: key,
+            };
+            if !known {
+                self.members.push(member.clone());
+                self.events.push(SessionEvent::MemberJoined(member.clone()));
+                broadcasts.push(SessionMessage::MemberJoined(member));
+            }
+            let welcome = SessionMessage::Welcome {
+                member: id,
+                info: self.info.clone().unwrap_or_else(|| SessionInfo::new("", 0)),
+                members: self.members.clone(),
+            };
+            if connection.send(&welcome).is_ok() {
+                joined.push((Some(id), connection, accepted));
+            }
+        }
+
+        let mut left = Vec::new();
+        for &mut (id, ref mut connection) in clients.iter_mut() {
+            match connection.flush().and_then(|_| connection.receive()) {
+                Ok(messages) => {
+                    if messages.contains(&SessionMessage::Leave) {
+                        left.push(id);
+                    }
+                },
+                Err(_) => left.push(id),
+            }
+        }
+        clients.retain(|&(id, _)| !left.contains(&id));
+        for id in left {
+            self.remove_member(id);
+            broadcasts.push(SessionMessage::MemberLeft(id));
+        }
+        for message in broadcasts.iter() {
+            for &mut (_, ref mut connection) in clients.iter_mut() {
+                let _ = connection.send(message);
+            }
+        }
+        for (id, connection, accepted) in joined {
+            match id {
+                Some(id) => clients.push((id, connection)),
+                None => pending.push((connection, accepted)),
+            }
+        }
+        if let Role::Host { pending: ref mut host_pending, clients: ref mut host_clients, .. } = self.role {
+            *host_pending = pending;
+            *host_clients = clients;
+        }
+    }
+
+    fn apply(&mut self, message: SessionMessage, host_address: &str) {
+        match message {
+            SessionMessage::Welcome { member, info, mut members } => {
+                for session_member in members.iter_mut().filter(|session_member| session_member.is_host) {
+                    session_member.address = host_address.to_string();
+                }
+                if self.local.is_none() {
+                    self.events.push(SessionEvent::Joined(info.clone()));
+                }
+                self.local = Some(member);
+                self.info = Some(info);
+                self.members = members;
+            },
+            SessionMessage::MemberJoined(member) => {
+                self.members.push(member.clone());
+                self.events.push(SessionEvent::MemberJoined(member));
+            },
+            SessionMessage::MemberLeft(member) => self.remove_member(member),
+            SessionMessage::Property { key, value } => {
+                if let Some(ref mut info) = self.info {
+                    info.properties.insert(key.clone(), value.clone());
+                }
+                self.events.push(SessionEvent::PropertyChanged(key, value));
+            },
+            SessionMessage::Rejected { reason } => self.reset(reason.as_str()),
+            SessionMessage::Join { .. } | SessionMessage::Leave => {},
+        }
+    }
+
+    fn update_client(&mut self) {
+        let (received, host_address) = match self.role {
+            Role::Client { ref mut connection } => {
+                (connection.flush().and_then(|_| connection.receive()), connection.stream.peer_addr().map(|address| address.ip().to_string()).unwrap_or_default())
+            },
+            _ => return,
+        };
+        match received {
+            Ok(messages) => {
+                for message in messages {
+                    self.apply(message, host_address.as_str());
+                }
+            },
+            Err(_) if self.local.is_some() => self.migrate_host(),
+            Err(_) => self.reset("could not join the session"),
+        }
+    }
+
+    //The host is gone.
+    fn migrate_host(&mut self) {
+        let old_hosts: Vec<MemberId> = self.members.iter().filter(|member| member.is_host).map(|member| member.id).collect();
+        for host in old_hosts {
+            self.remove_member(host);
+        }
+        let new_host = match self.migration.choose_host(self.members.as_slice()) {
+            Some(new_host) => new_host,
+            None => return self.reset("the host left"),
+        };
+        debug!("The host left the session, the new host is the member {}.", new_host);
+        for member in self.members.iter_mut() {
+            member.is_host = member.id == new_host;
+        }
+        self.events.push(SessionEvent::HostMigrated(new_host));
+
+        if Some(new_host) == self.local {
+            if let Err(error) = self.listen() {
+                error!("Could not host the session after the host migration: {}.", error);
+                self.reset("the host migration failed");
+            }
+            return;
+        }
+        let address = self.members.iter()
+            .find(|member| member.id == new_host)
+            .and_then(|member| (member.address.as_str(), self.port).to_socket_addrs().ok())
+            .and_then(|mut addresses| addresses.next());
+        match address {
+            Some(address) => self.role = Role::Migrating {
+                connecting: DirectSessionService::connect_in_background(address),
+            },
+            None => self.reset("the address of the new host is unknown"),
+        }
+    }
+
+    //Tries to connect until the new host listens, or until the migration times out.
+    fn connect_in_background(address: SocketAddr) -> Receiver<io::Result<TcpStream>> {
+        let deadline = Instant::now() + Duration::from_millis(MIGRATION_TIMEOUT_MS);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || loop {
+            match TcpStream::connect_timeout(&address, Duration::from_millis(CONNECT_TIMEOUT_MS)) {
+                Ok(stream) => {
+                    let _ = sender.send(Ok(stream));
+                    return;
+                },
+                Err(error) => if Instant::now() > deadline {
+                    let _ = sender.send(Err(error));
+                    return;
+                },
+            }
+            thread::sleep(Duration::from_millis(50));
+        });
+        receiver
+    }
+
+    fn update_migration(&mut self) {
+        let connected = match self.role {
+            Role::Migrating { ref connecting } => connecting.try_recv(),
+            _ => return,
+        };
+        let stream = match connected {
+            Ok(Ok(stream)) => stream,
+            Err(TryRecvError::Empty) => return,
+            Ok(Err(_)) | Err(TryRecvError::Disconnected) => return self.reset("the new host is unreachable"),
+        };
+        let join = self.join_message(true);
+        let joined = Connection::new(stream).and_then(|mut connection| {
+            connection.send(&join)?;
+            Ok(connection)
+        });
+        match joined {
+            Ok(connection) => self.role = Role::Client { connection },
+            Err(_) => self.reset("the new host is unreachable"),
+        }
+    }
+}
+
+impl VSessionService for DirectSessionService {
+    fn create_session(&mut self, info: SessionInfo, member_name: &str) -> NetworkResult<()> {
+        self.leave_session();
+        self.listen()?;
+        debug!("Hosting the session {} on the port {}.", info.name, self.port);
+        self.local_name = member_name.to_string();
+        self.rejoin_token = random_token();
+        self.local = Some(1);
+        self.next_member = 2;
+        self.members = vec![SessionMember {
+            id: 1,
+            name: member_name.to_string(),
+            address: String::new(),
+            is_host: true,
+            rejoin_key: rejoin_key(self.rejoin_token.as_str()),
+        }];
+        self.events.push(SessionEvent::Joined(info.clone()));
+        self.info = Some(info);
+        Ok(())
+    }
+
+    fn join_session(&mut self, address: &str, member_name: &str) -> NetworkResult<()> {
+        self.leave_session();
+        let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+            NetworkError::SessionError(format!("Invalid session address {}.", address))
+        })?;
+        debug!("Joining the session at {}.", address);
+        let mut connection = Connection::new(TcpStream::connect_timeout(&address, Duration::from_millis(CONNECT_TIMEOUT_MS))?)?;
+        self.local_name = member_name.to_string();
+        self.rejoin_token = random_token();
+        connection.send(&self.join_message(false))?;
+        self.port = address.port();
+        self.role = Role::Client { connection };
+        Ok(())
+    }
+
+    //When the host leaves, the other members migrate the session.
+    fn leave_session(&mut self) {
+        if let Role::Client { ref mut connection } = self.role {
+            let _ = connection.send(&SessionMessage::Leave);
+        }
+        if let Role::Idle = self.role {
+            return;
+        }
+        self.reset("left the session");
+        self.events.clear();
+    }
+
+    fn session(&self) -> Option<&SessionInfo> {
+        self.info.as_ref()
+    }
+
+    fn members(&self) -> &[SessionMember] {
+        self.members.as_slice()
+    }
+
+    fn local_member(&self) -> Option<MemberId> {
+        self.local
+    }
+
+    fn is_host(&self) -> bool {
+        match self.role {
+            Role::Host { .. } => true,
+            _ => false,
+        }
+    }
+
+    fn set_property(&mut self, key: &str, value: &str) -> NetworkResult<()> {
+        let message = SessionMessage::Property {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        match self.role {
+            Role::Host { ref mut clients, .. } => {
+                for &mut (_, ref mut connection) in clients.iter_mut() {
+                    let _ = connection.send(&message);
+                }
+            },
+            _ => return Err(NetworkError::SessionError(String::from("Only the host can set the session properties."))),
+        }
+        self.apply(message, "");
+        Ok(())
+    }
+
+    fn set_host_migration(&mut self, migration: Box<HostMigration>) {
+        self.migration = migration;
+    }
+
+    fn update(&mut self) -> Vec<SessionEvent> {
+        self.update_host();
+        self.update_client();
+        self.update_migration();
+        ::std::mem::replace(&mut self.events, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod session_test {
+    use super::*;
+    use std::thread;
+
+    //Far longer than any state change takes, even on a loaded machine: only a broken session reaches it.
+    const TEST_TIMEOUT_MS: u64 = 30000;
+
+    fn wait_until<F: FnMut() -> bool>(mut done: F) {
+        let deadline = Instant::now() + Duration::from_millis(TEST_TIMEOUT_MS);
+        while !done() {
+            assert!(Instant::now() < deadline, "The session didn't reach the expected state.");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn pump<F: Fn(&[DirectSessionService]) -> bool>(services: &mut [DirectSessionService], events: &mut [Vec<SessionEvent>], done: F) {
+        wait_until(|| {
+            for (service, events) in services.iter_mut().zip(events.iter_mut()) {
+                events.extend(service.update());
+            }
+            done(services)
+        });
+    }
+
+    //The address of the host, for the other services and the raw connections.
+    fn host_address(host: &DirectSessionService) -> String {
+        format!("127.0.0.1:{}", host.local_addr().unwrap().port())
+    }
+
+    #[test]
+    fn session_join_properties_and_host_migration() {
+        let mut services = vec![DirectSessionService::new(0), DirectSessionService::new(0), DirectSessionService::new(0)];
+        let mut events = vec![Vec::new(), Vec::new(), Vec::new()];
+        services[0].create_session(SessionInfo::new("Arena", 3), "host").unwrap();
+        let address = host_address(&services[0]);
+        services[1].join_session(address.as_str(), "alice").unwrap();
+        pump(&mut services, &mut events, |services| services[1].members().len() == 2);
+        services[2].join_session(address.as_str(), "bob").unwrap();
+        pump(&mut services, &mut events, |services| services.iter().all(|service| service.members().len() == 3));
+        assert_eq!(services[1].local_member(), Some(2));
+        assert!(events[1].contains(&SessionEvent::Joined(SessionInfo::new("Arena", 3))));
+
+        services[0].set_property("map", "dunes").unwrap();
+        assert!(services[1].set_property("map", "ice").is_err());
+        pump(&mut services, &mut events, |services| services[2].session().unwrap().properties.get("map").map(|map| map.as_str()) == Some("dunes"));
+
+        //alice is the oldest member left: she hosts the session, and bob rejoins her.
+        services[0].leave_session();
+        pump(&mut services, &mut events, |services| services[1].is_host() && services[1].members().len() == 2 && !services[2].is_host() && services[2].members().len() == 2);
+        //bob is connected to alice, and alice has accepted his rejoin.
+        pump(&mut services, &mut events, |services| {
+            let connected = match services[2].role {Role::Client { .. } => true, _ => false};
+            let accepted = match services[1].role {Role::Host { ref clients, .. } => clients.len() == 1, _ => false};
+            connected && accepted
+        });
+        assert!(events[2].contains(&SessionEvent::HostMigrated(2)));
+        assert_eq!(services[2].local_member(), Some(3));
+
+        services[2].leave_session();
+        pump(&mut services, &mut events, |services| services[1].members().len() == 1);
+        assert!(events[1].contains(&SessionEvent::MemberLeft(3)));
+    }
+
+    fn pending(service: &mut DirectSessionService) -> &mut Vec<(Connection, Instant)> {
+        match service.role {
+            Role::Host { ref mut pending, .. } => pending,
+            _ => panic!("The service doesn't host a session."),
+        }
+    }
+
+    #[test]
+    fn session_rejects_invalid_rejoin_and_idle_connections() {
+        let mut host = DirectSessionService::new(0);
+        host.create_session(SessionInfo::new("Arena", 4), "host").unwrap();
+        let address = host_address(&host);
+
+        //Claiming the id of the host without its token.
+        let mut intruder = TcpStream::connect(address.as_str()).unwrap();
+        let join = SessionMessage::Join { name: String::from("mallory"), rejoin_key: rejoin_key("mine"), member: Some(1), token: Some(String::from("guess")) };
+        let mut data = serde_json::to_vec(&join).unwrap();
+        data.push(b'\n');
+        intruder.write_all(data.as_slice()).unwrap();
+        //A line which never ends, and a connection which never joins.
+        let mut flooder = TcpStream::connect(address.as_str()).unwrap();
+        flooder.write_all(vec![b'x'; MAX_LINE_LENGTH + 1].as_slice()).unwrap();
+        let _idle = TcpStream::connect(address.as_str()).unwrap();
+
+        //The three connections are accepted at the first update, then the intruder and the flooder are closed.
+        wait_until(|| {
+            host.update();
+            pending(&mut host).len() == 1
+        });
+        intruder.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let mut reply = String::new();
+        intruder.read_to_string(&mut reply).unwrap();
+        assert!(reply.contains("Rejected"));
+        assert_eq!(host.members().len(), 1);
+        assert_eq!(pending(&mut host).len(), 1);
+
+        for &mut (_, ref mut accepted) in pending(&mut host).iter_mut() {
+            *accepted -= Duration::from_millis(JOIN_TIMEOUT_MS + 1);
+        }
+        host.update();
+        assert!(pending(&mut host).is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ LOD MANAGEMENT.
+
+ The level of detail of every entity is chosen in one place, from its size on the screen:
+ - mesh LOD: the coarsest LOD whose simplification error, projected on the screen, stays under the
+   max pixel error,
+ - animation update rate: the small entities are animated every 2, 4 or 8 frames,
+ - particle quality: the small emitters spawn fewer particles.
+
+ The max pixel error is scaled by the performance budget: raised when the frames take too long, lowered
+ back when there is time left. A triangle budget can be set too, the smallest entities are coarsened
+ until the budget is met.
+
+ Hysteresis: an entity changes of LOD only when the error is clearly under or over the threshold, a
+ camera moving around a LOD distance does not make the mesh pop every frame.
+*/
+
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ParticleQuality {
+    Minimal,
+    Reduced,
+    Full,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LodSettings {
+    //The simplification error accepted on the screen, in pixels.
+    pub max_pixel_error: f32,
+    //Relative margin around the threshold, before an entity changes of LOD.
+    pub hysteresis: f32,
+    //The target frame time, in milliseconds.
+    pub target_frame_time: f32,
+    //The max pixel error is never scaled above this factor.
+    pub max_error_scale: f32,
+    pub triangle_budget: Option<u64>,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        LodSettings {
+            max_pixel_error: 1.0,
+            hysteresis: 0.2,
+            target_frame_time: 1000.0 / 60.0,
+            max_error_scale: 8.0,
+            triangle_budget: None,
+        }
+    }
+}
+
+//The camera, for the projection of the errors on the screen.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LodView {
+    pub screen_height: f32,
+    //In radians.
+    pub vertical_fov: f32,
+}
+
+impl LodView {
+    //The size on the screen, in pixels, of a length at this distance from the camera.
+    pub fn project(&self, length: f32, distance: f32) -> f32 {
+        length * self.screen_height / (2.0 * distance.max(1e-3) * (self.vertical_fov * 0.5).tan())
+    }
+}
+
+//An entity to draw this frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LodCandidate {
+    pub id: u64,
+    pub distance: f32,
+    pub bounding_radius: f32,
+    //For each mesh LOD, from the most detailed: the simplification error in world units, and the triangle count.
+    pub lod_errors: Vec<f32>,
+    pub lod_triangles: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LodDecision {
+    pub id: u64,
+    pub mesh_lod: usize,
+    //The entity is animated every animation_interval frames.
+    pub animation_interval: u32,
+    pub particle_quality: ParticleQuality,
+    //The height of the entity on the screen, relative to the screen height.
+    pub coverage: f32,
+}
+
+pub struct LodManager {
+    settings: LodSettings,
+    current: HashMap<u64, usize>,
+    error_scale: f32,
+}
+
+impl LodManager {
+    pub fn new(settings: LodSettings) -> Self {
+        LodManager {
+            settings,
+            current: HashMap::new(),
+            error_scale: 1.0,
+        }
+    }
+
+    pub fn settings(&self) -> &LodSettings {
+        &self.settings
+    }
+
+    pub fn error_scale(&self) -> f32 {
+        self.error_scale
+    }
+
+    //The coarsest LOD with a projected error under the threshold.
+    fn coarsest_under(view: &LodView, candidate: &LodCandidate, threshold: f32) -> usize {
+        candidate.lod_errors.iter()
+            .rposition(|error| view.project(*error, candidate.distance) <= threshold)
+            .unwrap_or(0)
+    }
+
+    //The decisions of the frame, in the order of the candidates. The entities which are not candidates anymore are forgotten.
+    pub fn select(&mut self, view: &LodView, candidates: &[LodCandidate]) -> Vec<LodDecision> {
+        let threshold = self.settings.max_pixel_error * self.error_scale;
+        let hysteresis = self.settings.hysteresis;
+        let mut previous = ::std::mem::take(&mut self.current);
+
+        let mut decisions: Vec<LodDecision> = candidates.iter().map(|candidate| {
+            //Any LOD between these two is acceptable: the current one is kept if it is in the range.
+            let finest = LodManager::coarsest_under(view, candidate, threshold * (1.0 - hysteresis));
+            let coarsest = LodManager::coarsest_under(view, candidate, threshold * (1.0 + hysteresis));
+            let mesh_lod = match previous.remove(&candidate.id) {
+                Some(lod) => lod.max(finest).min(coarsest),
+                None => LodManager::coarsest_under(view, candidate, threshold),
+            };
+            let coverage = view.project(candidate.bounding_radius * 2.0, candidate.distance) / view.screen_height;
+            let (animation_interval, particle_quality) = if coverage > 0.25 {
+                (1, ParticleQuality::Full)
+            } else if coverage > 0.05 {
+                (2, ParticleQuality::Reduced)
+            } else if coverage > 0.01 {
+                (4, ParticleQuality::Minimal)
+            } else {
+                (8, ParticleQuality::Minimal)
+            };
+            LodDecision {
+                id: candidate.id,
+                mesh_lod,
+                animation_interval,
+                particle_quality,
+                coverage,
+            }
+        }).collect();
+
+        if let Some(budget) = self.settings.triangle_budget {
+            self.fit_triangle_budget(budget, candidates, decisions.as_mut_slice());
+        }
+        for decision in decisions.iter() {
+            self.current.insert(decision.id, decision.mesh_lod);
+        }
+        decisions
+    }
+
+    //The smallest entity on the screen is coarsened first, down to its coarsest LOD, then the next one.
+    fn fit_triangle_budget(&self, budget: u64, candidates: &[LodCandidate], decisions: &mut [LodDecision]) {
+        let triangles = |candidate: &LodCandidate, lod: usize| candidate.lod_triangles.get(lod).cloned().unwrap_or(0);
+        let mut total: u64 = candidates.iter().zip(decisions.iter()).map(|(candidate, decision)| triangles(candidate, decision.mesh_lod)).sum();
+        let mut order: Vec<usize> = (0..decisions.len()).collect();
+        order.sort_by(|a, b| decisions[*a].coverage.partial_cmp(&decisions[*b].coverage).unwrap_or(::std::cmp::Ordering::Equal));
+        for index in order {
+            let candidate = &candidates[index];
+            let decision = &mut decisions[index];
+            while total > budget && decision.mesh_lod + 1 < candidate.lod_triangles.len() {
+                total -= triangles(candidate, decision.mesh_lod).saturating_sub(triangles(candidate, decision.mesh_lod + 1));
+                decision.mesh_lod += 1;
+            }
+        }
+        if total > budget {
+            debug!("The triangle budget {} cannot be met, {} triangles at the coarsest LODs.", budget, total);
+        }
+    }
+
+    //The frame time, in milliseconds: the error scale follows the performance budget.
+    pub fn end_frame(&mut self, frame_time: f32) {
+        let target = self.settings.target_frame_time;
+        let scale = if frame_time > target {
+            self.error_scale * 1.1
+        } else if frame_time < target * 0.8 {
+            self.error_scale / 1.05
+        } else {
+            self.error_scale
+        };
+        let scale = scale.max(1.0).min(self.settings.max_error_scale);
+        if scale != self.error_scale {
+            trace!("LOD error scale: {}.", scale);
+        }
+        self.error_scale = scale;
+    }
+}
+
+#[cfg(test)]
+mod lod_test {
+    use super::*;
+
+    fn rock(id: u64, distance: f32) -> LodCandidate {
+        LodCandidate {
+            id,
+            distance,
+            bounding_radius: 1.0,
+            lod_errors: vec![0.0, 0.01, 0.04],
+            lod_triangles: vec![1000, 400, 100],
+        }
+    }
+
+    #[test]
+    fn lod_selection_with_hysteresis_and_budget() {
+        let view = LodView {
+            screen_height: 1000.0,
+            vertical_fov: ::std::f32::consts::FRAC_PI_2,
+        };
+        //0.01 units at 5 units: 1 pixel.
+        assert!((view.project(0.01, 5.0) - 1.0).abs() < 1e-4);
+
+        let mut manager = LodManager::new(LodSettings::default());
+        let near = manager.select(&view, &[rock(1, 1.0)]);
+        assert_eq!(near[0].mesh_lod, 0);
+        assert_eq!(near[0].animation_interval, 1);
+        assert_eq!(near[0].particle_quality, ParticleQuality::Full);
+        assert_eq!(manager.select(&view, &[rock(1, 5.5)])[0].mesh_lod, 0);
+        assert_eq!(manager.select(&view, &[rock(1, 7.0)])[0].mesh_lod, 1);
+        //Around the distance of the LOD 1: no popping back.
+        assert_eq!(manager.select(&view, &[rock(1, 4.5)])[0].mesh_lod, 1);
+        assert_eq!(manager.select(&view, &[rock(1, 3.0)])[0].mesh_lod, 0);
+        let far = manager.select(&view, &[rock(1, 500.0)]);
+        assert_eq!(far[0].mesh_lod, 2);
+        assert_eq!(far[0].animation_interval, 8);
+
+        //The small rocks are coarsened first.
+        let mut manager = LodManager::new(LodSettings {
+            triangle_budget: Some(1500),
+            ..LodSettings::default()
+        });
+        let decisions = manager.select(&view, &[rock(1, 1.0), rock(2, 2.0), rock(3, 1.5)]);
+        assert_eq!(decisions.iter().map(|decision| decision.mesh_lod).collect::<Vec<_>>(), vec![0, 2, 1]);
+
+        //Slow frames raise the error scale, up to the max.
+        for _ in 0..100 {
+            manager.end_frame(40.0);
+        }
+        assert_eq!(manager.error_scale(), 8.0);
+        manager.end_frame(5.0);
+        assert!(manager.error_scale() < 8.0);
+        assert_eq!(manager.select(&view, &[rock(1, 5.0)])[0].mesh_lod, 2);
+    }
+}
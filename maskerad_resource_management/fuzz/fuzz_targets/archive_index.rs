@@ -0,0 +1,27 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//An archive from an untrusted source: opening it and reading all its assets must never panic
+//or allocate more than the size of the archive.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate maskerad_resource_management;
+
+use std::io::Cursor;
+use maskerad_resource_management::pipeline::archive::Archive;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut archive) = Archive::from_reader(Cursor::new(data)) {
+        let guids: Vec<_> = archive.guids().into_iter().cloned().collect();
+        for guid in guids.iter() {
+            let asset = archive.read(guid).expect("An indexed asset could not be read.");
+            assert!(asset.map(|asset| asset.len() <= data.len()).unwrap_or(false));
+        }
+    }
+});
@@ -0,0 +1,17 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate maskerad_resource_management;
+
+use maskerad_resource_management::scenes::scene_binary::read_binary_scene;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = read_binary_scene(data);
+});
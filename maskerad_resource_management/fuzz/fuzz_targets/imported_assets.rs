@@ -0,0 +1,36 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//The payloads of the cooked assets. The first byte selects the reader.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate maskerad_resource_management;
+
+use maskerad_resource_management::pipeline::importers::audio_importer::SoundAsset;
+use maskerad_resource_management::pipeline::importers::mesh_importer::MeshAsset;
+use maskerad_resource_management::pipeline::importers::texture_importer::TextureAsset;
+
+fuzz_target!(|data: &[u8]| {
+    let (selector, payload) = match data.split_first() {
+        Some((selector, payload)) => (*selector, payload),
+        None => return,
+    };
+    //A payload which is read must be written back identically.
+    match selector % 3 {
+        0 => if let Ok(texture) = TextureAsset::from_bytes(payload) {
+            assert_eq!(texture.to_bytes().as_slice(), payload);
+        },
+        1 => if let Ok(mesh) = MeshAsset::from_bytes(payload) {
+            assert_eq!(mesh.to_bytes().as_slice(), payload);
+        },
+        _ => if let Ok(sound) = SoundAsset::from_bytes(payload) {
+            assert_eq!(sound.to_bytes().as_slice(), payload);
+        },
+    }
+});
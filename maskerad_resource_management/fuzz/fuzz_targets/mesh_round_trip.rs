@@ -0,0 +1,46 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//Structured inputs: valid meshes built by arbitrary must survive a write and a read.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+#[macro_use]
+extern crate arbitrary;
+extern crate maskerad_resource_management;
+
+use maskerad_resource_management::pipeline::importers::mesh_importer::{MeshAsset, MeshLod, MeshVertex};
+
+#[derive(Debug, Arbitrary)]
+struct FuzzMesh {
+    vertices: Vec<[f32; 8]>,
+    lods: Vec<(f32, f32, Vec<[u32; 3]>)>,
+}
+
+fuzz_target!(|input: FuzzMesh| {
+    if input.vertices.is_empty() {
+        return;
+    }
+    let vertex_count = input.vertices.len() as u32;
+    let mesh = MeshAsset {
+        vertices: input.vertices.iter().map(|values| MeshVertex {
+            position: [values[0], values[1], values[2]],
+            normal: [values[3], values[4], values[5]],
+            uv: [values[6], values[7]],
+        }).collect(),
+        lods: input.lods.iter().map(|&(error, distance, ref triangles)| MeshLod {
+            indices: triangles.iter().flat_map(|triangle| triangle.iter().map(|index| index % vertex_count)).collect(),
+            error,
+            distance,
+        }).collect(),
+    };
+    //Compared as bytes, the NaNs are not equal to themselves.
+    let data = mesh.to_bytes();
+    let read = MeshAsset::from_bytes(data.as_slice()).expect("A valid mesh could not be read.");
+    assert_eq!(read.to_bytes(), data);
+});
@@ -0,0 +1,225 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ ASSET CACHE.
+
+ The assets loaded on the heap, shared with handles (reference counted). An asset without a handle
+ outside the cache is unreferenced, but it is not unloaded right away: the next level often uses it again.
+
+ The garbage collection is incremental: every frame, it walks a part of the assets, within a time budget,
+ and unloads the assets unreferenced for a few frames. It only unloads when the memory used is above the
+ high watermark, and stops below the low watermark: between the two, the unreferenced assets stay in the
+ cache. Changing of level does not unload everything, the assets of the previous level are collected
+ when the memory is needed.
+*/
+
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use maskerad_core::name::{NameMap, PathId};
+
+pub type AssetHandle<T> = Rc<T>;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GcSettings {
+    //In bytes. The collection starts above the high watermark, and stops below the low watermark.
+    pub high_watermark: usize,
+    pub low_watermark: usize,
+    //The frames an asset stays unreferenced before it can be unloaded.
+    pub grace_frames: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GcReport {
+    pub visited: usize,
+    pub unloaded: Vec<PathId>,
+    pub freed: usize,
+}
+
+struct CachedAsset<T> {
+    path: PathId,
+    asset: Rc<T>,
+    size: usize,
+    //The frame where the asset was found unreferenced.
+    unreferenced_since: Option<u64>,
+}
+
+pub struct AssetCache<T> {
+    settings: GcSettings,
+    assets: Vec<CachedAsset<T>>,
+    index: NameMap<PathId, usize>,
+    memory_used: usize,
+    //The next asset to visit.
+    cursor: usize,
+    collecting: bool,
+}
+
+impl<T> AssetCache<T> {
+    pub fn new(settings: GcSettings) -> Self {
+        AssetCache {
+            settings,
+            assets: Vec::new(),
+            index: NameMap::default(),
+            memory_used: 0,
+            cursor: 0,
+            collecting: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    pub fn is_collecting(&self) -> bool {
+        self.collecting
+    }
+
+    pub fn contains<I: Into<PathId>>(&self, path: I) -> bool {
+        self.index.contains_key(&path.into())
+    }
+
+    //A handle to the asset, if it is still loaded.
+    pub fn get<I: Into<PathId>>(&self, path: I) -> Option<AssetHandle<T>> {
+        self.index.get(&path.into()).map(|position| self.assets[*position].asset.clone())
+    }
+
+    //The size is the memory used by the asset, in bytes. An asset loaded again replaces the previous one.
+    pub fn insert<I: Into<PathId>>(&mut self, path: I, asset: T, size: usize) -> AssetHandle<T> {
+        let path = path.into();
+        self.remove(path);
+        let asset = Rc::new(asset);
+        self.index.insert(path, self.assets.len());
+        self.assets.push(CachedAsset {
+            path,
+            asset: asset.clone(),
+            size,
+            unreferenced_since: None,
+        });
+        self.memory_used += size;
+        asset
+    }
+
+    fn remove(&mut self, path: PathId) -> Option<CachedAsset<T>> {
+        let position = self.index.remove(&path)?;
+        let removed = self.assets.swap_remove(position);
+        if position < self.assets.len() {
+            self.index.insert(self.assets[position].path, position);
+        }
+        self.memory_used -= removed.size;
+        Some(removed)
+    }
+
+    pub fn collect(&mut self, frame: u64, budget: Duration) -> GcReport {
+        let start = Instant::now();
+        self.collect_timed(frame, budget, || start.elapsed())
+    }
+
+    //A step of the collection: at most one walk over the assets, until the budget is spent.
+    //elapsed: the time spent since the start of the step.
+    pub fn collect_timed<C: FnMut() -> Duration>(&mut self, frame: u64, budget: Duration, mut elapsed: C) -> GcReport {
+        let mut report = GcReport::default();
+        if !self.collecting && self.memory_used > self.settings.high_watermark {
+            debug!("Asset collection started: {} bytes used, the high watermark is {} bytes.", self.memory_used, self.settings.high_watermark);
+            self.collecting = true;
+        }
+
+        let mut to_visit = self.assets.len();
+        while to_visit > 0 && !self.assets.is_empty() {
+            if report.visited > 0 && elapsed() >= budget {
+                break;
+            }
+            if self.cursor >= self.assets.len() {
+                self.cursor = 0;
+            }
+            to_visit -= 1;
+            report.visited += 1;
+
+            let unloadable = {
+                let cached = &mut self.assets[self.cursor];
+                if Rc::strong_count(&cached.asset) > 1 {
+                    cached.unreferenced_since = None;
+                    false
+                } else {
+                    let since = *cached.unreferenced_since.get_or_insert(frame);
+                    frame - since >= self.settings.grace_frames
+                }
+            };
+            if unloadable && self.collecting {
+                let path = self.assets[self.cursor].path;
+                let removed = self.remove(path).expect("The visited asset is in the cache.");
+                trace!("Unloading the unreferenced asset {}.", path);
+                report.freed += removed.size;
+                report.unloaded.push(path);
+                //The last asset took the place of the removed one, it is visited next.
+                if self.memory_used <= self.settings.low_watermark {
+                    debug!("Asset collection done: {} bytes used.", self.memory_used);
+                    self.collecting = false;
+                }
+            } else {
+                self.cursor += 1;
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod asset_cache_test {
+    use super::*;
+
+    #[test]
+    fn asset_cache_incremental_collection() {
+        let mut cache = AssetCache::new(GcSettings {
+            high_watermark: 1000,
+            low_watermark: 500,
+            grace_frames: 2,
+        });
+        let house = cache.insert("level1/house.mesh", "house", 400);
+        let tree = cache.insert("level1/tree.mesh", "tree", 300);
+        drop(tree);
+        cache.insert("common/player.mesh", "player", 200);
+        let player = cache.get("common/player.mesh").unwrap();
+        assert_eq!(cache.memory_used(), 900);
+
+        //Under the high watermark, the unreferenced tree stays cached.
+        for frame in 0..5 {
+            assert!(cache.collect(frame, Duration::from_secs(1)).unloaded.is_empty());
+        }
+        assert!(cache.get("level1/tree.mesh").is_some());
+
+        //Level change: the level 1 handles are dropped, the level 2 assets go over the high watermark.
+        drop(house);
+        let bridge = cache.insert("level2/bridge.mesh", "bridge", 300);
+        assert_eq!(cache.memory_used(), 1200);
+
+        //One asset visited per frame.
+        let mut unloaded = Vec::new();
+        for frame in 5..20 {
+            let mut first = true;
+            unloaded.extend(cache.collect_timed(frame, Duration::from_millis(1), || {
+                let elapsed = if first { Duration::from_millis(0) } else { Duration::from_millis(1) };
+                first = false;
+                elapsed
+            }).unloaded);
+        }
+        //The unreferenced house and tree are unloaded, down to the low watermark.
+        assert_eq!(unloaded.len(), 2);
+        assert!(cache.memory_used() <= 500);
+        assert!(!cache.is_collecting());
+        assert!(cache.contains("common/player.mesh") && cache.contains("level2/bridge.mesh"));
+        assert_eq!(*player, "player");
+        assert_eq!(*bridge, "bridge");
+    }
+}
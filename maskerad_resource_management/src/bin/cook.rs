@@ -0,0 +1,102 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//Cook the content of a game for a target platform.
+//
+//cook <source directory> <output directory> [--profile desktop-high|desktop-low|mobile] [--intermediate <directory>]
+//     [--package <name> --version <version> --requires-game <version>] [--verbose]
+//
+//The intermediate directory (".cook" next to the output directory by default) keeps the imported assets
+//between two cooks, so only the modified assets are imported again.
+
+extern crate maskerad_resource_management;
+#[macro_use]
+extern crate log;
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use log::{Log, Record, Level, LevelFilter, Metadata};
+use maskerad_resource_management::pipeline::content_manifest::ManifestPackage;
+use maskerad_resource_management::pipeline::cook::{CookSettings, cook};
+use maskerad_resource_management::pipeline::target_profile::TargetProfile;
+
+struct ConsoleLogger;
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            match record.level() {
+                Level::Error | Level::Warn => eprintln!("{}: {}", record.level(), record.args()),
+                _ => println!("{}", record.args()),
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: ConsoleLogger = ConsoleLogger;
+
+fn usage() -> ! {
+    eprintln!("Usage: cook <source directory> <output directory> [--profile desktop-high|desktop-low|mobile] [--intermediate <directory>] \\
+               [--package <name> --version <version> --requires-game <version>] [--verbose]");
+    process::exit(2);
+}
+
+fn main() {
+    let mut directories = Vec::new();
+    let mut profile = TargetProfile::default();
+    let mut intermediate = None;
+    let mut package = ManifestPackage::default();
+    let mut level = LevelFilter::Info;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => {
+                profile = match args.next().map(|name| TargetProfile::from_name(name.as_str())) {
+                    Some(Ok(profile)) => profile,
+                    Some(Err(error)) => {
+                        eprintln!("{}", error);
+                        usage();
+                    },
+                    None => usage(),
+                };
+            },
+            "--intermediate" => intermediate = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--package" => package.name = args.next().unwrap_or_else(|| usage()),
+            "--version" => package.version = args.next().unwrap_or_else(|| usage()),
+            "--requires-game" => package.requires_game = Some(args.next().unwrap_or_else(|| usage())),
+            "--verbose" => level = LevelFilter::Debug,
+            _ if arg.starts_with("--") => usage(),
+            _ => directories.push(PathBuf::from(arg)),
+        }
+    }
+    if directories.len() != 2 {
+        usage();
+    }
+
+    log::set_logger(&LOGGER).expect("No other logger is set.");
+    log::set_max_level(level);
+
+    let intermediate = intermediate.unwrap_or_else(|| directories[1].with_file_name(".cook"));
+    let mut settings = CookSettings::new(directories[0].clone(), directories[1].clone(), intermediate, profile);
+    settings.package = package;
+    match cook(&settings) {
+        Ok(ref report) if report.succeeded() => {},
+        Ok(_) => process::exit(1),
+        Err(error) => {
+            error!("The cook failed: {}", error);
+            process::exit(1);
+        },
+    }
+}
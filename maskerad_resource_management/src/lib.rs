@@ -9,10 +9,17 @@ extern crate maskerad_core;
 extern crate lewton;
 extern crate imagefmt;
 extern crate gltf;
+extern crate serde_json;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 #[macro_use]
 extern crate log;
 
 pub mod resources;
 pub mod resource_manager;
 pub mod resource_manager_errors;
-pub mod registries;
\ No newline at end of file
+pub mod asset_cache;
+pub mod registries;
+pub mod scenes;
+pub mod pipeline;
\ No newline at end of file
@@ -0,0 +1,189 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//The archives shipped with the game: the cooked assets, packed in a few big files instead of
+//thousands of small ones. The format is the KPAK archive of maskerad_core (see archive_filesystem),
+//keyed by the GUIDs of the assets: the same archives can be mounted as an ArchiveFilesystem.
+//
+//The archives can be read with a reader (Archive), or used in place from their memory (ArchiveView): a
+//mapped archive gives the assets without any copy, and the archived assets (see archived) are used directly.
+
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use maskerad_core::filesystem::archive_filesystem::{self, read_archive_index};
+use maskerad_core::filesystem::filesystem_error::FileSystemError;
+use pipeline::asset_guid::AssetGuid;
+use pipeline::pipeline_errors::{PipelineError, PipelineResult};
+
+pub use maskerad_core::filesystem::archive_filesystem::ARCHIVE_VERSION;
+
+//Write the assets in an archive, sorted by GUID. Returns the size of the archive.
+pub fn write_archive<W: Write>(assets: &BTreeMap<AssetGuid, Vec<u8>>, writer: W) -> PipelineResult<u64> {
+    let size = archive_filesystem::write_archive(assets, writer)?;
+    Ok(size)
+}
+
+//An archive opened for reading. Only the index is read, the assets are read on demand.
+pub struct Archive<R: Read + Seek> {
+    reader: R,
+    entries: BTreeMap<AssetGuid, (u64, u64)>,
+}
+
+impl<R: Read + Seek> Archive<R> {
+    //The index is checked against the size of the archive, and its keys must be GUIDs.
+    pub fn from_reader(mut reader: R) -> PipelineResult<Self> {
+        let mut entries = BTreeMap::new();
+        for (key, entry) in read_archive_index(&mut reader)? {
+            let guid = AssetGuid::parse(key.as_str()).ok_or_else(|| {
+                PipelineError::MalformedAsset(format!("Invalid GUID {} in the archive index.", key))
+            })?;
+            entries.insert(guid, entry);
+        }
+        debug!("Archive opened, {} assets.", entries.len());
+        Ok(Archive {
+            reader,
+            entries,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, guid: &AssetGuid) -> bool {
+        self.entries.contains_key(guid)
+    }
+
+    pub fn guids(&self) -> Vec<&AssetGuid> {
+        self.entries.keys().collect()
+    }
+
+    //None if the asset is not in this archive.
+    pub fn read(&mut self, guid: &AssetGuid) -> PipelineResult<Option<Vec<u8>>> {
+        let (position, size) = match self.entries.get(guid) {
+            Some(&entry) => entry,
+            None => return Ok(None),
+        };
+        let mut data = vec![0u8; size as usize];
+        self.reader.seek(SeekFrom::Start(position)).map_err(|io_error| FileSystemError::from(io_error))?;
+        self.reader.read_exact(data.as_mut_slice()).map_err(|io_error| FileSystemError::from(io_error))?;
+        Ok(Some(data))
+    }
+}
+
+//An archive in memory (mapped, or read at once). The assets are slices of this memory.
+pub struct ArchiveView<'a> {
+    data: &'a [u8],
+    entries: BTreeMap<AssetGuid, (u64, u64)>,
+}
+
+impl<'a> ArchiveView<'a> {
+    //The same checks as Archive::from_reader.
+    pub fn from_bytes(data: &'a [u8]) -> PipelineResult<Self> {
+        let entries = Archive::from_reader(Cursor::new(data))?.entries;
+        Ok(ArchiveView {
+            data,
+            entries,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, guid: &AssetGuid) -> bool {
+        self.entries.contains_key(guid)
+    }
+
+    //None if the asset is not in this archive.
+    pub fn get(&self, guid: &AssetGuid) -> Option<&'a [u8]> {
+        let data = self.data;
+        self.entries.get(guid).map(|&(position, size)| &data[position as usize..(position + size) as usize])
+    }
+}
+
+#[cfg(test)]
+mod archive_test {
+    use super::*;
+    use std::io::Cursor;
+    use maskerad_core::filesystem::archive_filesystem::ArchiveFilesystem;
+    use maskerad_core::filesystem::game_directories::RootDir;
+    use maskerad_core::filesystem::vfilesystem::VFilesystem;
+    use maskerad_core::filesystem::vpath::VPath;
+    use pipeline::content_manifest::{ContentManifest, ManifestAsset};
+
+    #[test]
+    fn archive_write_and_read() {
+        let house = AssetGuid::parse("0123456789abcdef0123456789abcdef").unwrap();
+        let door = AssetGuid::parse("fedcba9876543210fedcba9876543210").unwrap();
+        let mut assets = BTreeMap::new();
+        assets.insert(house.clone(), vec![1, 2, 3]);
+        assets.insert(door.clone(), vec![4, 5]);
+
+        let mut data = Vec::new();
+        let size = write_archive(&assets, &mut data).unwrap();
+        assert_eq!(size, data.len() as u64);
+
+        let mut archive = Archive::from_reader(Cursor::new(data.clone())).unwrap();
+        assert_eq!(archive.len(), 2);
+        assert_eq!(archive.read(&door).unwrap(), Some(vec![4, 5]));
+        assert_eq!(archive.read(&house).unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(archive.read(&AssetGuid::parse("00000000000000000000000000000000").unwrap()).unwrap(), None);
+        let view = ArchiveView::from_bytes(data.as_slice()).unwrap();
+        assert_eq!(view.get(&house), Some(&[1u8, 2, 3][..]));
+        assert_eq!(view.get(&door).map(|asset| (asset.as_ptr() as usize - data.as_ptr() as usize) % 16), Some(0));
+
+        //An index pointing out of the archive, a count too large for the archive, a truncated header.
+        let mut corrupted = data.clone();
+        corrupted[12 + 32 + 8] = 0xff;
+        assert!(Archive::from_reader(Cursor::new(corrupted)).is_err());
+        let mut corrupted = data.clone();
+        corrupted[8..12].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        assert!(Archive::from_reader(Cursor::new(corrupted)).is_err());
+        assert!(Archive::from_reader(Cursor::new(&data[..6])).is_err());
+        data[0] = b'X';
+        assert!(Archive::from_reader(Cursor::new(data)).is_err());
+    }
+
+    #[test]
+    fn archive_mounted_as_a_filesystem() {
+        let house = AssetGuid::parse("0123456789abcdef0123456789abcdef").unwrap();
+        let door = AssetGuid::parse("fedcba9876543210fedcba9876543210").unwrap();
+        let mut manifest = ContentManifest::new("desktop");
+        for (guid, path) in vec![(&house, "meshes/house.gltf"), (&door, "meshes/door.gltf")] {
+            manifest.assets.insert(guid.clone(), ManifestAsset {
+                path: String::from(path),
+                archive: String::from("base"),
+                importer: String::from("mesh"),
+                dependencies: Vec::new(),
+            });
+        }
+        let mut assets = BTreeMap::new();
+        assets.insert(house.clone(), vec![1, 2, 3]);
+        assets.insert(door.clone(), vec![4, 5]);
+        let mut data = Vec::new();
+        write_archive(&assets, &mut data).unwrap();
+
+        let mut filesystem = ArchiveFilesystem::from_reader(Cursor::new(data)).unwrap();
+        for (path, guid) in manifest.paths_in("base") {
+            filesystem = filesystem.with_path(path, guid.as_str()).unwrap();
+        }
+        let mut house_data = Vec::new();
+        filesystem.open(VPath::new(RootDir::AssetRoot, "meshes/house.gltf").unwrap()).unwrap().read_to_end(&mut house_data).unwrap();
+        assert_eq!(house_data, vec![1, 2, 3]);
+        assert_eq!(filesystem.metadata(VPath::new(RootDir::AssetRoot, door.as_str()).unwrap()).unwrap().len(), 2);
+        assert_eq!(filesystem.read_dir(VPath::new(RootDir::AssetRoot, "meshes").unwrap()).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,337 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ ARCHIVED ASSETS.
+
+ An optional format for the big cooked assets (meshes, navigation meshes), used directly from the memory of
+ the archive: the data is validated once, then the vertices and the indices are slices of the archive.
+ No deserialization, no allocation, the archive can be mapped in memory instead of being read.
+
+ Every section is aligned on 4 bytes from the start of the asset, and the archives align their entries
+ on 16 bytes: the data must start on a 4 bytes boundary (a mapped file, or a buffer allocated for u32s).
+ All the numbers are little endian. On a big endian target the views are refused, the assets must be
+ copied with to_mesh.
+
+ Mesh: magic "KAMS", version (u32), vertex count (u32), LOD count (u32)
+       LODs: first index (u32), index count (u32), error (f32), distance (f32)
+       vertices: position, normal, uv (8 f32)
+       indices: the indices of all the LODs (u32)
+
+ Navigation mesh: magic "KANV", version (u32), vertex count (u32), polygon count (u32)
+       polygons: first index (u32), index count (u32)
+       vertices: position (3 f32)
+       indices: the vertices of the polygons, in order (u32)
+*/
+
+use std::mem;
+use std::slice;
+use pipeline::importers::{push_u32, read_bytes, read_u32, read_magic, expect_end};
+use pipeline::importers::mesh_importer::{MeshAsset, MeshLod, MeshVertex};
+use pipeline::pipeline_errors::{PipelineError, PipelineResult};
+
+pub const ARCHIVED_VERSION: u32 = 1;
+const ARCHIVED_MESH_MAGIC: &'static [u8; 4] = b"KAMS";
+const ARCHIVED_NAVMESH_MAGIC: &'static [u8; 4] = b"KANV";
+const HEADER_SIZE: usize = 16;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ArchivedLod {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub error: f32,
+    pub distance: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct ArchivedPolygon {
+    first_index: u32,
+    index_count: u32,
+}
+
+pub fn is_archived_mesh(data: &[u8]) -> bool {
+    data.starts_with(ARCHIVED_MESH_MAGIC)
+}
+
+pub fn archive_mesh(mesh: &MeshAsset) -> Vec<u8> {
+    let index_count: usize = mesh.lods.iter().map(|lod| lod.indices.len()).sum();
+    let mut output = Vec::with_capacity(HEADER_SIZE + mesh.lods.len() * 16 + mesh.vertices.len() * 32 + index_count * 4);
+    output.extend_from_slice(ARCHIVED_MESH_MAGIC);
+    push_u32(&mut output, ARCHIVED_VERSION);
+    push_u32(&mut output, mesh.vertices.len() as u32);
+    push_u32(&mut output, mesh.lods.len() as u32);
+    let mut first_index = 0;
+    for lod in mesh.lods.iter() {
+        push_u32(&mut output, first_index);
+        push_u32(&mut output, lod.indices.len() as u32);
+        push_u32(&mut output, lod.error.to_bits());
+        push_u32(&mut output, lod.distance.to_bits());
+        first_index += lod.indices.len() as u32;
+    }
+    for vertex in mesh.vertices.iter() {
+        for value in vertex.position.iter().chain(vertex.normal.iter()).chain(vertex.uv.iter()) {
+            push_u32(&mut output, value.to_bits());
+        }
+    }
+    for index in mesh.lods.iter().flat_map(|lod| lod.indices.iter()) {
+        push_u32(&mut output, *index);
+    }
+    output
+}
+
+//The polygons are convex, their vertices in order.
+pub fn archive_navmesh(vertices: &[[f32; 3]], polygons: &[Vec<u32>]) -> Vec<u8> {
+    let mut output = Vec::new();
+    output.extend_from_slice(ARCHIVED_NAVMESH_MAGIC);
+    push_u32(&mut output, ARCHIVED_VERSION);
+    push_u32(&mut output, vertices.len() as u32);
+    push_u32(&mut output, polygons.len() as u32);
+    let mut first_index = 0;
+    for polygon in polygons.iter() {
+        push_u32(&mut output, first_index);
+        push_u32(&mut output, polygon.len() as u32);
+        first_index += polygon.len() as u32;
+    }
+    for value in vertices.iter().flat_map(|vertex| vertex.iter()) {
+        push_u32(&mut output, value.to_bits());
+    }
+    for index in polygons.iter().flat_map(|polygon| polygon.iter()) {
+        push_u32(&mut output, *index);
+    }
+    output
+}
+
+//The header of an archived asset: the two counts.
+fn read_header(data: &[u8], offset: &mut usize, magic: &[u8; 4], kind: &str) -> PipelineResult<(usize, usize)> {
+    if cfg!(target_endian = "big") {
+        return Err(PipelineError::MalformedAsset(format!("The archived {} cannot be used in place on a big endian target.", kind)));
+    }
+    if data.as_ptr() as usize % mem::align_of::<u32>() != 0 {
+        return Err(PipelineError::MalformedAsset(format!("The archived {} is not aligned on 4 bytes.", kind)));
+    }
+    read_magic(data, offset, magic, kind)?;
+    let version = read_u32(data, offset)?;
+    if version != ARCHIVED_VERSION {
+        return Err(PipelineError::MalformedAsset(format!("Archived {} version {}, expected {}.", kind, version, ARCHIVED_VERSION)));
+    }
+    let first = read_u32(data, offset)? as usize;
+    let second = read_u32(data, offset)? as usize;
+    Ok((first, second))
+}
+
+//Only used with the plain types of this file (u32, f32 and their structs), valid for any bit pattern.
+//The data is aligned on 4 bytes, and every section starts on a multiple of 4.
+fn view<'a, T>(data: &'a [u8], offset: &mut usize, count: usize) -> PipelineResult<&'a [T]> {
+    let size = count.checked_mul(mem::size_of::<T>()).ok_or_else(|| {
+        PipelineError::TruncatedAsset(format!("{} elements announced at the offset {}.", count, offset))
+    })?;
+    let bytes = read_bytes(data, offset, size)?;
+    debug_assert_eq!(bytes.as_ptr() as usize % mem::align_of::<T>(), 0);
+    Ok(unsafe { slice::from_raw_parts(bytes.as_ptr() as *const T, count) })
+}
+
+//A range of indices, checked against the index buffer and the vertices.
+fn check_range(indices: &[u32], first: u32, count: u32, vertex_count: usize, kind: &str, element: usize) -> PipelineResult<()> {
+    let range = match (first as usize).checked_add(count as usize) {
+        Some(end) if end <= indices.len() => &indices[first as usize..end],
+        _ => return Err(PipelineError::MalformedAsset(format!(
+            "The {} {} uses the indices {}+{}, the asset has {} indices.", kind, element, first, count, indices.len()
+        ))),
+    };
+    match range.iter().find(|index| **index as usize >= vertex_count) {
+        Some(index) => Err(PipelineError::MalformedAsset(format!("The {} {} uses the vertex {}, the asset has {} vertices.", kind, element, index, vertex_count))),
+        None => Ok(()),
+    }
+}
+
+//A mesh, used in place.
+#[derive(Debug, Copy, Clone)]
+pub struct ArchivedMesh<'a> {
+    lods: &'a [ArchivedLod],
+    vertices: &'a [MeshVertex],
+    indices: &'a [u32],
+}
+
+impl<'a> ArchivedMesh<'a> {
+    //The same checks as MeshAsset::from_bytes: the LODs are lists of triangles, of existing vertices.
+    pub fn from_bytes(data: &'a [u8]) -> PipelineResult<Self> {
+        let mut offset = 0;
+        let (vertex_count, lod_count) = read_header(data, &mut offset, ARCHIVED_MESH_MAGIC, "mesh")?;
+        let lods: &[ArchivedLod] = view(data, &mut offset, lod_count)?;
+        let vertices = view(data, &mut offset, vertex_count)?;
+        let remaining = (data.len() - offset) / 4;
+        let indices = view(data, &mut offset, remaining)?;
+        expect_end(data, offset)?;
+        for (level, lod) in lods.iter().enumerate() {
+            if lod.index_count % 3 != 0 {
+                return Err(PipelineError::MalformedAsset(format!("The LOD {} has {} indices, not a list of triangles.", level, lod.index_count)));
+            }
+            check_range(indices, lod.first_index, lod.index_count, vertex_count, "LOD", level)?;
+        }
+        Ok(ArchivedMesh {
+            lods,
+            vertices,
+            indices,
+        })
+    }
+
+    pub fn vertices(&self) -> &'a [MeshVertex] {
+        self.vertices
+    }
+
+    pub fn lods(&self) -> &'a [ArchivedLod] {
+        self.lods
+    }
+
+    pub fn lod_indices(&self, level: usize) -> &'a [u32] {
+        let lod = &self.lods[level];
+        &self.indices[lod.first_index as usize..(lod.first_index + lod.index_count) as usize]
+    }
+
+    pub fn select_lod(&self, distance: f32) -> usize {
+        self.lods.iter().rposition(|lod| lod.distance <= distance).unwrap_or(0)
+    }
+
+    //A copy, for the code which needs to own the mesh.
+    pub fn to_mesh(&self) -> MeshAsset {
+        MeshAsset {
+            vertices: self.vertices.to_vec(),
+            lods: (0..self.lods.len()).map(|level| MeshLod {
+                indices: self.lod_indices(level).to_vec(),
+                error: self.lods[level].error,
+                distance: self.lods[level].distance,
+            }).collect(),
+        }
+    }
+}
+
+//A navigation mesh, used in place.
+#[derive(Debug, Copy, Clone)]
+pub struct ArchivedNavMesh<'a> {
+    polygons: &'a [ArchivedPolygon],
+    vertices: &'a [[f32; 3]],
+    indices: &'a [u32],
+}
+
+impl<'a> ArchivedNavMesh<'a> {
+    //The polygons have at least 3 vertices, which exist.
+    pub fn from_bytes(data: &'a [u8]) -> PipelineResult<Self> {
+        let mut offset = 0;
+        let (vertex_count, polygon_count) = read_header(data, &mut offset, ARCHIVED_NAVMESH_MAGIC, "navigation mesh")?;
+        let polygons: &[ArchivedPolygon] = view(data, &mut offset, polygon_count)?;
+        let vertices = view(data, &mut offset, vertex_count)?;
+        let remaining = (data.len() - offset) / 4;
+        let indices = view(data, &mut offset, remaining)?;
+        expect_end(data, offset)?;
+        for (number, polygon) in polygons.iter().enumerate() {
+            if polygon.index_count < 3 {
+                return Err(PipelineError::MalformedAsset(format!("The polygon {} has {} vertices.", number, polygon.index_count)));
+            }
+            check_range(indices, polygon.first_index, polygon.index_count, vertex_count, "polygon", number)?;
+        }
+        Ok(ArchivedNavMesh {
+            polygons,
+            vertices,
+            indices,
+        })
+    }
+
+    pub fn vertices(&self) -> &'a [[f32; 3]] {
+        self.vertices
+    }
+
+    pub fn polygon_count(&self) -> usize {
+        self.polygons.len()
+    }
+
+    //The vertex indices of the polygon.
+    pub fn polygon(&self, number: usize) -> &'a [u32] {
+        let polygon = &self.polygons[number];
+        &self.indices[polygon.first_index as usize..(polygon.first_index + polygon.index_count) as usize]
+    }
+}
+
+//A copy of the data on a 4 bytes boundary, for the tests and the data which is not in an archive.
+pub fn aligned_copy(data: &[u8]) -> Vec<u32> {
+    let mut words = vec![0u32; (data.len() + 3) / 4];
+    for (word, bytes) in words.iter_mut().zip(data.chunks(4)) {
+        let mut le = [0u8; 4];
+        le[..bytes.len()].copy_from_slice(bytes);
+        *word = u32::from_le_bytes(le);
+    }
+    words
+}
+
+#[cfg(test)]
+mod archived_test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use pipeline::archive::{write_archive, ArchiveView};
+    use pipeline::asset_guid::AssetGuid;
+
+    fn as_bytes(words: &[u32], len: usize) -> &[u8] {
+        unsafe { slice::from_raw_parts(words.as_ptr() as *const u8, len) }
+    }
+
+    #[test]
+    fn archived_mesh_in_place() {
+        let vertex = |x: f32, z: f32| MeshVertex { position: [x, 0.0, z], normal: [0.0, 1.0, 0.0], uv: [x, z] };
+        let mesh = MeshAsset {
+            vertices: vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(1.0, 1.0), vertex(0.0, 1.0)],
+            lods: vec![
+                MeshLod { indices: vec![0, 1, 2, 0, 2, 3], error: 0.0, distance: 0.0 },
+                MeshLod { indices: vec![0, 1, 2], error: 0.5, distance: 10.0 },
+            ],
+        };
+        let data = archive_mesh(&mesh);
+        assert!(is_archived_mesh(data.as_slice()));
+
+        //In an archive, used from the memory of the archive.
+        let guid = AssetGuid::parse("0123456789abcdef0123456789abcdef").unwrap();
+        let mut assets = BTreeMap::new();
+        assets.insert(guid.clone(), data.clone());
+        let mut archive = Vec::new();
+        write_archive(&assets, &mut archive).unwrap();
+        let words = aligned_copy(archive.as_slice());
+        let archive = ArchiveView::from_bytes(as_bytes(words.as_slice(), archive.len())).unwrap();
+        let archived = ArchivedMesh::from_bytes(archive.get(&guid).unwrap()).unwrap();
+        assert_eq!(archived.vertices(), mesh.vertices.as_slice());
+        assert_eq!(archived.lod_indices(1), &[0, 1, 2]);
+        assert_eq!(archived.select_lod(20.0), 1);
+        assert_eq!(archived.to_mesh(), mesh);
+
+        //A vertex out of the mesh, a misaligned buffer, a truncated buffer.
+        let mut corrupted = data.clone();
+        let last = corrupted.len() - 4;
+        corrupted[last] = 9;
+        let words = aligned_copy(corrupted.as_slice());
+        assert!(ArchivedMesh::from_bytes(as_bytes(words.as_slice(), corrupted.len())).is_err());
+        let mut shifted = vec![0u8];
+        shifted.extend_from_slice(data.as_slice());
+        let words = aligned_copy(shifted.as_slice());
+        assert!(ArchivedMesh::from_bytes(&as_bytes(words.as_slice(), shifted.len())[1..]).is_err());
+        let words = aligned_copy(data.as_slice());
+        assert!(ArchivedMesh::from_bytes(as_bytes(words.as_slice(), data.len() - 8)).is_err());
+    }
+
+    #[test]
+    fn archived_navmesh_in_place() {
+        let vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, 1.0], [0.0, 0.0, 1.0], [2.0, 0.0, 0.5]];
+        let data = archive_navmesh(&vertices, &[vec![0, 1, 2, 3], vec![1, 4, 2]]);
+        let words = aligned_copy(data.as_slice());
+        let navmesh = ArchivedNavMesh::from_bytes(as_bytes(words.as_slice(), data.len())).unwrap();
+        assert_eq!(navmesh.vertices(), &vertices);
+        assert_eq!(navmesh.polygon_count(), 2);
+        assert_eq!(navmesh.polygon(1), &[1, 4, 2]);
+
+        let data = archive_navmesh(&vertices, &[vec![0, 1]]);
+        let words = aligned_copy(data.as_slice());
+        assert!(ArchivedNavMesh::from_bytes(as_bytes(words.as_slice(), data.len())).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ ASSET DATABASE.
+
+ The asset database maps the GUIDs of the assets to their current path, relative to the asset directory.
+ It is rebuilt by scanning the asset directory and reading the meta files. An asset without meta
+ file is new: a GUID is generated and its meta file is written.
+
+ Since the meta file moves with its asset, a moved or renamed asset keeps its GUID, and the
+ references using the GUID stay valid.
+ Scenes still using raw paths can be fixed up, and the references to unknown paths or GUIDs are reported.
+*/
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::io::Write;
+use maskerad_core::filesystem::filesystem::Filesystem;
+use maskerad_core::filesystem::filesystem_error::FileSystemError;
+use maskerad_core::random::RandomNumber;
+use maskerad_core::name::{NameMap, PathId};
+use pipeline::asset_guid::AssetGuid;
+use pipeline::asset_meta::{AssetMeta, meta_path, is_meta_path};
+use pipeline::pipeline_errors::PipelineResult;
+use scenes::scene_description::{SceneDescription, PropertyValue};
+
+//A reference of a scene which doesn't resolve to any known asset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingReference {
+    pub entity: u64,
+    pub component: String,
+    pub property: String,
+    //The path or the GUID.
+    pub reference: String,
+}
+
+pub struct AssetDatabase {
+    root: PathBuf,
+    paths: HashMap<AssetGuid, String>,
+    guids: NameMap<PathId, AssetGuid>,
+    random: RandomNumber,
+}
+
+impl AssetDatabase {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        AssetDatabase {
+            root: root.as_ref().to_path_buf(),
+            paths: HashMap::new(),
+            guids: NameMap::default(),
+            random: RandomNumber::new(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn guid_of(&self, path: &str) -> Option<&AssetGuid> {
+        self.guids.get(&PathId::lookup(path))
+    }
+
+    pub fn path_of(&self, guid: &AssetGuid) -> Option<&str> {
+        self.paths.get(guid).map(|path| path.as_str())
+    }
+
+    //The path and the GUID of all the assets, sorted by path.
+    pub fn assets(&self) -> Vec<(&str, &AssetGuid)> {
+        let mut assets: Vec<(&str, &AssetGuid)> = self.paths.iter()
+            .filter(|&(guid, path)| self.guids.get(&PathId::lookup(path)) == Some(guid))
+            .map(|(guid, path)| (path.as_str(), guid))
+            .collect();
+        assets.sort();
+        assets
+    }
+
+    pub fn register<S: Into<String>>(&mut self, path: S, guid: AssetGuid) {
+        let path = path.into();
+        trace!("Registering the asset {} with the GUID {}.", path, guid);
+        if let Some(old_path) = self.paths.insert(guid.clone(), path.clone()) {
+            self.guids.remove(&PathId::lookup(old_path));
+        }
+        self.guids.insert(PathId::new(path.as_str()), guid);
+    }
+
+    //Rebuild the database from the asset directory, creating the missing meta files.
+    //Returns the paths of the assets which received a new GUID.
+    pub fn scan(&mut self) -> PipelineResult<Vec<String>> {
+        debug!("Scanning the asset directory {}.", self.root.display());
+        self.paths.clear();
+        self.guids.clear();
+
+        let mut assets = Vec::new();
+        let root = self.root.clone();
+        collect_files(root.as_path(), &mut assets)?;
+        assets.sort();
+
+        let mut created = Vec::new();
+        for asset in assets {
+            let relative = relative_path(root.as_path(), asset.as_path());
+            let meta_file = meta_path(asset.as_path());
+
+            let mut meta = if meta_file.exists() {
+                Some(AssetMeta::from_reader(Filesystem::open(meta_file.as_path())?)?)
+            } else {
+                None
+            };
+
+            //A copied asset comes with the meta file of the original.
+            let duplicate = meta.as_ref().map(|meta| self.paths.contains_key(&meta.guid)).unwrap_or(false);
+            if duplicate {
+                warn!("The asset {} has the GUID of another asset, a new GUID is assigned.", relative);
+            }
+
+            if meta.is_none() || duplicate {
+                let guid = AssetGuid::generate(&mut self.random);
+                let new_meta = match meta.take() {
+                    Some(mut meta) => {
+                        meta.guid = guid;
+                        meta
+                    },
+                    None => AssetMeta::new(guid, importer_name(asset.as_path())),
+                };
+                let mut writer = Filesystem::create(meta_file.as_path())?;
+                new_meta.save(&mut writer)?;
+                writer.flush().map_err(|io_error| FileSystemError::from(io_error))?;
+                meta = Some(new_meta);
+                created.push(relative.clone());
+            }
+
+            if let Some(meta) = meta {
+                self.register(relative, meta.guid);
+            }
+        }
+        debug!("{} assets found, {} new GUIDs.", self.paths.len(), created.len());
+        Ok(created)
+    }
+
+    //Replace the path references of the scene by GUID references, and report the references to unknown assets.
+    pub fn fixup_scene(&self, scene: &mut SceneDescription) -> Vec<MissingReference> {
+        let mut missing = Vec::new();
+        for entity in scene.entities.iter_mut() {
+            for component in entity.components.iter_mut() {
+                for (property, value) in component.properties.iter_mut() {
+                    let fixed = match value {
+                        &mut PropertyValue::Asset(ref path) => match self.guid_of(path.as_str()) {
+                            Some(guid) => Some(PropertyValue::AssetGuid(guid.clone())),
+                            None => {
+                                missing.push(MissingReference {
+                                    entity: entity.id,
+                                    component: component.type_name.clone(),
+                                    property: property.clone(),
+                                    reference: path.clone(),
+                                });
+                                None
+                            },
+                        },
+                        &mut PropertyValue::AssetGuid(ref guid) => {
+                            if !self.paths.contains_key(guid) {
+                                missing.push(MissingReference {
+                                    entity: entity.id,
+                                    component: component.type_name.clone(),
+                                    property: property.clone(),
+                                    reference: guid.to_string(),
+                                });
+                            }
+                            None
+                        },
+                        _ => None,
+                    };
+                    if let Some(fixed) = fixed {
+                        *value = fixed;
+                    }
+                }
+            }
+        }
+        missing
+    }
+
+    //The "find missing references" report, without modifying the scene.
+    pub fn missing_references(&self, scene: &SceneDescription) -> Vec<MissingReference> {
+        self.fixup_scene(&mut scene.clone())
+    }
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) -> PipelineResult<()> {
+    for entry in Filesystem::read_dir(directory)? {
+        let path = entry.map_err(|io_error| FileSystemError::from(io_error))?.path();
+        if path.is_dir() {
+            collect_files(path.as_path(), files)?;
+        } else if !is_meta_path(path.as_path()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+//Relative to the asset directory, with '/' separators on every platform.
+fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative.components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+fn importer_name(path: &Path) -> String {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod asset_database_test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use scenes::scene_description::ComponentDescription;
+    use scenes::scene_description::EntityDescription;
+
+    #[test]
+    fn asset_database_guids_survive_a_move() {
+        let root = env::temp_dir().join("maskerad_asset_database_test");
+        let _ = fs::remove_dir_all(root.as_path());
+        fs::create_dir_all(root.join("meshes")).unwrap();
+        fs::write(root.join("meshes").join("house.gltf"), b"house").unwrap();
+
+        let mut database = AssetDatabase::new(root.as_path());
+        assert_eq!(database.scan().unwrap(), vec![String::from("meshes/house.gltf")]);
+        let guid = database.guid_of("meshes/house.gltf").unwrap().clone();
+
+        let mut scene = SceneDescription::new();
+        scene.entities.push(EntityDescription {
+            id: 0,
+            name: String::from("house"),
+            parent: None,
+            components: vec![
+                ComponentDescription::new("Mesh")
+                    .with_property("mesh", PropertyValue::Asset(String::from("meshes/house.gltf")))
+                    .with_property("material", PropertyValue::Asset(String::from("materials/wood.json"))),
+            ],
+        });
+        let missing = database.fixup_scene(&mut scene);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].reference, "materials/wood.json");
+        assert_eq!(scene.asset_guids(), vec![&guid]);
+
+        //Move the asset and its meta file.
+        fs::create_dir_all(root.join("buildings")).unwrap();
+        fs::rename(root.join("meshes").join("house.gltf"), root.join("buildings").join("house.gltf")).unwrap();
+        fs::rename(root.join("meshes").join("house.gltf.meta"), root.join("buildings").join("house.gltf.meta")).unwrap();
+        assert!(database.scan().unwrap().is_empty());
+        assert_eq!(database.path_of(&guid), Some("buildings/house.gltf"));
+        assert_eq!(database.missing_references(&scene).len(), 1);
+
+        //A copy gets a new GUID.
+        fs::copy(root.join("buildings").join("house.gltf"), root.join("buildings").join("house_copy.gltf")).unwrap();
+        fs::copy(root.join("buildings").join("house.gltf.meta"), root.join("buildings").join("house_copy.gltf.meta")).unwrap();
+        assert_eq!(database.scan().unwrap(), vec![String::from("buildings/house_copy.gltf")]);
+        assert_eq!(database.path_of(&guid), Some("buildings/house.gltf"));
+        assert_eq!(database.len(), 2);
+
+        fs::remove_dir_all(root.as_path()).unwrap();
+    }
+}
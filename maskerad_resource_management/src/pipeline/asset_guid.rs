@@ -0,0 +1,63 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use maskerad_core::random::RandomNumber;
+
+//A stable identifier, assigned to an asset when it is imported for the first time and stored in its
+//meta file. Assets reference each other with their GUID, so moving or renaming an asset doesn't break
+//the scenes and prefabs referencing it.
+//Stored as 32 lowercase hexadecimal digits, readable in the meta files and the text scenes.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct AssetGuid(String);
+
+impl AssetGuid {
+    pub fn generate(random: &mut RandomNumber) -> Self {
+        let parts: [u32; 4] = [random.gen(), random.gen(), random.gen(), random.gen()];
+        AssetGuid(format!("{:08x}{:08x}{:08x}{:08x}", parts[0], parts[1], parts[2], parts[3]))
+    }
+
+    pub fn parse(guid: &str) -> Option<Self> {
+        if guid.len() == 32 && guid.chars().all(|c| c.is_digit(16) && !c.is_uppercase()) {
+            Some(AssetGuid(guid.to_string()))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+//The key of the asset in the archives.
+impl AsRef<str> for AssetGuid {
+    fn as_ref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl fmt::Display for AssetGuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod asset_guid_test {
+    use super::*;
+
+    #[test]
+    fn asset_guid_generate_and_parse() {
+        let mut random = RandomNumber::from_seed([1, 2, 3, 4]);
+        let guid = AssetGuid::generate(&mut random);
+        assert_eq!(AssetGuid::parse(guid.as_str()), Some(guid.clone()));
+        assert_ne!(AssetGuid::generate(&mut random), guid);
+        assert!(AssetGuid::parse("meshes/house.gltf").is_none());
+        assert!(AssetGuid::parse("0123456789ABCDEF0123456789abcdef").is_none());
+    }
+}
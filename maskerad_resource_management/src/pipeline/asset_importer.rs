@@ -0,0 +1,31 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use pipeline::asset_meta::AssetMeta;
+use pipeline::pipeline_errors::PipelineResult;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportOutput {
+    //The imported data, written in the output directory of the pipeline.
+    pub data: Vec<u8>,
+    //The paths (or the GUIDs) of the assets used by this one (the textures of a material...).
+    //When one of them is reimported, this asset is reimported too.
+    pub dependencies: Vec<String>,
+}
+
+//Converts a source asset into the form loaded by the engine.
+pub trait AssetImporter {
+    fn name(&self) -> &str;
+
+    //Increment it when the output of the importer changes, every asset it imported is then reimported.
+    fn version(&self) -> u32;
+
+    //The extensions handled by the importer, lowercase and without dot.
+    fn extensions(&self) -> &[&str];
+
+    fn import(&self, path: &str, source: &[u8], meta: &AssetMeta) -> PipelineResult<ImportOutput>;
+}
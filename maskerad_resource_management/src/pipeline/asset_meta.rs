@@ -0,0 +1,61 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use serde_json;
+use pipeline::asset_guid::AssetGuid;
+use pipeline::pipeline_errors::PipelineResult;
+
+//The sidecar file of an asset: "meshes/house.gltf" has its meta file at "meshes/house.gltf.meta".
+//It is committed with the asset, and moved with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetMeta {
+    pub guid: AssetGuid,
+    #[serde(default)]
+    pub importer: String,
+    #[serde(default)]
+    pub importer_version: u32,
+    //Import settings, specific to the importer.
+    #[serde(default)]
+    pub settings: BTreeMap<String, String>,
+}
+
+impl AssetMeta {
+    pub fn new<S: Into<String>>(guid: AssetGuid, importer: S) -> Self {
+        AssetMeta {
+            guid,
+            importer: importer.into(),
+            importer_version: 0,
+            settings: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> PipelineResult<Self> {
+        let meta = serde_json::from_reader(reader)?;
+        Ok(meta)
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> PipelineResult<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+pub const META_EXTENSION: &'static str = "meta";
+
+pub fn meta_path<P: AsRef<Path>>(asset_path: P) -> PathBuf {
+    let mut path = asset_path.as_ref().as_os_str().to_os_string();
+    path.push(".");
+    path.push(META_EXTENSION);
+    PathBuf::from(path)
+}
+
+pub fn is_meta_path<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().extension().map(|extension| extension == META_EXTENSION).unwrap_or(false)
+}
@@ -0,0 +1,255 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ AUDIO PROCESSING.
+
+ Done by the audio importer:
+ - Resampling to the rate of the mixer, so the mixer never resamples at runtime.
+ - Loudness normalization: the integrated loudness (ITU-R BS.1770, K-weighted and gated) is measured,
+   and a gain is applied to reach the target loudness. All the sounds then have the same perceived
+   volume before mixing.
+ - The loop points and the markers follow the resampling.
+
+ The samples are planar: one Vec<f32> per channel, in [-1.0, 1.0].
+*/
+
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub name: String,
+    pub frame: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioBuffer {
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<f32>>,
+    //Start (included) and end (excluded) of the loop, in frames.
+    pub loop_points: Option<(u64, u64)>,
+    pub markers: Vec<Marker>,
+}
+
+impl AudioBuffer {
+    pub fn frames(&self) -> usize {
+        self.channels.first().map(|channel| channel.len()).unwrap_or(0)
+    }
+
+    //Read the loop points and the markers from vorbis comments:
+    //LOOPSTART and LOOPEND (or LOOPLENGTH) in frames, and MARKER as "<frame> <name>".
+    pub fn read_comments(&mut self, comments: &[(String, String)]) {
+        let find = |key: &str| {
+            comments.iter()
+                .find(|&&(ref comment, _)| comment.eq_ignore_ascii_case(key))
+                .and_then(|&(_, ref value)| value.trim().parse::<u64>().ok())
+        };
+        if let Some(start) = find("LOOPSTART") {
+            let end = find("LOOPEND")
+                .or_else(|| find("LOOPLENGTH").map(|length| start + length))
+                .unwrap_or(self.frames() as u64);
+            self.loop_points = Some((start, end));
+        }
+
+        for &(ref key, ref value) in comments.iter().filter(|&&(ref key, _)| key.eq_ignore_ascii_case("MARKER")) {
+            let mut parts = value.trim().splitn(2, ' ');
+            match (parts.next().and_then(|frame| frame.parse().ok()), parts.next()) {
+                (Some(frame), Some(name)) => self.markers.push(Marker {
+                    name: name.trim().to_string(),
+                    frame,
+                }),
+                _ => warn!("Invalid audio comment {}={}.", key, value),
+            }
+        }
+        self.markers.sort_by_key(|marker| marker.frame);
+    }
+
+    //Catmull-Rom interpolation, good enough for the usual 44.1kHz <-> 48kHz conversions.
+    pub fn resample(&self, sample_rate: u32) -> AudioBuffer {
+        if sample_rate == self.sample_rate || self.sample_rate == 0 {
+            return self.clone();
+        }
+        debug!("Resampling a sound from {}Hz to {}Hz.", self.sample_rate, sample_rate);
+        let ratio = sample_rate as f64 / self.sample_rate as f64;
+        let frames = (self.frames() as f64 * ratio).round() as usize;
+        let convert = |frame: u64| (frame as f64 * ratio).round() as u64;
+
+        let channels = self.channels.iter().map(|channel| {
+            let sample = |index: isize| channel[index.max(0).min(channel.len() as isize - 1) as usize];
+            (0..frames).map(|frame| {
+                let position = frame as f64 / ratio;
+                let index = position.floor() as isize;
+                let t = (position - index as f64) as f32;
+                let (p0, p1, p2, p3) = (sample(index - 1), sample(index), sample(index + 1), sample(index + 2));
+                0.5 * (2.0 * p1 + (p2 - p0) * t + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t * t)
+            }).collect()
+        }).collect();
+
+        AudioBuffer {
+            sample_rate,
+            channels,
+            loop_points: self.loop_points.map(|(start, end)| (convert(start), convert(end))),
+            markers: self.markers.iter().map(|marker| Marker {
+                name: marker.name.clone(),
+                frame: convert(marker.frame),
+            }).collect(),
+        }
+    }
+
+    //Integrated loudness in LUFS, None for silence or sounds shorter than a 400ms block.
+    pub fn loudness(&self) -> Option<f64> {
+        let block = (0.4 * self.sample_rate as f64) as usize;
+        let step = block / 4;
+        if block == 0 || self.frames() < block {
+            return None;
+        }
+
+        let filtered: Vec<Vec<f64>> = self.channels.iter().map(|channel| k_weighting(channel, self.sample_rate)).collect();
+        let mut block_powers = Vec::new();
+        let mut start = 0;
+        while start + block <= self.frames() {
+            let power: f64 = filtered.iter().map(|channel| {
+                channel[start..start + block].iter().map(|sample| sample * sample).sum::<f64>() / block as f64
+            }).sum();
+            block_powers.push(power);
+            start += step;
+        }
+
+        let to_lufs = |power: f64| -0.691 + 10.0 * power.log10();
+        let gated_mean = |threshold: f64| {
+            let gated: Vec<f64> = block_powers.iter().cloned().filter(|power| *power > 0.0 && to_lufs(*power) > threshold).collect();
+            if gated.is_empty() {None} else {Some(gated.iter().sum::<f64>() / gated.len() as f64)}
+        };
+        let absolute = gated_mean(-70.0)?;
+        gated_mean(to_lufs(absolute) - 10.0).map(to_lufs)
+    }
+
+    //Apply a gain to reach the target loudness. The samples going over full scale are clipped.
+    pub fn normalize_loudness(&mut self, target_lufs: f64) {
+        let loudness = match self.loudness() {
+            Some(loudness) => loudness,
+            None => {
+                trace!("The sound is too short or silent, its loudness is not normalized.");
+                return;
+            },
+        };
+        let gain = 10f64.powf((target_lufs - loudness) / 20.0) as f32;
+        debug!("Normalizing a sound from {:.1} LUFS to {:.1} LUFS (gain {:.3}).", loudness, target_lufs, gain);
+
+        let mut clipped = false;
+        for channel in self.channels.iter_mut() {
+            for sample in channel.iter_mut() {
+                *sample *= gain;
+                if sample.abs() > 1.0 {
+                    clipped = true;
+                    *sample = sample.max(-1.0).min(1.0);
+                }
+            }
+        }
+        if clipped {
+            warn!("The sound clipped while being normalized to {} LUFS.", target_lufs);
+        }
+    }
+}
+
+//The two biquads of BS.1770: a high shelf (head effects), then a high pass (RLB weighting).
+fn k_weighting(samples: &[f32], sample_rate: u32) -> Vec<f64> {
+    let rate = sample_rate as f64;
+
+    let (gain, q, frequency) = (4.0f64, 1.0 / 2f64.sqrt(), 1500.0);
+    let a = 10f64.powf(gain / 40.0);
+    let w0 = 2.0 * PI * frequency / rate;
+    let alpha = w0.sin() / (2.0 * q);
+    let cos = w0.cos();
+    let shelf = [
+        a * ((a + 1.0) + (a - 1.0) * cos + 2.0 * a.sqrt() * alpha),
+        -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+        a * ((a + 1.0) + (a - 1.0) * cos - 2.0 * a.sqrt() * alpha),
+        (a + 1.0) - (a - 1.0) * cos + 2.0 * a.sqrt() * alpha,
+        2.0 * ((a - 1.0) - (a + 1.0) * cos),
+        (a + 1.0) - (a - 1.0) * cos - 2.0 * a.sqrt() * alpha,
+    ];
+
+    let (q, frequency) = (0.5, 38.0);
+    let w0 = 2.0 * PI * frequency / rate;
+    let alpha = w0.sin() / (2.0 * q);
+    let cos = w0.cos();
+    let high_pass = [
+        (1.0 + cos) / 2.0,
+        -(1.0 + cos),
+        (1.0 + cos) / 2.0,
+        1.0 + alpha,
+        -2.0 * cos,
+        1.0 - alpha,
+    ];
+
+    let shelved = biquad(samples.iter().map(|sample| *sample as f64), shelf);
+    biquad(shelved.into_iter(), high_pass)
+}
+
+//[b0, b1, b2, a0, a1, a2]
+fn biquad<I: Iterator<Item = f64>>(samples: I, coefficients: [f64; 6]) -> Vec<f64> {
+    let [b0, b1, b2, a0, a1, a2] = coefficients;
+    let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+    samples.map(|x| {
+        let y = (b0 * x + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2) / a0;
+        x2 = x1;
+        x1 = x;
+        y2 = y1;
+        y1 = y;
+        y
+    }).collect()
+}
+
+#[cfg(test)]
+mod audio_processing_test {
+    use super::*;
+
+    fn sine(sample_rate: u32, seconds: f32, amplitude: f32) -> AudioBuffer {
+        let frames = (sample_rate as f32 * seconds) as usize;
+        let channel: Vec<f32> = (0..frames).map(|frame| {
+            amplitude * (2.0 * ::std::f32::consts::PI * 1000.0 * frame as f32 / sample_rate as f32).sin()
+        }).collect();
+        AudioBuffer {
+            sample_rate,
+            channels: vec![channel.clone(), channel],
+            loop_points: None,
+            markers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn audio_processing_loop_points_follow_resampling() {
+        let mut buffer = sine(44100, 1.0, 0.5);
+        buffer.read_comments(&[
+            (String::from("LOOPSTART"), String::from("44100")),
+            (String::from("LOOPLENGTH"), String::from("22050")),
+            (String::from("marker"), String::from("11025 footstep")),
+            (String::from("MARKER"), String::from("broken")),
+        ]);
+        assert_eq!(buffer.loop_points, Some((44100, 66150)));
+        assert_eq!(buffer.markers.len(), 1);
+
+        let resampled = buffer.resample(48000);
+        assert_eq!(resampled.frames(), 48000);
+        assert_eq!(resampled.loop_points, Some((48000, 72000)));
+        assert_eq!(resampled.markers[0].frame, 12000);
+        assert!(resampled.channels[0].iter().all(|sample| sample.abs() <= 0.51));
+    }
+
+    #[test]
+    fn audio_processing_loudness_normalization() {
+        let mut quiet = sine(48000, 2.0, 0.05);
+        let loud = sine(48000, 2.0, 0.5);
+        let difference = loud.loudness().unwrap() - quiet.loudness().unwrap();
+        assert!((difference - 20.0).abs() < 0.01);
+
+        quiet.normalize_loudness(-16.0);
+        assert!((quiet.loudness().unwrap() + 16.0).abs() < 0.01);
+        assert!(sine(48000, 0.1, 0.5).loudness().is_none());
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::hash::Hasher;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use maskerad_core::filesystem::filesystem::Filesystem;
+use maskerad_core::filesystem::filesystem_error::FileSystemError;
+use pipeline::import_cache::StableHasher;
+use pipeline::pipeline_errors::PipelineResult;
+
+//Expensive import results (compressed textures, processed sounds...), stored by the hash of
+//everything used to produce them. Two assets with the same source and settings share the result,
+//and reverting a change finds the previous result again.
+pub struct ContentCache {
+    directory: PathBuf,
+}
+
+impl ContentCache {
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        ContentCache {
+            directory: directory.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn key(parts: &[&[u8]]) -> String {
+        let mut hasher = StableHasher::default();
+        for part in parts {
+            hasher.write_usize(part.len());
+            hasher.write(part);
+        }
+        format!("{:016x}", hasher.finish())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory.join(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let path = self.path(key);
+        if !path.exists() {
+            return None;
+        }
+        let mut data = Vec::new();
+        match Filesystem::open(path.as_path()).map(|mut reader| reader.read_to_end(&mut data)) {
+            Ok(Ok(_)) => {
+                trace!("Content cache hit for {}.", key);
+                Some(data)
+            },
+            _ => {
+                warn!("Could not read the content cache entry {}.", key);
+                None
+            },
+        }
+    }
+
+    pub fn put(&self, key: &str, data: &[u8]) -> PipelineResult<()> {
+        Filesystem::mkdir(self.directory.as_path())?;
+        let mut writer = Filesystem::create(self.path(key))?;
+        writer.write_all(data).and_then(|_| writer.flush()).map_err(|io_error| FileSystemError::from(io_error))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//The manifest of the cooked content (manifest.json, at the root of the content directory).
+//The engine reads it at startup to know in which archive each asset is.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use serde_json;
+use pipeline::asset_guid::AssetGuid;
+use pipeline::pipeline_errors::PipelineResult;
+
+pub const MANIFEST_FILE: &'static str = "manifest.json";
+pub const ARCHIVE_DIRECTORY: &'static str = "archives";
+pub const ARCHIVE_EXTENSION: &'static str = "kpak";
+pub const BASE_PACKAGE: &'static str = "base";
+
+//The base game, or a DLC.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestPackage {
+    pub name: String,
+    pub version: String,
+    //The min version of the base game, for a DLC. The major version must be the same.
+    #[serde(default)]
+    pub requires_game: Option<String>,
+}
+
+impl Default for ManifestPackage {
+    fn default() -> Self {
+        ManifestPackage {
+            name: String::from(BASE_PACKAGE),
+            version: String::from("0.0.0"),
+            requires_game: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestArchive {
+    //The file, relative to the content directory.
+    pub file: String,
+    pub size: u64,
+    //Hash of the archive, to detect corrupted installations.
+    pub hash: String,
+    pub assets: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestAsset {
+    //The source path, for the logs and the tools.
+    pub path: String,
+    pub archive: String,
+    pub importer: String,
+    #[serde(default)]
+    pub dependencies: Vec<AssetGuid>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContentManifest {
+    #[serde(default)]
+    pub package: ManifestPackage,
+    //The target profile the content has been cooked for.
+    pub profile: String,
+    //By name.
+    pub archives: BTreeMap<String, ManifestArchive>,
+    pub assets: BTreeMap<AssetGuid, ManifestAsset>,
+}
+
+impl ContentManifest {
+    pub fn new<S: Into<String>>(profile: S) -> Self {
+        ContentManifest {
+            package: ManifestPackage::default(),
+            profile: profile.into(),
+            archives: BTreeMap::new(),
+            assets: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> PipelineResult<Self> {
+        let manifest = serde_json::from_reader(reader)?;
+        Ok(manifest)
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> PipelineResult<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    pub fn asset(&self, guid: &AssetGuid) -> Option<&ManifestAsset> {
+        self.assets.get(guid)
+    }
+
+    //The archive containing the asset.
+    pub fn archive_of(&self, guid: &AssetGuid) -> Option<&ManifestArchive> {
+        self.assets.get(guid).and_then(|asset| self.archives.get(asset.archive.as_str()))
+    }
+
+    //The GUID of an asset from its source path.
+    pub fn guid_of(&self, path: &str) -> Option<&AssetGuid> {
+        self.assets.iter().find(|&(_, asset)| asset.path == path).map(|(guid, _)| guid)
+    }
+
+    //The source paths of the assets of an archive, to mount it as an ArchiveFilesystem (see with_path).
+    pub fn paths_in(&self, archive: &str) -> Vec<(&str, &AssetGuid)> {
+        self.assets.iter()
+            .filter(|&(_, asset)| asset.archive == archive)
+            .map(|(guid, asset)| (asset.path.as_str(), guid))
+            .collect()
+    }
+}
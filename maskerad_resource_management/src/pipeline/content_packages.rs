@@ -0,0 +1,284 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ CONTENT PACKAGES.
+
+ The content of the game is made of packages: the base game, then the DLCs and expansions. Each package
+ is a cooked content directory (see cook), with its manifest.
+
+ The packages are mounted at startup (every package found in the DLC directory), or on demand (after a
+ purchase). Before being mounted, the manifest of a package is checked:
+ - it must be cooked for the same target profile as the base game,
+ - the base game must be recent enough: same major version, and at least the version required by the DLC.
+
+ The packages mounted last have the priority: a DLC can replace an asset of the base game.
+ Gameplay polls the content events to know when new content is available.
+*/
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use maskerad_core::filesystem::filesystem::Filesystem;
+use maskerad_core::filesystem::filesystem_error::FileSystemError;
+use pipeline::asset_guid::AssetGuid;
+use pipeline::content_manifest::{ContentManifest, MANIFEST_FILE};
+use pipeline::pipeline_errors::{PipelineError, PipelineResult};
+
+//major.minor.patch
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct GameVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl GameVersion {
+    //The missing numbers are 0: "1.2" is 1.2.0.
+    pub fn parse(version: &str) -> PipelineResult<Self> {
+        let numbers = version.trim().split('.')
+            .map(|number| number.parse::<u32>())
+            .collect::<Result<Vec<u32>, _>>()
+            .ok()
+            .filter(|numbers| !numbers.is_empty() && numbers.len() <= 3)
+            .ok_or_else(|| PipelineError::PackageError(format!("Invalid version {}.", version)))?;
+        Ok(GameVersion {
+            major: numbers[0],
+            minor: numbers.get(1).cloned().unwrap_or(0),
+            patch: numbers.get(2).cloned().unwrap_or(0),
+        })
+    }
+
+    //The game can run content requiring the given version.
+    pub fn satisfies(&self, required: &GameVersion) -> bool {
+        self.major == required.major && self.cmp(required) != Ordering::Less
+    }
+}
+
+impl fmt::Display for GameVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentEvent {
+    PackageMounted {
+        package: String,
+        version: String,
+        assets: usize,
+    },
+    PackageUnmounted {
+        package: String,
+    },
+    //A package found at startup which can't be mounted.
+    PackageRejected {
+        directory: PathBuf,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MountedPackage {
+    pub directory: PathBuf,
+    pub manifest: ContentManifest,
+}
+
+impl MountedPackage {
+    pub fn name(&self) -> &str {
+        self.manifest.package.name.as_str()
+    }
+}
+
+pub struct ContentPackages {
+    game_version: GameVersion,
+    profile: String,
+    //In mount order.
+    packages: Vec<MountedPackage>,
+    events: Vec<ContentEvent>,
+}
+
+impl ContentPackages {
+    pub fn new(game_version: GameVersion, profile: &str) -> Self {
+        ContentPackages {
+            game_version,
+            profile: profile.to_string(),
+            packages: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn packages(&self) -> &[MountedPackage] {
+        self.packages.as_slice()
+    }
+
+    pub fn is_mounted(&self, package: &str) -> bool {
+        self.packages.iter().any(|mounted| mounted.name() == package)
+    }
+
+    pub fn check_compatibility(&self, manifest: &ContentManifest) -> PipelineResult<()> {
+        if manifest.profile != self.profile {
+            return Err(PipelineError::PackageError(format!("The package {} is cooked for {}, the game for {}.",
+                                                           manifest.package.name, manifest.profile, self.profile)));
+        }
+        if let Some(ref required) = manifest.package.requires_game {
+            let required = GameVersion::parse(required.as_str())?;
+            if !self.game_version.satisfies(&required) {
+                return Err(PipelineError::PackageError(format!("The package {} requires the version {} of the game, the game is in version {}.",
+                                                               manifest.package.name, required, self.game_version)));
+            }
+        }
+        if self.is_mounted(manifest.package.name.as_str()) {
+            return Err(PipelineError::PackageError(format!("The package {} is already mounted.", manifest.package.name)));
+        }
+        Ok(())
+    }
+
+    //Mount the content directory of a package.
+    pub fn mount<P: AsRef<Path>>(&mut self, directory: P) -> PipelineResult<()> {
+        let directory = directory.as_ref();
+        let manifest = ContentManifest::from_reader(Filesystem::open(directory.join(MANIFEST_FILE))?)?;
+        self.check_compatibility(&manifest)?;
+
+        debug!("Mounting the package {} {} ({} assets).", manifest.package.name, manifest.package.version, manifest.assets.len());
+        self.events.push(ContentEvent::PackageMounted {
+            package: manifest.package.name.clone(),
+            version: manifest.package.version.clone(),
+            assets: manifest.assets.len(),
+        });
+        self.packages.push(MountedPackage {
+            directory: directory.to_path_buf(),
+            manifest,
+        });
+        Ok(())
+    }
+
+    //Mount the packages of the subdirectories of the DLC directory which are not mounted yet, in name order.
+    //Returns the names of the mounted packages. The incompatible packages are reported by the events.
+    pub fn discover<P: AsRef<Path>>(&mut self, dlc_directory: P) -> PipelineResult<Vec<String>> {
+        let mut directories = Vec::new();
+        for entry in Filesystem::read_dir(dlc_directory.as_ref())? {
+            let path = entry.map_err(|io_error| FileSystemError::from(io_error))?.path();
+            if path.join(MANIFEST_FILE).is_file() && !self.packages.iter().any(|package| package.directory == path) {
+                directories.push(path);
+            }
+        }
+        directories.sort();
+
+        let mut mounted = Vec::new();
+        for directory in directories {
+            match self.mount(directory.as_path()) {
+                Ok(()) => mounted.push(self.packages[self.packages.len() - 1].name().to_string()),
+                Err(error) => {
+                    warn!("The package in {} is not mounted: {}", directory.display(), error);
+                    self.events.push(ContentEvent::PackageRejected {
+                        directory,
+                        reason: error.to_string(),
+                    });
+                },
+            }
+        }
+        Ok(mounted)
+    }
+
+    pub fn unmount(&mut self, package: &str) -> bool {
+        match self.packages.iter().position(|mounted| mounted.name() == package) {
+            Some(index) => {
+                debug!("Unmounting the package {}.", package);
+                self.packages.remove(index);
+                self.events.push(ContentEvent::PackageUnmounted {
+                    package: package.to_string(),
+                });
+                true
+            },
+            None => false,
+        }
+    }
+
+    //The archive containing the asset, in the last mounted package having it.
+    pub fn locate(&self, guid: &AssetGuid) -> Option<PathBuf> {
+        self.packages.iter().rev()
+            .filter_map(|package| package.manifest.archive_of(guid).map(|archive| package.directory.join(archive.file.as_str())))
+            .next()
+    }
+
+    //The events since the last call.
+    pub fn poll_events(&mut self) -> Vec<ContentEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod content_packages_test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use pipeline::content_manifest::{ManifestArchive, ManifestAsset};
+
+    fn write_package(directory: &Path, name: &str, requires_game: Option<&str>, profile: &str, asset: &AssetGuid) {
+        let mut manifest = ContentManifest::new(profile);
+        manifest.package.name = name.to_string();
+        manifest.package.version = String::from("1.0");
+        manifest.package.requires_game = requires_game.map(|version| version.to_string());
+        manifest.archives.insert(String::from("common"), ManifestArchive {
+            file: String::from("archives/common.kpak"),
+            size: 0,
+            hash: String::new(),
+            assets: 1,
+        });
+        manifest.assets.insert(asset.clone(), ManifestAsset {
+            path: String::from("house.obj"),
+            archive: String::from("common"),
+            importer: String::from("mesh"),
+            dependencies: Vec::new(),
+        });
+        fs::create_dir_all(directory).unwrap();
+        manifest.save(fs::File::create(directory.join(MANIFEST_FILE)).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn content_packages_versions() {
+        let version = GameVersion::parse("1.4").unwrap();
+        assert_eq!(version.to_string(), "1.4.0");
+        assert!(version.satisfies(&GameVersion::parse("1.3.9").unwrap()));
+        assert!(!version.satisfies(&GameVersion::parse("1.5").unwrap()));
+        assert!(!version.satisfies(&GameVersion::parse("0.9").unwrap()));
+        assert!(GameVersion::parse("1.x").is_err());
+    }
+
+    #[test]
+    fn content_packages_mount_and_discover() {
+        let directory = env::temp_dir().join("maskerad_content_packages_test");
+        let _ = fs::remove_dir_all(directory.as_path());
+        let house = AssetGuid::parse("0123456789abcdef0123456789abcdef").unwrap();
+        write_package(directory.join("base").as_path(), "base", None, "desktop-high", &house);
+        write_package(directory.join("dlc/castle").as_path(), "castle", Some("1.2"), "desktop-high", &house);
+        write_package(directory.join("dlc/future").as_path(), "future", Some("1.9"), "desktop-high", &house);
+        write_package(directory.join("dlc/mobile").as_path(), "mobile", None, "mobile", &house);
+
+        let mut packages = ContentPackages::new(GameVersion::parse("1.4.2").unwrap(), "desktop-high");
+        packages.mount(directory.join("base")).unwrap();
+        assert_eq!(packages.locate(&house), Some(directory.join("base/archives/common.kpak")));
+        assert!(packages.mount(directory.join("base")).is_err());
+
+        assert_eq!(packages.discover(directory.join("dlc")).unwrap(), vec![String::from("castle")]);
+        assert_eq!(packages.locate(&house), Some(directory.join("dlc/castle/archives/common.kpak")));
+        let events = packages.poll_events();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[1], ContentEvent::PackageMounted { package: String::from("castle"), version: String::from("1.0"), assets: 1 });
+        match events[2] {
+            ContentEvent::PackageRejected { ref directory, .. } => assert!(directory.ends_with("future")),
+            _ => panic!("The future package should be rejected."),
+        }
+        assert!(packages.discover(directory.join("dlc")).unwrap().is_empty());
+
+        assert!(packages.unmount("castle"));
+        assert_eq!(packages.locate(&house), Some(directory.join("base/archives/common.kpak")));
+        assert_eq!(packages.poll_events().len(), 3);
+        fs::remove_dir_all(directory.as_path()).unwrap();
+    }
+}
@@ -0,0 +1,396 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ COOK.
+
+ Builds the content shipped with the game, for a target profile (see target_profile):
+ - The assets are imported for the profile (incrementally: the imported assets and the import cache of each
+   profile, and the content cache, are kept in an intermediate directory between two cooks).
+ - The scenes are validated. The content is not written if an import failed or a scene has errors.
+ - The assets are bundled into archives, following the dependency graph. The top-level scenes (the scenes
+   which are not included by another one) are the roots:
+   - an asset used by one root only goes in the archive of this root,
+   - an asset used by several roots goes in the "shared" archive,
+   - an asset used by no root goes in the "common" archive, loaded at startup.
+ - The manifest is generated.
+ - The string tables are checked against the localization keys of the scenes, and baked per locale (see
+   string_tables). The content is not written if a key is missing.
+
+ The content directory:
+ manifest.json
+ archives/<name>.kpak
+ localization/<locale>.json
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::hash::Hasher;
+use std::time::{Duration, Instant};
+use maskerad_core::filesystem::filesystem::Filesystem;
+use maskerad_core::filesystem::filesystem_error::FileSystemError;
+use pipeline::archive::write_archive;
+use pipeline::asset_database::AssetDatabase;
+use pipeline::asset_guid::AssetGuid;
+use pipeline::content_cache::ContentCache;
+use pipeline::content_manifest::{ContentManifest, ManifestArchive, ManifestAsset, ManifestPackage, MANIFEST_FILE, ARCHIVE_DIRECTORY, ARCHIVE_EXTENSION};
+use pipeline::import_cache::{ImportCache, StableHasher};
+use pipeline::incremental_import::{ImportPipeline, ImportReport};
+use pipeline::importers::audio_importer::AudioImporter;
+use pipeline::importers::mesh_importer::MeshImporter;
+use pipeline::importers::scene_importer::SceneImporter;
+use pipeline::importers::texture_importer::TextureImporter;
+use pipeline::pipeline_errors::PipelineResult;
+use pipeline::scene_validation::{SceneValidator, ValidationReport};
+use pipeline::string_tables::{scene_keys, KeyUsage, StringTables, LOCALIZATION_DIRECTORY};
+use pipeline::target_profile::TargetProfile;
+use scenes::scene_description::SceneDescription;
+use scenes::scene_errors::SceneError;
+
+pub const SHARED_ARCHIVE: &'static str = "shared";
+pub const COMMON_ARCHIVE: &'static str = "common";
+const IMPORT_CACHE_FILE: &'static str = "import_cache.json";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CookSettings {
+    pub source_directory: PathBuf,
+    pub output_directory: PathBuf,
+    pub intermediate_directory: PathBuf,
+    pub profile: TargetProfile,
+    //The base game by default.
+    pub package: ManifestPackage,
+    //The locale the texts are written in, "en" by default.
+    pub reference_locale: String,
+}
+
+impl CookSettings {
+    pub fn new<P: AsRef<Path>>(source_directory: P, output_directory: P, intermediate_directory: P, profile: TargetProfile) -> Self {
+        CookSettings {
+            source_directory: source_directory.as_ref().to_path_buf(),
+            output_directory: output_directory.as_ref().to_path_buf(),
+            intermediate_directory: intermediate_directory.as_ref().to_path_buf(),
+            profile,
+            package: ManifestPackage::default(),
+            reference_locale: String::from("en"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CookedArchive {
+    pub name: String,
+    pub assets: usize,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CookReport {
+    pub profile: String,
+    pub import: ImportReport,
+    //The scenes with diagnostics.
+    pub validation: Vec<(String, ValidationReport)>,
+    //Empty if the cook failed.
+    pub archives: Vec<CookedArchive>,
+    pub duration: Duration,
+}
+
+impl CookReport {
+    pub fn succeeded(&self) -> bool {
+        self.import.failed.is_empty() && self.validation.iter().all(|&(_, ref report)| !report.has_errors())
+    }
+}
+
+impl fmt::Display for CookReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Cook report ({}):", self.profile)?;
+        writeln!(f, "Import: {} imported, {} up to date, {} failed, {} removed.",
+                 self.import.imported.len(), self.import.up_to_date, self.import.failed.len(), self.import.removed)?;
+        for &(ref path, ref error) in self.import.failed.iter() {
+            writeln!(f, "  {}: {}", path, error)?;
+        }
+        for &(ref path, ref report) in self.validation.iter() {
+            writeln!(f, "Validation of {}:", path)?;
+            for diagnostic in report.diagnostics.iter() {
+                writeln!(f, "  {}", diagnostic)?;
+            }
+        }
+        for archive in self.archives.iter() {
+            writeln!(f, "Archive {}: {} assets, {} bytes.", archive.name, archive.assets, archive.size)?;
+        }
+        let seconds = self.duration.as_secs() as f64 + self.duration.subsec_nanos() as f64 / 1e9;
+        write!(f, "{} in {:.2}s.", if self.succeeded() {"Succeeded"} else {"Failed"}, seconds)
+    }
+}
+
+fn is_scene(path: &str) -> bool {
+    path.ends_with(".scene")
+}
+
+//"levels/village.scene" -> "levels_village"
+fn archive_name(path: &str) -> String {
+    let stem = if is_scene(path) {&path[..path.len() - ".scene".len()]} else {path};
+    stem.chars().map(|c| if c.is_alphanumeric() || c == '-' {c} else {'_'}).collect()
+}
+
+fn read_file(path: &Path) -> PipelineResult<Vec<u8>> {
+    let mut data = Vec::new();
+    Filesystem::open(path)?.read_to_end(&mut data).map_err(|io_error| FileSystemError::from(io_error))?;
+    Ok(data)
+}
+
+fn validate_scenes(database: &AssetDatabase) -> Vec<(String, ValidationReport)> {
+    let validator = SceneValidator::default();
+    let mut reports = Vec::new();
+    for (path, _) in database.assets().into_iter().filter(|&(path, _)| is_scene(path)) {
+        let load = |path: &str| {
+            let reader = Filesystem::open(database.root().join(path)).map_err(|error| {
+                SceneError::IncludeError(format!("Could not open the scene {}: {}", path, error))
+            })?;
+            SceneDescription::from_reader(reader)
+        };
+        let scene = load(path).and_then(|scene| scene.flatten(load));
+        let report = match scene {
+            Ok(scene) => validator.validate(&scene, Some(database)),
+            //Already reported by the import.
+            Err(_) => continue,
+        };
+        if !report.diagnostics.is_empty() {
+            reports.push((path.to_string(), report));
+        }
+    }
+    reports
+}
+
+//The localization keys used by the scenes.
+fn localization_keys(database: &AssetDatabase) -> Vec<KeyUsage> {
+    let mut usages = Vec::new();
+    for (path, _) in database.assets().into_iter().filter(|&(path, _)| is_scene(path)) {
+        let scene = Filesystem::open(database.root().join(path)).ok().and_then(|reader| SceneDescription::from_reader(reader).ok());
+        //The broken scenes are reported by the import.
+        if let Some(scene) = scene {
+            usages.extend(scene_keys(path, &scene));
+        }
+    }
+    usages
+}
+
+//The archive of each imported asset.
+fn bundle(cache: &ImportCache, database: &AssetDatabase) -> BTreeMap<AssetGuid, String> {
+    let imported: Vec<&AssetGuid> = cache.guids().into_iter().filter(|guid| database.path_of(guid).is_some()).collect();
+    let roots: Vec<&AssetGuid> = imported.iter()
+        .cloned()
+        .filter(|guid| database.path_of(guid).map(is_scene).unwrap_or(false))
+        .filter(|guid| cache.dependents(guid).is_empty())
+        .collect();
+
+    let mut users: BTreeMap<&AssetGuid, BTreeSet<&AssetGuid>> = BTreeMap::new();
+    for root in roots.iter() {
+        let mut stack = vec![*root];
+        while let Some(guid) = stack.pop() {
+            if users.entry(guid).or_insert_with(BTreeSet::new).insert(root) {
+                if let Some(record) = cache.get(guid) {
+                    stack.extend(record.dependencies.iter());
+                }
+            }
+        }
+    }
+
+    imported.into_iter().map(|guid| {
+        let archive = match users.get(guid) {
+            Some(roots) if roots.len() == 1 => {
+                let root = roots.iter().next().expect("The set has one element.");
+                archive_name(database.path_of(root).expect("The roots are in the database."))
+            },
+            Some(_) => SHARED_ARCHIVE.to_string(),
+            None => COMMON_ARCHIVE.to_string(),
+        };
+        (guid.clone(), archive)
+    }).collect()
+}
+
+//Import, validate, bundle and write the content directory.
+//Returns an error if the cook could not run, and a failed report if the content has errors.
+pub fn cook(settings: &CookSettings) -> PipelineResult<CookReport> {
+    let start = Instant::now();
+    debug!("Cooking {} for {} into {}.", settings.source_directory.display(), settings.profile.name, settings.output_directory.display());
+
+    let mut database = AssetDatabase::new(settings.source_directory.as_path());
+    database.scan()?;
+
+    let profile_directory = settings.intermediate_directory.join(settings.profile.name.as_str());
+    Filesystem::mkdir(profile_directory.as_path())?;
+    let cache_path = profile_directory.join(IMPORT_CACHE_FILE);
+    let cache = if cache_path.exists() {
+        ImportCache::from_reader(Filesystem::open(cache_path.as_path())?)?
+    } else {
+        ImportCache::new()
+    };
+    let content_cache = || ContentCache::new(settings.intermediate_directory.join("content"));
+    let mut pipeline = ImportPipeline::new(profile_directory.join("imported"))
+        .with_cache(cache)
+        .with_profile(settings.profile.clone());
+    pipeline.add_importer(Box::new(TextureImporter::for_profile(&settings.profile).with_cache(content_cache())));
+    pipeline.add_importer(Box::new(AudioImporter::for_profile(&settings.profile).with_cache(content_cache())));
+    pipeline.add_importer(Box::new(MeshImporter::for_profile(&settings.profile)));
+    pipeline.add_importer(Box::new(SceneImporter::new()));
+
+    let import = pipeline.import(&database)?;
+    pipeline.cache().save(Filesystem::create(cache_path.as_path())?)?;
+
+    let mut report = CookReport {
+        profile: settings.profile.name.clone(),
+        import,
+        validation: validate_scenes(&database),
+        archives: Vec::new(),
+        duration: Duration::from_secs(0),
+    };
+    let strings = StringTables::from_database(&database, settings.reference_locale.as_str())?;
+    if !strings.locales().is_empty() {
+        let localization = strings.check(localization_keys(&database).as_slice());
+        if !localization.diagnostics.is_empty() {
+            report.validation.push((LOCALIZATION_DIRECTORY.to_string(), localization));
+        }
+    }
+    if !report.succeeded() {
+        report.duration = start.elapsed();
+        error!("The content has errors, nothing is written.\n{}", report);
+        return Ok(report);
+    }
+
+    //Bundle.
+    let archives_of = bundle(pipeline.cache(), &database);
+    let mut archives: BTreeMap<&str, BTreeMap<AssetGuid, Vec<u8>>> = BTreeMap::new();
+    let mut manifest = ContentManifest::new(settings.profile.name.as_str());
+    manifest.package = settings.package.clone();
+    for (guid, archive) in archives_of.iter() {
+        let record = pipeline.cache().get(guid).expect("The bundled assets have been imported.");
+        archives.entry(archive.as_str()).or_insert_with(BTreeMap::new)
+            .insert(guid.clone(), read_file(pipeline.output_path(guid).as_path())?);
+        manifest.assets.insert(guid.clone(), ManifestAsset {
+            path: database.path_of(guid).unwrap_or_default().to_string(),
+            archive: archive.clone(),
+            importer: record.importer.clone(),
+            dependencies: record.dependencies.clone(),
+        });
+    }
+
+    //Write the content directory. The archives of the previous cook are removed.
+    let archive_directory = settings.output_directory.join(ARCHIVE_DIRECTORY);
+    if archive_directory.exists() {
+        Filesystem::rmrf(archive_directory.as_path())?;
+    }
+    Filesystem::mkdir(archive_directory.as_path())?;
+    for (name, assets) in archives.iter() {
+        let file = format!("{}/{}.{}", ARCHIVE_DIRECTORY, name, ARCHIVE_EXTENSION);
+        let mut data = Vec::new();
+        let size = write_archive(assets, &mut data)?;
+        Filesystem::create(settings.output_directory.join(file.as_str()))?
+            .write_all(data.as_slice())
+            .map_err(|io_error| FileSystemError::from(io_error))?;
+
+        let mut hasher = StableHasher::default();
+        hasher.write(data.as_slice());
+        manifest.archives.insert(name.to_string(), ManifestArchive {
+            file,
+            size,
+            hash: format!("{:016x}", hasher.finish()),
+            assets: assets.len(),
+        });
+        report.archives.push(CookedArchive {
+            name: name.to_string(),
+            assets: assets.len(),
+            size,
+        });
+    }
+    manifest.save(Filesystem::create(settings.output_directory.join(MANIFEST_FILE))?)?;
+
+    let localization_directory = settings.output_directory.join(LOCALIZATION_DIRECTORY);
+    if localization_directory.exists() {
+        Filesystem::rmrf(localization_directory.as_path())?;
+    }
+    for locale in strings.locales() {
+        Filesystem::mkdir(localization_directory.as_path())?;
+        Filesystem::create(localization_directory.join(format!("{}.json", locale)))?
+            .write_all(strings.bake(locale)?.as_slice())
+            .map_err(|io_error| FileSystemError::from(io_error))?;
+    }
+
+    report.duration = start.elapsed();
+    info!("{}", report);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod cook_test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use pipeline::archive::Archive;
+
+    const TRIANGLE: &'static str = "v 0 0 0\nv 1 0 0\nv 0 0 1\nf 1 2 3\n";
+
+    fn scene(meshes: &[&str]) -> String {
+        let mut scene = SceneDescription::new();
+        for (id, mesh) in meshes.iter().enumerate() {
+            scene.entities.push(::scenes::scene_description::EntityDescription {
+                id: id as u64,
+                name: mesh.to_string(),
+                parent: None,
+                components: vec![::scenes::scene_description::ComponentDescription::new("Mesh")
+                    .with_property("mesh", ::scenes::scene_description::PropertyValue::Asset(mesh.to_string()))],
+            });
+        }
+        let mut text = Vec::new();
+        scene.save(&mut text).unwrap();
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn cook_bundles_by_dependencies() {
+        let directory = env::temp_dir().join("maskerad_cook_test");
+        let _ = fs::remove_dir_all(directory.as_path());
+        let source = directory.join("assets");
+        fs::create_dir_all(source.join("levels")).unwrap();
+        for mesh in &["house.obj", "tree.obj", "rock.obj", "unused.obj"] {
+            fs::write(source.join(mesh), TRIANGLE).unwrap();
+        }
+        fs::write(source.join("levels/village.scene"), scene(&["house.obj", "tree.obj"])).unwrap();
+        fs::write(source.join("levels/forest.scene"), scene(&["tree.obj", "rock.obj"])).unwrap();
+        fs::create_dir_all(source.join("localization/en")).unwrap();
+        fs::write(source.join("localization/en/localization.json"), r#"{"title": "Village"}"#).unwrap();
+
+        let settings = CookSettings::new(source.clone(), directory.join("content"), directory.join("intermediate"), TargetProfile::mobile());
+        let report = cook(&settings).unwrap();
+        assert!(report.succeeded());
+        assert_eq!(report.import.imported.len(), 6);
+        let names: Vec<&str> = report.archives.iter().map(|archive| archive.name.as_str()).collect();
+        assert_eq!(names, vec!["common", "levels_forest", "levels_village", "shared"]);
+
+        let manifest = ContentManifest::from_reader(fs::File::open(directory.join("content/manifest.json")).unwrap()).unwrap();
+        let tree = manifest.guid_of("tree.obj").unwrap().clone();
+        assert_eq!(manifest.asset(&tree).unwrap().archive, "shared");
+        assert_eq!(manifest.asset(manifest.guid_of("house.obj").unwrap()).unwrap().archive, "levels_village");
+        assert_eq!(manifest.asset(manifest.guid_of("unused.obj").unwrap()).unwrap().archive, "common");
+        let shared = manifest.archive_of(&tree).unwrap();
+        let mut archive = Archive::from_reader(fs::File::open(directory.join("content").join(shared.file.as_str())).unwrap()).unwrap();
+        assert!(archive.read(&tree).unwrap().is_some());
+        //The unused key is a warning, the table is baked.
+        assert_eq!(report.validation[0].1.diagnostics[0].rule, "unused-string");
+        assert!(directory.join("content/localization/en.json").exists());
+
+        //A broken scene: nothing is written.
+        fs::write(source.join("levels/forest.scene"), scene(&["missing.obj"])).unwrap();
+        let report = cook(&settings).unwrap();
+        assert!(!report.succeeded());
+        assert_eq!(report.import.up_to_date, 5);
+        assert!(report.archives.is_empty());
+
+        fs::remove_dir_all(directory.as_path()).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::BTreeMap;
+use std::hash::Hasher;
+use std::io::{Read, Write};
+use serde_json;
+use pipeline::asset_guid::AssetGuid;
+use pipeline::pipeline_errors::PipelineResult;
+
+//What the asset has been imported from, the last time it was imported.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportRecord {
+    //Hash of the source file and of the import settings.
+    pub input_hash: u64,
+    pub importer: String,
+    pub importer_version: u32,
+    #[serde(default)]
+    pub dependencies: Vec<AssetGuid>,
+}
+
+//Saved in the cache directory of the pipeline between two runs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportCache {
+    records: BTreeMap<AssetGuid, ImportRecord>,
+}
+
+impl ImportCache {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> PipelineResult<Self> {
+        debug!("Deserializing the import cache.");
+        let cache = serde_json::from_reader(reader)?;
+        Ok(cache)
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> PipelineResult<()> {
+        debug!("Serializing the import cache ({} records).", self.records.len());
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn get(&self, guid: &AssetGuid) -> Option<&ImportRecord> {
+        self.records.get(guid)
+    }
+
+    pub fn insert(&mut self, guid: AssetGuid, record: ImportRecord) {
+        self.records.insert(guid, record);
+    }
+
+    pub fn remove(&mut self, guid: &AssetGuid) -> Option<ImportRecord> {
+        self.records.remove(guid)
+    }
+
+    //The assets which were imported with a dependency on the given one.
+    pub fn dependents(&self, guid: &AssetGuid) -> Vec<&AssetGuid> {
+        self.records.iter()
+            .filter(|&(_, record)| record.dependencies.contains(guid))
+            .map(|(dependent, _)| dependent)
+            .collect()
+    }
+
+    pub fn guids(&self) -> Vec<&AssetGuid> {
+        self.records.keys().collect()
+    }
+}
+
+//FNV-1a, stable between runs so the hashes can be saved in the cache.
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+pub fn input_hash(source: &[u8], settings: &BTreeMap<String, String>) -> u64 {
+    let mut hasher = StableHasher::default();
+    hasher.write(source);
+    for (key, value) in settings.iter() {
+        hasher.write(key.as_bytes());
+        hasher.write(&[0]);
+        hasher.write(value.as_bytes());
+        hasher.write(&[0]);
+    }
+    hasher.finish()
+}
@@ -0,0 +1,274 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//Imports ogg vorbis sounds as PCM, at the rate of the mixer.
+//
+//Import settings (meta file):
+//- "target_lufs": the target loudness, "-16" by default, "none" to keep the original loudness.
+//- "loop_start" and "loop_end": loop points in frames of the source, override the ones of the file.
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use resources::sound_resource::SoundResource;
+use pipeline::asset_importer::{AssetImporter, ImportOutput};
+use pipeline::asset_meta::AssetMeta;
+use pipeline::audio_processing::{AudioBuffer, Marker};
+use pipeline::content_cache::ContentCache;
+use pipeline::importers::{push_u32, push_u64, read_bytes, read_u32, read_u64, read_magic, read_count, expect_end};
+use pipeline::pipeline_errors::{PipelineError, PipelineResult};
+use pipeline::target_profile::TargetProfile;
+
+pub const AUDIO_IMPORTER_VERSION: u32 = 1;
+const SOUND_MAGIC: &'static [u8; 4] = b"KSND";
+pub const DEFAULT_TARGET_LUFS: f64 = -16.0;
+
+//The imported sound: interleaved 16 bits samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundAsset {
+    pub sample_rate: u32,
+    pub channels: u32,
+    pub samples: Vec<i16>,
+    pub loop_points: Option<(u64, u64)>,
+    pub markers: Vec<Marker>,
+}
+
+impl SoundAsset {
+    pub fn from_buffer(buffer: &AudioBuffer) -> Self {
+        let mut samples = Vec::with_capacity(buffer.frames() * buffer.channels.len());
+        for frame in 0..buffer.frames() {
+            for channel in buffer.channels.iter() {
+                samples.push((channel[frame].max(-1.0).min(1.0) * 32767.0).round() as i16);
+            }
+        }
+        SoundAsset {
+            sample_rate: buffer.sample_rate,
+            channels: buffer.channels.len() as u32,
+            samples,
+            loop_points: buffer.loop_points,
+            markers: buffer.markers.clone(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        output.extend_from_slice(SOUND_MAGIC);
+        push_u32(&mut output, self.sample_rate);
+        push_u32(&mut output, self.channels);
+        match self.loop_points {
+            Some((start, end)) => {
+                output.push(1);
+                push_u64(&mut output, start);
+                push_u64(&mut output, end);
+            },
+            None => output.push(0),
+        }
+        push_u32(&mut output, self.markers.len() as u32);
+        for marker in self.markers.iter() {
+            push_u64(&mut output, marker.frame);
+            push_u32(&mut output, marker.name.len() as u32);
+            output.extend_from_slice(marker.name.as_bytes());
+        }
+        push_u32(&mut output, self.samples.len() as u32);
+        for sample in self.samples.iter() {
+            output.extend_from_slice(&[*sample as u8, (*sample >> 8) as u8]);
+        }
+        output
+    }
+
+    pub fn from_bytes(data: &[u8]) -> PipelineResult<Self> {
+        let mut offset = 0;
+        read_magic(data, &mut offset, SOUND_MAGIC, "sound")?;
+        let sample_rate = read_u32(data, &mut offset)?;
+        let channels = read_u32(data, &mut offset)?;
+        let loop_points = match read_bytes(data, &mut offset, 1)?[0] {
+            0 => None,
+            1 => Some((read_u64(data, &mut offset)?, read_u64(data, &mut offset)?)),
+            flag => return Err(PipelineError::MalformedAsset(format!("Invalid loop flag {}.", flag))),
+        };
+        let mut markers = Vec::new();
+        for _ in 0..read_count(data, &mut offset, 12)? {
+            let frame = read_u64(data, &mut offset)?;
+            let len = read_u32(data, &mut offset)? as usize;
+            markers.push(Marker {
+                name: String::from_utf8(read_bytes(data, &mut offset, len)?.to_vec()).map_err(|_| {
+                    PipelineError::MalformedAsset(String::from("A marker name is not valid UTF-8."))
+                })?,
+                frame,
+            });
+        }
+        let count = read_count(data, &mut offset, 2)?;
+        let samples = read_bytes(data, &mut offset, count * 2)?
+            .chunks(2)
+            .map(|bytes| (bytes[0] as u16 | (bytes[1] as u16) << 8) as i16)
+            .collect();
+        expect_end(data, offset)?;
+
+        if channels == 0 || count % channels as usize != 0 {
+            return Err(PipelineError::MalformedAsset(format!("{} samples cannot be split in {} channels.", count, channels)));
+        }
+        let frames = (count / channels as usize) as u64;
+        if let Some((start, end)) = loop_points {
+            if start >= end || end > frames {
+                return Err(PipelineError::MalformedAsset(format!("Invalid loop points {}..{} for a sound of {} frames.", start, end, frames)));
+            }
+        }
+        Ok(SoundAsset {
+            sample_rate,
+            channels,
+            samples,
+            loop_points,
+            markers,
+        })
+    }
+}
+
+pub struct AudioImporter {
+    mixer_rate: u32,
+    cache: Option<ContentCache>,
+}
+
+impl AudioImporter {
+    pub fn new(mixer_rate: u32) -> Self {
+        AudioImporter {
+            mixer_rate,
+            cache: None,
+        }
+    }
+
+    //At the rate of the mixer of the target.
+    pub fn for_profile(profile: &TargetProfile) -> Self {
+        AudioImporter::new(profile.audio_sample_rate)
+    }
+
+    pub fn with_cache(mut self, cache: ContentCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    //Resample, normalize and apply the loop point settings.
+    pub fn process(&self, mut buffer: AudioBuffer, settings: &BTreeMap<String, String>) -> PipelineResult<SoundAsset> {
+        let parse_frame = |name: &str| -> PipelineResult<Option<u64>> {
+            match settings.get(name) {
+                Some(value) => value.parse().map(Some).map_err(|_| {
+                    PipelineError::ImportError(format!("Invalid value {} for the audio setting {}.", value, name))
+                }),
+                None => Ok(None),
+            }
+        };
+        match (parse_frame("loop_start")?, parse_frame("loop_end")?) {
+            (Some(start), Some(end)) => buffer.loop_points = Some((start, end)),
+            (Some(start), None) => buffer.loop_points = Some((start, buffer.frames() as u64)),
+            (None, Some(end)) => buffer.loop_points = Some((buffer.loop_points.map(|(start, _)| start).unwrap_or(0), end)),
+            (None, None) => {},
+        }
+        if let Some((start, end)) = buffer.loop_points {
+            if start >= end || end > buffer.frames() as u64 {
+                return Err(PipelineError::ImportError(format!("Invalid loop points {}..{} for a sound of {} frames.", start, end, buffer.frames())));
+            }
+        }
+
+        let target_lufs = match settings.get("target_lufs").map(|target| target.as_str()) {
+            Some("none") => None,
+            Some(target) => Some(target.parse::<f64>().map_err(|_| {
+                PipelineError::ImportError(format!("Invalid target loudness {}.", target))
+            })?),
+            None => Some(DEFAULT_TARGET_LUFS),
+        };
+
+        let mut buffer = buffer.resample(self.mixer_rate);
+        if let Some(target_lufs) = target_lufs {
+            buffer.normalize_loudness(target_lufs);
+        }
+        Ok(SoundAsset::from_buffer(&buffer))
+    }
+
+    fn decode(&self, source: &[u8]) -> PipelineResult<AudioBuffer> {
+        let mut sound = SoundResource::from_reader(Cursor::new(source))?;
+        let mut buffer = AudioBuffer {
+            sample_rate: sound.sample_rate(),
+            channels: vec![Vec::new(); sound.channels() as usize],
+            loop_points: None,
+            markers: Vec::new(),
+        };
+        while let Some(packet) = sound.decompress_packet()? {
+            for (channel, samples) in buffer.channels.iter_mut().zip(packet.into_iter()) {
+                channel.extend(samples.into_iter().map(|sample| sample as f32 / 32768.0));
+            }
+        }
+        buffer.read_comments(sound.comments());
+        Ok(buffer)
+    }
+}
+
+impl AssetImporter for AudioImporter {
+    fn name(&self) -> &str {
+        "audio"
+    }
+
+    fn version(&self) -> u32 {
+        AUDIO_IMPORTER_VERSION
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ogg"]
+    }
+
+    fn import(&self, path: &str, source: &[u8], meta: &AssetMeta) -> PipelineResult<ImportOutput> {
+        debug!("Importing the sound {}.", path);
+        let settings = format!("{:?} {} {}", meta.settings, self.mixer_rate, AUDIO_IMPORTER_VERSION);
+        let key = ContentCache::key(&[source, settings.as_bytes()]);
+        if let Some(cached) = self.cache.as_ref().and_then(|cache| cache.get(key.as_str())) {
+            return Ok(ImportOutput {
+                data: cached,
+                dependencies: Vec::new(),
+            });
+        }
+
+        let sound = self.process(self.decode(source)?, &meta.settings)?;
+        let data = sound.to_bytes();
+        if let Some(ref cache) = self.cache {
+            cache.put(key.as_str(), data.as_slice())?;
+        }
+        Ok(ImportOutput {
+            data,
+            dependencies: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod audio_importer_test {
+    use super::*;
+
+    #[test]
+    fn audio_importer_process() {
+        let buffer = AudioBuffer {
+            sample_rate: 24000,
+            channels: vec![vec![0.25; 24000], vec![-0.25; 24000]],
+            loop_points: None,
+            markers: vec![Marker { name: String::from("hit"), frame: 100 }],
+        };
+        let importer = AudioImporter::new(48000);
+        let mut settings = BTreeMap::new();
+        settings.insert(String::from("target_lufs"), String::from("none"));
+        settings.insert(String::from("loop_start"), String::from("12000"));
+
+        let sound = importer.process(buffer.clone(), &settings).unwrap();
+        assert_eq!(sound.channels, 2);
+        assert_eq!(sound.samples.len(), 48000 * 2);
+        assert_eq!(&sound.samples[..2], &[8192, -8192]);
+        assert_eq!(sound.loop_points, Some((24000, 48000)));
+        assert_eq!(sound.markers[0].frame, 200);
+        assert_eq!(SoundAsset::from_bytes(sound.to_bytes().as_slice()).unwrap(), sound);
+        let mut corrupted = sound.to_bytes();
+        corrupted[8] = 3;
+        assert!(SoundAsset::from_bytes(corrupted.as_slice()).is_err());
+
+        settings.insert(String::from("loop_end"), String::from("30000"));
+        assert!(importer.process(buffer, &settings).is_err());
+    }
+}
@@ -0,0 +1,415 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//Imports wavefront obj meshes, optimized for the vertex cache and the overdraw, with a LOD chain.
+//
+//Import settings (meta file):
+//- "lod_count": the number of LODs after the full detail mesh, 3 by default.
+//- "lod_ratio": the triangle count of a LOD, relative to the previous one. 0.5 by default.
+//- "lod_max_error": the max simplification error, relative to the size of the mesh. 0.01 by default.
+//- "lod_distance": the camera distance where the first LOD is used, doubled for each next LOD. 10 by default.
+//- "archived": "true" to cook the mesh in the archived format, used in place from the archive. false by default.
+//The most detailed LODs are dropped according to the target profile.
+
+use std::collections::{BTreeMap, HashMap};
+use pipeline::archived::archive_mesh;
+use pipeline::asset_importer::{AssetImporter, ImportOutput};
+use pipeline::asset_meta::AssetMeta;
+use pipeline::importers::{push_u32, read_u32, read_magic, read_count, expect_end};
+use pipeline::mesh_optimization::{optimize_vertex_cache, optimize_overdraw, simplify};
+use pipeline::pipeline_errors::{PipelineError, PipelineResult};
+use pipeline::target_profile::TargetProfile;
+
+pub const MESH_IMPORTER_VERSION: u32 = 1;
+const MESH_MAGIC: &'static [u8; 4] = b"KMSH";
+const VERTEX_CACHE_SIZE: usize = 32;
+const OVERDRAW_CLUSTER_TRIANGLES: usize = 64;
+
+//repr(C): the archived meshes are used in place (see archived).
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshLod {
+    pub indices: Vec<u32>,
+    //The simplification error, in mesh units.
+    pub error: f32,
+    //The LOD is used from this camera distance.
+    pub distance: f32,
+}
+
+//The LODs share the vertices of the full detail mesh, only the indices change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshAsset {
+    pub vertices: Vec<MeshVertex>,
+    pub lods: Vec<MeshLod>,
+}
+
+impl MeshAsset {
+    //Used by the renderer: the LOD to draw at the given camera distance.
+    pub fn select_lod(&self, distance: f32) -> usize {
+        self.lods.iter().rposition(|lod| lod.distance <= distance).unwrap_or(0)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        output.extend_from_slice(MESH_MAGIC);
+        push_u32(&mut output, self.vertices.len() as u32);
+        for vertex in self.vertices.iter() {
+            for value in vertex.position.iter().chain(vertex.normal.iter()).chain(vertex.uv.iter()) {
+                push_u32(&mut output, value.to_bits());
+            }
+        }
+        push_u32(&mut output, self.lods.len() as u32);
+        for lod in self.lods.iter() {
+            push_u32(&mut output, lod.error.to_bits());
+            push_u32(&mut output, lod.distance.to_bits());
+            push_u32(&mut output, lod.indices.len() as u32);
+            for index in lod.indices.iter() {
+                push_u32(&mut output, *index);
+            }
+        }
+        output
+    }
+
+    //The indices must form triangles of existing vertices.
+    pub fn from_bytes(data: &[u8]) -> PipelineResult<Self> {
+        let mut offset = 0;
+        read_magic(data, &mut offset, MESH_MAGIC, "mesh")?;
+        let read_f32 = |offset: &mut usize| read_u32(data, offset).map(f32::from_bits);
+        let vertex_count = read_count(data, &mut offset, 8 * 4)?;
+        let mut vertices = Vec::with_capacity(vertex_count);
+        for _ in 0..vertex_count {
+            let mut values = [0.0f32; 8];
+            for value in values.iter_mut() {
+                *value = read_f32(&mut offset)?;
+            }
+            vertices.push(MeshVertex {
+                position: [values[0], values[1], values[2]],
+                normal: [values[3], values[4], values[5]],
+                uv: [values[6], values[7]],
+            });
+        }
+        let mut lods = Vec::new();
+        for level in 0..read_count(data, &mut offset, 3 * 4)? {
+            let error = read_f32(&mut offset)?;
+            let distance = read_f32(&mut offset)?;
+            let index_count = read_count(data, &mut offset, 4)?;
+            if index_count % 3 != 0 {
+                return Err(PipelineError::MalformedAsset(format!("The LOD {} has {} indices, not a list of triangles.", level, index_count)));
+            }
+            let mut indices = Vec::with_capacity(index_count);
+            for _ in 0..index_count {
+                let index = read_u32(data, &mut offset)?;
+                if index as usize >= vertex_count {
+                    return Err(PipelineError::MalformedAsset(format!("The LOD {} uses the vertex {}, the mesh has {} vertices.", level, index, vertex_count)));
+                }
+                indices.push(index);
+            }
+            lods.push(MeshLod {
+                indices,
+                error,
+                distance,
+            });
+        }
+        expect_end(data, offset)?;
+        Ok(MeshAsset {
+            vertices,
+            lods,
+        })
+    }
+}
+
+//Parse the triangles of an obj file. Polygons are triangulated as fans, and the missing normals are
+//computed from the faces.
+pub fn parse_obj(source: &str) -> PipelineResult<(Vec<MeshVertex>, Vec<u32>)> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut unique: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+    let mut missing_normals = false;
+
+    for (line_number, line) in source.lines().enumerate() {
+        let error = || PipelineError::ImportError(format!("Invalid obj line {}: {}", line_number + 1, line));
+        let mut tokens = line.split_whitespace();
+        let floats = |tokens: ::std::str::SplitWhitespace| -> Vec<f32> {
+            tokens.filter_map(|token| token.parse().ok()).collect()
+        };
+        match tokens.next() {
+            Some("v") => {
+                let values = floats(tokens);
+                if values.len() < 3 {
+                    return Err(error());
+                }
+                positions.push([values[0], values[1], values[2]]);
+            },
+            Some("vn") => {
+                let values = floats(tokens);
+                if values.len() < 3 {
+                    return Err(error());
+                }
+                normals.push([values[0], values[1], values[2]]);
+            },
+            Some("vt") => {
+                let values = floats(tokens);
+                if values.len() < 2 {
+                    return Err(error());
+                }
+                uvs.push([values[0], values[1]]);
+            },
+            Some("f") => {
+                //Indices start at 1, negative indices are relative to the end.
+                let resolve = |token: Option<&str>, len: usize| -> Result<Option<usize>, PipelineError> {
+                    match token {
+                        None | Some("") => Ok(None),
+                        Some(token) => {
+                            let index: i64 = token.parse().map_err(|_| error())?;
+                            let index = if index < 0 {len as i64 + index} else {index - 1};
+                            if index < 0 || index as usize >= len {Err(error())} else {Ok(Some(index as usize))}
+                        },
+                    }
+                };
+                let mut face = Vec::new();
+                for corner in tokens {
+                    let mut parts = corner.split('/');
+                    let position = resolve(parts.next(), positions.len())?.ok_or_else(|| error())?;
+                    let uv = resolve(parts.next(), uvs.len())?;
+                    let normal = resolve(parts.next(), normals.len())?;
+                    missing_normals |= normal.is_none();
+                    let index = *unique.entry((position, uv, normal)).or_insert_with(|| {
+                        vertices.push(MeshVertex {
+                            position: positions[position],
+                            normal: normal.map(|normal| normals[normal]).unwrap_or([0.0; 3]),
+                            uv: uv.map(|uv| uvs[uv]).unwrap_or([0.0; 2]),
+                        });
+                        vertices.len() as u32 - 1
+                    });
+                    face.push(index);
+                }
+                if face.len() < 3 {
+                    return Err(error());
+                }
+                for i in 1..face.len() - 1 {
+                    indices.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    if missing_normals {
+        for triangle in indices.chunks(3) {
+            let (a, b, c) = (vertices[triangle[0] as usize].position, vertices[triangle[1] as usize].position, vertices[triangle[2] as usize].position);
+            let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+            let normal = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+            for vertex in triangle {
+                for c in 0..3 {
+                    vertices[*vertex as usize].normal[c] += normal[c];
+                }
+            }
+        }
+        for vertex in vertices.iter_mut() {
+            let n = vertex.normal;
+            let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+            if length > 0.0 {
+                vertex.normal = [n[0] / length, n[1] / length, n[2] / length];
+            }
+        }
+    }
+    Ok((vertices, indices))
+}
+
+fn parse_setting<T: ::std::str::FromStr>(settings: &BTreeMap<String, String>, name: &str, default: T) -> PipelineResult<T> {
+    match settings.get(name) {
+        Some(value) => value.parse().map_err(|_| {
+            PipelineError::ImportError(format!("Invalid value {} for the mesh setting {}.", value, name))
+        }),
+        None => Ok(default),
+    }
+}
+
+#[derive(Default)]
+pub struct MeshImporter {
+    skipped_lods: usize,
+}
+
+impl MeshImporter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn for_profile(profile: &TargetProfile) -> Self {
+        MeshImporter {
+            skipped_lods: profile.skipped_mesh_lods,
+        }
+    }
+
+    pub fn process(&self, vertices: Vec<MeshVertex>, indices: Vec<u32>, settings: &BTreeMap<String, String>) -> PipelineResult<MeshAsset> {
+        let lod_count: usize = parse_setting(settings, "lod_count", 3)?;
+        let lod_ratio: f32 = parse_setting(settings, "lod_ratio", 0.5)?;
+        let lod_max_error: f32 = parse_setting(settings, "lod_max_error", 0.01)?;
+        let lod_distance: f32 = parse_setting(settings, "lod_distance", 10.0)?;
+
+        if let Some(index) = vertices.iter().position(|vertex| vertex.position.iter().any(|c| !c.is_finite())) {
+            return Err(PipelineError::ImportError(format!("The position of the vertex {} is not finite.", index)));
+        }
+        let positions: Vec<[f32; 3]> = vertices.iter().map(|vertex| vertex.position).collect();
+        let mut min = [::std::f32::MAX; 3];
+        let mut max = [::std::f32::MIN; 3];
+        for position in positions.iter() {
+            for c in 0..3 {
+                min[c] = min[c].min(position[c]);
+                max[c] = max[c].max(position[c]);
+            }
+        }
+        let extent = (0..3).map(|c| (max[c] - min[c]).max(0.0).powi(2)).sum::<f32>().sqrt();
+
+        let optimize = |indices: &[u32]| {
+            let indices = optimize_vertex_cache(indices, positions.len(), VERTEX_CACHE_SIZE);
+            optimize_overdraw(indices.as_slice(), positions.as_slice(), OVERDRAW_CLUSTER_TRIANGLES)
+        };
+
+        let mut lods = vec![MeshLod {
+            indices: optimize(indices.as_slice()),
+            error: 0.0,
+            distance: 0.0,
+        }];
+        for level in 0..lod_count {
+            let previous = &lods[lods.len() - 1].indices;
+            let target = ((previous.len() / 3) as f32 * lod_ratio) as usize * 3;
+            let (simplified, error) = simplify(previous.as_slice(), positions.as_slice(), target, lod_max_error * extent);
+            //The max error is reached, the next LODs would be the same.
+            if simplified.len() >= previous.len() || simplified.is_empty() {
+                debug!("The LOD chain stops at {} LODs, the max error is reached.", lods.len());
+                break;
+            }
+            trace!("LOD {}: {} triangles, error {}.", level + 1, simplified.len() / 3, error);
+            let error = error.max(lods[lods.len() - 1].error);
+            lods.push(MeshLod {
+                indices: optimize(simplified.as_slice()),
+                error,
+                distance: lod_distance * 2f32.powi(level as i32),
+            });
+        }
+
+        //The coarsest LOD is always kept.
+        let skipped = self.skipped_lods.min(lods.len() - 1);
+        if skipped > 0 {
+            trace!("Dropping the {} most detailed LODs.", skipped);
+            lods.drain(..skipped);
+            lods[0].distance = 0.0;
+        }
+
+        //Vertex fetch: the vertices in the order of their first use by the full detail mesh.
+        let mut remap = vec![None; vertices.len()];
+        let mut ordered = Vec::with_capacity(vertices.len());
+        for index in lods[0].indices.iter() {
+            if remap[*index as usize].is_none() {
+                remap[*index as usize] = Some(ordered.len() as u32);
+                ordered.push(vertices[*index as usize]);
+            }
+        }
+        for lod in lods.iter_mut() {
+            for index in lod.indices.iter_mut() {
+                *index = remap[*index as usize].expect("The LODs only use the vertices of the full detail mesh.");
+            }
+        }
+
+        Ok(MeshAsset {
+            vertices: ordered,
+            lods,
+        })
+    }
+}
+
+impl AssetImporter for MeshImporter {
+    fn name(&self) -> &str {
+        "mesh"
+    }
+
+    fn version(&self) -> u32 {
+        MESH_IMPORTER_VERSION
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["obj"]
+    }
+
+    fn import(&self, path: &str, source: &[u8], meta: &AssetMeta) -> PipelineResult<ImportOutput> {
+        debug!("Importing the mesh {}.", path);
+        let (vertices, indices) = parse_obj(String::from_utf8_lossy(source).as_ref())?;
+        let mesh = self.process(vertices, indices, &meta.settings)?;
+        let archived: bool = parse_setting(&meta.settings, "archived", false)?;
+        Ok(ImportOutput {
+            data: if archived { archive_mesh(&mesh) } else { mesh.to_bytes() },
+            dependencies: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod mesh_importer_test {
+    use super::*;
+
+    #[test]
+    fn mesh_importer_obj_and_lods() {
+        //A 10x10 flat grid of quads.
+        let mut obj = String::new();
+        for z in 0..11 {
+            for x in 0..11 {
+                obj.push_str(&format!("v {} 0 {}\n", x, z));
+            }
+        }
+        for z in 0..10 {
+            for x in 0..10 {
+                let i = z * 11 + x + 1;
+                obj.push_str(&format!("f {} {} {} {}\n", i, i + 11, i + 12, i + 1));
+            }
+        }
+
+        let (vertices, indices) = parse_obj(obj.as_str()).unwrap();
+        assert_eq!(vertices.len(), 121);
+        assert_eq!(indices.len(), 200 * 3);
+        assert!((vertices[0].normal[1] - 1.0).abs() < 1e-5);
+
+        let mesh = MeshImporter::new().process(vertices.clone(), indices.clone(), &BTreeMap::new()).unwrap();
+        assert!(mesh.lods.len() > 1);
+        for pair in mesh.lods.windows(2) {
+            assert!(pair[1].indices.len() < pair[0].indices.len());
+            assert!(pair[1].distance > pair[0].distance);
+        }
+        assert_eq!(mesh.select_lod(0.0), 0);
+        assert_eq!(mesh.select_lod(10000.0), mesh.lods.len() - 1);
+        assert_eq!(MeshAsset::from_bytes(mesh.to_bytes().as_slice()).unwrap(), mesh);
+        let mut corrupted = mesh.to_bytes();
+        let last = corrupted.len() - 1;
+        corrupted[last] = 0xff;
+        assert!(MeshAsset::from_bytes(corrupted.as_slice()).is_err());
+
+        let mobile = MeshImporter::for_profile(&TargetProfile::mobile()).process(vertices, indices, &BTreeMap::new()).unwrap();
+        assert_eq!(mobile.lods.len(), mesh.lods.len() - 1);
+        assert_eq!(mobile.lods[0].indices.len(), mesh.lods[1].indices.len());
+        assert_eq!(mobile.lods[0].distance, 0.0);
+        assert!(mobile.vertices.len() < mesh.vertices.len());
+
+        assert!(parse_obj("f 1 2 3").is_err());
+
+        let (mut invalid, indices) = parse_obj("v 0 0 0\nv 1 0 0\nv 0 0 1\nf 1 2 3\n").unwrap();
+        invalid[1].position[2] = f32::NAN;
+        match MeshImporter::new().process(invalid, indices, &BTreeMap::new()) {
+            Err(PipelineError::ImportError(_)) => {},
+            result => panic!("Unexpected result {:?}.", result.map(|mesh| mesh.lods.len())),
+        }
+    }
+}
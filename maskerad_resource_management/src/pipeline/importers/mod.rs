@@ -0,0 +1,94 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use pipeline::pipeline_errors::{PipelineError, PipelineResult};
+
+pub mod texture_importer;
+pub mod audio_importer;
+pub mod mesh_importer;
+pub mod scene_importer;
+
+//Little endian helpers, shared by the formats of the imported assets.
+
+pub fn push_u32(output: &mut Vec<u8>, value: u32) {
+    output.extend_from_slice(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]);
+}
+
+pub fn push_u64(output: &mut Vec<u8>, value: u64) {
+    push_u32(output, value as u32);
+    push_u32(output, (value >> 32) as u32);
+}
+
+//The readers check every length against the data: the assets may come from untrusted mods.
+
+pub fn read_bytes<'a>(data: &'a [u8], offset: &mut usize, len: usize) -> PipelineResult<&'a [u8]> {
+    match offset.checked_add(len) {
+        Some(end) if end <= data.len() => {
+            let bytes = &data[*offset..end];
+            *offset = end;
+            Ok(bytes)
+        },
+        _ => Err(PipelineError::TruncatedAsset(format!("{} bytes expected at the offset {}, the data has {} bytes.", len, offset, data.len()))),
+    }
+}
+
+pub fn read_magic(data: &[u8], offset: &mut usize, magic: &[u8; 4], kind: &str) -> PipelineResult<()> {
+    if read_bytes(data, offset, 4)? != magic {
+        return Err(PipelineError::MalformedAsset(format!("The data is not an imported {}.", kind)));
+    }
+    Ok(())
+}
+
+//A number of elements, each taking at least min_element_size bytes in the rest of the data.
+//A count which cannot fit is refused before anything is allocated.
+pub fn read_count(data: &[u8], offset: &mut usize, min_element_size: usize) -> PipelineResult<usize> {
+    let count = read_u32(data, offset)? as usize;
+    match count.checked_mul(min_element_size) {
+        Some(size) if size <= data.len() - *offset => Ok(count),
+        _ => Err(PipelineError::TruncatedAsset(format!("{} elements announced at the offset {}, the data has {} bytes.", count, offset, data.len()))),
+    }
+}
+
+pub fn expect_end(data: &[u8], offset: usize) -> PipelineResult<()> {
+    if offset != data.len() {
+        return Err(PipelineError::MalformedAsset(format!("{} unexpected bytes at the end of the data.", data.len() - offset)));
+    }
+    Ok(())
+}
+
+pub fn read_u32(data: &[u8], offset: &mut usize) -> PipelineResult<u32> {
+    let bytes = read_bytes(data, offset, 4)?;
+    Ok(bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24)
+}
+
+pub fn read_u64(data: &[u8], offset: &mut usize) -> PipelineResult<u64> {
+    let low = read_u32(data, offset)? as u64;
+    let high = read_u32(data, offset)? as u64;
+    Ok(low | high << 32)
+}
+
+#[cfg(test)]
+mod importers_test {
+    use super::*;
+
+    #[test]
+    fn importers_bounds_checked_reads() {
+        let data = [1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 9];
+        let mut offset = 0;
+        assert_eq!(read_count(&data, &mut offset, 5).unwrap(), 1);
+        match read_count(&data, &mut offset, 1) {
+            Err(PipelineError::TruncatedAsset(_)) => {},
+            result => panic!("Unexpected result {:?}.", result.map_err(|error| error.to_string())),
+        }
+        let mut offset = 8;
+        assert!(read_bytes(&data, &mut offset, usize::max_value()).is_err());
+        assert_eq!(read_bytes(&data, &mut offset, 1).unwrap(), &[9]);
+        assert!(expect_end(&data, offset).is_ok());
+        assert!(expect_end(&data, 4).is_err());
+        assert!(read_magic(&data, &mut 0, b"KMSH", "mesh").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//Compiles the text form of the scenes and prefabs (".scene" files) to their binary form.
+//The assets referenced by the scene and the included scenes are its dependencies.
+
+use pipeline::asset_importer::{AssetImporter, ImportOutput};
+use pipeline::asset_meta::AssetMeta;
+use pipeline::pipeline_errors::PipelineResult;
+use scenes::scene_binary::{write_binary_scene, SCENE_BINARY_VERSION};
+use scenes::scene_description::SceneDescription;
+
+#[derive(Default)]
+pub struct SceneImporter;
+
+impl SceneImporter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl AssetImporter for SceneImporter {
+    fn name(&self) -> &str {
+        "scene"
+    }
+
+    //Follows the version of the binary format.
+    fn version(&self) -> u32 {
+        SCENE_BINARY_VERSION
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["scene"]
+    }
+
+    fn import(&self, path: &str, source: &[u8], _meta: &AssetMeta) -> PipelineResult<ImportOutput> {
+        debug!("Compiling the scene {}.", path);
+        let scene = SceneDescription::from_reader(source)?;
+        let mut data = Vec::new();
+        write_binary_scene(&scene, &mut data)?;
+
+        let mut dependencies: Vec<String> = scene.asset_references().into_iter().map(|path| path.to_string()).collect();
+        dependencies.extend(scene.asset_guids().into_iter().map(|guid| guid.to_string()));
+        dependencies.extend(scene.includes.iter().map(|include| include.path.clone()));
+        Ok(ImportOutput {
+            data,
+            dependencies,
+        })
+    }
+}
+
+#[cfg(test)]
+mod scene_importer_test {
+    use super::*;
+    use pipeline::asset_guid::AssetGuid;
+    use scenes::scene_binary::read_binary_scene;
+    use scenes::scene_description::{ComponentDescription, EntityDescription, PropertyValue, SceneInclude};
+
+    #[test]
+    fn scene_importer_dependencies() {
+        let mut scene = SceneDescription::new();
+        scene.entities.push(EntityDescription {
+            id: 0,
+            name: String::from("house"),
+            parent: None,
+            components: vec![ComponentDescription::new("Mesh").with_property("mesh", PropertyValue::Asset(String::from("meshes/house.obj")))],
+        });
+        scene.includes.push(SceneInclude {
+            path: String::from("prefabs/door.scene"),
+            parent: Some(0),
+        });
+        let mut source = Vec::new();
+        scene.save(&mut source).unwrap();
+
+        let meta = AssetMeta::new(AssetGuid::parse("0123456789abcdef0123456789abcdef").unwrap(), "scene");
+        let output = SceneImporter::new().import("village.scene", source.as_slice(), &meta).unwrap();
+        assert_eq!(output.dependencies, vec![String::from("meshes/house.obj"), String::from("prefabs/door.scene")]);
+        assert_eq!(read_binary_scene(output.data.as_slice()).unwrap(), scene);
+    }
+}
@@ -0,0 +1,293 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//Imports png, jpeg, tga and bmp images as GPU-ready textures.
+//
+//Import settings (meta file):
+//- "class": "albedo" (default), "normal" or "ui".
+//- "quality": "low", "medium" (default) or "high".
+//- The mip, size and "srgb" settings of texture_processing. The sRGB textures are sampled through an sRGB
+//  view, the GPU converts the texels to linear before filtering.
+//The quality and the max size are lowered to the ones of the target profile.
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use resources::image_resource::{ImageResource, ColorFormat};
+use pipeline::importers::{push_u32, read_u32, read_bytes, read_magic, read_count, expect_end};
+use pipeline::asset_importer::{AssetImporter, ImportOutput};
+use pipeline::asset_meta::AssetMeta;
+use pipeline::content_cache::ContentCache;
+use pipeline::pipeline_errors::{PipelineError, PipelineResult};
+use pipeline::target_profile::TargetProfile;
+use pipeline::texture_processing::{TextureProcessing, TextureLevel, process_texture};
+use pipeline::texture_compression::{TextureFormat, TexturePlatform, TextureClass, TextureQuality, TextureEncoder, select_format, compress};
+
+pub const TEXTURE_IMPORTER_VERSION: u32 = 3;
+const TEXTURE_MAGIC: &'static [u8; 4] = b"KTEX";
+
+//The imported texture: the format, the color space, the size of the first level, and the data of each mip level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureAsset {
+    pub format: TextureFormat,
+    //The color texels are sRGB encoded: sample them with the sRGB variant of the format.
+    pub srgb: bool,
+    pub width: u32,
+    pub height: u32,
+    pub mips: Vec<Vec<u8>>,
+}
+
+impl TextureAsset {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        output.extend_from_slice(TEXTURE_MAGIC);
+        push_u32(&mut output, self.format.id());
+        push_u32(&mut output, self.srgb as u32);
+        push_u32(&mut output, self.width);
+        push_u32(&mut output, self.height);
+        push_u32(&mut output, self.mips.len() as u32);
+        for mip in self.mips.iter() {
+            push_u32(&mut output, mip.len() as u32);
+            output.extend_from_slice(mip.as_slice());
+        }
+        output
+    }
+
+    //Each mip level must have the size of its format, at the half of the size of the previous level.
+    pub fn from_bytes(data: &[u8]) -> PipelineResult<Self> {
+        let mut offset = 0;
+        read_magic(data, &mut offset, TEXTURE_MAGIC, "texture")?;
+        let format_id = read_u32(data, &mut offset)?;
+        let format = TextureFormat::from_id(format_id).ok_or_else(|| {
+            PipelineError::MalformedAsset(format!("Unknown texture format {}.", format_id))
+        })?;
+        let srgb = match read_u32(data, &mut offset)? {
+            0 => false,
+            1 => true,
+            value => return Err(PipelineError::MalformedAsset(format!("Invalid color space {} for a texture.", value))),
+        };
+        let width = read_u32(data, &mut offset)?;
+        let height = read_u32(data, &mut offset)?;
+        let count = read_count(data, &mut offset, 4)?;
+        if width == 0 || height == 0 || count == 0 || count > 32 - width.max(height).leading_zeros() as usize {
+            return Err(PipelineError::MalformedAsset(format!("A {}x{} texture cannot have {} mip levels.", width, height, count)));
+        }
+        let mut mips = Vec::with_capacity(count);
+        for level in 0..count {
+            let len = read_u32(data, &mut offset)? as usize;
+            let expected = format.data_size((width as usize >> level).max(1), (height as usize >> level).max(1));
+            if len != expected {
+                return Err(PipelineError::MalformedAsset(format!("The mip level {} has {} bytes instead of {}.", level, len, expected)));
+            }
+            mips.push(read_bytes(data, &mut offset, len)?.to_vec());
+        }
+        expect_end(data, offset)?;
+        Ok(TextureAsset {
+            format,
+            srgb,
+            width,
+            height,
+            mips,
+        })
+    }
+}
+
+pub struct TextureImporter {
+    platform: TexturePlatform,
+    max_quality: TextureQuality,
+    max_size: Option<usize>,
+    encoders: Vec<Box<TextureEncoder>>,
+    cache: Option<ContentCache>,
+}
+
+impl TextureImporter {
+    pub fn new(platform: TexturePlatform) -> Self {
+        TextureImporter {
+            platform,
+            max_quality: TextureQuality::High,
+            max_size: None,
+            encoders: Vec::new(),
+            cache: None,
+        }
+    }
+
+    pub fn for_profile(profile: &TargetProfile) -> Self {
+        let mut importer = TextureImporter::new(profile.platform);
+        importer.max_quality = profile.max_texture_quality;
+        importer.max_size = profile.max_texture_size;
+        importer
+    }
+
+    //An encoder for a format without built-in encoder (ASTC).
+    pub fn with_encoder(mut self, encoder: Box<TextureEncoder>) -> Self {
+        self.encoders.push(encoder);
+        self
+    }
+
+    pub fn with_cache(mut self, cache: ContentCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    fn encode(&self, format: TextureFormat, rgba: &[u8], width: usize, height: usize, quality: TextureQuality) -> (TextureFormat, Vec<u8>) {
+        if let Some(data) = compress(format, rgba, width, height) {
+            return (format, data);
+        }
+        match self.encoders.iter().find(|encoder| encoder.format() == format) {
+            Some(encoder) => (format, encoder.encode(rgba, width, height, quality)),
+            None => {
+                warn!("No encoder for the texture format {:?}, the texture is stored uncompressed.", format);
+                (TextureFormat::Rgba8, rgba.to_vec())
+            },
+        }
+    }
+
+    //Compress RGBA8 pixels according to the import settings.
+    pub fn process(&self, rgba: &[u8], width: usize, height: usize, settings: &BTreeMap<String, String>) -> PipelineResult<TextureAsset> {
+        if rgba.len() != width * height * 4 {
+            return Err(PipelineError::ImportError(format!("{} bytes of pixels for a {}x{} texture.", rgba.len(), width, height)));
+        }
+        let class: TextureClass = settings.get("class").map(|class| class.parse()).unwrap_or(Ok(TextureClass::Albedo))
+            .map_err(|error| PipelineError::ImportError(error))?;
+        let quality: TextureQuality = settings.get("quality").map(|quality| quality.parse()).unwrap_or(Ok(TextureQuality::Medium))
+            .map_err(|error| PipelineError::ImportError(error))?
+            .min(self.max_quality);
+        let mut processing = TextureProcessing::from_settings(settings, class, quality)?;
+        processing.max_size = match (processing.max_size, self.max_size) {
+            (Some(size), Some(max_size)) => Some(size.min(max_size)),
+            (size, max_size) => size.or(max_size),
+        };
+        let has_alpha = rgba.chunks(4).any(|pixel| pixel[3] != 255);
+        let format = select_format(self.platform, class, quality, has_alpha);
+
+        let key = ContentCache::key(&[
+            rgba,
+            format!("{}x{} {:?} {:?} {:?} {}", width, height, quality, format, processing, TEXTURE_IMPORTER_VERSION).as_bytes(),
+        ]);
+        if let Some(cached) = self.cache.as_ref().and_then(|cache| cache.get(key.as_str())) {
+            if let Ok(texture) = TextureAsset::from_bytes(cached.as_slice()) {
+                return Ok(texture);
+            }
+        }
+
+        let levels = process_texture(TextureLevel {
+            width,
+            height,
+            rgba: rgba.to_vec(),
+        }, &processing);
+        debug!("Compressing a {}x{} {:?} texture ({} levels) to {:?}.", levels[0].width, levels[0].height, class, levels.len(), format);
+
+        let mut texture = TextureAsset {
+            format,
+            //The one and two channel formats have no sRGB variant.
+            srgb: processing.srgb && format != TextureFormat::Bc4 && format != TextureFormat::Bc5,
+            width: levels[0].width as u32,
+            height: levels[0].height as u32,
+            mips: Vec::with_capacity(levels.len()),
+        };
+        for level in levels.iter() {
+            let (level_format, data) = self.encode(format, level.rgba.as_slice(), level.width, level.height, quality);
+            texture.format = level_format;
+            texture.mips.push(data);
+        }
+        if let Some(ref cache) = self.cache {
+            cache.put(key.as_str(), texture.to_bytes().as_slice())?;
+        }
+        Ok(texture)
+    }
+}
+
+impl AssetImporter for TextureImporter {
+    fn name(&self) -> &str {
+        "texture"
+    }
+
+    fn version(&self) -> u32 {
+        TEXTURE_IMPORTER_VERSION
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["png", "jpg", "jpeg", "tga", "bmp"]
+    }
+
+    fn import(&self, path: &str, source: &[u8], meta: &AssetMeta) -> PipelineResult<ImportOutput> {
+        debug!("Importing the texture {}.", path);
+        let image = ImageResource::from_reader(&mut Cursor::new(source), ColorFormat::RGBA)?;
+        let texture = self.process(image.data(), image.width(), image.height(), &meta.settings)?;
+        Ok(ImportOutput {
+            data: texture.to_bytes(),
+            dependencies: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod texture_importer_test {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    struct FakeAstcEncoder;
+
+    impl TextureEncoder for FakeAstcEncoder {
+        fn format(&self) -> TextureFormat {
+            TextureFormat::Astc6x6
+        }
+
+        fn encode(&self, _rgba: &[u8], width: usize, height: usize, _quality: TextureQuality) -> Vec<u8> {
+            vec![0; TextureFormat::Astc6x6.data_size(width, height)]
+        }
+    }
+
+    #[test]
+    fn texture_importer_presets_and_cache() {
+        let rgba = vec![128u8; 8 * 8 * 4];
+        let mut settings = BTreeMap::new();
+
+        let desktop = TextureImporter::new(TexturePlatform::Desktop);
+        let texture = desktop.process(rgba.as_slice(), 8, 8, &settings).unwrap();
+        assert_eq!(texture.format, TextureFormat::Bc3);
+        assert!(texture.srgb);
+        assert_eq!(texture.mips.len(), 4);
+        assert_eq!(texture.mips[3].len(), 16);
+        assert_eq!(TextureAsset::from_bytes(texture.to_bytes().as_slice()).unwrap(), texture);
+        let mut corrupted = texture.to_bytes();
+        corrupted[24] = 0xff;
+        assert!(TextureAsset::from_bytes(corrupted.as_slice()).is_err());
+
+        settings.insert(String::from("class"), String::from("normal"));
+        let normal = desktop.process(rgba.as_slice(), 8, 8, &settings).unwrap();
+        assert_eq!(normal.format, TextureFormat::Bc5);
+        assert!(!normal.srgb);
+
+        //The quality is lowered by the profile.
+        settings.insert(String::from("class"), String::from("ui"));
+        settings.insert(String::from("quality"), String::from("high"));
+        assert_eq!(desktop.process(rgba.as_slice(), 8, 8, &settings).unwrap().format, TextureFormat::Rgba8);
+        let low = TextureImporter::for_profile(&TargetProfile::desktop_low());
+        assert_eq!(low.process(rgba.as_slice(), 8, 8, &settings).unwrap().format, TextureFormat::Bc3);
+        settings.remove("quality");
+
+        //No ASTC encoder: stored uncompressed.
+        let mobile = TextureImporter::new(TexturePlatform::Mobile);
+        settings.insert(String::from("class"), String::from("albedo"));
+        assert_eq!(mobile.process(rgba.as_slice(), 8, 8, &settings).unwrap().format, TextureFormat::Rgba8);
+
+        let directory = env::temp_dir().join("maskerad_texture_importer_test");
+        let _ = fs::remove_dir_all(directory.as_path());
+        let mobile = TextureImporter::new(TexturePlatform::Mobile)
+            .with_encoder(Box::new(FakeAstcEncoder))
+            .with_cache(ContentCache::new(directory.as_path()));
+        let texture = mobile.process(rgba.as_slice(), 8, 8, &settings).unwrap();
+        assert_eq!(texture.format, TextureFormat::Astc6x6);
+        assert_eq!(fs::read_dir(directory.as_path()).unwrap().count(), 1);
+        assert_eq!(mobile.process(rgba.as_slice(), 8, 8, &settings).unwrap(), texture);
+
+        settings.insert(String::from("quality"), String::from("best"));
+        assert!(mobile.process(rgba.as_slice(), 8, 8, &settings).is_err());
+        fs::remove_dir_all(directory.as_path()).unwrap();
+    }
+}
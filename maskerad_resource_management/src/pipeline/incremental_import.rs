@@ -0,0 +1,296 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ INCREMENTAL IMPORT.
+
+ Importing every asset of a big project takes minutes. The import pipeline keeps, for each asset,
+ the hash of its source and settings, the importer used and its version, and the assets it depends on.
+
+ An asset is reimported only if:
+ - it has never been imported,
+ - its source file or its import settings changed,
+ - its importer changed, or the version of its importer changed,
+ - one of its dependencies (directly or not) is reimported.
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use maskerad_core::filesystem::filesystem::Filesystem;
+use maskerad_core::filesystem::filesystem_error::FileSystemError;
+use pipeline::asset_database::AssetDatabase;
+use pipeline::asset_guid::AssetGuid;
+use pipeline::asset_importer::AssetImporter;
+use pipeline::asset_meta::{AssetMeta, meta_path};
+use pipeline::import_cache::{ImportCache, ImportRecord, input_hash};
+use pipeline::pipeline_errors::PipelineResult;
+use pipeline::target_profile::TargetProfile;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub imported: Vec<String>,
+    pub up_to_date: usize,
+    //The path of the asset and the error message.
+    pub failed: Vec<(String, String)>,
+    //Records of deleted assets removed from the cache.
+    pub removed: usize,
+}
+
+struct PendingImport {
+    path: String,
+    source: Vec<u8>,
+    meta: AssetMeta,
+    importer: usize,
+    input_hash: u64,
+}
+
+pub struct ImportPipeline {
+    importers: Vec<Box<AssetImporter>>,
+    cache: ImportCache,
+    output_directory: PathBuf,
+    profile: TargetProfile,
+}
+
+impl ImportPipeline {
+    pub fn new<P: AsRef<Path>>(output_directory: P) -> Self {
+        ImportPipeline {
+            importers: Vec::new(),
+            cache: ImportCache::new(),
+            output_directory: output_directory.as_ref().to_path_buf(),
+            profile: TargetProfile::default(),
+        }
+    }
+
+    //The profile whose overrides are applied to the settings of the meta files.
+    pub fn with_profile(mut self, profile: TargetProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    //Start from the cache saved by a previous run.
+    pub fn with_cache(mut self, cache: ImportCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn add_importer(&mut self, importer: Box<AssetImporter>) {
+        debug!("Adding the importer {} (version {}).", importer.name(), importer.version());
+        self.importers.push(importer);
+    }
+
+    pub fn cache(&self) -> &ImportCache {
+        &self.cache
+    }
+
+    pub fn output_path(&self, guid: &AssetGuid) -> PathBuf {
+        self.output_directory.join(guid.as_str())
+    }
+
+    //The importer named in the meta file, or the first importer handling the extension of the asset.
+    fn importer_for(&self, path: &str, meta: &AssetMeta) -> Option<usize> {
+        if let Some(index) = self.importers.iter().position(|importer| importer.name() == meta.importer) {
+            return Some(index);
+        }
+        let extension = Path::new(path).extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        self.importers.iter().position(|importer| importer.extensions().contains(&extension.as_str()))
+    }
+
+    fn is_up_to_date(&self, guid: &AssetGuid, pending: &PendingImport) -> bool {
+        let importer = &self.importers[pending.importer];
+        match self.cache.get(guid) {
+            Some(record) => {
+                record.input_hash == pending.input_hash &&
+                    record.importer == importer.name() &&
+                    record.importer_version == importer.version()
+            },
+            None => false,
+        }
+    }
+
+    pub fn import(&mut self, database: &AssetDatabase) -> PipelineResult<ImportReport> {
+        debug!("Importing the assets of {}.", database.root().display());
+        let mut report = ImportReport::default();
+
+        //Read the sources and find what changed.
+        let mut pending = BTreeMap::new();
+        let mut dirty = BTreeSet::new();
+        for (path, guid) in database.assets() {
+            let asset_path = database.root().join(path);
+            let mut meta = AssetMeta::from_reader(Filesystem::open(meta_path(asset_path.as_path()))?)?;
+            meta.settings = self.profile.resolve_settings(&meta.settings);
+            let importer = match self.importer_for(path, &meta) {
+                Some(importer) => importer,
+                None => {
+                    trace!("No importer for the asset {}.", path);
+                    continue;
+                },
+            };
+
+            let mut source = Vec::new();
+            Filesystem::open(asset_path.as_path())?.read_to_end(&mut source).map_err(|io_error| FileSystemError::from(io_error))?;
+            let import = PendingImport {
+                path: path.to_string(),
+                input_hash: input_hash(source.as_slice(), &meta.settings),
+                source,
+                meta,
+                importer,
+            };
+            if !self.is_up_to_date(guid, &import) {
+                dirty.insert(guid.clone());
+            }
+            pending.insert(guid.clone(), import);
+        }
+
+        //Cascade through the assets depending on the dirty ones.
+        let mut queue: Vec<AssetGuid> = dirty.iter().cloned().collect();
+        while let Some(guid) = queue.pop() {
+            for dependent in self.cache.dependents(&guid) {
+                if pending.contains_key(dependent) && dirty.insert(dependent.clone()) {
+                    trace!("{} is reimported because one of its dependencies changed.", pending[dependent].path);
+                    queue.push(dependent.clone());
+                }
+            }
+        }
+
+        //Forget the deleted assets.
+        let removed: Vec<AssetGuid> = self.cache.guids().into_iter()
+            .filter(|guid| database.path_of(guid).is_none())
+            .cloned()
+            .collect();
+        for guid in removed.iter() {
+            self.cache.remove(guid);
+        }
+        report.removed = removed.len();
+        report.up_to_date = pending.len() - dirty.len();
+
+        if !dirty.is_empty() {
+            Filesystem::mkdir(self.output_directory.as_path())?;
+        }
+        for guid in dirty {
+            let import = &pending[&guid];
+            let importer = &self.importers[import.importer];
+            debug!("Importing {} with the importer {}.", import.path, importer.name());
+
+            match importer.import(import.path.as_str(), import.source.as_slice(), &import.meta) {
+                Ok(output) => {
+                    let mut writer = Filesystem::create(self.output_directory.join(guid.as_str()))?;
+                    writer.write_all(output.data.as_slice()).map_err(|io_error| FileSystemError::from(io_error))?;
+
+                    let dependencies = output.dependencies.iter().filter_map(|dependency| {
+                        let guid = database.guid_of(dependency.as_str()).cloned().or_else(|| {
+                            AssetGuid::parse(dependency.as_str()).filter(|guid| database.path_of(guid).is_some())
+                        });
+                        if guid.is_none() {
+                            warn!("The asset {} depends on the unknown asset {}.", import.path, dependency);
+                        }
+                        guid
+                    }).collect();
+                    self.cache.insert(guid.clone(), ImportRecord {
+                        input_hash: import.input_hash,
+                        importer: importer.name().to_string(),
+                        importer_version: importer.version(),
+                        dependencies,
+                    });
+                    report.imported.push(import.path.clone());
+                },
+                Err(error) => {
+                    error!("Could not import {}: {}", import.path, error);
+                    //Retried at the next run.
+                    self.cache.remove(&guid);
+                    report.failed.push((import.path.clone(), error.to_string()));
+                },
+            }
+        }
+
+        report.imported.sort();
+        debug!("{} assets imported, {} up to date, {} failed.", report.imported.len(), report.up_to_date, report.failed.len());
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod incremental_import_test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use pipeline::asset_importer::ImportOutput;
+
+    //Uppercases the text, the lines "use <path>" are dependencies.
+    struct TextImporter(u32);
+
+    impl AssetImporter for TextImporter {
+        fn name(&self) -> &str {
+            "text"
+        }
+
+        fn version(&self) -> u32 {
+            self.0
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["txt"]
+        }
+
+        fn import(&self, _path: &str, source: &[u8], _meta: &AssetMeta) -> PipelineResult<ImportOutput> {
+            let text = String::from_utf8_lossy(source);
+            Ok(ImportOutput {
+                data: text.to_uppercase().into_bytes(),
+                dependencies: text.lines().filter(|line| line.starts_with("use ")).map(|line| line[4..].to_string()).collect(),
+            })
+        }
+    }
+
+    #[test]
+    fn incremental_import_cascade() {
+        let root = env::temp_dir().join("maskerad_incremental_import_test");
+        let _ = fs::remove_dir_all(root.as_path());
+        let assets = root.join("assets");
+        fs::create_dir_all(assets.as_path()).unwrap();
+        fs::write(assets.join("material.txt"), b"use texture.txt").unwrap();
+        fs::write(assets.join("texture.txt"), b"red").unwrap();
+        fs::write(assets.join("other.txt"), b"other").unwrap();
+
+        let mut database = AssetDatabase::new(assets.as_path());
+        database.scan().unwrap();
+        let mut pipeline = ImportPipeline::new(root.join("imported"));
+        pipeline.add_importer(Box::new(TextImporter(1)));
+
+        let report = pipeline.import(&database).unwrap();
+        assert_eq!(report.imported.len(), 3);
+        let texture = database.guid_of("texture.txt").unwrap().clone();
+        assert_eq!(fs::read(pipeline.output_path(&texture)).unwrap(), b"RED");
+
+        let report = pipeline.import(&database).unwrap();
+        assert!(report.imported.is_empty());
+        assert_eq!(report.up_to_date, 3);
+
+        //The material depends on the texture.
+        fs::write(assets.join("texture.txt"), b"blue").unwrap();
+        let report = pipeline.import(&database).unwrap();
+        assert_eq!(report.imported, vec![String::from("material.txt"), String::from("texture.txt")]);
+
+        //The cache survives a restart, a new importer version reimports everything.
+        let mut saved = Vec::new();
+        pipeline.cache().save(&mut saved).unwrap();
+        let mut pipeline = ImportPipeline::new(root.join("imported")).with_cache(ImportCache::from_reader(saved.as_slice()).unwrap());
+        pipeline.add_importer(Box::new(TextImporter(1)));
+        assert!(pipeline.import(&database).unwrap().imported.is_empty());
+        let mut pipeline = ImportPipeline::new(root.join("imported")).with_cache(pipeline.cache().clone());
+        pipeline.add_importer(Box::new(TextImporter(2)));
+        assert_eq!(pipeline.import(&database).unwrap().imported.len(), 3);
+
+        fs::remove_file(assets.join("other.txt")).unwrap();
+        fs::remove_file(assets.join("other.txt.meta")).unwrap();
+        database.scan().unwrap();
+        assert_eq!(pipeline.import(&database).unwrap().removed, 1);
+
+        fs::remove_dir_all(root.as_path()).unwrap();
+    }
+}
@@ -0,0 +1,392 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ MESH OPTIMIZATION.
+
+ Done by the mesh importer, on indexed triangle lists:
+ - Vertex cache optimization (Forsyth): the triangles are reordered so the vertices shaded recently
+   are reused, and the vertex shader runs fewer times.
+ - Overdraw optimization: the reordered triangles are grouped in clusters, and the clusters facing
+   outward are drawn first, so they hide the ones behind them.
+ - Simplification, to build the LOD chain: edges are collapsed in order of quadric error (Garland-Heckbert),
+   until the target triangle count or the max error is reached. The border vertices (UV seams, holes)
+   never move, and the collapses flipping triangles are rejected.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: usize, cache_size: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+    let mut score = match cache_position {
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let scaler = 1.0 / (cache_size - 3) as f32;
+            (1.0 - (position - 3) as f32 * scaler).powf(CACHE_DECAY_POWER)
+        },
+        None => 0.0,
+    };
+    score += VALENCE_BOOST_SCALE * (remaining_triangles as f32).powf(-VALENCE_BOOST_POWER);
+    score
+}
+
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize, cache_size: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let cache_size = cache_size.max(4);
+    let mut vertex_triangles = vec![Vec::new(); vertex_count];
+    for triangle in 0..triangle_count {
+        for corner in 0..3 {
+            vertex_triangles[indices[triangle * 3 + corner] as usize].push(triangle);
+        }
+    }
+    let mut remaining: Vec<usize> = vertex_triangles.iter().map(|triangles| triangles.len()).collect();
+    let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut scores: Vec<f32> = (0..vertex_count).map(|vertex| vertex_score(None, remaining[vertex], cache_size)).collect();
+    let triangle_score = |triangle: usize, scores: &[f32]| {
+        (0..3).map(|corner| scores[indices[triangle * 3 + corner] as usize]).sum::<f32>()
+    };
+    let mut triangle_scores: Vec<f32> = (0..triangle_count).map(|triangle| triangle_score(triangle, &scores)).collect();
+    let mut emitted = vec![false; triangle_count];
+    let mut cache: Vec<u32> = Vec::new();
+    let mut output = Vec::with_capacity(indices.len());
+    let mut best = (0..triangle_count).max_by(|a, b| triangle_scores[*a].total_cmp(&triangle_scores[*b]));
+    let mut scan_cursor = 0;
+
+    while let Some(triangle) = best {
+        emitted[triangle] = true;
+        let corners = [indices[triangle * 3], indices[triangle * 3 + 1], indices[triangle * 3 + 2]];
+        output.extend_from_slice(&corners);
+
+        for vertex in corners.iter() {
+            remaining[*vertex as usize] -= 1;
+            cache.retain(|cached| cached != vertex);
+        }
+        let mut new_cache: Vec<u32> = corners.to_vec();
+        new_cache.extend(cache.iter().cloned());
+        //The vertices pushed out of the cache lose their cache score too.
+        let evicted: Vec<u32> = if new_cache.len() > cache_size {new_cache.split_off(cache_size)} else {Vec::new()};
+        cache = new_cache;
+
+        for vertex in evicted.iter() {
+            cache_position[*vertex as usize] = None;
+            scores[*vertex as usize] = vertex_score(None, remaining[*vertex as usize], cache_size);
+        }
+        for (position, vertex) in cache.iter().enumerate() {
+            cache_position[*vertex as usize] = Some(position);
+            scores[*vertex as usize] = vertex_score(Some(position), remaining[*vertex as usize], cache_size);
+        }
+
+        //The next triangle is the best triangle using a vertex of the cache.
+        best = None;
+        let mut best_score = -1.0;
+        for vertex in cache.iter().chain(evicted.iter()) {
+            for adjacent in vertex_triangles[*vertex as usize].iter() {
+                if emitted[*adjacent] {
+                    continue;
+                }
+                triangle_scores[*adjacent] = triangle_score(*adjacent, &scores);
+                if triangle_scores[*adjacent] > best_score {
+                    best_score = triangle_scores[*adjacent];
+                    best = Some(*adjacent);
+                }
+            }
+        }
+        if best.is_none() {
+            while scan_cursor < triangle_count && emitted[scan_cursor] {
+                scan_cursor += 1;
+            }
+            if scan_cursor < triangle_count {
+                best = Some(scan_cursor);
+            }
+        }
+    }
+    output
+}
+
+//Average number of vertex shader invocations per triangle, with a FIFO cache. 0.5 is the best possible, 3 the worst.
+pub fn average_cache_miss_ratio(indices: &[u32], cache_size: usize) -> f32 {
+    if indices.is_empty() {
+        return 0.0;
+    }
+    let mut cache: Vec<u32> = Vec::new();
+    let mut misses = 0;
+    for index in indices {
+        if !cache.contains(index) {
+            misses += 1;
+            cache.push(*index);
+            if cache.len() > cache_size {
+                cache.remove(0);
+            }
+        }
+    }
+    misses as f32 / (indices.len() / 3) as f32
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn triangle_normal(positions: &[[f32; 3]], a: u32, b: u32, c: u32) -> [f32; 3] {
+    let (a, b, c) = (positions[a as usize], positions[b as usize], positions[c as usize]);
+    cross(sub(b, a), sub(c, a))
+}
+
+//Reorder clusters of cache-optimized triangles, the outward facing ones first.
+pub fn optimize_overdraw(indices: &[u32], positions: &[[f32; 3]], cluster_triangles: usize) -> Vec<u32> {
+    let cluster_size = cluster_triangles.max(1) * 3;
+    if indices.len() <= cluster_size || positions.is_empty() {
+        return indices.to_vec();
+    }
+
+    let mut center = [0.0f32; 3];
+    for position in positions {
+        for c in 0..3 {
+            center[c] += position[c] / positions.len() as f32;
+        }
+    }
+
+    let mut clusters: Vec<(f32, &[u32])> = indices.chunks(cluster_size).map(|cluster| {
+        let mut centroid = [0.0f32; 3];
+        let mut normal = [0.0f32; 3];
+        for triangle in cluster.chunks(3) {
+            let triangle_normal = triangle_normal(positions, triangle[0], triangle[1], triangle[2]);
+            for c in 0..3 {
+                normal[c] += triangle_normal[c];
+                for vertex in triangle {
+                    centroid[c] += positions[*vertex as usize][c] / cluster.len() as f32;
+                }
+            }
+        }
+        let length = dot(normal, normal).sqrt();
+        let facing = if length > 0.0 {dot(sub(centroid, center), normal) / length} else {0.0};
+        (facing, cluster)
+    }).collect();
+    clusters.sort_by(|a, b| b.0.total_cmp(&a.0));
+    clusters.iter().flat_map(|&(_, cluster)| cluster.iter().cloned()).collect()
+}
+
+//Symmetric 4x4 matrix, stored as the 10 coefficients of the upper triangle.
+#[derive(Debug, Copy, Clone, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn from_plane(normal: [f32; 3], point: [f32; 3], weight: f64) -> Self {
+        let (a, b, c) = (normal[0] as f64, normal[1] as f64, normal[2] as f64);
+        let d = -(a * point[0] as f64 + b * point[1] as f64 + c * point[2] as f64);
+        Quadric([
+            a * a * weight, a * b * weight, a * c * weight, a * d * weight,
+            b * b * weight, b * c * weight, b * d * weight,
+            c * c * weight, c * d * weight,
+            d * d * weight,
+        ])
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for i in 0..10 {
+            self.0[i] += other.0[i];
+        }
+    }
+
+    //Sum of the squared distances to the planes.
+    fn error(&self, point: [f32; 3]) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (point[0] as f64, point[1] as f64, point[2] as f64);
+        q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+//Simplify the triangle list until it has at most target_index_count indices, without moving the surface
+//more than max_error. Returns the indices and the error reached.
+pub fn simplify(indices: &[u32], positions: &[[f32; 3]], target_index_count: usize, max_error: f32) -> (Vec<u32>, f32) {
+    let mut quadrics = vec![Quadric::default(); positions.len()];
+    for triangle in indices.chunks(3) {
+        let normal = triangle_normal(positions, triangle[0], triangle[1], triangle[2]);
+        let area = dot(normal, normal).sqrt();
+        if area == 0.0 {
+            continue;
+        }
+        let unit = [normal[0] / area, normal[1] / area, normal[2] / area];
+        let quadric = Quadric::from_plane(unit, positions[triangle[0] as usize], 1.0);
+        for vertex in triangle {
+            quadrics[*vertex as usize].add(&quadric);
+        }
+    }
+
+    //An edge used by a single triangle is a border edge, its vertices are locked.
+    let mut edge_uses: HashMap<(u32, u32), usize> = HashMap::new();
+    for triangle in indices.chunks(3) {
+        for corner in 0..3 {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+            *edge_uses.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+    }
+    let mut locked = vec![false; positions.len()];
+    for (&(a, b), uses) in edge_uses.iter() {
+        if *uses == 1 {
+            locked[a as usize] = true;
+            locked[b as usize] = true;
+        }
+    }
+
+    let mut remap: Vec<u32> = (0..positions.len() as u32).collect();
+    let mut triangles: Vec<[u32; 3]> = indices.chunks(3).map(|triangle| [triangle[0], triangle[1], triangle[2]]).collect();
+    let max_cost = (max_error as f64) * (max_error as f64);
+    let mut reached_error = 0.0f64;
+
+    while triangles.len() * 3 > target_index_count {
+        //Candidate collapses: vertex "from" moves onto vertex "to".
+        let mut candidates = Vec::new();
+        let mut seen = HashSet::new();
+        for triangle in triangles.iter() {
+            for corner in 0..3 {
+                let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+                if !seen.insert((a.min(b), a.max(b))) {
+                    continue;
+                }
+                let mut quadric = quadrics[a as usize];
+                quadric.add(&quadrics[b as usize]);
+                if !locked[a as usize] {
+                    candidates.push((quadric.error(positions[b as usize]).max(0.0), a, b));
+                }
+                if !locked[b as usize] {
+                    candidates.push((quadric.error(positions[a as usize]).max(0.0), b, a));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut touched = HashSet::new();
+        let mut collapsed = 0;
+        let mut removable = triangles.len() * 3 - target_index_count;
+        for (cost, from, to) in candidates {
+            if cost > max_cost || removable == 0 {
+                break;
+            }
+            if touched.contains(&from) || touched.contains(&to) {
+                continue;
+            }
+            //The triangles around "from", which don't contain "to", must keep their orientation.
+            let flips = triangles.iter()
+                .filter(|triangle| triangle.contains(&from) && !triangle.contains(&to))
+                .any(|triangle| {
+                    let before = triangle_normal(positions, triangle[0], triangle[1], triangle[2]);
+                    let moved: Vec<u32> = triangle.iter().map(|vertex| if *vertex == from {to} else {*vertex}).collect();
+                    let after = triangle_normal(positions, moved[0], moved[1], moved[2]);
+                    dot(before, after) <= 0.0
+                });
+            if flips {
+                continue;
+            }
+
+            let removed_triangles = triangles.iter().filter(|triangle| triangle.contains(&from) && triangle.contains(&to)).count();
+            for triangle in triangles.iter() {
+                if triangle.contains(&from) {
+                    for vertex in triangle.iter() {
+                        touched.insert(*vertex);
+                    }
+                }
+            }
+            remap[from as usize] = to;
+            let quadric = quadrics[from as usize];
+            quadrics[to as usize].add(&quadric);
+            reached_error = reached_error.max(cost);
+            collapsed += 1;
+            removable = removable.saturating_sub(removed_triangles * 3);
+        }
+        if collapsed == 0 {
+            break;
+        }
+
+        for triangle in triangles.iter_mut() {
+            for vertex in triangle.iter_mut() {
+                while remap[*vertex as usize] != *vertex {
+                    *vertex = remap[*vertex as usize];
+                }
+            }
+        }
+        triangles.retain(|triangle| triangle[0] != triangle[1] && triangle[1] != triangle[2] && triangle[0] != triangle[2]);
+    }
+
+    (triangles.iter().flat_map(|triangle| triangle.iter().cloned()).collect(), reached_error.sqrt() as f32)
+}
+
+#[cfg(test)]
+mod mesh_optimization_test {
+    use super::*;
+
+    //A flat grid of size x size quads, in the XZ plane, facing up.
+    fn grid(size: u32) -> (Vec<[f32; 3]>, Vec<u32>) {
+        let mut positions = Vec::new();
+        for z in 0..size + 1 {
+            for x in 0..size + 1 {
+                positions.push([x as f32, 0.0, z as f32]);
+            }
+        }
+        let mut indices = Vec::new();
+        for z in 0..size {
+            for x in 0..size {
+                let i = z * (size + 1) + x;
+                indices.extend_from_slice(&[i, i + size + 1, i + 1, i + 1, i + size + 1, i + size + 2]);
+            }
+        }
+        (positions, indices)
+    }
+
+    #[test]
+    fn mesh_optimization_vertex_cache() {
+        let (positions, indices) = grid(16);
+        //Worst case order: the triangles shuffled.
+        let mut shuffled = Vec::new();
+        for step in 0..indices.len() / 3 {
+            let triangle = (step * 97) % (indices.len() / 3);
+            shuffled.extend_from_slice(&indices[triangle * 3..triangle * 3 + 3]);
+        }
+        let optimized = optimize_vertex_cache(shuffled.as_slice(), positions.len(), 16);
+        assert_eq!(optimized.len(), shuffled.len());
+        assert!(average_cache_miss_ratio(optimized.as_slice(), 16) < average_cache_miss_ratio(shuffled.as_slice(), 16));
+        assert!(average_cache_miss_ratio(optimized.as_slice(), 16) < 1.0);
+
+        let reordered = optimize_overdraw(optimized.as_slice(), positions.as_slice(), 8);
+        assert_eq!(reordered.len(), optimized.len());
+    }
+
+    #[test]
+    fn mesh_optimization_simplify() {
+        let (positions, indices) = grid(8);
+        //A flat grid can be simplified down to its border without any error.
+        let (simplified, error) = simplify(indices.as_slice(), positions.as_slice(), 0, 0.01);
+        assert!(simplified.len() < indices.len() / 2);
+        assert!(error < 0.01);
+        for triangle in simplified.chunks(3) {
+            assert!(triangle_normal(positions.as_slice(), triangle[0], triangle[1], triangle[2])[1] > 0.0);
+        }
+
+        //A bump in the middle is kept with a small max error.
+        let mut bumped = positions.clone();
+        bumped[4 * 9 + 4][1] = 2.0;
+        let (simplified, _) = simplify(indices.as_slice(), bumped.as_slice(), 0, 0.01);
+        assert!(simplified.contains(&(4 * 9 + 4)));
+    }
+}
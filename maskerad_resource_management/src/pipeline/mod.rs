@@ -0,0 +1,28 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+pub mod asset_guid;
+pub mod asset_meta;
+pub mod asset_database;
+pub mod pipeline_errors;
+pub mod asset_importer;
+pub mod import_cache;
+pub mod incremental_import;
+pub mod content_cache;
+pub mod texture_compression;
+pub mod texture_processing;
+pub mod audio_processing;
+pub mod mesh_optimization;
+pub mod scene_validation;
+pub mod string_tables;
+pub mod archive;
+pub mod archived;
+pub mod content_manifest;
+pub mod target_profile;
+pub mod cook;
+pub mod content_packages;
+pub mod importers;
@@ -0,0 +1,119 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::error::Error;
+use std::fmt;
+use serde_json::Error as JSONError;
+use maskerad_core::filesystem::filesystem_error::FileSystemError;
+use scenes::scene_errors::SceneError;
+use resources::resource_errors::ResourceError;
+
+#[derive(Debug)]
+pub enum PipelineError {
+    FilesystemError(String, FileSystemError),
+    MetaFormatError(String, JSONError),
+    SceneError(String, SceneError),
+    ImportError(String),
+    ResourceError(String, ResourceError),
+    CookError(String),
+    PackageError(String),
+    TruncatedAsset(String),
+    MalformedAsset(String),
+}
+
+unsafe impl Send for PipelineError {}
+unsafe impl Sync for PipelineError {}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &PipelineError::FilesystemError(ref description, _) => {
+                write!(f, "Filesystem error: {}", description)
+            },
+            &PipelineError::MetaFormatError(ref description, _) => {
+                write!(f, "Meta file format error: {}", description)
+            },
+            &PipelineError::SceneError(ref description, _) => {
+                write!(f, "Scene error: {}", description)
+            },
+            &PipelineError::ImportError(ref description) => {
+                write!(f, "Import error: {}", description)
+            },
+            &PipelineError::ResourceError(ref description, _) => {
+                write!(f, "Resource error: {}", description)
+            },
+            &PipelineError::CookError(ref description) => {
+                write!(f, "Cook error: {}", description)
+            },
+            &PipelineError::PackageError(ref description) => {
+                write!(f, "Content package error: {}", description)
+            },
+            &PipelineError::TruncatedAsset(ref description) => {
+                write!(f, "Truncated asset: {}", description)
+            },
+            &PipelineError::MalformedAsset(ref description) => {
+                write!(f, "Malformed asset: {}", description)
+            },
+        }
+    }
+}
+
+impl Error for PipelineError {
+    fn description(&self) -> &str {
+        match self {
+            &PipelineError::FilesystemError(_, _) => "FilesystemError",
+            &PipelineError::MetaFormatError(_, _) => "MetaFormatError",
+            &PipelineError::SceneError(_, _) => "SceneError",
+            &PipelineError::ImportError(_) => "ImportError",
+            &PipelineError::ResourceError(_, _) => "ResourceError",
+            &PipelineError::CookError(_) => "CookError",
+            &PipelineError::PackageError(_) => "PackageError",
+            &PipelineError::TruncatedAsset(_) => "TruncatedAsset",
+            &PipelineError::MalformedAsset(_) => "MalformedAsset",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match self {
+            &PipelineError::FilesystemError(_, ref cause) => Some(cause),
+            &PipelineError::MetaFormatError(_, ref cause) => Some(cause),
+            &PipelineError::SceneError(_, ref cause) => Some(cause),
+            &PipelineError::ImportError(_) => None,
+            &PipelineError::ResourceError(_, ref cause) => Some(cause),
+            &PipelineError::CookError(_) => None,
+            &PipelineError::PackageError(_) => None,
+            &PipelineError::TruncatedAsset(_) => None,
+            &PipelineError::MalformedAsset(_) => None,
+        }
+    }
+}
+
+pub type PipelineResult<T> = Result<T, PipelineError>;
+
+impl From<FileSystemError> for PipelineError {
+    fn from(error: FileSystemError) -> Self {
+        PipelineError::FilesystemError(String::from("Error while using the filesystem."), error)
+    }
+}
+
+impl From<JSONError> for PipelineError {
+    fn from(error: JSONError) -> Self {
+        PipelineError::MetaFormatError(String::from("Error while (de)serializing a meta file."), error)
+    }
+}
+
+impl From<SceneError> for PipelineError {
+    fn from(error: SceneError) -> Self {
+        PipelineError::SceneError(String::from("Error while processing a scene."), error)
+    }
+}
+
+impl From<ResourceError> for PipelineError {
+    fn from(error: ResourceError) -> Self {
+        PipelineError::ResourceError(String::from("Error while decoding the source of an asset."), error)
+    }
+}
@@ -0,0 +1,413 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SCENE VALIDATION.
+
+ Content errors (a missing mesh, a NaN position, a collider without rigid body) are cheaper to fix when
+ they are reported by the asset pipeline than when they crash the game.
+
+ The validator runs a list of rules on a scene, and reports each problem with the path of the entity
+ ("Village/House/Door"), so the problem can be found in the editor.
+ Prefabs are scenes too: validate a prefab directly, or flatten a scene (with its included prefabs) before
+ validating it.
+
+ The rules are configurable: custom rules can be added, and the severity of a rule can be changed or the
+ rule disabled.
+*/
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use pipeline::asset_database::AssetDatabase;
+use scenes::scene_description::{SceneDescription, EntityDescription, PropertyValue};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub rule: String,
+    //"Village/House/Door", or "#<id>" for an entity without name.
+    pub entity_path: String,
+    pub component: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.component {
+            Some(ref component) => write!(f, "{} [{}] {} ({}): {}", severity, self.rule, self.entity_path, component, self.message),
+            None => write!(f, "{} [{}] {}: {}", severity, self.rule, self.entity_path, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ValidationReport {
+    pub fn errors(&self) -> usize {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.severity == Severity::Error).count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.severity == Severity::Warning).count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors() > 0
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for diagnostic in self.diagnostics.iter() {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        write!(f, "{} errors, {} warnings.", self.errors(), self.warnings())
+    }
+}
+
+//What the rules can look at.
+pub struct ValidationContext<'a> {
+    pub scene: &'a SceneDescription,
+    //Without database, the asset references are not checked.
+    pub database: Option<&'a AssetDatabase>,
+    entities: HashMap<u64, &'a EntityDescription>,
+}
+
+impl<'a> ValidationContext<'a> {
+    pub fn new(scene: &'a SceneDescription, database: Option<&'a AssetDatabase>) -> Self {
+        ValidationContext {
+            scene,
+            database,
+            entities: scene.entities.iter().map(|entity| (entity.id, entity)).collect(),
+        }
+    }
+
+    pub fn entity(&self, id: u64) -> Option<&'a EntityDescription> {
+        self.entities.get(&id).cloned()
+    }
+
+    //The names of the ancestors and of the entity, separated by '/'.
+    pub fn entity_path(&self, id: u64) -> String {
+        let mut names = Vec::new();
+        let mut visited = HashSet::new();
+        let mut current = self.entity(id);
+        while let Some(entity) = current {
+            if !visited.insert(entity.id) {
+                break;
+            }
+            names.push(if entity.name.is_empty() {format!("#{}", entity.id)} else {entity.name.clone()});
+            current = entity.parent.and_then(|parent| self.entity(parent));
+        }
+        if names.is_empty() {
+            return format!("#{}", id);
+        }
+        names.reverse();
+        names.join("/")
+    }
+
+    //The entity, then its parent, up to the root.
+    pub fn ancestors(&self, id: u64) -> Vec<&'a EntityDescription> {
+        let mut ancestors = Vec::new();
+        let mut visited = HashSet::new();
+        let mut current = self.entity(id);
+        while let Some(entity) = current {
+            if !visited.insert(entity.id) {
+                break;
+            }
+            ancestors.push(entity);
+            current = entity.parent.and_then(|parent| self.entity(parent));
+        }
+        ancestors
+    }
+}
+
+//A problem found by a rule, before the severity of the rule is applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub entity: u64,
+    pub component: Option<String>,
+    pub message: String,
+}
+
+pub trait ValidationRule {
+    fn name(&self) -> &str;
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, context: &ValidationContext, findings: &mut Vec<Finding>);
+}
+
+//Two entities with the same id, or a parent which doesn't exist or is a descendant of the entity.
+pub struct HierarchyRule;
+
+impl ValidationRule for HierarchyRule {
+    fn name(&self) -> &str {
+        "hierarchy"
+    }
+
+    fn check(&self, context: &ValidationContext, findings: &mut Vec<Finding>) {
+        let mut ids = HashSet::new();
+        for entity in context.scene.entities.iter() {
+            if !ids.insert(entity.id) {
+                findings.push(Finding {
+                    entity: entity.id,
+                    component: None,
+                    message: format!("The id {} is used by several entities.", entity.id),
+                });
+            }
+            match entity.parent {
+                Some(parent) if context.entity(parent).is_none() => findings.push(Finding {
+                    entity: entity.id,
+                    component: None,
+                    message: format!("The parent {} doesn't exist.", parent),
+                }),
+                Some(_) if context.ancestors(entity.id).last().and_then(|root| root.parent).is_some() => findings.push(Finding {
+                    entity: entity.id,
+                    component: None,
+                    message: String::from("The entity is its own ancestor."),
+                }),
+                _ => {},
+            }
+        }
+    }
+}
+
+//References to assets unknown to the asset database.
+pub struct MissingAssetRule;
+
+impl ValidationRule for MissingAssetRule {
+    fn name(&self) -> &str {
+        "missing-asset"
+    }
+
+    fn check(&self, context: &ValidationContext, findings: &mut Vec<Finding>) {
+        let database = match context.database {
+            Some(database) => database,
+            None => return,
+        };
+        for missing in database.missing_references(context.scene) {
+            findings.push(Finding {
+                entity: missing.entity,
+                component: Some(missing.component),
+                message: format!("The property {} references the unknown asset {}.", missing.property, missing.reference),
+            });
+        }
+    }
+}
+
+//NaN or infinite values in the properties of some components (transforms by default).
+pub struct NonFiniteRule {
+    components: Vec<String>,
+}
+
+impl Default for NonFiniteRule {
+    fn default() -> Self {
+        NonFiniteRule::new(&["Transform"])
+    }
+}
+
+impl NonFiniteRule {
+    pub fn new(components: &[&str]) -> Self {
+        NonFiniteRule {
+            components: components.iter().map(|component| component.to_string()).collect(),
+        }
+    }
+}
+
+impl ValidationRule for NonFiniteRule {
+    fn name(&self) -> &str {
+        "non-finite"
+    }
+
+    fn check(&self, context: &ValidationContext, findings: &mut Vec<Finding>) {
+        for entity in context.scene.entities.iter() {
+            for component in entity.components.iter().filter(|component| self.components.contains(&component.type_name)) {
+                for (property, value) in component.properties.iter() {
+                    let finite = match value {
+                        &PropertyValue::Float(value) => value.is_finite(),
+                        &PropertyValue::Vector3(ref vector) => vector.iter().all(|value| value.is_finite()),
+                        _ => true,
+                    };
+                    if !finite {
+                        findings.push(Finding {
+                            entity: entity.id,
+                            component: Some(component.type_name.clone()),
+                            message: format!("The property {} is not finite: {:?}.", property, value),
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+//A component which needs another component, on the same entity or on an ancestor
+//(a collider needs a rigid body).
+pub struct RequiredComponentRule {
+    name: String,
+    component: String,
+    required: String,
+}
+
+impl RequiredComponentRule {
+    pub fn new<S: Into<String>>(component: S, required: S) -> Self {
+        let (component, required) = (component.into(), required.into());
+        RequiredComponentRule {
+            name: format!("requires-{}", required.to_lowercase()),
+            component,
+            required,
+        }
+    }
+}
+
+impl ValidationRule for RequiredComponentRule {
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    fn check(&self, context: &ValidationContext, findings: &mut Vec<Finding>) {
+        let has_component = |entity: &EntityDescription, type_name: &str| {
+            entity.components.iter().any(|component| component.type_name == type_name)
+        };
+        for entity in context.scene.entities.iter().filter(|entity| has_component(entity, self.component.as_str())) {
+            if !context.ancestors(entity.id).iter().any(|ancestor| has_component(ancestor, self.required.as_str())) {
+                findings.push(Finding {
+                    entity: entity.id,
+                    component: Some(self.component.clone()),
+                    message: format!("No {} on the entity or its ancestors.", self.required),
+                });
+            }
+        }
+    }
+}
+
+pub struct SceneValidator {
+    rules: Vec<Box<ValidationRule>>,
+    //None: the rule is disabled.
+    severities: BTreeMap<String, Option<Severity>>,
+}
+
+impl Default for SceneValidator {
+    fn default() -> Self {
+        let mut validator = SceneValidator::new();
+        validator.add_rule(Box::new(HierarchyRule));
+        validator.add_rule(Box::new(MissingAssetRule));
+        validator.add_rule(Box::new(NonFiniteRule::default()));
+        validator.add_rule(Box::new(RequiredComponentRule::new("Collider", "RigidBody")));
+        validator
+    }
+}
+
+impl SceneValidator {
+    //A validator without rules. Use default() for the built-in rules.
+    pub fn new() -> Self {
+        SceneValidator {
+            rules: Vec::new(),
+            severities: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_rule(&mut self, rule: Box<ValidationRule>) {
+        self.rules.push(rule);
+    }
+
+    pub fn rule_names(&self) -> Vec<&str> {
+        self.rules.iter().map(|rule| rule.name()).collect()
+    }
+
+    pub fn set_severity<S: Into<String>>(&mut self, rule: S, severity: Severity) {
+        self.severities.insert(rule.into(), Some(severity));
+    }
+
+    pub fn disable<S: Into<String>>(&mut self, rule: S) {
+        self.severities.insert(rule.into(), None);
+    }
+
+    pub fn validate(&self, scene: &SceneDescription, database: Option<&AssetDatabase>) -> ValidationReport {
+        let context = ValidationContext::new(scene, database);
+        let mut report = ValidationReport::default();
+        for rule in self.rules.iter() {
+            let severity = match self.severities.get(rule.name()) {
+                Some(&Some(severity)) => severity,
+                Some(&None) => {
+                    trace!("The validation rule {} is disabled.", rule.name());
+                    continue;
+                },
+                None => rule.default_severity(),
+            };
+            let mut findings = Vec::new();
+            rule.check(&context, &mut findings);
+            report.diagnostics.extend(findings.into_iter().map(|finding| Diagnostic {
+                severity,
+                rule: rule.name().to_string(),
+                entity_path: context.entity_path(finding.entity),
+                component: finding.component,
+                message: finding.message,
+            }));
+        }
+        //Errors first, then by entity.
+        report.diagnostics.sort_by(|a, b| b.severity.cmp(&a.severity).then_with(|| a.entity_path.cmp(&b.entity_path)));
+        debug!("Scene validated: {} errors, {} warnings.", report.errors(), report.warnings());
+        report
+    }
+}
+
+#[cfg(test)]
+mod scene_validation_test {
+    use super::*;
+    use scenes::scene_description::ComponentDescription;
+
+    fn entity(id: u64, name: &str, parent: Option<u64>, components: Vec<ComponentDescription>) -> EntityDescription {
+        EntityDescription {
+            id,
+            name: name.to_string(),
+            parent,
+            components,
+        }
+    }
+
+    #[test]
+    fn scene_validation_report() {
+        let mut scene = SceneDescription::new();
+        scene.entities.push(entity(1, "House", None, vec![ComponentDescription::new("RigidBody")]));
+        scene.entities.push(entity(2, "Door", Some(1), vec![
+            ComponentDescription::new("Collider"),
+            ComponentDescription::new("Transform").with_property("position", PropertyValue::Vector3([0.0, ::std::f32::NAN, 0.0])),
+        ]));
+        scene.entities.push(entity(3, "", None, vec![ComponentDescription::new("Collider")]));
+        scene.entities.push(entity(4, "Lost", Some(42), Vec::new()));
+
+        let mut validator = SceneValidator::default();
+        let report = validator.validate(&scene, None);
+        assert_eq!(report.errors(), 3);
+        let paths: Vec<&str> = report.diagnostics.iter().map(|diagnostic| diagnostic.entity_path.as_str()).collect();
+        assert_eq!(paths, vec!["#3", "House/Door", "Lost"]);
+        assert_eq!(report.diagnostics[1].rule, "non-finite");
+        assert_eq!(report.diagnostics[0].to_string(), "error [requires-rigidbody] #3 (Collider): No RigidBody on the entity or its ancestors.");
+
+        validator.set_severity("requires-rigidbody", Severity::Warning);
+        validator.disable("hierarchy");
+        let report = validator.validate(&scene, None);
+        assert_eq!((report.errors(), report.warnings()), (1, 1));
+        assert_eq!(report.diagnostics.last().unwrap().entity_path, "#3");
+    }
+}
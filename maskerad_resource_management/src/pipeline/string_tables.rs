@@ -0,0 +1,242 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ STRING TABLES.
+
+ The texts shown to the players are in string tables, one per locale, in the source assets:
+ localization/<locale>/localization.json, a JSON object from the localization keys to the texts.
+
+ The scenes and the UI reference the texts by key: in a scene, a text property whose name ends with
+ "_key" ("label_key", "tooltip_key") holds a localization key. The other assets report their keys
+ with KeyUsage.
+
+ The compile step checks the keys against the tables, before the content ships:
+ - a key used by an asset and missing from a table is an error: the players would see MISSING_STRING,
+ - a key of a table used by no asset is a warning,
+ - a text without the placeholders ("{player}") of the text of the reference locale is an error.
+ Then a pack is baked per locale, read by the localization system of the engine.
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use serde_json;
+use maskerad_core::filesystem::filesystem::Filesystem;
+use pipeline::asset_database::AssetDatabase;
+use pipeline::pipeline_errors::{PipelineError, PipelineResult};
+use pipeline::scene_validation::{Diagnostic, Severity, ValidationContext, ValidationReport};
+use scenes::scene_description::{PropertyValue, SceneDescription};
+
+pub const LOCALIZATION_DIRECTORY: &'static str = "localization";
+pub const STRING_TABLE_FILE: &'static str = "localization.json";
+const KEY_PROPERTY_SUFFIX: &'static str = "_key";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyUsage {
+    pub key: String,
+    //"levels/village.scene: Village/Sign (Label)".
+    pub used_by: String,
+}
+
+//The localization keys of a scene.
+pub fn scene_keys(path: &str, scene: &SceneDescription) -> Vec<KeyUsage> {
+    let context = ValidationContext::new(scene, None);
+    let mut usages = Vec::new();
+    for entity in scene.entities.iter() {
+        for component in entity.components.iter() {
+            for (name, value) in component.properties.iter() {
+                if let &PropertyValue::Text(ref key) = value {
+                    if name.ends_with(KEY_PROPERTY_SUFFIX) {
+                        usages.push(KeyUsage {
+                            key: key.clone(),
+                            used_by: format!("{}: {} ({})", path, context.entity_path(entity.id), component.type_name),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    usages
+}
+
+//"localization/fr/localization.json" -> "fr".
+pub fn table_locale(path: &str) -> Option<&str> {
+    let mut parts = path.split('/');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(LOCALIZATION_DIRECTORY), Some(locale), Some(STRING_TABLE_FILE), None) if !locale.is_empty() => Some(locale),
+        _ => None,
+    }
+}
+
+//The names of the "{placeholders}" of a text.
+fn placeholders(text: &str) -> BTreeSet<&str> {
+    let mut names = BTreeSet::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        match rest[start..].find('}') {
+            Some(end) => {
+                names.insert(&rest[start + 1..start + end]);
+                rest = &rest[start + end + 1..];
+            },
+            None => break,
+        }
+    }
+    names
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringTables {
+    reference_locale: String,
+    tables: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl StringTables {
+    //The reference locale is the one the texts are written in, the others are translations of it.
+    pub fn new<S: Into<String>>(reference_locale: S) -> Self {
+        StringTables {
+            reference_locale: reference_locale.into(),
+            tables: BTreeMap::new(),
+        }
+    }
+
+    //The tables of the asset database.
+    pub fn from_database<S: Into<String>>(database: &AssetDatabase, reference_locale: S) -> PipelineResult<Self> {
+        let mut tables = StringTables::new(reference_locale);
+        for (path, _) in database.assets() {
+            if let Some(locale) = table_locale(path) {
+                let file = Filesystem::open(database.root().join(path))?;
+                tables.add_table(locale, file)?;
+            }
+        }
+        Ok(tables)
+    }
+
+    pub fn add_table<S: Into<String>, R: Read>(&mut self, locale: S, reader: R) -> PipelineResult<()> {
+        let locale = locale.into();
+        let table: BTreeMap<String, String> = serde_json::from_reader(reader).map_err(|json_error| {
+            PipelineError::ImportError(format!("The string table of the locale {} is not a JSON object of texts: {}", locale, json_error))
+        })?;
+        debug!("String table {}: {} texts.", locale, table.len());
+        self.tables.insert(locale, table);
+        Ok(())
+    }
+
+    pub fn reference_locale(&self) -> &str {
+        self.reference_locale.as_str()
+    }
+
+    pub fn locales(&self) -> Vec<&str> {
+        self.tables.keys().map(|locale| locale.as_str()).collect()
+    }
+
+    pub fn get(&self, locale: &str, key: &str) -> Option<&str> {
+        self.tables.get(locale).and_then(|table| table.get(key)).map(|text| text.as_str())
+    }
+
+    pub fn check(&self, usages: &[KeyUsage]) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let mut diagnostic = |severity, rule: &str, entity_path: String, message: String| {
+            report.diagnostics.push(Diagnostic {
+                severity,
+                rule: rule.to_string(),
+                entity_path,
+                component: None,
+                message,
+            });
+        };
+        let reference = match self.tables.get(self.reference_locale.as_str()) {
+            Some(reference) => reference,
+            None => {
+                diagnostic(Severity::Error, "missing-table", self.reference_locale.clone(), String::from("The string table of the reference locale is missing."));
+                return report;
+            },
+        };
+
+        let used: BTreeSet<&str> = usages.iter().map(|usage| usage.key.as_str()).collect();
+        for (locale, table) in self.tables.iter() {
+            for usage in usages.iter() {
+                if !table.contains_key(usage.key.as_str()) {
+                    diagnostic(Severity::Error, "missing-string", usage.used_by.clone(), format!("The key {} is not in the {} string table.", usage.key, locale));
+                }
+            }
+            for (key, text) in table.iter() {
+                if text.trim().is_empty() {
+                    diagnostic(Severity::Error, "empty-string", locale.clone(), format!("The text of the key {} is empty.", key));
+                }
+                match reference.get(key.as_str()) {
+                    Some(reference_text) if placeholders(reference_text) != placeholders(text) => {
+                        diagnostic(Severity::Error, "placeholder-mismatch", locale.clone(), format!(
+                            "The text of the key {} has the placeholders {:?}, the {} text has {:?}.",
+                            key, placeholders(text), self.reference_locale, placeholders(reference_text)
+                        ));
+                    },
+                    Some(_) => {},
+                    None => diagnostic(Severity::Warning, "unknown-string", locale.clone(), format!("The key {} is not in the {} string table.", key, self.reference_locale)),
+                }
+            }
+        }
+        for key in reference.keys().filter(|key| !used.contains(key.as_str())) {
+            diagnostic(Severity::Warning, "unused-string", self.reference_locale.clone(), format!("The key {} is used by no asset.", key));
+        }
+        report
+    }
+
+    //The pack of a locale, the format of the localization system (a JSON object, sorted by key).
+    pub fn bake(&self, locale: &str) -> PipelineResult<Vec<u8>> {
+        let table = self.tables.get(locale).ok_or_else(|| {
+            PipelineError::CookError(format!("There is no string table for the locale {}.", locale))
+        })?;
+        serde_json::to_vec_pretty(table).map_err(PipelineError::from)
+    }
+}
+
+#[cfg(test)]
+mod string_tables_test {
+    use super::*;
+    use maskerad_core::localization::localization::Localization;
+    use scenes::scene_description::{ComponentDescription, EntityDescription};
+
+    #[test]
+    fn string_tables_check_and_bake() {
+        let mut scene = SceneDescription::new();
+        scene.entities.push(EntityDescription {
+            id: 1,
+            name: String::from("Sign"),
+            parent: None,
+            components: vec![ComponentDescription::new("Label")
+                .with_property("label_key", PropertyValue::Text(String::from("greeting")))
+                .with_property("font", PropertyValue::Text(String::from("serif")))],
+        });
+        let mut usages = scene_keys("levels/village.scene", &scene);
+        assert_eq!(usages.len(), 1);
+        assert_eq!(usages[0].used_by, "levels/village.scene: Sign (Label)");
+        usages.push(KeyUsage { key: String::from("welcome"), used_by: String::from("ui/menu.json") });
+        assert_eq!(table_locale("localization/fr/localization.json"), Some("fr"));
+        assert_eq!(table_locale("levels/localization.json"), None);
+
+        let mut tables = StringTables::new("en");
+        tables.add_table("en", r#"{"greeting": "Hello", "welcome": "Welcome {player}", "bye": "Good bye"}"#.as_bytes()).unwrap();
+        tables.add_table("fr", r#"{"greeting": "Bonjour", "welcome": "Bienvenue"}"#.as_bytes()).unwrap();
+        assert!(tables.add_table("es", "[1, 2]".as_bytes()).is_err());
+        let report = tables.check(usages.as_slice());
+        let rules: Vec<&str> = report.diagnostics.iter().map(|diagnostic| diagnostic.rule.as_str()).collect();
+        assert_eq!(rules, vec!["placeholder-mismatch", "unused-string"]);
+        assert!(report.has_errors());
+
+        tables.add_table("fr", r#"{"greeting": "Bonjour", "welcome": "Bienvenue {player}"}"#.as_bytes()).unwrap();
+        usages.push(KeyUsage { key: String::from("bye"), used_by: String::from("ui/menu.json") });
+        let report = tables.check(usages.as_slice());
+        assert_eq!(report.errors(), 1);
+        assert_eq!(report.diagnostics[0].rule, "missing-string");
+        assert_eq!(report.diagnostics[0].entity_path, "ui/menu.json");
+
+        let pack = tables.bake("fr").unwrap();
+        let localization = Localization::from_reader(pack.as_slice()).unwrap();
+        assert_eq!(localization.get("welcome"), Some("Bienvenue {player}"));
+        assert!(tables.bake("de").is_err());
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ TARGET PROFILES.
+
+ A target profile describes the hardware the content is cooked for. The importers consult it to produce
+ a variant of each asset for the target: texture formats and sizes, audio sample rate, mesh LODs.
+
+ Built-in profiles:
+ - "desktop-high": BC textures at the quality of the meta files, 48kHz audio, every LOD.
+ - "desktop-low": BC textures, medium quality at most, 1024 pixels at most, 44.1kHz audio, the full detail LOD is dropped.
+ - "mobile": ASTC textures, medium quality at most, 1024 pixels at most, 24kHz audio, the full detail LOD is dropped.
+
+ The settings of a meta file can be overridden for a profile, with the name of the profile after a '@':
+ "max_size@mobile": "256" replaces "max_size" when cooking for the mobile profile.
+*/
+
+use std::collections::BTreeMap;
+use pipeline::pipeline_errors::{PipelineError, PipelineResult};
+use pipeline::texture_compression::{TexturePlatform, TextureQuality};
+
+pub const DEFAULT_PROFILE: &'static str = "desktop-high";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetProfile {
+    pub name: String,
+    pub platform: TexturePlatform,
+    //The quality of the meta files is lowered to this one.
+    pub max_texture_quality: TextureQuality,
+    //The max size of the meta files is lowered to this one.
+    pub max_texture_size: Option<usize>,
+    //The rate of the mixer on the target.
+    pub audio_sample_rate: u32,
+    //The number of detailed LODs which are not shipped.
+    pub skipped_mesh_lods: usize,
+}
+
+impl TargetProfile {
+    pub fn desktop_high() -> Self {
+        TargetProfile {
+            name: String::from("desktop-high"),
+            platform: TexturePlatform::Desktop,
+            max_texture_quality: TextureQuality::High,
+            max_texture_size: None,
+            audio_sample_rate: 48000,
+            skipped_mesh_lods: 0,
+        }
+    }
+
+    pub fn desktop_low() -> Self {
+        TargetProfile {
+            name: String::from("desktop-low"),
+            platform: TexturePlatform::Desktop,
+            max_texture_quality: TextureQuality::Medium,
+            max_texture_size: Some(1024),
+            audio_sample_rate: 44100,
+            skipped_mesh_lods: 1,
+        }
+    }
+
+    pub fn mobile() -> Self {
+        TargetProfile {
+            name: String::from("mobile"),
+            platform: TexturePlatform::Mobile,
+            max_texture_quality: TextureQuality::Medium,
+            max_texture_size: Some(1024),
+            audio_sample_rate: 24000,
+            skipped_mesh_lods: 1,
+        }
+    }
+
+    pub fn built_in() -> Vec<TargetProfile> {
+        vec![TargetProfile::desktop_high(), TargetProfile::desktop_low(), TargetProfile::mobile()]
+    }
+
+    pub fn from_name(name: &str) -> PipelineResult<Self> {
+        TargetProfile::built_in().into_iter().find(|profile| profile.name == name).ok_or_else(|| {
+            PipelineError::CookError(format!("Unknown target profile {}.", name))
+        })
+    }
+
+    //The settings of a meta file for this profile: the "<setting>@<profile>" settings replace the "<setting>" settings,
+    //and the overrides of the other profiles are removed.
+    pub fn resolve_settings(&self, settings: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+        let mut resolved: BTreeMap<String, String> = settings.iter()
+            .filter(|&(key, _)| !key.contains('@'))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let suffix = format!("@{}", self.name);
+        for (key, value) in settings.iter().filter(|&(key, _)| key.ends_with(suffix.as_str())) {
+            resolved.insert(key[..key.len() - suffix.len()].to_string(), value.clone());
+        }
+        resolved
+    }
+}
+
+impl Default for TargetProfile {
+    fn default() -> Self {
+        TargetProfile::desktop_high()
+    }
+}
+
+#[cfg(test)]
+mod target_profile_test {
+    use super::*;
+
+    #[test]
+    fn target_profile_overrides() {
+        let mut settings = BTreeMap::new();
+        settings.insert(String::from("max_size"), String::from("2048"));
+        settings.insert(String::from("max_size@mobile"), String::from("256"));
+        settings.insert(String::from("quality@desktop-low"), String::from("low"));
+
+        let mobile = TargetProfile::from_name("mobile").unwrap();
+        let resolved = mobile.resolve_settings(&settings);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved["max_size"], "256");
+        let resolved = TargetProfile::desktop_low().resolve_settings(&settings);
+        assert_eq!((resolved["max_size"].as_str(), resolved["quality"].as_str()), ("2048", "low"));
+        assert!(TargetProfile::from_name("console").is_err());
+    }
+}
@@ -0,0 +1,339 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ TEXTURE COMPRESSION.
+
+ Textures are compressed at import, in a format the GPU of the target platform can sample directly:
+ BCn on desktop, ASTC on mobile.
+ The format depends on the class of the texture and on the quality preset:
+ - albedo: BC1 (BC3 with alpha) on desktop, ASTC with a block size depending on the quality on mobile,
+ - normal maps: BC5 (two channels, the third one is rebuilt in the shader) on desktop, ASTC 4x4 on mobile,
+ - UI: uncompressed at high quality (compression artifacts are very visible on text and flat colors).
+
+ The BC1, BC3, BC4 and BC5 encoders are built in. ASTC needs an external encoder, given to the
+ texture importer as a TextureEncoder. Without it, ASTC textures are stored uncompressed.
+*/
+
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum TextureFormat {
+    Rgba8,
+    Bc1,
+    Bc3,
+    Bc4,
+    Bc5,
+    Astc4x4,
+    Astc6x6,
+    Astc8x8,
+}
+
+impl TextureFormat {
+    pub fn id(&self) -> u32 {
+        match *self {
+            TextureFormat::Rgba8 => 0,
+            TextureFormat::Bc1 => 1,
+            TextureFormat::Bc3 => 3,
+            TextureFormat::Bc4 => 4,
+            TextureFormat::Bc5 => 5,
+            TextureFormat::Astc4x4 => 100,
+            TextureFormat::Astc6x6 => 101,
+            TextureFormat::Astc8x8 => 102,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Option<Self> {
+        [
+            TextureFormat::Rgba8, TextureFormat::Bc1, TextureFormat::Bc3, TextureFormat::Bc4,
+            TextureFormat::Bc5, TextureFormat::Astc4x4, TextureFormat::Astc6x6, TextureFormat::Astc8x8,
+        ].iter().cloned().find(|format| format.id() == id)
+    }
+
+    //Width and height of a block of pixels, 1 for uncompressed formats.
+    pub fn block_size(&self) -> usize {
+        match *self {
+            TextureFormat::Rgba8 => 1,
+            TextureFormat::Bc1 | TextureFormat::Bc3 | TextureFormat::Bc4 | TextureFormat::Bc5 | TextureFormat::Astc4x4 => 4,
+            TextureFormat::Astc6x6 => 6,
+            TextureFormat::Astc8x8 => 8,
+        }
+    }
+
+    pub fn bytes_per_block(&self) -> usize {
+        match *self {
+            TextureFormat::Rgba8 => 4,
+            TextureFormat::Bc1 | TextureFormat::Bc4 => 8,
+            _ => 16,
+        }
+    }
+
+    pub fn data_size(&self, width: usize, height: usize) -> usize {
+        let block = self.block_size();
+        ((width + block - 1) / block) * ((height + block - 1) / block) * self.bytes_per_block()
+    }
+
+    pub fn is_astc(&self) -> bool {
+        match *self {
+            TextureFormat::Astc4x4 | TextureFormat::Astc6x6 | TextureFormat::Astc8x8 => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TexturePlatform {
+    Desktop,
+    Mobile,
+}
+
+impl FromStr for TexturePlatform {
+    type Err = String;
+
+    fn from_str(platform: &str) -> Result<Self, Self::Err> {
+        match platform {
+            "desktop" => Ok(TexturePlatform::Desktop),
+            "mobile" => Ok(TexturePlatform::Mobile),
+            _ => Err(format!("Unknown platform {}.", platform)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TextureClass {
+    Albedo,
+    Normal,
+    Ui,
+}
+
+impl FromStr for TextureClass {
+    type Err = String;
+
+    fn from_str(class: &str) -> Result<Self, Self::Err> {
+        match class {
+            "albedo" => Ok(TextureClass::Albedo),
+            "normal" => Ok(TextureClass::Normal),
+            "ui" => Ok(TextureClass::Ui),
+            _ => Err(format!("Unknown texture class {}.", class)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum TextureQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl FromStr for TextureQuality {
+    type Err = String;
+
+    fn from_str(quality: &str) -> Result<Self, Self::Err> {
+        match quality {
+            "low" => Ok(TextureQuality::Low),
+            "medium" => Ok(TextureQuality::Medium),
+            "high" => Ok(TextureQuality::High),
+            _ => Err(format!("Unknown texture quality {}.", quality)),
+        }
+    }
+}
+
+pub fn select_format(platform: TexturePlatform, class: TextureClass, quality: TextureQuality, has_alpha: bool) -> TextureFormat {
+    match (platform, class, quality) {
+        (_, TextureClass::Ui, TextureQuality::High) => TextureFormat::Rgba8,
+        (TexturePlatform::Desktop, TextureClass::Normal, _) => TextureFormat::Bc5,
+        (TexturePlatform::Desktop, _, _) => if has_alpha {TextureFormat::Bc3} else {TextureFormat::Bc1},
+        (TexturePlatform::Mobile, TextureClass::Normal, _) => TextureFormat::Astc4x4,
+        (TexturePlatform::Mobile, _, TextureQuality::High) => TextureFormat::Astc4x4,
+        (TexturePlatform::Mobile, _, TextureQuality::Medium) => TextureFormat::Astc6x6,
+        (TexturePlatform::Mobile, TextureClass::Ui, TextureQuality::Low) => TextureFormat::Astc6x6,
+        (TexturePlatform::Mobile, _, TextureQuality::Low) => TextureFormat::Astc8x8,
+    }
+}
+
+//An external encoder, for the formats without built-in encoder.
+pub trait TextureEncoder {
+    fn format(&self) -> TextureFormat;
+    fn encode(&self, rgba: &[u8], width: usize, height: usize, quality: TextureQuality) -> Vec<u8>;
+}
+
+//Compress RGBA8 pixels with a built-in encoder. None for the formats without built-in encoder.
+pub fn compress(format: TextureFormat, rgba: &[u8], width: usize, height: usize) -> Option<Vec<u8>> {
+    match format {
+        TextureFormat::Rgba8 => Some(rgba.to_vec()),
+        TextureFormat::Bc1 => Some(encode_blocks(rgba, width, height, |block, output| encode_bc1(block, output))),
+        TextureFormat::Bc3 => Some(encode_blocks(rgba, width, height, |block, output| {
+            encode_bc4(&channel(block, 3), output);
+            encode_bc1(block, output);
+        })),
+        TextureFormat::Bc4 => Some(encode_blocks(rgba, width, height, |block, output| encode_bc4(&channel(block, 0), output))),
+        TextureFormat::Bc5 => Some(encode_blocks(rgba, width, height, |block, output| {
+            encode_bc4(&channel(block, 0), output);
+            encode_bc4(&channel(block, 1), output);
+        })),
+        _ => None,
+    }
+}
+
+//Call the encoder for each 4x4 block, the pixels outside the texture are clamped to the edge.
+fn encode_blocks<F>(rgba: &[u8], width: usize, height: usize, mut encode: F) -> Vec<u8> where
+    F: FnMut(&[[u8; 4]; 16], &mut Vec<u8>)
+{
+    let mut output = Vec::new();
+    if width == 0 || height == 0 {
+        return output;
+    }
+    for block_y in 0..(height + 3) / 4 {
+        for block_x in 0..(width + 3) / 4 {
+            let mut block = [[0u8; 4]; 16];
+            for i in 0..16 {
+                let x = (block_x * 4 + i % 4).min(width - 1);
+                let y = (block_y * 4 + i / 4).min(height - 1);
+                let offset = (y * width + x) * 4;
+                block[i].copy_from_slice(&rgba[offset..offset + 4]);
+            }
+            encode(&block, &mut output);
+        }
+    }
+    output
+}
+
+fn channel(block: &[[u8; 4]; 16], index: usize) -> [u8; 16] {
+    let mut values = [0u8; 16];
+    for i in 0..16 {
+        values[i] = block[i][index];
+    }
+    values
+}
+
+fn to_565(color: [u8; 4]) -> u16 {
+    ((color[0] as u16 >> 3) << 11) | ((color[1] as u16 >> 2) << 5) | (color[2] as u16 >> 3)
+}
+
+fn from_565(color: u16) -> [i32; 3] {
+    let r = ((color >> 11) & 31) as i32;
+    let g = ((color >> 5) & 63) as i32;
+    let b = (color & 31) as i32;
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+//The endpoints are the two most distant colors of the block.
+fn encode_bc1(block: &[[u8; 4]; 16], output: &mut Vec<u8>) {
+    let distance = |a: &[u8; 4], b: &[u8; 4]| {
+        (0..3).map(|c| (a[c] as i32 - b[c] as i32) * (a[c] as i32 - b[c] as i32)).sum::<i32>()
+    };
+    let (mut first, mut second) = (0, 0);
+    let mut max_distance = -1;
+    for i in 0..16 {
+        for j in i + 1..16 {
+            let d = distance(&block[i], &block[j]);
+            if d > max_distance {
+                max_distance = d;
+                first = i;
+                second = j;
+            }
+        }
+    }
+
+    let mut color0 = to_565(block[first]);
+    let mut color1 = to_565(block[second]);
+    //color0 > color1 selects the 4 colors mode.
+    if color0 < color1 {
+        ::std::mem::swap(&mut color0, &mut color1);
+    }
+
+    let mut indices = 0u32;
+    if color0 != color1 {
+        let c0 = from_565(color0);
+        let c1 = from_565(color1);
+        let palette = [
+            c0,
+            c1,
+            [(2 * c0[0] + c1[0]) / 3, (2 * c0[1] + c1[1]) / 3, (2 * c0[2] + c1[2]) / 3],
+            [(c0[0] + 2 * c1[0]) / 3, (c0[1] + 2 * c1[1]) / 3, (c0[2] + 2 * c1[2]) / 3],
+        ];
+        for (i, pixel) in block.iter().enumerate() {
+            let best = (0..4).min_by_key(|&p| {
+                let dr = palette[p][0] - pixel[0] as i32;
+                let dg = palette[p][1] - pixel[1] as i32;
+                let db = palette[p][2] - pixel[2] as i32;
+                dr * dr + dg * dg + db * db
+            }).unwrap_or(0);
+            indices |= (best as u32) << (2 * i);
+        }
+    }
+
+    output.extend_from_slice(&[color0 as u8, (color0 >> 8) as u8, color1 as u8, (color1 >> 8) as u8]);
+    output.extend_from_slice(&[indices as u8, (indices >> 8) as u8, (indices >> 16) as u8, (indices >> 24) as u8]);
+}
+
+//Single channel block, in the 8 values mode.
+fn encode_bc4(values: &[u8; 16], output: &mut Vec<u8>) {
+    let max = values.iter().cloned().max().unwrap_or(0);
+    let min = values.iter().cloned().min().unwrap_or(0);
+
+    let mut indices = 0u64;
+    if max != min {
+        let (a0, a1) = (max as i32, min as i32);
+        let mut palette = [a0, a1, 0, 0, 0, 0, 0, 0];
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as i32) * a0 + i as i32 * a1) / 7;
+        }
+        for (i, value) in values.iter().enumerate() {
+            let best = (0..8).min_by_key(|&p| (palette[p] - *value as i32).abs()).unwrap_or(0);
+            indices |= (best as u64) << (3 * i);
+        }
+    }
+
+    output.push(max);
+    output.push(min);
+    for byte in 0..6 {
+        output.push((indices >> (8 * byte)) as u8);
+    }
+}
+
+#[cfg(test)]
+mod texture_compression_test {
+    use super::*;
+
+    #[test]
+    fn texture_compression_format_selection() {
+        assert_eq!(select_format(TexturePlatform::Desktop, TextureClass::Albedo, TextureQuality::High, false), TextureFormat::Bc1);
+        assert_eq!(select_format(TexturePlatform::Desktop, TextureClass::Albedo, TextureQuality::High, true), TextureFormat::Bc3);
+        assert_eq!(select_format(TexturePlatform::Desktop, TextureClass::Normal, TextureQuality::Low, false), TextureFormat::Bc5);
+        assert_eq!(select_format(TexturePlatform::Mobile, TextureClass::Albedo, TextureQuality::Low, false), TextureFormat::Astc8x8);
+        assert_eq!(select_format(TexturePlatform::Mobile, TextureClass::Ui, TextureQuality::High, true), TextureFormat::Rgba8);
+    }
+
+    #[test]
+    fn texture_compression_bc1_and_bc5() {
+        //6x5 pixels: red on the left half, blue on the right half.
+        let mut rgba = Vec::new();
+        for _ in 0..5 {
+            for x in 0..6 {
+                rgba.extend_from_slice(if x < 3 {&[255, 0, 0, 255]} else {&[0, 0, 255, 255]});
+            }
+        }
+
+        let bc1 = compress(TextureFormat::Bc1, rgba.as_slice(), 6, 5).unwrap();
+        assert_eq!(bc1.len(), TextureFormat::Bc1.data_size(6, 5));
+        assert_eq!(bc1.len(), 4 * 8);
+        //First block: 3 red columns and 1 blue column.
+        let color0 = bc1[0] as u16 | (bc1[1] as u16) << 8;
+        let color1 = bc1[2] as u16 | (bc1[3] as u16) << 8;
+        assert_eq!(color0, 0xf800);
+        assert_eq!(color1, 0x001f);
+        let indices = bc1[4] as u32 | (bc1[5] as u32) << 8 | (bc1[6] as u32) << 16 | (bc1[7] as u32) << 24;
+        assert_eq!(indices & 0xff, 0b01_00_00_00);
+
+        let bc5 = compress(TextureFormat::Bc5, rgba.as_slice(), 6, 5).unwrap();
+        assert_eq!(bc5.len(), 4 * 16);
+        assert_eq!((bc5[0], bc5[1]), (255, 0));
+        assert!(compress(TextureFormat::Astc4x4, rgba.as_slice(), 6, 5).is_none());
+    }
+}
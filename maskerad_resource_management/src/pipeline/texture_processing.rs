@@ -0,0 +1,264 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ TEXTURE PROCESSING.
+
+ Done by the texture importer before the compression:
+ - Size clamping: textures bigger than the max size of the quality tier are downsampled.
+ - Mip generation, with a box or a triangle filter.
+ - sRGB textures (albedo, UI) are filtered in linear space, averaging sRGB values darkens the mips.
+ - The normals of the mips of a normal map are renormalized, the filtering shortens them.
+
+ Import settings (meta file):
+ - "mips": "true" (default) or "false".
+ - "mip_filter": "box" or "triangle" (default).
+ - "srgb": "true" or "false". By default, only normal maps are linear.
+ - "max_size": the max width and height of the texture, for every quality tier.
+ - "max_size_low", "max_size_medium", "max_size_high": the max size for one quality tier.
+*/
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use pipeline::pipeline_errors::{PipelineError, PipelineResult};
+use pipeline::texture_compression::{TextureClass, TextureQuality};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MipFilter {
+    Box,
+    Triangle,
+}
+
+impl FromStr for MipFilter {
+    type Err = String;
+
+    fn from_str(filter: &str) -> Result<Self, Self::Err> {
+        match filter {
+            "box" => Ok(MipFilter::Box),
+            "triangle" => Ok(MipFilter::Triangle),
+            _ => Err(format!("Unknown mip filter {}.", filter)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureProcessing {
+    pub generate_mips: bool,
+    pub filter: MipFilter,
+    pub srgb: bool,
+    pub normal_map: bool,
+    pub max_size: Option<usize>,
+}
+
+fn parse_setting<T: FromStr>(settings: &BTreeMap<String, String>, name: &str, default: T) -> PipelineResult<T> {
+    match settings.get(name) {
+        Some(value) => value.parse().map_err(|_| {
+            PipelineError::ImportError(format!("Invalid value {} for the texture setting {}.", value, name))
+        }),
+        None => Ok(default),
+    }
+}
+
+impl TextureProcessing {
+    pub fn from_settings(settings: &BTreeMap<String, String>, class: TextureClass, quality: TextureQuality) -> PipelineResult<Self> {
+        let tier = match quality {
+            TextureQuality::Low => "max_size_low",
+            TextureQuality::Medium => "max_size_medium",
+            TextureQuality::High => "max_size_high",
+        };
+        let max_size = match settings.get(tier).or_else(|| settings.get("max_size")) {
+            Some(size) => Some(size.parse::<usize>().ok().filter(|size| *size > 0).ok_or_else(|| {
+                PipelineError::ImportError(format!("Invalid max size {}.", size))
+            })?),
+            None => None,
+        };
+
+        Ok(TextureProcessing {
+            generate_mips: parse_setting(settings, "mips", true)?,
+            filter: parse_setting(settings, "mip_filter", MipFilter::Triangle)?,
+            srgb: parse_setting(settings, "srgb", class != TextureClass::Normal)?,
+            normal_map: class == TextureClass::Normal,
+            max_size,
+        })
+    }
+}
+
+//A level of the texture, RGBA8 pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureLevel {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value.max(0.0).min(1.0) * 255.0).round() as u8
+}
+
+impl TextureLevel {
+    fn to_float(&self, processing: &TextureProcessing) -> Vec<[f32; 4]> {
+        self.rgba.chunks(4).map(|pixel| {
+            let mut color = [0.0; 4];
+            for c in 0..4 {
+                color[c] = if processing.srgb && c < 3 {srgb_to_linear(pixel[c])} else {pixel[c] as f32 / 255.0};
+            }
+            color
+        }).collect()
+    }
+
+    fn from_float(width: usize, height: usize, pixels: &[[f32; 4]], processing: &TextureProcessing) -> Self {
+        let mut rgba = Vec::with_capacity(pixels.len() * 4);
+        for pixel in pixels {
+            let mut pixel = *pixel;
+            if processing.normal_map {
+                let normal = [pixel[0] * 2.0 - 1.0, pixel[1] * 2.0 - 1.0, pixel[2] * 2.0 - 1.0];
+                let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+                if length > 0.0 {
+                    for c in 0..3 {
+                        pixel[c] = normal[c] / length * 0.5 + 0.5;
+                    }
+                }
+            }
+            for c in 0..4 {
+                rgba.push(if processing.srgb && c < 3 {
+                    linear_to_srgb(pixel[c])
+                } else {
+                    (pixel[c].max(0.0).min(1.0) * 255.0).round() as u8
+                });
+            }
+        }
+        TextureLevel {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    //Half the size, at least 1x1.
+    pub fn downsample(&self, processing: &TextureProcessing) -> TextureLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let source = self.to_float(processing);
+        let (taps, weights): (&[isize], &[f32]) = match processing.filter {
+            MipFilter::Box => (&[0, 1], &[0.5, 0.5]),
+            MipFilter::Triangle => (&[-1, 0, 1, 2], &[0.125, 0.375, 0.375, 0.125]),
+        };
+
+        let clamp = |value: isize, max: usize| value.max(0).min(max as isize - 1) as usize;
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let mut color = [0.0f32; 4];
+                for (ty, wy) in taps.iter().zip(weights.iter()) {
+                    let sy = clamp((y * 2) as isize + ty, self.height);
+                    for (tx, wx) in taps.iter().zip(weights.iter()) {
+                        let sx = clamp((x * 2) as isize + tx, self.width);
+                        let sample = source[sy * self.width + sx];
+                        for c in 0..4 {
+                            color[c] += sample[c] * wx * wy;
+                        }
+                    }
+                }
+                pixels.push(color);
+            }
+        }
+        TextureLevel::from_float(width, height, pixels.as_slice(), processing)
+    }
+}
+
+//Clamp the size of the texture, then build the mip chain down to 1x1.
+pub fn process_texture(level: TextureLevel, processing: &TextureProcessing) -> Vec<TextureLevel> {
+    let mut level = level;
+    if let Some(max_size) = processing.max_size {
+        while level.width > max_size || level.height > max_size {
+            trace!("Clamping a {}x{} texture to the max size {}.", level.width, level.height, max_size);
+            level = level.downsample(processing);
+        }
+    }
+
+    let mut levels = vec![level];
+    if processing.generate_mips {
+        while {
+            let last = &levels[levels.len() - 1];
+            last.width > 1 || last.height > 1
+        } {
+            let next = levels[levels.len() - 1].downsample(processing);
+            levels.push(next);
+        }
+    }
+    levels
+}
+
+#[cfg(test)]
+mod texture_processing_test {
+    use super::*;
+
+    fn processing(settings: &[(&str, &str)], class: TextureClass) -> PipelineResult<TextureProcessing> {
+        let settings = settings.iter().map(|&(key, value)| (key.to_string(), value.to_string())).collect();
+        TextureProcessing::from_settings(&settings, class, TextureQuality::Low)
+    }
+
+    #[test]
+    fn texture_processing_mip_chain_and_clamp() {
+        let level = TextureLevel {
+            width: 8,
+            height: 4,
+            rgba: vec![255; 8 * 4 * 4],
+        };
+        let levels = process_texture(level.clone(), &processing(&[], TextureClass::Albedo).unwrap());
+        let sizes: Vec<(usize, usize)> = levels.iter().map(|level| (level.width, level.height)).collect();
+        assert_eq!(sizes, vec![(8, 4), (4, 2), (2, 1), (1, 1)]);
+        assert!(levels[3].rgba.iter().all(|value| *value == 255));
+
+        let levels = process_texture(level, &processing(&[("max_size_low", "2"), ("max_size", "4"), ("mips", "false")], TextureClass::Albedo).unwrap());
+        assert_eq!(levels.len(), 1);
+        assert_eq!((levels[0].width, levels[0].height), (2, 1));
+
+        assert!(processing(&[("mip_filter", "lanczos")], TextureClass::Albedo).is_err());
+        assert!(processing(&[("max_size", "0")], TextureClass::Albedo).is_err());
+    }
+
+    #[test]
+    fn texture_processing_srgb_and_normals() {
+        //Black and white checker: the average is 50% of the light, which is 188 in sRGB.
+        let checker = TextureLevel {
+            width: 2,
+            height: 2,
+            rgba: vec![0, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255, 0, 0, 0, 255],
+        };
+        let box_filter = [("mip_filter", "box")];
+        let mip = checker.downsample(&processing(&box_filter, TextureClass::Albedo).unwrap());
+        assert_eq!(&mip.rgba[..], &[188, 188, 188, 255]);
+        let mip = checker.downsample(&processing(&[("mip_filter", "box"), ("srgb", "false")], TextureClass::Albedo).unwrap());
+        assert_eq!(&mip.rgba[..], &[128, 128, 128, 255]);
+
+        //Two opposite normals along x, pointing up a little: the mip points up.
+        let normals = TextureLevel {
+            width: 2,
+            height: 1,
+            rgba: vec![255, 128, 160, 255, 0, 128, 160, 255],
+        };
+        let normal = processing(&box_filter, TextureClass::Normal).unwrap();
+        assert!(!normal.srgb);
+        let mip = normals.downsample(&normal);
+        assert_eq!(mip.rgba[2], 255);
+    }
+}
@@ -6,17 +6,16 @@
 // copied, modified, or distributed except according to those terms.
 
 
-use std::collections::HashMap;
-use std::path::{PathBuf, Path};
+use maskerad_core::name::{NameMap, PathId};
 use resources::image_resource::ImageResource;
 
 #[derive(Debug)]
-pub struct ImageRegistry<'a>(HashMap<PathBuf, &'a ImageResource>);
+pub struct ImageRegistry<'a>(NameMap<PathId, &'a ImageResource>);
 
 impl<'a> Default for ImageRegistry<'a> {
     fn default() -> Self {
         debug!("Creating a default ImageRegistry.");
-        ImageRegistry(HashMap::default())
+        ImageRegistry(NameMap::default())
     }
 }
 
@@ -30,18 +29,18 @@ impl<'a> ImageRegistry<'a> {
         self.0.is_empty()
     }
 
-    pub fn get<I: AsRef<Path>>(&self, path: I) -> Option<&&ImageResource> {
+    pub fn get<I: Into<PathId>>(&self, path: I) -> Option<&&ImageResource> {
         debug!("Trying to get a reference to an image in the ImageRegistry.");
-        self.0.get(path.as_ref())
+        self.0.get(&path.into())
     }
 
-    pub fn remove<I: AsRef<Path>>(&mut self, path: I) -> Option<&ImageResource> {
+    pub fn remove<I: Into<PathId>>(&mut self, path: I) -> Option<&ImageResource> {
         debug!("Removing an image in the ImageRegistry.");
-        self.0.remove(path.as_ref())
+        self.0.remove(&path.into())
     }
 
     pub fn insert<I>(&mut self, path: I, image: &'a ImageResource) -> Option<&ImageResource> where
-        I: Into<PathBuf>,
+        I: Into<PathId>,
     {
         debug!("Inserting an image in the ImageRegistry.");
         self.0.insert(path.into(),image)
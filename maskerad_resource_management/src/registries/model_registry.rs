@@ -5,17 +5,16 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use std::collections::HashMap;
-use std::path::{PathBuf, Path};
+use maskerad_core::name::{NameMap, PathId};
 use resources::model_resource::ModelResource;
 
 #[derive(Debug)]
-pub struct ModelRegistry<'a>(HashMap<PathBuf, &'a ModelResource>);
+pub struct ModelRegistry<'a>(NameMap<PathId, &'a ModelResource>);
 
 impl<'a> Default for ModelRegistry<'a> {
     fn default() -> Self {
         debug!("Creating a default ModelRegistry.");
-        ModelRegistry(HashMap::default())
+        ModelRegistry(NameMap::default())
     }
 }
 
@@ -29,18 +28,20 @@ impl<'a> ModelRegistry<'a> {
         self.0.is_empty()
     }
 
-    pub fn get<I: AsRef<Path>>(&self, path: I) -> Option<&&ModelResource> {
-        debug!("Trying to get a model resource with path {}.", path.as_ref().display());
-        self.0.get(path.as_ref())
+    pub fn get<I: Into<PathId>>(&self, path: I) -> Option<&&ModelResource> {
+        let path = path.into();
+        debug!("Trying to get a model resource with path {}.", path);
+        self.0.get(&path)
     }
 
-    pub fn remove<I: AsRef<Path>>(&mut self, path: I) -> Option<&ModelResource> {
-        debug!("Removing a model resource with path {}.", path.as_ref().display());
-        self.0.remove(path.as_ref())
+    pub fn remove<I: Into<PathId>>(&mut self, path: I) -> Option<&ModelResource> {
+        let path = path.into();
+        debug!("Removing a model resource with path {}.", path);
+        self.0.remove(&path)
     }
 
     pub fn insert<I>(&mut self, path: I, model: &'a ModelResource) -> Option<&ModelResource> where
-        I: Into<PathBuf>,
+        I: Into<PathId>,
     {
         debug!("Inserting a model resource.");
         self.0.insert(path.into(),model)
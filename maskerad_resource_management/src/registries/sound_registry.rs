@@ -7,17 +7,16 @@
 
 //TODO:Custom allocators if possible
 
-use std::collections::HashMap;
-use std::path::{PathBuf, Path};
+use maskerad_core::name::{NameMap, PathId};
 use resources::sound_resource::SoundResource;
 use std::io::{Read, Seek};
 
-pub struct SoundRegistry<'a, R: 'a + Read + Seek>(HashMap<PathBuf, &'a SoundResource<R>>);
+pub struct SoundRegistry<'a, R: 'a + Read + Seek>(NameMap<PathId, &'a SoundResource<R>>);
 
 impl<'a, R: Read + Seek> Default for SoundRegistry<'a, R> {
     fn default() -> Self {
         debug!("Creating a default SoundRegistry.");
-        SoundRegistry(HashMap::default())
+        SoundRegistry(NameMap::default())
     }
 }
 
@@ -31,18 +30,20 @@ impl<'a, R: Read + Seek> SoundRegistry<'a, R> {
         self.0.is_empty()
     }
 
-    pub fn get<I: AsRef<Path>>(&self, path: I) -> Option<&&SoundResource<R>> {
-        debug!("Trying to get a sound resource with path {}", path.as_ref().display());
-        self.0.get(path.as_ref())
+    pub fn get<I: Into<PathId>>(&self, path: I) -> Option<&&SoundResource<R>> {
+        let path = path.into();
+        debug!("Trying to get a sound resource with path {}", path);
+        self.0.get(&path)
     }
 
-    pub fn remove<I: AsRef<Path>>(&mut self, path: I) -> Option<&SoundResource<R>> {
-        debug!("Removing a sound resource with path {}", path.as_ref().display());
-        self.0.remove(path.as_ref())
+    pub fn remove<I: Into<PathId>>(&mut self, path: I) -> Option<&SoundResource<R>> {
+        let path = path.into();
+        debug!("Removing a sound resource with path {}", path);
+        self.0.remove(&path)
     }
 
     pub fn insert<I>(&mut self, path: I, sound: &'a SoundResource<R>) -> Option<&SoundResource<R>> where
-        I: Into<PathBuf>,
+        I: Into<PathId>,
     {
         debug!("Inserting a sound resource into the SoundRegistry.");
         self.0.insert(path.into(),sound)
@@ -67,6 +67,19 @@ impl ImageResource {
         Ok(ImageResource(img))
     }
 
+    pub fn width(&self) -> usize {
+        self.0.w
+    }
+
+    pub fn height(&self) -> usize {
+        self.0.h
+    }
+
+    //The pixels, in the color format requested when the image was read.
+    pub fn data(&self) -> &[u8] {
+        self.0.buf.as_slice()
+    }
+
     pub fn infos_from_path<P>(path: P) -> ResourceResult<Info> where
         P: AsRef<Path>
     {
@@ -28,6 +28,19 @@ impl<T: Read + Seek> SoundResource<T> {
         Ok(SoundResource(sound_stream))
     }
 
+    pub fn sample_rate(&self) -> u32 {
+        self.0.ident_hdr.audio_sample_rate
+    }
+
+    pub fn channels(&self) -> u8 {
+        self.0.ident_hdr.audio_channels
+    }
+
+    //The vorbis comments (key, value), where the loop points and the markers are stored.
+    pub fn comments(&self) -> &[(String, String)] {
+        self.0.comment_hdr.comment_list.as_slice()
+    }
+
     pub fn decompress_packet(&mut self) -> ResourceResult<Option<Vec<Vec<i16>>>> {
         self.0.read_dec_packet().map_err(|vorbis_error| {
             ResourceError::from(vorbis_error)
@@ -0,0 +1,288 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//The binary form of the scenes, shipped with the game. All the numbers are little endian,
+//the strings are prefixed by their length (u32).
+//
+//magic "KSCN", version (u32)
+//entities: count (u32), then for each: id (u64), name, parent flag (u8) + parent (u64),
+//          components: count (u32), then for each: type name, properties: count (u32), then name, tag (u8), value.
+//includes: count (u32), then for each: path, parent flag (u8) + parent (u64).
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use scenes::scene_description::{SceneDescription, EntityDescription, ComponentDescription, PropertyValue, SceneInclude};
+use pipeline::asset_guid::AssetGuid;
+use scenes::scene_errors::{SceneError, SceneResult};
+
+const MAGIC: &'static [u8; 4] = b"KSCN";
+pub const SCENE_BINARY_VERSION: u32 = 1;
+
+struct BinaryWriter<W: Write>(W);
+
+impl<W: Write> BinaryWriter<W> {
+    fn u8(&mut self, value: u8) -> SceneResult<()> {
+        self.0.write_all(&[value])?;
+        Ok(())
+    }
+
+    fn u32(&mut self, value: u32) -> SceneResult<()> {
+        let bytes = [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8];
+        self.0.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn u64(&mut self, value: u64) -> SceneResult<()> {
+        self.u32(value as u32)?;
+        self.u32((value >> 32) as u32)
+    }
+
+    fn f32(&mut self, value: f32) -> SceneResult<()> {
+        self.u32(value.to_bits())
+    }
+
+    fn string(&mut self, value: &str) -> SceneResult<()> {
+        self.u32(value.len() as u32)?;
+        self.0.write_all(value.as_bytes())?;
+        Ok(())
+    }
+
+    fn optional_id(&mut self, value: Option<u64>) -> SceneResult<()> {
+        match value {
+            Some(id) => {
+                self.u8(1)?;
+                self.u64(id)
+            },
+            None => {
+                self.u8(0)?;
+                self.u64(0)
+            },
+        }
+    }
+
+    fn property(&mut self, value: &PropertyValue) -> SceneResult<()> {
+        match value {
+            &PropertyValue::Bool(value) => {
+                self.u8(0)?;
+                self.u8(value as u8)
+            },
+            &PropertyValue::Int(value) => {
+                self.u8(1)?;
+                self.u64(value as u64)
+            },
+            &PropertyValue::Float(value) => {
+                self.u8(2)?;
+                self.u64(value.to_bits())
+            },
+            &PropertyValue::Text(ref value) => {
+                self.u8(3)?;
+                self.string(value.as_str())
+            },
+            &PropertyValue::Vector3(value) => {
+                self.u8(4)?;
+                for coordinate in value.iter() {
+                    self.f32(*coordinate)?;
+                }
+                Ok(())
+            },
+            &PropertyValue::Asset(ref path) => {
+                self.u8(5)?;
+                self.string(path.as_str())
+            },
+            &PropertyValue::AssetGuid(ref guid) => {
+                self.u8(6)?;
+                self.string(guid.as_str())
+            },
+        }
+    }
+}
+
+struct BinaryReader<R: Read>(R);
+
+impl<R: Read> BinaryReader<R> {
+    fn u8(&mut self) -> SceneResult<u8> {
+        let mut bytes = [0u8; 1];
+        self.0.read_exact(&mut bytes)?;
+        Ok(bytes[0])
+    }
+
+    fn u32(&mut self) -> SceneResult<u32> {
+        let mut bytes = [0u8; 4];
+        self.0.read_exact(&mut bytes)?;
+        Ok(bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24)
+    }
+
+    fn u64(&mut self) -> SceneResult<u64> {
+        let low = self.u32()? as u64;
+        let high = self.u32()? as u64;
+        Ok(low | high << 32)
+    }
+
+    fn f32(&mut self) -> SceneResult<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn string(&mut self) -> SceneResult<String> {
+        let len = self.u32()? as usize;
+        let mut bytes = Vec::new();
+        (&mut self.0).take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(SceneError::BinaryFormatError(String::from("The scene ends in the middle of a string.")));
+        }
+        String::from_utf8(bytes).map_err(|_| {
+            SceneError::BinaryFormatError(String::from("A string of the scene is not valid UTF-8."))
+        })
+    }
+
+    fn optional_id(&mut self) -> SceneResult<Option<u64>> {
+        let present = self.u8()?;
+        let id = self.u64()?;
+        Ok(if present != 0 {Some(id)} else {None})
+    }
+
+    fn property(&mut self) -> SceneResult<PropertyValue> {
+        match self.u8()? {
+            0 => Ok(PropertyValue::Bool(self.u8()? != 0)),
+            1 => Ok(PropertyValue::Int(self.u64()? as i64)),
+            2 => Ok(PropertyValue::Float(f64::from_bits(self.u64()?))),
+            3 => Ok(PropertyValue::Text(self.string()?)),
+            4 => Ok(PropertyValue::Vector3([self.f32()?, self.f32()?, self.f32()?])),
+            5 => Ok(PropertyValue::Asset(self.string()?)),
+            6 => {
+                let guid = self.string()?;
+                AssetGuid::parse(guid.as_str()).map(PropertyValue::AssetGuid).ok_or_else(|| {
+                    SceneError::BinaryFormatError(format!("{} is not a valid asset GUID.", guid))
+                })
+            },
+            tag => Err(SceneError::BinaryFormatError(format!("Unknown property tag {}.", tag))),
+        }
+    }
+}
+
+pub fn write_binary_scene<W: Write>(scene: &SceneDescription, writer: W) -> SceneResult<()> {
+    debug!("Writing the binary form of a scene with {} entities.", scene.entities.len());
+    let mut writer = BinaryWriter(writer);
+    writer.0.write_all(MAGIC)?;
+    writer.u32(SCENE_BINARY_VERSION)?;
+
+    writer.u32(scene.entities.len() as u32)?;
+    for entity in scene.entities.iter() {
+        writer.u64(entity.id)?;
+        writer.string(entity.name.as_str())?;
+        writer.optional_id(entity.parent)?;
+        writer.u32(entity.components.len() as u32)?;
+        for component in entity.components.iter() {
+            writer.string(component.type_name.as_str())?;
+            writer.u32(component.properties.len() as u32)?;
+            for (name, value) in component.properties.iter() {
+                writer.string(name.as_str())?;
+                writer.property(value)?;
+            }
+        }
+    }
+
+    writer.u32(scene.includes.len() as u32)?;
+    for include in scene.includes.iter() {
+        writer.string(include.path.as_str())?;
+        writer.optional_id(include.parent)?;
+    }
+    Ok(())
+}
+
+pub fn read_binary_scene<R: Read>(reader: R) -> SceneResult<SceneDescription> {
+    debug!("Reading the binary form of a scene.");
+    let mut reader = BinaryReader(reader);
+    let mut magic = [0u8; 4];
+    reader.0.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(SceneError::BinaryFormatError(String::from("The data is not a binary scene.")));
+    }
+    let version = reader.u32()?;
+    if version != SCENE_BINARY_VERSION {
+        return Err(SceneError::BinaryFormatError(format!("The binary scene has the version {}, the version {} was expected.", version, SCENE_BINARY_VERSION)));
+    }
+
+    let mut scene = SceneDescription::new();
+    for _ in 0..reader.u32()? {
+        let id = reader.u64()?;
+        let name = reader.string()?;
+        let parent = reader.optional_id()?;
+        let mut components = Vec::new();
+        for _ in 0..reader.u32()? {
+            let type_name = reader.string()?;
+            let mut properties = BTreeMap::new();
+            for _ in 0..reader.u32()? {
+                let name = reader.string()?;
+                properties.insert(name, reader.property()?);
+            }
+            components.push(ComponentDescription {
+                type_name,
+                properties,
+            });
+        }
+        scene.entities.push(EntityDescription {
+            id,
+            name,
+            parent,
+            components,
+        });
+    }
+
+    for _ in 0..reader.u32()? {
+        let path = reader.string()?;
+        let parent = reader.optional_id()?;
+        scene.includes.push(SceneInclude {
+            path,
+            parent,
+        });
+    }
+    Ok(scene)
+}
+
+//The converter of the asset pipeline: text form to binary form.
+pub fn compile_scene<R: Read, W: Write>(text: R, binary: W) -> SceneResult<()> {
+    let scene = SceneDescription::from_reader(text)?;
+    write_binary_scene(&scene, binary)
+}
+
+//Binary form to text form, to inspect a shipped scene.
+pub fn decompile_scene<R: Read, W: Write>(binary: R, text: W) -> SceneResult<()> {
+    let scene = read_binary_scene(binary)?;
+    scene.save(text)
+}
+
+#[cfg(test)]
+mod scene_binary_test {
+    use super::*;
+
+    #[test]
+    fn scene_binary_round_trip() {
+        let text = r#"{
+            "entities": [
+                {"id": 0, "name": "player", "components": [
+                    {"type_name": "Transform", "properties": {"position": {"Vector3": [1.0, 2.0, 3.0]}}},
+                    {"type_name": "Health", "properties": {"value": {"Int": -5}, "regenerates": {"Bool": true}, "rate": {"Float": 0.5}}},
+                    {"type_name": "Mesh", "properties": {"mesh": {"Asset": "meshes/player.gltf"}, "label": {"Text": "hero"}, "material": {"AssetGuid": "0123456789abcdef0123456789abcdef"}}}
+                ]},
+                {"id": 1, "parent": 0}
+            ],
+            "includes": [{"path": "scenes/house.json"}]
+        }"#;
+
+        let mut binary = Vec::new();
+        compile_scene(text.as_bytes(), &mut binary).unwrap();
+        let scene = read_binary_scene(binary.as_slice()).unwrap();
+        assert_eq!(scene, SceneDescription::from_reader(text.as_bytes()).unwrap());
+
+        let mut decompiled = Vec::new();
+        decompile_scene(binary.as_slice(), &mut decompiled).unwrap();
+        assert_eq!(SceneDescription::from_reader(decompiled.as_slice()).unwrap(), scene);
+
+        assert!(read_binary_scene(&binary[..binary.len() - 3]).is_err());
+        assert!(read_binary_scene(&b"NOPE"[..]).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ SCENES.
+
+ A scene is a list of entities, each one with a list of components. A component is a type name and
+ a map of properties, which can reference other assets.
+ A scene can include other scenes (a house included several times in a village scene).
+
+ Scenes have two forms:
+ - The text form (JSON), which is the source form. It is pretty-printed, with sorted keys, so the diffs
+   stay readable in source control.
+ - The binary form (see scene_binary), compiled by the asset pipeline and shipped with the game.
+*/
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Read, Write};
+use serde_json;
+use pipeline::asset_guid::AssetGuid;
+use scenes::scene_errors::{SceneError, SceneResult};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Vector3([f32; 3]),
+    //The path of another asset (a mesh, a texture...). Replaced by the GUID of the asset
+    //when the scene is fixed up by the asset database.
+    Asset(String),
+    AssetGuid(AssetGuid),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentDescription {
+    pub type_name: String,
+    #[serde(default)]
+    pub properties: BTreeMap<String, PropertyValue>,
+}
+
+impl ComponentDescription {
+    pub fn new<S: Into<String>>(type_name: S) -> Self {
+        ComponentDescription {
+            type_name: type_name.into(),
+            properties: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_property<S: Into<String>>(mut self, name: S, value: PropertyValue) -> Self {
+        self.properties.insert(name.into(), value);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityDescription {
+    //Unique in the scene.
+    pub id: u64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub parent: Option<u64>,
+    #[serde(default)]
+    pub components: Vec<ComponentDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneInclude {
+    pub path: String,
+    //The root entities of the included scene become children of this entity.
+    #[serde(default)]
+    pub parent: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub entities: Vec<EntityDescription>,
+    #[serde(default)]
+    pub includes: Vec<SceneInclude>,
+}
+
+impl SceneDescription {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> SceneResult<Self> {
+        debug!("Deserializing the text form of a scene.");
+        let scene = serde_json::from_reader(reader)?;
+        Ok(scene)
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> SceneResult<()> {
+        debug!("Serializing the text form of a scene with {} entities.", self.entities.len());
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    pub fn entity(&self, id: u64) -> Option<&EntityDescription> {
+        self.entities.iter().find(|entity| entity.id == id)
+    }
+
+    //All the assets referenced by the components of the scene, sorted and without duplicates.
+    pub fn asset_references(&self) -> Vec<&str> {
+        let mut references: Vec<&str> = self.entities.iter()
+            .flat_map(|entity| entity.components.iter())
+            .flat_map(|component| component.properties.values())
+            .filter_map(|value| match value {
+                &PropertyValue::Asset(ref path) => Some(path.as_str()),
+                _ => None,
+            })
+            .collect();
+        references.sort();
+        references.dedup();
+        references
+    }
+
+    //All the GUIDs referenced by the components of the scene, sorted and without duplicates.
+    pub fn asset_guids(&self) -> Vec<&AssetGuid> {
+        let mut guids: Vec<&AssetGuid> = self.entities.iter()
+            .flat_map(|entity| entity.components.iter())
+            .flat_map(|component| component.properties.values())
+            .filter_map(|value| match value {
+                &PropertyValue::AssetGuid(ref guid) => Some(guid),
+                _ => None,
+            })
+            .collect();
+        guids.sort();
+        guids.dedup();
+        guids
+    }
+
+    //Replace the includes by the entities of the included scenes, recursively.
+    //The included entities get new ids, after the ids of the scene.
+    pub fn flatten<F>(&self, mut load: F) -> SceneResult<SceneDescription> where
+        F: FnMut(&str) -> SceneResult<SceneDescription>
+    {
+        let mut flattened = SceneDescription {
+            entities: self.entities.clone(),
+            includes: Vec::new(),
+        };
+        let mut visiting = HashSet::new();
+        for include in self.includes.iter() {
+            flattened.append_include(include, &mut load, &mut visiting)?;
+        }
+        Ok(flattened)
+    }
+
+    fn append_include<F>(&mut self, include: &SceneInclude, load: &mut F, visiting: &mut HashSet<String>) -> SceneResult<()> where
+        F: FnMut(&str) -> SceneResult<SceneDescription>
+    {
+        if !visiting.insert(include.path.clone()) {
+            return Err(SceneError::IncludeError(format!("The scene {} includes itself.", include.path)));
+        }
+        debug!("Including the scene {}.", include.path);
+
+        let mut included = SceneDescription {
+            entities: Vec::new(),
+            includes: Vec::new(),
+        };
+        let scene = load(include.path.as_str())?;
+        included.entities = scene.entities;
+        for nested in scene.includes.iter() {
+            included.append_include(nested, load, visiting)?;
+        }
+
+        let offset = self.entities.iter().map(|entity| entity.id + 1).max().unwrap_or(0);
+        for mut entity in included.entities {
+            entity.id += offset;
+            entity.parent = match entity.parent {
+                Some(parent) => Some(parent + offset),
+                None => include.parent,
+            };
+            self.entities.push(entity);
+        }
+
+        visiting.remove(&include.path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod scene_description_test {
+    use super::*;
+
+    fn house() -> SceneDescription {
+        SceneDescription {
+            entities: vec![
+                EntityDescription {
+                    id: 0,
+                    name: String::from("house"),
+                    parent: None,
+                    components: vec![ComponentDescription::new("Mesh").with_property("mesh", PropertyValue::Asset(String::from("meshes/house.gltf")))],
+                },
+                EntityDescription {
+                    id: 1,
+                    name: String::from("door"),
+                    parent: Some(0),
+                    components: Vec::new(),
+                },
+            ],
+            includes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn scene_text_round_trip() {
+        let scene = house();
+        let mut text = Vec::new();
+        scene.save(&mut text).unwrap();
+        assert_eq!(SceneDescription::from_reader(text.as_slice()).unwrap(), scene);
+        assert_eq!(scene.asset_references(), vec!["meshes/house.gltf"]);
+    }
+
+    #[test]
+    fn scene_flatten_includes() {
+        let mut village = SceneDescription::new();
+        village.entities.push(EntityDescription {
+            id: 0,
+            name: String::from("village"),
+            parent: None,
+            components: Vec::new(),
+        });
+        village.includes.push(SceneInclude { path: String::from("house.json"), parent: Some(0) });
+        village.includes.push(SceneInclude { path: String::from("house.json"), parent: Some(0) });
+
+        let flattened = village.flatten(|_| Ok(house())).unwrap();
+        assert_eq!(flattened.entities.len(), 5);
+        assert_eq!(flattened.entity(3).unwrap().name, "house");
+        assert_eq!(flattened.entity(3).unwrap().parent, Some(0));
+        assert_eq!(flattened.entity(4).unwrap().parent, Some(3));
+
+        let mut recursive = SceneDescription::new();
+        recursive.includes.push(SceneInclude { path: String::from("self.json"), parent: None });
+        let copy = recursive.clone();
+        assert!(recursive.flatten(|_| Ok(copy.clone())).is_err());
+    }
+}
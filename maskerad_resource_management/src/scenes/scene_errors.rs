@@ -0,0 +1,75 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::error::Error;
+use std::fmt;
+use std::io::Error as IOError;
+use serde_json::Error as JSONError;
+
+#[derive(Debug)]
+pub enum SceneError {
+    TextFormatError(String, JSONError),
+    BinaryFormatError(String),
+    IOError(String, IOError),
+    IncludeError(String),
+}
+
+unsafe impl Send for SceneError {}
+unsafe impl Sync for SceneError {}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SceneError::TextFormatError(ref description, _) => {
+                write!(f, "Scene text format error: {}", description)
+            },
+            &SceneError::BinaryFormatError(ref description) => {
+                write!(f, "Scene binary format error: {}", description)
+            },
+            &SceneError::IOError(ref description, _) => {
+                write!(f, "I/O error: {}", description)
+            },
+            &SceneError::IncludeError(ref description) => {
+                write!(f, "Scene include error: {}", description)
+            },
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn description(&self) -> &str {
+        match self {
+            &SceneError::TextFormatError(_, _) => "TextFormatError",
+            &SceneError::BinaryFormatError(_) => "BinaryFormatError",
+            &SceneError::IOError(_, _) => "IOError",
+            &SceneError::IncludeError(_) => "IncludeError",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match self {
+            &SceneError::TextFormatError(_, ref cause) => Some(cause),
+            &SceneError::BinaryFormatError(_) => None,
+            &SceneError::IOError(_, ref cause) => Some(cause),
+            &SceneError::IncludeError(_) => None,
+        }
+    }
+}
+
+pub type SceneResult<T> = Result<T, SceneError>;
+
+impl From<JSONError> for SceneError {
+    fn from(error: JSONError) -> Self {
+        SceneError::TextFormatError(String::from("Error while (de)serializing the text form of a scene."), error)
+    }
+}
+
+impl From<IOError> for SceneError {
+    fn from(error: IOError) -> Self {
+        SceneError::IOError(String::from("Error while reading or writing a scene."), error)
+    }
+}
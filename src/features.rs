@@ -0,0 +1,156 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ ENGINE FEATURES.
+
+ Some subsystems are optional (cargo features) and the subsystems evolve with their own version. The game
+ code and the mods ask engine_features() what this build of the engine contains, and adapt (no voice chat,
+ another script language...) instead of failing when they link or run.
+
+ The features are known by name:
+ - "renderer", "audio", "gameplay", "networking": the subsystems, always compiled in.
+ - "voice": the voice chat of the audio subsystem.
+ - "scripting-wasm", "scripting-lua": the script backends.
+ - "vulkan": the Vulkan renderer backend.
+ - "web": the WebAssembly/browser platform.
+ A feature which is not compiled in is still listed, disabled, so a typo is not mistaken for a missing feature.
+*/
+
+use std::fmt;
+use audio;
+use gameplay;
+use gameplay::scripting::script_backend::ScriptBackendKind;
+use network;
+use renderer;
+
+pub const ENGINE_VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feature {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub enabled: bool,
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.enabled {
+            write!(f, "{} {}", self.name, self.version)
+        } else {
+            write!(f, "{} (disabled)", self.name)
+        }
+    }
+}
+
+//"1.2.3" -> (1, 2, 3), the missing or invalid numbers are 0, a pre-release suffix is ignored.
+fn parse_version(version: &str) -> (u32, u32, u32) {
+    let mut numbers = version.split(&['-', '+'][..]).next().unwrap_or("").split('.')
+        .map(|number| number.parse().unwrap_or(0));
+    (numbers.next().unwrap_or(0), numbers.next().unwrap_or(0), numbers.next().unwrap_or(0))
+}
+
+#[derive(Debug, Clone)]
+pub struct EngineFeatures {
+    version: &'static str,
+    features: Vec<Feature>,
+}
+
+impl EngineFeatures {
+    pub fn version(&self) -> &'static str {
+        self.version
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Feature> {
+        self.features.iter().find(|feature| feature.name == name)
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.get(name).map(|feature| feature.enabled).unwrap_or(false)
+    }
+
+    //The version of an enabled feature.
+    pub fn feature_version(&self, name: &str) -> Option<&'static str> {
+        self.get(name).and_then(|feature| if feature.enabled {Some(feature.version)} else {None})
+    }
+
+    //Whether the feature is enabled, with a version compatible with min_version: same major version, not older.
+    pub fn supports(&self, name: &str, min_version: &str) -> bool {
+        match self.feature_version(name) {
+            Some(version) => {
+                let (version, required) = (parse_version(version), parse_version(min_version));
+                version.0 == required.0 && version >= required
+            },
+            None => false,
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> ::std::slice::Iter<'a, Feature> {
+        self.features.iter()
+    }
+
+    //For the logs and the crash reports.
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("Maskerad engine {}", self.version)];
+        lines.extend(self.features.iter().map(|feature| format!("  {}", feature)));
+        lines
+    }
+}
+
+pub fn engine_features() -> EngineFeatures {
+    let features = vec![
+        Feature { name: "renderer", version: renderer::VERSION, enabled: true },
+        Feature { name: "audio", version: audio::VERSION, enabled: true },
+        Feature { name: "gameplay", version: gameplay::VERSION, enabled: true },
+        Feature { name: "networking", version: network::VERSION, enabled: true },
+        Feature { name: "voice", version: audio::VERSION, enabled: audio::VOICE_SUPPORT },
+        Feature { name: "scripting-wasm", version: gameplay::VERSION, enabled: ScriptBackendKind::Wasm.is_available() },
+        Feature { name: "scripting-lua", version: gameplay::VERSION, enabled: ScriptBackendKind::Lua.is_available() },
+        //The renderer has no Vulkan backend yet.
+        Feature { name: "vulkan", version: renderer::VERSION, enabled: false },
+        Feature { name: "web", version: ENGINE_VERSION, enabled: cfg!(feature = "wasm") },
+    ];
+    EngineFeatures {
+        version: ENGINE_VERSION,
+        features,
+    }
+}
+
+#[cfg(test)]
+mod features_test {
+    use super::*;
+
+    #[test]
+    fn query_the_engine_features() {
+        let features = engine_features();
+        assert_eq!(features.version(), ENGINE_VERSION);
+        assert!(features.has("renderer"));
+        assert!(features.has("networking"));
+        assert_eq!(features.has("voice"), audio::VOICE_SUPPORT);
+        assert!(!features.has("vulkan"));
+        assert!(features.get("vulkan").is_some());
+        assert!(features.get("vulcan").is_none());
+        assert_eq!(features.feature_version("vulkan"), None);
+
+        assert!(features.supports("audio", "0.1"));
+        assert!(features.supports("audio", "0.0.9"));
+        assert!(!features.supports("audio", "0.2.0"));
+        assert!(!features.supports("audio", "1.0.0"));
+        assert!(!features.supports("scripting-lua", "0.1.0"));
+
+        let lines = features.to_lines();
+        assert_eq!(lines.len(), 10);
+        assert!(lines.contains(&String::from("  vulkan (disabled)")));
+    }
+
+    #[test]
+    fn parse_versions() {
+        assert_eq!(parse_version("1.2.3"), (1, 2, 3));
+        assert_eq!(parse_version("0.4"), (0, 4, 0));
+        assert_eq!(parse_version("2.0.0-beta.1"), (2, 0, 0));
+    }
+}
@@ -0,0 +1,412 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ C FFI.
+
+ A stable C ABI to embed the engine in the tools and the bindings of other languages (C# or Python editors...).
+ - The engine is an opaque MkEngine pointer, created by mk_engine_create and freed by mk_engine_destroy.
+ - The entities are u64 (Entity::to_bits), MK_INVALID_ENTITY when there is none. The foreign code can't
+   use the Rust components, it attaches blobs of bytes to the entities, each one under a key of its choice.
+ - The assets are read from the working directory of the game, into buffers freed by mk_buffer_free.
+ - The main loop is driven by the host: mk_engine_frame runs the fixed_update callback for each fixed tick
+   of the frame, then the update callback with the interpolation alpha. The callbacks may call the other
+   functions with the engine pointer they receive.
+ - The functions returning an i32 return MK_OK, or a negative status and the message of mk_last_error.
+   A panic never crosses the boundary, it is reported as MK_ERROR.
+
+ The pointers must be null or valid: the strings are nul-terminated UTF-8, and a pointer returned by the engine
+ is only valid until the next call modifying the same object. mk_abi_version changes when a signature changes.
+*/
+
+//The safety rules of the unsafe functions are the ones above.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::time::Duration;
+use std::io::Read;
+use core::filesystem::filesystem::Filesystem;
+use core::filesystem::game_directories::RootDir;
+use core::filesystem::memory_filesystem::MemoryFilesystem;
+use core::filesystem::vfilesystem::VFilesystem;
+use core::filesystem::vpath::VPathBuf;
+use gameplay::ecs::entity::Entity;
+use gameplay::ecs::world::World;
+use gameplay::interpolation::FixedTimestep;
+
+pub const MK_ABI_VERSION: u32 = 1;
+pub const MK_INVALID_ENTITY: u64 = u64::MAX;
+
+pub const MK_OK: i32 = 0;
+pub const MK_ERROR: i32 = -1;
+pub const MK_INVALID_ARGUMENT: i32 = -2;
+pub const MK_NOT_FOUND: i32 = -3;
+
+static ENGINE_VERSION: &'static str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(message: String) {
+    warn!("FFI error: {}", message);
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+//Runs the function, and turns a panic into the default value.
+fn guard<T, F: FnOnce() -> T>(default: T, function: F) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(function)) {
+        Ok(value) => value,
+        Err(_) => {
+            set_last_error(String::from("The engine panicked."));
+            default
+        },
+    }
+}
+
+fn status(result: Result<(), (i32, String)>) -> i32 {
+    match result {
+        Ok(()) => MK_OK,
+        Err((status, message)) => {
+            set_last_error(message);
+            status
+        },
+    }
+}
+
+unsafe fn to_str<'a>(string: *const c_char, argument: &str) -> Result<&'a str, (i32, String)> {
+    if string.is_null() {
+        return Err((MK_INVALID_ARGUMENT, format!("{} is null.", argument)));
+    }
+    CStr::from_ptr(string).to_str().map_err(|_| (MK_INVALID_ARGUMENT, format!("{} is not UTF-8.", argument)))
+}
+
+//The bytes attached to an entity by the foreign code.
+#[derive(Debug, Default)]
+struct ForeignData(HashMap<u32, Vec<u8>>);
+
+pub type MkFixedUpdate = extern "C" fn(user_data: *mut c_void, engine: *mut MkEngine, step_seconds: f64);
+pub type MkUpdate = extern "C" fn(user_data: *mut c_void, engine: *mut MkEngine, frame_seconds: f64, alpha: f32);
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct MkCallbacks {
+    pub user_data: *mut c_void,
+    pub fixed_update: Option<MkFixedUpdate>,
+    pub update: Option<MkUpdate>,
+}
+
+impl Default for MkCallbacks {
+    fn default() -> Self {
+        MkCallbacks {
+            user_data: ptr::null_mut(),
+            fixed_update: None,
+            update: None,
+        }
+    }
+}
+
+pub struct MkEngine {
+    world: World,
+    filesystem: Box<VFilesystem>,
+    timestep: FixedTimestep,
+    callbacks: MkCallbacks,
+}
+
+#[no_mangle]
+pub extern "C" fn mk_abi_version() -> u32 {
+    MK_ABI_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn mk_engine_version() -> *const c_char {
+    ENGINE_VERSION.as_ptr() as *const c_char
+}
+
+//The message of the last error of this thread, null if there is none.
+#[no_mangle]
+pub extern "C" fn mk_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| last_error.borrow().as_ref().map(|message| message.as_ptr()).unwrap_or(ptr::null()))
+}
+
+//The directories of the game come from its name and its author. Without a name, the engine has an empty
+//filesystem in memory (the tests, the tools feeding the assets themselves). Returns null on failure.
+#[no_mangle]
+pub unsafe extern "C" fn mk_engine_create(game_name: *const c_char, game_author: *const c_char, tick_rate: u32) -> *mut MkEngine {
+    guard(ptr::null_mut(), || {
+        let filesystem: Box<VFilesystem> = if game_name.is_null() {
+            Box::new(MemoryFilesystem::new())
+        } else {
+            let directories = to_str(game_name, "game_name").and_then(|name| {
+                let author = if game_author.is_null() {name} else {to_str(game_author, "game_author")?};
+                Filesystem::new(name, author).map_err(|e| (MK_ERROR, e.to_string()))
+            });
+            match directories {
+                Ok(filesystem) => Box::new(filesystem),
+                Err((_, message)) => {
+                    set_last_error(message);
+                    return ptr::null_mut();
+                },
+            }
+        };
+        let step = Duration::from_nanos(1_000_000_000 / u64::from(tick_rate.max(1)));
+        debug!("Creating an engine through the FFI, {} ticks per second.", tick_rate.max(1));
+        Box::into_raw(Box::new(MkEngine {
+            world: World::new(),
+            filesystem,
+            timestep: FixedTimestep::new(step),
+            callbacks: MkCallbacks::default(),
+        }))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mk_engine_destroy(engine: *mut MkEngine) {
+    if !engine.is_null() {
+        guard((), || drop(Box::from_raw(engine)));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mk_engine_set_callbacks(engine: *mut MkEngine, callbacks: MkCallbacks) -> i32 {
+    match engine.as_mut() {
+        Some(engine) => {
+            engine.callbacks = callbacks;
+            MK_OK
+        },
+        None => status(Err((MK_INVALID_ARGUMENT, String::from("engine is null.")))),
+    }
+}
+
+//Runs one frame of the main loop. Returns the number of fixed ticks, or a negative status.
+#[no_mangle]
+pub unsafe extern "C" fn mk_engine_frame(engine: *mut MkEngine, frame_seconds: f64) -> i32 {
+    if engine.is_null() || frame_seconds.is_nan() || frame_seconds < 0.0 {
+        return status(Err((MK_INVALID_ARGUMENT, String::from("engine is null or frame_seconds is invalid."))));
+    }
+    guard(MK_ERROR, || {
+        //No borrow of the engine is alive while a callback runs, the callbacks use the engine too.
+        let (ticks, step, callbacks) = {
+            let engine = &mut *engine;
+            let ticks = engine.timestep.advance(Duration::from_secs_f64(frame_seconds));
+            (ticks, engine.timestep.step().as_secs_f64(), engine.callbacks)
+        };
+        if let Some(fixed_update) = callbacks.fixed_update {
+            for _ in 0..ticks {
+                fixed_update(callbacks.user_data, engine, step);
+            }
+        }
+        if let Some(update) = callbacks.update {
+            let alpha = (*engine).timestep.alpha();
+            update(callbacks.user_data, engine, frame_seconds, alpha);
+        }
+        ticks as i32
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mk_entity_spawn(engine: *mut MkEngine) -> u64 {
+    match engine.as_mut() {
+        Some(engine) => guard(MK_INVALID_ENTITY, || engine.world.spawn().to_bits()),
+        None => MK_INVALID_ENTITY,
+    }
+}
+
+//Despawns the children too.
+#[no_mangle]
+pub unsafe extern "C" fn mk_entity_despawn(engine: *mut MkEngine, entity: u64) -> bool {
+    match engine.as_mut() {
+        Some(engine) => guard(false, || engine.world.despawn(Entity::from_bits(entity))),
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mk_entity_is_alive(engine: *const MkEngine, entity: u64) -> bool {
+    match engine.as_ref() {
+        Some(engine) => entity != MK_INVALID_ENTITY && engine.world.is_alive(Entity::from_bits(entity)),
+        None => false,
+    }
+}
+
+//MK_INVALID_ENTITY as parent detaches the entity.
+#[no_mangle]
+pub unsafe extern "C" fn mk_entity_set_parent(engine: *mut MkEngine, entity: u64, parent: u64) -> bool {
+    match engine.as_mut() {
+        Some(engine) => {
+            let parent = if parent == MK_INVALID_ENTITY {None} else {Some(Entity::from_bits(parent))};
+            guard(false, || engine.world.set_parent(Entity::from_bits(entity), parent))
+        },
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mk_entity_set_name(engine: *mut MkEngine, entity: u64, name: *const c_char) -> i32 {
+    status(engine.as_mut().ok_or((MK_INVALID_ARGUMENT, String::from("engine is null."))).and_then(|engine| {
+        let name = to_str(name, "name")?;
+        let entity = Entity::from_bits(entity);
+        if !engine.world.is_alive(entity) {
+            return Err((MK_NOT_FOUND, format!("The entity {} is not alive.", entity)));
+        }
+        engine.world.set_name(entity, name);
+        Ok(())
+    }))
+}
+
+//A name, a path of names ("level/door") or "#index".
+#[no_mangle]
+pub unsafe extern "C" fn mk_entity_find(engine: *const MkEngine, reference: *const c_char) -> u64 {
+    match (engine.as_ref(), to_str(reference, "reference")) {
+        (Some(engine), Ok(reference)) => engine.world.resolve(reference).map(|entity| entity.to_bits()).unwrap_or(MK_INVALID_ENTITY),
+        _ => MK_INVALID_ENTITY,
+    }
+}
+
+//Copies the bytes under the key, replacing the previous ones.
+#[no_mangle]
+pub unsafe extern "C" fn mk_entity_set_data(engine: *mut MkEngine, entity: u64, key: u32, data: *const u8, len: usize) -> i32 {
+    status(engine.as_mut().ok_or((MK_INVALID_ARGUMENT, String::from("engine is null."))).and_then(|engine| {
+        if data.is_null() && len > 0 {
+            return Err((MK_INVALID_ARGUMENT, String::from("data is null.")));
+        }
+        let entity = Entity::from_bits(entity);
+        if !engine.world.is_alive(entity) {
+            return Err((MK_NOT_FOUND, format!("The entity {} is not alive.", entity)));
+        }
+        let bytes = if len == 0 {Vec::new()} else {slice::from_raw_parts(data, len).to_vec()};
+        if !engine.world.has::<ForeignData>(entity) {
+            engine.world.insert(entity, ForeignData::default());
+        }
+        if let Some(foreign) = engine.world.get_mut::<ForeignData>(entity) {
+            foreign.0.insert(key, bytes);
+        }
+        Ok(())
+    }))
+}
+
+//The bytes under the key, null if there are none. Valid until the data of the entity is modified.
+#[no_mangle]
+pub unsafe extern "C" fn mk_entity_get_data(engine: *const MkEngine, entity: u64, key: u32, len: *mut usize) -> *const u8 {
+    let data = engine.as_ref()
+        .and_then(|engine| engine.world.get::<ForeignData>(Entity::from_bits(entity)))
+        .and_then(|foreign| foreign.0.get(&key));
+    if let Some(len) = len.as_mut() {
+        *len = data.map(|data| data.len()).unwrap_or(0);
+    }
+    data.map(|data| data.as_ptr()).unwrap_or(ptr::null())
+}
+
+//Reads a file of the working directory into a new buffer, to free with mk_buffer_free. Null on failure.
+//len is required: the buffer can't be freed without its length.
+#[no_mangle]
+pub unsafe extern "C" fn mk_asset_load(engine: *const MkEngine, path: *const c_char, len: *mut usize) -> *mut u8 {
+    let result = engine.as_ref().ok_or((MK_INVALID_ARGUMENT, String::from("engine is null."))).and_then(|engine| {
+        if len.is_null() {
+            return Err((MK_INVALID_ARGUMENT, String::from("len is null.")));
+        }
+        let path = VPathBuf::new(RootDir::WorkingDirectory, to_str(path, "path")?).map_err(|e| (MK_INVALID_ARGUMENT, e.to_string()))?;
+        let mut bytes = Vec::new();
+        engine.filesystem.open(path.as_vpath())
+            .map_err(|e| (MK_NOT_FOUND, e.to_string()))?
+            .read_to_end(&mut bytes)
+            .map_err(|e| (MK_ERROR, e.to_string()))?;
+        Ok(bytes)
+    });
+    match result {
+        Ok(bytes) => {
+            let bytes = bytes.into_boxed_slice();
+            *len = bytes.len();
+            Box::into_raw(bytes) as *mut u8
+        },
+        Err(error) => {
+            status(Err(error));
+            ptr::null_mut()
+        },
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mk_buffer_free(buffer: *mut u8, len: usize) {
+    if !buffer.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer, len)));
+    }
+}
+
+#[cfg(test)]
+mod ffi_test {
+    use super::*;
+    use core::filesystem::vpath::VPath;
+
+    extern "C" fn count_ticks(user_data: *mut c_void, engine: *mut MkEngine, _step_seconds: f64) {
+        unsafe {
+            *(user_data as *mut u32) += 1;
+            mk_entity_spawn(engine);
+        }
+    }
+
+    #[test]
+    fn drive_the_engine_through_the_c_abi() {
+        unsafe {
+            assert_eq!(mk_abi_version(), MK_ABI_VERSION);
+            assert_eq!(CStr::from_ptr(mk_engine_version()).to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+            let engine = mk_engine_create(ptr::null(), ptr::null(), 10);
+            assert!(!engine.is_null());
+
+            let door = mk_entity_spawn(engine);
+            let level = mk_entity_spawn(engine);
+            assert!(mk_entity_is_alive(engine, door));
+            assert!(mk_entity_set_parent(engine, door, level));
+            assert_eq!(mk_entity_set_name(engine, level, b"level\0".as_ptr() as *const c_char), MK_OK);
+            assert_eq!(mk_entity_set_name(engine, door, b"door\0".as_ptr() as *const c_char), MK_OK);
+            assert_eq!(mk_entity_find(engine, b"level/door\0".as_ptr() as *const c_char), door);
+            assert_eq!(mk_entity_find(engine, b"window\0".as_ptr() as *const c_char), MK_INVALID_ENTITY);
+
+            assert_eq!(mk_entity_set_data(engine, door, 7, [1u8, 2, 3].as_ptr(), 3), MK_OK);
+            let mut len = 0;
+            let data = mk_entity_get_data(engine, door, 7, &mut len);
+            assert_eq!(slice::from_raw_parts(data, len), &[1, 2, 3]);
+            assert!(mk_entity_get_data(engine, door, 8, &mut len).is_null());
+            assert_eq!(len, 0);
+
+            assert!(mk_entity_despawn(engine, level));
+            assert!(!mk_entity_is_alive(engine, door));
+            assert_eq!(mk_entity_set_name(engine, door, b"ghost\0".as_ptr() as *const c_char), MK_NOT_FOUND);
+            assert!(CStr::from_ptr(mk_last_error()).to_str().unwrap().contains("not alive"));
+
+            (*engine).filesystem.write_all(VPath::new(RootDir::WorkingDirectory, "hello.txt").unwrap(), b"hello").unwrap();
+            let buffer = mk_asset_load(engine, b"hello.txt\0".as_ptr() as *const c_char, &mut len);
+            assert_eq!(slice::from_raw_parts(buffer, len), b"hello");
+            mk_buffer_free(buffer, len);
+            assert!(mk_asset_load(engine, b"missing.txt\0".as_ptr() as *const c_char, &mut len).is_null());
+            assert!(mk_asset_load(engine, b"hello.txt\0".as_ptr() as *const c_char, ptr::null_mut()).is_null());
+            assert!(CStr::from_ptr(mk_last_error()).to_str().unwrap().contains("len is null"));
+
+            let mut ticks = 0u32;
+            let callbacks = MkCallbacks {
+                user_data: &mut ticks as *mut u32 as *mut c_void,
+                fixed_update: Some(count_ticks),
+                update: None,
+            };
+            assert_eq!(mk_engine_set_callbacks(engine, callbacks), MK_OK);
+            assert_eq!(mk_engine_frame(engine, 0.25), 2);
+            assert_eq!(ticks, 2);
+            assert_eq!((*engine).world.len(), 2);
+            assert_eq!(mk_engine_frame(engine, -1.0), MK_INVALID_ARGUMENT);
+
+            mk_engine_destroy(engine);
+            assert_eq!(mk_entity_spawn(ptr::null_mut()), MK_INVALID_ENTITY);
+        }
+    }
+}
@@ -22,4 +22,20 @@ pub extern crate maskerad_debugging as debugging;
 pub extern crate maskerad_resource_management as resource_management;
 pub extern crate maskerad_core as core;
 
+#[macro_use]
+extern crate log;
+
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "wasm")]
+extern crate js_sys;
+#[cfg(feature = "wasm")]
+extern crate web_sys;
+
 pub mod engine;
+pub mod features;
+pub mod ffi;
+
+pub mod server;
+pub mod test_harness;
+pub mod platform;
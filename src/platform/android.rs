@@ -0,0 +1,458 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ ANDROID.
+
+ The glue of the NativeActivity (android_native_app_glue) gives the paths of the app storage, the
+ AAssetManager, the commands of the activity and the input events. This module maps them to the
+ engine:
+
+ - Filesystem: the user config, the logs and the saves are in the internal storage of the app (private,
+   included in the Auto Backup). The external storage, when mounted, is only used for large downloaded
+   content. The assets are in the APK, mounted read-only through the AAssetManager: the AndroidFilesystem
+   serves the AssetRoot from the APK and the other roots from the storage.
+ - Lifecycle: the commands of the activity become LifecycleEvents.
+ - Touch: the motion events become TouchEvents, a pointer id by finger.
+ - Audio: AAudio from the API level 27 (the AAudio of the API level 26 has known bugs), OpenSL ES
+   before. If the AAudio stream cannot be opened, OpenSL ES is tried.
+*/
+
+use std::io::Cursor;
+use std::path::{Component, Path, PathBuf};
+use core::filesystem::filesystem::Filesystem;
+use core::filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use core::filesystem::game_directories::{GameDirectories, RootDir};
+use core::filesystem::open_options::OpenOptions;
+use core::filesystem::vfilesystem::{VFile, VFilesystem, VMetadata};
+use core::filesystem::vpath::{VPath, VPathBuf};
+use inputs::touch::{TouchEvent, TouchPhase};
+use audio::output_device::{AudioBackend, AudioDeviceInfo};
+use platform::LifecycleEvent;
+
+//The storage directories of the app, from the ANativeActivity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndroidPaths {
+    pub internal_data: PathBuf,
+    //None if the external storage is not mounted.
+    pub external_data: Option<PathBuf>,
+    pub cache: PathBuf,
+}
+
+impl AndroidPaths {
+    pub fn game_directories(&self) -> GameDirectories {
+        GameDirectories::from_roots(
+            self.internal_data.clone(),
+            self.internal_data.join("config"),
+            self.internal_data.join("data"),
+        )
+    }
+
+    pub fn filesystem(&self) -> Filesystem {
+        Filesystem::with_directories(self.game_directories())
+    }
+
+    //Where the downloaded content goes, the internal storage if there is no external storage.
+    pub fn download_directory(&self) -> PathBuf {
+        self.external_data.as_ref().unwrap_or(&self.internal_data).join("downloads")
+    }
+}
+
+//The AAssetManager of the activity.
+pub trait ApkAssets {
+    fn read(&self, path: &str) -> Option<Vec<u8>>;
+
+    //The files of a directory of the APK (AAssetDir does not list the sub-directories).
+    fn list(&self, directory: &str) -> Vec<String>;
+}
+
+//The assets of the APK, read-only.
+pub struct ApkMount<A: ApkAssets> {
+    assets: A,
+}
+
+impl<A: ApkAssets> ApkMount<A> {
+    pub fn new(assets: A) -> Self {
+        ApkMount {
+            assets,
+        }
+    }
+
+    //The AAssetManager takes relative paths, without "." or "..".
+    fn asset_path<P: AsRef<Path>>(path: P) -> FileSystemResult<String> {
+        let mut components = Vec::new();
+        for component in path.as_ref().components() {
+            match component {
+                Component::Normal(name) => components.push(name.to_string_lossy().into_owned()),
+                Component::RootDir | Component::CurDir => {},
+                _ => return Err(FileSystemError::PermissionError(format!("The path {} leaves the APK assets !", path.as_ref().display()))),
+            }
+        }
+        Ok(components.join("/"))
+    }
+
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> FileSystemResult<Cursor<Vec<u8>>> {
+        let asset_path = ApkMount::<A>::asset_path(path)?;
+        debug!("Opening the APK asset {}.", asset_path);
+        match self.assets.read(asset_path.as_str()) {
+            Some(bytes) => Ok(Cursor::new(bytes)),
+            None => Err(FileSystemError::GameDirectoryError(format!("The APK has no asset {} !", asset_path))),
+        }
+    }
+
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        match ApkMount::<A>::asset_path(path) {
+            Ok(asset_path) => self.assets.read(asset_path.as_str()).is_some(),
+            Err(_) => false,
+        }
+    }
+
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> FileSystemResult<Vec<String>> {
+        let asset_path = ApkMount::<A>::asset_path(path)?;
+        Ok(self.assets.list(asset_path.as_str()))
+    }
+
+    pub fn create<P: AsRef<Path>>(&self, path: P) -> FileSystemResult<()> {
+        Err(FileSystemError::PermissionError(format!("The APK assets are read-only, {} cannot be created !", path.as_ref().display())))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ApkMetadata {
+    directory: bool,
+    len: u64,
+}
+
+impl VMetadata for ApkMetadata {
+    fn is_dir(&self) -> bool {
+        self.directory
+    }
+
+    fn is_file(&self) -> bool {
+        !self.directory
+    }
+
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+//The AssetRoot is read from the APK, the other roots are in the storage of the app.
+pub struct AndroidFilesystem<A: ApkAssets> {
+    apk: ApkMount<A>,
+    storage: Filesystem,
+}
+
+impl<A: ApkAssets> AndroidFilesystem<A> {
+    pub fn new(paths: &AndroidPaths, assets: A) -> Self {
+        AndroidFilesystem {
+            apk: ApkMount::new(assets),
+            storage: paths.filesystem(),
+        }
+    }
+}
+
+impl<A: ApkAssets> VFilesystem for AndroidFilesystem<A> {
+    fn open_with_options(&self, path: VPath, open_options: &OpenOptions) -> FileSystemResult<Box<VFile>> {
+        if path.root() != RootDir::AssetRoot {
+            return self.storage.open_with_options(path, open_options);
+        }
+        if open_options.write() || open_options.append() || open_options.create() || open_options.truncate() {
+            self.apk.create(path.as_str())?;
+        }
+        Ok(Box::new(self.apk.open(path.as_str())?))
+    }
+
+    fn mkdir(&self, path: VPath) -> FileSystemResult<()> {
+        if path.root() == RootDir::AssetRoot {
+            return self.apk.create(path.as_str());
+        }
+        self.storage.mkdir(path)
+    }
+
+    fn rm(&self, path: VPath) -> FileSystemResult<()> {
+        if path.root() == RootDir::AssetRoot {
+            return self.apk.create(path.as_str());
+        }
+        self.storage.rm(path)
+    }
+
+    fn rmrf(&self, path: VPath) -> FileSystemResult<()> {
+        if path.root() == RootDir::AssetRoot {
+            return self.apk.create(path.as_str());
+        }
+        self.storage.rmrf(path)
+    }
+
+    //A directory of the APK with sub-directories only is not found, AAssetDir does not list them.
+    fn metadata(&self, path: VPath) -> FileSystemResult<Box<VMetadata>> {
+        if path.root() != RootDir::AssetRoot {
+            return self.storage.metadata(path);
+        }
+        let asset_path = ApkMount::<A>::asset_path(path.as_str())?;
+        if let Some(bytes) = self.apk.assets.read(asset_path.as_str()) {
+            return Ok(Box::new(ApkMetadata { directory: false, len: bytes.len() as u64 }));
+        }
+        if asset_path.is_empty() || !self.apk.assets.list(asset_path.as_str()).is_empty() {
+            return Ok(Box::new(ApkMetadata { directory: true, len: 0 }));
+        }
+        Err(FileSystemError::GameDirectoryError(format!("The APK has no asset {} !", asset_path)))
+    }
+
+    fn read_dir(&self, path: VPath) -> FileSystemResult<Vec<VPathBuf>> {
+        if path.root() != RootDir::AssetRoot {
+            return self.storage.read_dir(path);
+        }
+        let mut entries = self.apk.read_dir(path.as_str())?.iter()
+            .map(|entry| VPathBuf::new(RootDir::AssetRoot, entry.as_str()))
+            .collect::<FileSystemResult<Vec<VPathBuf>>>()?;
+        entries.sort();
+        Ok(entries)
+    }
+}
+
+//The commands of android_native_app_glue.
+pub const APP_CMD_INIT_WINDOW: i32 = 1;
+pub const APP_CMD_TERM_WINDOW: i32 = 2;
+pub const APP_CMD_LOW_MEMORY: i32 = 9;
+pub const APP_CMD_RESUME: i32 = 11;
+pub const APP_CMD_SAVE_STATE: i32 = 12;
+pub const APP_CMD_PAUSE: i32 = 13;
+pub const APP_CMD_DESTROY: i32 = 15;
+
+//The size of the ANativeWindow is given for APP_CMD_INIT_WINDOW. The other commands are handled by the
+//window events (focus, resize) or ignored.
+pub fn lifecycle_event(command: i32, window_size: (u32, u32)) -> Option<LifecycleEvent> {
+    match command {
+        APP_CMD_INIT_WINDOW => Some(LifecycleEvent::SurfaceCreated { width: window_size.0, height: window_size.1 }),
+        APP_CMD_TERM_WINDOW => Some(LifecycleEvent::SurfaceDestroyed),
+        APP_CMD_LOW_MEMORY => Some(LifecycleEvent::LowMemory),
+        APP_CMD_RESUME => Some(LifecycleEvent::Resumed),
+        APP_CMD_PAUSE => Some(LifecycleEvent::Paused),
+        APP_CMD_SAVE_STATE | APP_CMD_DESTROY => Some(LifecycleEvent::Terminating),
+        _ => None,
+    }
+}
+
+//The actions of the AMotionEvents.
+const AMOTION_EVENT_ACTION_MASK: i32 = 0xff;
+const AMOTION_EVENT_ACTION_POINTER_INDEX_SHIFT: i32 = 8;
+pub const AMOTION_EVENT_ACTION_DOWN: i32 = 0;
+pub const AMOTION_EVENT_ACTION_UP: i32 = 1;
+pub const AMOTION_EVENT_ACTION_MOVE: i32 = 2;
+pub const AMOTION_EVENT_ACTION_CANCEL: i32 = 3;
+pub const AMOTION_EVENT_ACTION_POINTER_DOWN: i32 = 5;
+pub const AMOTION_EVENT_ACTION_POINTER_UP: i32 = 6;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MotionPointer {
+    pub id: u64,
+    pub x: f32,
+    pub y: f32,
+}
+
+//A motion event has all the pointers on the screen, the action tells which one changed.
+pub fn touch_events(action: i32, pointers: &[MotionPointer]) -> Vec<TouchEvent> {
+    let index = ((action >> AMOTION_EVENT_ACTION_POINTER_INDEX_SHIFT) & 0xff) as usize;
+    let event = |pointer: &MotionPointer, phase| TouchEvent { pointer_id: pointer.id, phase, x: pointer.x, y: pointer.y };
+    let single = |phase| pointers.get(index).map(|pointer| vec![event(pointer, phase)]).unwrap_or_default();
+
+    match action & AMOTION_EVENT_ACTION_MASK {
+        AMOTION_EVENT_ACTION_DOWN | AMOTION_EVENT_ACTION_POINTER_DOWN => single(TouchPhase::Began),
+        AMOTION_EVENT_ACTION_UP | AMOTION_EVENT_ACTION_POINTER_UP => single(TouchPhase::Ended),
+        AMOTION_EVENT_ACTION_MOVE => pointers.iter().map(|pointer| event(pointer, TouchPhase::Moved)).collect(),
+        AMOTION_EVENT_ACTION_CANCEL => pointers.iter().map(|pointer| event(pointer, TouchPhase::Cancelled)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AndroidAudioApi {
+    AAudio,
+    OpenSLES,
+}
+
+impl AndroidAudioApi {
+    pub fn for_api_level(api_level: u32) -> Self {
+        if api_level >= 27 {AndroidAudioApi::AAudio} else {AndroidAudioApi::OpenSLES}
+    }
+}
+
+//The id of the default output: OpenSL ES cannot choose the device.
+pub const DEFAULT_OUTPUT_ID: &'static str = "default";
+
+//The native streams, the device id is the one of the AudioDeviceInfo of Java.
+pub trait AndroidAudioStream {
+    fn open(&mut self, api: AndroidAudioApi, device_id: Option<i32>) -> Result<(), String>;
+
+    fn close(&mut self);
+}
+
+pub struct AndroidAudioBackend<S: AndroidAudioStream> {
+    stream: S,
+    api: AndroidAudioApi,
+    //Given by the AudioDeviceCallback of the AudioManager.
+    devices: Vec<AudioDeviceInfo>,
+}
+
+impl<S: AndroidAudioStream> AndroidAudioBackend<S> {
+    pub fn new(stream: S, api_level: u32) -> Self {
+        AndroidAudioBackend {
+            stream,
+            api: AndroidAudioApi::for_api_level(api_level),
+            devices: Vec::new(),
+        }
+    }
+
+    pub fn api(&self) -> AndroidAudioApi {
+        self.api
+    }
+
+    pub fn set_devices(&mut self, devices: Vec<AudioDeviceInfo>) {
+        self.devices = devices;
+    }
+}
+
+impl<S: AndroidAudioStream> AudioBackend for AndroidAudioBackend<S> {
+    fn enumerate_outputs(&mut self) -> Vec<AudioDeviceInfo> {
+        let default = AudioDeviceInfo {
+            id: String::from(DEFAULT_OUTPUT_ID),
+            name: String::from("Default"),
+            is_default: true,
+        };
+        let mut outputs = vec![default];
+        if self.api == AndroidAudioApi::AAudio {
+            outputs.extend(self.devices.iter().cloned().map(|mut device| {
+                device.is_default = false;
+                device
+            }));
+        }
+        outputs
+    }
+
+    fn open_output(&mut self, id: &str) -> Result<(), String> {
+        let device_id = if id == DEFAULT_OUTPUT_ID {
+            None
+        } else {
+            Some(id.parse::<i32>().map_err(|_| format!("{} is not an Android audio device id !", id))?)
+        };
+
+        match self.stream.open(self.api, device_id) {
+            Ok(()) => Ok(()),
+            Err(ref error) if self.api == AndroidAudioApi::AAudio => {
+                warn!("Could not open the AAudio stream ({}), falling back to OpenSL ES.", error);
+                self.api = AndroidAudioApi::OpenSLES;
+                self.stream.open(AndroidAudioApi::OpenSLES, None)
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    fn close_output(&mut self) {
+        self.stream.close();
+    }
+}
+
+#[cfg(test)]
+mod android_test {
+    use super::*;
+    use std::collections::HashMap;
+    use std::env;
+    use std::io::Read;
+
+    struct Assets(HashMap<&'static str, Vec<u8>>);
+
+    impl ApkAssets for Assets {
+        fn read(&self, path: &str) -> Option<Vec<u8>> {
+            self.0.get(path).cloned()
+        }
+
+        fn list(&self, directory: &str) -> Vec<String> {
+            let mut files: Vec<String> = self.0.keys().filter(|key| key.starts_with(directory)).map(|key| key.to_string()).collect();
+            files.sort();
+            files
+        }
+    }
+
+    struct Stream {
+        opened: Vec<(AndroidAudioApi, Option<i32>)>,
+    }
+
+    impl AndroidAudioStream for Stream {
+        fn open(&mut self, api: AndroidAudioApi, device_id: Option<i32>) -> Result<(), String> {
+            self.opened.push((api, device_id));
+            if api == AndroidAudioApi::AAudio {Err(String::from("AAUDIO_ERROR_INTERNAL"))} else {Ok(())}
+        }
+
+        fn close(&mut self) {}
+    }
+
+    #[test]
+    fn android_backend() {
+        let paths = AndroidPaths {
+            internal_data: PathBuf::from("/data/user/0/org.maskerad.game/files"),
+            external_data: None,
+            cache: PathBuf::from("/data/user/0/org.maskerad.game/cache"),
+        };
+        let directories = paths.game_directories();
+        assert_eq!(directories.get(&RootDir::UserSaveRoot), Some(Path::new("/data/user/0/org.maskerad.game/files/data/game_saves")));
+        assert_eq!(paths.download_directory(), PathBuf::from("/data/user/0/org.maskerad.game/files/downloads"));
+
+        let mut assets = HashMap::new();
+        assets.insert("levels/intro.kscene", b"scene".to_vec());
+        let apk = ApkMount::new(Assets(assets));
+        let mut scene = String::new();
+        apk.open("/levels/./intro.kscene").unwrap().read_to_string(&mut scene).unwrap();
+        assert_eq!(scene, "scene");
+        assert!(apk.open("levels/../../secrets").is_err());
+        assert!(apk.create("levels/new.kscene").is_err());
+        assert_eq!(apk.read_dir("levels").unwrap(), vec![String::from("levels/intro.kscene")]);
+
+        assert_eq!(lifecycle_event(APP_CMD_INIT_WINDOW, (1080, 2400)), Some(LifecycleEvent::SurfaceCreated { width: 1080, height: 2400 }));
+        assert_eq!(lifecycle_event(APP_CMD_PAUSE, (0, 0)), Some(LifecycleEvent::Paused));
+
+        let pointers = [MotionPointer { id: 4, x: 1.0, y: 2.0 }, MotionPointer { id: 7, x: 3.0, y: 4.0 }];
+        let second_up = AMOTION_EVENT_ACTION_POINTER_UP | (1 << AMOTION_EVENT_ACTION_POINTER_INDEX_SHIFT);
+        assert_eq!(touch_events(second_up, &pointers), vec![TouchEvent { pointer_id: 7, phase: TouchPhase::Ended, x: 3.0, y: 4.0 }]);
+        assert_eq!(touch_events(AMOTION_EVENT_ACTION_MOVE, &pointers).len(), 2);
+
+        let mut backend = AndroidAudioBackend::new(Stream { opened: Vec::new() }, 30);
+        backend.set_devices(vec![AudioDeviceInfo { id: String::from("12"), name: String::from("USB headset"), is_default: false }]);
+        assert_eq!(backend.enumerate_outputs().len(), 2);
+        assert!(backend.open_output("12").is_ok());
+        assert_eq!(backend.api(), AndroidAudioApi::OpenSLES);
+        assert_eq!(backend.stream.opened, vec![(AndroidAudioApi::AAudio, Some(12)), (AndroidAudioApi::OpenSLES, None)]);
+        assert_eq!(backend.enumerate_outputs().len(), 1);
+    }
+
+    #[test]
+    fn android_filesystem() {
+        let storage = env::temp_dir().join("maskerad_android_filesystem_test");
+        let paths = AndroidPaths {
+            internal_data: storage.clone(),
+            external_data: None,
+            cache: storage.join("cache"),
+        };
+        let mut assets = HashMap::new();
+        assets.insert("levels/intro.kscene", b"scene".to_vec());
+        let fs = AndroidFilesystem::new(&paths, Assets(assets));
+
+        assert_eq!(fs.read_to_string(VPath::new(RootDir::AssetRoot, "levels/intro.kscene").unwrap()).unwrap(), "scene");
+        assert_eq!(fs.metadata(VPath::new(RootDir::AssetRoot, "levels/intro.kscene").unwrap()).unwrap().len(), 5);
+        assert!(fs.metadata(VPath::new(RootDir::AssetRoot, "levels").unwrap()).unwrap().is_dir());
+        assert!(!fs.exists(VPath::new(RootDir::AssetRoot, "levels/outro.kscene").unwrap()));
+        assert_eq!(fs.read_dir(VPath::new(RootDir::AssetRoot, "levels").unwrap()).unwrap(), vec![VPathBuf::new(RootDir::AssetRoot, "levels/intro.kscene").unwrap()]);
+        assert!(fs.create(VPath::new(RootDir::AssetRoot, "levels/new.kscene").unwrap()).is_err());
+        assert!(fs.rmrf(VPath::new(RootDir::AssetRoot, "levels").unwrap()).is_err());
+
+        fs.mkdir(VPath::root_of(RootDir::UserSaveRoot)).unwrap();
+        fs.write_all(VPath::new(RootDir::UserSaveRoot, "slot.sav").unwrap(), b"save").unwrap();
+        assert!(fs.exists(VPath::new(RootDir::UserSaveRoot, "slot.sav").unwrap()));
+        assert!(storage.join("data/game_saves/slot.sav").exists());
+        fs.rmrf(VPath::root_of(RootDir::WorkingDirectory)).unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ IOS.
+
+ The scaffolding of the iOS platform, before the Metal backend of the renderer:
+ - Filesystem: the app sandbox. The saves are in Documents (backed up, the player may see them),
+   the config in Library/Application Support (backed up, hidden), the logs in Library/Caches (the OS may
+   purge them). The assets are in the app bundle, which is the working directory.
+ - Surface: the view of the app is backed by a CAMetalLayer. The layer is given to the MetalSurfaceHooks
+   of the renderer when the view is laid out, with its drawable size and scale.
+ - Lifecycle: the notifications of UIApplication become LifecycleEvents. A backgrounded app must not use
+   the GPU (the OS kills it), the rendering stops as soon as the app resigns active.
+*/
+
+use std::os::raw::c_void;
+use std::path::PathBuf;
+use core::filesystem::filesystem::Filesystem;
+use core::filesystem::game_directories::{GameDirectories, RootDir};
+use gameplay::event::EventBus;
+use platform::{Lifecycle, LifecycleEvent};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IosSandbox {
+    //NSHomeDirectory().
+    pub home: PathBuf,
+    //The main bundle, read-only.
+    pub bundle: PathBuf,
+}
+
+impl IosSandbox {
+    pub fn documents(&self) -> PathBuf {
+        self.home.join("Documents")
+    }
+
+    pub fn application_support(&self) -> PathBuf {
+        self.home.join("Library").join("Application Support")
+    }
+
+    pub fn caches(&self) -> PathBuf {
+        self.home.join("Library").join("Caches")
+    }
+
+    pub fn game_directories(&self) -> GameDirectories {
+        GameDirectories::from_roots(self.bundle.clone(), self.application_support(), self.application_support())
+            .with_root(RootDir::UserSaveRoot, self.documents().join("game_saves"))
+            .with_root(RootDir::EngineLogRoot, self.caches().join("maskerad_logs"))
+    }
+
+    pub fn filesystem(&self) -> Filesystem {
+        Filesystem::with_directories(self.game_directories())
+    }
+}
+
+//The CAMetalLayer of the view, owned by UIKit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MetalLayer {
+    pub layer: *mut c_void,
+    //In pixels: the size of the view in points, by the scale.
+    pub drawable_width: u32,
+    pub drawable_height: u32,
+    pub contents_scale: f32,
+}
+
+//Implemented by the Metal backend of the renderer.
+pub trait MetalSurfaceHooks {
+    fn create_surface(&mut self, layer: &MetalLayer) -> Result<(), String>;
+
+    fn resize_surface(&mut self, layer: &MetalLayer);
+
+    fn destroy_surface(&mut self);
+}
+
+//The notifications of UIApplication.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ApplicationNotification {
+    WillResignActive,
+    DidBecomeActive,
+    DidEnterBackground,
+    WillEnterForeground,
+    DidReceiveMemoryWarning,
+    WillTerminate,
+}
+
+pub fn lifecycle_event(notification: ApplicationNotification) -> Option<LifecycleEvent> {
+    match notification {
+        ApplicationNotification::WillResignActive => Some(LifecycleEvent::Paused),
+        ApplicationNotification::DidBecomeActive => Some(LifecycleEvent::Resumed),
+        //The state is saved when resigning active, the app may be suspended without notice after this one.
+        ApplicationNotification::DidEnterBackground => Some(LifecycleEvent::Terminating),
+        ApplicationNotification::DidReceiveMemoryWarning => Some(LifecycleEvent::LowMemory),
+        ApplicationNotification::WillTerminate => Some(LifecycleEvent::Terminating),
+        ApplicationNotification::WillEnterForeground => None,
+    }
+}
+
+pub struct IosApplication {
+    lifecycle: Lifecycle,
+    surface_hooks: Option<Box<MetalSurfaceHooks>>,
+    layer: Option<MetalLayer>,
+}
+
+impl IosApplication {
+    pub fn new() -> Self {
+        IosApplication {
+            lifecycle: Lifecycle::new(1.0),
+            surface_hooks: None,
+            layer: None,
+        }
+    }
+
+    pub fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    pub fn set_surface_hooks(&mut self, hooks: Box<MetalSurfaceHooks>) {
+        self.surface_hooks = Some(hooks);
+    }
+
+    pub fn on_notification(&mut self, notification: ApplicationNotification, bus: &mut EventBus) {
+        if let Some(event) = lifecycle_event(notification) {
+            self.lifecycle.handle(event, bus);
+        }
+    }
+
+    //From layoutSubviews of the view: the first layout creates the surface, the others resize it.
+    pub fn on_layout(&mut self, layer: MetalLayer, bus: &mut EventBus) -> Result<(), String> {
+        let created = self.layer.is_some();
+        if let Some(ref mut hooks) = self.surface_hooks {
+            if created {
+                hooks.resize_surface(&layer);
+            } else {
+                hooks.create_surface(&layer)?;
+            }
+        }
+        self.layer = Some(layer);
+        self.lifecycle.handle(LifecycleEvent::SurfaceCreated { width: layer.drawable_width, height: layer.drawable_height }, bus);
+        Ok(())
+    }
+
+    //The view is removed from the window.
+    pub fn on_view_removed(&mut self, bus: &mut EventBus) {
+        if self.layer.take().is_some() {
+            if let Some(ref mut hooks) = self.surface_hooks {
+                hooks.destroy_surface();
+            }
+            self.lifecycle.handle(LifecycleEvent::SurfaceDestroyed, bus);
+        }
+    }
+}
+
+#[cfg(test)]
+mod ios_test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::ptr;
+    use std::rc::Rc;
+    use platform::LifecycleReaction;
+
+    struct Hooks(Rc<RefCell<Vec<String>>>);
+
+    impl MetalSurfaceHooks for Hooks {
+        fn create_surface(&mut self, layer: &MetalLayer) -> Result<(), String> {
+            self.0.borrow_mut().push(format!("create {}x{}", layer.drawable_width, layer.drawable_height));
+            Ok(())
+        }
+
+        fn resize_surface(&mut self, layer: &MetalLayer) {
+            self.0.borrow_mut().push(format!("resize {}x{}", layer.drawable_width, layer.drawable_height));
+        }
+
+        fn destroy_surface(&mut self) {
+            self.0.borrow_mut().push(String::from("destroy"));
+        }
+    }
+
+    #[test]
+    fn ios_scaffolding() {
+        let sandbox = IosSandbox {
+            home: PathBuf::from("/var/mobile/Containers/Data/Application/GAME"),
+            bundle: PathBuf::from("/var/containers/Bundle/Application/GAME/Game.app"),
+        };
+        let directories = sandbox.game_directories();
+        assert_eq!(directories.get(&RootDir::UserSaveRoot), Some(Path::new("/var/mobile/Containers/Data/Application/GAME/Documents/game_saves")));
+        assert_eq!(directories.get(&RootDir::EngineLogRoot), Some(Path::new("/var/mobile/Containers/Data/Application/GAME/Library/Caches/maskerad_logs")));
+        assert_eq!(directories.get(&RootDir::WorkingDirectory), Some(sandbox.bundle.as_path()));
+
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut application = IosApplication::new();
+        application.set_surface_hooks(Box::new(Hooks(calls.clone())));
+        let mut bus = EventBus::new();
+        let layer = MetalLayer { layer: ptr::null_mut(), drawable_width: 1170, drawable_height: 2532, contents_scale: 3.0 };
+        application.on_layout(layer, &mut bus).unwrap();
+        application.on_layout(MetalLayer { drawable_width: 2532, drawable_height: 1170, ..layer }, &mut bus).unwrap();
+        assert!(application.lifecycle().should_render());
+
+        application.on_notification(ApplicationNotification::WillResignActive, &mut bus);
+        application.on_notification(ApplicationNotification::DidEnterBackground, &mut bus);
+        assert!(!application.lifecycle().should_render());
+        application.on_view_removed(&mut bus);
+        assert_eq!(*calls.borrow(), vec![String::from("create 1170x2532"), String::from("resize 2532x1170"), String::from("destroy")]);
+        assert_eq!(bus.read::<LifecycleReaction>().iter().filter(|reaction| ***reaction == LifecycleReaction::SaveState).count(), 2);
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ PLATFORMS.
+
+ On the desktop, the game directories come from the environment, and the window events from the
+ windowing library. The other platforms have their own rules:
+ - the directories are given by the OS (the app sandbox), and the assets may not be plain files,
+ - the application is paused, resumed and killed by the OS, and its surface may be destroyed while it
+   is still alive.
+
+ Each backend translates the lifecycle of its OS into LifecycleEvents. The Lifecycle keeps the window
+ events of the game consistent with them, and publishes what the engine must do on the event bus.
+*/
+
+pub mod android;
+pub mod ios;
+#[cfg(feature = "wasm")]
+pub mod web;
+
+use gameplay::event::EventBus;
+use gameplay::window_events::{WindowEvent, WindowHandler, WindowPolicy};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LifecycleEvent {
+    Paused,
+    Resumed,
+    //The size in physical pixels.
+    SurfaceCreated { width: u32, height: u32 },
+    SurfaceDestroyed,
+    LowMemory,
+    //The last chance to save, the process may be killed without any other event.
+    Terminating,
+}
+
+//Published on the event bus.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LifecycleReaction {
+    //The game saves its state, the OS may kill a paused application.
+    SaveState,
+    //The renderer destroys its swapchain before the surface is gone.
+    ReleaseSurface,
+    CreateSurface { width: u32, height: u32 },
+    //The resource caches drop what is not used.
+    TrimMemory,
+}
+
+pub struct Lifecycle {
+    window: WindowHandler,
+    paused: bool,
+    has_surface: bool,
+}
+
+impl Lifecycle {
+    pub fn new(scale_factor: f32) -> Self {
+        Lifecycle {
+            window: WindowHandler::new(WindowPolicy::default(), 0, 0, scale_factor),
+            paused: false,
+            has_surface: false,
+        }
+    }
+
+    pub fn window(&self) -> &WindowHandler {
+        &self.window
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    //Nothing can be rendered without a surface.
+    pub fn should_render(&self) -> bool {
+        !self.paused && self.has_surface && self.window.should_render()
+    }
+
+    pub fn handle(&mut self, event: LifecycleEvent, bus: &mut EventBus) {
+        debug!("Lifecycle event: {:?}.", event);
+        match event {
+            LifecycleEvent::Paused => {
+                if self.paused {
+                    return;
+                }
+                self.paused = true;
+                self.window.handle(WindowEvent::FocusLost, bus);
+                self.window.handle(WindowEvent::Minimized, bus);
+                bus.publish(LifecycleReaction::SaveState);
+            },
+            LifecycleEvent::Resumed => {
+                self.paused = false;
+                self.window.handle(WindowEvent::Restored, bus);
+                self.window.handle(WindowEvent::FocusGained, bus);
+            },
+            LifecycleEvent::SurfaceCreated { width, height } => {
+                self.has_surface = true;
+                bus.publish(LifecycleReaction::CreateSurface { width, height });
+                self.window.handle(WindowEvent::Resized { width, height }, bus);
+            },
+            LifecycleEvent::SurfaceDestroyed => {
+                if self.has_surface {
+                    self.has_surface = false;
+                    bus.publish(LifecycleReaction::ReleaseSurface);
+                }
+            },
+            LifecycleEvent::LowMemory => {
+                bus.publish(LifecycleReaction::TrimMemory);
+            },
+            LifecycleEvent::Terminating => {
+                bus.publish(LifecycleReaction::SaveState);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod platform_test {
+    use super::*;
+    use gameplay::window_events::WindowReaction;
+
+    #[test]
+    fn lifecycle_reactions() {
+        let mut bus = EventBus::new();
+        let mut lifecycle = Lifecycle::new(2.0);
+        assert!(!lifecycle.should_render());
+
+        lifecycle.handle(LifecycleEvent::SurfaceCreated { width: 1080, height: 2400 }, &mut bus);
+        assert!(lifecycle.should_render());
+        bus.end_frame();
+        assert_eq!(bus.read::<LifecycleReaction>(), vec![&LifecycleReaction::CreateSurface { width: 1080, height: 2400 }]);
+        bus.end_frame();
+
+        lifecycle.handle(LifecycleEvent::Paused, &mut bus);
+        lifecycle.handle(LifecycleEvent::Paused, &mut bus);
+        lifecycle.handle(LifecycleEvent::SurfaceDestroyed, &mut bus);
+        lifecycle.handle(LifecycleEvent::SurfaceDestroyed, &mut bus);
+        assert!(!lifecycle.should_render());
+        bus.end_frame();
+        assert_eq!(bus.read::<LifecycleReaction>(), vec![&LifecycleReaction::SaveState, &LifecycleReaction::ReleaseSurface]);
+        assert_eq!(bus.read::<WindowReaction>(), vec![&WindowReaction::ResetInputs, &WindowReaction::PauseRequested]);
+        bus.end_frame();
+
+        lifecycle.handle(LifecycleEvent::Resumed, &mut bus);
+        assert!(!lifecycle.should_render());
+        lifecycle.handle(LifecycleEvent::SurfaceCreated { width: 1080, height: 2400 }, &mut bus);
+        assert!(lifecycle.should_render());
+    }
+}
@@ -0,0 +1,776 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ WEB.
+
+ In the browser, there is no filesystem and no blocking call:
+ - The user data (config, logs, saves) is kept in memory, loaded from IndexedDB at startup, and the
+   modified files are written back to IndexedDB by flush (after a save, and when the page is hidden).
+ - The assets are fetched from the server, next to the page, and mounted read-only: a file must be
+   prefetched before being opened, the loading screens wait for the prefetched files.
+ - WebFilesystem is the VFilesystem of the browser, over both: the VFilesystem stays synchronous, the
+   waiting is done before (prefetch) and after (flush). Only the saves and the user config are persisted,
+   the logs and the other user data are lost when the page is closed.
+ - The canvas is the window: its size and the device pixel ratio become window events, the visibility
+   of the page becomes the lifecycle.
+ - The game loop does not own the thread: requestAnimationFrame calls it once per display frame, and
+   the fixed steps are run from the elapsed time. A hidden tab gets no animation frame, the elapsed time
+   of the next frame is clamped.
+*/
+
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use js_sys::{Array, Uint8Array};
+use web_sys::{HtmlCanvasElement, IdbDatabase, IdbOpenDbRequest, IdbRequest, IdbTransactionMode, Response};
+use core::filesystem::filesystem_error::{FileSystemError, FileSystemResult};
+use core::filesystem::game_directories::{GameDirectories, RootDir};
+use core::filesystem::memory_filesystem::MemoryFilesystem;
+use core::filesystem::open_options::OpenOptions;
+use core::filesystem::vfilesystem::{join_path, VFile, VFilesystem, VMetadata};
+use core::filesystem::vpath::{VPath, VPathBuf};
+use gameplay::window_events::WindowEvent;
+use platform::LifecycleEvent;
+
+//The roots of the in-memory user data.
+pub fn web_directories() -> GameDirectories {
+    GameDirectories::from_roots(PathBuf::from("/"), PathBuf::from("/config"), PathBuf::from("/data"))
+}
+
+//Where the user data is persisted.
+pub trait PersistentStore {
+    fn put(&mut self, path: &str, bytes: &[u8]);
+
+    fn delete(&mut self, path: &str);
+}
+
+pub struct UserData {
+    files: HashMap<String, Vec<u8>>,
+    modified: BTreeSet<String>,
+    removed: BTreeSet<String>,
+}
+
+impl UserData {
+    //With the files loaded from the persistent store.
+    pub fn load(files: Vec<(String, Vec<u8>)>) -> Self {
+        UserData {
+            files: files.into_iter().collect(),
+            modified: BTreeSet::new(),
+            removed: BTreeSet::new(),
+        }
+    }
+
+    pub fn read(&self, path: &str) -> FileSystemResult<Cursor<Vec<u8>>> {
+        match self.files.get(path) {
+            Some(bytes) => Ok(Cursor::new(bytes.clone())),
+            None => Err(FileSystemError::GameDirectoryError(format!("The user data has no file {} !", path))),
+        }
+    }
+
+    pub fn write(&mut self, path: &str, bytes: Vec<u8>) {
+        self.files.insert(path.to_string(), bytes);
+        self.removed.remove(path);
+        self.modified.insert(path.to_string());
+    }
+
+    pub fn remove(&mut self, path: &str) {
+        if self.files.remove(path).is_some() {
+            self.modified.remove(path);
+            self.removed.insert(path.to_string());
+        }
+    }
+
+    pub fn list(&self, directory: &str) -> Vec<String> {
+        let prefix = format!("{}/", directory.trim_end_matches('/'));
+        let mut files: Vec<String> = self.files.keys().filter(|path| path.starts_with(prefix.as_str())).cloned().collect();
+        files.sort();
+        files
+    }
+
+    pub fn is_flushed(&self) -> bool {
+        self.modified.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn flush(&mut self, store: &mut PersistentStore) {
+        debug!("Flushing {} modified and {} removed user files.", self.modified.len(), self.removed.len());
+        for path in self.removed.iter() {
+            store.delete(path.as_str());
+        }
+        for path in self.modified.iter() {
+            store.put(path.as_str(), self.files[path].as_slice());
+        }
+        self.modified.clear();
+        self.removed.clear();
+    }
+}
+
+//Fetches the assets from the server, the results come later.
+pub trait AssetFetcher {
+    fn request(&mut self, path: &str);
+
+    fn poll_completed(&mut self) -> Vec<(String, Result<Vec<u8>, String>)>;
+}
+
+//The fetched assets, read-only.
+pub struct FetchMount<F: AssetFetcher> {
+    fetcher: F,
+    pending: BTreeSet<String>,
+    fetched: HashMap<String, Vec<u8>>,
+    failed: HashMap<String, String>,
+}
+
+impl<F: AssetFetcher> FetchMount<F> {
+    pub fn new(fetcher: F) -> Self {
+        FetchMount {
+            fetcher,
+            pending: BTreeSet::new(),
+            fetched: HashMap::new(),
+            failed: HashMap::new(),
+        }
+    }
+
+    fn normalize(path: &str) -> String {
+        path.trim_start_matches('/').to_string()
+    }
+
+    pub fn prefetch(&mut self, path: &str) {
+        let path = FetchMount::<F>::normalize(path);
+        if !self.fetched.contains_key(&path) && self.pending.insert(path.clone()) {
+            self.failed.remove(&path);
+            self.fetcher.request(path.as_str());
+        }
+    }
+
+    //Called each frame.
+    pub fn poll(&mut self) {
+        for (path, result) in self.fetcher.poll_completed() {
+            self.pending.remove(&path);
+            match result {
+                Ok(bytes) => {
+                    self.fetched.insert(path, bytes);
+                },
+                Err(error) => {
+                    warn!("Could not fetch the asset {}: {}.", path, error);
+                    self.failed.insert(path, error);
+                },
+            }
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn open(&self, path: &str) -> FileSystemResult<Cursor<Vec<u8>>> {
+        let path = FetchMount::<F>::normalize(path);
+        if let Some(bytes) = self.fetched.get(&path) {
+            return Ok(Cursor::new(bytes.clone()));
+        }
+        let description = match self.failed.get(&path) {
+            Some(error) => format!("The asset {} could not be fetched: {} !", path, error),
+            None if self.pending.contains(&path) => format!("The asset {} is still being fetched !", path),
+            None => format!("The asset {} has not been prefetched !", path),
+        };
+        Err(FileSystemError::GameDirectoryError(description))
+    }
+
+    pub fn create(&self, path: &str) -> FileSystemResult<()> {
+        Err(FileSystemError::PermissionError(format!("The fetched assets are read-only, {} cannot be created !", path)))
+    }
+
+    //None if the asset has not been fetched.
+    pub fn len(&self, path: &str) -> Option<u64> {
+        self.fetched.get(&FetchMount::<F>::normalize(path)).map(|bytes| bytes.len() as u64)
+    }
+
+    //A directory exists if a fetched asset is in it.
+    pub fn is_directory(&self, path: &str) -> bool {
+        let path = FetchMount::<F>::normalize(path);
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        path.is_empty() || self.fetched.keys().any(|known| known.starts_with(prefix.as_str()))
+    }
+
+    //The fetched assets and directories in the directory.
+    pub fn read_dir(&self, path: &str) -> Vec<String> {
+        let directory = FetchMount::<F>::normalize(path);
+        let directory = directory.trim_end_matches('/');
+        let mut entries: Vec<String> = self.fetched.keys().filter_map(|known| {
+            let name = if directory.is_empty() {
+                known.as_str()
+            } else if known.starts_with(directory) && known[directory.len()..].starts_with('/') {
+                &known[directory.len() + 1..]
+            } else {
+                return None;
+            };
+            Some(join_path(directory, name.split('/').next().unwrap_or(name)))
+        }).collect();
+        entries.sort();
+        entries.dedup();
+        entries
+    }
+}
+
+//The roots written to the persistent store.
+pub const PERSISTED_ROOTS: [RootDir; 2] = [RootDir::UserSaveRoot, RootDir::UserConfigRoot];
+
+//The files written and the files removed.
+type Changes = (Vec<(String, Vec<u8>)>, Vec<String>);
+
+//The working directory and the AssetRoot are fetched, the other roots are in memory.
+pub struct WebFilesystem<F: AssetFetcher> {
+    directories: GameDirectories,
+    assets: FetchMount<F>,
+    user: MemoryFilesystem,
+    //The content of the persisted files, as in the store.
+    persisted: HashMap<String, Vec<u8>>,
+}
+
+impl<F: AssetFetcher> WebFilesystem<F> {
+    //With the files loaded from the persistent store (IndexedDbStore::take_loaded).
+    pub fn new(fetcher: F, files: Vec<(String, Vec<u8>)>) -> FileSystemResult<Self> {
+        let mut filesystem = WebFilesystem {
+            directories: web_directories(),
+            assets: FetchMount::new(fetcher),
+            user: MemoryFilesystem::new(),
+            persisted: HashMap::new(),
+        };
+        for (key, bytes) in files {
+            match filesystem.root_of(key.as_str()) {
+                Some((root_dir, path)) => {
+                    let path = VPathBuf::new(root_dir, path.as_str())?;
+                    if let Some(parent) = path.as_vpath().parent() {
+                        filesystem.user.mkdir(parent)?;
+                    }
+                    filesystem.user.write_all(path.as_vpath(), bytes.as_slice())?;
+                    filesystem.persisted.insert(key, bytes);
+                },
+                None => warn!("The persisted file {} is not in a persisted root, it is ignored.", key),
+            }
+        }
+        debug!("Web filesystem created with {} persisted files.", filesystem.persisted.len());
+        Ok(filesystem)
+    }
+
+    fn is_asset_root(root_dir: RootDir) -> bool {
+        root_dir == RootDir::WorkingDirectory || root_dir == RootDir::AssetRoot
+    }
+
+    //The key of a file in the store: its path in the web directories.
+    fn key(&self, path: VPath) -> String {
+        let root = self.directories.get(&path.root()).map(|root| root.to_string_lossy().into_owned()).unwrap_or_default();
+        format!("{}/{}", root.trim_end_matches('/'), path.as_str())
+    }
+
+    fn root_of(&self, key: &str) -> Option<(RootDir, String)> {
+        PERSISTED_ROOTS.iter().filter_map(|root_dir| {
+            let root = self.directories.get(root_dir)?.to_string_lossy().into_owned();
+            let root = root.trim_matches('/');
+            let key = key.trim_start_matches('/');
+            if key.starts_with(root) && key[root.len()..].starts_with('/') {
+                Some((*root_dir, String::from(&key[root.len() + 1..])))
+            } else {
+                None
+            }
+        }).next()
+    }
+
+    //To prefetch the assets, and to poll the fetches each frame.
+    pub fn assets(&mut self) -> &mut FetchMount<F> {
+        &mut self.assets
+    }
+
+    fn files(&self, directory: VPath, files: &mut Vec<VPathBuf>) -> FileSystemResult<()> {
+        for entry in self.user.read_dir(directory)? {
+            if self.user.metadata(entry.as_vpath())?.is_dir() {
+                self.files(entry.as_vpath(), files)?;
+            } else {
+                files.push(entry);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_flushed(&self) -> bool {
+        self.changes().map(|(written, removed)| written.is_empty() && removed.is_empty()).unwrap_or(false)
+    }
+
+    //The persisted files written and removed since the last flush.
+    fn changes(&self) -> FileSystemResult<Changes> {
+        let mut written = Vec::new();
+        let mut keys = BTreeSet::new();
+        for root_dir in PERSISTED_ROOTS.iter() {
+            let mut files = Vec::new();
+            self.files(VPath::root_of(*root_dir), &mut files)?;
+            for path in files {
+                let bytes = self.user.read_bytes(path.as_vpath())?;
+                let key = self.key(path.as_vpath());
+                if self.persisted.get(&key) != Some(&bytes) {
+                    written.push((key.clone(), bytes));
+                }
+                keys.insert(key);
+            }
+        }
+        let mut removed: Vec<String> = self.persisted.keys().filter(|key| !keys.contains(*key)).cloned().collect();
+        removed.sort();
+        Ok((written, removed))
+    }
+
+    //Writes the modified saves and config to the store, after a save and when the page is hidden.
+    pub fn flush(&mut self, store: &mut PersistentStore) -> FileSystemResult<()> {
+        let (written, removed) = self.changes()?;
+        debug!("Flushing {} modified and {} removed user files.", written.len(), removed.len());
+        for key in removed {
+            store.delete(key.as_str());
+            self.persisted.remove(&key);
+        }
+        for (key, bytes) in written {
+            store.put(key.as_str(), bytes.as_slice());
+            self.persisted.insert(key, bytes);
+        }
+        Ok(())
+    }
+}
+
+impl<F: AssetFetcher> VFilesystem for WebFilesystem<F> {
+    fn open_with_options(&self, path: VPath, open_options: &OpenOptions) -> FileSystemResult<Box<VFile>> {
+        if !WebFilesystem::<F>::is_asset_root(path.root()) {
+            return self.user.open_with_options(path, open_options);
+        }
+        if open_options.write() || open_options.append() || open_options.create() || open_options.truncate() {
+            self.assets.create(path.as_str())?;
+        }
+        Ok(Box::new(self.assets.open(path.as_str())?))
+    }
+
+    fn mkdir(&self, path: VPath) -> FileSystemResult<()> {
+        if WebFilesystem::<F>::is_asset_root(path.root()) {
+            return Err(FileSystemError::PermissionError(String::from("asset roots are read-only")));
+        }
+        self.user.mkdir(path)
+    }
+
+    fn rm(&self, path: VPath) -> FileSystemResult<()> {
+        if WebFilesystem::<F>::is_asset_root(path.root()) {
+            return Err(FileSystemError::PermissionError(String::from("asset roots are read-only")));
+        }
+        self.user.rm(path)
+    }
+
+    fn rmrf(&self, path: VPath) -> FileSystemResult<()> {
+        if WebFilesystem::<F>::is_asset_root(path.root()) {
+            return Err(FileSystemError::PermissionError(String::from("asset roots are read-only")));
+        }
+        self.user.rmrf(path)
+    }
+
+    //Only the fetched assets exist.
+    fn metadata(&self, path: VPath) -> FileSystemResult<Box<VMetadata>> {
+        if !WebFilesystem::<F>::is_asset_root(path.root()) {
+            return self.user.metadata(path);
+        }
+        if let Some(len) = self.assets.len(path.as_str()) {
+            return Ok(Box::new(WebMetadata { directory: false, len }));
+        }
+        if self.assets.is_directory(path.as_str()) {
+            return Ok(Box::new(WebMetadata { directory: true, len: 0 }));
+        }
+        Err(FileSystemError::GameDirectoryError(format!("The asset {} has not been fetched !", path)))
+    }
+
+    fn read_dir(&self, path: VPath) -> FileSystemResult<Vec<VPathBuf>> {
+        if !WebFilesystem::<F>::is_asset_root(path.root()) {
+            return self.user.read_dir(path);
+        }
+        self.assets.read_dir(path.as_str()).iter().map(|entry| VPathBuf::new(path.root(), entry.as_str())).collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WebMetadata {
+    directory: bool,
+    len: u64,
+}
+
+impl VMetadata for WebMetadata {
+    fn is_dir(&self) -> bool {
+        self.directory
+    }
+
+    fn is_file(&self) -> bool {
+        !self.directory
+    }
+
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FrameSteps {
+    pub steps: u32,
+    //The interpolation factor between the last two steps, for the rendering.
+    pub alpha: f64,
+}
+
+pub struct AnimationFrameClock {
+    step_milliseconds: f64,
+    max_steps: u32,
+    previous: Option<f64>,
+    accumulator: f64,
+}
+
+impl AnimationFrameClock {
+    pub fn new(steps_per_second: u32, max_steps: u32) -> Self {
+        AnimationFrameClock {
+            step_milliseconds: 1000.0 / steps_per_second as f64,
+            max_steps,
+            previous: None,
+            accumulator: 0.0,
+        }
+    }
+
+    //With the timestamp given to the requestAnimationFrame callback, in milliseconds.
+    pub fn on_animation_frame(&mut self, timestamp: f64) -> FrameSteps {
+        let elapsed = match self.previous {
+            Some(previous) => (timestamp - previous).max(0.0),
+            None => 0.0,
+        };
+        self.previous = Some(timestamp);
+        self.accumulator = (self.accumulator + elapsed).min(self.step_milliseconds * self.max_steps as f64);
+
+        let steps = (self.accumulator / self.step_milliseconds).floor() as u32;
+        self.accumulator -= steps as f64 * self.step_milliseconds;
+        FrameSteps {
+            steps,
+            alpha: self.accumulator / self.step_milliseconds,
+        }
+    }
+}
+
+//The browser side.
+
+fn window() -> Result<web_sys::Window, JsValue> {
+    web_sys::window().ok_or_else(|| JsValue::from_str("No window, the engine does not run in a page."))
+}
+
+//Calls the frame callback at each animation frame, until it returns false.
+pub fn run_animation_frames(mut frame: Box<FnMut(f64) -> bool>) -> Result<(), JsValue> {
+    let callback: Rc<RefCell<Option<Closure<FnMut(f64)>>>> = Rc::new(RefCell::new(None));
+    let next = callback.clone();
+    *callback.borrow_mut() = Some(Closure::wrap(Box::new(move |timestamp: f64| {
+        if !frame(timestamp) {
+            //Dropping the closure ends the loop.
+            next.borrow_mut().take();
+            return;
+        }
+        if let (Ok(window), Some(closure)) = (window(), next.borrow().as_ref()) {
+            if let Err(error) = window.request_animation_frame(closure.as_ref().unchecked_ref()) {
+                error!("Could not request an animation frame: {:?}.", error);
+            }
+        }
+    }) as Box<FnMut(f64)>));
+
+    let borrowed = callback.borrow();
+    window()?.request_animation_frame(borrowed.as_ref().expect("The frame callback has just been set").as_ref().unchecked_ref())?;
+    Ok(())
+}
+
+//The size of the canvas in physical pixels, and the device pixel ratio.
+pub fn canvas_event(canvas: &HtmlCanvasElement) -> Result<WindowEvent, JsValue> {
+    let scale_factor = window()?.device_pixel_ratio();
+    let width = (canvas.client_width() as f64 * scale_factor).round() as u32;
+    let height = (canvas.client_height() as f64 * scale_factor).round() as u32;
+    canvas.set_width(width);
+    canvas.set_height(height);
+    Ok(WindowEvent::DpiChanged { scale_factor: scale_factor as f32, width, height })
+}
+
+//From the visibilitychange events of the document.
+pub fn visibility_event() -> Result<LifecycleEvent, JsValue> {
+    let document = window()?.document().ok_or_else(|| JsValue::from_str("The page has no document."))?;
+    Ok(if document.hidden() {LifecycleEvent::Paused} else {LifecycleEvent::Resumed})
+}
+
+type Completed = Rc<RefCell<Vec<(String, Result<Vec<u8>, String>)>>>;
+
+pub struct BrowserFetcher {
+    base_url: String,
+    completed: Completed,
+}
+
+impl BrowserFetcher {
+    pub fn new<S: Into<String>>(base_url: S) -> Self {
+        BrowserFetcher {
+            base_url: base_url.into(),
+            completed: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    //The closures of the promises are called once, and forgotten: they are dropped by the JS garbage collector.
+    fn failure(completed: Completed, path: String) -> Closure<FnMut(JsValue)> {
+        Closure::once(move |error: JsValue| {
+            completed.borrow_mut().push((path, Err(format!("{:?}", error))));
+        })
+    }
+
+    fn fetch(&self, path: String) -> Result<(), JsValue> {
+        let url = format!("{}/{}", self.base_url.trim_end_matches('/'), path);
+        let completed = self.completed.clone();
+        let response_path = path.clone();
+
+        let on_response = Closure::once(move |response: JsValue| {
+            let body = response.dyn_into::<Response>().and_then(|response| {
+                if response.ok() {
+                    response.array_buffer()
+                } else {
+                    Err(JsValue::from_str(format!("HTTP status {}", response.status()).as_str()))
+                }
+            });
+            match body {
+                Ok(body) => {
+                    let (bytes_completed, bytes_path) = (completed.clone(), response_path.clone());
+                    let on_bytes: Closure<FnMut(JsValue)> = Closure::once(move |buffer: JsValue| {
+                        bytes_completed.borrow_mut().push((bytes_path, Ok(Uint8Array::new(&buffer).to_vec())));
+                    });
+                    let on_error = BrowserFetcher::failure(completed, response_path);
+                    body.then2(&on_bytes, &on_error);
+                    on_bytes.forget();
+                    on_error.forget();
+                },
+                Err(error) => completed.borrow_mut().push((response_path, Err(format!("{:?}", error)))),
+            }
+        }) as Closure<FnMut(JsValue)>;
+        let on_error = BrowserFetcher::failure(self.completed.clone(), path);
+
+        window()?.fetch_with_str(url.as_str()).then2(&on_response, &on_error);
+        on_response.forget();
+        on_error.forget();
+        Ok(())
+    }
+}
+
+impl AssetFetcher for BrowserFetcher {
+    fn request(&mut self, path: &str) {
+        if let Err(error) = self.fetch(path.to_string()) {
+            self.completed.borrow_mut().push((path.to_string(), Err(format!("{:?}", error))));
+        }
+    }
+
+    fn poll_completed(&mut self) -> Vec<(String, Result<Vec<u8>, String>)> {
+        self.completed.borrow_mut().drain(..).collect()
+    }
+}
+
+const OBJECT_STORE: &'static str = "files";
+
+//The user data in an IndexedDB database, a record by file.
+pub struct IndexedDbStore {
+    database: Rc<RefCell<Option<IdbDatabase>>>,
+    loaded: Rc<RefCell<Option<Vec<(String, Vec<u8>)>>>>,
+}
+
+impl IndexedDbStore {
+    pub fn open(name: &str) -> Result<Self, JsValue> {
+        let factory = window()?.indexed_db()?.ok_or_else(|| JsValue::from_str("IndexedDB is not available."))?;
+        let request: IdbOpenDbRequest = factory.open_with_u32(name, 1)?;
+
+        let upgrade = Closure::once_into_js(move |event: web_sys::Event| {
+            let result = event.target()
+                .and_then(|target| target.dyn_into::<IdbRequest>().ok())
+                .and_then(|request| request.result().ok())
+                .and_then(|database| database.dyn_into::<IdbDatabase>().ok())
+                .map(|database| database.create_object_store(OBJECT_STORE));
+            if let Some(Err(error)) = result {
+                error!("Could not create the IndexedDB object store: {:?}.", error);
+            }
+        });
+        request.set_onupgradeneeded(Some(upgrade.unchecked_ref()));
+
+        let store = IndexedDbStore {
+            database: Rc::new(RefCell::new(None)),
+            loaded: Rc::new(RefCell::new(None)),
+        };
+        let (database, loaded) = (store.database.clone(), store.loaded.clone());
+        let opened = request.clone();
+        let success = Closure::once_into_js(move || {
+            let result = opened.result().and_then(|database| database.dyn_into::<IdbDatabase>().map_err(JsValue::from))
+                .and_then(|opened_database| {
+                    IndexedDbStore::load_all(&opened_database, loaded)?;
+                    *database.borrow_mut() = Some(opened_database);
+                    Ok(())
+                });
+            if let Err(error) = result {
+                error!("Could not open the IndexedDB database: {:?}.", error);
+            }
+        });
+        request.set_onsuccess(Some(success.unchecked_ref()));
+        Ok(store)
+    }
+
+    fn load_all(database: &IdbDatabase, loaded: Rc<RefCell<Option<Vec<(String, Vec<u8>)>>>>) -> Result<(), JsValue> {
+        let store = database.transaction_with_str(OBJECT_STORE)?.object_store(OBJECT_STORE)?;
+        //The keys and the values come in the same order.
+        let keys = store.get_all_keys()?;
+        let values = store.get_all()?;
+        let values_request = values.clone();
+        let on_values = Closure::once_into_js(move || {
+            let files = match (keys.result(), values_request.result()) {
+                (Ok(keys), Ok(values)) => Array::from(&keys).iter().zip(Array::from(&values).iter())
+                    .filter_map(|(key, value)| key.as_string().map(|path| (path, Uint8Array::new(&value).to_vec())))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            *loaded.borrow_mut() = Some(files);
+        });
+        values.set_onsuccess(Some(on_values.unchecked_ref()));
+        Ok(())
+    }
+
+    //Some(files) once the database is opened and read, for UserData::load.
+    pub fn take_loaded(&mut self) -> Option<Vec<(String, Vec<u8>)>> {
+        self.loaded.borrow_mut().take()
+    }
+
+    fn with_store<T: FnOnce(&web_sys::IdbObjectStore) -> Result<IdbRequest, JsValue>>(&self, operation: T) {
+        let result = match self.database.borrow().as_ref() {
+            Some(database) => database.transaction_with_str_and_mode(OBJECT_STORE, IdbTransactionMode::Readwrite)
+                .and_then(|transaction| transaction.object_store(OBJECT_STORE))
+                .and_then(|store| operation(&store)),
+            None => Err(JsValue::from_str("The database is not opened yet.")),
+        };
+        if let Err(error) = result {
+            error!("IndexedDB operation failed: {:?}.", error);
+        }
+    }
+}
+
+impl PersistentStore for IndexedDbStore {
+    fn put(&mut self, path: &str, bytes: &[u8]) {
+        self.with_store(|store| store.put_with_key(&Uint8Array::from(bytes), &JsValue::from_str(path)));
+    }
+
+    fn delete(&mut self, path: &str) {
+        self.with_store(|store| store.delete(&JsValue::from_str(path)));
+    }
+}
+
+#[cfg(test)]
+mod web_test {
+    use super::*;
+    use std::io::Read;
+    use std::path::Path;
+
+    #[derive(Default)]
+    struct Store {
+        operations: Vec<String>,
+    }
+
+    impl PersistentStore for Store {
+        fn put(&mut self, path: &str, bytes: &[u8]) {
+            self.operations.push(format!("put {} {}", path, bytes.len()));
+        }
+
+        fn delete(&mut self, path: &str) {
+            self.operations.push(format!("delete {}", path));
+        }
+    }
+
+    struct Fetcher {
+        requested: Vec<String>,
+    }
+
+    impl AssetFetcher for Fetcher {
+        fn request(&mut self, path: &str) {
+            self.requested.push(path.to_string());
+        }
+
+        fn poll_completed(&mut self) -> Vec<(String, Result<Vec<u8>, String>)> {
+            self.requested.drain(..).map(|path| {
+                let result = if path.ends_with(".missing") {Err(String::from("HTTP status 404"))} else {Ok(path.clone().into_bytes())};
+                (path, result)
+            }).collect()
+        }
+    }
+
+    #[test]
+    fn web_backend() {
+        let directories = web_directories();
+        assert_eq!(directories.get(&RootDir::UserSaveRoot), Some(Path::new("/data/game_saves")));
+
+        let mut user_data = UserData::load(vec![(String::from("/data/game_saves/slot0"), vec![1, 2])]);
+        user_data.write("/data/game_saves/slot1", vec![3]);
+        user_data.remove("/data/game_saves/slot0");
+        assert_eq!(user_data.list("/data/game_saves"), vec![String::from("/data/game_saves/slot1")]);
+        let mut store = Store::default();
+        user_data.flush(&mut store);
+        assert!(user_data.is_flushed());
+        assert_eq!(store.operations, vec![String::from("delete /data/game_saves/slot0"), String::from("put /data/game_saves/slot1 1")]);
+
+        let mut assets = FetchMount::new(Fetcher { requested: Vec::new() });
+        assets.prefetch("/levels/intro.kscene");
+        assets.prefetch("levels/intro.kscene");
+        assets.prefetch("levels/outro.missing");
+        assert_eq!(assets.fetcher.requested.len(), 2);
+        assert!(assets.open("levels/intro.kscene").is_err());
+        assets.poll();
+        assert!(!assets.is_loading());
+        let mut scene = String::new();
+        assets.open("levels/intro.kscene").unwrap().read_to_string(&mut scene).unwrap();
+        assert_eq!(scene, "levels/intro.kscene");
+        assert!(assets.open("levels/outro.missing").is_err());
+
+        //50 steps per second, at most 5 steps by frame.
+        let mut clock = AnimationFrameClock::new(50, 5);
+        assert_eq!(clock.on_animation_frame(1000.0).steps, 0);
+        assert_eq!(clock.on_animation_frame(1050.0), FrameSteps { steps: 2, alpha: 0.5 });
+        //The tab was hidden for a minute.
+        assert_eq!(clock.on_animation_frame(61000.0).steps, 5);
+    }
+
+    #[test]
+    fn web_filesystem() {
+        let persisted = vec![
+            (String::from("/data/game_saves/slots/slot0"), vec![1, 2]),
+            (String::from("/config/input.json"), b"{}".to_vec()),
+            (String::from("/data/cache/shaders"), vec![0]),
+        ];
+        let mut fs = WebFilesystem::new(Fetcher { requested: Vec::new() }, persisted).unwrap();
+        assert!(fs.exists(VPath::new(RootDir::UserSaveRoot, "slots/slot0").unwrap()));
+        assert!(!fs.exists(VPath::new(RootDir::UserDataRoot, "cache/shaders").unwrap()));
+        assert!(fs.is_flushed());
+
+        fs.write_all(VPath::new(RootDir::UserSaveRoot, "slots/slot1").unwrap(), &[3]).unwrap();
+        fs.rm(VPath::new(RootDir::UserConfigRoot, "input.json").unwrap()).unwrap();
+        fs.create(VPath::new(RootDir::EngineLogRoot, "engine.log").unwrap()).unwrap();
+        let mut store = Store::default();
+        fs.flush(&mut store).unwrap();
+        assert!(fs.is_flushed());
+        assert_eq!(store.operations, vec![String::from("delete /config/input.json"), String::from("put /data/game_saves/slots/slot1 1")]);
+
+        fs.assets().prefetch("levels/intro.kscene");
+        assert!(fs.open(VPath::new(RootDir::AssetRoot, "levels/intro.kscene").unwrap()).is_err());
+        fs.assets().poll();
+        assert_eq!(fs.metadata(VPath::new(RootDir::AssetRoot, "levels/intro.kscene").unwrap()).unwrap().len(), 19);
+        assert!(fs.metadata(VPath::new(RootDir::WorkingDirectory, "levels").unwrap()).unwrap().is_dir());
+        assert_eq!(fs.read_dir(VPath::root_of(RootDir::AssetRoot)).unwrap(), vec![VPathBuf::new(RootDir::AssetRoot, "levels").unwrap()]);
+        assert_eq!(fs.read_dir(VPath::new(RootDir::AssetRoot, "levels").unwrap()).unwrap(), vec![VPathBuf::new(RootDir::AssetRoot, "levels/intro.kscene").unwrap()]);
+        assert!(fs.create(VPath::new(RootDir::AssetRoot, "levels/intro.kscene").unwrap()).is_err());
+        let intro = VPath::new(RootDir::AssetRoot, "levels/intro.kscene").unwrap();
+        for result in [fs.rm(intro), fs.rmrf(intro), fs.mkdir(VPath::new(RootDir::AssetRoot, "mods").unwrap())].iter() {
+            match result {
+                &Err(FileSystemError::PermissionError(ref description)) => assert_eq!(description, "asset roots are read-only"),
+                result => panic!("Unexpected result {:?}.", result),
+            }
+        }
+        assert!(fs.exists(intro));
+    }
+}
@@ -0,0 +1,360 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ DEDICATED SERVER.
+
+ The server runs the fixed-step simulation at its tick rate, without window: only the simulation
+ systems are registered, the rendering and audio systems are refused.
+
+ The admin console is a line-based TCP socket on the loopback interface:
+ - "status": the tick, the tick rate, and the status of each system.
+ - "tickrate <ticks per second>".
+ - "shutdown [reason]": the systems are shut down in the inverse order of registration, after the
+   clients have been notified by the network system.
+*/
+
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use core::system_status::{SystemStatus, StatusBoard};
+
+pub const DEFAULT_TICK_RATE: u32 = 30;
+pub const DEFAULT_ADMIN_PORT: u16 = 27020;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SystemKind {
+    Simulation,
+    Network,
+    Rendering,
+    Audio,
+}
+
+pub trait ServerSystem {
+    fn name(&self) -> &str;
+
+    fn kind(&self) -> SystemKind;
+
+    fn fixed_update(&mut self, tick: u64, step_seconds: f64);
+
+    //Polled after each tick.
+    fn status(&self) -> SystemStatus {
+        SystemStatus::Ok
+    }
+
+    //The network system notifies the clients here.
+    fn shutdown(&mut self, _reason: &str) {}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub tick_rate: u32,
+    //None: no admin console.
+    pub admin_port: Option<u16>,
+    //After a hitch, at most this number of ticks are simulated at once, the others are dropped.
+    pub max_catch_up_ticks: u32,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            tick_rate: DEFAULT_TICK_RATE,
+            admin_port: Some(DEFAULT_ADMIN_PORT),
+            max_catch_up_ticks: 5,
+        }
+    }
+}
+
+struct AdminConsole {
+    listener: TcpListener,
+    connections: Vec<(BufReader<TcpStream>, String)>,
+}
+
+impl AdminConsole {
+    fn bind(port: u16) -> Result<Self, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|error| format!("Could not open the admin console on the port {}: {}.", port, error))?;
+        listener.set_nonblocking(true).map_err(|error| error.to_string())?;
+        Ok(AdminConsole {
+            listener,
+            connections: Vec::new(),
+        })
+    }
+
+    //The complete command lines received, with the index of their connection.
+    fn poll(&mut self) -> Vec<(usize, String)> {
+        while let Ok((stream, address)) = self.listener.accept() {
+            debug!("Admin console connection from {}.", address);
+            if stream.set_nonblocking(true).is_ok() {
+                self.connections.push((BufReader::new(stream), String::new()));
+            }
+        }
+        let mut commands = Vec::new();
+        let mut closed = Vec::new();
+        for (index, &mut (ref mut reader, ref mut line)) in self.connections.iter_mut().enumerate() {
+            loop {
+                match reader.read_line(line) {
+                    Ok(0) => {
+                        closed.push(index);
+                        break;
+                    },
+                    Ok(_) if line.ends_with('\n') => commands.push((index, ::std::mem::replace(line, String::new()).trim().to_string())),
+                    Ok(_) => {},
+                    Err(ref error) if error.kind() == ErrorKind::WouldBlock => break,
+                    Err(_) => {
+                        closed.push(index);
+                        break;
+                    },
+                }
+            }
+        }
+        for index in closed.into_iter().rev() {
+            self.connections.remove(index);
+        }
+        commands
+    }
+
+    fn reply(&mut self, connection: usize, text: &str) {
+        if let Some(&mut (ref mut reader, _)) = self.connections.get_mut(connection) {
+            let _ = writeln!(reader.get_mut(), "{}", text);
+        }
+    }
+}
+
+pub struct DedicatedServer {
+    config: ServerConfig,
+    systems: Vec<Box<ServerSystem>>,
+    console: Option<AdminConsole>,
+    tick: u64,
+    next_tick: Option<Instant>,
+    shutdown: Option<String>,
+    statuses: StatusBoard,
+}
+
+impl DedicatedServer {
+    pub fn new(config: ServerConfig) -> Result<Self, String> {
+        let console = match config.admin_port {
+            Some(port) => Some(AdminConsole::bind(port)?),
+            None => None,
+        };
+        Ok(DedicatedServer {
+            config,
+            systems: Vec::new(),
+            console,
+            tick: 0,
+            next_tick: None,
+            shutdown: None,
+            statuses: StatusBoard::new(),
+        })
+    }
+
+    //False if the system is refused: a dedicated server has no rendering and no audio.
+    pub fn register(&mut self, system: Box<ServerSystem>) -> bool {
+        match system.kind() {
+            SystemKind::Rendering | SystemKind::Audio => {
+                warn!("The {:?} system {} is not registered on a dedicated server.", system.kind(), system.name());
+                false
+            },
+            _ => {
+                debug!("Registering the server system {}.", system.name());
+                self.systems.push(system);
+                true
+            },
+        }
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn tick_rate(&self) -> u32 {
+        self.config.tick_rate
+    }
+
+    pub fn set_tick_rate(&mut self, tick_rate: u32) {
+        debug!("Server tick rate: {} ticks per second.", tick_rate);
+        self.config.tick_rate = tick_rate.max(1);
+    }
+
+    pub fn statuses(&self) -> &StatusBoard {
+        &self.statuses
+    }
+
+    pub fn request_shutdown(&mut self, reason: &str) {
+        self.shutdown = Some(reason.to_string());
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_some()
+    }
+
+    fn step_duration(&self) -> Duration {
+        Duration::from_nanos(1_000_000_000 / self.config.tick_rate as u64)
+    }
+
+    fn execute(&mut self, command: &str) -> String {
+        let mut words = command.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("status"), _) => {
+                let systems: Vec<String> = self.systems.iter().map(|system| {
+                    format!("{} ({})", system.name(), self.statuses.status(system.name()).cloned().unwrap_or_default())
+                }).collect();
+                format!("tick {} at {} ticks per second, systems: {}", self.tick, self.config.tick_rate, systems.join(", "))
+            },
+            (Some("tickrate"), Some(rate)) => match rate.parse::<u32>() {
+                Ok(rate) if rate > 0 => {
+                    self.set_tick_rate(rate);
+                    format!("tick rate set to {}", rate)
+                },
+                _ => format!("invalid tick rate {}", rate),
+            },
+            (Some("shutdown"), _) => {
+                let reason = command["shutdown".len()..].trim();
+                self.request_shutdown(if reason.is_empty() {"server shutdown"} else {reason});
+                String::from("shutting down")
+            },
+            _ => format!("unknown command {}", command),
+        }
+    }
+
+    fn poll_console(&mut self) {
+        let commands = match self.console {
+            Some(ref mut console) => console.poll(),
+            None => return,
+        };
+        for (connection, command) in commands {
+            let reply = self.execute(command.as_str());
+            if let Some(ref mut console) = self.console {
+                console.reply(connection, reply.as_str());
+            }
+        }
+    }
+
+    //Simulate the ticks due at this time. Returns the number of simulated ticks.
+    pub fn update(&mut self, now: Instant) -> u32 {
+        self.poll_console();
+        let step = self.step_duration();
+        let mut next_tick = self.next_tick.unwrap_or(now);
+        let mut ticks = 0;
+        while next_tick <= now && ticks < self.config.max_catch_up_ticks {
+            self.tick += 1;
+            let step_seconds = 1.0 / self.config.tick_rate as f64;
+            for system in self.systems.iter_mut() {
+                system.fixed_update(self.tick, step_seconds);
+                self.statuses.update(system.name(), system.status());
+            }
+            next_tick += step;
+            ticks += 1;
+        }
+        if next_tick <= now {
+            warn!("The server is running late, {} ms of simulation are dropped.", (now - next_tick).as_secs() * 1000 + (now - next_tick).subsec_nanos() as u64 / 1_000_000);
+            next_tick = now + step;
+        }
+        self.next_tick = Some(next_tick);
+        ticks
+    }
+
+    fn shutdown_systems(&mut self, reason: &str) {
+        debug!("Shutting down the server: {}.", reason);
+        for system in self.systems.iter_mut().rev() {
+            system.shutdown(reason);
+        }
+    }
+
+    //Run until a shutdown is requested.
+    pub fn run(&mut self) {
+        while self.shutdown.is_none() {
+            self.update(Instant::now());
+            if let Some(next_tick) = self.next_tick {
+                let now = Instant::now();
+                if next_tick > now {
+                    thread::sleep(next_tick - now);
+                }
+            }
+        }
+        let reason = self.shutdown.clone().unwrap_or_default();
+        self.shutdown_systems(reason.as_str());
+    }
+}
+
+#[cfg(test)]
+mod server_test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Recorder {
+        kind: SystemKind,
+        log: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl ServerSystem for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn kind(&self) -> SystemKind {
+            self.kind
+        }
+
+        fn fixed_update(&mut self, tick: u64, _step_seconds: f64) {
+            self.log.borrow_mut().push(format!("tick {}", tick));
+        }
+
+        fn status(&self) -> SystemStatus {
+            if self.log.borrow().len() >= 3 {SystemStatus::Degraded(String::from("lagging"))} else {SystemStatus::Ok}
+        }
+
+        fn shutdown(&mut self, reason: &str) {
+            self.log.borrow_mut().push(format!("shutdown: {}", reason));
+        }
+    }
+
+    #[test]
+    fn server_tick_rate_and_console() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut server = DedicatedServer::new(ServerConfig {
+            tick_rate: 10,
+            admin_port: Some(47820),
+            max_catch_up_ticks: 3,
+        }).unwrap();
+        assert!(!server.register(Box::new(Recorder { kind: SystemKind::Rendering, log: log.clone() })));
+        assert!(server.register(Box::new(Recorder { kind: SystemKind::Simulation, log: log.clone() })));
+
+        let start = Instant::now();
+        assert_eq!(server.update(start), 1);
+        assert_eq!(server.update(start + Duration::from_millis(50)), 0);
+        assert_eq!(server.update(start + Duration::from_millis(200)), 2);
+        //A hitch: only 3 ticks are caught up.
+        assert_eq!(server.update(start + Duration::from_secs(10)), 3);
+
+        assert_eq!(server.statuses().status("recorder"), Some(&SystemStatus::Degraded(String::from("lagging"))));
+
+        let mut admin = TcpStream::connect(("127.0.0.1", 47820)).unwrap();
+        writeln!(admin, "status").unwrap();
+        writeln!(admin, "tickrate 60").unwrap();
+        writeln!(admin, "shutdown maintenance").unwrap();
+        let mut replies = BufReader::new(admin.try_clone().unwrap());
+        let mut reply = String::new();
+        for _ in 0..100 {
+            server.update(Instant::now());
+            if server.is_shutting_down() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        replies.read_line(&mut reply).unwrap();
+        assert!(reply.trim().ends_with("systems: recorder (degraded (lagging))"));
+        reply.clear();
+        replies.read_line(&mut reply).unwrap();
+        assert_eq!(reply.trim(), "tick rate set to 60");
+        assert_eq!(server.tick_rate(), 60);
+
+        server.run();
+        assert_eq!(log.borrow().last().unwrap(), "shutdown: maintenance");
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright 2017-2018 Maskerad Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ TEST HARNESS.
+
+ Runs the engine loop headless, for the end-to-end tests of the games: the state of the game is
+ stepped by its systems, at a fixed tick rate and without wall clock, so a test gives the same result
+ on every machine.
+
+ - Like on the dedicated server, only the simulation and network systems run, the rendering and audio
+   systems are refused.
+ - The inputs are scripted: each input is given at a tick, and the systems receive the inputs of the
+   current tick.
+ - The assertions on the state of the game panic with the tick and the description of the check.
+*/
+
+use std::collections::BTreeMap;
+use server::{SystemKind, DEFAULT_TICK_RATE};
+
+pub trait HarnessSystem<S, I> {
+    fn name(&self) -> &str;
+
+    fn kind(&self) -> SystemKind {
+        SystemKind::Simulation
+    }
+
+    fn fixed_update(&mut self, state: &mut S, inputs: &[I], tick: u64, step_seconds: f64);
+}
+
+pub struct TestHarness<S, I> {
+    state: S,
+    systems: Vec<Box<HarnessSystem<S, I>>>,
+    inputs: BTreeMap<u64, Vec<I>>,
+    tick: u64,
+    tick_rate: u32,
+}
+
+impl<S, I> TestHarness<S, I> {
+    pub fn new(state: S) -> Self {
+        TestHarness {
+            state,
+            systems: Vec::new(),
+            inputs: BTreeMap::new(),
+            tick: 0,
+            tick_rate: DEFAULT_TICK_RATE,
+        }
+    }
+
+    pub fn with_tick_rate(mut self, tick_rate: u32) -> Self {
+        self.tick_rate = tick_rate.max(1);
+        self
+    }
+
+    //The rendering and audio systems are refused.
+    pub fn register(&mut self, system: Box<HarnessSystem<S, I>>) -> bool {
+        match system.kind() {
+            SystemKind::Rendering | SystemKind::Audio => {
+                warn!("The test harness is headless, the system {} is not registered.", system.name());
+                false
+            },
+            _ => {
+                debug!("Registering the system {} in the test harness.", system.name());
+                self.systems.push(system);
+                true
+            },
+        }
+    }
+
+    //Give an input to the systems at a tick. An input for a past tick is never given.
+    pub fn script_input(&mut self, tick: u64, input: I) {
+        if tick < self.tick {
+            warn!("The input scripted at the tick {} is in the past (tick {}).", tick, self.tick);
+        }
+        self.inputs.entry(tick).or_insert_with(Vec::new).push(input);
+    }
+
+    //The number of ticks already run.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    fn step(&mut self) {
+        let step_seconds = 1.0 / self.tick_rate as f64;
+        let inputs = self.inputs.remove(&self.tick).unwrap_or_else(Vec::new);
+        for system in self.systems.iter_mut() {
+            system.fixed_update(&mut self.state, inputs.as_slice(), self.tick, step_seconds);
+        }
+        self.tick += 1;
+    }
+
+    pub fn run(&mut self, ticks: u64) -> &mut Self {
+        trace!("Running {} ticks from the tick {}.", ticks, self.tick);
+        for _ in 0..ticks {
+            self.step();
+        }
+        self
+    }
+
+    //Run until the condition is true, at most max_ticks. Return the tick at which the condition became true.
+    pub fn run_until<F>(&mut self, max_ticks: u64, condition: F) -> Option<u64> where
+        F: Fn(&S) -> bool
+    {
+        for _ in 0..max_ticks {
+            if condition(&self.state) {
+                return Some(self.tick);
+            }
+            self.step();
+        }
+        if condition(&self.state) {Some(self.tick)} else {None}
+    }
+
+    //Panics if the check fails.
+    pub fn assert_state<F>(&self, description: &str, check: F) -> &Self where
+        F: Fn(&S) -> bool
+    {
+        if !check(&self.state) {
+            panic!("At the tick {}, the check \"{}\" failed.", self.tick, description);
+        }
+        self
+    }
+
+    //Panics if the value differs from the expected one.
+    pub fn assert_value<T, F>(&self, description: &str, expected: T, value: F) -> &Self where
+        T: PartialEq + ::std::fmt::Debug,
+        F: Fn(&S) -> T
+    {
+        let value = value(&self.state);
+        if value != expected {
+            panic!("At the tick {}, {} is {:?} instead of {:?}.", self.tick, description, value, expected);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod test_harness_test {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct Game {
+        position: f64,
+        jumps: u32,
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    enum Input {
+        Jump,
+    }
+
+    struct Movement;
+
+    impl HarnessSystem<Game, Input> for Movement {
+        fn name(&self) -> &str {
+            "movement"
+        }
+
+        fn fixed_update(&mut self, state: &mut Game, inputs: &[Input], _tick: u64, step_seconds: f64) {
+            state.position += 3.0 * step_seconds;
+            state.jumps += inputs.iter().filter(|input| **input == Input::Jump).count() as u32;
+        }
+    }
+
+    struct Renderer;
+
+    impl HarnessSystem<Game, Input> for Renderer {
+        fn name(&self) -> &str {
+            "renderer"
+        }
+
+        fn kind(&self) -> SystemKind {
+            SystemKind::Rendering
+        }
+
+        fn fixed_update(&mut self, _state: &mut Game, _inputs: &[Input], _tick: u64, _step_seconds: f64) {}
+    }
+
+    #[test]
+    fn test_harness_runs_scripted_ticks() {
+        let mut harness = TestHarness::new(Game::default()).with_tick_rate(10);
+        assert!(!harness.register(Box::new(Renderer)));
+        assert!(harness.register(Box::new(Movement)));
+        harness.script_input(2, Input::Jump);
+        harness.script_input(2, Input::Jump);
+        harness.script_input(7, Input::Jump);
+
+        harness.run(5)
+            .assert_value("the number of jumps", 2, |game| game.jumps)
+            .assert_state("the player moved", |game| (game.position - 1.5).abs() < 0.0001);
+        assert_eq!(harness.run_until(100, |game| game.position >= 2.9999), Some(10));
+        harness.assert_value("the number of jumps", 3, |game| game.jumps);
+    }
+
+    #[test]
+    #[should_panic(expected = "At the tick 1, the number of jumps is 0 instead of 1.")]
+    fn test_harness_failed_assertion() {
+        let mut harness: TestHarness<Game, Input> = TestHarness::new(Game::default());
+        harness.register(Box::new(Movement));
+        harness.run(1).assert_value("the number of jumps", 1, |game| game.jumps);
+    }
+}